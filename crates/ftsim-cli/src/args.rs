@@ -3,7 +3,9 @@
 //! Defines the command-line argument structure using `clap`.
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
-use std::path::PathBuf;
+use ftsim_types::scenario::CodecErrorPolicy;
+use serde::Serialize;
+use std::{net::SocketAddr, path::PathBuf};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -21,14 +23,185 @@ pub struct Cli {
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// Run a simulation from a scenario file.
-    Run(RunOpts),
+    Run(Box<RunOpts>),
+    /// Run a scenario across many seeds in parallel and aggregate the results.
+    Sweep(SweepOpts),
+    /// Time a fixed set of synthetic engine benchmarks, optionally against a
+    /// saved baseline.
+    Bench(BenchOpts),
     /// List all compiled and available protocols.
-    ListProtocols,
+    ListProtocols {
+        /// Also list protocols that exist but were not compiled in.
+        #[arg(long)]
+        all: bool,
+    },
     /// Validate a scenario file for correctness.
     Validate {
         #[arg(value_name = "SCENARIO_PATH")]
         scenario: PathBuf,
     },
+    /// Re-run a scenario and check it against a recorded event trace, a
+    /// recording of RNG draws, or both.
+    Replay {
+        #[arg(value_name = "TRACE_PATH", required_unless_present = "rng")]
+        trace: Option<PathBuf>,
+        /// Path to the scenario file the trace was recorded from.
+        #[arg(short, long)]
+        scenario: PathBuf,
+        /// Check every RNG draw against a recording made with `--record-rng`.
+        #[arg(long, value_name = "RNG_PATH")]
+        rng: Option<PathBuf>,
+        /// Enable or disable checking the scenario's invariants, as the
+        /// recorded run did.
+        #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+        invariants: Option<bool>,
+        #[command(flatten)]
+        engine: EngineOpts,
+    },
+    /// Generate a scenario file from a parameterized template.
+    NewScenario(NewScenarioOpts),
+    /// List messages that were delivered despite a crash or partition on
+    /// the sending side, from a message journal.
+    Inspect {
+        #[arg(value_name = "MESSAGES_JSONL", required_unless_present = "rng_diff")]
+        journal: Option<PathBuf>,
+        /// Find the first event at which two runs' RNG draw positions
+        /// differ. Takes two artifact directories or `rng-positions.jsonl`
+        /// files.
+        #[arg(long, num_args = 2, value_names = ["RUN_A", "RUN_B"], conflicts_with = "journal")]
+        rng_diff: Option<Vec<PathBuf>>,
+        /// Instead of listing suspects, build a graph of who sent how many
+        /// messages and bytes to whom from the journal's deliveries. Writes
+        /// to OUT, as JSON if it ends in `.json` and DOT otherwise, or
+        /// prints DOT without it.
+        #[arg(long, num_args = 0..=1, value_name = "OUT")]
+        flow_graph: Option<Option<PathBuf>>,
+        /// Split the flow graph's edges by message variant.
+        #[arg(long, requires = "flow_graph")]
+        by_variant: bool,
+        /// The scenario the journal was recorded from, for a flow sub-graph
+        /// per phase.
+        #[arg(long, requires = "flow_graph")]
+        scenario: Option<PathBuf>,
+        /// Only look at messages sent at or after this sim time, in
+        /// milliseconds. Of a rotated journal, only the segments in range
+        /// are read.
+        #[arg(long, value_name = "MS", conflicts_with = "rng_diff")]
+        from_ms: Option<u64>,
+        /// Only look at messages sent at or before this sim time, in
+        /// milliseconds.
+        #[arg(long, value_name = "MS", conflicts_with = "rng_diff")]
+        to_ms: Option<u64>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct SweepOpts {
+    #[arg(short, long)]
+    pub scenario: PathBuf,
+
+    /// Number of seeds to run.
+    #[arg(long, default_value_t = 10)]
+    pub seeds: u64,
+
+    /// The first seed; runs use consecutive seeds from here. Defaults to the
+    /// scenario's seed, or 0.
+    #[arg(long)]
+    pub first_seed: Option<u64>,
+
+    /// Number of runs executed at once. Defaults to the available cores.
+    #[arg(long)]
+    pub parallel: Option<usize>,
+
+    /// Stop each run at this sim time in milliseconds.
+    #[arg(long)]
+    pub stop_at: Option<u64>,
+
+    /// Write the aggregate and every run's report to this file as JSON.
+    #[arg(long)]
+    pub report_json: Option<PathBuf>,
+
+    /// A previous sweep's `--report-json` to compare throughput against.
+    #[arg(long)]
+    pub baseline: Option<PathBuf>,
+
+    /// Fail if the median events/sec is this many percent below the
+    /// baseline's.
+    #[arg(long, default_value_t = 10.0)]
+    pub max_slowdown: f64,
+}
+
+#[derive(Args, Debug)]
+pub struct BenchOpts {
+    /// Only run benchmarks whose name contains this.
+    #[arg(long)]
+    pub filter: Option<String>,
+
+    /// Multiply every benchmark's operation count by this, e.g. 0.1 for a
+    /// quick run.
+    #[arg(long, default_value_t = 1.0)]
+    pub scale: f64,
+
+    /// Run each benchmark this many times and report the fastest run.
+    #[arg(long, default_value_t = 3)]
+    pub runs: usize,
+
+    /// A previous `--save-baseline` to compare against.
+    #[arg(long)]
+    pub baseline: Option<PathBuf>,
+
+    /// Write the results to this file as JSON, for a later `--baseline`.
+    #[arg(long)]
+    pub save_baseline: Option<PathBuf>,
+
+    /// Fail if any benchmark's ops/sec is this many percent below the
+    /// baseline's.
+    #[arg(long, default_value_t = 10.0)]
+    pub max_regression: f64,
+}
+
+/// Scenario templates available to `new-scenario`.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Template {
+    /// Degrade, partition, crash, heal and restart one region, checking
+    /// leadership and availability after each stage.
+    RegionDrill,
+}
+
+#[derive(Args, Debug)]
+pub struct NewScenarioOpts {
+    #[arg(long, value_enum)]
+    pub template: Template,
+
+    /// Number of regions; nodes are assigned to regions in contiguous blocks.
+    #[arg(long, default_value_t = 3)]
+    pub regions: usize,
+
+    #[arg(long, default_value_t = 3)]
+    pub nodes_per_region: usize,
+
+    /// Registered protocol name, as shown by `list-protocols`.
+    #[arg(long, default_value = "raft_lite")]
+    pub protocol: String,
+
+    /// The region to evacuate.
+    #[arg(long, default_value_t = 0)]
+    pub region: usize,
+
+    /// Length of each drill stage, in milliseconds.
+    #[arg(long, default_value_t = 2_000)]
+    pub stage_ms: u64,
+
+    /// Base link delay applied to the region during the degrade stage, in milliseconds.
+    #[arg(long, default_value_t = 20)]
+    pub degraded_delay_ms: u64,
+
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Write the scenario here instead of to stdout.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
 }
 
 #[derive(Args, Debug)]
@@ -49,7 +222,210 @@ pub struct RunOpts {
     #[arg(long)]
     pub headless: bool,
 
-    // Other options from the spec would go here.
+    /// TUI color theme. Defaults to the `FTSIM_THEME` environment variable,
+    /// then `dark`.
+    #[arg(long, value_enum)]
+    pub theme: Option<TuiTheme>,
+
+    /// Preset bundle of run defaults. Individual flags below override it.
+    #[arg(long)]
+    pub mode: Option<RunMode>,
+
+    /// Telemetry verbosity for simulation logs.
+    #[arg(long)]
+    pub telemetry: Option<TelemetryLevel>,
+
+    /// Enable or disable the determinism canary, which runs a headless
+    /// scenario a second time and fails unless it ends in the same state.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub canary: Option<bool>,
+
+    /// Enable or disable detailed event journaling.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub journal: Option<bool>,
+
+    /// Enable or disable checking the scenario's invariants.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub invariants: Option<bool>,
+
+    /// Enable or disable per-message traces.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub msg_trace: Option<bool>,
+
+    /// Enable or disable the wall-clock phase profiler.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub profile: Option<bool>,
+
+    /// Enable or disable the end-of-run throughput report.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub throughput: Option<bool>,
+
+    /// Directory to write run artifacts into.
+    #[arg(long)]
+    pub artifact_dir: Option<PathBuf>,
+
+    /// Sim time covered by one column of the run timeline, in milliseconds.
+    /// Defaults to fitting the whole run into the terminal width.
+    #[arg(long)]
+    pub timeline_bucket: Option<u64>,
+
+    /// Record every store mutation and write the journal to this file as JSONL.
+    #[arg(long)]
+    pub store_journal: Option<PathBuf>,
+
+    /// Write the structured run report to this file as JSON.
+    #[arg(long)]
+    pub report_json: Option<PathBuf>,
+
+    /// Record every sent message with the sender's view at send time and
+    /// write the journal to this file as JSONL, for `ftsim inspect`, which
+    /// reads it compressed too.
+    #[arg(long)]
+    pub msg_journal: Option<PathBuf>,
+
+    /// Write a graph of who sent how many messages and bytes to whom to
+    /// this file: JSON if it ends in `.json`, DOT otherwise. Has a
+    /// sub-graph per phase when the scenario defines phases.
+    #[arg(long, value_name = "PATH")]
+    pub flow_graph_out: Option<PathBuf>,
+
+    /// Split the flow graph's edges by message variant.
+    #[arg(long, requires = "flow_graph_out")]
+    pub flow_graph_by_variant: bool,
+
+    /// Record the full event trace to this file, for `ftsim replay`. Record
+    /// with `--headless`, since TUI snapshot ticks are part of the trace.
+    /// With an artifact directory, also writes the per-event RNG draw
+    /// positions there, for `ftsim inspect --rng-diff`.
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+
+    /// Record the value of every RNG draw to this file, for
+    /// `ftsim replay --rng`.
+    #[arg(long)]
+    pub record_rng: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub engine: EngineOpts,
+
+    /// Stop right before the first event at or after this sim time, in
+    /// milliseconds. Combined with `--break-on-fault`, only faults match.
+    #[arg(long)]
+    pub break_at_time: Option<u64>,
+
+    /// Stop right before the first fault event.
+    #[arg(long)]
+    pub break_on_fault: bool,
+
+    /// Stop after this many seconds of wall-clock time, still writing the
+    /// report and artifacts.
+    #[arg(long, value_name = "SECS")]
+    pub wall_timeout: Option<u64>,
+
+    /// Print a digest of the final simulation state, for checking that two
+    /// runs of the same seed end in the same state.
+    #[arg(long)]
+    pub print_state_hash: bool,
+
+    /// Log a `STATE_HASH` telemetry event with the state digest after every
+    /// this many events.
+    #[arg(long, value_name = "EVENTS")]
+    pub state_hash_every: Option<u64>,
+
+    /// Print the effective configuration as JSON and exit without running.
+    #[arg(long)]
+    pub dump_config: bool,
+
+    /// Keep the events that scroll out of the TUI's recent-events buffer in
+    /// segment files in this directory, for paging through after the run.
+    #[arg(long, value_name = "DIR")]
+    pub event_spill: Option<PathBuf>,
+
+    /// The disk budget of `--event-spill`, in MiB. The oldest events are
+    /// deleted beyond it.
+    #[arg(long, value_name = "MIB", default_value_t = 64)]
+    pub event_spill_mib: u64,
+
+    /// Write every logged event to this file, one JSON object per line.
+    /// Events are written on a thread of their own; any the disk cannot keep
+    /// up with are dropped and counted rather than slowing the run. Like
+    /// the other outputs, a file named `*.gz` or `*.zst` is compressed, in
+    /// a build with the `compression` feature.
+    #[arg(long, value_name = "FILE")]
+    pub events_out: Option<PathBuf>,
+
+    /// Serve the engine's counters on this address for Prometheus to
+    /// scrape while the run goes, e.g. `127.0.0.1:9184`. Needs a build with
+    /// the `metrics_prom` feature.
+    #[arg(long, value_name = "ADDR")]
+    pub metrics_listen: Option<SocketAddr>,
+
+    /// Split the events file and the message and store journals into
+    /// segments of about this many MiB as they are written,
+    /// `messages-0001.jsonl` and so on for `messages.jsonl`, with a
+    /// `messages.manifest.json` listing each segment's sim-time range.
+    /// Fractions are allowed.
+    #[arg(long, value_name = "MIB")]
+    pub rotate_mib: Option<f64>,
+
+    /// Keep only the newest this many segments of each rotated output.
+    #[arg(long, value_name = "N", requires = "rotate_mib")]
+    pub keep_segments: Option<usize>,
+
+    /// Keep only the newest segments of each rotated output that fit in
+    /// this many MiB.
+    #[arg(long, value_name = "MIB", requires = "rotate_mib")]
+    pub keep_mib: Option<f64>,
+}
+
+/// Flags that change how the engine executes a scenario. `replay` takes
+/// them too, since it must execute the scenario like the recorded run did.
+#[derive(Args, Debug, Clone, Default)]
+pub struct EngineOpts {
+    /// How to handle messages a protocol fails to decode: drop,
+    /// count-and-continue or fail. Overrides the scenario's `on_codec_error`.
+    #[arg(long)]
+    pub on_codec_error: Option<CodecErrorPolicy>,
+
+    /// Stop after processing this many events. Overrides the scenario's
+    /// `stop_after_events`.
+    #[arg(long)]
+    pub max_events: Option<u64>,
+
+    /// Stop once only UI ticks and periodic directives remain queued.
+    #[arg(long)]
+    pub stop_on_quiescence: bool,
+}
+
+/// Named preset bundles of run defaults.
+#[derive(ValueEnum, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunMode {
+    /// Light telemetry, determinism canary on, short default budget.
+    Quick,
+    /// Detailed journaling, invariants, message traces, and artifacts.
+    Thorough,
+    /// All optional telemetry off; profiler and throughput reporting on.
+    Benchmark,
+}
+
+/// Color themes of the TUI.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TuiTheme {
+    Dark,
+    Light,
+    HighContrast,
+    /// No colors; node statuses are told apart by symbol only.
+    Mono,
+}
+
+/// How much simulation logging to emit.
+#[derive(ValueEnum, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TelemetryLevel {
+    Off,
+    Normal,
+    Detailed,
 }
 
 #[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
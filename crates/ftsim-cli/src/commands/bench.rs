@@ -0,0 +1,337 @@
+//! # ftsim-cli::commands::bench
+//!
+//! Implements the `bench` subcommand, which times a fixed set of synthetic
+//! engine workloads: the event queue, the message pipeline, snapshot
+//! construction, store operations and writing journal records out. Every
+//! workload uses a pinned seed, runs without a telemetry consumer or
+//! logging, and reports operations per wall-clock second. Results can be
+//! saved and compared against a baseline from an earlier run.
+
+use crate::args::BenchOpts;
+use anyhow::Result;
+use ftsim_engine::{
+    prelude::*,
+    segments::{self, RotationPolicy, SegmentWriter},
+    sim::EngineCtx,
+};
+use ftsim_proto::api::StoreView as ProtoStoreView;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{fs, hint::black_box, io::Write, path::Path, time::Instant};
+
+/// The seed every benchmark's simulation and RNG is created with.
+const SEED: u64 = 0x5eed;
+
+/// One benchmark's best run.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BenchResult {
+    pub name: String,
+    pub ops: u64,
+    pub secs: f64,
+    pub ops_per_sec: f64,
+}
+
+/// The results of a `bench` run, as written by `--save-baseline`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BenchReport {
+    pub scale: f64,
+    pub runs: usize,
+    pub results: Vec<BenchResult>,
+}
+
+/// A benchmark: its name, its operation count at scale 1, and a workload
+/// that performs that many operations (or another count, scaled) and
+/// returns how many it performed.
+struct Bench {
+    name: &'static str,
+    ops: u64,
+    run: fn(u64) -> u64,
+}
+
+const BENCHES: &[Bench] = &[
+    Bench { name: "event_queue", ops: 5_000_000, run: event_queue },
+    Bench { name: "message_pipeline/3_nodes", ops: 300_000, run: |ops| message_pipeline(3, ops) },
+    Bench { name: "message_pipeline/32_nodes", ops: 100_000, run: |ops| message_pipeline(32, ops) },
+    Bench { name: "snapshot/10_nodes", ops: 20_000, run: |ops| snapshot(10, ops) },
+    Bench { name: "snapshot/100_nodes", ops: 2_000, run: |ops| snapshot(100, ops) },
+    Bench { name: "snapshot/1000_nodes", ops: 20, run: |ops| snapshot(1000, ops) },
+    Bench { name: "store/mem", ops: 1_000_000, run: |ops| store(None, ops) },
+    Bench { name: "store/faulty", ops: 1_000_000, run: |ops| store(Some(faults()), ops) },
+    Bench { name: "output/jsonl", ops: 500_000, run: |ops| output("jsonl", None, ops) },
+    Bench { name: "output/rotated", ops: 500_000, run: |ops| output("jsonl", Some(RotationPolicy::new(1 << 20)), ops) },
+    #[cfg(feature = "compression")]
+    Bench { name: "output/gzip", ops: 500_000, run: |ops| output("jsonl.gz", None, ops) },
+    #[cfg(feature = "compression")]
+    Bench { name: "output/zstd", ops: 500_000, run: |ops| output("jsonl.zst", None, ops) },
+];
+
+pub fn exec(opts: BenchOpts) -> Result<()> {
+    if opts.scale.is_nan() || opts.scale <= 0.0 {
+        return Err(anyhow::anyhow!("--scale must be positive, got {}", opts.scale));
+    }
+    let runs = opts.runs.max(1);
+    let benches: Vec<&Bench> =
+        BENCHES.iter().filter(|b| opts.filter.as_ref().map_or(true, |f| b.name.contains(f.as_str()))).collect();
+    if benches.is_empty() {
+        return Err(anyhow::anyhow!("No benchmark matches '{}'", opts.filter.unwrap_or_default()));
+    }
+    println!("Running {} benchmarks at scale {}, best of {} runs", benches.len(), opts.scale, runs);
+
+    let mut report = BenchReport { scale: opts.scale, runs, results: Vec::new() };
+    for bench in benches {
+        let ops = ((bench.ops as f64 * opts.scale) as u64).max(1);
+        let result = (0..runs)
+            .map(|_| measure(bench, ops))
+            .max_by(|a, b| a.ops_per_sec.total_cmp(&b.ops_per_sec))
+            .expect("at least one run");
+        println!(
+            "   • {:<28} {:>14.0} ops/s ({} ops in {:.3}s)",
+            result.name, result.ops_per_sec, result.ops, result.secs
+        );
+        report.results.push(result);
+    }
+
+    if let Some(path) = &opts.save_baseline {
+        fs::write(path, serde_json::to_string_pretty(&report)?)?;
+        println!("📋 Baseline written to {}", path.display());
+    }
+    if let Some(path) = &opts.baseline {
+        compare(&report, path, opts.max_regression)?;
+    }
+    Ok(())
+}
+
+fn measure(bench: &Bench, ops: u64) -> BenchResult {
+    let started = Instant::now();
+    let ops = (bench.run)(ops);
+    let secs = started.elapsed().as_secs_f64();
+    BenchResult {
+        name: bench.name.to_string(),
+        ops,
+        secs,
+        ops_per_sec: if secs > 0.0 { ops as f64 / secs } else { 0.0 },
+    }
+}
+
+/// Prints each benchmark's change against the report at `baseline`, and
+/// fails if any is more than `max_regression` percent slower.
+fn compare(report: &BenchReport, baseline: &Path, max_regression: f64) -> Result<()> {
+    let before: BenchReport = serde_json::from_str(&fs::read_to_string(baseline)?)
+        .map_err(|e| anyhow::anyhow!("{} is not a bench baseline: {}", baseline.display(), e))?;
+    if before.scale != report.scale {
+        println!("⚠️  The baseline ran at scale {}; rates may not be comparable", before.scale);
+    }
+    println!("⏱️  Compared with {}:", baseline.display());
+    println!("   {:<28} {:>14} {:>14} {:>9}", "benchmark", "baseline", "now", "change");
+    let mut regressed = Vec::new();
+    for result in &report.results {
+        let Some(old) = before.results.iter().find(|r| r.name == result.name).filter(|r| r.ops_per_sec > 0.0) else {
+            println!("   {:<28} {:>14} {:>14.0} {:>9}", result.name, "-", result.ops_per_sec, "new");
+            continue;
+        };
+        let change = (result.ops_per_sec - old.ops_per_sec) / old.ops_per_sec * 100.0;
+        let mark = if -change > max_regression { " ❌" } else { "" };
+        println!(
+            "   {:<28} {:>14.0} {:>14.0} {:>+8.1}%{}",
+            result.name, old.ops_per_sec, result.ops_per_sec, change, mark
+        );
+        if -change > max_regression {
+            regressed.push(format!("{} ({:.1}%)", result.name, -change));
+        }
+    }
+    if !regressed.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Benchmarks regressed by more than the allowed {}%: {}",
+            max_regression,
+            regressed.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Keeps a simulation's event queue at a steady 1024 events, each operation
+/// running the earliest, a timer no protocol set, and scheduling another a
+/// random delay after it.
+fn event_queue(ops: u64) -> u64 {
+    const DEPTH: u64 = 1024;
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut sim = simulation(16, 0);
+    let schedule = |sim: &mut Simulation, time: SimTime, i: u64| {
+        let node_id = (i % 16) as NodeId;
+        let event = Event::TimerFired { node_id, timer_id: TimerId(u64::MAX) };
+        sim.schedule_at(time, event, EventDiscriminant::timer(node_id));
+    };
+    for i in 0..DEPTH {
+        schedule(&mut sim, rng.gen_range(0..sim_from_ms(10)), i);
+    }
+    for i in 0..ops {
+        let now = sim.step().expect("the queue is never empty");
+        schedule(&mut sim, now + rng.gen_range(0..sim_from_ms(10)), i);
+    }
+    black_box(sim.events_processed());
+    ops
+}
+
+/// Passes tokens around a ring of `nodes` until about `messages` have been
+/// delivered, counting one operation per delivered message.
+fn message_pipeline(nodes: usize, messages: u64) -> u64 {
+    let hops = (messages / nodes as u64).max(1);
+    let mut sim = simulation(nodes, hops);
+    sim.run().metrics.messages_delivered
+}
+
+/// Builds telemetry snapshots of an idle world of `nodes` nodes.
+fn snapshot(nodes: usize, ops: u64) -> u64 {
+    let sim = simulation(nodes, 0);
+    for _ in 0..ops {
+        black_box(sim.telemetry().build_snapshot(sim.world(), sim.now()));
+    }
+    ops
+}
+
+/// Rates that make roughly one operation in a hundred fail.
+fn faults() -> StoreFaultModel {
+    StoreFaultModel {
+        fsync_fail_rate: 0.01,
+        write_error_rate: 0.01,
+        read_error_rate: 0.01,
+        torn_write_rate: 0.01,
+        stale_read_rate: 0.01,
+        bit_rot_rate: 0.01,
+        ..Default::default()
+    }
+}
+
+/// Cycles through log appends, log reads, KV puts, KV gets and fsyncs on a
+/// memory store, through a fault-injecting view if `faults` is given.
+fn store(faults: Option<StoreFaultModel>, ops: u64) -> u64 {
+    let mut sim = simulation(1, 0);
+    let mut ctx = EngineCtx { sim: &mut sim, current_node_id: Some(0), store_delay: 0, last_sent: None };
+    let mut mem = MemStore::new();
+    let mut faulty;
+    let view: &mut dyn ProtoStoreView = match &faults {
+        Some(model) => {
+            faulty = FaultyStoreView::new(&mut mem, model, &mut ctx);
+            &mut faulty
+        }
+        None => &mut mem,
+    };
+    let value = bytes::Bytes::from_static(&[0xab; 64]);
+    for i in 0..ops {
+        let key = bytes::Bytes::from((i % 1024).to_le_bytes().to_vec());
+        // Failed operations still count; they are part of the workload
+        let _ = match i % 5 {
+            0 => view.append_log(LogRecord::new(1, value.clone())).map(drop),
+            1 => view.read_log(i / 5).map(drop),
+            2 => view.kv_put(key, value.clone()),
+            3 => view.kv_get(&key).map(drop),
+            _ => view.fsync(),
+        };
+    }
+    ops
+}
+
+/// A journal-sized record for the output benchmarks.
+#[derive(Serialize)]
+struct OutputRecord {
+    time: SimTime,
+    node: NodeId,
+    op: &'static str,
+    len: usize,
+    digest: u64,
+}
+
+/// Writes `ops` records to a temporary JSONL file with `extension`, which
+/// picks its compression, into segments rotated by `rotation` if given, to
+/// weigh what rotation and compression add to the write path.
+fn output(extension: &str, rotation: Option<RotationPolicy>, ops: u64) -> u64 {
+    let dir = std::env::temp_dir().join(format!("ftsim-bench-output-{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("temporary directory");
+    let path = dir.join(format!("records.{}", extension));
+    let record = |i: u64| OutputRecord {
+        time: i as SimTime * 1_000,
+        node: (i % 16) as NodeId,
+        op: "append_log",
+        len: 64,
+        digest: i.wrapping_mul(0x9e37_79b9_7f4a_7c15),
+    };
+    match rotation {
+        Some(policy) => {
+            let mut out = SegmentWriter::create(&path, policy).expect("rotated output");
+            for i in 0..ops {
+                out.append(i as SimTime * 1_000, &record(i)).expect("write");
+            }
+            black_box(out.finish().expect("finish"));
+        }
+        None => {
+            let mut out = segments::create_output(&path).expect("output");
+            for i in 0..ops {
+                serde_json::to_writer(&mut out, &record(i)).expect("write");
+                out.write_all(b"\n").expect("write");
+            }
+            out.finish().expect("finish");
+        }
+    }
+    fs::remove_dir_all(&dir).ok();
+    ops
+}
+
+/// A ready-to-run simulation of `nodes` token ring nodes on a full mesh,
+/// with a detached telemetry bus.
+fn simulation(nodes: usize, hops: u64) -> Simulation {
+    let world = World {
+        nodes: (0..nodes)
+            .map(|i| {
+                Node::new(i as NodeId, Box::new(TokenRing { nodes: nodes as NodeId, hops }), Box::new(MemStore::new()))
+            })
+            .collect(),
+        net: Net::from_topology(nodes, &TopologySpec::FullMesh),
+    };
+    let telemetry = TelemetryBus::detached(nodes, &TelemetrySpec::default());
+    let mut sim = Simulation::new(SEED, world, telemetry);
+    sim.init();
+    sim
+}
+
+/// Each node starts a token that travels `hops` hops around the ring.
+struct TokenRing {
+    nodes: NodeId,
+    hops: u64,
+}
+
+impl TokenRing {
+    fn forward(&self, ctx: &mut dyn ProtoCtx, hops: u64) {
+        let next = (ctx.node_id() + 1) % self.nodes;
+        ctx.send_raw(next, self.proto_tag(), bytes::Bytes::from(hops.to_le_bytes().to_vec()));
+    }
+}
+
+impl ProtocolDyn for TokenRing {
+    fn name(&self) -> &'static str {
+        "token_ring"
+    }
+
+    fn proto_tag(&self) -> ProtoTag {
+        ProtoTag(100)
+    }
+
+    fn init(&mut self, _ctx: &mut dyn ProtoCtx) {}
+
+    fn start(&mut self, ctx: &mut dyn ProtoCtx) {
+        if self.hops > 0 {
+            self.forward(ctx, self.hops);
+        }
+    }
+
+    fn on_message(&mut self, ctx: &mut dyn ProtoCtx, _src: NodeId, bytes: &[u8]) -> Result<(), CodecError> {
+        let hops = u64::from_le_bytes(bytes.try_into().map_err(|_| CodecError("bad token".into()))?);
+        if hops > 1 {
+            self.forward(ctx, hops - 1);
+        }
+        Ok(())
+    }
+
+    fn on_timer(&mut self, _ctx: &mut dyn ProtoCtx, _timer: TimerId) {}
+
+    fn on_fault(&mut self, _ctx: &mut dyn ProtoCtx, _fault: FaultEvent) {}
+}
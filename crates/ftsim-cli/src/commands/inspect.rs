@@ -0,0 +1,191 @@
+//! # ftsim-cli::commands::inspect
+//!
+//! Implements the `inspect` subcommand.
+
+use anyhow::Result;
+use crate::wiring::load_scenario;
+use ftsim_engine::{
+    flow::FlowGraph,
+    net::MessageJournal,
+    prelude::{sim_from_ms, SimTime},
+    rng::{DrawPositions, PositionEntry, SitePosition},
+    segments,
+};
+use std::{
+    fs,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+/// The file in an artifact directory that holds per-event RNG draw
+/// positions.
+pub const RNG_POSITIONS_FILE: &str = "rng-positions.jsonl";
+
+/// How many entries around a divergence `--rng-diff` shows from each run.
+const DIFF_CONTEXT: usize = 3;
+
+pub fn exec(
+    journal: Option<PathBuf>,
+    rng_diff: Option<Vec<PathBuf>>,
+    flow_graph: Option<Option<PathBuf>>,
+    by_variant: bool,
+    scenario: Option<PathBuf>,
+    range_ms: (Option<u64>, Option<u64>),
+) -> Result<()> {
+    let range = match range_ms {
+        (None, None) => None,
+        (from, to) => Some((sim_from_ms(from.unwrap_or(0)), to.map_or(SimTime::MAX, sim_from_ms))),
+    };
+    match (journal, rng_diff.as_deref()) {
+        (_, Some([a, b])) => rng_diff_exec(a, b),
+        (Some(journal), _) if flow_graph.is_some() => {
+            flow_graph_exec(journal, range, flow_graph.flatten(), by_variant, scenario)
+        }
+        (Some(journal), _) => journal_exec(journal, range),
+        _ => unreachable!("clap requires a journal or two runs"),
+    }
+}
+
+fn read_positions(path: &Path) -> Result<DrawPositions> {
+    let file_path = if path.is_dir() { path.join(RNG_POSITIONS_FILE) } else { path.to_path_buf() };
+    let file = fs::File::open(&file_path).map_err(|e| anyhow::anyhow!("{}: {}", file_path.display(), e))?;
+    Ok(DrawPositions::read_jsonl(BufReader::new(file))?)
+}
+
+fn describe(entry: &PositionEntry) -> String {
+    let sites: Vec<String> = entry
+        .sites
+        .iter()
+        .map(|(site, position)| format!("{}={} (last {:#x})", site, position.draws, position.last))
+        .collect();
+    let event = entry.event_id.map_or("-".to_string(), |id| id.to_string());
+    format!("event {} at t={} {}: {}", event, entry.time, entry.kind, sites.join(", "))
+}
+
+fn rng_diff_exec(a: &Path, b: &Path) -> Result<()> {
+    let (left, right) = (read_positions(a)?, read_positions(b)?);
+    let Some(divergence) = left.first_divergence(&right) else {
+        println!(
+            "RNG draw positions match across all {} events of {} and {}",
+            left.entries.len(),
+            a.display(),
+            b.display()
+        );
+        return Ok(());
+    };
+    let event = |entry: &Option<PositionEntry>| match entry.as_ref().map(|e| e.event_id) {
+        Some(Some(id)) => id.to_string(),
+        Some(None) => "outside events".to_string(),
+        None => "end of run".to_string(),
+    };
+    println!(
+        "RNG draw positions diverge at entry #{}: event {} in {}, event {} in {}",
+        divergence.index,
+        event(&divergence.left),
+        a.display(),
+        event(&divergence.right),
+        b.display()
+    );
+    let position = |p: &Option<SitePosition>| p.map_or("no draws".to_string(), |p| p.to_string());
+    for (site, l, r) in &divergence.sites {
+        println!("  {}: {} vs {}", site, position(l), position(r));
+    }
+    let from = divergence.index.saturating_sub(DIFF_CONTEXT);
+    for (path, positions) in [(a, &left), (b, &right)] {
+        println!("{}:", path.display());
+        for (i, entry) in positions.entries.iter().enumerate().skip(from).take(2 * DIFF_CONTEXT + 1) {
+            let marker = if i == divergence.index { ">" } else { " " };
+            println!("{} #{} {}", marker, i, describe(entry));
+        }
+    }
+    Err(anyhow::anyhow!("RNG draw positions diverge at entry #{}", divergence.index))
+}
+
+/// Reads a journal, rotated or not, keeping the messages sent in `range`.
+fn read_journal(path: &Path, range: Option<(SimTime, SimTime)>) -> Result<MessageJournal> {
+    let input = segments::open_input(path, range).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+    let mut journal = MessageJournal::read_jsonl(input)?;
+    if let Some((from, to)) = range {
+        journal.retain(|r| (from..=to).contains(&r.sent_at));
+    }
+    Ok(journal)
+}
+
+/// Renders a flow graph as JSON if `path` ends in `.json`, DOT otherwise.
+pub fn render_flow_graph(graph: &FlowGraph, path: &Path) -> String {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => graph.to_json(),
+        _ => graph.to_dot(),
+    }
+}
+
+fn flow_graph_exec(
+    path: PathBuf,
+    range: Option<(SimTime, SimTime)>,
+    out: Option<PathBuf>,
+    by_variant: bool,
+    scenario: Option<PathBuf>,
+) -> Result<()> {
+    let journal = read_journal(&path, range)?;
+    let phases = match &scenario {
+        Some(scenario) => load_scenario(scenario)?.phases,
+        None => Vec::new(),
+    };
+    let graph = FlowGraph::from_journal(&journal, by_variant, &phases);
+    let Some(out) = out else {
+        print!("{}", graph.to_dot());
+        return Ok(());
+    };
+    fs::write(&out, render_flow_graph(&graph, &out))?;
+    println!(
+        "Flow graph of {} messages from {}: {} edges written to {}",
+        journal.records().len(),
+        path.display(),
+        graph.edges().len(),
+        out.display()
+    );
+    if let Some(sampling) = journal.sampling() {
+        println!("Sampled at rate {}; the graph only covers the sampled messages", sampling.rate);
+    }
+    Ok(())
+}
+
+fn journal_exec(path: PathBuf, range: Option<(SimTime, SimTime)>) -> Result<()> {
+    let journal = read_journal(&path, range)?;
+    let suspects = journal.suspects();
+    println!(
+        "Inspected {} messages from {}: {} suspect",
+        journal.records().len(),
+        path.display(),
+        suspects.len()
+    );
+    if let Some(sampling) = journal.sampling() {
+        let sampled = journal.records().iter().filter(|r| r.sampled).count();
+        println!(
+            "Sampled at rate {} (always including {:?}); {} sampled records, about {:.0} messages in the run",
+            sampling.rate,
+            sampling.always_include,
+            sampled,
+            if sampling.rate > 0.0 { sampled as f64 / sampling.rate } else { 0.0 }
+        );
+    }
+    for suspect in suspects {
+        let r = suspect.record;
+        let reasons: Vec<String> = suspect.reasons.iter().map(ToString::to_string).collect();
+        let view = match r.view {
+            Some(v) => format!("incarnation {}, byzantine {}", v.incarnation, v.byzantine),
+            None => "sender view not sampled".to_string(),
+        };
+        println!(
+            "  msg {} {}->{} tag {} sent at t={} ({}): {}",
+            r.msg_id,
+            r.src,
+            r.dst,
+            r.proto_tag.0,
+            r.sent_at,
+            view,
+            reasons.join(", ")
+        );
+    }
+    Ok(())
+}
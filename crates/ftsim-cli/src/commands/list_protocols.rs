@@ -2,17 +2,30 @@
 //!
 //! Implements the `list-protocols` subcommand.
 
-use crate::wiring::get_registry;
+use crate::wiring::ProtocolRegistry;
 use anyhow::Result;
+use std::io::IsTerminal;
 
-pub fn exec() -> Result<()> {
+pub fn exec(all: bool) -> Result<()> {
+    let registry = ProtocolRegistry::builtin();
     println!("Available Protocols:");
     println!("{:<20} | {:<10}", "Name", "ProtoTag");
     println!("{:-<20}-|-{:-<10}", "", "");
 
-    for (name, tag, _) in get_registry() {
+    for (name, tag, _) in registry.protocols() {
         println!("{:<20} | {:<10}", name, tag.0);
     }
 
+    if all {
+        // Greyed out on a terminal; the note says the same in plain text
+        let (dim, reset) = if std::io::stdout().is_terminal() { ("\x1b[2m", "\x1b[0m") } else { ("", "") };
+        for known in registry.disabled() {
+            println!(
+                "{}{:<20} | {:<10} (not compiled in; rebuild with --features {}){}",
+                dim, known.name, known.tag.0, known.feature, reset
+            );
+        }
+    }
+
     Ok(())
 }
@@ -3,5 +3,10 @@
 //! This module contains the implementation of all CLI subcommands.
 
 pub mod run;
+pub mod bench;
+pub mod inspect;
 pub mod list_protocols;
+pub mod new_scenario;
+pub mod replay;
+pub mod sweep;
 pub mod validate;
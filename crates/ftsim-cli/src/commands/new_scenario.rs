@@ -0,0 +1,55 @@
+//! # ftsim-cli::commands::new_scenario
+//!
+//! Implements the `new-scenario` subcommand.
+
+use crate::{
+    args::{NewScenarioOpts, Template},
+    templates::{RegionDrill, REGION_DRILL_STAGES},
+    wiring::ProtocolRegistry,
+};
+use anyhow::{anyhow, Result};
+use ftsim_engine::prelude::*;
+use std::fs;
+
+pub fn exec(opts: NewScenarioOpts) -> Result<()> {
+    let proto = ProtocolRegistry::builtin().tag(&opts.protocol)?;
+
+    let (scenario, header) = match opts.template {
+        Template::RegionDrill => {
+            let drill = RegionDrill {
+                regions: opts.regions,
+                nodes_per_region: opts.nodes_per_region,
+                proto,
+                region: opts.region,
+                stage: sim_from_ms(opts.stage_ms),
+                degraded_delay: sim_from_ms(opts.degraded_delay_ms),
+                seed: opts.seed,
+            };
+            let scenario = drill.build().map_err(|e| anyhow!(e))?;
+            let header = format!(
+                "# Scenario: Region Evacuation Drill\n\
+                 #\n\
+                 # Generated by `ftsim new-scenario --template region-drill`.\n\
+                 # {} regions of {} {} nodes; region {} is drilled in {}ms stages:\n\
+                 # {}.\n\n",
+                opts.regions,
+                opts.nodes_per_region,
+                opts.protocol,
+                opts.region,
+                opts.stage_ms,
+                REGION_DRILL_STAGES.join(", "),
+            );
+            (scenario, header)
+        }
+    };
+
+    let text = header + &toml::to_string(&scenario)?;
+    match &opts.output {
+        Some(path) => {
+            fs::write(path, text)?;
+            println!("Wrote scenario '{}' to {}", scenario.name, path.display());
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
@@ -0,0 +1,89 @@
+//! # ftsim-cli::commands::replay
+//!
+//! Implements the `replay` subcommand.
+
+use crate::{
+    args::EngineOpts,
+    wiring::{apply_engine_settings, build_world, finalize_world_setup, load_scenario},
+};
+use anyhow::Result;
+use ftsim_engine::{
+    prelude::*,
+    rng::{EventTrace, RngRecording},
+    scenario::{load_and_schedule, register_invariants},
+    segments,
+};
+use std::{io::BufRead, path::Path, path::PathBuf};
+
+fn open(path: &Path) -> Result<Box<dyn BufRead>> {
+    segments::open_input(path, None).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+}
+
+pub fn exec(
+    trace_path: Option<PathBuf>,
+    scenario_path: PathBuf,
+    rng_path: Option<PathBuf>,
+    invariants: Option<bool>,
+    engine: EngineOpts,
+) -> Result<()> {
+    let trace = match &trace_path {
+        Some(path) => Some(EventTrace::read_from(open(path)?)?),
+        None => None,
+    };
+    let recording = match &rng_path {
+        Some(path) => Some(RngRecording::read_from(open(path)?)?),
+        None => None,
+    };
+    let scenario = load_scenario(&scenario_path)?;
+    let seed = match (&trace, &recording) {
+        (Some(trace), Some(recording)) if trace.seed != recording.seed => {
+            return Err(anyhow::anyhow!(
+                "The trace was recorded with seed {} but the RNG draws with seed {}",
+                trace.seed,
+                recording.seed
+            ));
+        }
+        (Some(trace), _) => trace.seed,
+        (None, Some(recording)) => recording.seed,
+        (None, None) => unreachable!("clap requires a trace or an RNG recording"),
+    };
+    if let Some(trace) = &trace {
+        println!("Replaying {} events of scenario '{}' with seed: {}", trace.events.len(), scenario.name, seed);
+    }
+    if let Some(recording) = &recording {
+        println!("Checking {} RNG draws of scenario '{}' with seed: {}", recording.draws.len(), scenario.name, seed);
+    }
+
+    let mut world = build_world(&scenario)?;
+    finalize_world_setup(&mut world);
+    let num_nodes = world.nodes.len();
+    let telemetry = TelemetryBus::detached(num_nodes, &scenario.telemetry);
+
+    let mut sim = Simulation::new(seed, world, telemetry);
+    apply_engine_settings(&mut sim, &scenario, &engine);
+    // Draws made while protocols start are checked too
+    let checking_rng = recording.is_some();
+    if let Some(recording) = recording {
+        sim.check_rng(recording);
+    }
+    sim.init();
+    load_and_schedule(&mut sim, &scenario)?;
+    // Checked by default, as in a run without a mode
+    if invariants.unwrap_or(true) {
+        register_invariants(&mut sim, &scenario)?;
+    }
+
+    if let Some(trace) = trace {
+        match sim.replay(trace) {
+            Ok(events) => println!("Replay matched the trace ({} events).", events),
+            Err(divergence) => return Err(anyhow::anyhow!("{}", divergence)),
+        }
+    }
+    if checking_rng {
+        match sim.replay_rng() {
+            Ok(draws) => println!("Replay matched the RNG recording ({} draws).", draws),
+            Err(mismatch) => return Err(anyhow::anyhow!("{}", mismatch)),
+        }
+    }
+    Ok(())
+}
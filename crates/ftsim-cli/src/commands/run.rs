@@ -4,26 +4,62 @@
 
 use crate::{
     args::RunOpts,
+    crash_report,
     logging::{HeadlessFormatter, SimulationFormatter},
-    wiring::{build_world, finalize_world_setup, get_seed},
+    options::{RunConfig, RunMeta, RunOptions},
+    wiring::{apply_engine_settings, build_world, finalize_world_setup, get_seed, load_scenario, run_phases},
 };
 use anyhow::Result;
-use ftsim_engine::{prelude::*, scenario::load_and_schedule, telemetry::tracing_layer::SimContextLayer};
-use std::fs;
+use ftsim_engine::{
+    control::{BreakKind, Breakpoint, ControlMsg},
+    prelude::*,
+    scenario::{load_and_schedule, register_invariants},
+    segments::{self, RotationPolicy},
+    state_hash::StateHasher,
+    telemetry::{spill::EventSpill, tracing_layer::SimContextLayer},
+    timeline::{self, RenderOpts, DEFAULT_TIMELINE_WIDTH},
+};
+use std::{
+    fs,
+    hash::Hasher,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 use tracing_subscriber::prelude::*;
 
+/// How many engine categories `--profile` lists under the run time.
+const PROFILE_TOP_CATEGORIES: usize = 5;
+
 pub fn exec(opts: RunOpts) -> Result<()> {
+    let run_opts = RunOptions::resolve(&opts);
+
     // 1. Parse scenario ONCE
-    let content = fs::read_to_string(&opts.scenario)?;
-    let scenario: Scenario = match opts.scenario.extension().and_then(|s| s.to_str()) {
-        Some("yaml") | Some("yml") => serde_yaml::from_str(&content)?,
-        Some("toml") => toml::from_str(&content)?,
-        _ => return Err(anyhow::anyhow!("Unsupported scenario file extension")),
-    };
-    scenario.validate().map_err(|e| anyhow::anyhow!(e))?;
+    let scenario = load_scenario(&opts.scenario)?;
+    let outputs = [&opts.store_journal, &opts.msg_journal, &opts.record, &opts.record_rng, &opts.events_out];
+    for path in outputs.into_iter().flatten() {
+        segments::check_compression(path)?;
+    }
+    let mib_to_bytes = |mib: f64| (mib.max(0.0) * (1024.0 * 1024.0)) as u64;
+    let rotation = opts.rotate_mib.map(|mib| RotationPolicy {
+        max_segment_bytes: mib_to_bytes(mib),
+        keep_segments: opts.keep_segments,
+        keep_bytes: opts.keep_mib.map(mib_to_bytes),
+    });
 
     let seed = get_seed(opts.seed, scenario.seed);
-    println!("Running scenario '{}' with seed: {}", scenario.name, seed);
+    if !opts.dump_config {
+        println!("Running scenario '{}' with seed: {}", scenario.name, seed);
+    }
+
+    let mut scenario_hasher = StateHasher::new();
+    scenario_hasher.write(&fs::read(&opts.scenario)?);
+    let started_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let meta = RunMeta {
+        run_id: format!("{}-{}", started_at.as_millis(), std::process::id()),
+        scenario: scenario.name.clone(),
+        scenario_hash: format!("{:016x}", scenario_hasher.finish()),
+        seed,
+        mode: run_opts.mode,
+    };
 
     // 2. Build and finalize the world
     let mut world = build_world(&scenario)?;
@@ -33,11 +69,21 @@ pub fn exec(opts: RunOpts) -> Result<()> {
     // 3. Setup Telemetry and Control Channels
     let (snapshot_tx, snapshot_rx) = crossbeam_channel::unbounded();
     let (control_tx, control_rx) = crossbeam_channel::unbounded();
-    let telemetry = TelemetryBus::new(snapshot_tx, num_nodes);
+    let events_out = opts.events_out.clone().filter(|_| !opts.dump_config);
+    let telemetry = TelemetryBus::with_events_out(snapshot_tx, num_nodes, &scenario.telemetry, events_out, rotation)?;
     let sim_context_layer = SimContextLayer::new(&telemetry);
+    if let Some(dir) = opts.event_spill.as_ref().filter(|_| !opts.dump_config) {
+        telemetry.set_event_spill(EventSpill::create(dir, opts.event_spill_mib.saturating_mul(1024 * 1024))?);
+    }
+    if let Some(addr) = opts.metrics_listen.filter(|_| !opts.dump_config) {
+        install_metrics_exporter(addr)?;
+    }
     
-    // Setup enhanced logging based on headless mode
-    if opts.headless {
+    // Setup enhanced logging based on headless mode. A config dump prints
+    // nothing but the JSON, so it installs no subscriber.
+    if opts.dump_config {
+        // Leave stdout to the dump
+    } else if opts.headless {
         // Use simplified formatter for headless mode
         tracing_subscriber::registry()
             .with(sim_context_layer)
@@ -46,13 +92,19 @@ pub fn exec(opts: RunOpts) -> Result<()> {
                     .event_format(HeadlessFormatter)
                     .with_ansi(true)
             )
-            .with(tracing_subscriber::EnvFilter::from_default_env().add_directive("ftsim=info".parse().unwrap()))
+            .with(tracing_subscriber::EnvFilter::from_default_env().add_directive(run_opts.log_directive().parse().unwrap()))
             .init();
         
         println!("\n🎮 Starting FTSim headless execution...");
         println!("📊 Scenario: {}", scenario.name);
         println!("🎲 Seed: {}", seed);
         println!("⚙️  Nodes: {}", num_nodes);
+        if let Some(addr) = opts.metrics_listen {
+            println!("📡 Metrics: http://{}/metrics", addr);
+        }
+        if let Some(mode) = meta.mode {
+            println!("🧭 Mode: {:?}", mode);
+        }
         println!("{}", "=".repeat(60));
     } else {
         // Use detailed formatter for interactive/TUI mode
@@ -67,14 +119,110 @@ pub fn exec(opts: RunOpts) -> Result<()> {
             .init();
     }
 
-    // 4. Setup TUI (feature-gated)
+    // 4. Create the simulation
     let use_tui = !opts.headless;
+    #[cfg(not(feature = "tui"))]
+    if use_tui && !opts.dump_config {
+        println!("Warning: TUI requested but 'tui' feature is not enabled. Running headless.");
+    }
+    let tui_enabled = use_tui && cfg!(feature = "tui");
 
+    let setup_started = Instant::now();
+    let crash_telemetry = telemetry.clone();
+    let mut sim = Simulation::new(seed, world, telemetry);
+    // Headless runs have no controller, so a breakpoint there ends the run;
+    // their channel only carries the Ctrl-C shutdown
+    if tui_enabled {
+        sim.set_control_channel(control_rx);
+    } else {
+        sim.set_shutdown_channel(control_rx);
+    }
+    sim.set_wall_timeout(opts.wall_timeout.map(Duration::from_secs));
+    sim.set_state_hash_interval(opts.state_hash_every);
+    if opts.break_at_time.is_some() || opts.break_on_fault {
+        sim.add_breakpoint(Breakpoint {
+            kind: opts.break_on_fault.then_some(BreakKind::Fault),
+            from: opts.break_at_time.map(sim_from_ms),
+            ..Breakpoint::default()
+        });
+    }
+    apply_engine_settings(&mut sim, &scenario, &opts.engine);
+    if opts.store_journal.is_some() {
+        sim.enable_store_journal();
+    }
+    let artifact_journal =
+        run_opts.artifact_dir.as_ref().filter(|_| run_opts.msg_trace).map(|d| d.join("messages.jsonl"));
+    let msg_journals: Vec<_> = opts.msg_journal.iter().cloned().chain(artifact_journal).collect();
+    if !msg_journals.is_empty() {
+        match &scenario.journal_sampling {
+            Some(sampling) => sim.enable_sampled_message_journal(sampling.clone()),
+            None => sim.enable_message_journal(),
+        }
+    }
+    if opts.flow_graph_out.is_some() {
+        sim.enable_flow_graph(opts.flow_graph_by_variant, &scenario.phases);
+    }
+    if opts.record.is_some() {
+        sim.record_trace();
+    }
+    if opts.record_rng.is_some() {
+        sim.record_rng();
+    }
+    if run_opts.profile {
+        sim.enable_profiling();
+    }
+    if run_opts.artifact_dir.is_some() && (opts.record.is_some() || run_opts.journal) {
+        sim.record_draw_positions();
+    }
+    sim.init();
+    load_and_schedule(&mut sim, &scenario)?;
+    if run_opts.invariants {
+        register_invariants(&mut sim, &scenario)?;
+    }
+    let setup_elapsed = setup_started.elapsed();
+
+    let stop_at = opts
+        .stop_at
+        .map(sim_from_ms)
+        .or(scenario.stop_at)
+        .or(run_opts.default_stop_at);
+    let run_config = RunConfig {
+        scenario: scenario.name.clone(),
+        mode: run_opts.mode,
+        log_level: run_opts.telemetry,
+        stop_at,
+        headless: !tui_enabled,
+        artifact_dir: run_opts.artifact_dir.clone(),
+        sim: sim.effective_config(),
+    };
+    if opts.dump_config {
+        println!("{}", serde_json::to_string_pretty(&run_config)?);
+        return Ok(());
+    }
+    if let Some(dir) = &run_opts.artifact_dir {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("meta.json"), serde_json::to_string_pretty(&meta)?)?;
+        fs::write(dir.join("config.json"), serde_json::to_string_pretty(&run_config)?)?;
+    }
+    // Rotated journals are written out as the run goes
+    if let Some(policy) = rotation {
+        if let Some(path) = &opts.store_journal {
+            sim.stream_store_journal(path, policy)?;
+        }
+        for path in &msg_journals {
+            sim.stream_message_journal(path, policy)?;
+        }
+    }
+
+    // 5. Setup TUI (feature-gated)
     #[cfg(feature = "tui")]
-    let tui_handle = if use_tui {
+    let tui_handle = if tui_enabled {
         let control_tx_clone = control_tx.clone();
+        let config_summary = run_config.summary();
+        let theme = tui_theme(opts.theme)?;
+        let event_spill = opts.event_spill.clone();
         Some(std::thread::spawn(move || {
-            ftsim_tui::run_tui(snapshot_rx, control_tx_clone).expect("TUI failed");
+            ftsim_tui::run_tui(snapshot_rx, control_tx_clone, config_summary, theme, event_spill).expect("TUI failed");
         }))
     } else {
         None
@@ -82,61 +230,395 @@ pub fn exec(opts: RunOpts) -> Result<()> {
 
     #[cfg(not(feature = "tui"))]
     let tui_handle: Option<std::thread::JoinHandle<()>> = {
-        if use_tui {
-            println!("Warning: TUI requested but 'tui' feature is not enabled. Running headless.");
-        }
+        // Nothing else reads snapshots
+        drop(snapshot_rx);
         None
     };
 
-    // 5. Create and run the simulation
-    let mut sim = Simulation::new(seed, world, telemetry);
-    sim.set_control_channel(control_rx);
-    sim.init();
-    load_and_schedule(&mut sim, &scenario)?;
-
-    if use_tui && tui_handle.is_some() {
+    if tui_handle.is_some() {
         sim.schedule_at(0, Event::UiSnapshotTick, EventDiscriminant::ui());
+    } else {
+        install_shutdown_handler(control_tx.clone());
     }
+    crash_report::install(
+        meta.clone(),
+        crash_telemetry,
+        run_opts.artifact_dir.clone(),
+        tui_handle.is_some(),
+    );
 
-    if let Some(stop_at_ms) = opts.stop_at {
-        sim.run_until(sim_from_ms(stop_at_ms));
-    } else if let Some(stop_at_ns) = scenario.stop_at {
-        sim.run_until(stop_at_ns);
-    } else {
-        sim.run();
+    // 6. Run the simulation
+    let run_started = Instant::now();
+
+    // Run up to the end of each phase in turn and check its expectations
+    let (mut report, failed_phases) = run_phases(&mut sim, &scenario, stop_at, |phase, failures| {
+        if failures.is_empty() {
+            println!("✅ Phase '{}' passed", phase.name);
+        } else {
+            println!("❌ Phase '{}' failed: {}", phase.name, failures.join("; "));
+        }
+    });
+    let run_elapsed = run_started.elapsed();
+    sim.publish_finished(&report.outcome);
+    let mut usage = sim.resource_usage();
+    if run_opts.profile {
+        usage.profile.insert("setup".to_string(), setup_elapsed.as_nanos() as u64);
+    }
+    report.usage = Some(usage.clone());
+
+    // The TUI restores the terminal when the user quits, which also shuts
+    // the run down; wait for it before printing anything else
+    if let Some(handle) = tui_handle {
+        handle.join().map_err(|_| anyhow::anyhow!("TUI thread panicked"))?;
+    }
+
+    if run_opts.profile {
+        println!("⏱️  Profile:");
+        println!("   • Setup: {:?}", setup_elapsed);
+        println!("   • Run: {:?}", run_elapsed);
+        for (category, ns) in usage.top_categories(PROFILE_TOP_CATEGORIES) {
+            println!("     • {}: {:?}", category, Duration::from_nanos(ns));
+        }
+    }
+    if run_opts.throughput {
+        let events = report.events_processed;
+        let secs = run_elapsed.as_secs_f64();
+        let rate = if secs > 0.0 { events as f64 / secs } else { 0.0 };
+        println!("🚀 Throughput: {} events in {:.3}s ({:.0} events/s)", events, secs, rate);
+    }
+    // The TUI's snapshot ticks are events of their own, which a second run
+    // would not have
+    if run_opts.canary && !tui_enabled {
+        let hash = run_canary(&sim, &scenario, &opts, seed, run_opts.invariants, stop_at)?;
+        println!("🐤 Determinism canary: a second run reached the same state ({:016x})", hash);
+    }
+
+    if let Some(path) = &opts.report_json {
+        fs::write(path, serde_json::to_string_pretty(&report)?)?;
+        println!("📋 Report written to {}", path.display());
+    }
+    if opts.print_state_hash {
+        println!("🔑 State hash: {:016x}", sim.state_hash());
+    }
+
+    let (store_segments, message_segments) = sim.finish_journal_streams()?;
+    if let (Some(path), Some(journal)) = (&opts.store_journal, sim.store_journal()) {
+        match store_segments {
+            Some(manifest) => print_segments("Store journal", "mutations", path, &manifest),
+            None => {
+                let mut out = segments::create_output(path)?;
+                journal.write_jsonl(&mut out)?;
+                out.finish()?;
+                println!("📝 Store journal: {} mutations written to {}", journal.entries().len(), path.display());
+            }
+        }
+    }
+    if let Some(journal) = sim.message_journal() {
+        for path in msg_journals.iter().skip(message_segments.len()) {
+            let mut out = segments::create_output(path)?;
+            journal.write_jsonl(&mut out)?;
+            out.finish()?;
+            println!("📝 Message journal: {} messages written to {}", journal.records().len(), path.display());
+        }
+        for (path, manifest) in msg_journals.iter().zip(&message_segments) {
+            print_segments("Message journal", "messages", path, manifest);
+        }
+        if let Some(sampling) = journal.sampling() {
+            println!("   • Sampled at rate {}, always including {:?}", sampling.rate, sampling.always_include);
+        }
+    }
+
+    if let (Some(path), Some(graph)) = (&opts.flow_graph_out, sim.flow_graph()) {
+        fs::write(path, super::inspect::render_flow_graph(graph, path))?;
+        println!("🕸️  Flow graph: {} edges written to {}", graph.edges().len(), path.display());
+    }
+
+    if let (Some(path), Some(trace)) = (&opts.record, sim.event_trace()) {
+        let mut out = segments::create_output(path)?;
+        trace.write_to(&mut out)?;
+        out.finish()?;
+        println!("🎞️  Event trace: {} events written to {}", trace.events.len(), path.display());
+    }
+    if let Some((path, summary)) = sim.telemetry().finish_events_out() {
+        match &summary.manifest {
+            Some(manifest) => print_segments("Events", "events", &path, manifest),
+            None => println!("🧾 Events: {} events written to {}", summary.written, path.display()),
+        }
+        if summary.dropped > 0 {
+            println!("   • {} events dropped because the writer fell behind", summary.dropped);
+        }
+    }
+    if let (Some(path), Some(recording)) = (&opts.record_rng, sim.rng_recording()) {
+        let mut out = segments::create_output(path)?;
+        recording.write_to(&mut out)?;
+        out.finish()?;
+        println!("🎲 RNG draws: {} values written to {}", recording.draws.len(), path.display());
+    }
+    if let (Some(dir), Some(positions)) = (&run_opts.artifact_dir, sim.draw_positions()) {
+        let path = dir.join(super::inspect::RNG_POSITIONS_FILE);
+        positions.write_jsonl(std::io::BufWriter::new(fs::File::create(&path)?))?;
+        println!("🎲 RNG positions: {} events written to {}", positions.entries.len(), path.display());
+    }
+
+    if let Some(failure) = sim.codec_failure() {
+        return Err(anyhow::anyhow!("Simulation stopped on codec error: {}", failure));
+    }
+
+    // 7. Shutdown and Summary
+    let width = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(DEFAULT_TIMELINE_WIDTH);
+    let render_opts = RenderOpts {
+        bucket: opts.timeline_bucket.map(sim_from_ms),
+        width,
+    };
+    let rendered_timeline = timeline::render(sim.timeline(), sim.now(), &render_opts);
+    if let Some(dir) = &run_opts.artifact_dir {
+        fs::write(dir.join("timeline.txt"), &rendered_timeline)?;
     }
 
-    // 6. Shutdown and Summary
     if opts.headless {
         println!("{}", "=".repeat(60));
-        println!("🏁 Simulation completed successfully!");
-        
-        // Get final snapshot for summary
-        let final_snapshot = sim.telemetry().build_snapshot(&sim.world(), sim.now());
+        println!("🛑 Run ended: {}", report.outcome);
+        match sim.breakpoint_hit() {
+            Some(breakpoint) => {
+                println!("⏸️  Stopped at breakpoint '{}' with the clock at t={}", breakpoint, sim.now())
+            }
+            None => println!("🏁 Simulation completed successfully!"),
+        }
+
         println!("📈 Final Metrics:");
-        println!("   • Messages Sent: {}", final_snapshot.metrics.messages_sent);
-        println!("   • Messages Delivered: {}", final_snapshot.metrics.messages_delivered);
-        println!("   • Timers Fired: {}", final_snapshot.metrics.timers_fired);
-        println!("   • Faults Injected: {}", final_snapshot.metrics.faults_injected);
+        println!("   • Events Processed: {}", report.events_processed);
+        println!("   • Messages Sent: {}", report.metrics.messages_sent);
+        println!("   • Messages Delivered: {}", report.metrics.messages_delivered);
+        if report.metrics.delivery_latency.count > 0 {
+            println!("   • Delivery Latency: {}", report.metrics.delivery_latency);
+        }
+        println!("   • Timers Fired: {}", report.metrics.timers_fired);
+        println!("   • Faults Injected: {}", report.metrics.faults_injected);
+        if report.metrics.client_requests > 0 {
+            let m = &report.metrics;
+            let mean = m.client_request_latency_ns.checked_div(m.client_responses).unwrap_or(0);
+            println!(
+                "   • Client Requests: {} ({} answered, mean latency {}ns)",
+                m.client_requests, m.client_responses, mean
+            );
+            if m.client_request_latency.count > 0 {
+                println!("   • Client Latency: {}", m.client_request_latency);
+            }
+        }
+        for (key, h) in &report.metrics.histograms {
+            println!("   • {}: p50 {}, p95 {}, p99 {} over {}", key, h.p50, h.p95, h.p99, h.count);
+        }
+        if report.metrics.delay_clamped > 0 {
+            println!("   • Delays Clamped: {} (check the scenario's delay distributions)", report.metrics.delay_clamped);
+        }
+        if report.metrics.gray_failure_ignored > 0 {
+            println!("   • Ignored by Gray Failures: {}", report.metrics.gray_failure_ignored);
+        }
+        if report.metrics.simultaneous_restarts > 0 {
+            println!("   • Simultaneous Restarts: {} (within the same millisecond)", report.metrics.simultaneous_restarts);
+        }
+        for condition in &report.conditions {
+            println!("   • Condition {}", condition);
+        }
+        if let Some(slo) = &report.slo {
+            match slo.attainment {
+                Some(attainment) => println!(
+                    "   • SLO p99 ≤ {} ms: met in {:.1}% of {} windows",
+                    slo.slo.p99_ms,
+                    attainment,
+                    slo.windows.iter().filter(|w| w.requests > 0).count()
+                ),
+                None => println!("   • SLO p99 ≤ {} ms: no window with requests judged", slo.slo.p99_ms),
+            }
+            for window in slo.violations() {
+                println!("⚠️  SLO violated: {}", window);
+            }
+        }
+        if let Some(convergence) = &report.convergence {
+            println!("   • Convergence: {}", convergence);
+        }
+        for (node, deferred) in &report.flood_deferrals {
+            println!("⚠️  Flood valve deferred {} events of node {}; timings after its floods are shifted", deferred, node);
+        }
+        for intervention in &report.interventions {
+            match intervention.hit {
+                Some(hit) => println!("🎯 Intervention '{}' hit message {} at t={}", intervention, hit.msg_id, hit.time),
+                None => println!(
+                    "⚠️  Intervention '{}' never hit: only {} matching messages were sent",
+                    intervention, intervention.seen
+                ),
+            }
+        }
+        if !sim.codec_error_counts().is_empty() {
+            let rejected: u64 = sim.codec_error_counts().values().sum();
+            println!("   • Messages Rejected: {} (failed to decode)", rejected);
+            for ((tag, dst), count) in sim.codec_error_counts() {
+                println!("     - tag {} -> node {}: {}", tag.0, dst, count);
+            }
+        }
+        if !sim.unroutable_counts().is_empty() {
+            println!("   • Unroutable Messages:");
+            for ((tag, dst), count) in sim.unroutable_counts() {
+                println!("     - tag {} -> node {}: {}", tag.0, dst, count);
+            }
+        }
         
         println!("\n🏷️  Final Node States:");
-        for node_snap in final_snapshot.nodes {
-            let role = node_snap.custom.get("role")
-                .and_then(|v| v.as_str())
+        for node in &report.nodes {
+            let role = report
+                .node_state(node.id, "role")
+                .and_then(|r| r.as_str())
+                .or_else(|| report.node_kv(node.id, "role"))
                 .unwrap_or("unknown");
-            let data_entries = node_snap.custom.get("data_entries")
-                .and_then(|v| v.as_str())
-                .unwrap_or("0");
+            let data_entries = report.node_metric(node.id, "data_entries").unwrap_or(0.0);
             println!("   • Node {}: {} [{} status] - {} data entries", 
-                     node_snap.id, role, format!("{:?}", node_snap.status).to_lowercase(), data_entries);
+                     node.id, role, format!("{:?}", node.status).to_lowercase(), data_entries);
+            if let Some(serde_json::Value::Object(state)) = node.custom.get(STATE_KEY) {
+                let fields: Vec<String> = state
+                    .iter()
+                    .filter(|(key, _)| key.as_str() != "role")
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect();
+                println!("     state: {}", fields.join(", "));
+            }
+            if let Some(store) = &node.store {
+                let last_term = store.last_log_term.map_or("-".to_string(), |t| t.to_string());
+                println!("     store: log {} (last term {}), {} kv keys, ~{} bytes",
+                         store.log_len, last_term, store.kv_keys, store.approx_bytes);
+                if let Some(keys) = &store.keys {
+                    println!("     keys: {}", keys.join(", "));
+                }
+            }
         }
+
+        println!("\n🕒 Timeline:");
+        print!("{}", rendered_timeline);
+        println!("⏱️  {}", usage);
     }
 
-    if let Some(handle) = tui_handle {
-        // In a real app, we'd signal the TUI to shut down.
-        // For now, the user quits with 'q'. We'll let the process exit.
-        // handle.join().expect("TUI thread panicked");
+    if let Some(violation) = sim.invariant_violation() {
+        return Err(anyhow::anyhow!("Simulation stopped: {}", violation));
+    }
+
+    if !failed_phases.is_empty() {
+        return Err(anyhow::anyhow!("Phase checks failed: {}", failed_phases.join(", ")));
     }
 
     Ok(())
 }
+
+/// Runs the scenario again with the same seed and no telemetry consumer,
+/// for no more events than `sim` processed, and fails unless it ends with
+/// the same state hash. Returns that hash.
+fn run_canary(
+    sim: &Simulation,
+    scenario: &Scenario,
+    opts: &RunOpts,
+    seed: u64,
+    invariants: bool,
+    stop_at: Option<SimTime>,
+) -> Result<u64> {
+    let mut world = build_world(scenario)?;
+    finalize_world_setup(&mut world);
+    let telemetry = TelemetryBus::detached(world.nodes.len(), &scenario.telemetry);
+    let mut canary = Simulation::new(seed, world, telemetry);
+    apply_engine_settings(&mut canary, scenario, &opts.engine);
+    // A first run cut short by the wall clock or Ctrl-C is matched up to
+    // where it stopped
+    canary.set_max_events(Some(sim.events_processed()));
+    canary.init();
+    load_and_schedule(&mut canary, scenario)?;
+    if invariants {
+        register_invariants(&mut canary, scenario)?;
+    }
+    run_phases(&mut canary, scenario, stop_at, |_, _| {});
+
+    let (first, second) = (sim.state_hash(), canary.state_hash());
+    if (first, sim.events_processed()) != (second, canary.events_processed()) {
+        return Err(anyhow::anyhow!(
+            "Determinism canary: a second run with seed {} processed {} events to state {:016x}, the first {} to {:016x}",
+            seed,
+            canary.events_processed(),
+            second,
+            sim.events_processed(),
+            first
+        ));
+    }
+    Ok(first)
+}
+
+/// Prints where a rotated output went and what retention dropped.
+fn print_segments(what: &str, unit: &str, path: &std::path::Path, manifest: &segments::Manifest) {
+    println!(
+        "📝 {}: {} {} written to {} segments listed in {}",
+        what,
+        manifest.records(),
+        unit,
+        manifest.segments.len(),
+        segments::manifest_path(path).display()
+    );
+    if manifest.dropped_segments > 0 {
+        println!(
+            "   • Retention deleted the {} oldest segments, {} {}",
+            manifest.dropped_segments, manifest.dropped_records, unit
+        );
+    }
+}
+
+/// Picks the TUI theme from `--theme`, then `FTSIM_THEME`.
+#[cfg(feature = "tui")]
+fn tui_theme(flag: Option<crate::args::TuiTheme>) -> Result<ftsim_tui::theme::Theme> {
+    use crate::args::TuiTheme;
+    use ftsim_tui::theme::{Theme, ThemeName};
+    let flag = flag.map(|theme| match theme {
+        TuiTheme::Dark => ThemeName::Dark,
+        TuiTheme::Light => ThemeName::Light,
+        TuiTheme::HighContrast => ThemeName::HighContrast,
+        TuiTheme::Mono => ThemeName::Mono,
+    });
+    let name = ThemeName::resolve(flag).map_err(anyhow::Error::msg)?;
+    Ok(Theme::new(name))
+}
+
+/// Serves the engine's `metrics` counters on `addr` for the rest of the
+/// process.
+#[cfg(feature = "metrics_prom")]
+fn install_metrics_exporter(addr: std::net::SocketAddr) -> Result<()> {
+    ftsim_engine::telemetry::prometheus::install(addr)
+        .map_err(|e| anyhow::anyhow!("Could not serve metrics on {}: {}", addr, e))
+}
+
+#[cfg(not(feature = "metrics_prom"))]
+fn install_metrics_exporter(_addr: std::net::SocketAddr) -> Result<()> {
+    anyhow::bail!("--metrics-listen needs the Prometheus exporter; rebuild with --features metrics_prom")
+}
+
+/// Turns the first Ctrl-C into a clean shutdown, so the run still writes its
+/// report and artifacts. A second Ctrl-C exits right away.
+#[cfg(unix)]
+fn install_shutdown_handler(control_tx: crossbeam_channel::Sender<ControlMsg>) {
+    use signal_hook::{consts::SIGINT, iterator::Signals};
+    let mut signals = match Signals::new([SIGINT]) {
+        Ok(signals) => signals,
+        Err(err) => {
+            tracing::warn!("Could not install the Ctrl-C handler: {}", err);
+            return;
+        }
+    };
+    std::thread::spawn(move || {
+        let mut signals = signals.forever();
+        if signals.next().is_some() {
+            eprintln!("\n🛑 Interrupted; finishing the current event (Ctrl-C again to abort)");
+            let _ = control_tx.send(ControlMsg::Shutdown);
+        }
+        if signals.next().is_some() {
+            std::process::exit(130);
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn install_shutdown_handler(_control_tx: crossbeam_channel::Sender<ControlMsg>) {}
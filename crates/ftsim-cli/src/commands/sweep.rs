@@ -0,0 +1,256 @@
+//! # ftsim-cli::commands::sweep
+//!
+//! Implements the `sweep` subcommand, which runs one scenario across a range
+//! of seeds on a pool of threads and aggregates the reports. Every run builds
+//! its own world, telemetry bus and RNG, so a seed's report does not depend on
+//! which thread ran it or what ran beside it.
+
+use crate::{
+    args::SweepOpts,
+    wiring::{build_world, finalize_world_setup, load_scenario, run_phases},
+};
+use anyhow::Result;
+use ftsim_engine::{
+    prelude::*,
+    scenario::{load_and_schedule, register_invariants},
+};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+/// The result of running the scenario with one seed.
+#[derive(Serialize, Debug, Clone)]
+pub struct SeedRun {
+    pub seed: u64,
+    pub failed_phases: Vec<String>,
+    pub invariant_violation: Option<String>,
+    pub codec_failure: Option<String>,
+    pub report: SimulationReport,
+}
+
+impl SeedRun {
+    pub fn failed(&self) -> bool {
+        !self.failed_phases.is_empty() || self.invariant_violation.is_some() || self.codec_failure.is_some()
+    }
+}
+
+/// The spread of one metric across runs.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Distribution {
+    pub min: u64,
+    pub p50: u64,
+    pub max: u64,
+    pub mean: f64,
+}
+
+impl Distribution {
+    fn of(mut values: Vec<u64>) -> Self {
+        values.sort_unstable();
+        let n = values.len().max(1);
+        Self {
+            min: values.first().copied().unwrap_or(0),
+            p50: values.get(values.len() / 2).copied().unwrap_or(0),
+            max: values.last().copied().unwrap_or(0),
+            mean: values.iter().sum::<u64>() as f64 / n as f64,
+        }
+    }
+}
+
+/// The aggregate over every run of a sweep. Apart from `throughput` and each
+/// run's `report.usage`, it contains no wall-clock data, so sweeping the same
+/// seeds again produces the same summary.
+#[derive(Serialize, Debug, Clone)]
+pub struct SweepSummary {
+    pub scenario: String,
+    pub seeds: Vec<u64>,
+    /// The seeds of runs that violated an invariant, failed a phase or
+    /// stopped on a codec error, for replaying with `ftsim run --seed`.
+    pub failed_seeds: Vec<u64>,
+    pub invariant_violations: usize,
+    pub phase_failures: usize,
+    /// Number of runs per outcome.
+    pub outcomes: BTreeMap<&'static str, usize>,
+    pub metrics: BTreeMap<&'static str, Distribution>,
+    /// Events per wall-clock second across runs. Runs executed side by side
+    /// compete for the machine, so compare sweeps with the same `--parallel`.
+    pub throughput: Distribution,
+    pub runs: Vec<SeedRun>,
+}
+
+impl SweepSummary {
+    pub fn new(scenario: &str, runs: Vec<SeedRun>) -> Self {
+        let mut outcomes = BTreeMap::new();
+        for run in &runs {
+            *outcomes.entry(run.report.outcome.reason()).or_insert(0) += 1;
+        }
+        let metric = |f: fn(&SimulationReport) -> u64| Distribution::of(runs.iter().map(|r| f(&r.report)).collect());
+        let metrics = BTreeMap::from([
+            ("events_processed", metric(|r| r.events_processed)),
+            ("final_time_ms", metric(|r| (r.final_time / 1_000_000) as u64)),
+            ("messages_sent", metric(|r| r.metrics.messages_sent)),
+            ("messages_delivered", metric(|r| r.metrics.messages_delivered)),
+            ("timers_fired", metric(|r| r.metrics.timers_fired)),
+            ("faults_injected", metric(|r| r.metrics.faults_injected)),
+        ]);
+        let throughput = Distribution::of(
+            runs.iter()
+                .filter_map(|r| r.report.usage.as_ref())
+                .map(|u| u.events_per_sec as u64)
+                .collect(),
+        );
+        Self {
+            scenario: scenario.to_string(),
+            seeds: runs.iter().map(|r| r.seed).collect(),
+            failed_seeds: runs.iter().filter(|r| r.failed()).map(|r| r.seed).collect(),
+            invariant_violations: runs.iter().filter(|r| r.invariant_violation.is_some()).count(),
+            phase_failures: runs.iter().filter(|r| !r.failed_phases.is_empty()).count(),
+            outcomes,
+            metrics,
+            throughput,
+            runs,
+        }
+    }
+}
+
+pub fn exec(opts: SweepOpts) -> Result<()> {
+    let scenario = load_scenario(&opts.scenario)?;
+    let first = opts.first_seed.or(scenario.seed).unwrap_or(0);
+    let seeds: Vec<u64> = (0..opts.seeds).map(|i| first.wrapping_add(i)).collect();
+    let threads = opts
+        .parallel
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+        .clamp(1, seeds.len().max(1));
+    let stop_at = opts.stop_at.map(sim_from_ms).or(scenario.stop_at);
+    println!(
+        "Sweeping scenario '{}' over {} seeds from {} on {} threads",
+        scenario.name,
+        seeds.len(),
+        first,
+        threads
+    );
+
+    let started = Instant::now();
+    let runs = run_seeds(&scenario, &seeds, stop_at, threads)?;
+    let elapsed = started.elapsed();
+    let summary = SweepSummary::new(&scenario.name, runs);
+
+    println!("🧪 {} runs in {:?}, {} failed", summary.runs.len(), elapsed, summary.failed_seeds.len());
+    let outcomes: Vec<String> = summary.outcomes.iter().map(|(o, n)| format!("{} {}", o, n)).collect();
+    println!("   • Outcomes: {}", outcomes.join(", "));
+    println!("   • Invariant violations: {}", summary.invariant_violations);
+    println!("   • Phase failures: {}", summary.phase_failures);
+    println!("📈 Metrics (min / p50 / max, mean):");
+    for (name, d) in &summary.metrics {
+        println!("   • {}: {} / {} / {}, {:.1}", name, d.min, d.p50, d.max, d.mean);
+    }
+    let t = &summary.throughput;
+    println!("⏱️  Events/s: {} / {} / {}, {:.1}", t.min, t.p50, t.max, t.mean);
+    for run in summary.runs.iter().filter(|r| r.failed()) {
+        let reason = run
+            .invariant_violation
+            .clone()
+            .or(run.codec_failure.clone())
+            .unwrap_or_else(|| format!("phases failed: {}", run.failed_phases.join(", ")));
+        println!("❌ Seed {}: {}", run.seed, reason);
+    }
+
+    if let Some(path) = &opts.report_json {
+        fs::write(path, serde_json::to_string_pretty(&summary)?)?;
+        println!("📋 Sweep report written to {}", path.display());
+    }
+
+    if let Some(path) = &opts.baseline {
+        check_throughput(&summary, path, opts.max_slowdown)?;
+    }
+
+    if !summary.failed_seeds.is_empty() {
+        let seeds: Vec<String> = summary.failed_seeds.iter().map(|s| s.to_string()).collect();
+        return Err(anyhow::anyhow!(
+            "{} of {} runs failed; replay with `ftsim run --scenario {} --seed <seed>` for seeds {}",
+            seeds.len(),
+            summary.runs.len(),
+            opts.scenario.display(),
+            seeds.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Fails if the median events/sec of `summary` is more than `max_slowdown`
+/// percent below that of the sweep report at `baseline`.
+fn check_throughput(summary: &SweepSummary, baseline: &std::path::Path, max_slowdown: f64) -> Result<()> {
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(baseline)?)?;
+    let Some(before) = json["throughput"]["p50"].as_u64().filter(|p50| *p50 > 0) else {
+        return Err(anyhow::anyhow!("{} has no throughput to compare against", baseline.display()));
+    };
+    let now = summary.throughput.p50;
+    let change = (now as f64 - before as f64) / before as f64 * 100.0;
+    println!("⏱️  Median events/s: {} vs {} in baseline ({:+.1}%)", now, before, change);
+    if -change > max_slowdown {
+        return Err(anyhow::anyhow!(
+            "Throughput regressed by {:.1}%, more than the allowed {}%",
+            -change,
+            max_slowdown
+        ));
+    }
+    Ok(())
+}
+
+/// Runs every seed on `threads` worker threads, returning the runs in seed
+/// order.
+fn run_seeds(scenario: &Scenario, seeds: &[u64], stop_at: Option<SimTime>, threads: usize) -> Result<Vec<SeedRun>> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<SeedRun>>>> = Mutex::new((0..seeds.len()).map(|_| None).collect());
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(&seed) = seeds.get(i) else {
+                    break;
+                };
+                let run = run_seed(scenario, seed, stop_at);
+                results.lock().unwrap()[i] = Some(run);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|run| run.expect("every seed is run"))
+        .collect()
+}
+
+/// Builds and runs an independent simulation of the scenario with `seed`.
+fn run_seed(scenario: &Scenario, seed: u64, stop_at: Option<SimTime>) -> Result<SeedRun> {
+    let mut world = build_world(scenario)?;
+    finalize_world_setup(&mut world);
+    let telemetry = TelemetryBus::detached(world.nodes.len(), &scenario.telemetry);
+    let mut sim = Simulation::new(seed, world, telemetry);
+    sim.set_codec_error_policy(scenario.on_codec_error);
+    sim.set_scheduling_policy(scenario.scheduling);
+    sim.set_future_message_policy(scenario.future_message_policy);
+    sim.set_flood_valve(scenario.flood_valve);
+    sim.set_max_events(scenario.stop_after_events);
+    sim.set_stop_on_quiescence(scenario.stop_on_quiescence);
+    sim.init();
+    load_and_schedule(&mut sim, scenario)?;
+    register_invariants(&mut sim, scenario)?;
+
+    let (mut report, failed_phases) = run_phases(&mut sim, scenario, stop_at, |_, _| {});
+    report.usage = Some(sim.resource_usage());
+    Ok(SeedRun {
+        seed,
+        failed_phases,
+        invariant_violation: sim.invariant_violation().map(|v| v.to_string()),
+        codec_failure: sim.codec_failure().map(|f| f.to_string()),
+        report,
+    })
+}
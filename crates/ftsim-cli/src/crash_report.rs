@@ -0,0 +1,83 @@
+//! # ftsim-cli::crash_report
+//!
+//! Writes a crash report when `run` panics, so a failed run can be
+//! reproduced: the run's identity, the event the engine was executing (from
+//! `ftsim_engine::crash_context`), queue statistics and the most recent
+//! telemetry events. The report goes to the artifact directory, or to the
+//! system temp directory when there is none.
+
+use crate::options::RunMeta;
+use ftsim_engine::{
+    crash_context::{self, EventContext},
+    prelude::*,
+    telemetry::snapshot::LogSnap,
+};
+use serde::Serialize;
+use std::{
+    fs,
+    panic::PanicInfo,
+    path::{Path, PathBuf},
+};
+
+/// The most telemetry events a report lists.
+const RECENT_EVENTS: usize = 50;
+
+/// What a crash report records.
+#[derive(Serialize, Debug)]
+pub struct CrashReport<'a> {
+    #[serde(flatten)]
+    pub meta: &'a RunMeta,
+    pub panic: String,
+    /// Where the panic was raised, as `file:line:column`.
+    pub location: Option<String>,
+    /// The event being executed and the queue around it, or `None` if the
+    /// panic happened outside the engine's event loop.
+    pub event: Option<EventContext>,
+    /// The last telemetry events logged before the panic, oldest first.
+    pub recent_events: Vec<LogSnap>,
+}
+
+/// Installs a panic hook that writes a crash report for the run described
+/// by `meta`, restores the terminal if the TUI is active, then hands the
+/// panic to the previous hook.
+pub fn install(meta: RunMeta, telemetry: TelemetryBus, dir: Option<PathBuf>, tui: bool) {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let path = match &dir {
+            Some(dir) => dir.join("crash-report.json"),
+            None => std::env::temp_dir().join(format!("ftsim-crash-{}.json", meta.run_id)),
+        };
+        let written = write(&path, &meta, &telemetry, info);
+        if tui {
+            #[cfg(feature = "tui")]
+            ftsim_tui::restore_terminal();
+        }
+        match written {
+            Ok(()) => eprintln!("💥 Crash report written to {}", path.display()),
+            Err(err) => eprintln!("💥 Could not write a crash report to {}: {}", path.display(), err),
+        }
+        previous(info);
+    }));
+}
+
+fn write(path: &Path, meta: &RunMeta, telemetry: &TelemetryBus, info: &PanicInfo<'_>) -> anyhow::Result<()> {
+    let panic = match (info.payload().downcast_ref::<&str>(), info.payload().downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "<non-string panic payload>".to_string(),
+    };
+    let mut recent_events = telemetry.try_recent_events().unwrap_or_default();
+    recent_events.drain(..recent_events.len().saturating_sub(RECENT_EVENTS));
+    let report = CrashReport {
+        meta,
+        panic,
+        location: info.location().map(|l| l.to_string()),
+        event: crash_context::current_event(),
+        recent_events,
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&report)?)?;
+    Ok(())
+}
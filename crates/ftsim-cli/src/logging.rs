@@ -1,10 +1,13 @@
 //! # ftsim-cli::logging
 //!
 //! Enhanced logging formatters for better visualization of simulation activity.
+//!
+//! Both take the sim time and node of each event from the stamp
+//! `SimContextLayer` leaves for them, so they must be layered after it.
 
+use ftsim_engine::telemetry::tracing_layer::{take_stamp, SimStamp};
 use std::fmt;
 use tracing::{Event, Subscriber};
-use tracing::field::Field;
 use tracing_subscriber::{
     fmt::{format::Writer, FormatEvent, FormatFields},
     registry::LookupSpan,
@@ -21,50 +24,21 @@ impl SimulationFormatter {
             timer: std::time::Instant::now(),
         }
     }
-
-    fn format_sim_time(sim_time_ns: u128) -> String {
-        if sim_time_ns == 0 {
-            "0ns".to_string()
-        } else if sim_time_ns < 1_000 {
-            format!("{}ns", sim_time_ns)
-        } else if sim_time_ns < 1_000_000 {
-            format!("{:.1}μs", sim_time_ns as f64 / 1_000.0)
-        } else if sim_time_ns < 1_000_000_000 {
-            format!("{:.1}ms", sim_time_ns as f64 / 1_000_000.0)
-        } else {
-            format!("{:.1}s", sim_time_ns as f64 / 1_000_000_000.0)
-        }
-    }
-
-    fn extract_node_id(event: &Event) -> Option<u32> {
-        let mut visitor = NodeIdExtractor::default();
-        event.record(&mut visitor);
-        visitor.node_id
-    }
-}
-
-#[derive(Default)]
-struct NodeIdExtractor {
-    node_id: Option<u32>,
 }
 
-impl tracing::field::Visit for NodeIdExtractor {
-    fn record_u64(&mut self, field: &Field, value: u64) {
-        if field.name() == "node_id" {
-            self.node_id = Some(value as u32);
-        }
-    }
-
-    fn record_i64(&mut self, field: &Field, value: i64) {
-        if field.name() == "node_id" && value >= 0 {
-            self.node_id = Some(value as u32);
-        }
+/// Formats a sim time with the largest unit it has a whole one of.
+fn format_sim_time(sim_time_ns: u128) -> String {
+    if sim_time_ns == 0 {
+        "0ns".to_string()
+    } else if sim_time_ns < 1_000 {
+        format!("{}ns", sim_time_ns)
+    } else if sim_time_ns < 1_000_000 {
+        format!("{:.1}μs", sim_time_ns as f64 / 1_000.0)
+    } else if sim_time_ns < 1_000_000_000 {
+        format!("{:.1}ms", sim_time_ns as f64 / 1_000_000.0)
+    } else {
+        format!("{:.1}s", sim_time_ns as f64 / 1_000_000_000.0)
     }
-
-    fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
-    fn record_str(&mut self, _field: &Field, _value: &str) {}
-    fn record_bool(&mut self, _field: &Field, _value: bool) {}
-    fn record_error(&mut self, _field: &Field, _value: &(dyn std::error::Error + 'static)) {}
 }
 
 impl<S, N> FormatEvent<S, N> for SimulationFormatter
@@ -80,25 +54,14 @@ where
     ) -> fmt::Result {
         let elapsed = self.timer.elapsed();
         let metadata = event.metadata();
-        
-        // Extract simulation time from span context if available
-        let sim_time = if let Some(_span) = ctx.lookup_current() {
-            // Try to extract sim_time from span extensions or fields
-            // This is a simplified approach; in practice you might store this differently
-            None
-        } else {
-            None
-        };
-
-        // Extract node ID from the event
-        let node_id = Self::extract_node_id(event);
+        let stamp = take_stamp();
 
         // Format timestamp
         write!(writer, "\x1b[90m[{:>8.3}s]\x1b[0m ", elapsed.as_secs_f64())?;
 
         // Add simulation time if available
-        if let Some(st) = sim_time {
-            write!(writer, "\x1b[36m(sim: {})\x1b[0m ", Self::format_sim_time(st))?;
+        if let Some(stamp) = stamp {
+            write!(writer, "\x1b[36m(sim: {})\x1b[0m ", format_sim_time(stamp.sim_time))?;
         }
 
         // Format level with color
@@ -113,7 +76,7 @@ where
         write!(writer, "{}[{:>5}]\x1b[0m ", level_color, level)?;
 
         // Add node ID if available
-        if let Some(nid) = node_id {
+        if let Some(nid) = stamp.and_then(|s| s.node_id) {
             write!(writer, "\x1b[35m[N{}]\x1b[0m ", nid)?;
         }
 
@@ -145,33 +108,24 @@ where
         event: &Event<'_>,
     ) -> fmt::Result {
         let metadata = event.metadata();
+        // Taken even from skipped events, so none is left for the next one
+        let stamp = take_stamp();
         
         // Skip debug and trace messages in headless mode
         if matches!(*metadata.level(), tracing::Level::DEBUG | tracing::Level::TRACE) {
             return Ok(());
         }
 
-        let target = metadata.target();
-        let node_id = Self::extract_node_id(event);
-
-        // Simplified format for key events
-        match target {
-            "events" => {
-                // These are simulation engine events, give them special treatment
-                if let Some(nid) = node_id {
-                    write!(writer, "🎯 N{} ", nid)?;
-                } else {
-                    write!(writer, "🎯 SIM ")?;
-                }
-            }
-            _ => {
-                // Protocol or other events
-                if let Some(nid) = node_id {
-                    write!(writer, "📋 N{} ", nid)?;
-                } else {
-                    write!(writer, "📋 --- ")?;
-                }
-            }
+        // Simulation engine events get special treatment
+        let engine_event = metadata.target() == "events";
+        let marker = if engine_event { "🎯" } else { "📋" };
+        match stamp.and_then(|s| s.node_id) {
+            Some(nid) => write!(writer, "{} N{} ", marker, nid)?,
+            None if engine_event => write!(writer, "{} SIM ", marker)?,
+            None => write!(writer, "{} --- ", marker)?,
+        }
+        if let Some(SimStamp { sim_time, .. }) = stamp {
+            write!(writer, "(sim: {}) ", format_sim_time(sim_time))?;
         }
 
         // Format the message without extra metadata
@@ -181,10 +135,113 @@ where
     }
 }
 
-impl HeadlessFormatter {
-    fn extract_node_id(event: &Event) -> Option<u32> {
-        let mut visitor = NodeIdExtractor::default();
-        event.record(&mut visitor);
-        visitor.node_id
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ftsim_engine::{prelude::*, telemetry::tracing_layer::SimContextLayer};
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+    use tracing_subscriber::{fmt::format::DefaultFields, layer::Layered, prelude::*, Registry};
+
+    /// Collects what a formatter writes.
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Capture {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8_lossy(&self.0.lock().unwrap()).lines().map(str::to_string).collect()
+        }
+    }
+
+    /// Runs `f` with `formatter` layered after a `SimContextLayer` on `bus`,
+    /// returning the lines it wrote.
+    fn capture<F>(bus: &TelemetryBus, formatter: F, f: impl FnOnce()) -> Vec<String>
+    where
+        F: FormatEvent<Layered<SimContextLayer, Registry>, DefaultFields> + Send + Sync + 'static,
+    {
+        let out = Capture::default();
+        let writer = out.clone();
+        let subscriber = tracing_subscriber::registry().with(SimContextLayer::new(bus)).with(
+            tracing_subscriber::fmt::layer().event_format(formatter).with_writer(move || writer.clone()),
+        );
+        tracing::subscriber::with_default(subscriber, f);
+        out.lines()
+    }
+
+    fn emit() {
+        tracing::info!(target: "events", "engine event");
+        tracing::info!(node_id = 1u32, "event with a node_id field");
+        tracing::error_span!("node", node_id = 2u32).in_scope(|| tracing::info!("event in a node span"));
+    }
+
+    #[test]
+    fn test_formatters_stamp_sim_time_and_node() {
+        let bus = TelemetryBus::detached(3, &TelemetrySpec::default());
+        bus.set_current_time(12_300_000, EventId(7));
+        for lines in [capture(&bus, HeadlessFormatter, emit), capture(&bus, SimulationFormatter::new(), emit)] {
+            assert_eq!(lines.len(), 3, "{:?}", lines);
+            assert!(lines.iter().all(|line| line.contains("(sim: 12.3ms)")), "{:?}", lines);
+            assert!(!lines[0].contains("[N") && !lines[0].contains(" N"), "{}", lines[0]);
+            assert!(lines[1].contains("N1"), "{}", lines[1]);
+            assert!(lines[2].contains("N2"), "{}", lines[2]);
+        }
+    }
+
+    #[test]
+    fn test_headless_marks_events_without_a_node_and_never_reuses_a_stamp() {
+        use tracing_subscriber::filter::filter_fn;
+        let bus = TelemetryBus::detached(3, &TelemetrySpec::default());
+        bus.set_current_time(12_300_000, EventId(7));
+        let out = Capture::default();
+        let writer = out.clone();
+        // The stamping layer skips the "unstamped" target
+        let subscriber = tracing_subscriber::registry()
+            .with(SimContextLayer::new(&bus).with_filter(filter_fn(|meta| meta.target() != "unstamped")))
+            .with(tracing_subscriber::fmt::layer().event_format(HeadlessFormatter).with_writer(move || writer.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "events", "engine event");
+            tracing::info!(node_id = 1u32, "stamped");
+            tracing::info!(target: "unstamped", "not stamped");
+            tracing::info!(node_id = %2u32, "node_id recorded with %");
+        });
+        let lines = out.lines();
+        assert!(lines[0].starts_with("🎯 SIM (sim: 12.3ms) "), "{}", lines[0]);
+        assert!(lines[1].starts_with("📋 N1 (sim: 12.3ms) "), "{}", lines[1]);
+        assert!(lines[2].starts_with("📋 --- ") && !lines[2].contains("sim:"), "{}", lines[2]);
+        assert!(lines[3].starts_with("📋 N2 "), "{}", lines[3]);
+    }
+
+    #[test]
+    #[cfg(feature = "raft_lite")]
+    fn test_protocol_events_carry_their_node() {
+        use ftsim_proto::{api::boxed_dyn, protocols::raft_lite::RaftLite};
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let bus = TelemetryBus::new(tx, 3, &TelemetrySpec::default());
+        let lines = capture(&bus, HeadlessFormatter, || {
+            let world = World::full_mesh(3, |_| boxed_dyn(RaftLite::default()));
+            let mut sim = Simulation::new(1, world, bus.clone());
+            sim.init();
+            sim.run_until(sim_from_ms(1_000));
+        });
+        // Raft logs its election timeouts without a node_id field
+        let timeouts: Vec<&String> = lines.iter().filter(|line| line.contains("Election timeout")).collect();
+        assert!(!timeouts.is_empty(), "{:?}", lines);
+        for line in timeouts {
+            assert!(line.contains("(sim: ") && !line.contains("(sim: 0ns)"), "{}", line);
+            assert!(["N0 ", "N1 ", "N2 "].iter().any(|node| line.contains(node)), "{}", line);
+        }
     }
 }
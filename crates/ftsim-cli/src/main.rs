@@ -11,7 +11,10 @@ use clap::Parser;
 
 mod args;
 mod commands;
+mod crash_report;
 mod logging;
+mod options;
+mod templates;
 mod wiring;
 
 fn main() -> Result<()> {
@@ -19,14 +22,25 @@ fn main() -> Result<()> {
 
     // Note: Tracing initialization is now handled inside the `run` command
     // to ensure it has access to the simulation-specific telemetry bus.
-    // A simple logger is used for other commands.
-    if !matches!(args.command, Command::Run(_)) {
+    // A sweep runs many simulations at once, so it logs nothing. A simple
+    // logger is used for other commands. Benchmarks run without logging so
+    // that it does not skew their timings.
+    if !matches!(args.command, Command::Run(_) | Command::Sweep(_) | Command::Bench(_)) {
         tracing_subscriber::fmt().with_env_filter("info").init();
     }
 
     match args.command {
-        Command::Run(opts) => commands::run::exec(opts),
-        Command::ListProtocols => commands::list_protocols::exec(),
+        Command::Run(opts) => commands::run::exec(*opts),
+        Command::Sweep(opts) => commands::sweep::exec(opts),
+        Command::Bench(opts) => commands::bench::exec(opts),
+        Command::ListProtocols { all } => commands::list_protocols::exec(all),
         Command::Validate { scenario } => commands::validate::exec(scenario),
+        Command::Replay { trace, scenario, rng, invariants, engine } => {
+            commands::replay::exec(trace, scenario, rng, invariants, engine)
+        }
+        Command::NewScenario(opts) => commands::new_scenario::exec(opts),
+        Command::Inspect { journal, rng_diff, flow_graph, by_variant, scenario, from_ms, to_ms } => {
+            commands::inspect::exec(journal, rng_diff, flow_graph, by_variant, scenario, (from_ms, to_ms))
+        }
     }
 }
@@ -0,0 +1,246 @@
+//! # ftsim-cli::options
+//!
+//! Resolves the effective run options from a `--mode` preset plus any
+//! explicitly passed flags. Presets are only structured defaults; an explicit
+//! flag always wins over the preset value.
+
+use crate::args::{RunMode, RunOpts, TelemetryLevel};
+use ftsim_engine::prelude::*;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// The default artifact directory used by presets that write artifacts.
+const DEFAULT_ARTIFACT_DIR: &str = "ftsim-artifacts";
+
+/// Sim-time budget applied by the quick preset when neither the command line
+/// nor the scenario sets a stop time.
+const QUICK_STOP_AT_MS: u64 = 10_000;
+
+/// The fully resolved options for a single run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunOptions {
+    pub mode: Option<RunMode>,
+    pub telemetry: TelemetryLevel,
+    pub canary: bool,
+    pub journal: bool,
+    pub invariants: bool,
+    pub msg_trace: bool,
+    pub profile: bool,
+    pub throughput: bool,
+    pub artifact_dir: Option<PathBuf>,
+    /// Stop time used when neither `--stop-at` nor the scenario sets one.
+    pub default_stop_at: Option<SimTime>,
+}
+
+impl RunOptions {
+    /// Returns the structured defaults for a mode (or for no mode at all).
+    pub fn preset(mode: Option<RunMode>) -> Self {
+        let base = Self {
+            mode,
+            telemetry: TelemetryLevel::Normal,
+            canary: false,
+            journal: false,
+            invariants: true,
+            msg_trace: false,
+            profile: false,
+            throughput: false,
+            artifact_dir: None,
+            default_stop_at: None,
+        };
+        match mode {
+            None => base,
+            Some(RunMode::Quick) => Self {
+                telemetry: TelemetryLevel::Off,
+                canary: true,
+                default_stop_at: Some(sim_from_ms(QUICK_STOP_AT_MS)),
+                ..base
+            },
+            Some(RunMode::Thorough) => Self {
+                telemetry: TelemetryLevel::Detailed,
+                journal: true,
+                invariants: true,
+                msg_trace: true,
+                artifact_dir: Some(PathBuf::from(DEFAULT_ARTIFACT_DIR)),
+                ..base
+            },
+            Some(RunMode::Benchmark) => Self {
+                telemetry: TelemetryLevel::Off,
+                invariants: false,
+                profile: true,
+                throughput: true,
+                ..base
+            },
+        }
+    }
+
+    /// Applies the preset selected by `--mode`, then any explicit flags.
+    pub fn resolve(opts: &RunOpts) -> Self {
+        let preset = Self::preset(opts.mode);
+        Self {
+            mode: opts.mode,
+            telemetry: opts.telemetry.unwrap_or(preset.telemetry),
+            canary: opts.canary.unwrap_or(preset.canary),
+            journal: opts.journal.unwrap_or(preset.journal),
+            invariants: opts.invariants.unwrap_or(preset.invariants),
+            msg_trace: opts.msg_trace.unwrap_or(preset.msg_trace),
+            profile: opts.profile.unwrap_or(preset.profile),
+            throughput: opts.throughput.unwrap_or(preset.throughput),
+            artifact_dir: opts.artifact_dir.clone().or(preset.artifact_dir),
+            default_stop_at: preset.default_stop_at,
+        }
+    }
+
+    /// Returns the tracing filter directive for the chosen telemetry level.
+    pub fn log_directive(&self) -> &'static str {
+        match self.telemetry {
+            TelemetryLevel::Off => "ftsim=warn",
+            TelemetryLevel::Normal => "ftsim=info",
+            TelemetryLevel::Detailed => "ftsim=debug",
+        }
+    }
+}
+
+/// Everything a run is configured with once presets, the scenario and
+/// explicit flags are merged. Printed by `--dump-config` and written to
+/// `config.json` in the artifact directory.
+#[derive(Serialize, Debug, Clone)]
+pub struct RunConfig {
+    pub scenario: String,
+    pub mode: Option<RunMode>,
+    pub log_level: TelemetryLevel,
+    pub stop_at: Option<SimTime>,
+    pub headless: bool,
+    pub artifact_dir: Option<PathBuf>,
+    #[serde(flatten)]
+    pub sim: EffectiveConfig,
+}
+
+impl RunConfig {
+    /// Returns a short human-readable summary, one setting per line, for
+    /// the TUI's config panel.
+    #[cfg(feature = "tui")]
+    pub fn summary(&self) -> Vec<String> {
+        let sim = &self.sim;
+        let engine = &sim.engine;
+        let protocol = sim.nodes.first().map_or("-", |n| n.protocol);
+        let partitioned = sim.links.iter().filter(|l| l.faults.partitioned).count();
+        let mut lines = vec![
+            format!("Scenario: {} (seed {})", self.scenario, sim.seed),
+            format!("Mode: {}", self.mode.map_or("none".to_string(), |m| format!("{:?}", m).to_lowercase())),
+            format!("Nodes: {} x {}", sim.nodes.len(), protocol),
+            format!("Links: {} ({} partitioned)", sim.links.len(), partitioned),
+            format!(
+                "Stop: {}, max events {}, quiescence {}",
+                self.stop_at.map_or("none".to_string(), |t| format!("t={}", t)),
+                engine.max_events.map_or("none".to_string(), |n| n.to_string()),
+                if engine.stop_on_quiescence { "on" } else { "off" },
+            ),
+            format!("Codec errors: {:?}", engine.codec_error_policy),
+            format!("Scheduling: {:?}", engine.scheduling),
+        ];
+        if !engine.invariants.is_empty() {
+            lines.push(format!(
+                "Invariants: {} (every {} events)",
+                engine.invariants.join(", "),
+                engine.invariant_check_every
+            ));
+        }
+        if let Some(policy) = engine.future_message_policy {
+            lines.push(format!("Future messages: {:?} beyond {}ns of skew", policy.action, policy.max_skew));
+        }
+        if let Some(valve) = engine.flood_valve {
+            lines.push(format!("Flood valve: defer past {} events per node and instant by {}ns", valve.threshold, valve.quantum));
+        }
+        if let Some(policy) = engine.restart_policy {
+            lines.push(format!("Restart stagger: {:?}", policy.stagger));
+        }
+        if let Some(slo) = engine.slo {
+            lines.push(format!("SLO: p99 ≤ {} ms per {}ns window after {}ns of warm-up", slo.p99_ms, slo.window, slo.warmup));
+        }
+        if let Some(store) = sim.nodes.first().and_then(|n| n.store) {
+            lines.push(format!(
+                "Store: {}, {:?}, checksums {}",
+                store.kind,
+                store.durability,
+                if store.checksums { "on" } else { "off" }
+            ));
+        }
+        let quotas: Vec<String> = sim
+            .nodes
+            .iter()
+            .filter_map(|n| n.write_quota.as_ref().map(|q| format!("{} ({} B/s, {:?})", n.id, q.bytes_per_sec, q.on_exceeded)))
+            .collect();
+        if !quotas.is_empty() {
+            lines.push(format!("Write quotas: {}", quotas.join(", ")));
+        }
+        lines.push(format!("Features: {}", sim.features.join(", ")));
+        lines
+    }
+}
+
+/// Metadata describing a run, written to the artifact directory.
+#[derive(Serialize, Debug, Clone)]
+pub struct RunMeta {
+    /// Distinguishes runs of the same scenario and seed.
+    pub run_id: String,
+    pub scenario: String,
+    /// FNV-1a over the scenario file, as 16 hex digits.
+    pub scenario_hash: String,
+    pub seed: u64,
+    pub mode: Option<RunMode>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{Cli, Command};
+    use clap::Parser;
+
+    fn resolve(args: &[&str]) -> RunOptions {
+        let argv = ["ftsim", "run", "--scenario", "s.yaml"].iter().chain(args);
+        match Cli::parse_from(argv).command {
+            Command::Run(opts) => RunOptions::resolve(&opts),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_mode_presets() {
+        let quick = resolve(&["--mode", "quick"]);
+        assert_eq!(quick.telemetry, TelemetryLevel::Off);
+        assert!(quick.canary);
+        assert_eq!(quick.default_stop_at, Some(sim_from_ms(QUICK_STOP_AT_MS)));
+
+        let thorough = resolve(&["--mode", "thorough"]);
+        assert_eq!(thorough.telemetry, TelemetryLevel::Detailed);
+        assert!(thorough.journal && thorough.invariants && thorough.msg_trace);
+        assert_eq!(thorough.artifact_dir, Some(PathBuf::from(DEFAULT_ARTIFACT_DIR)));
+        assert!(!thorough.canary);
+
+        let bench = resolve(&["--mode", "benchmark"]);
+        assert_eq!(bench.telemetry, TelemetryLevel::Off);
+        assert!(!bench.canary && !bench.journal && !bench.msg_trace);
+        assert!(bench.profile && bench.throughput && !bench.invariants);
+
+        assert_eq!(resolve(&[]), RunOptions::preset(None));
+    }
+
+    #[test]
+    fn test_explicit_flags_override_preset() {
+        let opts = resolve(&[
+            "--mode",
+            "quick",
+            "--canary=false",
+            "--telemetry",
+            "detailed",
+            "--journal",
+            "--artifact-dir",
+            "out",
+        ]);
+        assert_eq!(opts.mode, Some(RunMode::Quick));
+        assert!(!opts.canary);
+        assert!(opts.journal);
+        assert_eq!(opts.telemetry, TelemetryLevel::Detailed);
+        assert_eq!(opts.artifact_dir, Some(PathBuf::from("out")));
+    }
+}
@@ -0,0 +1,145 @@
+//! # ftsim-cli::templates
+//!
+//! Programmatic scenario generators behind `ftsim new-scenario`.
+
+use ftsim_engine::prelude::*;
+
+/// The base link delay every link starts with, restored when a drill heals.
+/// Matches `LinkFaultModel::default`.
+const DEFAULT_BASE_DELAY: u64 = 10;
+
+/// Parameters of the region evacuation drill.
+#[derive(Debug, Clone)]
+pub struct RegionDrill {
+    pub regions: usize,
+    pub nodes_per_region: usize,
+    pub proto: ProtoTag,
+    /// The region that is degraded, cut off, crashed and brought back.
+    pub region: usize,
+    /// The length of each stage, including the initial baseline.
+    pub stage: SimTime,
+    pub degraded_delay: SimTime,
+    pub seed: Option<u64>,
+}
+
+/// The drill's stages, in order.
+pub const REGION_DRILL_STAGES: [&str; 6] = ["baseline", "degrade", "partition", "crash", "heal", "restart"];
+
+impl RegionDrill {
+    fn num_nodes(&self) -> usize {
+        self.regions * self.nodes_per_region
+    }
+
+    fn region_nodes(&self) -> Vec<NodeId> {
+        let first = self.region * self.nodes_per_region;
+        (first..first + self.nodes_per_region).map(|n| n as NodeId).collect()
+    }
+
+    /// The links into and out of the drilled region.
+    fn region_links(&self) -> Vec<LinkId> {
+        let n = self.num_nodes();
+        let region = self.region_nodes();
+        let mut links = Vec::new();
+        for src in 0..n as NodeId {
+            for dst in 0..n as NodeId {
+                if src != dst && (region.contains(&src) || region.contains(&dst)) {
+                    links.push(full_mesh_link(n, src, dst));
+                }
+            }
+        }
+        links
+    }
+
+    /// Builds the scenario: each stage applies its faults at its start and
+    /// is checked at its end.
+    pub fn build(&self) -> Result<Scenario, String> {
+        if self.regions < 2 {
+            return Err("a region drill needs at least two regions".to_string());
+        }
+        if self.nodes_per_region == 0 {
+            return Err("a region drill needs at least one node per region".to_string());
+        }
+        if self.region >= self.regions {
+            return Err(format!("region {} does not exist; there are {}", self.region, self.regions));
+        }
+
+        let n = self.num_nodes();
+        let survivors = n - self.nodes_per_region;
+        let stage_start = |i: usize| self.stage * i as u128;
+        let end = stage_start(REGION_DRILL_STAGES.len());
+
+        // While the region is cut off or down, only the remaining nodes can
+        // be expected to elect a leader, and only if they hold a majority.
+        let all_up = vec![PhaseCheck::LeaderExists, PhaseCheck::MinAvailability(1.0)];
+        let mut degraded = vec![PhaseCheck::MinAvailability(1.0)];
+        let mut evacuated = vec![PhaseCheck::MinAvailability(survivors as f64 / n as f64)];
+        if survivors * 2 > n {
+            degraded.push(PhaseCheck::LeaderExists);
+            evacuated.push(PhaseCheck::LeaderExists);
+        }
+        let checks = [all_up.clone(), all_up.clone(), degraded, evacuated.clone(), evacuated, all_up];
+
+        let mut builder = Scenario::builder(
+            format!("region_drill_r{}x{}_region{}", self.regions, self.nodes_per_region, self.region),
+            n,
+            self.proto,
+        )
+        .stop_at(end);
+        if let Some(seed) = self.seed {
+            builder = builder.seed(seed);
+        }
+        for (i, (name, expect)) in REGION_DRILL_STAGES.iter().zip(checks).enumerate() {
+            builder = builder.phase(*name, stage_start(i), stage_start(i + 1), expect);
+        }
+
+        // Each directive is labeled with the stage that applies it.
+        let delay = |dist| {
+            self.region_links()
+                .into_iter()
+                .map(move |link| Action::LinkDelay { link, dist })
+        };
+        let stage = |i: usize| (REGION_DRILL_STAGES[i], stage_start(i));
+        let (label, at) = stage(1);
+        for action in delay(DelaySpec::Const(self.degraded_delay as u64)) {
+            builder = builder.at_labeled(label, at, action);
+        }
+        let (label, at) = stage(2);
+        builder = builder.at_labeled(label, at, Action::Partition { sets: vec![self.region_nodes()] });
+        let (label, at) = stage(3);
+        for node in self.region_nodes() {
+            // No automatic restart; the restart stage brings the nodes back
+            // explicitly.
+            builder = builder.at_labeled(label, at, Action::Crash { node, duration: SimDuration::Forever });
+        }
+        let (label, at) = stage(4);
+        builder = builder.at_labeled(label, at, Action::HealPartition);
+        for action in delay(DelaySpec::Const(DEFAULT_BASE_DELAY)) {
+            builder = builder.at_labeled(label, at, action);
+        }
+        let (label, at) = stage(5);
+        for node in self.region_nodes() {
+            builder = builder.at_labeled(label, at, Action::Restart { node });
+        }
+        builder.build()
+    }
+}
+
+/// The id of the `src -> dst` link in a full mesh of `n` nodes, following
+/// the order in which `Net::from_topology` creates links.
+fn full_mesh_link(n: usize, src: NodeId, dst: NodeId) -> LinkId {
+    let dst_slot = if dst < src { dst } else { dst - 1 };
+    src as LinkId * (n as LinkId - 1) + dst_slot as LinkId
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_full_mesh_link_ids_match_net() {
+        let net = Net::from_topology(4, &TopologySpec::FullMesh);
+        for link in net.links.values() {
+            assert_eq!(full_mesh_link(4, link.src, link.dst), link.id);
+        }
+    }
+}
@@ -3,58 +3,167 @@
 //! Contains the logic for instantiating and connecting all the components
 //! of the simulator (engine, world, protocols, telemetry).
 
-use ftsim_engine::{node::Node, prelude::*, store::MemStore, world::World};
-use ftsim_proto::{
-    api::boxed_dyn,
-    protocols::{primary_backup::PrimaryBackup, raft_lite::RaftLite},
+use crate::args::EngineOpts;
+use ftsim_engine::{
+    invariants::BUILTIN_INVARIANTS,
+    node::Node,
+    prelude::*,
+    scenario::{check_expressions, check_phase},
+    store::MemStore,
+    world::World,
 };
+use ftsim_proto::protocols::{KnownProtocol, KNOWN_PROTOCOLS};
 use rand::Rng;
+use std::{fs, path::Path};
 
 type ProtoFactory = fn() -> Box<dyn ProtocolDyn>;
 
-/// The central registry of all available protocols.
-static REGISTRY: &[(&'static str, ProtoTag, ProtoFactory)] = &[
-    (
-        "raft_lite",
-        ProtoTag(1),
-        || boxed_dyn(RaftLite::default()),
-    ),
-    (
-        "primary_backup",
-        ProtoTag(2),
-        || boxed_dyn(PrimaryBackup::new()),
-    ),
-];
-
-/// Finds a protocol factory in the registry by its tag.
-pub fn get_proto_factory(tag: ProtoTag) -> Option<ProtoFactory> {
-    REGISTRY
-        .iter()
-        .find(|(_, t, _)| *t == tag)
-        .map(|(_, _, factory)| *factory)
+/// The protocols compiled into this binary, plus the table of every protocol
+/// that exists, so that a protocol whose feature is disabled can be told
+/// apart from one that does not exist.
+pub struct ProtocolRegistry {
+    protocols: Vec<(&'static str, ProtoTag, ProtoFactory)>,
+    known: &'static [KnownProtocol],
+}
+
+impl ProtocolRegistry {
+    pub fn new(protocols: Vec<(&'static str, ProtoTag, ProtoFactory)>, known: &'static [KnownProtocol]) -> Self {
+        Self { protocols, known }
+    }
+
+    /// The protocols enabled by this build's features.
+    pub fn builtin() -> Self {
+        let protocols: Vec<(&'static str, ProtoTag, ProtoFactory)> = vec![
+            #[cfg(feature = "raft_lite")]
+            ("raft_lite", ProtoTag(1), || {
+                ftsim_proto::api::boxed_dyn(ftsim_proto::protocols::raft_lite::RaftLite::default())
+            }),
+            #[cfg(feature = "primary_backup")]
+            ("primary_backup", ProtoTag(2), || {
+                ftsim_proto::api::boxed_dyn(ftsim_proto::protocols::primary_backup::PrimaryBackup::new())
+            }),
+            #[cfg(feature = "batch_replicate")]
+            ("batch_replicate", ProtoTag(3), || {
+                ftsim_proto::api::boxed_dyn(ftsim_proto::protocols::batch_replicate::BatchReplicate::new())
+            }),
+            #[cfg(feature = "failure_detector")]
+            ("failure_detector", ProtoTag(4), || {
+                ftsim_proto::api::boxed_dyn(ftsim_proto::protocols::failure_detector::FailureDetector::new())
+            }),
+            #[cfg(feature = "two_phase_commit")]
+            ("two_phase_commit", ProtoTag(5), || {
+                ftsim_proto::api::boxed_dyn(ftsim_proto::protocols::two_phase_commit::TwoPhaseCommit::new())
+            }),
+            #[cfg(feature = "gossip")]
+            ("gossip", ProtoTag(6), || ftsim_proto::api::boxed_dyn(ftsim_proto::protocols::gossip::Gossip::new())),
+            #[cfg(feature = "ping")]
+            ("ping", ProtoTag(7), || ftsim_proto::api::boxed_dyn(ftsim_proto::protocols::ping::Ping::new())),
+            #[cfg(feature = "causal_broadcast")]
+            ("causal_broadcast", ProtoTag(8), || {
+                ftsim_proto::api::boxed_dyn(ftsim_proto::protocols::causal_broadcast::CausalBroadcast::new())
+            }),
+            #[cfg(feature = "crdt")]
+            ("crdt", ProtoTag(9), || ftsim_proto::api::boxed_dyn(ftsim_proto::protocols::crdt::Crdt::new())),
+            #[cfg(feature = "lease_kv")]
+            ("lease_kv", ProtoTag(10), || {
+                ftsim_proto::api::boxed_dyn(ftsim_proto::protocols::lease_kv::LeaseKv::new())
+            }),
+        ];
+        Self::new(protocols, KNOWN_PROTOCOLS)
+    }
+
+    /// The registered protocols, in tag order.
+    pub fn protocols(&self) -> &[(&'static str, ProtoTag, ProtoFactory)] {
+        &self.protocols
+    }
+
+    /// Known protocols that are not registered, in tag order.
+    pub fn disabled(&self) -> impl Iterator<Item = &KnownProtocol> {
+        self.known
+            .iter()
+            .filter(|known| !self.protocols.iter().any(|(_, tag, _)| *tag == known.tag))
+    }
+
+    /// Finds a protocol factory by its tag.
+    pub fn factory(&self, tag: ProtoTag) -> anyhow::Result<ProtoFactory> {
+        match self.protocols.iter().find(|(_, t, _)| *t == tag) {
+            Some((_, _, factory)) => Ok(*factory),
+            None => Err(self.missing(self.known.iter().find(|k| k.tag == tag), &format!("with tag {}", tag.0))),
+        }
+    }
+
+    /// Finds a protocol's tag by its name.
+    pub fn tag(&self, name: &str) -> anyhow::Result<ProtoTag> {
+        match self.protocols.iter().find(|(n, _, _)| *n == name) {
+            Some((_, tag, _)) => Ok(*tag),
+            None => Err(self.missing(self.known.iter().find(|k| k.name == name), &format!("'{}'", name))),
+        }
+    }
+
+    fn missing(&self, known: Option<&KnownProtocol>, what: &str) -> anyhow::Error {
+        match known {
+            Some(known) => anyhow::anyhow!(
+                "Protocol '{}' (tag {}) exists but was not compiled in; rebuild with --features {}",
+                known.name,
+                known.tag.0,
+                known.feature
+            ),
+            None => anyhow::anyhow!("Protocol {} not found; see `ftsim list-protocols --all`", what),
+        }
+    }
 }
 
-/// Returns the entire protocol registry.
-pub fn get_registry() -> &'static [(&'static str, ProtoTag, ProtoFactory)] {
-    REGISTRY
+/// Reads and validates a scenario file (YAML or TOML).
+pub fn load_scenario(path: &Path) -> anyhow::Result<Scenario> {
+    let content = fs::read_to_string(path)?;
+    let scenario: Scenario = match path.extension().and_then(|s| s.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&content)?,
+        Some("toml") => toml::from_str(&content)?,
+        _ => return Err(anyhow::anyhow!("Unsupported scenario file extension")),
+    };
+    scenario.validate().map_err(|e| anyhow::anyhow!(e))?;
+    if let Some(name) = scenario.invariants.iter().find(|n| !BUILTIN_INVARIANTS.contains(&n.as_str())) {
+        return Err(anyhow::anyhow!(
+            "Unknown invariant '{}'; expected one of {}",
+            name,
+            BUILTIN_INVARIANTS.join(", ")
+        ));
+    }
+    check_expressions(&scenario)?;
+    Ok(scenario)
 }
 
 /// Constructs the initial `World` state from a scenario.
 pub fn build_world(scenario: &Scenario) -> anyhow::Result<World> {
-    let factory = get_proto_factory(scenario.initial.proto)
-        .ok_or_else(|| anyhow::anyhow!("Protocol with tag {:?} not found", scenario.initial.proto))?;
+    let registry = ProtocolRegistry::builtin();
+    let factories = scenario
+        .initial
+        .proto
+        .tags()
+        .iter()
+        .map(|&tag| registry.factory(tag))
+        .collect::<anyhow::Result<Vec<_>>>()?;
 
     let nodes = (0..scenario.initial.nodes)
         .map(|i| {
-            let proto = factory();
-            // For now, all nodes get a simple in-memory store.
-            // A more advanced setup could configure this from the scenario.
-            let store = Box::new(MemStore::new());
-            Node::new(i as NodeId, proto, store)
+            let mut protos = factories.iter().map(|factory| factory());
+            // All nodes get an in-memory store configured from the scenario.
+            let spec = &scenario.initial.store;
+            let store = Box::new(MemStore::with_durability(spec.durability).with_checksums(spec.checksums));
+            let mut node = Node::new(i as NodeId, protos.next().expect("validated non-empty"), store);
+            for proto in protos {
+                node.add_protocol(proto);
+            }
+            node.set_store_latency(spec.latency);
+            node.set_write_quota(spec.write_quota.clone().filter(|q| q.applies_to(i as NodeId)));
+            let net = &scenario.initial.net;
+            node.set_reassembly(net.reassembly_timeout, net.max_reassemblies);
+            node
         })
         .collect();
 
-    let net = Net::from_topology(scenario.initial.nodes, &scenario.topology);
+    let mut net = Net::from_topology(scenario.initial.nodes, &scenario.topology);
+    net.set_mtu(scenario.initial.net.mtu, scenario.initial.net.oversize_policy);
 
     Ok(World { nodes, net })
 }
@@ -64,7 +173,9 @@ pub fn build_world(scenario: &Scenario) -> anyhow::Result<World> {
 pub fn finalize_world_setup(world: &mut World) {
     let all_node_ids: Vec<NodeId> = (0..world.nodes.len() as NodeId).collect();
     for node_id in all_node_ids {
-        let peers: Vec<NodeId> = world.net.peers_of(node_id).collect();
+        // Ascending, independent of the order links were added in
+        let mut peers: Vec<NodeId> = world.net.peers_of(node_id).collect();
+        peers.sort_unstable();
         world.nodes[node_id as usize].set_peers(peers);
     }
 }
@@ -75,3 +186,99 @@ pub fn get_seed(opts_seed: Option<u64>, scenario_seed: Option<u64>) -> u64 {
         .or(scenario_seed)
         .unwrap_or_else(|| rand::thread_rng().gen())
 }
+
+/// Applies the scenario's engine settings, with the flags that override
+/// them, to a simulation.
+pub fn apply_engine_settings(sim: &mut Simulation, scenario: &Scenario, opts: &EngineOpts) {
+    sim.set_codec_error_policy(opts.on_codec_error.unwrap_or(scenario.on_codec_error));
+    sim.set_scheduling_policy(scenario.scheduling);
+    sim.set_future_message_policy(scenario.future_message_policy);
+    sim.set_flood_valve(scenario.flood_valve);
+    sim.set_max_events(opts.max_events.or(scenario.stop_after_events));
+    sim.set_stop_on_quiescence(opts.stop_on_quiescence || scenario.stop_on_quiescence);
+}
+
+/// Runs the simulation to the end of each scenario phase in turn, checking
+/// the phase's expectations there, and then on to `stop_at`. Phases ending
+/// after `stop_at` are skipped. `on_phase` is called with every checked
+/// phase and its failures. Returns the final report and the names of the
+/// phases that failed.
+pub fn run_phases(
+    sim: &mut Simulation,
+    scenario: &Scenario,
+    stop_at: Option<SimTime>,
+    mut on_phase: impl FnMut(&Phase, &[String]),
+) -> (SimulationReport, Vec<String>) {
+    let mut phases: Vec<&Phase> = scenario.phases.iter().collect();
+    phases.sort_by_key(|phase| phase.end);
+    let mut failed_phases = Vec::new();
+    for phase in phases {
+        if stop_at.is_some_and(|stop| phase.end > stop) {
+            break;
+        }
+        // Events at `end` belong to the next phase
+        let phase_report = sim.run_until(phase.end.saturating_sub(1));
+        let failures = check_phase(sim, phase);
+        on_phase(phase, &failures);
+        if !failures.is_empty() {
+            failed_phases.push(phase.name.clone());
+        }
+        if !matches!(phase_report.outcome, SimulationOutcome::StopTime(_)) {
+            return (phase_report, failed_phases);
+        }
+    }
+    let report = match stop_at {
+        Some(stop_at) => sim.run_to_end(stop_at),
+        None => sim.run(),
+    };
+    (report, failed_phases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The built-in registry with `primary_backup` left out, as in a build
+    /// without its feature.
+    fn without_primary_backup() -> ProtocolRegistry {
+        let protocols = ProtocolRegistry::builtin()
+            .protocols()
+            .iter()
+            .filter(|(name, _, _)| *name != "primary_backup")
+            .copied()
+            .collect();
+        ProtocolRegistry::new(protocols, KNOWN_PROTOCOLS)
+    }
+
+    #[test]
+    fn test_builtin_protocols_match_the_known_table() {
+        let registry = ProtocolRegistry::builtin();
+        for (name, tag, factory) in registry.protocols() {
+            let known = KNOWN_PROTOCOLS.iter().find(|k| k.name == *name).unwrap();
+            assert_eq!(known.tag, *tag);
+            assert!(known.compiled);
+            assert_eq!(factory().proto_tag(), *tag);
+        }
+        let disabled: Vec<_> = registry.disabled().collect();
+        assert!(disabled.iter().all(|k| !k.compiled));
+    }
+
+    #[test]
+    fn test_disabled_protocol_errors_name_the_feature() {
+        let registry = without_primary_backup();
+        let expected = "Protocol 'primary_backup' (tag 2) exists but was not compiled in; \
+                        rebuild with --features primary_backup";
+        assert_eq!(registry.factory(ProtoTag(2)).err().unwrap().to_string(), expected);
+        assert_eq!(registry.tag("primary_backup").unwrap_err().to_string(), expected);
+        assert_eq!(registry.disabled().map(|k| k.name).collect::<Vec<_>>(), ["primary_backup"]);
+
+        assert_eq!(
+            registry.factory(ProtoTag(42)).err().unwrap().to_string(),
+            "Protocol with tag 42 not found; see `ftsim list-protocols --all`"
+        );
+        assert_eq!(
+            registry.tag("paxos").unwrap_err().to_string(),
+            "Protocol 'paxos' not found; see `ftsim list-protocols --all`"
+        );
+    }
+}
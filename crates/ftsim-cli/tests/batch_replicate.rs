@@ -0,0 +1,30 @@
+//! Runs the watermark batching scenario and checks that every follower
+//! applied every batch the leader replicated, the partitioned one included.
+
+use std::process::Command;
+
+#[test]
+fn test_batch_replicate_followers_apply_every_batch() {
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/batch_replicate.toml");
+    let dir = std::env::temp_dir().join(format!("ftsim-batch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("report.json");
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario", scenario, "--report-json", path.to_str().unwrap()])
+        .output()
+        .expect("failed to run ftsim");
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    // Stopped at the scenario's own stop time
+    assert_eq!(report["outcome"]["value"], 200_000_000);
+    let nodes = report["nodes"].as_array().unwrap();
+    let batches = &nodes[0]["custom"]["batches"];
+    assert!(batches.as_str().is_some_and(|n| n.parse::<u64>().unwrap() > 0), "{}", nodes[0]);
+    let applied = &nodes[1]["custom"]["applied"];
+    for follower in &nodes[1..] {
+        assert_eq!(&follower["custom"]["last_batch"], batches, "{}", follower);
+        assert_eq!(&follower["custom"]["applied"], applied, "{}", follower);
+    }
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,59 @@
+//! Runs `ftsim bench` at a small scale: saving a baseline, comparing against
+//! it, failing on a regression, and what the workloads count.
+
+use std::process::{Command, Output};
+
+fn bench(args: &[&str]) -> Output {
+    bench_matching("store", args)
+}
+
+fn bench_matching(filter: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["bench", "--filter", filter, "--scale", "0.01", "--runs", "1"])
+        .args(args)
+        .output()
+        .expect("failed to run ftsim")
+}
+
+#[test]
+fn test_bench_saves_and_compares_a_baseline() {
+    let dir = std::env::temp_dir().join(format!("ftsim-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("baseline.json");
+    let baseline = path.to_str().unwrap();
+
+    let out = bench(&["--save-baseline", baseline]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let names: Vec<&str> = json["results"].as_array().unwrap().iter().map(|r| r["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["store/mem", "store/faulty"]);
+    assert!(json["results"][0]["ops_per_sec"].as_f64().unwrap() > 0.0);
+
+    // Any slowdown short of stopping entirely is allowed
+    let out = bench(&["--baseline", baseline, "--max-regression", "100"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    assert!(stdout.contains("store/faulty"), "{}", stdout);
+    assert!(stdout.contains('%'), "{}", stdout);
+
+    // A baseline a million times faster than any machine is a regression
+    let mut json = json;
+    for result in json["results"].as_array_mut().unwrap() {
+        result["ops_per_sec"] = serde_json::json!(1e15);
+    }
+    std::fs::write(&path, json.to_string()).unwrap();
+    let out = bench(&["--baseline", baseline]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains('❌'));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("store/mem"));
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_message_pipeline_counts_delivered_messages() {
+    let out = bench_matching("message_pipeline/32", &[]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    // 32 tokens of 31 hops each, rather than every event the run processed
+    assert!(stdout.contains("(992 ops in"), "{}", stdout);
+}
@@ -0,0 +1,18 @@
+//! Runs a scenario headless with a breakpoint and checks that the summary
+//! reports the breakpoint stop rather than a completed run.
+
+use std::process::Command;
+
+#[test]
+fn test_headless_breakpoint_stop_is_reported() {
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/ping.toml");
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario", scenario, "--break-at-time", "50"])
+        .output()
+        .expect("failed to run ftsim");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    assert!(stdout.contains("🛑 Run ended: hit a breakpoint"), "{}", stdout);
+    assert!(stdout.contains("⏸️  Stopped at breakpoint 'any event from t=50000000'"), "{}", stdout);
+    assert!(!stdout.contains("completed successfully"), "{}", stdout);
+}
@@ -0,0 +1,21 @@
+//! Runs a scenario with `--canary` and checks that the second run is reported
+//! to end in the state the first one printed.
+
+use std::process::Command;
+
+#[test]
+fn test_canary_reruns_the_scenario_to_the_same_state() {
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/raft_partition.toml");
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario", scenario, "--seed", "5"])
+        .args(["--stop-at", "1000", "--canary", "--print-state-hash"])
+        .output()
+        .expect("failed to run ftsim");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    let find = |prefix: &str| stdout.lines().find_map(|line| line.strip_prefix(prefix).map(str::to_string));
+    let hash = find("🔑 State hash: ").expect("no state hash printed");
+    let canary = find("🐤 Determinism canary: a second run reached the same state ")
+        .expect("no canary line printed");
+    assert_eq!(canary, format!("({})", hash));
+}
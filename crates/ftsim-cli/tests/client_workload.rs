@@ -0,0 +1,50 @@
+//! Runs the primary-backup client workload scenario and checks that every
+//! client request was answered and replicated to both backups, and that
+//! the read returned a replicated value.
+
+use std::process::Command;
+
+#[test]
+fn test_client_workload_replicates_every_put() {
+    let dir = std::env::temp_dir().join(format!("ftsim-client-workload-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/primary_backup_workload.toml");
+    let path = dir.join("report.json");
+    let events = dir.join("events.jsonl");
+
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario", scenario, "--report-json", path.to_str().unwrap()])
+        .args(["--events-out", events.to_str().unwrap()])
+        .output()
+        .expect("failed to run ftsim");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    assert!(stdout.contains("Phase 'converged' passed"), "{}", stdout);
+
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    // 100 Puts and one Get, all answered
+    assert_eq!(report["metrics"]["client_requests"], 101);
+    assert_eq!(report["metrics"]["client_responses"], 101);
+    // Every answer and every delivery is in the latency percentiles
+    let metrics = &report["metrics"];
+    assert_eq!(metrics["client_request_latency"]["count"], 101, "{}", metrics);
+    assert_eq!(metrics["delivery_latency"]["count"], metrics["messages_delivered"], "{}", metrics);
+    assert!(metrics["delivery_latency"]["p50"].as_u64() <= metrics["delivery_latency"]["p99"].as_u64());
+    assert!(stdout.contains("Delivery Latency: p50"), "{}", stdout);
+    // A Put is answered once a backup acknowledged it, a round trip later
+    let client = &metrics["client_request_latency"];
+    assert!(client["p50"].as_u64() > Some(0), "{}", client);
+
+    let responses: Vec<serde_json::Value> = std::fs::read_to_string(&events)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .filter(|event: &serde_json::Value| event["event_type"] == "CLIENT_RESPONSE")
+        .collect();
+    assert_eq!(responses.len(), 101);
+    let get = responses.iter().find(|e| e["details"].as_str().unwrap().starts_with("get ")).expect("no answer to the Get");
+    assert_eq!(get["node_id"], 1);
+    assert!(get["details"].as_str().unwrap().contains(r#"answered Value(Some("v42"))"#), "{}", get);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,56 @@
+//! Panics a run with the `testutil` panic fault and checks the crash report
+//! it leaves in the artifact directory.
+
+use std::process::Command;
+
+const SCENARIO: &str = r#"
+name = "panic drill"
+seed = 9
+topology = "FullMesh"
+
+[initial]
+nodes = 3
+proto = 1
+
+[[directives]]
+At = [20_000_000, { Custom = { name = "test_panic", args = {} } }]
+"#;
+
+#[test]
+fn test_panic_writes_a_crash_report() {
+    let dir = std::env::temp_dir().join(format!("ftsim-crash-report-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("panic.toml");
+    std::fs::write(&path, SCENARIO).unwrap();
+    let artifacts = dir.join("artifacts");
+
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario", path.to_str().unwrap(), "--stop-at", "100"])
+        .args(["--artifact-dir", artifacts.to_str().unwrap()])
+        .output()
+        .expect("failed to run ftsim");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(!out.status.success());
+    assert!(stderr.contains("Crash report written to"), "{}", stderr);
+
+    let report: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(artifacts.join("crash-report.json")).unwrap()).unwrap();
+    assert_eq!(report["seed"], 9);
+    assert_eq!(report["scenario"], "panic drill");
+    assert_eq!(report["scenario_hash"].as_str().unwrap().len(), 16);
+    assert!(report["panic"].as_str().unwrap().contains("test_panic"));
+    let event = &report["event"];
+    assert_eq!(event["time"], 20_000_000);
+    assert!(event["event"].as_str().unwrap().contains("test_panic"));
+    assert!(event["queue_len"].as_u64().unwrap() > 0);
+
+    // The fault was logged right before it ran, under the same event id
+    let last = report["recent_events"].as_array().unwrap().last().unwrap().clone();
+    assert_eq!(last["event_type"], "FAULT_INJECTED");
+    assert_eq!(last["event_id"], event["event_id"]);
+    let meta: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(artifacts.join("meta.json")).unwrap()).unwrap();
+    assert_eq!(meta["run_id"], report["run_id"]);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,33 @@
+//! Runs the CRDT scenario: both sides of a partition write, and once it
+//! heals every node merges to the same counter and set.
+
+use std::process::Command;
+
+#[test]
+fn test_crdt_converges_on_every_write_after_the_partition_heals() {
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/crdt.toml");
+    let dir = std::env::temp_dir().join(format!("ftsim-crdt-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("report.json");
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario", scenario, "--report-json", path.to_str().unwrap()])
+        .output()
+        .expect("failed to run ftsim");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    assert!(stdout.contains("Phase 'split' passed"), "{}", stdout);
+    assert!(stdout.contains("Phase 'converged' passed"), "{}", stdout);
+
+    // The invariant held to the end, and the nodes agree on every increment
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(report["outcome"]["reason"], "stop_time");
+    for node in report["nodes"].as_array().unwrap() {
+        assert_eq!(node["custom"]["counter"], 15, "{}", node["custom"]);
+        assert_eq!(node["custom"]["elements"], 2, "{}", node["custom"]);
+    }
+    let convergence = &report["convergence"];
+    assert_eq!(convergence["converged_nodes"], 5, "{}", convergence);
+    let converged_at = convergence["converged_at"].as_u64().expect("never converged");
+    assert!((600_000_000..=800_000_000).contains(&converged_at), "converged at {}", converged_at);
+    std::fs::remove_dir_all(&dir).ok();
+}
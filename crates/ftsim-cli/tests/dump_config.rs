@@ -0,0 +1,96 @@
+//! Dumps the effective configuration of a scenario with store, network and
+//! command-line overrides, and checks the merged values.
+
+use std::process::Command;
+
+const SCENARIO: &str = r#"
+name = "config dump"
+seed = 11
+topology = "FullMesh"
+stop_after_events = 500
+invariants = ["at_most_one_leader"]
+directives = []
+
+[initial]
+nodes = 3
+proto = 1
+
+[initial.store]
+durability = "BufferedUntilFsync"
+checksums = true
+
+[initial.net]
+mtu = 512
+oversize_policy = "Fragment"
+max_reassemblies = 8
+"#;
+
+#[test]
+fn test_dump_config_merges_overrides() {
+    let dir = std::env::temp_dir().join(format!("ftsim-dump-config-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("dump.toml");
+    std::fs::write(&path, SCENARIO).unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario", path.to_str().unwrap()])
+        .args(["--max-events", "200", "--on-codec-error", "fail", "--stop-at", "50", "--dump-config"])
+        .output()
+        .expect("failed to run ftsim");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    let config: serde_json::Value = serde_json::from_str(&stdout).expect("dump is not JSON");
+
+    assert_eq!(config["seed"], 11);
+    assert_eq!(config["stop_at"], 50_000_000);
+    // The command line wins over the scenario's `stop_after_events`
+    assert_eq!(config["engine"]["max_events"], 200);
+    assert_eq!(config["engine"]["codec_error_policy"], "fail");
+    assert_eq!(config["engine"]["invariants"], serde_json::json!(["at_most_one_leader"]));
+
+    let nodes = config["nodes"].as_array().unwrap();
+    assert_eq!(nodes.len(), 3);
+    for node in nodes {
+        assert_eq!(node["protocol"], "raft_lite");
+        assert_eq!(node["peers"].as_array().unwrap().len(), 2);
+        assert_eq!(node["store"]["durability"], "BufferedUntilFsync");
+        assert_eq!(node["store"]["checksums"], true);
+        assert_eq!(node["max_reassemblies"], 8);
+        assert_eq!(node["reassembly_timeout"], 1_000_000_000u64);
+    }
+
+    let links = config["links"].as_array().unwrap();
+    assert_eq!(links.len(), 6);
+    for link in links {
+        assert_eq!(link["mtu_bytes"], 512);
+        assert_eq!(link["oversize_policy"], "Fragment");
+        assert_eq!(link["partitioned"], false);
+    }
+
+    // Nothing ran, so no artifacts or run output were produced
+    assert!(!stdout.contains("Phase"));
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_dump_config_lists_every_protocol_of_a_node() {
+    let dir = std::env::temp_dir().join(format!("ftsim-dump-config-protos-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("protos.toml");
+    let scenario = SCENARIO.replace("proto = 1", "proto = [1, 2]");
+    std::fs::write(&path, scenario).unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario", path.to_str().unwrap(), "--dump-config"])
+        .output()
+        .expect("failed to run ftsim");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    let config: serde_json::Value = serde_json::from_str(&stdout).expect("dump is not JSON");
+
+    for node in config["nodes"].as_array().unwrap() {
+        assert_eq!(node["protocol"], "raft_lite");
+        assert_eq!(node["other_protocols"], serde_json::json!([["primary_backup", 2]]));
+    }
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,41 @@
+//! Runs a scenario with `--events-out` and reads the file back: one valid
+//! JSON event per line, numbered in the order they were logged.
+
+use std::process::Command;
+
+#[test]
+fn test_events_out_writes_every_event_as_a_json_line() {
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/crdt.toml");
+    let dir = std::env::temp_dir().join(format!("ftsim-events-out-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("events.jsonl");
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario", scenario, "--events-out", path.to_str().unwrap()])
+        .output()
+        .expect("failed to run ftsim");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    assert!(stdout.contains("events written to"), "{}", stdout);
+    assert!(!stdout.contains("events dropped"), "{}", stdout);
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    let mut last_time = 0;
+    let mut types = std::collections::BTreeSet::new();
+    for (i, line) in text.lines().enumerate() {
+        let event: serde_json::Value =
+            serde_json::from_str(line).unwrap_or_else(|e| panic!("line {} is not JSON ({}): {}", i + 1, e, line));
+        for field in ["seq", "event_id", "time", "event_type", "details", "node_id"] {
+            assert!(event.get(field).is_some(), "line {} has no {}: {}", i + 1, field, line);
+        }
+        // Nothing was dropped, so the sequence numbers have no gaps
+        assert_eq!(event["seq"].as_u64(), Some(i as u64), "{}", line);
+        let time = event["time"].as_u64().unwrap();
+        assert!(time >= last_time, "line {}: time {} after {}", i + 1, time, last_time);
+        last_time = time;
+        types.insert(event["event_type"].as_str().unwrap().to_string());
+    }
+    // More events than the recent-events ring holds, faults among them
+    assert!(text.lines().count() > 100, "{} events", text.lines().count());
+    assert!(types.contains("FAULT_INJECTED"), "{:?}", types);
+    std::fs::remove_dir_all(&dir).ok();
+}
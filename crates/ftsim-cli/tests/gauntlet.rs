@@ -0,0 +1,349 @@
+//! The gauntlet: runs every bundled protocol through a standard sequence of
+//! fault stages with a fixed seed. Each run must end without a panic, an
+//! invariant violation or a stall, and with the protocol's own conditions
+//! met once the faults are over. Protocols and stages are tables, so
+//! covering a new protocol or stage is one entry; every protocol that
+//! `list-protocols` shows must have one.
+
+use std::process::Command;
+
+const SEED: u64 = 7;
+
+/// Every stage's faults fall between these times, leaving the protocols
+/// until `STOP_AT` to settle. By `FAULTS_FROM` every protocol with a leader
+/// has elected its first.
+const FAULTS_FROM: u64 = 400_000_000;
+const FAULTS_UNTIL: u64 = 700_000_000;
+const STOP_AT: u64 = 1_500_000_000;
+
+/// A bundled protocol and what it must get right after every stage.
+struct Subject {
+    name: &'static str,
+    tag: u16,
+    nodes: u32,
+    /// The role, as published under the `role` KV in any case, of the node
+    /// the crash stage takes down, if the protocol has a leader; node 0
+    /// otherwise.
+    leader_role: Option<&'static str>,
+    /// Directives, as TOML, that run in every stage, e.g. client writes.
+    workload: &'static [&'static str],
+    /// Built-in invariants checked throughout the run.
+    invariants: &'static [&'static str],
+    /// Phase checks, as TOML, that must hold when the run ends.
+    settled: &'static [&'static str],
+}
+
+const SUBJECTS: &[Subject] = &[
+    Subject {
+        name: "raft_lite",
+        tag: 1,
+        nodes: 3,
+        leader_role: Some("leader"),
+        workload: &[],
+        invariants: &["single_leader_per_term"],
+        settled: &["'LeaderExists'"],
+    },
+    Subject {
+        name: "primary_backup",
+        tag: 2,
+        nodes: 3,
+        leader_role: Some("primary"),
+        // The second write goes to every node; only the primary takes it
+        workload: &[
+            "At = [100_000_000, { ClientRequest = { node = 0, op = { Put = { key = 'a', value = '1' } } } }]",
+            "At = [900_000_000, { ClientRequest = { node = 0, op = { Put = { key = 'b', value = '2' } } } }]",
+            "At = [900_000_000, { ClientRequest = { node = 1, op = { Put = { key = 'b', value = '2' } } } }]",
+            "At = [900_000_000, { ClientRequest = { node = 2, op = { Put = { key = 'b', value = '2' } } } }]",
+        ],
+        // Without a quorum a partition splits the brain, and a former
+        // primary that comes back takes writes until it learns of its
+        // successor, so two primaries can briefly coexist, but never in
+        // the same epoch
+        invariants: &["single_leader_per_term"],
+        settled: &["{ Expr = 'all(nodes where data_entries == 2)' }"],
+    },
+    Subject {
+        name: "batch_replicate",
+        tag: 3,
+        nodes: 4,
+        leader_role: Some("leader"),
+        workload: &[],
+        invariants: &[],
+        settled: &["'LeaderExists'"],
+    },
+    Subject {
+        name: "failure_detector",
+        tag: 4,
+        nodes: 3,
+        leader_role: None,
+        workload: &[],
+        invariants: &[],
+        // The bare name would read the `suspects` KV, a list
+        settled: &["{ Expr = 'all(nodes where metric(\"suspects\") == 0)' }"],
+    },
+    Subject {
+        name: "two_phase_commit",
+        tag: 5,
+        nodes: 3,
+        leader_role: Some("coordinator"),
+        workload: &["At = [100_000_000, { ClientRequest = { node = 0, op = { Put = { key = 'a', value = '1' } } } }]"],
+        invariants: &[],
+        settled: &["{ Expr = 'all(nodes where in_doubt == 0)' }"],
+    },
+    Subject {
+        name: "gossip",
+        tag: 6,
+        nodes: 4,
+        leader_role: None,
+        workload: &[
+            "At = [100_000_000, { ClientRequest = { node = 0, op = { Put = { key = 'a', value = '1' } } } }]",
+            "At = [900_000_000, { ClientRequest = { node = 1, op = { Put = { key = 'b', value = '2' } } } }]",
+        ],
+        invariants: &[],
+        settled: &["{ Expr = 'all(nodes where replica_hash == kv(0, \"replica_hash\") && entries == 2)' }"],
+    },
+    Subject {
+        name: "ping",
+        tag: 7,
+        nodes: 3,
+        leader_role: None,
+        workload: &[],
+        invariants: &[],
+        settled: &["{ Expr = 'all(nodes where rtt_p99_ms < 1)' }"],
+    },
+    Subject {
+        name: "causal_broadcast",
+        tag: 8,
+        nodes: 3,
+        leader_role: None,
+        workload: &[
+            "At = [100_000_000, { ClientRequest = { node = 0, op = { Put = { key = 'a', value = '1' } } } }]",
+            "At = [300_000_000, { ClientRequest = { node = 1, op = { Put = { key = 'b', value = '2' } } } }]",
+            "At = [900_000_000, { ClientRequest = { node = 2, op = { Put = { key = 'c', value = '3' } } } }]",
+        ],
+        invariants: &["causal_delivery"],
+        settled: &["{ Expr = 'all(nodes where delivered == 3 && buffered == 0)' }"],
+    },
+    Subject {
+        name: "crdt",
+        tag: 9,
+        nodes: 3,
+        leader_role: None,
+        workload: &[
+            "At = [100_000_000, { ClientRequest = { node = 0, op = { Put = { key = 'incr', value = '2' } } } }]",
+            "At = [300_000_000, { ClientRequest = { node = 1, op = { Put = { key = 'add', value = 'x' } } } }]",
+            "At = [900_000_000, { ClientRequest = { node = 2, op = { Put = { key = 'incr', value = '3' } } } }]",
+        ],
+        invariants: &["crdt_convergence_after_quiescence"],
+        settled: &["{ Expr = 'all(nodes where counter == 5 && elements == 1)' }"],
+    },
+    Subject {
+        name: "lease_kv",
+        tag: 10,
+        nodes: 3,
+        leader_role: Some("leader"),
+        // Only the leader takes the write and serves the reads
+        workload: &[
+            "At = [900_000_000, { ClientRequest = { node = 0, op = { Put = { key = 'a', value = '1' } } } }]",
+            "At = [900_000_000, { ClientRequest = { node = 1, op = { Put = { key = 'a', value = '1' } } } }]",
+            "At = [900_000_000, { ClientRequest = { node = 2, op = { Put = { key = 'a', value = '1' } } } }]",
+            "At = [1_200_000_000, { ClientRequest = { node = 0, op = { Get = { key = 'a' } } } }]",
+            "At = [1_200_000_000, { ClientRequest = { node = 1, op = { Get = { key = 'a' } } } }]",
+            "At = [1_200_000_000, { ClientRequest = { node = 2, op = { Get = { key = 'a' } } } }]",
+        ],
+        invariants: &["linearizable_reads", "single_leader_per_term"],
+        settled: &["{ Expr = 'count(nodes where role == \"leader\") == 1' }"],
+    },
+];
+
+/// A fault stage.
+struct Stage {
+    name: &'static str,
+    /// The directives, as TOML, that inject the stage's faults into a
+    /// subject's cluster, given the node that leads it when they start.
+    faults: fn(&Subject, u32) -> Vec<String>,
+    /// Caps the run's events, so that a protocol that floods or spins
+    /// fails quickly instead of slowing the suite down.
+    max_events: u64,
+}
+
+const STAGES: &[Stage] = &[
+    Stage { name: "clean", faults: |_, _| Vec::new(), max_events: 200_000 },
+    Stage {
+        name: "leader_crash",
+        faults: |_, leader| {
+            let duration = FAULTS_UNTIL - FAULTS_FROM;
+            vec![format!("At = [{}, {{ Crash = {{ node = {}, duration = {} }} }}]", FAULTS_FROM, leader, duration)]
+        },
+        max_events: 200_000,
+    },
+    Stage {
+        name: "partition_heal",
+        faults: |s, _| {
+            // Isolating all nodes but the last isolates the last as well
+            let sets: Vec<String> = (0..s.nodes - 1).map(|n| format!("[{}]", n)).collect();
+            vec![
+                format!("At = [{}, {{ Partition = {{ sets = [{}] }} }}]", FAULTS_FROM, sets.join(", ")),
+                format!("At = [{}, 'HealPartition']", FAULTS_UNTIL),
+            ]
+        },
+        max_events: 200_000,
+    },
+    Stage { name: "flaky_network", faults: |s, _| on_links(s, "LinkDrop", 0.1), max_events: 200_000 },
+    Stage {
+        name: "store_write_errors",
+        faults: |s, _| {
+            (0..s.nodes)
+                .flat_map(|node| {
+                    [(FAULTS_FROM, 0.05), (FAULTS_UNTIL, 0.0)].map(|(at, rate)| {
+                        format!("At = [{}, {{ StoreFault = {{ node = {}, kind = 'WriteError', rate = {:?} }} }}]", at, node, rate)
+                    })
+                })
+                .collect()
+        },
+        max_events: 200_000,
+    },
+    Stage { name: "duplicate_storm", faults: |s, _| on_links(s, "LinkDuplicate", 0.5), max_events: 400_000 },
+];
+
+/// Sets `action`'s probability to `p` on every link of the full mesh for the
+/// fault window, and back to zero after it.
+fn on_links(subject: &Subject, action: &str, p: f64) -> Vec<String> {
+    let links = subject.nodes * (subject.nodes - 1);
+    (0..links)
+        .flat_map(|link| {
+            [(FAULTS_FROM, p), (FAULTS_UNTIL, 0.0)]
+                .map(|(at, p)| format!("At = [{}, {{ {} = {{ link = {}, p = {:?} }} }}]", at, action, link, p))
+        })
+        .collect()
+}
+
+/// Writes the scenario of `subject` with `directives` that stops at
+/// `stop_at`, checking the `settled` phase if the run gets that far.
+fn scenario(subject: &Subject, name: &str, directives: Vec<String>, stop_at: u64, max_events: u64) -> String {
+    let invariants: Vec<String> = subject.invariants.iter().map(|name| format!("'{}'", name)).collect();
+    let directives: Vec<String> = subject.workload.iter().map(|d| d.to_string()).chain(directives).collect();
+    let mut toml = format!(
+        "name = 'gauntlet_{}_{}'\nseed = {}\ntopology = 'FullMesh'\nstop_at = {}\nstop_after_events = {}\n\
+         invariants = [{}]\n{}\n[initial]\nnodes = {}\nproto = {}\n",
+        subject.name,
+        name,
+        SEED,
+        stop_at,
+        max_events,
+        invariants.join(", "),
+        if directives.is_empty() { "directives = []\n" } else { "" },
+        subject.nodes,
+        subject.tag
+    );
+    for directive in directives {
+        toml.push_str(&format!("\n[[directives]]\n{}\n", directive));
+    }
+    if stop_at > FAULTS_UNTIL {
+        toml.push_str(&format!(
+            "\n[[phases]]\nname = 'settled'\nstart = {}\nend = {}\nexpect = [{}]\n",
+            FAULTS_UNTIL,
+            stop_at,
+            subject.settled.join(", ")
+        ));
+    }
+    toml
+}
+
+/// Runs `subject` with `directives` until `stop_at`, returning the run's
+/// output and report, or why it failed.
+fn simulate(
+    dir: &std::path::Path,
+    subject: &Subject,
+    name: &str,
+    directives: Vec<String>,
+    stop_at: u64,
+    max_events: u64,
+) -> Result<(String, serde_json::Value), String> {
+    let cell = format!("{}/{}", subject.name, name);
+    let path = dir.join(format!("{}_{}.toml", subject.name, name));
+    let report_path = dir.join(format!("{}_{}.json", subject.name, name));
+    std::fs::write(&path, scenario(subject, name, directives, stop_at, max_events)).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--wall-timeout", "60", "--scenario"])
+        .arg(&path)
+        .arg("--report-json")
+        .arg(&report_path)
+        .output()
+        .expect("failed to run ftsim");
+    let stdout = String::from_utf8_lossy(&out.stdout).into_owned();
+    if !out.status.success() {
+        return Err(format!("{} failed:\n{}\n{}", cell, stdout, String::from_utf8_lossy(&out.stderr)));
+    }
+    let report = serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+    Ok((stdout, report))
+}
+
+/// Finds the node that holds `subject`'s leader role when the faults
+/// start, by running the workload alone until then and reading the roles
+/// the nodes last published. Leaderless protocols lead with node 0.
+fn leader(dir: &std::path::Path, subject: &Subject) -> Result<u32, String> {
+    let Some(role) = subject.leader_role else {
+        return Ok(0);
+    };
+    let (_, report) = simulate(dir, subject, "probe", Vec::new(), FAULTS_FROM, 200_000)?;
+    let nodes = report["nodes"].as_array().expect("the report lists the nodes");
+    let leaders: Vec<u32> = nodes
+        .iter()
+        .filter(|node| node["custom"]["role"].as_str().is_some_and(|r| r.eq_ignore_ascii_case(role)))
+        .map(|node| node["id"].as_u64().unwrap() as u32)
+        .collect();
+    match leaders[..] {
+        [leader] => Ok(leader),
+        _ => Err(format!("{} has {} nodes in role {:?} when the faults start", subject.name, leaders.len(), role)),
+    }
+}
+
+/// Runs one cell of the matrix, returning why it failed, if it did.
+fn run(dir: &std::path::Path, subject: &Subject, leader: u32, stage: &Stage) -> Result<(), String> {
+    let cell = format!("{}/{}", subject.name, stage.name);
+    let faults = (stage.faults)(subject, leader);
+    let (stdout, report) = simulate(dir, subject, stage.name, faults, STOP_AT, stage.max_events)?;
+    if !stdout.contains("Phase 'settled' passed") {
+        return Err(format!("{} did not settle:\n{}", cell, stdout));
+    }
+    // Running out of events or wall time before the stop time is a stall
+    match report["outcome"]["reason"].as_str() {
+        Some("stop_time" | "queue_exhausted") => Ok(()),
+        reason => Err(format!("{} stalled: {:?}", cell, reason)),
+    }
+}
+
+#[test]
+fn test_every_protocol_survives_the_gauntlet() {
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim")).arg("list-protocols").output().expect("failed to run ftsim");
+    let listed = String::from_utf8_lossy(&out.stdout);
+    // A protocol's row is its name and its numeric tag
+    let registered: Vec<(&str, u16)> = listed
+        .lines()
+        .filter_map(|line| line.split_once('|'))
+        .filter_map(|(name, tag)| Some((name.trim(), tag.trim().parse().ok()?)))
+        .collect();
+    let covered: Vec<(&str, u16)> = SUBJECTS.iter().map(|s| (s.name, s.tag)).collect();
+    assert_eq!(registered, covered, "every registered protocol needs a gauntlet entry");
+
+    let dir = std::env::temp_dir().join(format!("ftsim-gauntlet-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let dir = dir.as_path();
+    let failures: Vec<String> = std::thread::scope(|scope| {
+        let probes: Vec<_> = SUBJECTS.iter().map(|subject| scope.spawn(move || leader(dir, subject))).collect();
+        let leaders: Vec<_> = probes.into_iter().map(|probe| probe.join().unwrap()).collect();
+        let mut failures: Vec<String> = leaders.iter().filter_map(|leader| leader.clone().err()).collect();
+        let runs: Vec<_> = SUBJECTS
+            .iter()
+            .zip(&leaders)
+            .filter_map(|(subject, leader)| Some((subject, *leader.as_ref().ok()?)))
+            .flat_map(|(subject, leader)| STAGES.iter().map(move |stage| (subject, leader, stage)))
+            .map(|(subject, leader, stage)| scope.spawn(move || run(dir, subject, leader, stage)))
+            .collect();
+        failures.extend(runs.into_iter().filter_map(|run| run.join().unwrap().err()));
+        failures
+    });
+    std::fs::remove_dir_all(dir).ok();
+    assert!(failures.is_empty(), "{} gauntlet runs failed:\n\n{}", failures.len(), failures.join("\n\n"));
+}
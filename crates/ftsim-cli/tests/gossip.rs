@@ -0,0 +1,29 @@
+//! Runs the gossip scenario: both sides of a partition write, and all nodes
+//! agree on every entry shortly after the partition heals.
+
+use std::process::Command;
+
+#[test]
+fn test_gossip_converges_after_the_partition_heals() {
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/gossip.toml");
+    let dir = std::env::temp_dir().join(format!("ftsim-gossip-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("report.json");
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario", scenario, "--report-json", path.to_str().unwrap()])
+        .output()
+        .expect("failed to run ftsim");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    assert!(stdout.contains("Phase 'split' passed"), "{}", stdout);
+    assert!(stdout.contains("Phase 'converged' passed"), "{}", stdout);
+
+    // The heal is at 600ms; a few gossip rounds must suffice
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let convergence = &report["convergence"];
+    assert_eq!(convergence["converged_nodes"], 5, "{}", convergence);
+    assert_eq!(convergence["nodes"], 5, "{}", convergence);
+    let converged_at = convergence["converged_at"].as_u64().expect("never converged");
+    assert!((600_000_000..=800_000_000).contains(&converged_at), "converged at {}", converged_at);
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,58 @@
+//! Runs the lease KV scenarios: a leader cut off from its followers stops
+//! serving reads when its lease runs out, unless its clock runs behind, in
+//! which case it serves a stale read that `linearizable_reads` catches.
+
+use std::{path::Path, process::Command};
+
+/// Runs a scenario, returning whether ftsim succeeded, its stdout and the
+/// JSON report.
+fn run(dir: &Path, name: &str) -> (bool, String, serde_json::Value) {
+    let scenario = Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("../../scenarios/{}.toml", name));
+    let path = dir.join(format!("{}.json", name));
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario"])
+        .arg(&scenario)
+        .arg("--report-json")
+        .arg(&path)
+        .output()
+        .expect("failed to run ftsim");
+    let stdout = format!("{}\n{}", String::from_utf8_lossy(&out.stdout), String::from_utf8_lossy(&out.stderr));
+    let report = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    (out.status.success(), stdout, report)
+}
+
+fn latest(report: &serde_json::Value, node: usize, key: &str) -> Option<f64> {
+    report["nodes"][node]["metrics"][key].as_array()?.last()?["value"].as_f64()
+}
+
+#[test]
+fn test_lease_kv_reads_stay_linearizable_with_correct_clocks() {
+    let dir = std::env::temp_dir().join(format!("ftsim-lease-kv-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (ok, stdout, report) = run(&dir, "lease_kv");
+    assert!(ok, "{}", stdout);
+    assert!(stdout.contains("Phase 'failed_over' passed"), "{}", stdout);
+    assert_eq!(report["outcome"]["reason"], "stop_time");
+
+    // The cut-off leader refused the read; its successor served the new write
+    assert_eq!(latest(&report, 2, "reads_served"), Some(0.0));
+    assert_eq!(latest(&report, 2, "reads_refused"), Some(1.0));
+    let leader = (0..2).find(|&n| report["nodes"][n]["custom"]["state"]["role"] == "leader").expect("no new leader");
+    assert_eq!(report["nodes"][leader]["custom"]["state"]["last_read"]["entry"]["value"], "2");
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_lease_kv_leader_with_a_slow_clock_serves_a_stale_read() {
+    let dir = std::env::temp_dir().join(format!("ftsim-lease-kv-skew-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (ok, stdout, report) = run(&dir, "lease_kv_clock_skew");
+    assert!(!ok, "{}", stdout);
+    assert!(stdout.contains("invariant 'linearizable_reads' violated"), "{}", stdout);
+    assert_eq!(report["outcome"]["reason"], "invariant_violated");
+
+    // Node 2 still believed in its lease, and read the first write
+    assert_eq!(latest(&report, 2, "reads_served"), Some(1.0));
+    assert_eq!(report["nodes"][2]["custom"]["state"]["last_read"]["entry"]["value"], "1");
+    std::fs::remove_dir_all(&dir).ok();
+}
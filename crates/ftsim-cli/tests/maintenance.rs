@@ -0,0 +1,31 @@
+//! Runs the primary-backup maintenance scenarios side by side: the same
+//! crash, announced in one and not in the other.
+
+use std::process::Command;
+
+fn run(scenario: &str) -> String {
+    let path = format!("{}/../../scenarios/{}", env!("CARGO_MANIFEST_DIR"), scenario);
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario", &path])
+        .output()
+        .expect("failed to run ftsim");
+    let stdout = String::from_utf8_lossy(&out.stdout).into_owned();
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    stdout
+}
+
+#[test]
+fn test_announced_crash_drains_the_primary() {
+    let stdout = run("primary_backup_drain.toml");
+    // Node 1 took over before node 0 went down and accepted the next write
+    assert!(stdout.contains("Phase 'drained' passed"), "{}", stdout);
+    assert!(stdout.contains("Phase 'rejoined' passed"), "{}", stdout);
+}
+
+#[test]
+fn test_unannounced_crash_fails_over() {
+    let stdout = run("primary_backup_abrupt.toml");
+    // Node 1 noticed the silence, took over and accepted the next write
+    assert!(stdout.contains("Phase 'failed_over' passed"), "{}", stdout);
+    assert!(stdout.contains("Phase 'rejoined' passed"), "{}", stdout);
+}
@@ -0,0 +1,68 @@
+//! Scrapes `--metrics-listen` while a headless run goes and finds the
+//! engine's counters there, labelled per node.
+
+#![cfg(feature = "metrics_prom")]
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+fn scrape(addr: &str) -> Option<String> {
+    let mut stream = TcpStream::connect(addr).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(5))).ok()?;
+    write!(stream, "GET /metrics HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", addr).ok()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+    Some(response)
+}
+
+/// The value of the sample of `metric` with exactly `labels`, if scraped.
+fn sample(body: &str, metric: &str, labels: &str) -> Option<f64> {
+    let prefix = format!("{}{{{}}} ", metric, labels);
+    body.lines().find_map(|line| line.strip_prefix(&prefix)?.parse().ok())
+}
+
+#[test]
+fn test_metrics_listen_serves_counters_during_a_run() {
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/ping.toml");
+    // Ask the OS for a free port, then hand it to the run
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    // Far past the scenario's end, so the run is still going when scraped
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario", scenario, "--metrics-listen", &addr])
+        .args(["--stop-at", "100000000", "--wall-timeout", "60"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to run ftsim");
+
+    let started = Instant::now();
+    let mut body = String::new();
+    while started.elapsed() < Duration::from_secs(30) {
+        if let Some(response) = scrape(&addr) {
+            body = response;
+            if sample(&body, "ftsim_timer_fired_total", "node=\"2\"").is_some_and(|v| v > 0.0) {
+                break;
+            }
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    let still_running = child.try_wait().unwrap().is_none();
+    child.kill().ok();
+    child.wait().ok();
+    assert!(still_running, "the run ended before it was scraped:\n{}", body);
+
+    assert!(body.starts_with("HTTP/1.1 200"), "{}", body);
+    assert!(body.contains("# TYPE ftsim_net_msg_sent_total counter"), "{}", body);
+    for node in 0..3 {
+        let labels = format!("node=\"{}\"", node);
+        for metric in ["ftsim_net_msg_sent_total", "ftsim_timer_fired_total"] {
+            let value = sample(&body, metric, &labels);
+            assert!(value.is_some_and(|v| v > 0.0), "{}{{{}}} is {:?}:\n{}", metric, labels, value, body);
+        }
+    }
+    assert!(body.contains("ftsim_net_msg_delivered_total{node=\"0\",link=\""), "{}", body);
+}
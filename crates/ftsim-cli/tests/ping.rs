@@ -0,0 +1,41 @@
+//! Runs the ping scenario: the RTTs the nodes measure rise when a LinkDelay
+//! slows the links between two of them, and fall when it is lifted.
+
+use std::process::Command;
+
+#[test]
+fn test_ping_rtts_follow_link_delays() {
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/ping.toml");
+    let dir = std::env::temp_dir().join(format!("ftsim-ping-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("report.json");
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario", scenario, "--report-json", path.to_str().unwrap()])
+        .output()
+        .expect("failed to run ftsim");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    for phase in ["baseline", "slowed", "recovered"] {
+        assert!(stdout.contains(&format!("Phase '{}' passed", phase)), "{}", stdout);
+    }
+
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let node = &report["nodes"][0];
+    // The links to node 1 end at 1ms each way; node 2 keeps the defaults
+    let rtt: serde_json::Value = serde_json::from_str(node["custom"]["rtt"].as_str().unwrap()).unwrap();
+    let to_1 = rtt["1"]["min_ms"].as_f64().unwrap();
+    let to_2 = rtt["2"]["p99_ms"].as_f64().unwrap();
+    assert!((2.0..2.1).contains(&to_1), "{}", rtt);
+    assert!(to_2 < 0.1, "{}", rtt);
+
+    // The worst p99 went up at the first LinkDelay and down at the second
+    let at = |time: u64| {
+        let samples = node["metrics"]["rtt_p99_ms"].as_array().unwrap();
+        let latest = samples.iter().filter(|s| s["time"].as_u64().unwrap() <= time).last().unwrap();
+        latest["value"].as_f64().unwrap()
+    };
+    assert!(at(290_000_000) < 0.1);
+    assert!(at(690_000_000) >= 20.0);
+    assert!(at(1_100_000_000) < 3.0);
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,54 @@
+//! Generates the region drill template and runs it against raft_lite, so the
+//! template keeps validating and passing its own phase checks.
+
+use std::process::Command;
+
+fn ftsim(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(args)
+        .output()
+        .expect("failed to run ftsim")
+}
+
+#[test]
+fn test_region_drill_template_runs_green() {
+    let dir = std::env::temp_dir().join(format!("ftsim-region-drill-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("drill.toml");
+    let path = path.to_str().unwrap();
+
+    let generated = ftsim(&[
+        "new-scenario",
+        "--template",
+        "region-drill",
+        "--regions",
+        "3",
+        "--nodes-per-region",
+        "3",
+        "--protocol",
+        "raft_lite",
+        "--seed",
+        "7",
+        "--output",
+        path,
+    ]);
+    assert!(generated.status.success(), "{}", String::from_utf8_lossy(&generated.stderr));
+
+    let validated = ftsim(&["validate", path]);
+    assert!(validated.status.success(), "{}", String::from_utf8_lossy(&validated.stderr));
+
+    let events = dir.join("events.jsonl");
+    let run = ftsim(&["run", "--scenario", path, "--headless", "--events-out", events.to_str().unwrap()]);
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(run.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&run.stderr));
+    for stage in ["baseline", "degrade", "partition", "crash", "heal", "restart"] {
+        assert!(stdout.contains(&format!("Phase '{}' passed", stage)), "{}", stdout);
+    }
+    // Every stage after the baseline fires directives labeled with its name
+    let events = std::fs::read_to_string(&events).unwrap();
+    for stage in ["degrade", "partition", "crash", "heal", "restart"] {
+        assert!(events.contains(&format!("Directive '{}' fired", stage)), "{}", stage);
+    }
+
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,140 @@
+//! Records a run's event trace and RNG draws, replays both, and checks that
+//! a tampered RNG recording is reported at the draw that differs and that a
+//! replay runs with the options the run was recorded with.
+
+use std::process::Command;
+
+fn ftsim(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(args)
+        .output()
+        .expect("failed to run ftsim")
+}
+
+#[test]
+fn test_replay_checks_recorded_rng_draws() {
+    let dir = std::env::temp_dir().join(format!("ftsim-replay-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/raft_partition.toml");
+    let trace = dir.join("trace.json");
+    let rng = dir.join("rng.json");
+
+    let out = ftsim(&[
+        "run",
+        "--headless",
+        "--scenario",
+        scenario,
+        "--stop-at",
+        "1000",
+        "--record",
+        trace.to_str().unwrap(),
+        "--record-rng",
+        rng.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+
+    let out = ftsim(&["replay", trace.to_str().unwrap(), "--scenario", scenario, "--rng", rng.to_str().unwrap()]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    assert!(stdout.contains("Replay matched the trace"), "{}", stdout);
+    assert!(stdout.contains("Replay matched the RNG recording"), "{}", stdout);
+
+    // Flip one recorded value; the RNG check alone catches it
+    let mut recording: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&rng).unwrap()).unwrap();
+    let draws = recording["draws"].as_array_mut().unwrap();
+    let index = draws.len() / 2;
+    let draw = &mut draws[index];
+    draw["value"] = serde_json::json!(draw["value"].as_u64().unwrap() ^ 1);
+    let (site, ordinal) = (draw["site"].as_str().unwrap().to_string(), draw["ordinal"].as_u64().unwrap());
+    std::fs::write(&rng, recording.to_string()).unwrap();
+
+    let out = ftsim(&["replay", "--scenario", scenario, "--rng", rng.to_str().unwrap()]);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(!out.status.success());
+    assert!(stderr.contains(&format!("diverged at draw #{}", index)), "{}", stderr);
+    assert!(stderr.contains(&format!("{} draw #{}", site, ordinal)), "{}", stderr);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+/// Runs the scenario with `seed`, recording RNG positions into `dir`.
+fn record_positions(scenario: &str, seed: &str, dir: &std::path::Path) {
+    let trace = dir.join("trace.json");
+    let out = ftsim(&[
+        "run",
+        "--headless",
+        "--scenario",
+        scenario,
+        "--seed",
+        seed,
+        "--stop-at",
+        "1000",
+        "--record",
+        trace.to_str().unwrap(),
+        "--artifact-dir",
+        dir.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(dir.join("rng-positions.jsonl").exists());
+}
+
+#[test]
+fn test_rng_diff_finds_the_first_diverging_event() {
+    let dir = std::env::temp_dir().join(format!("ftsim-rng-diff-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/raft_partition.toml");
+    // The same run with wider jitter on link 0, which only changes what
+    // happens once the jitter is first applied
+    let jittery = dir.join("jittery.toml");
+    let toml = std::fs::read_to_string(scenario).unwrap();
+    assert!(toml.contains("hi = 8_000_000"));
+    std::fs::write(&jittery, toml.replace("hi = 8_000_000", "hi = 80_000_000")).unwrap();
+    let runs: Vec<_> = ["a", "b", "c"].iter().map(|name| dir.join(name)).collect();
+    record_positions(scenario, "5", &runs[0]);
+    record_positions(scenario, "5", &runs[1]);
+    record_positions(jittery.to_str().unwrap(), "5", &runs[2]);
+
+    let out = ftsim(&["inspect", "--rng-diff", runs[0].to_str().unwrap(), runs[1].to_str().unwrap()]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    assert!(stdout.contains("RNG draw positions match"), "{}", stdout);
+
+    let out = ftsim(&["inspect", "--rng-diff", runs[0].to_str().unwrap(), runs[2].to_str().unwrap()]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(!out.status.success());
+    assert!(stdout.contains("RNG draw positions diverge at entry #"), "{}", stdout);
+    // The entries around the divergence are listed from both runs
+    let marked: Vec<_> = stdout.lines().filter(|l| l.starts_with("> #")).collect();
+    assert_eq!(marked.len(), 2, "{}", stdout);
+    // Nothing differs before the jitter is first applied
+    for line in marked {
+        let time: u128 = line.split(" at t=").nth(1).unwrap().split(' ').next().unwrap().parse().unwrap();
+        assert!(time >= 15_000_000, "{}", stdout);
+    }
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_replay_runs_with_the_recorded_run_options() {
+    let dir = std::env::temp_dir().join(format!("ftsim-replay-opts-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // Breaks `linearizable_reads` at 1.5s
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/lease_kv_clock_skew.toml");
+    let trace = dir.join("trace.json");
+    let out = ftsim(&["run", "--headless", "--scenario", scenario, "--invariants=false", "--record", trace.to_str().unwrap()]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+
+    // Checking the invariants stops the replay where the run went on
+    let out = ftsim(&["replay", trace.to_str().unwrap(), "--scenario", scenario]);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(!out.status.success());
+    assert!(stderr.contains("replayed: <end of run>"), "{}", stderr);
+
+    let out = ftsim(&["replay", trace.to_str().unwrap(), "--scenario", scenario, "--invariants=false"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    assert!(stdout.contains("Replay matched the trace"), "{}", stdout);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,98 @@
+//! Writes a rotated message journal, reads it back through its manifest with
+//! `inspect`, and checks that a time range only reads the segments it
+//! overlaps. Compressed outputs rotate and read back the same way.
+
+use std::process::Command;
+
+fn ftsim(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(args)
+        .output()
+        .expect("failed to run ftsim")
+}
+
+/// The message count `inspect` reports.
+fn inspected(out: &std::process::Output) -> u64 {
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    let count = stdout.strip_prefix("Inspected ").and_then(|rest| rest.split(' ').next());
+    count.and_then(|n| n.parse().ok()).unwrap_or_else(|| panic!("{}", stdout))
+}
+
+#[test]
+fn test_inspect_reads_a_rotated_journal_by_time_range() {
+    let dir = std::env::temp_dir().join(format!("ftsim-rotation-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/raft_partition.toml");
+    let plain = dir.join("plain.jsonl");
+    let rotated = dir.join("messages.jsonl");
+
+    for (path, rotate) in [(&plain, None), (&rotated, Some("0.02"))] {
+        let mut args = vec!["run", "--headless", "--scenario", scenario, "--stop-at", "2000", "--seed", "3"];
+        args.extend(["--msg-journal", path.to_str().unwrap()]);
+        args.extend(rotate.iter().flat_map(|mib| ["--rotate-mib", mib]));
+        let out = ftsim(&args);
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    }
+    let manifest: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("messages.manifest.json")).unwrap()).unwrap();
+    let segments = manifest["segments"].as_array().unwrap();
+    assert!(segments.len() > 2, "{}", manifest);
+    assert_eq!(segments[0]["file"], "messages-0001.jsonl");
+    assert!(!rotated.exists());
+
+    // The rotated set reads back as the whole journal
+    let all = inspected(&ftsim(&["inspect", plain.to_str().unwrap()]));
+    assert_eq!(inspected(&ftsim(&["inspect", rotated.to_str().unwrap()])), all);
+
+    // A range across a segment boundary needs only the two segments around
+    // it, and finds the same messages as the unrotated journal
+    let boundary_ms = (segments[1]["first_time"].as_u64().unwrap() / 1_000_000).to_string();
+    let from_ms = (boundary_ms.parse::<u64>().unwrap() - 20).to_string();
+    let to_ms = (boundary_ms.parse::<u64>().unwrap() + 20).to_string();
+    let range = ["--from-ms", from_ms.as_str(), "--to-ms", to_ms.as_str()];
+    let expected = inspected(&ftsim(&[&["inspect", plain.to_str().unwrap()][..], &range].concat()));
+    assert!(expected > 0 && expected < all);
+    for segment in &segments[2..] {
+        std::fs::remove_file(dir.join(segment["file"].as_str().unwrap())).unwrap();
+    }
+    let manifest_path = dir.join("messages.manifest.json");
+    let out = ftsim(&[&["inspect", manifest_path.to_str().unwrap()][..], &range].concat());
+    assert_eq!(inspected(&out), expected);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_compressed_outputs_rotate_and_read_back() {
+    let dir = std::env::temp_dir().join(format!("ftsim-rotation-compressed-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/raft_partition.toml");
+    let plain = dir.join("plain.jsonl");
+    let messages = dir.join("messages.jsonl.gz");
+    let events = dir.join("events.jsonl.zst");
+    let run = ["run", "--headless", "--scenario", scenario, "--stop-at", "2000", "--seed", "3"];
+    let out = ftsim(&[&run[..], &["--msg-journal", plain.to_str().unwrap()]].concat());
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let out = ftsim(
+        &[
+            &run[..],
+            &["--msg-journal", messages.to_str().unwrap(), "--events-out", events.to_str().unwrap()],
+            &["--rotate-mib", "0.02"],
+        ]
+        .concat(),
+    );
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+
+    let outputs = [("messages.manifest.json", "messages-0001.jsonl.gz"), ("events.manifest.json", "events-0001.jsonl.zst")];
+    for (manifest, first) in outputs {
+        let manifest: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.join(manifest)).unwrap()).unwrap();
+        let segments = manifest["segments"].as_array().unwrap();
+        assert!(segments.len() > 1, "{}", manifest);
+        assert_eq!(segments[0]["file"], first);
+    }
+    let all = inspected(&ftsim(&["inspect", plain.to_str().unwrap()]));
+    assert_eq!(inspected(&ftsim(&["inspect", messages.to_str().unwrap()])), all);
+    std::fs::remove_dir_all(&dir).ok();
+}
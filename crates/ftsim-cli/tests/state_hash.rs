@@ -0,0 +1,28 @@
+//! Runs a scenario twice per seed with `--print-state-hash` and checks that
+//! the final state digest only depends on the seed.
+
+use std::process::Command;
+
+fn state_hash(seed: &str) -> String {
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/raft_partition.toml");
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario", scenario, "--seed", seed])
+        .args(["--stop-at", "1000", "--state-hash-every", "50", "--print-state-hash"])
+        .output()
+        .expect("failed to run ftsim");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("🔑 State hash: "))
+        .expect("no state hash printed")
+        .to_string()
+}
+
+#[test]
+fn test_state_hash_depends_only_on_the_seed() {
+    let hash = state_hash("5");
+    assert_eq!(hash.len(), 16);
+    assert_eq!(state_hash("5"), hash);
+    assert_ne!(state_hash("6"), hash);
+}
@@ -0,0 +1,92 @@
+//! Sweeps a scenario across seeds and checks that every seed's report is
+//! distinct and does not depend on how the runs were scheduled.
+
+use std::{collections::HashSet, path::Path, process::Command};
+
+fn ftsim(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(args)
+        .output()
+        .expect("failed to run ftsim")
+}
+
+fn read_json(path: &Path) -> serde_json::Value {
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+/// Removes the wall-clock measurements from a sweep or run report, which
+/// differ between runs, checking that they were there.
+fn without_usage(mut json: serde_json::Value) -> serde_json::Value {
+    match json.get_mut("runs") {
+        Some(runs) => {
+            for run in runs.as_array_mut().unwrap() {
+                strip_usage(&mut run["report"]);
+            }
+            let throughput = json.as_object_mut().unwrap().remove("throughput").unwrap();
+            assert!(throughput["p50"].as_u64().unwrap() > 0);
+        }
+        None => strip_usage(&mut json),
+    }
+    json
+}
+
+fn strip_usage(report: &mut serde_json::Value) {
+    let usage = report.as_object_mut().unwrap().remove("usage").unwrap();
+    assert!(usage["events_processed"].as_u64().unwrap() > 0);
+    assert!(usage["wall_ns"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn test_sweep_reports_are_distinct_and_reproducible() {
+    let dir = std::env::temp_dir().join(format!("ftsim-sweep-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/raft_partition.toml");
+
+    let mut sweeps = Vec::new();
+    for parallel in ["4", "1"] {
+        let path = dir.join(format!("sweep-{}.json", parallel));
+        let out = ftsim(&[
+            "sweep",
+            "--scenario",
+            scenario,
+            "--seeds",
+            "10",
+            "--first-seed",
+            "100",
+            "--parallel",
+            parallel,
+            "--stop-at",
+            "1000",
+            "--report-json",
+            path.to_str().unwrap(),
+        ]);
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+        sweeps.push(without_usage(read_json(&path)));
+    }
+    assert_eq!(sweeps[0], sweeps[1]);
+
+    let runs = sweeps[0]["runs"].as_array().unwrap();
+    let seeds: Vec<u64> = runs.iter().map(|r| r["seed"].as_u64().unwrap()).collect();
+    assert_eq!(seeds, (100..110).collect::<Vec<_>>());
+    let reports: HashSet<String> = runs.iter().map(|r| r["report"].to_string()).collect();
+    assert_eq!(reports.len(), 10);
+
+    // A sweep run matches a standalone run of the same seed
+    let path = dir.join("run-103.json");
+    let out = ftsim(&[
+        "run",
+        "--headless",
+        "--scenario",
+        scenario,
+        "--seed",
+        "103",
+        "--stop-at",
+        "1000",
+        "--report-json",
+        path.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(without_usage(read_json(&path)), runs[3]["report"]);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
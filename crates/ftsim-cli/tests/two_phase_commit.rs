@@ -0,0 +1,102 @@
+//! Runs the two-phase commit scenarios: a coordinator crash that leaves the
+//! participants disagreeing until it recovers, and a participant crash that
+//! makes the coordinator abort, as does a participant that cannot persist
+//! its vote.
+
+use std::process::Command;
+
+fn run(scenario: &str) -> String {
+    let out = Command::new(env!("CARGO_BIN_EXE_ftsim"))
+        .args(["run", "--headless", "--scenario", scenario])
+        .output()
+        .expect("failed to run ftsim");
+    let stdout = String::from_utf8_lossy(&out.stdout).into_owned();
+    assert!(out.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&out.stderr));
+    stdout
+}
+
+/// Runs the scenario `text`, written to a file of its own named `name`.
+fn run_toml(name: &str, text: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("ftsim-2pc-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.toml", name));
+    std::fs::write(&path, text).unwrap();
+    let stdout = run(path.to_str().unwrap());
+    std::fs::remove_dir_all(&dir).ok();
+    stdout
+}
+
+#[test]
+fn test_coordinator_crash_leaves_a_participant_in_doubt_until_it_recovers() {
+    let scenario = concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenarios/two_phase_commit.toml");
+    let stdout = run(scenario);
+    // Node 1 committed and node 2 is in doubt while the coordinator is down
+    assert!(stdout.contains("Phase 'in_doubt' passed"), "{}", stdout);
+    assert!(stdout.contains("Phase 'resolved' passed"), "{}", stdout);
+}
+
+#[test]
+fn test_coordinator_aborts_when_a_participant_does_not_vote() {
+    let stdout = run_toml(
+        "abort",
+        r#"
+name = "two_phase_commit_abort"
+seed = 5
+topology = "FullMesh"
+stop_at = 1_000_000_000
+
+[initial]
+nodes = 3
+proto = 5
+
+[[directives]]
+At = [50_000_000, { Crash = { node = 2, duration = 500_000_000 } }]
+
+[[directives]]
+At = [100_000_000, { ClientRequest = { node = 0, op = { Put = { key = "k", value = "v" } } } }]
+
+[[phases]]
+name = "aborted"
+start = 0
+end = 1_000_000_000
+expect = [
+    { Expr = 'metric(0, "aborted") == 1 && metric(1, "aborted") == 1' },
+    { Expr = 'all(nodes where committed == 0 && in_doubt == 0)' },
+]
+"#,
+    );
+    assert!(stdout.contains("Phase 'aborted' passed"), "{}", stdout);
+}
+
+#[test]
+fn test_a_participant_that_cannot_persist_its_vote_votes_no() {
+    let stdout = run_toml(
+        "write_error",
+        r#"
+name = "two_phase_commit_write_error"
+seed = 5
+topology = "FullMesh"
+stop_at = 1_000_000_000
+
+[initial]
+nodes = 3
+proto = 5
+
+[[directives]]
+At = [50_000_000, { StoreFault = { node = 2, kind = "WriteError", rate = 1.0 } }]
+
+[[directives]]
+At = [100_000_000, { ClientRequest = { node = 0, op = { Put = { key = "k", value = "v" } } } }]
+
+[[phases]]
+name = "aborted"
+start = 0
+end = 1_000_000_000
+expect = [
+    { Expr = 'metric(0, "aborted") == 1 && metric(1, "aborted") == 1' },
+    { Expr = 'all(nodes where committed == 0 && in_doubt == 0)' },
+]
+"#,
+    );
+    assert!(stdout.contains("Phase 'aborted' passed"), "{}", stdout);
+}
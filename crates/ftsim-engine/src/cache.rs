@@ -0,0 +1,241 @@
+//! # ftsim-engine::cache
+//!
+//! A bounded cache for engine bookkeeping that would otherwise grow for as
+//! long as a run goes, such as client requests awaiting an answer or
+//! fragments awaiting the rest of their message. It holds at most
+//! `CacheSpec::max_entries` entries and, if `max_age` is set, none older
+//! than that in sim time.
+//!
+//! Entries are evicted in the order they were inserted, tracked by a
+//! sequence number rather than by hash order, so identical runs evict
+//! identical entries. Reading an entry does not refresh it; inserting it
+//! again does.
+
+use crate::prelude::*;
+use std::collections::BTreeMap;
+
+/// Why an entry was evicted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    /// The cache was full.
+    Full,
+    /// The entry outlived `max_age`.
+    Age,
+}
+
+impl Eviction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Eviction::Full => "full",
+            Eviction::Age => "age",
+        }
+    }
+}
+
+/// What a cache has done since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub inserts: u64,
+    /// Entries evicted to make room for another.
+    pub evicted_full: u64,
+    /// Entries evicted for outliving `max_age`.
+    pub evicted_age: u64,
+}
+
+#[derive(Clone)]
+struct Slot<V> {
+    seq: u64,
+    inserted_at: SimTime,
+    value: V,
+}
+
+/// A cache bounded by entry count and, optionally, entry age.
+#[derive(Clone)]
+pub struct BoundedCache<K, V> {
+    name: &'static str,
+    spec: CacheSpec,
+    entries: BTreeMap<K, Slot<V>>,
+    // Insertion order: sequence number to key, oldest first
+    order: BTreeMap<u64, K>,
+    next_seq: u64,
+    stats: CacheStats,
+}
+
+impl<K: Ord + Clone, V> BoundedCache<K, V> {
+    /// Creates an empty cache. `name` labels its eviction counter. A spec
+    /// allowing no entries is taken to allow one.
+    pub fn new(name: &'static str, spec: CacheSpec) -> Self {
+        Self {
+            name,
+            spec: CacheSpec { max_entries: spec.max_entries.max(1), ..spec },
+            entries: BTreeMap::new(),
+            order: BTreeMap::new(),
+            next_seq: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Returns the limits the cache enforces.
+    pub fn spec(&self) -> CacheSpec {
+        self.spec
+    }
+
+    /// Inserts or replaces the entry for `key` as the newest one. Returns
+    /// the entry evicted to make room for it, if the cache was full.
+    pub fn insert(&mut self, key: K, value: V, now: SimTime) -> Option<(K, V)> {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let slot = Slot { seq, inserted_at: now, value };
+        if let Some(old) = self.entries.insert(key.clone(), slot) {
+            self.order.remove(&old.seq);
+        }
+        self.order.insert(seq, key);
+        self.stats.inserts += 1;
+        if self.entries.len() <= self.spec.max_entries {
+            return None;
+        }
+        self.stats.evicted_full += 1;
+        self.record_eviction(Eviction::Full);
+        self.pop_oldest()
+    }
+
+    /// Evicts every entry older than `max_age` as of `now`, oldest first,
+    /// and returns them.
+    pub fn expire(&mut self, now: SimTime) -> Vec<(K, V)> {
+        let mut expired = Vec::new();
+        let Some(max_age) = self.spec.max_age else {
+            return expired;
+        };
+        while let Some((_, key)) = self.order.first_key_value() {
+            if now.saturating_sub(self.entries[key].inserted_at) <= max_age {
+                break;
+            }
+            self.stats.evicted_age += 1;
+            self.record_eviction(Eviction::Age);
+            expired.extend(self.pop_oldest());
+        }
+        expired
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|slot| &slot.value)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.entries.get_mut(key).map(|slot| &mut slot.value)
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Removes the entry for `key`, returning its value. A removal is not
+    /// an eviction.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let slot = self.entries.remove(key)?;
+        self.order.remove(&slot.seq);
+        Some(slot.value)
+    }
+
+    /// Removes every entry `keep` rejects. Like `remove`, this evicts
+    /// nothing.
+    pub fn retain(&mut self, mut keep: impl FnMut(&K, &V) -> bool) {
+        let order = &mut self.order;
+        self.entries.retain(|key, slot| {
+            let kept = keep(key, &slot.value);
+            if !kept {
+                order.remove(&slot.seq);
+            }
+            kept
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    fn pop_oldest(&mut self) -> Option<(K, V)> {
+        let (_, key) = self.order.pop_first()?;
+        let slot = self.entries.remove(&key)?;
+        Some((key, slot.value))
+    }
+
+    fn record_eviction(&self, reason: Eviction) {
+        ::metrics::counter!(
+            ftsim_types::metrics::MET_CACHE_EVICTED,
+            ftsim_types::metrics::LBL_CACHE => self.name,
+            ftsim_types::metrics::LBL_REASON => reason.as_str()
+        )
+        .increment(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(max_entries: usize, max_age: Option<SimTime>) -> CacheSpec {
+        CacheSpec { max_entries, max_age }
+    }
+
+    #[test]
+    fn test_millions_of_entries_stay_within_the_limits() {
+        let mut cache = BoundedCache::new("test", spec(64, Some(1_000)));
+        let mut evicted = 0;
+        for id in 0..2_000_000u64 {
+            let now = SimTime::from(id);
+            evicted += cache.expire(now).len();
+            evicted += usize::from(cache.insert(id, [0u8; 32], now).is_some());
+            // Every other entry is answered right away
+            if id % 2 == 0 {
+                assert!(cache.remove(&id).is_some());
+            }
+            assert!(cache.len() <= 64);
+        }
+        let stats = cache.stats();
+        assert_eq!(stats.inserts, 2_000_000);
+        // The unanswered half fills the cache long before any ages out
+        assert_eq!((stats.evicted_full, stats.evicted_age), (1_000_000 - 64, 0));
+        assert_eq!(evicted as u64, stats.evicted_full);
+        assert_eq!(cache.len(), 64);
+    }
+
+    #[test]
+    fn test_entries_age_out_in_insertion_order() {
+        let mut cache = BoundedCache::new("test", spec(100, Some(10)));
+        cache.insert(1u64, "a", 0);
+        cache.insert(2, "b", 5);
+        // Reading does not refresh an entry, inserting it again does
+        assert_eq!(cache.get(&1), Some(&"a"));
+        assert!(cache.expire(11).iter().map(|(k, _)| *k).eq([1]));
+        cache.insert(2, "c", 12);
+        assert!(cache.expire(20).is_empty());
+        assert_eq!(cache.expire(23), [(2, "c")]);
+        assert_eq!(cache.stats().evicted_age, 2);
+    }
+
+    #[test]
+    fn test_a_full_cache_evicts_the_oldest_entry() {
+        let mut cache = BoundedCache::new("test", spec(2, None));
+        assert_eq!(cache.insert(1u64, (), 0), None);
+        assert_eq!(cache.insert(2, (), 0), None);
+        assert_eq!(cache.insert(3, (), 0), Some((1, ())));
+        cache.retain(|key, _| *key != 2);
+        assert_eq!(cache.insert(4, (), 0), None);
+        assert!(cache.contains(&3) && cache.contains(&4));
+        assert_eq!(cache.stats().evicted_full, 1);
+    }
+}
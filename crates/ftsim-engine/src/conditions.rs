@@ -0,0 +1,647 @@
+//! # ftsim-engine::conditions
+//!
+//! Temporal conditions over the signals a run publishes: node KVs, numeric
+//! metrics, node status and whether each invariant held at its last check.
+//! A condition names the signals its predicate reads, and the telemetry bus
+//! forwards a change only to the conditions that read the changed signal.
+//! Deadlines, such as the end of an `Eventually` window, are kept in one
+//! ordered set, so conditions cost work per relevant change and per
+//! deadline, not per event.
+//!
+//! Windows are half-open and measured from when the condition was added: a
+//! change at the instant a window ends comes too late for it. Deadlines due
+//! at or before a change's time are settled before the change is applied.
+//! A window that would end past the last representable time never ends.
+//!
+//! Values are decoded once, when they are recorded, so evaluating a
+//! predicate parses nothing. The engine's progress is saved with the rest
+//! of a simulation's state, so loading a state rewinds the conditions too.
+
+use crate::{
+    expr::{self, Expr},
+    invariants::is_leader_role,
+    prelude::*,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// Something a condition's predicate can read.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Signal {
+    /// A custom KV published by a node.
+    Kv { node: NodeId, key: String },
+    /// A numeric metric published by a node, as its latest sample.
+    Metric { node: NodeId, key: String },
+    /// A node's status.
+    Status(NodeId),
+    /// Whether the invariant with this name held at its last check.
+    Oracle(String),
+}
+
+/// A signal's value, in the form predicates read it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignalValue {
+    /// A KV as published, and as an expression reads it.
+    Kv { json: Value, value: expr::Value },
+    Metric(f64),
+    Status(NodeStatus),
+    Oracle(bool),
+}
+
+impl SignalValue {
+    pub fn kv(json: Value) -> Self {
+        let value = expr::Value::from_json(&json);
+        Self::Kv { json, value }
+    }
+}
+
+/// The latest values of the signals conditions read.
+pub struct SignalView<'a> {
+    values: &'a BTreeMap<Signal, SignalValue>,
+}
+
+impl SignalView<'_> {
+    pub fn get(&self, signal: &Signal) -> Option<&SignalValue> {
+        self.values.get(signal)
+    }
+
+    pub fn kv(&self, node: NodeId, key: &str) -> Option<&Value> {
+        match self.get(&Signal::Kv { node, key: key.to_string() })? {
+            SignalValue::Kv { json, .. } => Some(json),
+            _ => None,
+        }
+    }
+
+    pub fn metric(&self, node: NodeId, key: &str) -> Option<f64> {
+        match self.get(&Signal::Metric { node, key: key.to_string() })? {
+            SignalValue::Metric(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn status(&self, node: NodeId) -> Option<NodeStatus> {
+        match self.get(&Signal::Status(node))? {
+            SignalValue::Status(status) => Some(*status),
+            _ => None,
+        }
+    }
+
+    pub fn oracle(&self, name: &str) -> Option<bool> {
+        match self.get(&Signal::Oracle(name.to_string()))? {
+            SignalValue::Oracle(holds) => Some(*holds),
+            _ => None,
+        }
+    }
+}
+
+/// A `SignalView` as an expression reads it.
+struct ExprView<'a> {
+    view: &'a SignalView<'a>,
+    num_nodes: usize,
+}
+
+impl expr::Context for ExprView<'_> {
+    fn num_nodes(&self) -> usize {
+        self.num_nodes
+    }
+
+    fn kv(&self, node: NodeId, key: &str) -> Option<expr::Value> {
+        match self.view.get(&Signal::Kv { node, key: key.to_string() })? {
+            SignalValue::Kv { value, .. } => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    fn metric(&self, node: NodeId, key: &str) -> Option<f64> {
+        self.view.metric(node, key)
+    }
+
+    fn status(&self, node: NodeId) -> Option<NodeStatus> {
+        self.view.status(node)
+    }
+
+    fn oracle(&self, name: &str) -> Option<bool> {
+        self.view.oracle(name)
+    }
+}
+
+/// A test over signals, and the signals it reads.
+pub struct Predicate {
+    signals: Vec<Signal>,
+    eval: Box<dyn Fn(&SignalView) -> bool + Send>,
+}
+
+impl Predicate {
+    /// Creates a predicate that reads `signals`. Changes to any other
+    /// signal are not forwarded to it, so `eval` must read only these.
+    pub fn new(signals: Vec<Signal>, eval: impl Fn(&SignalView) -> bool + Send + 'static) -> Self {
+        Self {
+            signals,
+            eval: Box::new(eval),
+        }
+    }
+
+    /// Exactly one of nodes `0..num_nodes` is up and publishes the
+    /// `leader` (or `primary`) role.
+    pub fn single_leader(num_nodes: usize) -> Self {
+        let nodes = 0..num_nodes as NodeId;
+        let signals = nodes
+            .clone()
+            .flat_map(|node| [Signal::Status(node), Signal::Kv { node, key: "role".to_string() }])
+            .collect();
+        Self::new(signals, move |view| {
+            let leaders = nodes.clone().filter(|&node| {
+                view.status(node) == Some(NodeStatus::Up) && view.kv(node, "role").is_some_and(is_leader_role)
+            });
+            leaders.count() == 1
+        })
+    }
+
+    /// Holds when `expr` does, with quantifiers ranging over nodes
+    /// `0..num_nodes`.
+    pub fn expr(expr: Expr, num_nodes: usize) -> Self {
+        Self::new(expr.signals(num_nodes), move |view| expr.holds(&ExprView { view, num_nodes }))
+    }
+
+    fn holds(&self, values: &BTreeMap<Signal, SignalValue>) -> bool {
+        (self.eval)(&SignalView { values })
+    }
+}
+
+/// When a condition's predicate must hold, measured from when the
+/// condition was added.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Temporal {
+    /// At some instant within `within`. Satisfied the first time it holds,
+    /// failed once `within` has passed.
+    Eventually { within: SimTime },
+    /// At every instant of `window`. Failed the first time it does not
+    /// hold, satisfied once `window` has passed.
+    AlwaysWithin { window: SimTime },
+    /// Continuously for `duration`. Satisfied once it has; failed if that
+    /// has not happened within `within`, when set.
+    HoldsFor { duration: SimTime, within: Option<SimTime> },
+}
+
+/// A named predicate with the temporal operator it is checked under.
+pub struct Condition {
+    pub name: String,
+    pub predicate: Predicate,
+    pub temporal: Temporal,
+}
+
+impl Condition {
+    pub fn new(name: impl Into<String>, predicate: Predicate, temporal: Temporal) -> Self {
+        Self {
+            name: name.into(),
+            predicate,
+            temporal,
+        }
+    }
+}
+
+/// Where a condition stands.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "state", content = "at", rename_all = "snake_case")]
+pub enum ConditionState {
+    Pending,
+    Satisfied(SimTime),
+    Failed(SimTime),
+}
+
+/// A condition's state, as listed in `SimulationReport`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConditionOutcome {
+    pub name: String,
+    pub temporal: Temporal,
+    pub added_at: SimTime,
+    pub state: ConditionState,
+}
+
+impl fmt::Display for ConditionOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.state {
+            ConditionState::Pending => write!(f, "'{}' pending", self.name),
+            ConditionState::Satisfied(at) => write!(f, "'{}' satisfied at t={}", self.name, at),
+            ConditionState::Failed(at) => write!(f, "'{}' failed at t={}", self.name, at),
+        }
+    }
+}
+
+struct Tracked {
+    condition: Condition,
+    added_at: SimTime,
+    state: ConditionState,
+    /// When the predicate last started holding, under `HoldsFor`.
+    holding_since: Option<SimTime>,
+    /// The pending entry in `ConditionEngine::deadlines`, if any.
+    deadline: Option<SimTime>,
+}
+
+impl Tracked {
+    /// The end of the condition's window, if it has one that ends.
+    fn window_end(&self) -> Option<SimTime> {
+        let window = match self.condition.temporal {
+            Temporal::Eventually { within } => within,
+            Temporal::AlwaysWithin { window } => window,
+            Temporal::HoldsFor { within, .. } => within?,
+        };
+        self.added_at.checked_add(window)
+    }
+
+    fn status(&self) -> TrackedStatus {
+        TrackedStatus {
+            added_at: self.added_at,
+            state: self.state,
+            holding_since: self.holding_since,
+            deadline: self.deadline,
+        }
+    }
+}
+
+/// What a `Tracked` condition has seen so far.
+#[derive(Debug, Clone, Copy)]
+struct TrackedStatus {
+    added_at: SimTime,
+    state: ConditionState,
+    holding_since: Option<SimTime>,
+    deadline: Option<SimTime>,
+}
+
+/// A `ConditionEngine`'s progress, saved with a simulation's state. The
+/// conditions themselves are not part of it.
+#[derive(Debug, Clone, Default)]
+pub struct ConditionStatus {
+    conditions: Vec<TrackedStatus>,
+    values: BTreeMap<Signal, SignalValue>,
+    deadlines: BTreeSet<(SimTime, usize)>,
+}
+
+/// Tracks a set of conditions as the signals they read change.
+#[derive(Default)]
+pub struct ConditionEngine {
+    conditions: Vec<Tracked>,
+    /// The conditions reading each signal, by index.
+    interest: BTreeMap<Signal, Vec<usize>>,
+    /// The latest value of each signal in `interest`.
+    values: BTreeMap<Signal, SignalValue>,
+    /// Pending time-driven transitions, by time and condition index.
+    deadlines: BTreeSet<(SimTime, usize)>,
+}
+
+impl ConditionEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    /// Whether some condition reads `signal`.
+    pub fn wants(&self, signal: &Signal) -> bool {
+        self.interest.contains_key(signal)
+    }
+
+    /// Adds a condition at `now` and evaluates it. `seed` supplies the
+    /// current value of each signal no earlier condition reads.
+    pub fn add(&mut self, condition: Condition, now: SimTime, seed: impl Fn(&Signal) -> Option<SignalValue>) {
+        self.advance_to(now);
+        let idx = self.conditions.len();
+        for signal in &condition.predicate.signals {
+            if !self.interest.contains_key(signal) {
+                if let Some(value) = seed(signal) {
+                    self.values.insert(signal.clone(), value);
+                }
+            }
+            self.interest.entry(signal.clone()).or_default().push(idx);
+        }
+        self.conditions.push(Tracked {
+            condition,
+            added_at: now,
+            state: ConditionState::Pending,
+            holding_since: None,
+            deadline: None,
+        });
+        let window_end = self.conditions[idx].window_end();
+        self.set_deadline(idx, window_end);
+        self.evaluate(idx, now);
+    }
+
+    /// Records a signal's value at `now` and re-evaluates the conditions
+    /// that read it, if it changed. Ignored if no condition reads it.
+    pub fn update(&mut self, signal: Signal, value: SignalValue, now: SimTime) {
+        if !self.wants(&signal) {
+            return;
+        }
+        self.advance_to(now);
+        if self.values.get(&signal) == Some(&value) {
+            return;
+        }
+        let readers = self.interest[&signal].clone();
+        self.values.insert(signal, value);
+        for idx in readers {
+            self.evaluate(idx, now);
+        }
+    }
+
+    /// Settles the deadlines due at or before `now`.
+    pub fn advance_to(&mut self, now: SimTime) {
+        while let Some(&(at, idx)) = self.deadlines.first() {
+            if at > now {
+                break;
+            }
+            self.deadlines.pop_first();
+            self.conditions[idx].deadline = None;
+            self.expire(idx, at);
+        }
+    }
+
+    /// Returns the progress of every condition.
+    pub fn save(&self) -> ConditionStatus {
+        ConditionStatus {
+            conditions: self.conditions.iter().map(Tracked::status).collect(),
+            values: self.values.clone(),
+            deadlines: self.deadlines.clone(),
+        }
+    }
+
+    /// Puts the conditions back where `save` found them. Conditions added
+    /// since are dropped, as they did not exist yet.
+    pub fn restore(&mut self, status: ConditionStatus) {
+        let kept = status.conditions.len().min(self.conditions.len());
+        self.conditions.truncate(kept);
+        for (tracked, saved) in self.conditions.iter_mut().zip(status.conditions) {
+            tracked.added_at = saved.added_at;
+            tracked.state = saved.state;
+            tracked.holding_since = saved.holding_since;
+            tracked.deadline = saved.deadline;
+        }
+        self.interest.retain(|_, readers| {
+            readers.retain(|&idx| idx < kept);
+            !readers.is_empty()
+        });
+        self.values = status.values;
+        self.values.retain(|signal, _| self.interest.contains_key(signal));
+        self.deadlines = status.deadlines;
+        self.deadlines.retain(|&(_, idx)| idx < kept);
+    }
+
+    /// Returns every condition's state, in the order they were added.
+    pub fn outcomes(&self) -> Vec<ConditionOutcome> {
+        self.conditions
+            .iter()
+            .map(|t| ConditionOutcome {
+                name: t.condition.name.clone(),
+                temporal: t.condition.temporal,
+                added_at: t.added_at,
+                state: t.state,
+            })
+            .collect()
+    }
+
+    fn evaluate(&mut self, idx: usize, now: SimTime) {
+        let tracked = &mut self.conditions[idx];
+        if tracked.state != ConditionState::Pending {
+            return;
+        }
+        let holds = tracked.condition.predicate.holds(&self.values);
+        match tracked.condition.temporal {
+            Temporal::Eventually { .. } if holds => self.settle(idx, ConditionState::Satisfied(now)),
+            Temporal::AlwaysWithin { .. } if !holds => self.settle(idx, ConditionState::Failed(now)),
+            Temporal::HoldsFor { duration, .. } => match (holds, tracked.holding_since) {
+                (true, None) if duration == 0 => self.settle(idx, ConditionState::Satisfied(now)),
+                (true, None) => {
+                    tracked.holding_since = Some(now);
+                    let done = now.checked_add(duration);
+                    let deadline = match (tracked.window_end(), done) {
+                        (Some(end), Some(done)) => Some(end.min(done)),
+                        (end, done) => end.or(done),
+                    };
+                    self.set_deadline(idx, deadline);
+                }
+                (false, Some(_)) => {
+                    tracked.holding_since = None;
+                    let window_end = tracked.window_end();
+                    self.set_deadline(idx, window_end);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// Handles the deadline at `at` of a pending condition.
+    fn expire(&mut self, idx: usize, at: SimTime) {
+        let tracked = &self.conditions[idx];
+        let state = match tracked.condition.temporal {
+            Temporal::Eventually { .. } => ConditionState::Failed(at),
+            Temporal::AlwaysWithin { .. } => ConditionState::Satisfied(at),
+            Temporal::HoldsFor { duration, .. } => match tracked.holding_since {
+                Some(since) if since.checked_add(duration).is_some_and(|done| done <= at) => {
+                    ConditionState::Satisfied(at)
+                }
+                _ => ConditionState::Failed(at),
+            },
+        };
+        self.settle(idx, state);
+    }
+
+    fn settle(&mut self, idx: usize, state: ConditionState) {
+        self.set_deadline(idx, None);
+        let tracked = &mut self.conditions[idx];
+        tracked.state = state;
+        tracked.holding_since = None;
+    }
+
+    fn set_deadline(&mut self, idx: usize, deadline: Option<SimTime>) {
+        let tracked = &mut self.conditions[idx];
+        if let Some(old) = tracked.deadline.take() {
+            self.deadlines.remove(&(old, idx));
+        }
+        if let Some(at) = deadline {
+            tracked.deadline = Some(at);
+            self.deadlines.insert((at, idx));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    fn flag() -> Signal {
+        Signal::Kv { node: 0, key: "ok".to_string() }
+    }
+
+    /// Holds while node 0 publishes `ok = true`.
+    fn flag_is_set() -> Predicate {
+        Predicate::new(vec![flag()], |view| view.kv(0, "ok") == Some(&Value::Bool(true)))
+    }
+
+    /// Runs one condition over `script`, a list of (time in ms, flag
+    /// value) changes, and returns its state at `end_ms`.
+    fn run(temporal: Temporal, script: &[(u64, bool)], end_ms: u64) -> ConditionState {
+        let mut engine = ConditionEngine::new();
+        engine.add(Condition::new("flag", flag_is_set(), temporal), 0, |_| None);
+        for &(ms, value) in script {
+            engine.update(flag(), SignalValue::kv(Value::Bool(value)), sim_from_ms(ms));
+        }
+        engine.advance_to(sim_from_ms(end_ms));
+        engine.outcomes()[0].state
+    }
+
+    #[test]
+    fn test_eventually() {
+        let within = Temporal::Eventually { within: sim_from_ms(100) };
+        assert_eq!(run(within, &[(10, false), (40, true)], 200), ConditionState::Satisfied(sim_from_ms(40)));
+        // Once satisfied, later changes do not matter
+        assert_eq!(run(within, &[(40, true), (50, false)], 200), ConditionState::Satisfied(sim_from_ms(40)));
+        assert_eq!(run(within, &[(10, false)], 99), ConditionState::Pending);
+        assert_eq!(run(within, &[(10, false)], 200), ConditionState::Failed(sim_from_ms(100)));
+        // The window is half-open
+        assert_eq!(run(within, &[(100, true)], 200), ConditionState::Failed(sim_from_ms(100)));
+    }
+
+    #[test]
+    fn test_always_within() {
+        let window = Temporal::AlwaysWithin { window: sim_from_ms(100) };
+        // Unknown signals do not satisfy the predicate
+        assert_eq!(run(window, &[], 200), ConditionState::Failed(0));
+
+        let mut engine = ConditionEngine::new();
+        let seed = |_: &Signal| Some(SignalValue::kv(Value::Bool(true)));
+        engine.add(Condition::new("held", flag_is_set(), window), 0, seed);
+        engine.add(Condition::new("broken", flag_is_set(), window), 0, seed);
+        engine.update(flag(), SignalValue::kv(Value::Bool(true)), sim_from_ms(30));
+        assert_eq!(engine.outcomes()[0].state, ConditionState::Pending);
+        engine.advance_to(sim_from_ms(100));
+        // A break at the end of the window comes too late
+        engine.update(flag(), SignalValue::kv(Value::Bool(false)), sim_from_ms(100));
+        let states: Vec<_> = engine.outcomes().iter().map(|o| o.state).collect();
+        assert_eq!(states, [ConditionState::Satisfied(sim_from_ms(100)); 2]);
+
+        let mut engine = ConditionEngine::new();
+        engine.add(Condition::new("broken", flag_is_set(), window), 0, seed);
+        engine.update(flag(), SignalValue::kv(Value::Bool(false)), sim_from_ms(60));
+        engine.update(flag(), SignalValue::kv(Value::Bool(true)), sim_from_ms(70));
+        engine.advance_to(sim_from_ms(200));
+        assert_eq!(engine.outcomes()[0].state, ConditionState::Failed(sim_from_ms(60)));
+    }
+
+    #[test]
+    fn test_holds_for() {
+        let stable = Temporal::HoldsFor {
+            duration: sim_from_ms(50),
+            within: Some(sim_from_ms(200)),
+        };
+        assert_eq!(run(stable, &[(10, true)], 300), ConditionState::Satisfied(sim_from_ms(60)));
+        // Each flap restarts the clock
+        let flapping = [(10, true), (40, false), (45, true), (80, false), (90, true)];
+        assert_eq!(run(stable, &flapping, 300), ConditionState::Satisfied(sim_from_ms(140)));
+        // A break at the instant the hold completes comes too late
+        assert_eq!(run(stable, &[(10, true), (60, false)], 300), ConditionState::Satisfied(sim_from_ms(60)));
+        // The hold must complete within the window
+        assert_eq!(run(stable, &[(170, true)], 300), ConditionState::Failed(sim_from_ms(200)));
+        assert_eq!(run(stable, &[(10, true), (20, false)], 300), ConditionState::Failed(sim_from_ms(200)));
+
+        let unbounded = Temporal::HoldsFor {
+            duration: sim_from_ms(50),
+            within: None,
+        };
+        assert_eq!(run(unbounded, &[(10, true), (20, false)], 10_000), ConditionState::Pending);
+        assert_eq!(run(unbounded, &[(900, true)], 10_000), ConditionState::Satisfied(sim_from_ms(950)));
+    }
+
+    #[test]
+    fn test_windows_past_the_end_of_time_never_close() {
+        let mut engine = ConditionEngine::new();
+        let endless = Temporal::HoldsFor { duration: SimTime::MAX, within: Some(SimTime::MAX) };
+        engine.add(Condition::new("endless", flag_is_set(), endless), sim_from_ms(1), |_| None);
+        engine.update(flag(), SignalValue::kv(Value::Bool(true)), sim_from_ms(2));
+        engine.advance_to(SimTime::MAX);
+        assert_eq!(engine.outcomes()[0].state, ConditionState::Pending);
+    }
+
+    #[test]
+    fn test_only_conditions_reading_a_signal_are_evaluated() {
+        let evaluations = Arc::new(AtomicUsize::new(0));
+        let counted = evaluations.clone();
+        let predicate = Predicate::new(vec![Signal::Metric { node: 1, key: "term".to_string() }], move |view| {
+            counted.fetch_add(1, Ordering::Relaxed);
+            view.metric(1, "term").is_some_and(|term| term >= 3.0)
+        });
+        let mut engine = ConditionEngine::new();
+        engine.add(Condition::new("term", predicate, Temporal::Eventually { within: sim_from_ms(1_000) }), 0, |_| None);
+        assert_eq!(evaluations.load(Ordering::Relaxed), 1);
+
+        for i in 0..1_000 {
+            engine.update(Signal::Metric { node: 0, key: "term".to_string() }, SignalValue::Metric(i as f64), i as SimTime);
+            engine.update(Signal::Kv { node: 1, key: "role".to_string() }, SignalValue::kv(Value::from("x")), i as SimTime);
+        }
+        assert_eq!(evaluations.load(Ordering::Relaxed), 1);
+        assert!(!engine.wants(&Signal::Status(1)));
+
+        // Unchanged values are not re-evaluated either
+        let term = Signal::Metric { node: 1, key: "term".to_string() };
+        for value in [1.0, 1.0, 2.0, 3.0] {
+            engine.update(term.clone(), SignalValue::Metric(value), sim_from_ms(1));
+        }
+        assert_eq!(evaluations.load(Ordering::Relaxed), 4);
+        assert_eq!(engine.outcomes()[0].state, ConditionState::Satisfied(sim_from_ms(1)));
+    }
+
+    #[test]
+    fn test_single_leader_reads_roles_and_status() {
+        let mut engine = ConditionEngine::new();
+        let stable = Temporal::HoldsFor {
+            duration: sim_from_ms(3_000),
+            within: None,
+        };
+        let seed = |signal: &Signal| match signal {
+            Signal::Status(_) => Some(SignalValue::Status(NodeStatus::Up)),
+            _ => None,
+        };
+        engine.add(Condition::new("stable leader", Predicate::single_leader(3), stable), 0, seed);
+        let role = |node| Signal::Kv { node, key: "role".to_string() };
+        engine.update(role(0), SignalValue::kv(Value::from("Leader")), sim_from_ms(100));
+        engine.update(role(1), SignalValue::kv(Value::from("Leader")), sim_from_ms(1_000));
+        engine.update(role(0), SignalValue::kv(Value::from("Follower")), sim_from_ms(1_200));
+        // A crashed leader no longer counts
+        let down = SignalValue::Status(NodeStatus::Down);
+        engine.update(Signal::Status(1), down, sim_from_ms(2_000));
+        engine.update(role(2), SignalValue::kv(Value::from("leader")), sim_from_ms(2_500));
+        engine.advance_to(sim_from_ms(10_000));
+        assert_eq!(engine.outcomes()[0].state, ConditionState::Satisfied(sim_from_ms(5_500)));
+    }
+
+    #[test]
+    fn test_expression_predicates_read_what_they_name() {
+        let expr = Expr::parse(r#"count(nodes where status == "Down") <= 1 && kv(0, "role") == "leader""#).unwrap();
+        let mut engine = ConditionEngine::new();
+        let always = Temporal::AlwaysWithin { window: sim_from_ms(1_000) };
+        let seed = |signal: &Signal| match signal {
+            Signal::Status(_) => Some(SignalValue::Status(NodeStatus::Up)),
+            Signal::Kv { .. } => Some(SignalValue::kv(Value::from("leader"))),
+            _ => None,
+        };
+        engine.add(Condition::new("one down at most", Predicate::expr(expr, 3), always), 0, seed);
+        assert!(engine.wants(&Signal::Status(2)));
+        assert!(!engine.wants(&Signal::Kv { node: 1, key: "role".to_string() }));
+
+        let down = SignalValue::Status(NodeStatus::Down);
+        engine.update(Signal::Status(1), down.clone(), sim_from_ms(100));
+        assert_eq!(engine.outcomes()[0].state, ConditionState::Pending);
+        engine.update(Signal::Status(2), down, sim_from_ms(200));
+        assert_eq!(engine.outcomes()[0].state, ConditionState::Failed(sim_from_ms(200)));
+    }
+}
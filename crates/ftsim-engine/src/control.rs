@@ -2,7 +2,8 @@
 //!
 //! Defines control messages that can be sent from the TUI to the simulation engine.
 
-use crate::prelude::*;
+use crate::{events::Event, prelude::*};
+use std::fmt;
 
 /// Control messages sent from the TUI to the simulation engine.
 #[derive(Debug, Clone)]
@@ -13,6 +14,10 @@ pub enum ControlMsg {
     Resume,
     /// Execute a single step (process one event).
     Step,
+    /// Execute the given number of events, then pause.
+    StepN(u64),
+    /// Execute every event scheduled at or before the given time, then pause.
+    RunUntil(SimTime),
     /// Kill a specific node.
     KillNode(NodeId),
     /// Restart a specific node.
@@ -24,8 +29,106 @@ pub enum ControlMsg {
     },
     /// Heal all network partitions.
     HealPartition,
-    /// Adjust simulation speed (1.0 = normal, 0.5 = half speed, 2.0 = double speed).
+    /// Adjust simulation speed in sim nanoseconds per wall-clock nanosecond
+    /// (1.0 = real time, 0.5 = half speed, 2.0 = double speed). Zero or an
+    /// infinite value removes the limit.
     SetSpeed(f32),
+    /// Pause right before any event matching the breakpoint executes.
+    SetBreakpoint(Breakpoint),
+    /// Remove every breakpoint equal to the given one.
+    ClearBreakpoint(Breakpoint),
+    /// End the run cleanly after the current event, as if a stop condition
+    /// had been met.
+    Shutdown,
+}
+
+/// The kinds of events a breakpoint can match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakKind {
+    Deliver,
+    TimerFired,
+    Fault,
+    ClientRequest,
+    SendFailed,
+}
+
+/// A condition on the next event to execute. Every field that is set must
+/// match; a breakpoint with no fields set matches any event except UI ticks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub kind: Option<BreakKind>,
+    /// The receiving node of a delivery, the node owning a timer, or the
+    /// node targeted by a fault.
+    pub node: Option<NodeId>,
+    /// The protocol of a delivery. Never matches other events.
+    pub proto_tag: Option<ProtoTag>,
+    /// Earliest matching event time, inclusive.
+    pub from: Option<SimTime>,
+    /// Latest matching event time, inclusive.
+    pub until: Option<SimTime>,
+    /// A byte string the delivered payload must contain. Never matches
+    /// other events.
+    pub payload_contains: Option<Vec<u8>>,
+}
+
+impl Breakpoint {
+    /// Whether `event`, scheduled at `time`, satisfies the breakpoint.
+    pub fn matches(&self, time: SimTime, event: &Event) -> bool {
+        let (kind, node) = match event {
+            Event::Deliver { env, .. } => (BreakKind::Deliver, Some(env.dst)),
+            Event::TimerFired { node_id, .. } => (BreakKind::TimerFired, Some(*node_id)),
+            Event::Fault(fault) => (BreakKind::Fault, fault.node_id()),
+            Event::ClientRequest { node_id, .. } => (BreakKind::ClientRequest, Some(*node_id)),
+            Event::Announce { node_id, .. } => (BreakKind::Fault, Some(*node_id)),
+            Event::SendFailed { node_id, .. } => (BreakKind::SendFailed, Some(*node_id)),
+            Event::Depart { .. } | Event::UiSnapshotTick => return false,
+        };
+        let env = match event {
+            Event::Deliver { env, .. } => Some(env),
+            _ => None,
+        };
+        self.kind.map_or(true, |k| k == kind)
+            && self.node.map_or(true, |n| node == Some(n))
+            && self.from.map_or(true, |from| time >= from)
+            && self.until.map_or(true, |until| time <= until)
+            && self
+                .proto_tag
+                .map_or(true, |tag| env.is_some_and(|env| env.proto_tag == tag))
+            && self.payload_contains.as_ref().map_or(true, |needle| {
+                env.is_some_and(|env| {
+                    needle.is_empty() || env.payload.windows(needle.len()).any(|w| w == needle.as_slice())
+                })
+            })
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            Some(BreakKind::Deliver) => write!(f, "deliver")?,
+            Some(BreakKind::TimerFired) => write!(f, "timer")?,
+            Some(BreakKind::Fault) => write!(f, "fault")?,
+            Some(BreakKind::ClientRequest) => write!(f, "client request")?,
+            Some(BreakKind::SendFailed) => write!(f, "send failure")?,
+            None => write!(f, "any event")?,
+        }
+        if let Some(node) = self.node {
+            write!(f, " on node {}", node)?;
+        }
+        if let Some(tag) = self.proto_tag {
+            write!(f, " tag {}", tag.0)?;
+        }
+        if let Some(from) = self.from {
+            write!(f, " from t={}", from)?;
+        }
+        if let Some(until) = self.until {
+            write!(f, " until t={}", until)?;
+        }
+        if let Some(needle) = &self.payload_contains {
+            write!(f, " containing {:?}", String::from_utf8_lossy(needle))?;
+        }
+        Ok(())
+    }
 }
 
 /// The state of simulation execution control.
@@ -35,8 +138,10 @@ pub enum SimulationState {
     Running,
     /// Simulation is paused.
     Paused,
-    /// Simulation is stepping (will pause after next event).
-    Stepping,
+    /// Simulation is stepping (will pause after `remaining` more events).
+    Stepping { remaining: u64 },
+    /// Simulation is running until its clock would pass the given time.
+    RunningUntil(SimTime),
     /// Simulation has completed.
     Completed,
 }
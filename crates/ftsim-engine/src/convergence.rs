@@ -0,0 +1,146 @@
+//! # ftsim-engine::convergence
+//!
+//! Tracks whether nodes agree on replicated state, for anti-entropy
+//! protocols such as `protocols::gossip`. Such a protocol publishes a hash
+//! of its state under `REPLICA_HASH_KEY`. Among the up nodes that have published
+//! one, those sharing the most common hash are converged, and the cluster
+//! has converged once all of them share it. The tracker records when that
+//! last became and stopped being true, so that a run can report how long
+//! the nodes took to agree after a partition healed.
+
+use crate::prelude::*;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+pub use ftsim_proto::api::REPLICA_HASH_KEY;
+
+/// How far the nodes agree, as listed in `SimulationReport`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConvergenceReport {
+    /// The up nodes that have published a hash.
+    pub nodes: usize,
+    /// How many of them share the most common hash.
+    pub converged_nodes: usize,
+    /// When the nodes last came to share one hash, if they share it now.
+    pub converged_at: Option<SimTime>,
+    /// When they last stopped sharing one, if they ever did.
+    pub diverged_at: Option<SimTime>,
+}
+
+impl ConvergenceReport {
+    /// How long the nodes took to converge after they last diverged.
+    pub fn time_to_converge(&self) -> Option<SimTime> {
+        Some(self.converged_at?.saturating_sub(self.diverged_at?))
+    }
+}
+
+impl fmt::Display for ConvergenceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{} nodes share the majority hash", self.converged_nodes, self.nodes)?;
+        match (self.converged_at, self.time_to_converge()) {
+            (Some(at), Some(took)) => {
+                write!(f, ", converged at {:.3} s, {:.3} ms after diverging", at as f64 / 1e9, took as f64 / 1e6)
+            }
+            (Some(at), None) => write!(f, ", converged at {:.3} s", at as f64 / 1e9),
+            (None, _) => write!(f, ", not converged"),
+        }
+    }
+}
+
+/// Follows each node's published hash and status.
+#[derive(Debug, Clone, Default)]
+pub struct ConvergenceTracker {
+    hashes: BTreeMap<NodeId, String>,
+    down: BTreeSet<NodeId>,
+    converged_at: Option<SimTime>,
+    diverged_at: Option<SimTime>,
+}
+
+impl ConvergenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the hash `node` published at `now`. Returns whether the
+    /// nodes converged or diverged because of it, if either.
+    pub fn record_hash(&mut self, node: NodeId, hash: String, now: SimTime) -> Option<bool> {
+        self.hashes.insert(node, hash);
+        self.update(now)
+    }
+
+    /// Records whether `node` is up at `now`, as `record_hash` does. Down
+    /// nodes keep their last hash but do not count.
+    pub fn record_status(&mut self, node: NodeId, up: bool, now: SimTime) -> Option<bool> {
+        if up {
+            self.down.remove(&node);
+        } else {
+            self.down.insert(node);
+        }
+        self.update(now)
+    }
+
+    fn update(&mut self, now: SimTime) -> Option<bool> {
+        let report = self.report()?;
+        let converged = report.nodes > 0 && report.converged_nodes == report.nodes;
+        match (converged, self.converged_at.is_some()) {
+            (true, false) => {
+                self.converged_at = Some(now);
+                Some(true)
+            }
+            (false, true) => {
+                self.converged_at = None;
+                self.diverged_at = Some(now);
+                Some(false)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the current agreement, or `None` if no node has published a
+    /// hash.
+    pub fn report(&self) -> Option<ConvergenceReport> {
+        if self.hashes.is_empty() {
+            return None;
+        }
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for (node, hash) in &self.hashes {
+            if !self.down.contains(node) {
+                *counts.entry(hash).or_default() += 1;
+            }
+        }
+        Some(ConvergenceReport {
+            nodes: counts.values().sum(),
+            converged_nodes: counts.values().copied().max().unwrap_or(0),
+            converged_at: self.converged_at,
+            diverged_at: self.diverged_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convergence_follows_the_up_nodes_hashes() {
+        let mut tracker = ConvergenceTracker::new();
+        assert_eq!(tracker.report(), None);
+        assert_eq!(tracker.record_hash(0, "a".into(), 0), Some(true));
+        assert_eq!(tracker.record_hash(1, "a".into(), 0), None);
+        assert_eq!(tracker.record_hash(2, "b".into(), 10), Some(false));
+        let report = tracker.report().unwrap();
+        assert_eq!((report.nodes, report.converged_nodes), (3, 2));
+        assert_eq!((report.converged_at, report.diverged_at), (None, Some(10)));
+
+        // A down node's stale hash does not count
+        assert_eq!(tracker.record_status(2, false, 20), Some(true));
+        assert_eq!(tracker.record_status(2, true, 30), Some(false));
+        assert_eq!(tracker.record_hash(2, "a".into(), 45), Some(true));
+        let report = tracker.report().unwrap();
+        assert_eq!((report.nodes, report.converged_nodes), (3, 3));
+        assert_eq!(report.time_to_converge(), Some(15));
+    }
+}
@@ -0,0 +1,52 @@
+//! # ftsim-engine::crash_context
+//!
+//! Tracks, per thread, which event the simulation is executing, so that a
+//! panic hook can report where a run failed. The engine updates it as it
+//! dispatches each event; a hook reads it with `current_event` while the
+//! panicking thread unwinds, or after the panic has been caught.
+//!
+//! Builds with the `testutil` feature also recognize a `Custom` fault named
+//! `test_panic`, which panics when it runs, for testing panic handling.
+
+use crate::prelude::*;
+use serde::Serialize;
+use std::cell::RefCell;
+
+/// The name of the custom fault that panics in `testutil` builds.
+pub const TEST_PANIC_FAULT: &str = "test_panic";
+
+/// The event being executed on this thread, and the queue around it.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct EventContext {
+    pub seed: u64,
+    pub time: SimTime,
+    pub event_id: EventId,
+    /// What the event does, e.g. `timer 3 on node 1`.
+    pub event: String,
+    /// Events executed before this one.
+    pub events_processed: u64,
+    /// Events left in the queue, canceled ones included.
+    pub queue_len: usize,
+    /// Queued events other than UI ticks and periodic directive faults.
+    pub active_events: usize,
+    /// Queued events that were canceled and will not run.
+    pub canceled_events: usize,
+}
+
+thread_local! {
+    static CURRENT_EVENT: RefCell<Option<EventContext>> = const { RefCell::new(None) };
+}
+
+/// Returns the event this thread is executing, or was executing when it
+/// panicked.
+pub fn current_event() -> Option<EventContext> {
+    CURRENT_EVENT.with(|current| current.borrow().clone())
+}
+
+pub(crate) fn enter_event(context: EventContext) {
+    CURRENT_EVENT.with(|current| *current.borrow_mut() = Some(context));
+}
+
+pub(crate) fn leave_event() {
+    CURRENT_EVENT.with(|current| *current.borrow_mut() = None);
+}
@@ -0,0 +1,99 @@
+//! # ftsim-engine::effective_config
+//!
+//! Defines `EffectiveConfig`, a read-only dump of how a simulation is actually
+//! configured once the scenario, its defaults and every command-line override
+//! have been applied. It is built from the live `Simulation` rather than from
+//! the scenario file, so it shows what the engine will do, not what was asked
+//! for.
+
+use crate::net::LinkFaultModel;
+use crate::prelude::*;
+use crate::store::StoreConfig;
+use serde::Serialize;
+
+/// The merged configuration of a simulation.
+#[derive(Serialize, Debug, Clone)]
+pub struct EffectiveConfig {
+    pub seed: u64,
+    pub engine: EngineConfig,
+    pub telemetry: TelemetrySpec,
+    pub nodes: Vec<NodeConfig>,
+    /// Every directed link, ordered by link id.
+    pub links: Vec<LinkConfig>,
+    /// Cargo features the engine was built with.
+    pub features: Vec<&'static str>,
+}
+
+/// Options of the run loop itself.
+#[derive(Serialize, Debug, Clone)]
+pub struct EngineConfig {
+    pub codec_error_policy: CodecErrorPolicy,
+    pub scheduling: SchedulingPolicy,
+    pub max_events: Option<u64>,
+    pub stop_on_quiescence: bool,
+    /// Sim nanoseconds per wall-clock nanosecond; `None` is unlimited.
+    pub speed: Option<f32>,
+    pub wall_timeout_ms: Option<u64>,
+    pub state_hash_every: Option<u64>,
+    pub future_message_policy: Option<FutureMessagePolicy>,
+    pub flood_valve: Option<FloodValve>,
+    pub restart_policy: Option<RestartPolicy>,
+    pub caches: CachesSpec,
+    pub slo: Option<Slo>,
+    /// The names of the registered invariants.
+    pub invariants: Vec<String>,
+    pub invariant_check_every: u64,
+    pub breakpoints: Vec<String>,
+    pub store_journal: bool,
+    pub message_journal: bool,
+    /// Whether a flow graph is aggregated, and if so whether it is split
+    /// by message variant.
+    pub flow_graph: Option<bool>,
+    pub journal_sampling: Option<JournalSampling>,
+}
+
+/// The configuration of one node.
+#[derive(Serialize, Debug, Clone)]
+pub struct NodeConfig {
+    pub id: NodeId,
+    pub protocol: &'static str,
+    pub proto_tag: ProtoTag,
+    /// The protocols the node runs besides `protocol`, by name and tag.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub other_protocols: Vec<(&'static str, ProtoTag)>,
+    pub peers: Vec<NodeId>,
+    pub byzantine: bool,
+    pub clock_skew_ns: i128,
+    /// `None` for store backends that do not describe themselves.
+    pub store: Option<StoreConfig>,
+    pub store_latency: Option<StoreLatencySpec>,
+    pub store_faults: StoreFaultModel,
+    pub write_quota: Option<WriteQuotaSpec>,
+    pub reassembly_timeout: SimTime,
+    pub max_reassemblies: usize,
+}
+
+/// The fault model of one directed link.
+#[derive(Serialize, Debug, Clone)]
+pub struct LinkConfig {
+    pub id: LinkId,
+    pub src: NodeId,
+    pub dst: NodeId,
+    #[serde(flatten)]
+    pub faults: LinkFaultModel,
+}
+
+/// Returns the engine features enabled in this build.
+pub(crate) fn enabled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "tracing-layer") {
+        features.push("tracing-layer");
+    }
+    if cfg!(feature = "trace-export") {
+        features.push("trace-export");
+    }
+    if cfg!(feature = "byzantine") {
+        features.push("byzantine");
+    }
+    features
+}
@@ -21,33 +21,112 @@ impl EventDiscriminant {
     pub fn fault() -> Self {
         Self(0, u32::MAX)
     } // Faults have highest priority
+    /// Faults scheduled by periodic (`Every`) scenario directives. They do
+    /// not count as activity when checking for quiescence.
+    pub fn periodic_fault() -> Self {
+        Self(0, u32::MAX - 1)
+    }
     pub fn timer(src: NodeId) -> Self {
         Self(1, src)
     }
     pub fn delivery(src: NodeId) -> Self {
         Self(2, src)
     }
+    /// Watermarks sort after every fault, timer and delivery at the same time.
+    pub fn watermark(node: NodeId) -> Self {
+        Self(3, node)
+    }
     pub fn ui() -> Self {
         Self(255, u32::MAX)
     } // UI ticks have lowest priority
+
+    /// Whether the event is deferred until every non-deferred event at the
+    /// same time has run, regardless of insertion order. True for watermarks
+    /// only; UI ticks keep their place in insertion order.
+    pub fn is_deferred(&self) -> bool {
+        self.0 == 3
+    }
+
+    /// Returns the event kind's priority; lower runs first under
+    /// `SchedulingPolicy::Priority`.
+    pub fn priority(&self) -> u8 {
+        self.0
+    }
+
+    /// Returns the `(kind, node)` pair this discriminant orders by.
+    pub fn parts(&self) -> (u8, NodeId) {
+        (self.0, self.1)
+    }
 }
 
 /// Represents all possible events that can be scheduled in the simulation.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Event {
     /// Deliver a network message to a destination node.
     Deliver { env: Envelope, link_id: LinkId },
+    /// A message sent with a delay leaves its sender, whose link's faults
+    /// are sampled then. Dropped if the sender has crashed since, i.e.
+    /// `incarnation` is stale.
+    Depart { env: Envelope, incarnation: u64 },
     /// A timer set by a protocol has fired.
     TimerFired { node_id: NodeId, timer_id: TimerId },
     /// A fault injection event scheduled by the scenario runner.
     Fault(FaultEventInternal),
+    /// A client operation issued to a node by the scenario runner.
+    ClientRequest { node_id: NodeId, op: ClientOp },
+    /// Tells a node ahead of time that a scenario fault of kind
+    /// `fault_kind` will hit it at `at`.
+    Announce { node_id: NodeId, fault_kind: FaultKind, at: SimTime },
+    /// Tells a protocol that opted in that one of its sends was dropped.
+    /// Ignored if the node has restarted since, i.e. `incarnation` is
+    /// stale.
+    SendFailed {
+        node_id: NodeId,
+        incarnation: u64,
+        proto_tag: ProtoTag,
+        dst: NodeId,
+        msg_id: MsgId,
+        reason: SendFailure,
+    },
     /// A periodic tick to generate a snapshot for the TUI.
     UiSnapshotTick,
 }
 
+impl Event {
+    /// Returns the event's kind, as counted in `SimulationReport`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::Deliver { .. } => "deliver",
+            Event::Depart { .. } => "depart",
+            Event::TimerFired { .. } => "timer",
+            Event::Fault(_) => "fault",
+            Event::ClientRequest { .. } => "client",
+            Event::Announce { .. } => "announce",
+            Event::SendFailed { .. } => "send_failed",
+            Event::UiSnapshotTick => "ui_tick",
+        }
+    }
+
+    /// Returns a short, deterministic description of the event for traces.
+    pub fn describe(&self) -> String {
+        match self {
+            Event::Deliver { env, .. } => format!("deliver {}->{} msg {}", env.src, env.dst, env.msg_id),
+            Event::Depart { env, .. } => format!("depart {}->{} msg {}", env.src, env.dst, env.msg_id),
+            Event::TimerFired { node_id, timer_id } => format!("timer {} on node {}", timer_id, node_id),
+            Event::Fault(fault) => format!("fault {:?}", fault),
+            Event::ClientRequest { node_id, op } => format!("client {} on node {}", op.kind(), node_id),
+            Event::Announce { node_id, fault_kind, at } => format!("announce {} at {} to node {}", fault_kind, at, node_id),
+            Event::SendFailed { node_id, msg_id, reason, .. } => {
+                format!("send of msg {} failed ({}) on node {}", msg_id, reason, node_id)
+            }
+            Event::UiSnapshotTick => "ui tick".to_string(),
+        }
+    }
+}
+
 /// A wrapper for an `Event` that includes scheduling information.
 /// This is the type stored in the simulation's priority queue.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Queued<T> {
     pub id: EventId,
     pub time: SimTime,
@@ -55,15 +134,28 @@ pub struct Queued<T> {
     /// scheduled at the exact same time.
     pub insert_seq: u64,
     pub discriminant: EventDiscriminant,
+    /// How the event orders against others at the same time. Every event
+    /// in one queue has the same policy.
+    pub policy: SchedulingPolicy,
     pub payload: T,
 }
 
+impl Queued<Event> {
+    /// Whether the event leaves the protocols idle: a UI tick or a fault
+    /// from a periodic scenario directive.
+    pub fn is_idle(&self) -> bool {
+        matches!(self.payload, Event::UiSnapshotTick)
+            || self.discriminant == EventDiscriminant::periodic_fault()
+    }
+}
+
 impl<T> Queued<T> {
     pub fn new(
         id: EventId,
         time: SimTime,
         insert_seq: u64,
         discriminant: EventDiscriminant,
+        policy: SchedulingPolicy,
         payload: T,
     ) -> Self {
         Self {
@@ -71,6 +163,7 @@ impl<T> Queued<T> {
             time,
             insert_seq,
             discriminant,
+            policy,
             payload,
         }
     }
@@ -99,12 +192,22 @@ impl<T> Ord for Queued<T> {
     /// Compares events for the priority queue.
     /// `BinaryHeap` is a max-heap, so we reverse the ordering to make it a min-heap.
     /// The primary sort key is `time` (earlier is greater).
-    /// The secondary sort key is `insert_seq` (earlier is greater).
-    /// The tertiary sort key is `discriminant` for stable tie-breaking.
+    /// Under `SchedulingPolicy::Legacy`, watermarks then sort after all
+    /// others, and `insert_seq` decides the rest.
+    /// Under `SchedulingPolicy::Priority`, the discriminant's priority comes
+    /// next, then `insert_seq`.
+    /// The final key is `discriminant` for stable tie-breaking.
     fn cmp(&self, other: &Self) -> Ordering {
+        let same_time = match self.policy {
+            SchedulingPolicy::Legacy => {
+                other.discriminant.is_deferred().cmp(&self.discriminant.is_deferred())
+            }
+            SchedulingPolicy::Priority => other.discriminant.priority().cmp(&self.discriminant.priority()),
+        };
         other
             .time
             .cmp(&self.time)
+            .then(same_time)
             .then_with(|| other.insert_seq.cmp(&self.insert_seq))
             .then_with(|| other.discriminant.cmp(&self.discriminant))
     }
@@ -125,7 +228,7 @@ pub enum LinkModelChange {
 pub enum FaultEventInternal {
     Crash {
         node_id: NodeId,
-        duration: SimTime,
+        duration: SimDuration,
     },
     Restart {
         node_id: NodeId,
@@ -151,12 +254,122 @@ pub enum FaultEventInternal {
         node_id: NodeId,
         enabled: bool,
     },
+    GrayFailure {
+        node_id: NodeId,
+        ignore_from: GrayFilter,
+        duration: SimDuration,
+    },
     BroadcastBytes {
         payload_hex: String,
         proto_tag: Option<ProtoTag>,
     },
     Custom {
         name: String,
-        args: toml::Value,
+        args: CustomArgs,
     },
+    /// Arms an intervention on the messages sent from then on.
+    Intervene(crate::interventions::Intervention),
+}
+
+impl FaultEventInternal {
+    /// Returns the node targeted by this fault, if it targets a single node.
+    pub fn node_id(&self) -> Option<NodeId> {
+        match self {
+            FaultEventInternal::Crash { node_id, .. }
+            | FaultEventInternal::Restart { node_id }
+            | FaultEventInternal::ClockSkew { node_id, .. }
+            | FaultEventInternal::StoreFault { node_id, .. }
+            | FaultEventInternal::ByzantineFlip { node_id, .. }
+            | FaultEventInternal::GrayFailure { node_id, .. } => Some(*node_id),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BinaryHeap;
+
+    /// One event of every kind, all at the same time.
+    fn same_time_kinds() -> Vec<EventDiscriminant> {
+        vec![
+            EventDiscriminant::fault(),
+            EventDiscriminant::periodic_fault(),
+            EventDiscriminant::timer(1),
+            EventDiscriminant::timer(0),
+            EventDiscriminant::delivery(2),
+            EventDiscriminant::delivery(0),
+            EventDiscriminant::watermark(0),
+            EventDiscriminant::ui(),
+        ]
+    }
+
+    /// Calls `visit` with every permutation of `items` (Heap's algorithm).
+    fn permutations<T: Clone>(items: &mut [T], k: usize, visit: &mut impl FnMut(&[T])) {
+        if k <= 1 {
+            visit(items);
+            return;
+        }
+        for i in 0..k {
+            permutations(items, k - 1, visit);
+            let j = if k % 2 == 0 { i } else { 0 };
+            items.swap(j, k - 1);
+        }
+    }
+
+    /// Pushes `order` into a heap, inserted in slice order, and returns the
+    /// insertion sequence numbers in pop order.
+    fn pop_order(order: &[EventDiscriminant], policy: SchedulingPolicy) -> Vec<u64> {
+        let mut heap: BinaryHeap<Queued<()>> = order
+            .iter()
+            .enumerate()
+            .map(|(seq, d)| Queued::new(EventId(seq as u64), 100, seq as u64, *d, policy, ()))
+            .collect();
+        std::iter::from_fn(|| heap.pop().map(|q| q.insert_seq)).collect()
+    }
+
+    /// Checks every insertion order of `same_time_kinds` against `expected`,
+    /// which sorts `(discriminant, insert_seq)` pairs into the wanted order.
+    fn check_all_orders(policy: SchedulingPolicy, expected: impl Fn(&mut Vec<(EventDiscriminant, u64)>)) {
+        let mut kinds = same_time_kinds();
+        let n = kinds.len();
+        let mut checked = 0;
+        permutations(&mut kinds, n, &mut |order| {
+            let mut want: Vec<(EventDiscriminant, u64)> =
+                order.iter().enumerate().map(|(seq, d)| (*d, seq as u64)).collect();
+            expected(&mut want);
+            let want: Vec<u64> = want.into_iter().map(|(_, seq)| seq).collect();
+            assert_eq!(pop_order(order, policy), want, "{:?} inserted as {:?}", policy, order);
+            checked += 1;
+        });
+        assert_eq!(checked, 40_320);
+    }
+
+    #[test]
+    fn test_legacy_order_is_insertion_order_with_watermarks_last() {
+        check_all_orders(SchedulingPolicy::Legacy, |events| {
+            events.sort_by_key(|(d, seq)| (*d == EventDiscriminant::watermark(0), *seq));
+        });
+    }
+
+    #[test]
+    fn test_priority_order_puts_faults_first() {
+        check_all_orders(SchedulingPolicy::Priority, |events| {
+            events.sort_by_key(|(d, seq)| (d.priority(), *seq));
+        });
+    }
+
+    #[test]
+    fn test_time_comes_before_priority() {
+        for policy in [SchedulingPolicy::Legacy, SchedulingPolicy::Priority] {
+            let mut heap = BinaryHeap::from([
+                Queued::new(EventId(0), 20, 0, EventDiscriminant::fault(), policy, ()),
+                Queued::new(EventId(1), 10, 1, EventDiscriminant::watermark(0), policy, ()),
+                Queued::new(EventId(2), 10, 2, EventDiscriminant::delivery(0), policy, ()),
+            ]);
+            let order: Vec<EventId> = std::iter::from_fn(|| heap.pop().map(|q| q.id)).collect();
+            assert_eq!(order, vec![EventId(2), EventId(1), EventId(0)], "{:?}", policy);
+        }
+    }
 }
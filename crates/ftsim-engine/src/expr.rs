@@ -0,0 +1,944 @@
+//! # ftsim-engine::expr
+//!
+//! A small expression language over the signals a run publishes, shared by
+//! condition predicates and phase expectations:
+//!
+//! ```text
+//! count(nodes where status == "Down") <= 1
+//! kv(2, "role") == "leader" && metric(2, "term") >= 3
+//! !oracle("single_leader") || any(nodes where role == "candidate")
+//! ```
+//!
+//! `kv(node, key)`, `metric(node, key)`, `status(node)` and `oracle(name)`
+//! read a signal; `count`, `any` and `all` quantify over the nodes. Inside
+//! `nodes where ...`, `id` and `status` are the node's own, `kv(key)` and
+//! `metric(key)` read its signals, and any other bare name reads the node's
+//! KV of that name or, failing that, its metric. Node IDs and keys must be
+//! literals, so the signals an expression reads are known when it parses.
+//!
+//! Expressions cannot loop, recurse or call out, and evaluation never
+//! fails: a signal that was never published reads as `null`, which equals
+//! only `null`, orders against nothing and is false where a boolean is
+//! expected. Mixing types that can never compare, or using a number where a
+//! boolean is expected, is rejected when the expression parses.
+//!
+//! Parsing accepts any KV, metric or oracle name. Checking an expression
+//! against a `Schema` also rejects names the schema does not declare and
+//! node IDs beyond the run's nodes, so a typo fails the scenario when it
+//! loads rather than reading `null` for the whole run.
+
+use crate::{conditions::Signal, prelude::*};
+use std::{collections::BTreeSet, fmt, str::FromStr};
+
+/// A value an expression reads or computes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+}
+
+impl Value {
+    /// Converts a published KV value. Arrays and objects read as their JSON
+    /// text.
+    pub fn from_json(value: &serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(b) => Value::Bool(*b),
+            serde_json::Value::Number(n) => n.as_f64().map_or(Value::Null, Value::Num),
+            serde_json::Value::String(s) => Value::Str(s.clone()),
+            other => Value::Str(other.to_string()),
+        }
+    }
+
+    /// Whether the value counts as true where a boolean is expected.
+    pub fn is_true(&self) -> bool {
+        matches!(self, Value::Bool(true))
+    }
+}
+
+impl From<NodeStatus> for Value {
+    fn from(status: NodeStatus) -> Self {
+        let name = match status {
+            NodeStatus::Up => "Up",
+            NodeStatus::Down => "Down",
+            NodeStatus::Recovering => "Recovering",
+        };
+        Value::Str(name.to_string())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => b.fmt(f),
+            Value::Num(n) => n.fmt(f),
+            Value::Str(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+/// What an expression reads.
+pub trait Context {
+    /// The number of nodes quantifiers range over, `0..num_nodes`.
+    fn num_nodes(&self) -> usize;
+    fn kv(&self, node: NodeId, key: &str) -> Option<Value>;
+    fn metric(&self, node: NodeId, key: &str) -> Option<f64>;
+    fn status(&self, node: NodeId) -> Option<NodeStatus>;
+    /// Whether the invariant with this name held at its last check.
+    fn oracle(&self, name: &str) -> Option<bool>;
+}
+
+/// The names a strictly checked expression may read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schema {
+    pub nodes: usize,
+    pub kvs: BTreeSet<String>,
+    pub metrics: BTreeSet<String>,
+    pub oracles: BTreeSet<String>,
+}
+
+/// Why an expression did not parse or check, at a character offset into
+/// its source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExprError {
+    pub at: usize,
+    pub message: String,
+}
+
+impl ExprError {
+    fn new(at: usize, message: impl Into<String>) -> Self {
+        Self { at, message: message.into() }
+    }
+
+    /// Renders the error under `source`, with a caret at the offending
+    /// character.
+    pub fn render(&self, source: &str) -> String {
+        format!("{}\n{}^ {}", source, " ".repeat(self.at), self.message)
+    }
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.at + 1)
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+/// A parsed expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    source: String,
+    root: Node,
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self, ExprError> {
+        let tokens = lex(source)?;
+        let mut parser = Parser { tokens, pos: 0, in_quantifier: false };
+        let (root, at) = parser.parse_or()?;
+        expect_bool(&root, at)?;
+        match parser.peek() {
+            Tok::End => Ok(Self { source: source.to_string(), root }),
+            tok => Err(parser.unexpected("an operator", tok)),
+        }
+    }
+
+    /// Parses an expression and checks it against `schema`.
+    pub fn parse_strict(source: &str, schema: &Schema) -> Result<Self, ExprError> {
+        let expr = Self::parse(source)?;
+        expr.check(schema)?;
+        Ok(expr)
+    }
+
+    /// Checks that the expression reads only names `schema` declares, on
+    /// nodes that exist, returning the first that does not.
+    pub fn check(&self, schema: &Schema) -> Result<(), ExprError> {
+        self.root.check(schema)
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn eval(&self, ctx: &dyn Context) -> Value {
+        self.root.eval(ctx, None)
+    }
+
+    /// Whether the expression evaluates to `true`.
+    pub fn holds(&self, ctx: &dyn Context) -> bool {
+        self.eval(ctx).is_true()
+    }
+
+    /// The signals the expression reads when quantifiers range over
+    /// `0..num_nodes`.
+    pub fn signals(&self, num_nodes: usize) -> Vec<Signal> {
+        let mut signals = BTreeSet::new();
+        self.root.signals(num_nodes as NodeId, &mut signals);
+        signals.into_iter().collect()
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ExprError;
+
+    fn from_str(source: &str) -> Result<Self, ExprError> {
+        Self::parse(source)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// A name read by an expression, with where it appears.
+#[derive(Debug, Clone, PartialEq)]
+struct Name {
+    text: String,
+    at: usize,
+}
+
+/// The node a read is on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    Node {
+        id: NodeId,
+        at: usize,
+    },
+    /// The node a quantifier is at.
+    Current,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quantifier {
+    Count,
+    Any,
+    All,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Lit(Value),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Cmp(Comparison, Box<Node>, Box<Node>),
+    Kv {
+        node: Target,
+        key: Name,
+    },
+    Metric {
+        node: Target,
+        key: Name,
+    },
+    Status(Target),
+    Oracle(Name),
+    /// The ID of the node a quantifier is at.
+    Id,
+    /// A bare name in a quantifier: the node's KV, or else its metric.
+    Field(Name),
+    Quantify(Quantifier, Box<Node>),
+}
+
+/// The type of value a node evaluates to, where it is known statically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Bool,
+    Num,
+    Str,
+    Any,
+}
+
+impl Type {
+    fn describe(self) -> &'static str {
+        match self {
+            Type::Bool => "a boolean",
+            Type::Num => "a number",
+            Type::Str => "a string",
+            Type::Any => "a value",
+        }
+    }
+}
+
+impl Node {
+    fn ty(&self) -> Type {
+        match self {
+            Node::Lit(Value::Null) | Node::Kv { .. } | Node::Field(_) => Type::Any,
+            Node::Lit(Value::Bool(_)) | Node::Not(_) | Node::And(..) | Node::Or(..) | Node::Cmp(..) => Type::Bool,
+            Node::Lit(Value::Num(_)) | Node::Metric { .. } | Node::Id => Type::Num,
+            Node::Lit(Value::Str(_)) | Node::Status(_) => Type::Str,
+            Node::Oracle(_) => Type::Bool,
+            Node::Quantify(Quantifier::Count, _) => Type::Num,
+            Node::Quantify(..) => Type::Bool,
+        }
+    }
+
+    fn eval(&self, ctx: &dyn Context, current: Option<NodeId>) -> Value {
+        let on = |target: &Target| match *target {
+            Target::Node { id, .. } => id,
+            Target::Current => current.expect("node-relative reads only parse inside quantifiers"),
+        };
+        match self {
+            Node::Lit(value) => value.clone(),
+            Node::Not(inner) => Value::Bool(!inner.eval(ctx, current).is_true()),
+            Node::And(lhs, rhs) => Value::Bool(lhs.eval(ctx, current).is_true() && rhs.eval(ctx, current).is_true()),
+            Node::Or(lhs, rhs) => Value::Bool(lhs.eval(ctx, current).is_true() || rhs.eval(ctx, current).is_true()),
+            Node::Cmp(op, lhs, rhs) => Value::Bool(compare(*op, &lhs.eval(ctx, current), &rhs.eval(ctx, current))),
+            Node::Kv { node, key } => ctx.kv(on(node), &key.text).unwrap_or(Value::Null),
+            Node::Metric { node, key } => ctx.metric(on(node), &key.text).map_or(Value::Null, Value::Num),
+            Node::Status(node) => ctx.status(on(node)).map_or(Value::Null, Value::from),
+            Node::Oracle(name) => ctx.oracle(&name.text).map_or(Value::Null, Value::Bool),
+            Node::Id => Value::Num(f64::from(on(&Target::Current))),
+            Node::Field(name) => {
+                let node = on(&Target::Current);
+                ctx.kv(node, &name.text).or_else(|| ctx.metric(node, &name.text).map(Value::Num)).unwrap_or(Value::Null)
+            }
+            Node::Quantify(quantifier, body) => {
+                let mut nodes = 0..ctx.num_nodes() as NodeId;
+                let holds = |node| body.eval(ctx, Some(node)).is_true();
+                match quantifier {
+                    Quantifier::Count => Value::Num(nodes.filter(|&n| holds(n)).count() as f64),
+                    Quantifier::Any => Value::Bool(nodes.any(holds)),
+                    Quantifier::All => Value::Bool(nodes.all(holds)),
+                }
+            }
+        }
+    }
+
+    fn check(&self, schema: &Schema) -> Result<(), ExprError> {
+        let node_exists = |target: &Target| match *target {
+            Target::Node { id, at } if id as usize >= schema.nodes => {
+                Err(ExprError::new(at, format!("node {} does not exist; the run has {} nodes", id, schema.nodes)))
+            }
+            _ => Ok(()),
+        };
+        let declared = |names: &BTreeSet<String>, name: &Name, what: &str| {
+            if names.contains(&name.text) {
+                Ok(())
+            } else {
+                Err(ExprError::new(name.at, format!("unknown {} '{}'", what, name.text)))
+            }
+        };
+        match self {
+            Node::Lit(_) | Node::Id => Ok(()),
+            Node::Not(inner) | Node::Quantify(_, inner) => inner.check(schema),
+            Node::And(lhs, rhs) | Node::Or(lhs, rhs) | Node::Cmp(_, lhs, rhs) => {
+                lhs.check(schema)?;
+                rhs.check(schema)
+            }
+            Node::Kv { node, key } => {
+                node_exists(node)?;
+                declared(&schema.kvs, key, "KV")
+            }
+            Node::Metric { node, key } => {
+                node_exists(node)?;
+                declared(&schema.metrics, key, "metric")
+            }
+            Node::Status(node) => node_exists(node),
+            Node::Oracle(name) => declared(&schema.oracles, name, "oracle"),
+            Node::Field(name) if schema.kvs.contains(&name.text) || schema.metrics.contains(&name.text) => Ok(()),
+            Node::Field(name) => Err(ExprError::new(
+                name.at,
+                format!("unknown name '{}'; it is neither a declared KV nor metric", name.text),
+            )),
+        }
+    }
+
+    fn signals(&self, num_nodes: NodeId, out: &mut BTreeSet<Signal>) {
+        let nodes = |target: &Target| match *target {
+            Target::Node { id, .. } => id..id.saturating_add(1),
+            Target::Current => 0..num_nodes,
+        };
+        match self {
+            Node::Lit(_) | Node::Id => {}
+            Node::Not(inner) | Node::Quantify(_, inner) => inner.signals(num_nodes, out),
+            Node::And(lhs, rhs) | Node::Or(lhs, rhs) | Node::Cmp(_, lhs, rhs) => {
+                lhs.signals(num_nodes, out);
+                rhs.signals(num_nodes, out);
+            }
+            Node::Kv { node, key } => {
+                out.extend(nodes(node).map(|node| Signal::Kv { node, key: key.text.clone() }));
+            }
+            Node::Metric { node, key } => {
+                out.extend(nodes(node).map(|node| Signal::Metric { node, key: key.text.clone() }));
+            }
+            Node::Status(node) => out.extend(nodes(node).map(Signal::Status)),
+            Node::Oracle(name) => {
+                out.insert(Signal::Oracle(name.text.clone()));
+            }
+            Node::Field(name) => {
+                for node in 0..num_nodes {
+                    out.insert(Signal::Kv { node, key: name.text.clone() });
+                    out.insert(Signal::Metric { node, key: name.text.clone() });
+                }
+            }
+        }
+    }
+}
+
+/// Whether `lhs op rhs` holds. Numbers and strings order among their own
+/// kind; every other pair only compares for equality.
+fn compare(op: Comparison, lhs: &Value, rhs: &Value) -> bool {
+    match (op, lhs, rhs) {
+        (_, Value::Num(a), Value::Num(b)) => op.holds(*a, *b),
+        (_, Value::Str(a), Value::Str(b)) => op.holds(a.cmp(b) as i8 as f64, 0.0),
+        (Comparison::Eq, _, _) => lhs == rhs,
+        (Comparison::Ne, _, _) => lhs != rhs,
+        _ => false,
+    }
+}
+
+fn expect_bool(node: &Node, at: usize) -> Result<(), ExprError> {
+    match node.ty() {
+        Type::Bool | Type::Any => Ok(()),
+        ty => Err(ExprError::new(at, format!("expected a boolean, found {}", ty.describe()))),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Num(f64),
+    Str(String),
+    Ident(String),
+    LParen,
+    RParen,
+    Comma,
+    Not,
+    And,
+    Or,
+    Cmp(Comparison),
+    End,
+}
+
+impl Tok {
+    fn describe(&self) -> String {
+        match self {
+            Tok::Num(n) => format!("number {}", n),
+            Tok::Str(s) => format!("string {:?}", s),
+            Tok::Ident(name) => format!("'{}'", name),
+            Tok::LParen => "'('".to_string(),
+            Tok::RParen => "')'".to_string(),
+            Tok::Comma => "','".to_string(),
+            Tok::Not => "'!'".to_string(),
+            Tok::And => "'&&'".to_string(),
+            Tok::Or => "'||'".to_string(),
+            Tok::Cmp(op) => format!("'{}'", op.as_str()),
+            Tok::End => "the end".to_string(),
+        }
+    }
+}
+
+/// Splits `source` into tokens, each with the character offset it starts
+/// at. The last token is always `Tok::End`.
+fn lex(source: &str) -> Result<Vec<(Tok, usize)>, ExprError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let at = i;
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let tok = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '0'..='9' | '-' if c != '-' || next.is_some_and(|n| n.is_ascii_digit()) => {
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[at..i].iter().collect();
+                let n = text.parse().map_err(|_| ExprError::new(at, format!("invalid number '{}'", text)))?;
+                tokens.push((Tok::Num(n), at));
+                continue;
+            }
+            '"' | '\'' => {
+                let (s, end) = lex_string(&chars, at)?;
+                i = end;
+                tokens.push((Tok::Str(s), at));
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push((Tok::Ident(chars[at..i].iter().collect()), at));
+                continue;
+            }
+            '(' => Tok::LParen,
+            ')' => Tok::RParen,
+            ',' => Tok::Comma,
+            '!' if next == Some('=') => Tok::Cmp(Comparison::Ne),
+            '!' => Tok::Not,
+            '=' if next == Some('=') => Tok::Cmp(Comparison::Eq),
+            '<' if next == Some('=') => Tok::Cmp(Comparison::Le),
+            '<' => Tok::Cmp(Comparison::Lt),
+            '>' if next == Some('=') => Tok::Cmp(Comparison::Ge),
+            '>' => Tok::Cmp(Comparison::Gt),
+            '&' if next == Some('&') => Tok::And,
+            '|' if next == Some('|') => Tok::Or,
+            '=' | '&' | '|' => {
+                return Err(ExprError::new(at, format!("expected '{}{}', found '{}'", c, c, c)));
+            }
+            c => return Err(ExprError::new(at, format!("unexpected character '{}'", c))),
+        };
+        i += match tok {
+            Tok::Cmp(Comparison::Lt | Comparison::Gt) | Tok::Not | Tok::LParen | Tok::RParen | Tok::Comma => 1,
+            _ => 2,
+        };
+        tokens.push((tok, at));
+    }
+    tokens.push((Tok::End, chars.len()));
+    Ok(tokens)
+}
+
+/// Reads the string literal opening at `start`, returning it and the offset
+/// just past its closing quote.
+fn lex_string(chars: &[char], start: usize) -> Result<(String, usize), ExprError> {
+    let quote = chars[start];
+    let mut s = String::new();
+    let mut i = start + 1;
+    while let Some(&c) = chars.get(i) {
+        match c {
+            c if c == quote => return Ok((s, i + 1)),
+            '\\' => {
+                let escaped = match chars.get(i + 1) {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some(&c @ ('\\' | '"' | '\'')) => c,
+                    _ => return Err(ExprError::new(i, "invalid escape")),
+                };
+                s.push(escaped);
+                i += 2;
+            }
+            c => {
+                s.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err(ExprError::new(start, "unterminated string"))
+}
+
+const FUNCTIONS: &str = "kv, metric, status, oracle, count, any, all";
+
+/// A recursive-descent parser. Each `parse_*` returns the node and the
+/// offset it starts at, for errors about the node as a whole.
+struct Parser {
+    tokens: Vec<(Tok, usize)>,
+    pos: usize,
+    in_quantifier: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].0
+    }
+
+    fn at(&self) -> usize {
+        self.tokens[self.pos].1
+    }
+
+    fn next(&mut self) -> (Tok, usize) {
+        let token = self.tokens[self.pos].clone();
+        if token.0 != Tok::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn unexpected(&self, wanted: &str, found: &Tok) -> ExprError {
+        ExprError::new(self.at(), format!("expected {}, found {}", wanted, found.describe()))
+    }
+
+    fn expect(&mut self, tok: Tok) -> Result<(), ExprError> {
+        if *self.peek() == tok {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.unexpected(&tok.describe(), self.peek()))
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<(Node, usize), ExprError> {
+        let (mut lhs, at) = self.parse_and()?;
+        while *self.peek() == Tok::Or {
+            expect_bool(&lhs, at)?;
+            self.next();
+            let (rhs, rhs_at) = self.parse_and()?;
+            expect_bool(&rhs, rhs_at)?;
+            lhs = Node::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok((lhs, at))
+    }
+
+    fn parse_and(&mut self) -> Result<(Node, usize), ExprError> {
+        let (mut lhs, at) = self.parse_not()?;
+        while *self.peek() == Tok::And {
+            expect_bool(&lhs, at)?;
+            self.next();
+            let (rhs, rhs_at) = self.parse_not()?;
+            expect_bool(&rhs, rhs_at)?;
+            lhs = Node::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok((lhs, at))
+    }
+
+    /// `!` applies to a whole comparison: `!status(0) == "Up"` negates the
+    /// comparison.
+    fn parse_not(&mut self) -> Result<(Node, usize), ExprError> {
+        if *self.peek() != Tok::Not {
+            return self.parse_cmp();
+        }
+        let (_, at) = self.next();
+        let (inner, inner_at) = self.parse_not()?;
+        expect_bool(&inner, inner_at)?;
+        Ok((Node::Not(Box::new(inner)), at))
+    }
+
+    fn parse_cmp(&mut self) -> Result<(Node, usize), ExprError> {
+        let (lhs, at) = self.parse_primary()?;
+        let Tok::Cmp(op) = *self.peek() else {
+            return Ok((lhs, at));
+        };
+        let (_, op_at) = self.next();
+        let (rhs, _) = self.parse_primary()?;
+        match (lhs.ty(), rhs.ty()) {
+            (Type::Any, _) | (_, Type::Any) => {}
+            (l, r) if l != r => {
+                return Err(ExprError::new(op_at, format!("cannot compare {} to {}", l.describe(), r.describe())));
+            }
+            (Type::Bool, _) if !matches!(op, Comparison::Eq | Comparison::Ne) => {
+                return Err(ExprError::new(
+                    op_at,
+                    format!("booleans do not order; '{}' needs numbers or strings", op.as_str()),
+                ));
+            }
+            _ => {}
+        }
+        if let Tok::Cmp(_) = self.peek() {
+            return Err(ExprError::new(self.at(), "comparisons do not chain; join them with '&&'"));
+        }
+        Ok((Node::Cmp(op, Box::new(lhs), Box::new(rhs)), at))
+    }
+
+    fn parse_primary(&mut self) -> Result<(Node, usize), ExprError> {
+        let at = self.at();
+        let node = match self.next().0 {
+            Tok::Num(n) => Node::Lit(Value::Num(n)),
+            Tok::Str(s) => Node::Lit(Value::Str(s)),
+            Tok::LParen => {
+                let (inner, _) = self.parse_or()?;
+                self.expect(Tok::RParen)?;
+                inner
+            }
+            Tok::Ident(name) if *self.peek() == Tok::LParen => {
+                self.next();
+                let call = self.parse_call(&name, at)?;
+                self.expect(Tok::RParen)?;
+                call
+            }
+            Tok::Ident(name) => match name.as_str() {
+                "true" => Node::Lit(Value::Bool(true)),
+                "false" => Node::Lit(Value::Bool(false)),
+                "null" => Node::Lit(Value::Null),
+                "id" if self.in_quantifier => Node::Id,
+                "status" if self.in_quantifier => Node::Status(Target::Current),
+                _ if self.in_quantifier => Node::Field(Name { text: name, at }),
+                "nodes" => return Err(ExprError::new(at, "'nodes' only appears in count, any or all")),
+                _ => {
+                    return Err(ExprError::new(
+                        at,
+                        format!("unknown name '{}'; bare names only read a node inside count, any or all", name),
+                    ))
+                }
+            },
+            tok => {
+                self.pos -= usize::from(tok != Tok::End);
+                return Err(self.unexpected("an expression", &tok));
+            }
+        };
+        Ok((node, at))
+    }
+
+    /// Parses the arguments of a call to `name`, up to its closing `)`.
+    fn parse_call(&mut self, name: &str, at: usize) -> Result<Node, ExprError> {
+        match name {
+            "kv" | "metric" => {
+                let node = if self.in_quantifier && matches!(self.peek(), Tok::Str(_)) {
+                    Target::Current
+                } else {
+                    let node = self.parse_node_id()?;
+                    self.expect(Tok::Comma)?;
+                    node
+                };
+                let key = self.parse_name()?;
+                Ok(if name == "kv" { Node::Kv { node, key } } else { Node::Metric { node, key } })
+            }
+            "status" => Ok(Node::Status(self.parse_node_id()?)),
+            "oracle" => Ok(Node::Oracle(self.parse_name()?)),
+            "count" | "any" | "all" => {
+                if self.in_quantifier {
+                    return Err(ExprError::new(at, "quantifiers do not nest"));
+                }
+                match self.next() {
+                    (Tok::Ident(nodes), _) if nodes == "nodes" => {}
+                    (tok, _) => {
+                        self.pos -= usize::from(tok != Tok::End);
+                        return Err(self.unexpected("'nodes'", &tok));
+                    }
+                }
+                let body = match self.peek() {
+                    Tok::RParen => Node::Lit(Value::Bool(true)),
+                    Tok::Ident(kw) if kw == "where" => {
+                        self.next();
+                        self.in_quantifier = true;
+                        let body = self.parse_or();
+                        self.in_quantifier = false;
+                        let (body, body_at) = body?;
+                        expect_bool(&body, body_at)?;
+                        body
+                    }
+                    tok => return Err(self.unexpected("'where' or ')'", tok)),
+                };
+                let quantifier = match name {
+                    "count" => Quantifier::Count,
+                    "any" => Quantifier::Any,
+                    _ => Quantifier::All,
+                };
+                Ok(Node::Quantify(quantifier, Box::new(body)))
+            }
+            _ => Err(ExprError::new(at, format!("unknown function '{}'; expected one of {}", name, FUNCTIONS))),
+        }
+    }
+
+    fn parse_node_id(&mut self) -> Result<Target, ExprError> {
+        match self.peek().clone() {
+            Tok::Num(n) if n >= 0.0 && n.fract() == 0.0 && n <= f64::from(NodeId::MAX) => {
+                let (_, at) = self.next();
+                Ok(Target::Node { id: n as NodeId, at })
+            }
+            tok => Err(self.unexpected("a node ID", &tok)),
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<Name, ExprError> {
+        match self.peek().clone() {
+            Tok::Str(text) => {
+                let (_, at) = self.next();
+                Ok(Name { text, at })
+            }
+            tok => Err(self.unexpected("a quoted name", &tok)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// A run of three nodes: node 0 leads and node 2 is down.
+    #[derive(Default)]
+    struct Fixture {
+        kvs: BTreeMap<(NodeId, &'static str), Value>,
+        metrics: BTreeMap<(NodeId, &'static str), f64>,
+        oracles: BTreeMap<&'static str, bool>,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let mut fixture = Self::default();
+            fixture.kvs.insert((0, "role"), Value::Str("leader".into()));
+            fixture.kvs.insert((1, "role"), Value::Str("follower".into()));
+            fixture.kvs.insert((0, "ready"), Value::Bool(true));
+            for node in 0..3 {
+                fixture.metrics.insert((node, "term"), 3.0 + f64::from(node));
+            }
+            fixture.oracles.insert("single_leader", true);
+            fixture
+        }
+    }
+
+    impl Context for Fixture {
+        fn num_nodes(&self) -> usize {
+            3
+        }
+
+        fn kv(&self, node: NodeId, key: &str) -> Option<Value> {
+            self.kvs.iter().find(|((n, k), _)| *n == node && *k == key).map(|(_, v)| v.clone())
+        }
+
+        fn metric(&self, node: NodeId, key: &str) -> Option<f64> {
+            self.metrics.iter().find(|((n, k), _)| *n == node && *k == key).map(|(_, v)| *v)
+        }
+
+        fn status(&self, node: NodeId) -> Option<NodeStatus> {
+            (node < 3).then_some(if node == 2 { NodeStatus::Down } else { NodeStatus::Up })
+        }
+
+        fn oracle(&self, name: &str) -> Option<bool> {
+            self.oracles.get(name).copied()
+        }
+    }
+
+    fn eval(source: &str) -> Value {
+        Expr::parse(source).unwrap_or_else(|e| panic!("{}", e.render(source))).eval(&Fixture::new())
+    }
+
+    fn holds(source: &str) -> bool {
+        eval(source).is_true()
+    }
+
+    fn error(source: &str) -> (usize, String) {
+        let e = Expr::parse(source).expect_err(source);
+        (e.at, e.message)
+    }
+
+    fn schema() -> Schema {
+        Schema {
+            nodes: 3,
+            kvs: ["role".to_string()].into(),
+            metrics: ["term".to_string()].into(),
+            oracles: ["single_leader".to_string()].into(),
+        }
+    }
+
+    #[test]
+    fn test_reads_and_comparisons() {
+        assert!(holds(r#"kv(0, "role") == "leader""#));
+        assert!(holds(r#"kv(1, 'role') != "leader""#));
+        assert!(holds(r#"metric(2, "term") >= 5"#));
+        assert!(holds(r#"metric(0, "term") < 3.5"#));
+        assert!(holds(r#"status(2) == "Down" && status(0) == "Up""#));
+        assert!(holds(r#"oracle("single_leader")"#));
+        assert!(holds(r#"kv(0, "ready")"#));
+        assert!(holds(r#""a" < "b""#));
+        assert!(holds("-1 < 0"));
+        assert_eq!(eval(r#"kv(0, "role")"#), Value::Str("leader".into()));
+    }
+
+    #[test]
+    fn test_boolean_operators_and_precedence() {
+        assert!(holds("true || false && false"));
+        assert!(!holds("(true || false) && false"));
+        assert!(holds("!false && !(1 > 2)"));
+        assert!(holds("!!true"));
+        // `!` negates the whole comparison
+        assert!(holds(r#"!status(2) == "Up""#));
+        assert!(holds(r#"  kv(0,"role")=="leader"||false  "#));
+    }
+
+    #[test]
+    fn test_missing_signals_read_as_null() {
+        assert_eq!(eval(r#"kv(1, "ready")"#), Value::Null);
+        assert_eq!(eval(r#"oracle("unknown")"#), Value::Null);
+        assert!(!holds(r#"kv(1, "ready")"#));
+        assert!(!holds(r#"metric(0, "missing") < 1"#));
+        assert!(!holds(r#"metric(0, "missing") >= 1"#));
+        assert!(holds(r#"metric(0, "missing") != 1"#));
+        assert!(holds(r#"kv(2, "role") == null"#));
+        assert!(!holds(r#"status(7) == "Up""#));
+        // Mismatched values only ever differ
+        assert!(!holds(r#"kv(0, "role") > 1"#));
+        assert!(holds(r#"kv(0, "role") != 1"#));
+    }
+
+    #[test]
+    fn test_quantifiers() {
+        assert!(holds(r#"count(nodes where status == "Down") == 1"#));
+        assert!(holds("count(nodes) == 3"));
+        assert!(holds(r#"count(nodes where status == "Up") >= 2"#));
+        assert!(holds(r#"any(nodes where role == "leader")"#));
+        assert!(holds(r#"count(nodes where kv("role") == "leader" && id == 0) == 1"#));
+        assert!(holds(r#"all(nodes where term >= 3)"#));
+        assert!(holds(r#"all(nodes where metric("term") > id)"#));
+        assert!(!holds(r#"all(nodes where status(0) == "Up" && status == "Up")"#));
+        assert!(holds("any(nodes where id == 2)"));
+        // A node with neither a KV nor a metric of the name reads null
+        assert!(holds(r#"count(nodes where role != null) == 2"#));
+    }
+
+    #[test]
+    fn test_parse_errors_point_at_the_offending_character() {
+        assert_eq!(error("kv(0, \"role\") = 1").0, 14);
+        assert_eq!(error("true & false"), (5, "expected '&&', found '&'".to_string()));
+        assert_eq!(error("1 < 2 < 3"), (6, "comparisons do not chain; join them with '&&'".to_string()));
+        assert_eq!(error("\"open"), (0, "unterminated string".to_string()));
+        assert_eq!(error("true #"), (5, "unexpected character '#'".to_string()));
+        assert_eq!(error("(true"), (5, "expected ')', found the end".to_string()));
+        assert_eq!(error("true false").0, 5);
+        assert_eq!(error("").1, "expected an expression, found the end");
+        assert_eq!(error("leader == 1").0, 0);
+        assert_eq!(error("nodes").0, 0);
+        assert_eq!(error("frob(1)").1, format!("unknown function 'frob'; expected one of {}", FUNCTIONS));
+        assert_eq!(error("status(1.5) == \"Up\"").0, 7);
+        assert_eq!(error("status(-1) == \"Up\"").0, 7);
+        assert_eq!(error("kv(0, role)").0, 6);
+        assert_eq!(error("kv(\"role\")").0, 3);
+        assert_eq!(error("count(where true)").0, 6);
+        assert_eq!(error("count(nodes if true)").0, 12);
+        assert_eq!(error("any(nodes where count(nodes) > 1)").0, 16);
+        assert_eq!(error("\"a\\q\"").0, 2);
+        assert_eq!(error("1.2.3 > 0"), (0, "invalid number '1.2.3'".to_string()));
+    }
+
+    #[test]
+    fn test_type_errors() {
+        assert_eq!(error("count(nodes)"), (0, "expected a boolean, found a number".to_string()));
+        assert_eq!(error("true && 1").0, 8);
+        assert_eq!(error("!\"x\"").0, 1);
+        assert_eq!(error("any(nodes where id)").0, 16);
+        assert_eq!(error(r#"status(0) == 1"#), (10, "cannot compare a string to a number".to_string()));
+        assert_eq!(error("true < false").0, 5);
+        // A KV can hold anything
+        assert!(Expr::parse(r#"kv(0, "x") == 1 || kv(0, "x") == "one""#).is_ok());
+    }
+
+    #[test]
+    fn test_render_marks_the_offending_character() {
+        let source = "status(0) === \"Up\"";
+        let e = Expr::parse(source).unwrap_err();
+        assert_eq!(e.render(source), "status(0) === \"Up\"\n            ^ expected '==', found '='");
+        assert_eq!(e.to_string(), "expected '==', found '=' at column 13");
+    }
+
+    #[test]
+    fn test_strict_checks_names_against_the_schema() {
+        let schema = schema();
+        assert!(Expr::parse_strict(r#"kv(2, "role") == "leader" && metric(0, "term") > 1"#, &schema).is_ok());
+        assert!(Expr::parse_strict(r#"count(nodes where status == "Down" || role == "x" || term > 1) < 2"#, &schema)
+            .is_ok());
+        assert!(Expr::parse_strict(r#"oracle("single_leader")"#, &schema).is_ok());
+
+        let strict_error = |source: &str| {
+            // Lenient parsing accepts it
+            let expr = Expr::parse(source).unwrap();
+            let e = expr.check(&schema).unwrap_err();
+            (e.at, e.message)
+        };
+        assert_eq!(strict_error(r#"kv(0, "rloe") == 1"#), (6, "unknown KV 'rloe'".to_string()));
+        assert_eq!(strict_error(r#"metric(0, "trem") > 1"#), (10, "unknown metric 'trem'".to_string()));
+        assert_eq!(strict_error(r#"oracle("nope")"#), (7, "unknown oracle 'nope'".to_string()));
+        assert_eq!(
+            strict_error(r#"any(nodes where stauts == "Down")"#),
+            (16, "unknown name 'stauts'; it is neither a declared KV nor metric".to_string())
+        );
+        assert_eq!(strict_error(r#"status(3) == "Up""#), (7, "node 3 does not exist; the run has 3 nodes".to_string()));
+    }
+
+    #[test]
+    fn test_signals_cover_every_read() {
+        let expr = Expr::parse(r#"kv(1, "role") == "leader" || count(nodes where status == "Down") > 0"#).unwrap();
+        let signals = expr.signals(2);
+        assert_eq!(signals, [Signal::Kv { node: 1, key: "role".into() }, Signal::Status(0), Signal::Status(1),]);
+        let expr = Expr::parse(r#"oracle("o") && any(nodes where role == "x")"#).unwrap();
+        assert_eq!(expr.signals(1).len(), 3);
+    }
+}
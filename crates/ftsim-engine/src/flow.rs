@@ -0,0 +1,283 @@
+//! # ftsim-engine::flow
+//!
+//! A weighted graph of who talks to whom. Every delivered protocol message
+//! adds to the edge from its sender to its receiver, counting messages and
+//! payload bytes, optionally split by message variant. When the scenario
+//! defines phases, each phase also gets a sub-graph of the deliveries that
+//! fell into it.
+//!
+//! The graph exports as DOT, for rendering, and as JSON, for analysis. Edge
+//! thickness is bucketed from the message counts with integer arithmetic,
+//! so the same run always renders the same way.
+
+use crate::net::MessageJournal;
+use crate::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// The number of edge thickness buckets in DOT output.
+const PEN_BUCKETS: u64 = 4;
+
+/// The variant of messages the protocol could not decode.
+const UNKNOWN_VARIANT: &str = "<unknown>";
+
+/// A directed edge, per message variant when the graph is split by variant.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EdgeKey {
+    pub src: NodeId,
+    pub dst: NodeId,
+    pub variant: Option<String>,
+}
+
+/// What went over an edge.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EdgeWeight {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+/// The edges of one phase, keyed by delivery time within `[start, end)`.
+#[derive(Debug, Clone)]
+pub struct PhaseFlow {
+    pub name: String,
+    pub start: SimTime,
+    pub end: SimTime,
+    pub edges: BTreeMap<EdgeKey, EdgeWeight>,
+}
+
+/// The aggregated message flow of a run.
+#[derive(Debug, Clone, Default)]
+pub struct FlowGraph {
+    by_variant: bool,
+    nodes: BTreeSet<NodeId>,
+    edges: BTreeMap<EdgeKey, EdgeWeight>,
+    phases: Vec<PhaseFlow>,
+}
+
+/// One edge as exported to JSON.
+#[derive(Serialize, Debug)]
+struct JsonEdge<'a> {
+    src: NodeId,
+    dst: NodeId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    variant: Option<&'a str>,
+    messages: u64,
+    bytes: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct JsonPhase<'a> {
+    name: &'a str,
+    start: SimTime,
+    end: SimTime,
+    edges: Vec<JsonEdge<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+struct JsonGraph<'a> {
+    by_variant: bool,
+    nodes: Vec<NodeId>,
+    edges: Vec<JsonEdge<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    phases: Vec<JsonPhase<'a>>,
+}
+
+impl FlowGraph {
+    /// Starts an empty graph over `nodes`, so that nodes that never send or
+    /// receive still show up.
+    pub fn new(nodes: impl IntoIterator<Item = NodeId>, by_variant: bool) -> Self {
+        Self {
+            by_variant,
+            nodes: nodes.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Adds a sub-graph per phase.
+    pub fn with_phases(mut self, phases: &[Phase]) -> Self {
+        self.phases = phases
+            .iter()
+            .map(|p| PhaseFlow { name: p.name.clone(), start: p.start, end: p.end, edges: BTreeMap::new() })
+            .collect();
+        self
+    }
+
+    /// Builds the graph from the deliveries in a message journal. A sampled
+    /// journal only yields the sampled part of the flow.
+    pub fn from_journal(journal: &MessageJournal, by_variant: bool, phases: &[Phase]) -> Self {
+        let mut graph = Self::new([], by_variant).with_phases(phases);
+        for record in journal.records() {
+            for delivery in &record.deliveries {
+                graph.record(delivery.time, record.src, record.dst, record.bytes, record.variant.as_deref());
+            }
+        }
+        graph
+    }
+
+    /// Returns whether edges are split by message variant.
+    pub fn by_variant(&self) -> bool {
+        self.by_variant
+    }
+
+    pub fn nodes(&self) -> &BTreeSet<NodeId> {
+        &self.nodes
+    }
+
+    pub fn edges(&self) -> &BTreeMap<EdgeKey, EdgeWeight> {
+        &self.edges
+    }
+
+    pub fn phases(&self) -> &[PhaseFlow] {
+        &self.phases
+    }
+
+    /// Returns the weight of the edge from `src` to `dst`, summed over
+    /// variants.
+    pub fn weight(&self, src: NodeId, dst: NodeId) -> EdgeWeight {
+        self.edges
+            .range(EdgeKey { src, dst, variant: None }..)
+            .take_while(|(key, _)| key.src == src && key.dst == dst)
+            .fold(EdgeWeight::default(), |sum, (_, w)| EdgeWeight {
+                messages: sum.messages + w.messages,
+                bytes: sum.bytes + w.bytes,
+            })
+    }
+
+    /// Counts a message of `bytes` delivered at `time`. `variant` is ignored
+    /// unless the graph is split by variant.
+    pub fn record(&mut self, time: SimTime, src: NodeId, dst: NodeId, bytes: u64, variant: Option<&str>) {
+        let key = EdgeKey {
+            src,
+            dst,
+            variant: self.by_variant.then(|| variant.unwrap_or(UNKNOWN_VARIANT).to_string()),
+        };
+        self.nodes.extend([src, dst]);
+        for phase in self.phases.iter_mut().filter(|p| p.start <= time && time < p.end) {
+            add(&mut phase.edges, key.clone(), bytes);
+        }
+        add(&mut self.edges, key, bytes);
+    }
+
+    /// Renders the graph as DOT, with one cluster per phase.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph flow {\n  node [shape=circle];\n");
+        write_edges(&mut out, "  ", "", &self.nodes, &self.edges);
+        for (i, phase) in self.phases.iter().enumerate() {
+            let prefix = format!("p{}_", i);
+            let _ = writeln!(out, "  subgraph cluster_p{} {{", i);
+            let _ = writeln!(out, "    label=\"{} [{}, {})\";", escape(&phase.name), phase.start, phase.end);
+            write_edges(&mut out, "    ", &prefix, &self.nodes, &phase.edges);
+            out.push_str("  }\n");
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Renders the graph as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        let graph = JsonGraph {
+            by_variant: self.by_variant,
+            nodes: self.nodes.iter().copied().collect(),
+            edges: json_edges(&self.edges),
+            phases: self
+                .phases
+                .iter()
+                .map(|p| JsonPhase { name: &p.name, start: p.start, end: p.end, edges: json_edges(&p.edges) })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&graph).expect("flow graphs always serialize")
+    }
+}
+
+fn add(edges: &mut BTreeMap<EdgeKey, EdgeWeight>, key: EdgeKey, bytes: u64) {
+    let weight = edges.entry(key).or_default();
+    weight.messages += 1;
+    weight.bytes += bytes;
+}
+
+fn json_edges(edges: &BTreeMap<EdgeKey, EdgeWeight>) -> Vec<JsonEdge<'_>> {
+    edges
+        .iter()
+        .map(|(key, w)| JsonEdge {
+            src: key.src,
+            dst: key.dst,
+            variant: key.variant.as_deref(),
+            messages: w.messages,
+            bytes: w.bytes,
+        })
+        .collect()
+}
+
+/// The thickness bucket of an edge, from 1 to `PEN_BUCKETS`, relative to
+/// the busiest edge of the same graph.
+fn pen_width(messages: u64, max: u64) -> u64 {
+    (messages * PEN_BUCKETS).div_ceil(max.max(1)).clamp(1, PEN_BUCKETS)
+}
+
+fn write_edges(
+    out: &mut String,
+    indent: &str,
+    prefix: &str,
+    nodes: &BTreeSet<NodeId>,
+    edges: &BTreeMap<EdgeKey, EdgeWeight>,
+) {
+    for node in nodes {
+        let _ = writeln!(out, "{}{}n{} [label=\"n{}\"];", indent, prefix, node, node);
+    }
+    let max = edges.values().map(|w| w.messages).max().unwrap_or(0);
+    for (key, w) in edges {
+        let variant = key.variant.as_deref().map_or(String::new(), |v| format!("{}: ", escape(v)));
+        let _ = writeln!(
+            out,
+            "{}{}n{} -> {}n{} [label=\"{}{} msgs / {} B\", penwidth={}];",
+            indent,
+            prefix,
+            key.src,
+            prefix,
+            key.dst,
+            variant,
+            w.messages,
+            w.bytes,
+            pen_width(w.messages, max)
+        );
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phase(name: &str, start: SimTime, end: SimTime) -> Phase {
+        Phase { name: name.to_string(), start, end, expect: Default::default() }
+    }
+
+    #[test]
+    fn test_edges_split_by_variant_and_phase() {
+        let mut graph = FlowGraph::new(0..3, true).with_phases(&[phase("early", 0, 100), phase("late", 100, 200)]);
+        graph.record(10, 0, 1, 8, Some("Ping"));
+        graph.record(20, 0, 1, 8, Some("Ping"));
+        graph.record(30, 1, 0, 4, Some("Pong"));
+        graph.record(100, 0, 1, 16, None);
+
+        assert_eq!(graph.weight(0, 1), EdgeWeight { messages: 3, bytes: 32 });
+        assert_eq!(graph.weight(1, 2), EdgeWeight::default());
+        let unknown = EdgeKey { src: 0, dst: 1, variant: Some(UNKNOWN_VARIANT.to_string()) };
+        assert_eq!(graph.edges()[&unknown], EdgeWeight { messages: 1, bytes: 16 });
+        assert_eq!(graph.phases()[0].edges.len(), 2);
+        assert_eq!(graph.phases()[1].edges.keys().collect::<Vec<_>>(), [&unknown]);
+    }
+
+    #[test]
+    fn test_pen_width_buckets() {
+        assert_eq!(pen_width(1, 100), 1);
+        assert_eq!(pen_width(26, 100), 2);
+        assert_eq!(pen_width(75, 100), 3);
+        assert_eq!(pen_width(100, 100), 4);
+        assert_eq!(pen_width(0, 0), 1);
+    }
+}
@@ -0,0 +1,182 @@
+//! # ftsim-engine::histogram
+//!
+//! A fixed-bucket histogram of nanosecond latencies. Values below 32 get a
+//! bucket each; above that, every power of two is split into 16 equal
+//! buckets, so a percentile read from the buckets is within 1/16 of the
+//! recorded value. Percentiles are clamped to the smallest and largest
+//! value recorded, so a histogram of one repeated value reports it exactly.
+//! `AtomicHistogram` records into the same buckets without a lock, for
+//! values recorded on every event.
+
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// The number of buckets each power of two is split into, as a power of two.
+const SUB_BUCKET_BITS: u32 = 4;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+
+/// How many buckets there are, the last holding `u64::MAX`.
+const BUCKETS: usize = bucket_of(u64::MAX) as usize + 1;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Histogram {
+    /// Non-empty buckets, by index.
+    buckets: BTreeMap<u32, u64>,
+    count: u64,
+    min: u64,
+    max: u64,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, value: u64) {
+        *self.buckets.entry(bucket_of(value)).or_default() += 1;
+        if self.count == 0 {
+            (self.min, self.max) = (value, value);
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the nearest-rank `q`th percentile, for `q` in `0.0..=100.0`,
+    /// as the upper bound of its bucket, or `None` if nothing was recorded.
+    pub fn percentile(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q / 100.0 * self.count as f64).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        let bucket = self.buckets.iter().find_map(|(&bucket, &n)| {
+            seen += n;
+            (seen >= rank).then_some(bucket)
+        })?;
+        Some(bucket_max(bucket).clamp(self.min, self.max))
+    }
+}
+
+const fn bucket_of(value: u64) -> u32 {
+    if value < 2 * SUB_BUCKETS {
+        return value as u32;
+    }
+    let shift = (63 - value.leading_zeros()) - SUB_BUCKET_BITS;
+    (shift << SUB_BUCKET_BITS) + (value >> shift) as u32
+}
+
+/// The largest value that falls into `bucket`.
+fn bucket_max(bucket: u32) -> u64 {
+    if u64::from(bucket) < 2 * SUB_BUCKETS {
+        return u64::from(bucket);
+    }
+    let shift = (bucket >> SUB_BUCKET_BITS) - 1;
+    let top = SUB_BUCKETS + u64::from(bucket) % SUB_BUCKETS;
+    ((top + 1) << shift).wrapping_sub(1)
+}
+
+/// A histogram one thread records into while others read it. Every bucket
+/// is an atomic counter, so recording takes no lock.
+#[derive(Debug)]
+pub struct AtomicHistogram {
+    buckets: Box<[AtomicU64]>,
+    min: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Default for AtomicHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            min: AtomicU64::new(u64::MAX),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+impl AtomicHistogram {
+    pub fn record(&self, value: u64) {
+        // The bounds before the bucket, so a reader that sees the value
+        // counted also sees it within them
+        self.min.fetch_min(value, Ordering::Relaxed);
+        self.max.fetch_max(value, Ordering::Relaxed);
+        self.buckets[bucket_of(value) as usize].fetch_add(1, Ordering::Release);
+    }
+
+    /// Copies the values recorded so far into a `Histogram`.
+    pub fn load(&self) -> Histogram {
+        let mut histogram = Histogram::new();
+        for (bucket, n) in self.buckets.iter().enumerate() {
+            let n = n.load(Ordering::Acquire);
+            if n > 0 {
+                histogram.buckets.insert(bucket as u32, n);
+                histogram.count += n;
+            }
+        }
+        if histogram.count > 0 {
+            histogram.min = self.min.load(Ordering::Relaxed);
+            histogram.max = self.max.load(Ordering::Relaxed);
+        }
+        histogram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets_bound_their_values() {
+        for value in [0, 1, 31, 32, 33, 1_000, 49_999_999, 50_000_000, u64::MAX / 3, u64::MAX] {
+            let bucket = bucket_of(value);
+            assert!(bucket_max(bucket) >= value, "{} above its bucket", value);
+            assert!(bucket == 0 || bucket_max(bucket - 1) < value, "{} below its bucket", value);
+            // Within 1/16 of the value
+            assert!(bucket_max(bucket) - value <= value / SUB_BUCKETS, "{} too coarse", value);
+        }
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let mut histogram = Histogram::new();
+        assert_eq!(histogram.percentile(99.0), None);
+        for _ in 0..10 {
+            histogram.record(7_000_000);
+        }
+        assert_eq!(histogram.percentile(50.0), Some(7_000_000));
+        assert_eq!(histogram.percentile(99.0), Some(7_000_000));
+
+        for value in 1..=100 {
+            histogram.record(value * 1_000);
+        }
+        assert_eq!(histogram.count(), 110);
+        // The upper bound of the smallest value's bucket
+        assert_eq!(histogram.percentile(0.0), Some(1_023));
+        assert_eq!(histogram.percentile(100.0), Some(7_000_000));
+        let p50 = histogram.percentile(50.0).unwrap();
+        assert!((55_000..=55_000 + 55_000 / 16).contains(&p50), "p50 {}", p50);
+    }
+
+    #[test]
+    fn test_atomic_histogram_loads_what_a_histogram_records() {
+        let (atomic, mut expected) = (AtomicHistogram::default(), Histogram::new());
+        assert_eq!(atomic.load(), expected);
+        for value in [0, 31, 7_000_000, 55_000, u64::MAX, 7_000_000] {
+            atomic.record(value);
+            expected.record(value);
+        }
+        assert_eq!(atomic.load(), expected);
+    }
+}
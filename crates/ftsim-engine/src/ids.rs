@@ -5,9 +5,10 @@
 use crate::prelude::*;
 
 /// A generator for various kinds of simulation IDs.
+#[derive(Hash, Clone)]
 pub struct IdGen {
     event_id: EventId,
-    msg_id: u64,
+    msg_id: MsgId,
     timer_id: TimerId,
     /// Used for deterministic tie-breaking in the event queue.
     insertion_seq: u64,
@@ -16,28 +17,28 @@ pub struct IdGen {
 impl IdGen {
     pub fn new() -> Self {
         Self {
-            event_id: 0,
-            msg_id: 0,
-            timer_id: 0,
+            event_id: EventId(0),
+            msg_id: MsgId(0),
+            timer_id: TimerId(0),
             insertion_seq: 0,
         }
     }
 
     pub fn next_event_id(&mut self) -> EventId {
         let id = self.event_id;
-        self.event_id = self.event_id.checked_add(1).expect("EventId overflow");
+        self.event_id = self.event_id.checked_next().expect("EventId overflow");
         id
     }
 
-    pub fn next_msg_id(&mut self) -> u64 {
+    pub fn next_msg_id(&mut self) -> MsgId {
         let id = self.msg_id;
-        self.msg_id = self.msg_id.checked_add(1).expect("MsgId overflow");
+        self.msg_id = self.msg_id.checked_next().expect("MsgId overflow");
         id
     }
 
     pub fn next_timer_id(&mut self) -> TimerId {
         let id = self.timer_id;
-        self.timer_id = self.timer_id.checked_add(1).expect("TimerId overflow");
+        self.timer_id = self.timer_id.checked_next().expect("TimerId overflow");
         id
     }
 
@@ -50,3 +51,43 @@ impl IdGen {
         id
     }
 }
+
+impl Default for IdGen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_serialize_as_bare_numbers() {
+        assert_eq!(serde_json::to_string(&TimerId(7)).unwrap(), "7");
+        assert_eq!(serde_json::to_string(&MsgId(u64::MAX)).unwrap(), u64::MAX.to_string());
+        assert_eq!(serde_json::from_str::<EventId>("42").unwrap(), EventId(42));
+        let ids = (TimerId(1), MsgId(2), EventId(3));
+        let json = serde_json::to_string(&ids).unwrap();
+        assert_eq!(json, "[1,2,3]");
+        assert_eq!(serde_json::from_str::<(TimerId, MsgId, EventId)>(&json).unwrap(), ids);
+    }
+
+    #[test]
+    fn test_ids_display_as_bare_numbers() {
+        // Metric labels and log lines print IDs as plain integers
+        assert_eq!(TimerId(12).to_string(), "12");
+        assert_eq!(format!("msg {}", MsgId(0)), "msg 0");
+        assert_eq!(format!("{:>4}", EventId(5)), "   5");
+    }
+
+    #[test]
+    fn test_generator_counts_each_kind_separately() {
+        let mut ids = IdGen::new();
+        assert_eq!(ids.next_timer_id(), TimerId(0));
+        assert_eq!(ids.next_timer_id(), TimerId(1));
+        assert_eq!(ids.next_msg_id(), MsgId(0));
+        assert_eq!(ids.next_event_id(), EventId(0));
+        assert_eq!(u64::from(ids.next_msg_id()), 1);
+    }
+}
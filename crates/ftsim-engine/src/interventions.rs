@@ -0,0 +1,101 @@
+//! # ftsim-engine::interventions
+//!
+//! Surgical interventions on single messages, for minimizing a failing
+//! trace: "drop the 3rd AppendEntries from node 1 to node 2". An armed
+//! intervention counts the messages sent from its `src` to its `dst`,
+//! optionally only those of one kind, and acts on exactly the `n`th. The
+//! count depends only on what the protocols send, so the same seed always
+//! hits the same message, and the `msg_id` it hit is recorded for the report
+//! and the message journal.
+
+use crate::prelude::*;
+use serde::Serialize;
+
+/// What an intervention does to the message it hits.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterventionKind {
+    Drop,
+    /// Holds the message back by this much sim time before the link's own
+    /// delay.
+    Delay(SimTime),
+}
+
+/// The message an intervention acted on.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterventionHit {
+    pub msg_id: MsgId,
+    /// The sim time the message was sent at.
+    pub time: SimTime,
+}
+
+/// An armed intervention and its bookkeeping.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Intervention {
+    pub src: NodeId,
+    pub dst: NodeId,
+    /// Only messages of this kind, as named by
+    /// `ProtocolDyn::message_variant`, are counted.
+    pub variant: Option<String>,
+    /// The occurrence to act on, counting from 1.
+    pub n: u64,
+    pub kind: InterventionKind,
+    /// Matching messages counted so far.
+    pub seen: u64,
+    pub hit: Option<InterventionHit>,
+}
+
+impl Intervention {
+    pub fn new(src: NodeId, dst: NodeId, variant: Option<String>, n: u64, kind: InterventionKind) -> Self {
+        Self {
+            src,
+            dst,
+            variant,
+            n,
+            kind,
+            seen: 0,
+            hit: None,
+        }
+    }
+
+    /// Whether the intervention still counts messages from `src` to `dst`.
+    pub fn watches(&self, src: NodeId, dst: NodeId) -> bool {
+        self.hit.is_none() && self.src == src && self.dst == dst
+    }
+
+    /// Counts a message this intervention watches, of kind `variant`, and
+    /// returns whether it is the occurrence to act on.
+    pub fn observe(&mut self, env: &Envelope, variant: Option<&str>, now: SimTime) -> bool {
+        if self.variant.as_deref().is_some_and(|want| variant != Some(want)) {
+            return false;
+        }
+        self.seen += 1;
+        if self.seen != self.n {
+            return false;
+        }
+        self.hit = Some(InterventionHit {
+            msg_id: env.msg_id,
+            time: now,
+        });
+        true
+    }
+
+    /// Hashes the intervention's progress for `Simulation::state_hash`.
+    pub fn hash_state<H: std::hash::Hasher>(&self, hasher: &mut H) {
+        hasher.write_u64(self.seen);
+        hasher.write_u64(self.hit.map_or(u64::MAX, |hit| hit.msg_id.0));
+    }
+}
+
+impl std::fmt::Display for Intervention {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            InterventionKind::Drop => write!(f, "drop")?,
+            InterventionKind::Delay(by) => write!(f, "delay by {}ns", by)?,
+        }
+        write!(f, " message #{}", self.n)?;
+        if let Some(variant) = &self.variant {
+            write!(f, " of kind {}", variant)?;
+        }
+        write!(f, " from node {} to node {}", self.src, self.dst)
+    }
+}
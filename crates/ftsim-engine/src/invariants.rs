@@ -0,0 +1,373 @@
+//! # ftsim-engine::invariants
+//!
+//! Safety properties checked by the engine while a run is in progress. An
+//! `Invariant` sees the world and every node's published custom KVs; the
+//! simulation evaluates its registered invariants after every fault and every
+//! `K` other events, and stops at the first violation. Built-in invariants can
+//! be named from a scenario's `invariants` list.
+
+use crate::{convergence::REPLICA_HASH_KEY, prelude::*};
+use indexmap::IndexMap;
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt};
+
+/// A property that must hold throughout a run.
+pub trait Invariant: Send {
+    /// A short name used in violation reports.
+    fn name(&self) -> &str;
+
+    /// Checks the property. `node_kvs[i]` holds the custom KVs last
+    /// published by node `i`.
+    fn check(&mut self, world: &World, node_kvs: &[IndexMap<String, Value>], time: SimTime) -> Result<(), String>;
+}
+
+/// The first invariant that failed in a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvariantViolation {
+    pub invariant: String,
+    pub time: SimTime,
+    /// The event after which the check failed.
+    pub event_id: EventId,
+    pub message: String,
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invariant '{}' violated at t={} after event {}: {}",
+            self.invariant, self.time, self.event_id, self.message
+        )
+    }
+}
+
+/// The names accepted by `builtin`.
+pub const BUILTIN_INVARIANTS: &[&str] = &[
+    "single_leader_per_term",
+    "at_most_one_leader",
+    "all_nodes_up",
+    "log_matching",
+    "causal_delivery",
+    "crdt_convergence_after_quiescence",
+    "linearizable_reads",
+];
+
+/// Returns the built-in invariant with this name.
+pub fn builtin(name: &str) -> Option<Box<dyn Invariant>> {
+    match name {
+        "single_leader_per_term" => Some(Box::<SingleLeaderPerTerm>::default()),
+        "at_most_one_leader" => Some(Box::new(AtMostOneLeader)),
+        "all_nodes_up" => Some(Box::new(AllNodesUp)),
+        "log_matching" => Some(Box::new(LogMatching)),
+        "causal_delivery" => Some(Box::new(CausalDelivery)),
+        "crdt_convergence_after_quiescence" => Some(Box::<CrdtConvergence>::default()),
+        "linearizable_reads" => Some(Box::<LinearizableReads>::default()),
+        _ => None,
+    }
+}
+
+/// Returns whether a node is up and publishes the `leader` (or `primary`) role.
+fn is_up_leader(world: &World, node_kvs: &[IndexMap<String, Value>], node: usize) -> bool {
+    world.nodes[node].status == NodeStatus::Up
+        && node_kvs
+            .get(node)
+            .and_then(|kvs| kvs.get("role"))
+            .is_some_and(is_leader_role)
+}
+
+/// Whether a published `role` is `leader` or `primary`, in any case.
+pub(crate) fn is_leader_role(role: &Value) -> bool {
+    role.as_str()
+        .is_some_and(|r| r.eq_ignore_ascii_case("leader") || r.eq_ignore_ascii_case("primary"))
+}
+
+/// No two nodes are ever leader in the same `term`, over the whole run.
+/// Reads the `role` custom KV and the `term` metric (or KV).
+#[derive(Debug, Default)]
+pub struct SingleLeaderPerTerm {
+    leaders: BTreeMap<u64, NodeId>,
+}
+
+impl Invariant for SingleLeaderPerTerm {
+    fn name(&self) -> &str {
+        "single_leader_per_term"
+    }
+
+    fn check(&mut self, world: &World, node_kvs: &[IndexMap<String, Value>], _time: SimTime) -> Result<(), String> {
+        for node in 0..world.nodes.len() {
+            if !is_up_leader(world, node_kvs, node) {
+                continue;
+            }
+            let Some(term) = node_kvs[node]
+                .get("term")
+                .and_then(|t| {
+                    t.as_str()
+                        .and_then(|s| s.parse().ok())
+                        .or_else(|| t.as_u64())
+                        .or_else(|| t.as_f64().map(|f| f as u64))
+                })
+            else {
+                continue;
+            };
+            let leader = *self.leaders.entry(term).or_insert(node as NodeId);
+            if leader != node as NodeId {
+                return Err(format!("nodes {} and {} were both leader in term {}", leader, node, term));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// At most one up node reports the leader role at any moment. Stricter than
+/// `single_leader_per_term`: a deposed leader that has not yet heard of the
+/// new term breaks it.
+#[derive(Debug, Default)]
+pub struct AtMostOneLeader;
+
+impl Invariant for AtMostOneLeader {
+    fn name(&self) -> &str {
+        "at_most_one_leader"
+    }
+
+    fn check(&mut self, world: &World, node_kvs: &[IndexMap<String, Value>], _time: SimTime) -> Result<(), String> {
+        let leaders: Vec<usize> = (0..world.nodes.len())
+            .filter(|&node| is_up_leader(world, node_kvs, node))
+            .collect();
+        if leaders.len() > 1 {
+            return Err(format!("nodes {:?} are all leader", leaders));
+        }
+        Ok(())
+    }
+}
+
+/// Every node is up.
+#[derive(Debug, Default)]
+pub struct AllNodesUp;
+
+impl Invariant for AllNodesUp {
+    fn name(&self) -> &str {
+        "all_nodes_up"
+    }
+
+    fn check(&mut self, world: &World, _node_kvs: &[IndexMap<String, Value>], _time: SimTime) -> Result<(), String> {
+        match world.nodes.iter().find(|n| n.status != NodeStatus::Up) {
+            Some(node) => Err(format!("node {} is {:?}", node.id, node.status)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Two nodes whose logs end in an entry of the same index and term hold
+/// the same log, Raft's log matching property. Reads the `last_log_index`,
+/// `last_log_term` and `log_hash` KVs, the last a hash of the whole log.
+/// Nodes that publish no log are skipped.
+#[derive(Debug, Default)]
+pub struct LogMatching;
+
+impl Invariant for LogMatching {
+    fn name(&self) -> &str {
+        "log_matching"
+    }
+
+    fn check(&mut self, _world: &World, node_kvs: &[IndexMap<String, Value>], _time: SimTime) -> Result<(), String> {
+        let mut tails: BTreeMap<(u64, u64), (usize, &Value)> = BTreeMap::new();
+        for (node, kvs) in node_kvs.iter().enumerate() {
+            let (Some(index), Some(term), Some(hash)) = (
+                kvs.get("last_log_index").and_then(Value::as_u64),
+                kvs.get("last_log_term").and_then(Value::as_u64),
+                kvs.get("log_hash"),
+            ) else {
+                continue;
+            };
+            let (other, other_hash) = *tails.entry((index, term)).or_insert((node, hash));
+            if other_hash != hash {
+                return Err(format!(
+                    "nodes {} and {} both end at index {} in term {} but hold different logs",
+                    other, node, index, term
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// No node delivers a message before one that causally precedes it. Reads
+/// the `delivered` field of each node's published state: the messages in
+/// delivery order, each with its `origin` and the vector `clock` it was sent
+/// with, which maps each node to how many of its messages the origin had
+/// delivered, the message itself included. Nodes that publish no such
+/// field are skipped; an entry without a numeric origin and clock fails.
+#[derive(Debug, Default)]
+pub struct CausalDelivery;
+
+impl Invariant for CausalDelivery {
+    fn name(&self) -> &str {
+        "causal_delivery"
+    }
+
+    fn check(&mut self, _world: &World, node_kvs: &[IndexMap<String, Value>], _time: SimTime) -> Result<(), String> {
+        for (node, kvs) in node_kvs.iter().enumerate() {
+            let Some(delivered) = kvs.get(STATE_KEY).and_then(|state| state.get("delivered")).and_then(Value::as_array)
+            else {
+                continue;
+            };
+            // How many messages from each origin the node had delivered
+            let mut counts: BTreeMap<u64, u64> = BTreeMap::new();
+            for msg in delivered {
+                let (Some(origin), Some(clock)) =
+                    (msg.get("origin").and_then(Value::as_u64), msg.get("clock").and_then(Value::as_object))
+                else {
+                    return Err(format!("node {} delivered a malformed message {}", node, msg));
+                };
+                for (from, count) in clock {
+                    let (Ok(from), Some(count)) = (from.parse::<u64>(), count.as_u64()) else {
+                        return Err(format!("node {} delivered a message with a malformed clock {}", node, msg));
+                    };
+                    let before = if from == origin { count.saturating_sub(1) } else { count };
+                    let had = counts.get(&from).copied().unwrap_or(0);
+                    if had < before {
+                        return Err(format!(
+                            "node {} delivered message {} from node {} before message {} from node {}, which precedes it",
+                            node,
+                            clock.get(&origin.to_string()).and_then(Value::as_u64).unwrap_or(0),
+                            origin,
+                            had + 1,
+                            from
+                        ));
+                    }
+                }
+                *counts.entry(origin).or_default() += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every read returns the latest write committed anywhere when it ran.
+/// Reads two fields of each node's published state: `committed`, the latest
+/// write of each key the node committed, and `last_read`, the node's latest
+/// read with its `seq`uence number. Writes carry a `version` of `term` and
+/// `index`, ordered by term, then index. A read is judged once, at the
+/// first check that sees it, against the writes committed by then, so it is
+/// exact when invariants are checked after every event. Nodes that publish
+/// no such fields are skipped.
+#[derive(Debug, Default)]
+pub struct LinearizableReads {
+    /// The `seq` of the last read judged on each node.
+    judged: BTreeMap<usize, u64>,
+}
+
+/// The (term, index) of a published write, or (0, 0) for none.
+fn write_version(entry: Option<&Value>) -> (u64, u64) {
+    let field = |name| entry.and_then(|e| e["version"][name].as_u64()).unwrap_or(0);
+    (field("term"), field("index"))
+}
+
+impl Invariant for LinearizableReads {
+    fn name(&self) -> &str {
+        "linearizable_reads"
+    }
+
+    fn check(&mut self, _world: &World, node_kvs: &[IndexMap<String, Value>], _time: SimTime) -> Result<(), String> {
+        let states = || node_kvs.iter().enumerate().filter_map(|(node, kvs)| Some((node, kvs.get(STATE_KEY)?)));
+        let mut latest: BTreeMap<&str, ((u64, u64), usize, &Value)> = BTreeMap::new();
+        for (node, state) in states() {
+            let Some(committed) = state.get("committed").and_then(Value::as_object) else {
+                continue;
+            };
+            for (key, entry) in committed {
+                let version = write_version(Some(entry));
+                if latest.get(key.as_str()).map_or(true, |(held, _, _)| *held < version) {
+                    latest.insert(key, (version, node, entry));
+                }
+            }
+        }
+        for (node, state) in states() {
+            let Some(read) = state.get("last_read").filter(|read| !read.is_null()) else {
+                continue;
+            };
+            let (Some(key), Some(seq)) = (read["key"].as_str(), read["seq"].as_u64()) else {
+                continue;
+            };
+            if self.judged.insert(node, seq) == Some(seq) {
+                continue;
+            }
+            let entry = read.get("entry").filter(|entry| !entry.is_null());
+            let Some(&(version, writer, write)) = latest.get(key) else {
+                continue;
+            };
+            if write_version(entry) < version {
+                return Err(format!(
+                    "node {} read {} = {} (version {:?}) after node {} committed {} (version {:?})",
+                    node,
+                    key,
+                    entry.map_or(Value::Null, |e| e["value"].clone()),
+                    write_version(entry),
+                    writer,
+                    write["value"],
+                    version
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How long the cluster must stay quiet before
+/// `crdt_convergence_after_quiescence` expects the up nodes to agree: a few
+/// merge rounds of `protocols::crdt`, with room for slow links.
+pub const QUIESCENCE_WINDOW: SimTime = 300_000_000;
+
+/// Once faults stop, every up node converges on the same state. Reads the
+/// hash each node publishes under `REPLICA_HASH_KEY`. The cluster is quiet
+/// while no link is partitioned or drops messages and no gray failure is in
+/// effect; it must then have held still, with no node going up or down and
+/// no up node's hash changing, for `QUIESCENCE_WINDOW` before the hashes
+/// must all match. Nodes that publish no hash are skipped.
+#[derive(Debug, Default)]
+pub struct CrdtConvergence {
+    /// The down nodes and the up nodes' hashes when they last changed.
+    last: Option<(Vec<NodeId>, BTreeMap<usize, Value>)>,
+    still_since: SimTime,
+}
+
+impl Invariant for CrdtConvergence {
+    fn name(&self) -> &str {
+        "crdt_convergence_after_quiescence"
+    }
+
+    fn check(&mut self, world: &World, node_kvs: &[IndexMap<String, Value>], time: SimTime) -> Result<(), String> {
+        let down: Vec<NodeId> = world.nodes.iter().filter(|n| n.status != NodeStatus::Up).map(|n| n.id).collect();
+        let hashes: BTreeMap<usize, Value> = node_kvs
+            .iter()
+            .enumerate()
+            .filter(|(node, _)| world.nodes.get(*node).is_some_and(|n| n.status == NodeStatus::Up))
+            .filter_map(|(node, kvs)| Some((node, kvs.get(REPLICA_HASH_KEY)?.clone())))
+            .collect();
+        let faulty = world.net.links.values().any(|link| link.faults.partitioned || link.faults.drop.0 > 0.0)
+            || world.nodes.iter().any(|n| n.gray_failure(time).is_some());
+        let now = (down, hashes);
+        if faulty || self.last.as_ref() != Some(&now) {
+            self.last = Some(now);
+            self.still_since = time;
+            return Ok(());
+        }
+        let hashes = &now.1;
+        if time.saturating_sub(self.still_since) < QUIESCENCE_WINDOW {
+            return Ok(());
+        }
+        let mut distinct: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (node, hash) in hashes {
+            distinct.entry(hash.to_string()).or_default().push(*node);
+        }
+        if distinct.len() > 1 {
+            let groups: Vec<String> = distinct.iter().map(|(hash, nodes)| format!("{:?} hold {}", nodes, hash)).collect();
+            return Err(format!(
+                "nodes still disagree {:.3} s after the cluster went quiet: {}",
+                (time - self.still_since) as f64 / 1e9,
+                groups.join(", ")
+            ));
+        }
+        Ok(())
+    }
+}
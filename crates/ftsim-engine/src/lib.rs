@@ -4,21 +4,37 @@
 //! world state management, network and storage models, fault injection logic,
 //! and the telemetry pipeline.
 
-// NOTE: Using unsafe code for performance-critical borrow checker workarounds
-// All unsafe usage is carefully documented and limited to specific patterns
+#![forbid(unsafe_code)]
 
 // Public modules, re-exporting key types for users of the engine.
+pub mod cache;
+pub mod conditions;
 pub mod control;
+pub mod convergence;
+pub mod crash_context;
+pub mod effective_config;
 pub mod events;
+pub mod expr;
+pub mod flow;
+pub mod histogram;
 pub mod ids;
+pub mod interventions;
+pub mod invariants;
 pub mod net;
 pub mod node;
 pub mod prelude;
+pub mod report;
 pub mod rng;
 pub mod scenario;
+pub mod segments;
 pub mod sim;
+pub mod slo;
+pub mod state_hash;
 pub mod store;
 pub mod telemetry;
+pub mod testkit;
+pub mod timeline;
+pub mod usage;
 pub mod world;
 
 // Internal-only modules
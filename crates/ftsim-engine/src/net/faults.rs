@@ -3,37 +3,299 @@
 //! Provides deterministic sampling functions for the fault models.
 //! All functions take a `RngDiscipline` to ensure that every random draw
 //! is recorded for reproducibility.
+//!
+//! Replays are compared across machines, so a draw must map to the same
+//! delay or outcome on every platform. Uniform delays use `rand`'s integer
+//! range sampling, pinned by the lockfile, and trials go through
+//! `rng::bernoulli`. `Normal` and `Pareto` draws need a logarithm and an
+//! exponential, whose `std` versions call the platform's libm and may round
+//! differently from one machine to the next. They are computed here from
+//! series in IEEE 754 basic operations and `sqrt`, which are correctly
+//! rounded everywhere. The committed vectors in
+//! `tests/determinism_vectors.json` turn any divergence into a test failure.
 
-use crate::{prelude::*, rng::RngDiscipline};
-use rand::Rng;
-
-/// Samples a delay value from a `DelaySpec` distribution.
-pub fn sample_delay(mut rng: RngDiscipline, spec: &ftsim_types::scenario::DelaySpec) -> SimTime {
-    match spec {
-        ftsim_types::scenario::DelaySpec::Const(d) => (*d).into(),
-        ftsim_types::scenario::DelaySpec::Uniform { lo, hi } => {
-            if lo >= hi {
-                (*lo).into()
-            } else {
-                rng.gen_range(*lo..=*hi).into()
-            }
-        }
-        ftsim_types::scenario::DelaySpec::Normal { mu, sigma } => {
-            // Simple approximation for normal distribution using uniform
-            // In a real implementation, you'd use proper normal distribution sampling
-            let base = (*mu as u64).max(1);
-            let variance = (*sigma as u64).max(1);
-            rng.gen_range(base.saturating_sub(variance)..=base + variance).into()
+use crate::{
+    prelude::*,
+    rng::{bernoulli, RngDiscipline},
+};
+use rand::{Rng, RngCore};
+
+/// A delay drawn from a `DelaySpec`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampledDelay {
+    pub delay: SimTime,
+    /// Set when the draw fell outside the spec's bounds and was clamped
+    /// into them.
+    pub clamped: bool,
+}
+
+/// Samples a delay value from a `DelaySpec` distribution, clamped into
+/// `DelaySpec::bounds`.
+///
+/// The spec must have passed `DelaySpec::validate`, as every spec in a
+/// loaded scenario or a connected link has. An inverted `Uniform` range
+/// panics in `rand`; bad `Normal` or `Pareto` parameters draw meaningless,
+/// though bounded, delays.
+pub fn sample_delay(mut rng: RngDiscipline, spec: &ftsim_types::scenario::DelaySpec) -> SampledDelay {
+    let raw = match *spec {
+        ftsim_types::scenario::DelaySpec::Const(d) => d,
+        ftsim_types::scenario::DelaySpec::Uniform { lo, hi } => rng.gen_range(lo..=hi),
+        ftsim_types::scenario::DelaySpec::Normal { mu, sigma, .. } => {
+            // Marsaglia's polar method, keeping one of the pair it draws
+            let (u, s) = loop {
+                let u = 2.0 * unit(&mut rng) - 1.0;
+                let v = 2.0 * unit(&mut rng) - 1.0;
+                let s = u * u + v * v;
+                if s > 0.0 && s < 1.0 {
+                    break (u, s);
+                }
+            };
+            to_delay(mu + sigma * u * (-2.0 * ln(s) / s).sqrt())
         }
-        ftsim_types::scenario::DelaySpec::Pareto { scale, shape: _ } => {
-            // Simple approximation for Pareto distribution
-            // In a real implementation, you'd use proper Pareto distribution sampling
-            (*scale as u64).max(1).into()
+        ftsim_types::scenario::DelaySpec::Pareto { scale, shape, .. } => {
+            // Inverse transform: U^(-1/shape) is at least 1 for U in (0, 1]
+            let u = 1.0 - unit(&mut rng);
+            to_delay(scale * exp(-ln(u) / shape))
         }
+    };
+    let (min, max) = spec.bounds();
+    let delay = raw.clamp(min, max);
+    SampledDelay {
+        delay: delay.into(),
+        clamped: delay != raw,
+    }
+}
+
+/// Draws a float uniformly from `[0, 1)` with 53 bits of precision.
+fn unit(rng: &mut RngDiscipline) -> f64 {
+    (rng.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+}
+
+/// Rounds a drawn delay to nanoseconds. Negative draws become 0 and
+/// huge ones `u64::MAX`, both left for the bounds to clamp.
+fn to_delay(value: f64) -> u64 {
+    value.round() as u64
+}
+
+/// The natural logarithm of a positive, normal `x`.
+fn ln(x: f64) -> f64 {
+    // x = m * 2^e with m in [sqrt(1/2), sqrt(2)]
+    let bits = x.to_bits();
+    let mut e = ((bits >> 52) & 0x7ff) as i64 - 1023;
+    let mut m = f64::from_bits((bits & ((1 << 52) - 1)) | (1023 << 52));
+    if m > std::f64::consts::SQRT_2 {
+        m /= 2.0;
+        e += 1;
+    }
+    // ln(m) = 2 * atanh(s), with |s| below 0.172
+    let s = (m - 1.0) / (m + 1.0);
+    let s2 = s * s;
+    let mut term = s;
+    let mut sum = 0.0;
+    for k in 0..12 {
+        sum += term / (2 * k + 1) as f64;
+        term *= s2;
     }
+    e as f64 * std::f64::consts::LN_2 + 2.0 * sum
+}
+
+/// `e` raised to `x`, saturating to 0 and infinity.
+fn exp(x: f64) -> f64 {
+    if x.is_nan() {
+        return x;
+    }
+    if x > 710.0 {
+        return f64::INFINITY;
+    }
+    if x < -746.0 {
+        return 0.0;
+    }
+    // e^x = 2^k * e^r with |r| at most ln(2) / 2. ln(2) is split so that
+    // k times its high part is exact.
+    const LN2_HI: f64 = 6.931_471_803_691_238e-1;
+    const LN2_LO: f64 = 1.908_214_929_270_587_7e-10;
+    let k = (x / std::f64::consts::LN_2).round();
+    let r = (x - k * LN2_HI) - k * LN2_LO;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for n in 1..20 {
+        term *= r / n as f64;
+        sum += term;
+    }
+    // Scaled in two steps so that neither factor leaves the normal range
+    let k = k as i64;
+    let half = k / 2;
+    sum * pow2(half) * pow2(k - half)
+}
+
+/// 2 raised to `k`, for `k` within the exponent range of a normal `f64`.
+fn pow2(k: i64) -> f64 {
+    f64::from_bits(((k.clamp(-1022, 1023) + 1023) as u64) << 52)
 }
 
 /// Performs a Bernoulli trial (coin flip) with probability `p`.
 pub fn trial(mut rng: RngDiscipline, spec: &Bernoulli) -> bool {
-    rng.gen_bool(spec.0)
+    bernoulli(&mut rng, spec.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::{Recorder, RngStreams};
+    use ftsim_types::scenario::{DelaySpec, DEFAULT_MAX_DELAY};
+
+    /// Draws `n` delays from `spec`.
+    fn draw(spec: &DelaySpec, seed: u64, n: usize) -> Vec<SampledDelay> {
+        let mut streams = RngStreams::new(seed);
+        let mut recorder = Recorder::new(seed);
+        let site = recorder.site("net.delay.base", None);
+        (0..n)
+            .map(|_| sample_delay(RngDiscipline::new(&mut streams, &mut recorder, site), spec))
+            .collect()
+    }
+
+    #[test]
+    fn test_normal_draws_are_clamped() {
+        let spec = DelaySpec::Normal { mu: 1_000.0, sigma: 900.0, min: Some(500), max: Some(1_500) };
+        let draws = draw(&spec, 1, 200);
+        assert!(draws.iter().all(|d| (500..=1_500).contains(&d.delay)));
+        assert!(draws.iter().any(|d| d.clamped && d.delay == 500));
+        assert!(draws.iter().any(|d| d.clamped && d.delay == 1_500));
+        assert!(draws.iter().any(|d| !d.clamped));
+    }
+
+    #[test]
+    fn test_pareto_draws_default_to_a_one_minute_cap() {
+        let spec = DelaySpec::Pareto { scale: 1e18, shape: 2.0, min: None, max: None };
+        let draws = draw(&spec, 1, 1);
+        assert_eq!(draws[0], SampledDelay { delay: DEFAULT_MAX_DELAY as SimTime, clamped: true });
+        let spec = DelaySpec::Pareto { scale: 10.0, shape: 2.0, min: Some(100), max: None };
+        assert_eq!(draw(&spec, 1, 1)[0], SampledDelay { delay: 100, clamped: true });
+    }
+
+    #[test]
+    fn test_const_and_uniform_draws_are_never_clamped() {
+        // A deliberate constant delay is honored even past the default cap
+        let spec = DelaySpec::Const(DEFAULT_MAX_DELAY * 2);
+        assert_eq!(draw(&spec, 1, 1)[0], SampledDelay { delay: (DEFAULT_MAX_DELAY * 2) as SimTime, clamped: false });
+        let spec = DelaySpec::Uniform { lo: 10, hi: 20 };
+        assert!(draw(&spec, 1, 100).iter().all(|d| !d.clamped && (10..=20).contains(&d.delay)));
+    }
+
+    #[test]
+    fn test_invalid_delay_specs_are_rejected() {
+        let invalid = [
+            (DelaySpec::Uniform { lo: 20, hi: 10 }, "lo 20 above hi 10"),
+            (DelaySpec::Normal { mu: -1.0, sigma: 1.0, min: None, max: None }, "mu is -1"),
+            (DelaySpec::Normal { mu: 1.0, sigma: f64::NAN, min: None, max: None }, "sigma is NaN"),
+            (DelaySpec::Pareto { scale: 1.0, shape: -2.0, min: None, max: None }, "shape is -2"),
+            (DelaySpec::Pareto { scale: 1.0, shape: 0.0, min: None, max: None }, "shape 0"),
+            (DelaySpec::Pareto { scale: 1.0, shape: 2.0, min: Some(10), max: Some(5) }, "min 10 above max 5"),
+            (DelaySpec::Normal { mu: 1.0, sigma: 1.0, min: Some(DEFAULT_MAX_DELAY + 1), max: None }, "above max"),
+        ];
+        for (spec, expected) in invalid {
+            let err = spec.validate().unwrap_err();
+            assert!(err.contains(expected), "{:?}: {}", spec, err);
+        }
+        assert!(DelaySpec::Uniform { lo: 10, hi: 10 }.validate().is_ok());
+        assert!(DelaySpec::Normal { mu: 0.0, sigma: 0.0, min: None, max: None }.validate().is_ok());
+
+        let scenario = Scenario::builder("bad delay", 2, ProtoTag(1))
+            .at(0, Action::LinkDelay { link: 0, dist: DelaySpec::Uniform { lo: 2, hi: 1 } })
+            .build();
+        assert!(scenario.unwrap_err().starts_with("Directive 0: Uniform delay"));
+    }
+
+    /// An arbitrary valid spec: any variant, parameters across many orders
+    /// of magnitude and random bounds.
+    fn arbitrary_spec(rng: &mut impl Rng) -> DelaySpec {
+        let magnitude = |rng: &mut dyn rand::RngCore| {
+            let exp = rng.gen_range(0..=18);
+            rng.gen_range(0..=10u64.pow(exp))
+        };
+        let bound = |rng: &mut dyn rand::RngCore| rng.gen_bool(0.5).then(|| magnitude(rng));
+        let spec = match rng.gen_range(0..4) {
+            0 => DelaySpec::Const(magnitude(rng)),
+            1 => {
+                let (a, b) = (magnitude(rng), magnitude(rng));
+                DelaySpec::Uniform { lo: a.min(b), hi: a.max(b) }
+            }
+            2 => DelaySpec::Normal {
+                mu: magnitude(rng) as f64,
+                sigma: magnitude(rng) as f64 * rng.gen_range(0.0..4.0),
+                min: bound(rng),
+                max: bound(rng),
+            },
+            _ => DelaySpec::Pareto {
+                scale: magnitude(rng) as f64,
+                shape: rng.gen_range(0.0..4.0),
+                min: bound(rng),
+                max: bound(rng),
+            },
+        };
+        match spec {
+            // Swap bounds drawn the wrong way round rather than drop the case
+            DelaySpec::Normal { mu, sigma, min: Some(lo), max: Some(hi) } if lo > hi => {
+                DelaySpec::Normal { mu, sigma, min: Some(hi), max: Some(lo) }
+            }
+            DelaySpec::Pareto { scale, shape, min: Some(lo), max: Some(hi) } if lo > hi => {
+                DelaySpec::Pareto { scale, shape, min: Some(hi), max: Some(lo) }
+            }
+            spec => spec,
+        }
+    }
+
+    #[test]
+    fn test_sampled_delays_stay_within_bounds() {
+        use rand::SeedableRng;
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0xde1a);
+        let mut checked = 0;
+        for case in 0..2_000 {
+            let spec = arbitrary_spec(&mut rng);
+            // Bounds above the default cap with no explicit max are invalid
+            if spec.validate().is_err() {
+                continue;
+            }
+            checked += 1;
+            let (min, max) = spec.bounds();
+            for d in draw(&spec, case, 10) {
+                assert!((min as SimTime..=max as SimTime).contains(&d.delay), "{:?} drew {}", spec, d.delay);
+            }
+        }
+        assert!(checked > 1_000, "only {} valid specs", checked);
+    }
+
+    #[test]
+    fn test_ln_and_exp_match_std() {
+        for x in [1e-300, 2.0f64.powi(-53), 1e-9, 0.3, 0.5, 0.999_999, 1.0, 1.5, 2.0, 1e6, 1.7e308] {
+            assert!((ln(x) - x.ln()).abs() <= 1e-15 * x.ln().abs().max(1.0), "ln {}", x);
+        }
+        for x in [-700.0, -20.0, -1.0, -1e-12, 0.0, 0.5, 1.0, 41.4, 700.0] {
+            assert!((exp(x) - x.exp()).abs() <= 1e-14 * x.exp(), "exp {}", x);
+        }
+        assert_eq!((exp(-800.0), exp(800.0)), (0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn test_normal_draws_follow_the_distribution() {
+        let spec = DelaySpec::Normal { mu: 1e6, sigma: 1e5, min: None, max: None };
+        let draws: Vec<f64> = draw(&spec, 7, 20_000).iter().map(|d| d.delay as f64).collect();
+        let mean = draws.iter().sum::<f64>() / draws.len() as f64;
+        let sd = (draws.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / draws.len() as f64).sqrt();
+        assert!((mean - 1e6).abs() < 3e3, "mean {}", mean);
+        assert!((sd - 1e5).abs() < 3e3, "sd {}", sd);
+        // About 95% fall within two standard deviations
+        let within = draws.iter().filter(|d| (**d - 1e6).abs() <= 2e5).count();
+        assert!((18_700..=19_300).contains(&within), "{} within 2 sd", within);
+    }
+
+    #[test]
+    fn test_pareto_draws_follow_the_distribution() {
+        let spec = DelaySpec::Pareto { scale: 1_000.0, shape: 2.0, min: None, max: None };
+        let draws = draw(&spec, 7, 20_000);
+        assert!(draws.iter().all(|d| d.delay >= 1_000 && !d.clamped));
+        // P(X > 10 * scale) = 10^-shape
+        let tail = draws.iter().filter(|d| d.delay > 10_000).count();
+        assert!((140..=260).contains(&tail), "{} in the tail", tail);
+    }
 }
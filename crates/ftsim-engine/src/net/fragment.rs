@@ -0,0 +1,233 @@
+//! # ftsim-engine::net::fragment
+//!
+//! MTU fragmentation and reassembly. Oversized payloads are split into
+//! fragments that each travel as their own envelope; the destination holds
+//! them in a bounded `ReassemblyBuffer` until the message is complete.
+//!
+//! Semantics: a message is dispatched to the protocol only once every one of
+//! its fragments has arrived. Partial messages are discarded when they exceed
+//! the reassembly timeout or when the buffer is full (oldest first), so losing
+//! any single fragment loses the whole message. The buffer is a
+//! `BoundedCache`, which enforces both limits.
+//!
+//! Fragments of a message already reassembled, such as duplicates that
+//! arrive late, are dropped rather than starting a partial that could only
+//! time out. The buffer remembers as many reassembled messages as it may
+//! hold partial ones, for as long as the reassembly timeout.
+
+use crate::{cache::BoundedCache, prelude::*};
+use bytes::{Bytes, BytesMut};
+
+/// Splits an envelope into fragments of at most `mtu` payload bytes.
+pub fn fragment(env: &Envelope, mtu: usize) -> Vec<Envelope> {
+    let mtu = mtu.max(1);
+    let count = env.payload.len().div_ceil(mtu) as u32;
+    (0..count)
+        .map(|index| {
+            let start = index as usize * mtu;
+            let end = (start + mtu).min(env.payload.len());
+            Envelope {
+                payload: env.payload.slice(start..end),
+                fragment: Some(FragmentInfo { index, count }),
+                ..env.clone()
+            }
+        })
+        .collect()
+}
+
+/// A message whose fragments are still arriving.
+#[derive(Clone)]
+struct Partial {
+    // The receiving node, which discarding the message is counted against
+    dst: NodeId,
+    parts: Vec<Option<Bytes>>,
+    received: u32,
+}
+
+/// Why a partial message was discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReassemblyFailure {
+    Timeout,
+    Overflow,
+}
+
+impl ReassemblyFailure {
+    fn as_str(self) -> &'static str {
+        match self {
+            ReassemblyFailure::Timeout => "timeout",
+            ReassemblyFailure::Overflow => "overflow",
+        }
+    }
+}
+
+/// Per-node buffer of partially received messages, keyed by source and message ID.
+#[derive(Clone)]
+pub struct ReassemblyBuffer {
+    pending: BoundedCache<(NodeId, MsgId), Partial>,
+    reassembled: BoundedCache<(NodeId, MsgId), ()>,
+    failures: u64,
+}
+
+impl ReassemblyBuffer {
+    pub fn new(timeout: SimTime, max_pending: usize) -> Self {
+        let spec = CacheSpec { max_entries: max_pending, max_age: Some(timeout) };
+        Self {
+            pending: BoundedCache::new("reassembly", spec),
+            reassembled: BoundedCache::new("reassembled", spec),
+            failures: 0,
+        }
+    }
+
+    /// Returns how long a partial message may wait for its fragments.
+    pub fn timeout(&self) -> SimTime {
+        self.pending.spec().max_age.unwrap_or(SimTime::MAX)
+    }
+
+    /// Returns how many partial messages may be buffered at once.
+    pub fn max_pending(&self) -> usize {
+        self.pending.spec().max_entries
+    }
+
+    /// Accepts one fragment. Returns the reassembled envelope once all of its
+    /// fragments have arrived. Duplicate fragments are ignored, including
+    /// those of a message reassembled recently.
+    pub fn insert(&mut self, now: SimTime, env: Envelope) -> Option<Envelope> {
+        let info = env.fragment?;
+        self.expire(now);
+
+        let key = (env.src, env.msg_id);
+        if self.reassembled.contains(&key) {
+            return None;
+        }
+        if !self.pending.contains(&key) {
+            let partial = Partial {
+                dst: env.dst,
+                parts: vec![None; info.count as usize],
+                received: 0,
+            };
+            if let Some((_, evicted)) = self.pending.insert(key, partial, now) {
+                self.record_failure(evicted.dst, ReassemblyFailure::Overflow);
+            }
+        }
+
+        let partial = self.pending.get_mut(&key).unwrap();
+        let slot = partial.parts.get_mut(info.index as usize)?;
+        if slot.is_none() {
+            *slot = Some(env.payload.clone());
+            partial.received += 1;
+        }
+        if partial.received < info.count {
+            return None;
+        }
+
+        let partial = self.pending.remove(&key).unwrap();
+        self.reassembled.insert(key, (), now);
+        let mut payload = BytesMut::new();
+        for part in partial.parts.into_iter().flatten() {
+            payload.extend_from_slice(&part);
+        }
+        Some(Envelope {
+            payload: payload.freeze(),
+            fragment: None,
+            ..env
+        })
+    }
+
+    /// Discards partial messages older than the reassembly timeout.
+    pub fn expire(&mut self, now: SimTime) {
+        self.reassembled.expire(now);
+        for (_, partial) in self.pending.expire(now) {
+            self.record_failure(partial.dst, ReassemblyFailure::Timeout);
+        }
+    }
+
+    /// Drops all partial messages, e.g. when the node crashes.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.reassembled.clear();
+    }
+
+    /// Returns the number of partially reassembled messages held.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the number of partial messages discarded so far.
+    pub fn failures(&self) -> u64 {
+        self.failures
+    }
+
+    fn record_failure(&mut self, node_id: NodeId, reason: ReassemblyFailure) {
+        self.failures += 1;
+        tracing::debug!(node_id, reason = reason.as_str(), "Discarding incomplete reassembly");
+        ::metrics::counter!(
+            ftsim_types::metrics::MET_NET_REASSEMBLY_FAILED,
+            ftsim_types::metrics::LBL_REASON => reason.as_str()
+        )
+        .increment(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(msg_id: MsgId, len: usize) -> Envelope {
+        Envelope {
+            src: 0,
+            dst: 1,
+            proto_tag: ProtoTag(1),
+            payload: Bytes::from((0..len).map(|i| i as u8).collect::<Vec<_>>()),
+            msg_id,
+            create_time: 0,
+            sent_at_local: 0,
+            trace_id: 0,
+            fragment: None,
+        }
+    }
+
+    #[test]
+    fn test_fragments_reassemble_in_any_order() {
+        let original = env(MsgId(1), 2500);
+        let mut frags = fragment(&original, 1000);
+        assert_eq!(frags.len(), 3);
+        frags.reverse();
+
+        let mut buf = ReassemblyBuffer::new(1_000, 8);
+        assert!(buf.insert(0, frags[0].clone()).is_none());
+        assert!(buf.insert(0, frags[0].clone()).is_none());
+        assert!(buf.insert(0, frags[1].clone()).is_none());
+        let whole = buf.insert(0, frags[2].clone()).unwrap();
+        assert_eq!(whole.payload, original.payload);
+        assert_eq!(whole.fragment, None);
+        assert_eq!(buf.pending(), 0);
+    }
+
+    #[test]
+    fn test_a_fragment_arriving_after_its_message_is_dropped() {
+        let frags = fragment(&env(MsgId(1), 30), 10);
+        let mut buf = ReassemblyBuffer::new(1_000, 8);
+        for (i, frag) in frags.iter().enumerate() {
+            assert_eq!(buf.insert(i as SimTime, frag.clone()).is_some(), i == 2);
+        }
+        // A duplicate of the first fragment, after the message was whole
+        assert!(buf.insert(500, frags[0].clone()).is_none());
+        assert_eq!(buf.pending(), 0);
+        buf.expire(10_000);
+        assert_eq!(buf.failures(), 0);
+    }
+
+    #[test]
+    fn test_incomplete_reassemblies_do_not_leak() {
+        let mut buf = ReassemblyBuffer::new(1_000, 4);
+        for msg_id in 0..100 {
+            // Only the first of three fragments ever arrives.
+            let first = fragment(&env(MsgId(msg_id), 30), 10).remove(0);
+            buf.insert(msg_id as SimTime * 10, first);
+            assert!(buf.pending() <= 4);
+        }
+        buf.expire(10_000);
+        assert_eq!(buf.pending(), 0);
+        assert_eq!(buf.failures(), 100);
+    }
+}
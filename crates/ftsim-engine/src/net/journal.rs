@@ -0,0 +1,348 @@
+//! # ftsim-engine::net::journal
+//!
+//! An optional record of every protocol message sent during a run, annotated
+//! with what the sender's world looked like at send time: its status, whether
+//! it was cut off by a partition, whether it was byzantine, and which
+//! incarnation of the node sent it. Deliveries are appended to the same
+//! record, so a post-run pass can point at messages that only arrived because
+//! they were already in flight when a fault began, or that a crashed node
+//! still managed to emit.
+//!
+//! At high message rates the journal can keep a deterministic sample of
+//! lifecycles instead. A message is sampled by hashing its id with the seed,
+//! so replays sample the same messages, and the categories named in
+//! `JournalSampling::always_include` are journaled in full regardless.
+
+use crate::node::runtime::NodeStatus;
+use crate::prelude::*;
+#[cfg(feature = "trace-export")]
+use crate::segments::{RotationPolicy, SegmentWriter};
+use bytes::Bytes;
+use fxhash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The sender's view of itself and the network when a message was sent.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SenderView {
+    pub status: NodeStatus,
+    /// Whether any link touching the sender was partitioned.
+    pub any_link_partitioned: bool,
+    /// Whether the link to this message's destination was partitioned.
+    pub partitioned_from_dst: bool,
+    pub byzantine: bool,
+    /// How many times the sender had been restarted.
+    pub incarnation: u64,
+    /// Whether the sender reported the leader (or primary) role.
+    #[serde(default)]
+    pub leader: bool,
+}
+
+/// A message arriving at an up destination.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delivery {
+    pub time: SimTime,
+    /// Whether the link was partitioned when the message arrived.
+    pub across_partition: bool,
+    /// Whether the receiving protocol rejected the message.
+    #[serde(default)]
+    pub decode_failed: bool,
+}
+
+/// One sent message and everything that happened to it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MessageRecord {
+    pub msg_id: MsgId,
+    pub src: NodeId,
+    pub dst: NodeId,
+    pub proto_tag: ProtoTag,
+    pub sent_at: SimTime,
+    /// The sending event and the sender's view. Both are `None` when the
+    /// message was skipped by sampling at send time and only journaled
+    /// because a delivery fell into an always-included category.
+    pub event_id: Option<EventId>,
+    pub view: Option<SenderView>,
+    /// Whether the message was picked by the sampling rate, as opposed to
+    /// being kept for its category. Scale estimates by sampled records only.
+    #[serde(default = "default_sampled")]
+    pub sampled: bool,
+    pub deliveries: Vec<Delivery>,
+    /// The payload size.
+    #[serde(default)]
+    pub bytes: u64,
+    /// The message variant, as the destination's protocol classifies it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    /// The message payload, kept only by journals created `with_payloads`.
+    /// Never written to JSONL.
+    #[serde(skip)]
+    pub payload: Option<Bytes>,
+}
+
+fn default_sampled() -> bool {
+    true
+}
+
+impl MessageRecord {
+    /// Returns the categories this message's send and deliveries fall into.
+    pub fn categories(&self) -> Vec<JournalCategory> {
+        let mut categories = Vec::new();
+        let faulty_send = self
+            .view
+            .is_some_and(|v| v.status != NodeStatus::Up || v.any_link_partitioned || v.byzantine);
+        if faulty_send || self.deliveries.iter().any(|d| d.across_partition) {
+            categories.push(JournalCategory::Faults);
+        }
+        if self.deliveries.iter().any(|d| d.decode_failed) {
+            categories.push(JournalCategory::Errors);
+        }
+        if self.view.is_some_and(|v| v.leader) {
+            categories.push(JournalCategory::Leadership);
+        }
+        categories
+    }
+}
+
+/// Why a message is worth a second look.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuspectReason {
+    /// The sender was not up when it sent the message.
+    SentWhileDown,
+    /// The link to the destination was partitioned at send time.
+    SentWhilePartitioned,
+    /// The message was in flight when the partition began and arrived anyway.
+    DeliveredAcrossPartition,
+}
+
+impl fmt::Display for SuspectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::SentWhileDown => "sent while down",
+            Self::SentWhilePartitioned => "sent while partitioned",
+            Self::DeliveredAcrossPartition => "delivered across a partition",
+        })
+    }
+}
+
+/// A delivered message whose delivery contradicts the sender's view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suspect<'a> {
+    pub record: &'a MessageRecord,
+    pub reasons: Vec<SuspectReason>,
+}
+
+/// The first line of a sampled journal file.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    sampling: JournalSampling,
+}
+
+/// All journaled messages of a run, in send order.
+#[derive(Debug, Default, Clone)]
+pub struct MessageJournal {
+    records: Vec<MessageRecord>,
+    /// Index into `records` by message id; ids are not dense once fault
+    /// injection and fragmentation draw from the same generator.
+    by_id: FxHashMap<MsgId, usize>,
+    /// The sampling configuration and the seed it hashes with; `None`
+    /// journals every message.
+    sampling: Option<(JournalSampling, u64)>,
+    /// Whether records keep their payloads.
+    keep_payloads: bool,
+}
+
+impl MessageJournal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a journal that keeps a sample of messages chosen by `seed`.
+    pub fn sampled(sampling: JournalSampling, seed: u64) -> Self {
+        Self {
+            sampling: Some((sampling, seed)),
+            ..Self::default()
+        }
+    }
+
+    /// Makes the journal keep each message's payload, for tests that decode
+    /// the recorded traffic.
+    pub fn with_payloads(mut self) -> Self {
+        self.keep_payloads = true;
+        self
+    }
+
+    /// Returns whether records keep their payloads.
+    pub fn keeps_payloads(&self) -> bool {
+        self.keep_payloads
+    }
+
+    pub fn records(&self) -> &[MessageRecord] {
+        &self.records
+    }
+
+    /// Returns the sampling configuration, if the journal is sampled.
+    pub fn sampling(&self) -> Option<&JournalSampling> {
+        self.sampling.as_ref().map(|(sampling, _)| sampling)
+    }
+
+    /// Returns whether the sampling rate picks this message. The choice
+    /// depends only on the id and the seed.
+    pub fn is_sampled(&self, msg_id: MsgId) -> bool {
+        let Some((sampling, seed)) = &self.sampling else {
+            return true;
+        };
+        let h = mix(seed ^ msg_id.0.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        ((h >> 11) as f64 / (1u64 << 53) as f64) < sampling.rate
+    }
+
+    fn always_includes(&self, record: &MessageRecord) -> bool {
+        self.sampling.as_ref().is_some_and(|(sampling, _)| {
+            record.categories().iter().any(|c| sampling.always_include.contains(c))
+        })
+    }
+
+    /// Journals a send if it is sampled or falls into an always-included
+    /// category.
+    pub fn record_send(&mut self, mut record: MessageRecord) {
+        if !self.keep_payloads {
+            record.payload = None;
+        }
+        record.sampled = self.is_sampled(record.msg_id);
+        if record.sampled || self.always_includes(&record) {
+            self.push(record);
+        }
+    }
+
+    /// Keeps only the records `keep` returns true for.
+    pub fn retain(&mut self, keep: impl FnMut(&MessageRecord) -> bool) {
+        self.records.retain(keep);
+        self.by_id = self.records.iter().enumerate().map(|(i, r)| (r.msg_id, i)).collect();
+    }
+
+    /// Removes and returns, in send order, the records of the messages not
+    /// in `in_flight`, which no delivery can be added to anymore.
+    pub fn take_settled(&mut self, in_flight: &FxHashSet<MsgId>) -> Vec<MessageRecord> {
+        let records = std::mem::take(&mut self.records);
+        let (settled, open) = records.into_iter().partition(|r| !in_flight.contains(&r.msg_id));
+        self.records = open;
+        self.by_id = self.records.iter().enumerate().map(|(i, r)| (r.msg_id, i)).collect();
+        settled
+    }
+
+    fn push(&mut self, record: MessageRecord) {
+        self.by_id.insert(record.msg_id, self.records.len());
+        self.records.push(record);
+    }
+
+    /// Appends a delivery to the message it belongs to. A delivery of a
+    /// message skipped at send time starts a record of its own if it falls
+    /// into an always-included category. Deliveries of messages that were
+    /// never sent by a protocol, like injected faults, are ignored.
+    pub fn record_delivery(&mut self, env: &Envelope, delivery: Delivery) {
+        if let Some(&i) = self.by_id.get(&env.msg_id) {
+            self.records[i].deliveries.push(delivery);
+            return;
+        }
+        if env.src == u32::MAX || self.sampling.is_none() {
+            return;
+        }
+        let record = MessageRecord {
+            msg_id: env.msg_id,
+            src: env.src,
+            dst: env.dst,
+            proto_tag: env.proto_tag,
+            sent_at: env.create_time,
+            event_id: None,
+            view: None,
+            sampled: false,
+            deliveries: vec![delivery],
+            bytes: env.payload.len() as u64,
+            variant: None,
+            payload: self.keep_payloads.then(|| env.payload.clone()),
+        };
+        if self.always_includes(&record) {
+            self.push(record);
+        }
+    }
+
+    /// Returns every delivered message that a partition or crash should have
+    /// stopped. `Net::send` drops messages on partitioned links, so in this
+    /// engine a partitioned send only shows up if a protocol sends from
+    /// inside a fault hook; in-flight crossings are the common case.
+    pub fn suspects(&self) -> Vec<Suspect<'_>> {
+        self.records
+            .iter()
+            .filter(|r| !r.deliveries.is_empty())
+            .filter_map(|record| {
+                let mut reasons = Vec::new();
+                if record.view.is_some_and(|v| v.status != NodeStatus::Up) {
+                    reasons.push(SuspectReason::SentWhileDown);
+                }
+                if record.view.is_some_and(|v| v.partitioned_from_dst) {
+                    reasons.push(SuspectReason::SentWhilePartitioned);
+                }
+                if record.deliveries.iter().any(|d| d.across_partition) {
+                    reasons.push(SuspectReason::DeliveredAcrossPartition);
+                }
+                (!reasons.is_empty()).then_some(Suspect { record, reasons })
+            })
+            .collect()
+    }
+
+    /// Writes one JSON object per line, led by the sampling configuration
+    /// when the journal is sampled.
+    #[cfg(feature = "trace-export")]
+    pub fn write_jsonl<W: std::io::Write>(&self, mut out: W) -> std::io::Result<()> {
+        if let Some(sampling) = self.sampling() {
+            serde_json::to_writer(&mut out, &Header { sampling: sampling.clone() })?;
+            out.write_all(b"\n")?;
+        }
+        for record in &self.records {
+            serde_json::to_writer(&mut out, record)?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()
+    }
+
+    /// Starts size-rotated segments at `path` for the records, each led by
+    /// the sampling configuration when the journal is sampled. Records are
+    /// placed by their send time.
+    #[cfg(feature = "trace-export")]
+    pub fn create_segments(&self, path: &std::path::Path, policy: RotationPolicy) -> std::io::Result<SegmentWriter> {
+        let out = SegmentWriter::create(path, policy)?;
+        match self.sampling() {
+            Some(sampling) => out.with_header(&Header { sampling: sampling.clone() }),
+            None => Ok(out),
+        }
+    }
+
+    /// Reads a journal written by `write_jsonl`. The seed of a sampled
+    /// journal is not stored, so `is_sampled` is only meaningful in the run
+    /// that wrote it.
+    #[cfg(feature = "trace-export")]
+    pub fn read_jsonl<R: std::io::BufRead>(input: R) -> std::io::Result<Self> {
+        let mut journal = Self::new();
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // Records hold u128 times, which serde cannot buffer for an
+            // untagged enum, so the header is recognized by its key.
+            if line.starts_with("{\"sampling\"") {
+                let header: Header = serde_json::from_str(&line)?;
+                journal.sampling = Some((header.sampling, 0));
+            } else {
+                journal.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(journal)
+    }
+}
+
+/// The splitmix64 finalizer.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -2,7 +2,10 @@
 //!
 //! Defines the data structures for network links, including their fault models.
 
-use crate::prelude::*;
+use crate::{prelude::*, state_hash::StateHasher};
+use ftsim_types::scenario::DelaySpec;
+use serde::Serialize;
+use std::hash::Hasher;
 
 /// Represents a directed link in the network graph.
 #[derive(Clone, Debug)]
@@ -14,7 +17,7 @@ pub struct NetLink {
 }
 
 /// A collection of fault models that can be applied to a network link.
-#[derive(Clone, Debug)]
+#[derive(Serialize, Clone, Debug)]
 pub struct LinkFaultModel {
     pub drop: Bernoulli,
     pub duplicate: Bernoulli,
@@ -25,6 +28,7 @@ pub struct LinkFaultModel {
     pub partitioned: bool,
     pub bandwidth_bytes_per_ms: Option<u64>,
     pub mtu_bytes: Option<usize>,
+    pub oversize_policy: ftsim_types::scenario::OversizePolicy,
 }
 
 impl Default for LinkFaultModel {
@@ -43,7 +47,61 @@ impl Default for LinkFaultModel {
             partitioned: false,
             bandwidth_bytes_per_ms: None,
             mtu_bytes: None,
+            oversize_policy: ftsim_types::scenario::OversizePolicy::Reject,
         }
     }
 }
 
+
+impl LinkFaultModel {
+    /// Rejects a model whose delays cannot be sampled.
+    pub fn validate(&self) -> Result<(), String> {
+        self.base_delay.validate().map_err(|e| format!("base_delay: {}", e))?;
+        self.jitter.validate().map_err(|e| format!("jitter: {}", e))
+    }
+
+    /// Feeds every field of the model into a state hash.
+    pub fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_f64(self.drop.0);
+        hasher.write_f64(self.duplicate.0);
+        hasher.write_f64(self.corrupt.0);
+        hash_delay(&self.base_delay, hasher);
+        hash_delay(&self.jitter, hasher);
+        hasher.write_usize(self.reorder_window);
+        hasher.write_u8(self.partitioned as u8);
+        hasher.write_u8(self.bandwidth_bytes_per_ms.is_some() as u8);
+        hasher.write_u64(self.bandwidth_bytes_per_ms.unwrap_or(0));
+        hasher.write_u8(self.mtu_bytes.is_some() as u8);
+        hasher.write_usize(self.mtu_bytes.unwrap_or(0));
+        hasher.write_u8(self.oversize_policy as u8);
+    }
+}
+
+fn hash_delay(delay: &DelaySpec, hasher: &mut StateHasher) {
+    match *delay {
+        DelaySpec::Const(ms) => {
+            hasher.write_u8(0);
+            hasher.write_u64(ms);
+        }
+        DelaySpec::Uniform { lo, hi } => {
+            hasher.write_u8(1);
+            hasher.write_u64(lo);
+            hasher.write_u64(hi);
+        }
+        DelaySpec::Normal { mu, sigma, .. } => {
+            hasher.write_u8(2);
+            hasher.write_f64(mu);
+            hasher.write_f64(sigma);
+        }
+        DelaySpec::Pareto { scale, shape, .. } => {
+            hasher.write_u8(3);
+            hasher.write_f64(scale);
+            hasher.write_f64(shape);
+        }
+    }
+    if let DelaySpec::Normal { .. } | DelaySpec::Pareto { .. } = delay {
+        let (min, max) = delay.bounds();
+        hasher.write_u64(min);
+        hasher.write_u64(max);
+    }
+}
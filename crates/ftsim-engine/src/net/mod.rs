@@ -16,9 +16,13 @@ use petgraph::{
 };
 
 mod faults;
+mod fragment;
+mod journal;
 mod link;
 
-pub use faults::sample_delay;
+pub use faults::{sample_delay, SampledDelay};
+pub use fragment::{fragment, ReassemblyBuffer, ReassemblyFailure};
+pub use journal::{Delivery, MessageJournal, MessageRecord, SenderView, Suspect, SuspectReason};
 pub use link::{LinkFaultModel, NetLink};
 
 /// Represents a node in the network graph.
@@ -42,20 +46,25 @@ pub struct Net {
 }
 
 impl Net {
-    /// Creates a new network from a topology specification.
-    pub fn from_topology(num_nodes: usize, spec: &TopologySpec) -> Self {
+    /// Creates a network of `num_nodes` nodes without any links.
+    pub fn empty(num_nodes: usize) -> Self {
         let mut graph = Graph::new();
         let node_indices: Vec<NodeIndex> = (0..num_nodes)
             .map(|i| graph.add_node(NetNode { id: i as NodeId }))
             .collect();
 
-        let mut net = Self {
+        Self {
             graph,
             links: FxHashMap::default(),
             node_indices,
             link_index: FxHashMap::default(),
             link_id_counter: 0,
-        };
+        }
+    }
+
+    /// Creates a new network from a topology specification.
+    pub fn from_topology(num_nodes: usize, spec: &TopologySpec) -> Self {
+        let mut net = Self::empty(num_nodes);
 
         let edges = match spec {
             TopologySpec::FullMesh => {
@@ -80,7 +89,7 @@ impl Net {
         net
     }
 
-    fn add_link(&mut self, src: NodeId, dst: NodeId, faults: LinkFaultModel) {
+    fn add_link(&mut self, src: NodeId, dst: NodeId, faults: LinkFaultModel) -> LinkId {
         let id = self.link_id_counter;
         self.link_id_counter += 1;
         let link = NetLink { id, src, dst, faults };
@@ -91,6 +100,52 @@ impl Net {
         );
         self.links.insert(id, link);
         self.link_index.insert(id, edge_index);
+        id
+    }
+
+    /// Adds a directed link from `src` to `dst`, or replaces the fault model
+    /// of the existing one, and returns its id. Fails, leaving the network
+    /// as it was, if the model's delays fail validation. Panics if either
+    /// node does not exist.
+    ///
+    /// A two-node network whose link from 0 to 1 drops a third of all
+    /// messages:
+    ///
+    /// ```
+    /// # use ftsim_engine::{net::LinkFaultModel, prelude::*};
+    /// let mut net = Net::empty(2);
+    /// net.connect(0, 1, LinkFaultModel { drop: Bernoulli(0.3), ..Default::default() })?;
+    /// net.connect(1, 0, LinkFaultModel::default())?;
+    /// assert_eq!(net.peers_of(0).collect::<Vec<_>>(), [1]);
+    /// # Ok::<(), String>(())
+    /// ```
+    #[cfg(any(test, feature = "testutil"))]
+    pub fn connect(&mut self, src: NodeId, dst: NodeId, faults: LinkFaultModel) -> Result<LinkId, String> {
+        faults.validate()?;
+        if let Some(id) = self.link_between(src, dst).map(|l| l.id) {
+            self.links.get_mut(&id).unwrap().faults = faults;
+            return Ok(id);
+        }
+        Ok(self.add_link(src, dst, faults))
+    }
+
+    /// Removes the directed link from `src` to `dst`, returning it.
+    #[cfg(any(test, feature = "testutil"))]
+    pub fn disconnect(&mut self, src: NodeId, dst: NodeId) -> Option<NetLink> {
+        let id = self.link_between(src, dst)?.id;
+        let edge = self.link_index.remove(&id)?;
+        // Removing an edge moves the graph's last edge into its slot
+        let last = EdgeIndex::new(self.graph.edge_count() - 1);
+        self.graph.remove_edge(edge);
+        if let Some(moved) = self.link_index.values_mut().find(|e| **e == last) {
+            *moved = edge;
+        }
+        self.links.remove(&id)
+    }
+
+    /// Returns the number of nodes in the network.
+    pub fn node_count(&self) -> usize {
+        self.node_indices.len()
     }
 
     /// Returns an iterator over the peer IDs of a given node.
@@ -99,82 +154,210 @@ impl Net {
         self.graph.neighbors(idx).map(move |i| self.graph[i].id)
     }
 
+    /// Returns the directed link from `src` to `dst`, if there is one.
+    pub fn link_between(&self, src: NodeId, dst: NodeId) -> Option<&NetLink> {
+        self.links.values().find(|l| l.src == src && l.dst == dst)
+    }
+
+    /// Returns whether any link out of or into `nid` is partitioned.
+    pub fn any_partitioned(&self, nid: NodeId) -> bool {
+        self.links
+            .values()
+            .any(|l| (l.src == nid || l.dst == nid) && l.faults.partitioned)
+    }
+
     /// Processes an outgoing message from a node, applies the relevant link
-    /// fault model, and schedules 0 or more `Deliver` events.
-    pub fn send(&mut self, ctx: &mut EngineCtx, env: Envelope) {
+    /// fault model, and schedules 0 or more `Deliver` events, each held back
+    /// by `hold` on top of the link's delay. Takes the net through `ctx`
+    /// rather than `&self`, since the context already borrows the whole
+    /// simulation. Messages dropped for want of a usable link are reported
+    /// to the sending protocol, if it opted in with `send_failure_delay`.
+    pub fn send(ctx: &mut EngineCtx, env: Envelope, hold: SimTime) {
         // Find the link ID based on src/dst
-        let link_id = self
+        let link_id = ctx
+            .sim
+            .world
+            .net
             .links
             .values()
             .find(|l| l.src == env.src && l.dst == env.dst)
             .map(|l| l.id);
 
         if let Some(link_id) = link_id {
-            let link = self.links.get(&link_id).unwrap();
+            let link = ctx.sim.world.net.links.get(&link_id).unwrap();
 
             // --- Apply Fault Model ---
             if link.faults.partitioned {
-                tracing::debug!(msg_id = env.msg_id, "Message dropped due to partition");
+                tracing::debug!(msg_id = %env.msg_id, "Message dropped due to partition");
                 ::metrics::counter!(
                     ftsim_types::metrics::MET_NET_MSG_DROPPED,
                     ftsim_types::metrics::LBL_REASON => "partition",
                     ftsim_types::metrics::LBL_SRC => env.src.to_string(),
                     ftsim_types::metrics::LBL_DST => env.dst.to_string()
                 ).increment(1);
+                ctx.sim.telemetry().record_drop(env.dst, Some(link_id));
+                Self::fail_send(ctx, &env, hold, SendFailure::Partitioned);
                 return;
             }
 
-            if faults::trial(ctx.rng("net.drop"), &link.faults.drop) {
-                tracing::debug!(msg_id = env.msg_id, "Message dropped by fault model");
-                ::metrics::counter!(
-                    ftsim_types::metrics::MET_NET_MSG_DROPPED,
-                    ftsim_types::metrics::LBL_REASON => "drop_probability",
-                    ftsim_types::metrics::LBL_SRC => env.src.to_string(),
-                    ftsim_types::metrics::LBL_DST => env.dst.to_string()
-                ).increment(1);
+            if let Some(mtu) = link.faults.mtu_bytes.filter(|mtu| env.payload.len() > *mtu) {
+                match link.faults.oversize_policy {
+                    OversizePolicy::Reject => {
+                        tracing::debug!(msg_id = %env.msg_id, mtu, "Message dropped, exceeds MTU");
+                        ::metrics::counter!(
+                            ftsim_types::metrics::MET_NET_MSG_DROPPED,
+                            ftsim_types::metrics::LBL_REASON => "exceeds_mtu",
+                            ftsim_types::metrics::LBL_SRC => env.src.to_string(),
+                            ftsim_types::metrics::LBL_DST => env.dst.to_string()
+                        ).increment(1);
+                        ctx.sim.telemetry().record_drop(env.dst, Some(link_id));
+                        Self::fail_send(ctx, &env, hold, SendFailure::TooLarge);
+                    }
+                    OversizePolicy::Fragment => {
+                        let fragments = fragment(&env, mtu);
+                        ::metrics::counter!(
+                            ftsim_types::metrics::MET_NET_FRAGMENTS,
+                            ftsim_types::metrics::LBL_SRC => env.src.to_string(),
+                            ftsim_types::metrics::LBL_DST => env.dst.to_string()
+                        ).increment(fragments.len() as u64);
+                        // Each fragment is subject to the fault model on its own.
+                        for frag in fragments {
+                            Self::transmit(ctx, link_id, frag, hold);
+                        }
+                    }
+                }
                 return;
             }
 
-            let base_delay = sample_delay(ctx.rng("net.delay.base"), &link.faults.base_delay);
-            let jitter = sample_delay(ctx.rng("net.delay.jitter"), &link.faults.jitter);
-            let total_delay = base_delay + jitter;
-            let delivery_time = ctx.sim.now() + total_delay;
-
-            let deliver_event = Event::Deliver {
-                env: env.clone(),
-                link_id,
-            };
-            // Use SOURCE node for tie-breaking
-            let discriminant = EventDiscriminant::delivery(env.src);
-            ctx.sim
-                .schedule_at(delivery_time, deliver_event, discriminant);
-
-            // Handle duplication
-            if faults::trial(ctx.rng("net.duplicate"), &link.faults.duplicate) {
-                tracing::debug!(msg_id = env.msg_id, "Message duplicated by fault model");
-                let dup_delay = sample_delay(ctx.rng("net.delay.dup"), &link.faults.base_delay);
-                let dup_delivery_time = ctx.sim.now() + dup_delay;
-                let dup_event = Event::Deliver { env, link_id };
-                ctx.sim
-                    .schedule_at(dup_delivery_time, dup_event, discriminant);
+            Self::transmit(ctx, link_id, env, hold);
+        } else {
+            tracing::debug!(msg_id = %env.msg_id, "Message dropped, no link to its destination");
+            ::metrics::counter!(
+                ftsim_types::metrics::MET_NET_MSG_DROPPED,
+                ftsim_types::metrics::LBL_REASON => "no_link",
+                ftsim_types::metrics::LBL_SRC => env.src.to_string(),
+                ftsim_types::metrics::LBL_DST => env.dst.to_string()
+            ).increment(1);
+            ctx.sim.telemetry().record_drop(env.dst, None);
+            Self::fail_send(ctx, &env, hold, SendFailure::NoLink);
+        }
+    }
+
+    /// Schedules `on_send_failed` for the sender of a dropped message, once
+    /// its protocol's detection delay has passed after the message would
+    /// have left.
+    fn fail_send(ctx: &mut EngineCtx, env: &Envelope, hold: SimTime, reason: SendFailure) {
+        let node = ctx.sim.world.node(env.src);
+        let Some(delay) = node.send_failure_delay(env.proto_tag) else {
+            return;
+        };
+        let event = Event::SendFailed {
+            node_id: env.src,
+            incarnation: node.incarnation(),
+            proto_tag: env.proto_tag,
+            dst: env.dst,
+            msg_id: env.msg_id,
+            reason,
+        };
+        match checked_add(delay, hold).and_then(|delay| checked_add(ctx.busy_until()?, delay)) {
+            Ok(at) => {
+                ctx.sim.schedule_at(at, event, EventDiscriminant::timer(env.src));
             }
+            Err(err) => ctx.time_overflow("net.send_failed", err),
         }
     }
 
-    pub fn set_partition(&mut self, sets: Vec<Vec<NodeId>>) {
-        // A simple implementation: for any two nodes in different sets,
-        // mark the link between them as partitioned.
+    /// Configures the MTU and oversize policy of every link.
+    pub fn set_mtu(&mut self, mtu: Option<usize>, policy: OversizePolicy) {
         for link in self.links.values_mut() {
-            let src_set = sets.iter().find(|s| s.contains(&link.src));
-            let dst_set = sets.iter().find(|s| s.contains(&link.dst));
+            link.faults.mtu_bytes = mtu;
+            link.faults.oversize_policy = policy;
+        }
+    }
+
+    /// Applies the drop, delay and duplication models of a link to a single
+    /// envelope and schedules its delivery.
+    fn transmit(ctx: &mut EngineCtx, link_id: LinkId, env: Envelope, hold: SimTime) {
+        let mut sampler = ctx.link_sampler(link_id);
+        let model = sampler.model;
+        if faults::trial(sampler.rng("net.drop"), &model.drop) {
+            tracing::debug!(msg_id = %env.msg_id, "Message dropped by fault model");
+            ::metrics::counter!(
+                ftsim_types::metrics::MET_NET_MSG_DROPPED,
+                ftsim_types::metrics::LBL_REASON => "drop_probability",
+                ftsim_types::metrics::LBL_SRC => env.src.to_string(),
+                ftsim_types::metrics::LBL_DST => env.dst.to_string()
+            ).increment(1);
+            ctx.sim.telemetry().record_drop(env.dst, Some(link_id));
+            return;
+        }
+
+        let base_delay = sampler.sample_delay("net.delay.base", &model.base_delay);
+        let jitter = sampler.sample_delay("net.delay.jitter", &model.jitter);
+        let delivery_time = match checked_add(base_delay, jitter)
+            .and_then(|delay| checked_add(delay, hold))
+            .and_then(|delay| checked_add(ctx.busy_until()?, delay))
+        {
+            Ok(time) => time,
+            Err(err) => {
+                ctx.time_overflow("net.deliver", err);
+                return;
+            }
+        };
+
+        let deliver_event = Event::Deliver {
+            env: env.clone(),
+            link_id,
+        };
+        // Use SOURCE node for tie-breaking
+        let discriminant = EventDiscriminant::delivery(env.src);
+        ctx.sim
+            .schedule_at(delivery_time, deliver_event, discriminant);
+
+        // Handle duplication
+        let mut sampler = ctx.link_sampler(link_id);
+        let model = sampler.model;
+        if faults::trial(sampler.rng("net.duplicate"), &model.duplicate) {
+            tracing::debug!(msg_id = %env.msg_id, "Message duplicated by fault model");
+            let dup_delay = sampler.sample_delay("net.delay.dup", &model.base_delay);
+            match checked_add(dup_delay, hold).and_then(|delay| checked_add(ctx.busy_until()?, delay)) {
+                Ok(dup_delivery_time) => {
+                    let dup_event = Event::Deliver { env, link_id };
+                    ctx.sim
+                        .schedule_at(dup_delivery_time, dup_event, discriminant);
+                }
+                Err(err) => ctx.time_overflow("net.duplicate", err),
+            }
+        }
+    }
+
+    /// Returns every link id in ascending order. Iterate links through this
+    /// rather than over `links` directly wherever the order can be observed,
+    /// since hash map order is not stable across builds and platforms.
+    pub fn sorted_link_ids(&self) -> Vec<LinkId> {
+        let mut ids: Vec<LinkId> = self.links.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Marks every link between two nodes in different sets as partitioned
+    /// and returns the ids of those links in ascending order. Nodes in no set
+    /// keep all their links.
+    pub fn set_partition(&mut self, sets: Vec<Vec<NodeId>>) -> Vec<LinkId> {
+        let mut cut = Vec::new();
+        for id in self.sorted_link_ids() {
+            let link = self.links.get_mut(&id).unwrap();
+            let src_set = sets.iter().position(|s| s.contains(&link.src));
+            let dst_set = sets.iter().position(|s| s.contains(&link.dst));
 
             if let (Some(s1), Some(s2)) = (src_set, dst_set) {
-                // If the pointers are not equal, they are in different sets.
-                if !std::ptr::eq(s1, s2) {
+                if s1 != s2 {
                     link.faults.partitioned = true;
+                    cut.push(id);
                 }
             }
         }
+        cut
     }
 
     pub fn heal_partition(&mut self) {
@@ -183,3 +366,114 @@ impl Net {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that every link's graph edge joins its own endpoints.
+    fn assert_indices_consistent(net: &Net) {
+        assert_eq!(net.links.len(), net.graph.edge_count());
+        assert_eq!(net.link_index.len(), net.links.len());
+        for link in net.links.values() {
+            let edge = net.link_index[&link.id];
+            let (src, dst) = net.graph.edge_endpoints(edge).unwrap();
+            assert_eq!((net.graph[src].id, net.graph[dst].id), (link.src, link.dst));
+        }
+    }
+
+    fn peers(net: &Net, node: NodeId) -> Vec<NodeId> {
+        let mut peers: Vec<NodeId> = net.peers_of(node).collect();
+        peers.sort_unstable();
+        peers
+    }
+
+    #[test]
+    fn test_connect_and_disconnect_keep_indices_consistent() {
+        let mut net = Net::empty(3);
+        assert!(net.links.is_empty());
+        let a = net.connect(0, 1, LinkFaultModel::default()).unwrap();
+        let b = net.connect(1, 2, LinkFaultModel::default()).unwrap();
+        net.connect(2, 0, LinkFaultModel::default()).unwrap();
+        // Connecting an existing pair only replaces its fault model
+        let lossy = LinkFaultModel { drop: Bernoulli(0.5), ..Default::default() };
+        assert_eq!(net.connect(0, 1, lossy), Ok(a));
+        assert_eq!(net.link_between(0, 1).unwrap().faults.drop.0, 0.5);
+        assert_indices_consistent(&net);
+
+        // Removing the first edge moves the last one into its slot
+        assert_eq!(net.disconnect(0, 1).unwrap().id, a);
+        assert!(net.disconnect(0, 1).is_none());
+        assert_indices_consistent(&net);
+        assert_eq!(peers(&net, 0), Vec::<NodeId>::new());
+        assert_eq!(peers(&net, 2), [0]);
+        assert_eq!(net.link_between(1, 2).unwrap().id, b);
+
+        // Fresh links never reuse an id
+        assert!(net.connect(0, 1, LinkFaultModel::default()).unwrap() > a);
+        assert_indices_consistent(&net);
+
+        // A model whose delays cannot be sampled is rejected
+        let jitter = ftsim_types::scenario::DelaySpec::Uniform { lo: 2, hi: 1 };
+        let inverted = LinkFaultModel { jitter, ..Default::default() };
+        let err = net.connect(1, 0, inverted).unwrap_err();
+        assert!(err.starts_with("jitter: Uniform delay has lo 2 above hi 1"), "{}", err);
+        assert!(net.link_between(1, 0).is_none());
+    }
+
+    #[test]
+    fn test_partition_assignment_does_not_depend_on_link_history() {
+        let sets = vec![vec![0, 2], vec![1, 3], vec![4]];
+        // The same links, added in different orders and after different
+        // removals, so the hash maps hold them in different orders
+        let build = |order: &[(NodeId, NodeId)], churn: bool| {
+            let mut net = Net::empty(5);
+            if churn {
+                for dst in 1..5 {
+                    net.connect(0, dst, LinkFaultModel::default()).unwrap();
+                }
+                for dst in 1..5 {
+                    net.disconnect(0, dst);
+                }
+            }
+            for &(src, dst) in order {
+                net.connect(src, dst, LinkFaultModel::default()).unwrap();
+            }
+            let cut: Vec<(NodeId, NodeId)> = net
+                .set_partition(sets.clone())
+                .into_iter()
+                .map(|id| (net.links[&id].src, net.links[&id].dst))
+                .collect();
+            let flags: Vec<bool> = net.sorted_link_ids().iter().map(|id| net.links[id].faults.partitioned).collect();
+            (cut, flags)
+        };
+        let pairs: Vec<(NodeId, NodeId)> = (0..5).flat_map(|s| (0..5).filter(move |&d| d != s).map(move |d| (s, d))).collect();
+        let reversed: Vec<_> = pairs.iter().rev().copied().collect();
+
+        let (cut, flags) = build(&pairs, false);
+        assert_eq!(cut.len(), 16);
+        assert!(cut.contains(&(0, 1)) && !cut.contains(&(0, 2)));
+        for _ in 0..10 {
+            assert_eq!(build(&pairs, false), (cut.clone(), flags.clone()));
+            assert_eq!(build(&pairs, true).1, flags);
+        }
+        // Listed by link id, which follows insertion order
+        let (reversed_cut, _) = build(&reversed, false);
+        assert_eq!(reversed_cut, cut.iter().rev().copied().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_partition_only_cuts_links_between_sets() {
+        let mut net = Net::from_topology(4, &TopologySpec::FullMesh);
+        net.disconnect(0, 3);
+        net.set_partition(vec![vec![0, 1], vec![2, 3]]);
+        for link in net.links.values() {
+            let crosses = (link.src < 2) != (link.dst < 2);
+            assert_eq!(link.faults.partitioned, crosses, "{} -> {}", link.src, link.dst);
+        }
+        assert!(net.any_partitioned(0));
+        net.heal_partition();
+        assert!(!(0..4).any(|n| net.any_partitioned(n)));
+        assert_indices_consistent(&net);
+    }
+}
@@ -0,0 +1,79 @@
+//! # ftsim-engine::node::meta
+//!
+//! A node's metadata area: a small map the engine keeps apart from the
+//! node's store. Unlike the store, it is never subject to the
+//! `StoreFaultModel` and survives every crash intact, so the engine can rely
+//! on what it keeps there: the node's incarnation, its UUID and the store's
+//! durable boundary. Protocols read it through `ProtoCtx::node_meta_get` but
+//! cannot write it. Values are text, so that reports can show them as is.
+
+use crate::{prelude::*, state_hash::StateHasher};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt::Write, hash::Hasher};
+
+/// A node's engine-managed metadata, keyed by names such as
+/// `META_INCARNATION`.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct NodeMeta(BTreeMap<String, String>);
+
+impl NodeMeta {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// Reads a value written by `put_u64`.
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        self.get(key)?.parse().ok()
+    }
+
+    pub(crate) fn put(&mut self, key: &str, value: String) {
+        self.0.insert(key.to_string(), value);
+    }
+
+    /// Writes `value` in decimal, reusing the entry's buffer: the engine
+    /// moves the durable log boundary on every durable write.
+    pub(crate) fn put_u64(&mut self, key: &str, value: u64) {
+        match self.0.get_mut(key) {
+            Some(entry) => {
+                entry.clear();
+                let _ = write!(entry, "{}", value);
+            }
+            None => self.put(key, value.to_string()),
+        }
+    }
+
+    /// Returns every entry, in key order.
+    pub fn entries(&self) -> &BTreeMap<String, String> {
+        &self.0
+    }
+}
+
+/// Derives node `node`'s UUID from the seed, as a version 4 UUID. It draws
+/// from a generator of its own, so no RNG stream moves.
+pub fn node_uuid(seed: u64, node: NodeId) -> String {
+    let mut hasher = StateHasher::new();
+    hasher.write_u64(seed);
+    hasher.write_bytes(b"node.uuid");
+    hasher.write_u32(node);
+    let mut rng = ChaCha20Rng::seed_from_u64(hasher.finish());
+    let mut bytes = [0u8; 16];
+    rng.fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let v = u128::from_be_bytes(bytes);
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        v >> 96,
+        (v >> 80) & 0xffff,
+        (v >> 64) & 0xffff,
+        (v >> 48) & 0xffff,
+        v & 0xffff_ffff_ffff
+    )
+}
@@ -2,7 +2,9 @@
 //!
 //! This module contains the node runtime and timer management.
 
+pub mod meta;
 pub mod runtime;
 pub mod timers;
 
-pub use runtime::{Node, NodeStatus};
+pub use meta::NodeMeta;
+pub use runtime::{CodecFailure, GrayFailure, Node, NodeCheckpoint, NodeState, NodeStatus};
@@ -1,20 +1,38 @@
 //! # ftsim-engine::node::runtime
 //!
 //! Contains the `Node` struct and its core logic for handling events.
-//! The `Node` acts as a host for a `ProtocolDyn` instance, providing it with
-//! the necessary context to interact with the simulation engine.
+//! The `Node` acts as a host for one or more `ProtocolDyn` instances,
+//! providing them with the necessary context to interact with the
+//! simulation engine.
+//!
+//! Messages are routed to the protocol whose tag the envelope carries, and
+//! each protocol has its own timer namespace: it only sees, cancels and
+//! receives the timers it set. Lifecycle hooks and faults go to every
+//! protocol, in the order they were added.
+//!
+//! The context a protocol callback gets borrows the whole simulation, node
+//! included. Handlers therefore take the context rather than `&mut self`,
+//! and the protocol is taken out of its node for the length of a callback.
 
-use super::timers::TimerWheel;
+use super::{meta::NodeMeta, timers::TimerWheel};
 use crate::{
     events::FaultEventInternal,
+    net::ReassemblyBuffer,
     prelude::*,
-    sim::EngineCtx,
-    store::{Store, StoreFaultModel, StoreView},
+    rng::bernoulli,
+    sim::{EngineCtx, PendingClientRequest},
+    store::{Store, StoreCheckpoint, StoreFaultModel, StoreView, WriteQuota},
+};
+use bytes::Bytes;
+use ftsim_proto::{
+    api::{InitCtx, Maintenance},
+    FaultEvent, ProtocolDyn,
 };
-use ftsim_proto::{FaultEvent, ProtocolDyn};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
 
 /// The operational status of a node.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum NodeStatus {
     /// The node is running normally.
     Up,
@@ -24,50 +42,270 @@ pub enum NodeStatus {
     Recovering,
 }
 
+/// A message a protocol failed to decode, reported under
+/// `CodecErrorPolicy::Fail`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodecFailure {
+    pub src: NodeId,
+    pub dst: NodeId,
+    pub msg_id: MsgId,
+    pub proto_tag: ProtoTag,
+    /// The name of the protocol that rejected the message.
+    pub protocol: &'static str,
+    pub payload_hex: String,
+    pub error: String,
+}
+
+impl std::fmt::Display for CodecFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "protocol '{}' on node {} failed to decode message {} from {} (tag {}): {}; payload: {}",
+            self.protocol, self.dst, self.msg_id, self.src, self.proto_tag.0, self.error, self.payload_hex
+        )
+    }
+}
+
+/// A gray failure in effect on a node: which senders it ignores, and until
+/// when. The node's protocols are not told about it.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GrayFailure {
+    pub ignore_from: GrayFilter,
+    /// When the failure ends, or `None` if it never does.
+    pub until: Option<SimTime>,
+    /// Messages ignored so far.
+    pub ignored: u64,
+}
+
+/// A saved copy of a node's store, metadata, status and clock skew.
+pub struct NodeCheckpoint {
+    store: StoreCheckpoint,
+    meta: NodeMeta,
+    status: NodeStatus,
+    clock_skew_ns: i128,
+}
+
+/// Everything `Simulation::save_state` needs to rewind a node: its
+/// checkpoint plus each protocol's serialized state, pending timers,
+/// partially reassembled messages and the faults in effect on it.
+pub struct NodeState {
+    checkpoint: NodeCheckpoint,
+    protos: Vec<Bytes>,
+    store_faults: StoreFaultModel,
+    write_quota: Option<WriteQuota>,
+    timers: Vec<TimerWheel>,
+    reassembly: ReassemblyBuffer,
+    gray_failure: Option<GrayFailure>,
+    byzantine: bool,
+    announced: Vec<Maintenance>,
+}
+
+/// A protocol's name, tag and `send_failure_delay`.
+struct ProtoInfo {
+    name: &'static str,
+    tag: ProtoTag,
+    send_failure_delay: Option<SimTime>,
+}
+
+impl ProtoInfo {
+    fn new(proto: &dyn ProtocolDyn) -> Self {
+        Self {
+            name: proto.name(),
+            tag: proto.proto_tag(),
+            send_failure_delay: proto.send_failure_delay(),
+        }
+    }
+}
+
 /// Represents a single node in the simulated system.
 pub struct Node {
     pub id: NodeId,
     pub status: NodeStatus,
     /// A logical clock skew applied to this node's perception of time.
     pub clock_skew_ns: i128,
-    /// The protocol logic running on this node.
-    proto: Box<dyn ProtocolDyn>,
+    /// The protocols running on this node, in the order they were added,
+    /// with distinct tags. A slot is `None` only while one of its protocol's
+    /// callbacks runs.
+    protos: Vec<Option<Box<dyn ProtocolDyn>>>,
+    /// What the engine reads of each protocol, by slot, read when it was
+    /// added so it is known while the protocol's callbacks run.
+    infos: Vec<ProtoInfo>,
+    /// The slot of the protocol whose callback runs, whose timer namespace
+    /// its timer operations use. `None` between callbacks.
+    active: Option<usize>,
     /// The persistent storage backend for this node.
     store: Box<dyn Store>,
+    /// The engine's metadata about the node, kept apart from the store:
+    /// its incarnation, UUID and durable store boundary.
+    meta: NodeMeta,
     /// The fault model for this node's storage.
     store_faults: StoreFaultModel,
-    /// The timer management system for this node.
-    timers: TimerWheel,
+    /// The simulated latency of this node's storage, if any.
+    store_latency: Option<StoreLatencySpec>,
+    /// The rate limit on this node's store writes, if any.
+    write_quota: Option<WriteQuota>,
+    /// The timers of each protocol, by slot.
+    timers: Vec<TimerWheel>,
     /// A list of peers this node can communicate with.
     peers: Vec<NodeId>,
     /// Flag indicating if Byzantine behaviors are enabled for this node.
     byzantine: bool,
+    /// Partially received fragmented messages addressed to this node.
+    reassembly: ReassemblyBuffer,
+    /// The last gray failure applied to the node, which may have ended.
+    gray_failure: Option<GrayFailure>,
+    /// The faults announced to the node, soonest first, at sim times
+    /// without its clock skew. Ones that have happened are dropped lazily.
+    announced: Vec<Maintenance>,
 }
 
 impl Node {
     /// Creates a new node.
     pub fn new(id: NodeId, proto: Box<dyn ProtocolDyn>, store: Box<dyn Store>) -> Self {
+        let mut meta = NodeMeta::new();
+        meta.put_u64(META_INCARNATION, 0);
+        // Whatever the store starts with counts as durable
+        if let Some(len) = store.log_len() {
+            meta.put_u64(META_DURABLE_LOG_LEN, len);
+        }
         Self {
             id,
             status: NodeStatus::Up,
             clock_skew_ns: 0,
-            proto,
+            infos: vec![ProtoInfo::new(proto.as_ref())],
+            protos: vec![Some(proto)],
+            active: None,
             store,
+            meta,
             store_faults: StoreFaultModel::default(),
-            timers: TimerWheel::new(),
+            store_latency: None,
+            write_quota: None,
+            timers: vec![TimerWheel::new()],
             peers: Vec::new(),
             byzantine: false,
+            reassembly: ReassemblyBuffer::new(
+                NetSpec::default().reassembly_timeout,
+                NetSpec::default().max_reassemblies,
+            ),
+            gray_failure: None,
+            announced: Vec::new(),
         }
     }
 
-    /// Forwards the `init` call to the hosted protocol.
-    pub fn init(&mut self, ctx: &mut dyn ProtoCtx) {
-        self.proto.init(ctx);
+    /// Adds another protocol to the node, with its own timer namespace.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node already runs a protocol with the same tag.
+    pub fn add_protocol(&mut self, proto: Box<dyn ProtocolDyn>) {
+        let tag = proto.proto_tag();
+        assert!(
+            self.slot(tag).is_none(),
+            "node {} already runs a protocol with tag {}",
+            self.id,
+            tag.0
+        );
+        self.infos.push(ProtoInfo::new(proto.as_ref()));
+        self.protos.push(Some(proto));
+        self.timers.push(TimerWheel::new());
+    }
+
+    /// Returns the protocol in `slot`, or `None` while one of its callbacks
+    /// runs.
+    fn proto_at(&self, slot: usize) -> Option<&dyn ProtocolDyn> {
+        self.protos[slot].as_deref()
+    }
+
+    /// Returns the slot of the protocol with tag `tag`.
+    fn slot(&self, tag: ProtoTag) -> Option<usize> {
+        self.infos.iter().position(|info| info.tag == tag)
+    }
+
+    /// The timers of the protocol whose callback runs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if none is running one.
+    fn wheel(&self) -> &TimerWheel {
+        &self.timers[self.active.expect("timer operations run in a protocol callback")]
+    }
+
+    fn wheel_mut(&mut self) -> &mut TimerWheel {
+        &mut self.timers[self.active.expect("timer operations run in a protocol callback")]
+    }
+
+    /// Runs `f` on the protocol in `slot` of node `node_id`, taken out of
+    /// the node so that `ctx` can still reach the node while it runs. The
+    /// bus knows the node meanwhile, which ties what the protocol logs to it.
+    fn dispatch_to<R>(
+        ctx: &mut EngineCtx,
+        node_id: NodeId,
+        slot: usize,
+        f: impl FnOnce(&mut dyn ProtocolDyn, &mut EngineCtx) -> R,
+    ) -> R {
+        let outer = ctx.sim.telemetry().set_current_node(Some(node_id));
+        let node = ctx.sim.world.node_mut(node_id);
+        node.active = Some(slot);
+        let mut proto = node.protos[slot].take().expect("protocol callbacks do not nest");
+        let result = f(proto.as_mut(), ctx);
+        let node = ctx.sim.world.node_mut(node_id);
+        node.protos[slot] = Some(proto);
+        node.active = None;
+        ctx.sim.telemetry().set_current_node(outer);
+        result
+    }
+
+    /// Runs `f` on every protocol of node `node_id`, in order.
+    fn dispatch_all(
+        ctx: &mut EngineCtx,
+        node_id: NodeId,
+        mut f: impl FnMut(&mut dyn ProtocolDyn, &mut EngineCtx),
+    ) {
+        for slot in 0..ctx.sim.world.node(node_id).protos.len() {
+            Self::dispatch_to(ctx, node_id, slot, &mut f);
+        }
+    }
+
+    /// Forwards the `init` call to the protocols of node `node_id` through a
+    /// restricted context that rejects sends and timers.
+    pub fn init(ctx: &mut EngineCtx, node_id: NodeId) {
+        Self::dispatch_all(ctx, node_id, |proto, ctx| proto.init(&mut InitCtx::new(ctx)));
+    }
+
+    /// Forwards the `start` call to the protocols of node `node_id`.
+    pub fn start(ctx: &mut EngineCtx, node_id: NodeId) {
+        Self::dispatch_all(ctx, node_id, |proto, ctx| proto.start(ctx));
+    }
+
+    /// Forwards the `on_shutdown` call to the protocols of node `node_id`.
+    pub fn shutdown(ctx: &mut EngineCtx, node_id: NodeId) {
+        Self::dispatch_all(ctx, node_id, |proto, ctx| proto.on_shutdown(ctx));
+    }
+
+    /// Returns the name of the node's first protocol.
+    pub fn proto_name(&self) -> &'static str {
+        self.infos[0].name
     }
 
-    /// Returns the protocol tag of the hosted protocol.
+    /// Returns the tag of the node's first protocol.
     pub fn proto_tag(&self) -> ProtoTag {
-        self.proto.proto_tag()
+        self.infos[0].tag
+    }
+
+    /// Returns the name and tag of every protocol on the node, in order.
+    pub fn protocols(&self) -> Vec<(&'static str, ProtoTag)> {
+        self.infos.iter().map(|info| (info.name, info.tag)).collect()
+    }
+
+    /// Names the kind of an encoded message of protocol `tag`, as that
+    /// protocol sees it. `None` if the node does not run it, or while it is
+    /// running a callback.
+    pub fn message_variant(&self, tag: ProtoTag, bytes: &[u8]) -> Option<String> {
+        self.protos
+            .iter()
+            .flatten()
+            .find(|p| p.proto_tag() == tag)?
+            .message_variant(bytes)
     }
 
     /// Sets the list of peers for this node.
@@ -75,6 +313,106 @@ impl Node {
         self.peers = peers;
     }
 
+    /// Captures the node's store and fault-relevant state.
+    pub fn checkpoint(&self) -> NodeCheckpoint {
+        NodeCheckpoint {
+            store: self.store.checkpoint(),
+            meta: self.meta.clone(),
+            status: self.status,
+            clock_skew_ns: self.clock_skew_ns,
+        }
+    }
+
+    /// Restores state captured by `checkpoint`.
+    pub fn restore(&mut self, checkpoint: NodeCheckpoint) {
+        self.store.restore(checkpoint.store);
+        self.meta = checkpoint.meta;
+        self.status = checkpoint.status;
+        self.clock_skew_ns = checkpoint.clock_skew_ns;
+    }
+
+    /// Captures the node's complete state, failing if any of its protocols
+    /// does not implement `snapshot_state`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called while one of the node's protocols runs a callback.
+    pub fn save_state(&self) -> Result<NodeState, SimError> {
+        let protos = (0..self.protos.len())
+            .map(|slot| {
+                let proto = self.proto_at(slot).expect("protocol is running a callback");
+                proto.snapshot_state().ok_or(SimError::SnapshotUnsupported {
+                    node: self.id,
+                    protocol: proto.name(),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(NodeState {
+            checkpoint: self.checkpoint(),
+            protos,
+            store_faults: self.store_faults,
+            write_quota: self.write_quota.clone(),
+            timers: self.timers.clone(),
+            reassembly: self.reassembly.clone(),
+            gray_failure: self.gray_failure.clone(),
+            byzantine: self.byzantine,
+            announced: self.announced.clone(),
+        })
+    }
+
+    /// Restores state captured by `save_state`. If any of the node's
+    /// protocols fails to restore, or the state is of a node running a
+    /// different number of protocols, the node is left as it was.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called while one of the node's protocols runs a callback.
+    pub fn restore_state(&mut self, state: NodeState) -> Result<(), SimError> {
+        if state.protos.len() != self.protos.len() {
+            return Err(SimError::RestoreFailed {
+                node: self.id,
+                message: format!("state of {} protocols, node runs {}", state.protos.len(), self.protos.len()),
+            });
+        }
+        let current = self.save_state()?;
+        if let Err(err) = self.restore_protocols(&state.protos) {
+            self.restore_protocols(&current.protos)
+                .expect("a protocol fails to restore the state it just saved");
+            return Err(err);
+        }
+        self.restore(state.checkpoint);
+        self.store_faults = state.store_faults;
+        self.write_quota = state.write_quota;
+        self.timers = state.timers;
+        self.reassembly = state.reassembly;
+        self.gray_failure = state.gray_failure;
+        self.byzantine = state.byzantine;
+        self.announced = state.announced;
+        Ok(())
+    }
+
+    fn restore_protocols(&mut self, protos: &[Bytes]) -> Result<(), SimError> {
+        for (proto, saved) in self.protos.iter_mut().zip(protos) {
+            proto
+                .as_deref_mut()
+                .expect("protocol is running a callback")
+                .restore_state(saved)
+                .map_err(|e| SimError::RestoreFailed { node: self.id, message: e.0 })?;
+        }
+        Ok(())
+    }
+
+    /// Returns each protocol's serialized state, if all of them support
+    /// snapshots and none is running a callback.
+    pub fn protocol_state(&self) -> Option<Vec<Bytes>> {
+        (0..self.protos.len()).map(|slot| self.proto_at(slot)?.snapshot_state()).collect()
+    }
+
+    /// Returns read-only access to the node's storage backend.
+    pub fn store(&self) -> &dyn Store {
+        self.store.as_ref()
+    }
+
     /// Returns a mutable view into the node's storage.
     pub fn store_view(&mut self) -> &mut dyn StoreView {
         self.store.as_view()
@@ -85,9 +423,60 @@ impl Node {
         &mut self.store_faults
     }
 
-    /// Returns the number of active timers.
+    /// Returns the node's storage fault model.
+    pub fn store_fault_model(&self) -> StoreFaultModel {
+        self.store_faults
+    }
+
+    /// Sets the simulated latency model for this node's storage.
+    pub fn set_store_latency(&mut self, latency: Option<StoreLatencySpec>) {
+        self.store_latency = latency;
+    }
+
+    /// Returns the simulated latency model for this node's storage.
+    pub fn store_latency(&self) -> Option<StoreLatencySpec> {
+        self.store_latency
+    }
+
+    /// Limits how fast this node may write to its store, with a full bucket.
+    pub fn set_write_quota(&mut self, spec: Option<WriteQuotaSpec>) {
+        self.write_quota = spec.map(WriteQuota::new);
+    }
+
+    /// Returns the node's write quota and how much it has throttled.
+    pub fn write_quota(&self) -> Option<&WriteQuota> {
+        self.write_quota.as_ref()
+    }
+
+    /// Returns mutable access to the node's write quota.
+    pub fn write_quota_mut(&mut self) -> &mut Option<WriteQuota> {
+        &mut self.write_quota
+    }
+
+    /// Configures the reassembly buffer for fragmented messages.
+    pub fn set_reassembly(&mut self, timeout: SimTime, max_pending: usize) {
+        self.reassembly = ReassemblyBuffer::new(timeout, max_pending);
+    }
+
+    /// Feeds a message fragment into the reassembly buffer, returning the
+    /// whole message once all of its fragments have arrived.
+    pub fn reassemble(&mut self, now: SimTime, env: Envelope) -> Option<Envelope> {
+        self.reassembly.insert(now, env)
+    }
+
+    /// Returns the node's fragment reassembly buffer.
+    pub fn reassembly(&self) -> &ReassemblyBuffer {
+        &self.reassembly
+    }
+
+    /// Returns the number of active timers, over all protocols.
     pub fn timers_len(&self) -> usize {
-        self.timers.active_timers()
+        self.timers.iter().map(TimerWheel::active_timers).sum()
+    }
+
+    /// Returns the bytes held for the payloads of pending timers.
+    pub fn timer_payload_bytes(&self) -> usize {
+        self.timers.iter().map(TimerWheel::payload_bytes).sum()
     }
 
     /// Returns whether the node is in byzantine mode.
@@ -95,87 +484,452 @@ impl Node {
         self.byzantine
     }
 
-    /// Handles an incoming message delivery event.
-    pub fn handle_message(&mut self, ctx: &mut EngineCtx, env: Envelope) {
-        if self.status != NodeStatus::Up {
-            tracing::debug!(node_id = self.id, msg_id = env.msg_id, "Message dropped, node is down");
+    /// Returns the gray failure in effect on the node at `now`, if any.
+    pub fn gray_failure(&self, now: SimTime) -> Option<&GrayFailure> {
+        self.gray_failure.as_ref().filter(|gray| !gray.until.is_some_and(|until| now >= until))
+    }
+
+    /// Starts a gray failure, replacing any earlier one. It ends at `until`,
+    /// if given.
+    pub fn set_gray_failure(&mut self, ignore_from: GrayFilter, until: Option<SimTime>) {
+        self.gray_failure = Some(GrayFailure {
+            ignore_from,
+            until,
+            ignored: 0,
+        });
+    }
+
+    /// Handles an incoming message delivery event. Returns whether the
+    /// protocol accepted the message. Messages whose tag no protocol on the
+    /// node has are counted as unroutable and dropped. Messages a gray
+    /// failure ignores count as accepted, since the network delivered them.
+    pub fn handle_message(ctx: &mut EngineCtx, env: Envelope) -> bool {
+        let node_id = env.dst;
+        let node = ctx.sim.world.node(node_id);
+        if node.status != NodeStatus::Up {
+            tracing::debug!(node_id, msg_id = %env.msg_id, "Message dropped, node is down");
             // TODO: Increment omission metric
-            return;
+            return false;
         }
+        if Self::gray_ignores(ctx, &env) {
+            tracing::debug!(node_id, msg_id = %env.msg_id, src = env.src, "Message ignored, node is gray-failing");
+            ::metrics::counter!(
+                ftsim_types::metrics::MET_GRAY_FAILURE_IGNORED,
+                ftsim_types::metrics::LBL_NODE => node_id.to_string(),
+                ftsim_types::metrics::LBL_SRC => env.src.to_string()
+            ).increment(1);
+            ctx.sim.telemetry().increment_metric("gray_failure_ignored");
+            if let Some(gray) = &mut ctx.sim.world.node_mut(node_id).gray_failure {
+                gray.ignored += 1;
+            }
+            return true;
+        }
+        let node = ctx.sim.world.node(node_id);
+        let Some(slot) = node.slot(env.proto_tag) else {
+            tracing::warn!(
+                node_id,
+                msg_id = %env.msg_id,
+                tag = env.proto_tag.0,
+                "Message dropped, no protocol has its tag"
+            );
+            ctx.sim.count_unroutable(env.proto_tag, node_id);
+            return false;
+        };
 
         // Dispatch to the protocol.
-        if let Err(e) = self.proto.on_message(ctx, env.src, &env.payload) {
-            tracing::error!(error = %e, "Protocol failed to handle message");
+        let result = Self::dispatch_to(ctx, node_id, slot, |proto, ctx| proto.on_message(ctx, env.src, &env.payload));
+        if let Err(e) = &result {
+            ctx.sim.count_codec_error(env.proto_tag, node_id);
+            // Let the protocol react, e.g. by distrusting the sender
+            let malformed = FaultEvent::MalformedMessage { src: env.src, msg_id: env.msg_id, len: env.payload.len() };
+            Self::dispatch_to(ctx, node_id, slot, |proto, ctx| proto.on_fault(ctx, malformed));
+            match ctx.sim.codec_error_policy() {
+                CodecErrorPolicy::Drop => {
+                    tracing::error!(error = %e, "Protocol failed to handle message");
+                }
+                CodecErrorPolicy::CountAndContinue => {
+                    tracing::warn!(error = %e, src = env.src, dst = node_id, "Protocol failed to handle message");
+                }
+                CodecErrorPolicy::Fail => {
+                    let failure = CodecFailure {
+                        src: env.src,
+                        dst: node_id,
+                        msg_id: env.msg_id,
+                        proto_tag: env.proto_tag,
+                        protocol: ctx.sim.world.node(node_id).infos[slot].name,
+                        payload_hex: env.payload.iter().fold(String::new(), |mut hex, b| {
+                            let _ = write!(hex, "{:02x}", b);
+                            hex
+                        }),
+                        error: e.to_string(),
+                    };
+                    tracing::error!(%failure, "Stopping on codec error");
+                    ctx.sim.halt_on_codec_error(failure);
+                }
+            }
+        }
+        result.is_ok()
+    }
+
+    /// Decides whether a gray failure on the destination drops `env` before
+    /// its protocol sees it.
+    fn gray_ignores(ctx: &mut EngineCtx, env: &Envelope) -> bool {
+        let Some(gray) = ctx.sim.world.node(env.dst).gray_failure(ctx.sim.now()) else {
+            return false;
+        };
+        match gray.ignore_from {
+            GrayFilter::Peers(ref peers) => peers.contains(&env.src),
+            GrayFilter::Probability(p) => bernoulli(&mut ctx.rng("fault.gray_failure"), p),
+        }
+    }
+
+    /// Returns the send failure detection delay of the protocol with `tag`,
+    /// if it has opted in to `on_send_failed`.
+    pub fn send_failure_delay(&self, tag: ProtoTag) -> Option<SimTime> {
+        self.infos
+            .iter()
+            .find(|info| info.tag == tag)
+            .and_then(|info| info.send_failure_delay)
+    }
+
+    /// Reports a failed send to the protocol with `tag` on node `node_id`,
+    /// unless the node crashed since it sent the message.
+    pub fn handle_send_failed(
+        ctx: &mut EngineCtx,
+        node_id: NodeId,
+        incarnation: u64,
+        tag: ProtoTag,
+        dst: NodeId,
+        msg_id: MsgId,
+        reason: SendFailure,
+    ) {
+        let node = ctx.sim.world.node(node_id);
+        if node.status != NodeStatus::Up || node.incarnation() != incarnation {
+            tracing::debug!(node_id, %msg_id, "Send failure ignored, node crashed since the send");
+            return;
+        }
+        if let Some(slot) = node.slot(tag) {
+            Self::dispatch_to(ctx, node_id, slot, |proto, ctx| proto.on_send_failed(ctx, dst, msg_id, reason));
         }
     }
 
     /// Handles a timer firing event.
-    pub fn handle_timer(&mut self, ctx: &mut EngineCtx, timer_id: TimerId) {
-        if self.status != NodeStatus::Up {
-            tracing::debug!(node_id = self.id, %timer_id, "Timer ignored, node is down");
+    pub fn handle_timer(ctx: &mut EngineCtx, node_id: NodeId, timer_id: TimerId) {
+        let node = ctx.sim.world.node_mut(node_id);
+        if node.status != NodeStatus::Up {
+            tracing::debug!(node_id, %timer_id, "Timer ignored, node is down");
             return;
         }
 
-        // Check if the timer is still valid before dispatching.
-        if self.timers.fire_timer(timer_id) {
+        // Check if the timer is still valid, and whose it is, before dispatching.
+        let fired = node
+            .timers
+            .iter_mut()
+            .enumerate()
+            .find_map(|(slot, wheel)| wheel.fire_timer(timer_id).map(|fired| (slot, fired)));
+        if let Some((slot, (timer_id, payload))) = fired {
             ::metrics::counter!(
                 ftsim_types::metrics::MET_TIMER_FIRED,
-                ftsim_types::metrics::LBL_NODE => self.id.to_string()
+                ftsim_types::metrics::LBL_NODE => node_id.to_string()
             ).increment(1);
-            self.proto.on_timer(ctx, timer_id);
+            // Re-arm before dispatching, so the handler can cancel the next firing
+            if let Some(period) = node.timers[slot].period(timer_id) {
+                Self::rearm_timer(ctx, node_id, slot, timer_id, period);
+            }
+            Self::dispatch_to(ctx, node_id, slot, |proto, ctx| match payload {
+                Some(payload) => proto.on_timer_payload(ctx, timer_id, &payload),
+                None => proto.on_timer(ctx, timer_id),
+            });
+        }
+    }
+
+    /// Hands a client operation to the protocols of node `node_id`, in
+    /// order, until one answers. A protocol that answers `Pending` responds
+    /// later with `ProtoCtx::respond_to_client`, unless the node crashes
+    /// first. Operations sent to a down node go unanswered.
+    pub fn handle_client_request(ctx: &mut EngineCtx, node_id: NodeId, op: &ClientOp) {
+        ::metrics::counter!(
+            ftsim_types::metrics::MET_CLIENT_REQUESTS,
+            ftsim_types::metrics::LBL_NODE => node_id.to_string(),
+            ftsim_types::metrics::LBL_KIND => op.kind()
+        ).increment(1);
+        if ctx.sim.world.node(node_id).status != NodeStatus::Up {
+            tracing::debug!(node_id, ?op, "Client request ignored, node is down");
+            return;
+        }
+        let request = ctx.sim.current_event();
+        let pending = PendingClientRequest { node_id, kind: op.kind(), issued_at: ctx.sim.now() };
+        let slots = ctx.sim.world.node(node_id).protos.len();
+        ctx.sim.serving_client = Some(request);
+        let response = (0..slots)
+            .find_map(|slot| Self::dispatch_to(ctx, node_id, slot, |proto, ctx| proto.on_client_request(ctx, op)));
+        ctx.sim.serving_client = None;
+        match response {
+            Some(ClientResponse::Pending) => {
+                ctx.sim.track_pending_client(request, pending);
+            }
+            Some(response) => Self::answer_client(ctx, request, pending, response),
+            None => {}
         }
     }
 
-    /// Applies a fault to the node, changing its state.
-    pub fn apply_fault(&mut self, ctx: &mut EngineCtx, f: FaultEventInternal) {
-        match f {
+    /// Records the answer to client request `request`. The response takes
+    /// effect once the handler's store latency has passed, so its latency
+    /// runs from when the request was issued until then.
+    pub(crate) fn answer_client(
+        ctx: &mut EngineCtx,
+        request: ClientRequestId,
+        pending: PendingClientRequest,
+        response: ClientResponse,
+    ) {
+        let answered_at = match ctx.busy_until() {
+            Ok(answered_at) => answered_at,
+            Err(err) => return ctx.time_overflow("client.response", err),
+        };
+        let latency = answered_at - pending.issued_at;
+        ::metrics::histogram!(ftsim_types::metrics::MET_CLIENT_LATENCY_HISTO).record(latency as f64);
+        ctx.sim.telemetry().record_client_response(answered_at, latency);
+        tracing::debug!(node_id = pending.node_id, %request, ?response, latency, "Client request answered");
+        ctx.sim.telemetry().log_event(
+            "CLIENT_RESPONSE".to_string(),
+            format!("{} {} on node {} answered {:?} after {}ns", pending.kind, request, pending.node_id, response, latency),
+            Some(pending.node_id),
+            EventSeverity::Debug,
+        );
+    }
+
+    /// Applies a fault to node `node_id`, changing its state.
+    pub fn apply_fault(ctx: &mut EngineCtx, node_id: NodeId, f: FaultEventInternal) {
+        let node = ctx.sim.world.node_mut(node_id);
+        let fault = match f {
             FaultEventInternal::Crash { .. } => {
-                self.status = NodeStatus::Down;
-                self.timers.clear(); // Drop all pending timers on crash
-                self.proto.on_fault(ctx, FaultEvent::NodeCrashed);
+                node.status = NodeStatus::Down;
+                let timers: Vec<EventId> = node.timers.iter_mut().flat_map(TimerWheel::clear).collect();
+                node.reassembly.clear();
+                node.store.discard_unsynced(node.meta.get_u64(META_DURABLE_LOG_LEN).unwrap_or(0));
+                // Whatever the node had yet to answer goes unanswered
+                ctx.sim.pending_clients.retain(|_, pending| pending.node_id != node_id);
+                // Drop all pending timers on crash, along with their events
+                for event_id in timers {
+                    ctx.sim.cancel_event(event_id);
+                }
+                ctx.sim.telemetry().record_status(node_id, NodeStatus::Down);
+                FaultEvent::NodeCrashed
             }
             FaultEventInternal::Restart { .. } => {
-                self.status = NodeStatus::Up;
-                // Re-initialize the protocol state
-                self.proto.init(ctx);
-                self.proto.on_fault(ctx, FaultEvent::NodeRecovered);
+                node.status = NodeStatus::Up;
+                let incarnation = node.incarnation() + 1;
+                node.meta.put_u64(META_INCARNATION, incarnation);
+                ctx.sim.telemetry().record_status(node_id, NodeStatus::Up);
+                // Re-initialize the protocol state and rejoin the cluster
+                Self::init(ctx, node_id);
+                Self::start(ctx, node_id);
+                FaultEvent::NodeRecovered
             }
             FaultEventInternal::ClockSkew { skew_ns, .. } => {
-                self.clock_skew_ns = skew_ns;
-                self.proto
-                    .on_fault(ctx, FaultEvent::ClockSkewed { skew_ns });
+                node.clock_skew_ns = skew_ns;
+                FaultEvent::ClockSkewed { skew_ns }
             }
             FaultEventInternal::StoreFault { kind, .. } => {
                 // The store fault model is already updated in sim.rs handle_fault
                 // Now notify the protocol
-                self.proto.on_fault(ctx, FaultEvent::StoreFaulted { kind });
+                FaultEvent::StoreFaulted { kind }
             }
             FaultEventInternal::ByzantineFlip { enabled, .. } => {
-                self.byzantine = enabled;
-                self.proto.on_fault(ctx, FaultEvent::ByzantineEnabled(enabled));
+                node.byzantine = enabled;
+                FaultEvent::ByzantineEnabled(enabled)
             }
+
             // Other faults would be handled here.
-            _ => {}
+            _ => return,
+        };
+        Self::dispatch_all(ctx, node_id, |proto, ctx| proto.on_fault(ctx, fault.clone()));
+    }
+
+    /// Tells node `node_id` that a fault of kind `fault_kind` will hit it at
+    /// sim time `at`. A crashed node is not told, but still finds the fault
+    /// in `pending_maintenance` once it restarts.
+    pub fn announce(ctx: &mut EngineCtx, node_id: NodeId, fault_kind: FaultKind, at: SimTime) {
+        let now = ctx.sim.now();
+        let node = ctx.sim.world.node_mut(node_id);
+        node.announced.retain(|m| m.at > now);
+        let pos = node.announced.partition_point(|m| m.at <= at);
+        node.announced.insert(pos, Maintenance { fault_kind, at });
+        if node.status != NodeStatus::Up {
+            return;
         }
+        let at = ctx.node_clock(node_id, at);
+        Self::dispatch_all(ctx, node_id, |proto, ctx| proto.on_fault(ctx, FaultEvent::Scheduled { fault_kind, at }));
+    }
+
+    /// Returns the faults announced to the node that have not happened by
+    /// sim time `now`, soonest first, at sim times without its clock skew.
+    pub fn pending_maintenance(&self, now: SimTime) -> impl Iterator<Item = Maintenance> + '_ {
+        self.announced.iter().copied().filter(move |m| m.at > now)
     }
 
-    /// Sets a new timer for this node.
-    pub fn set_timer(&mut self, ctx: &mut EngineCtx, after: SimTime) -> TimerId {
-        let fire_at = ctx.sim.now().saturating_add(after);
+    /// Sets a new timer on node `node_id`, holding `payload` until it fires.
+    /// A timer whose deadline overflows `SimTime` is reported and never
+    /// fires.
+    pub fn set_timer(ctx: &mut EngineCtx, node_id: NodeId, after: SimTime, payload: Option<Bytes>) -> TimerId {
         let timer_id = ctx.sim.id_gen.next_timer_id();
+        let fire_at = match ctx.busy_until().and_then(|busy_until| checked_add(busy_until, after)) {
+            Ok(fire_at) => fire_at,
+            Err(err) => {
+                ctx.time_overflow("node.timer", err);
+                return timer_id;
+            }
+        };
+        let event = Event::TimerFired { node_id, timer_id };
+        let event_id = ctx.sim
+            .schedule_at(fire_at, event, EventDiscriminant::timer(node_id));
+        let timers = ctx.sim.world.node_mut(node_id).wheel_mut();
+        match payload {
+            Some(payload) => timers.add_timer_with(timer_id, fire_at, event_id, payload),
+            None => timers.add_timer(timer_id, fire_at, event_id),
+        }
+        timer_id
+    }
+
+    /// Sets a timer on node `node_id` that fires every `period` until
+    /// canceled. A zero period would fire forever without sim time
+    /// advancing, so such a timer fires once.
+    pub fn set_periodic_timer(ctx: &mut EngineCtx, node_id: NodeId, period: SimTime, payload: Option<Bytes>) -> TimerId {
+        let timer_id = Self::set_timer(ctx, node_id, period, payload);
+        if period > 0 {
+            ctx.sim.world.node_mut(node_id).wheel_mut().set_period(timer_id, period);
+        } else {
+            tracing::warn!(node_id, %timer_id, "Periodic timer with a zero period fires once");
+        }
+        timer_id
+    }
+
+    /// Schedules the next firing of a periodic timer of the protocol in
+    /// `slot` that just fired, one `period` after its previous deadline so
+    /// that it does not drift, even if the flood valve held the firing
+    /// back. Deadlines the firing was held back past are skipped. A
+    /// deadline that overflows `SimTime` is reported and ends the timer.
+    fn rearm_timer(ctx: &mut EngineCtx, node_id: NodeId, slot: usize, timer_id: TimerId, period: SimTime) {
+        let timers = &ctx.sim.world.node(node_id).timers[slot];
+        let Some(deadline) = timers.deadline(timer_id) else {
+            return;
+        };
+        let now = ctx.sim.now();
+        let next = checked_add(deadline, period).and_then(|next| {
+            let lag = now.saturating_sub(next);
+            checked_add(next, lag.div_ceil(period) * period)
+        });
+        let deadline = match next {
+            Ok(deadline) => deadline,
+            Err(err) => {
+                ctx.time_overflow("node.periodic_timer", err);
+                ctx.sim.world.node_mut(node_id).timers[slot].cancel_timer(timer_id);
+                return;
+            }
+        };
+        let scheduled_id = ctx.sim.id_gen.next_timer_id();
         let event = Event::TimerFired {
-            node_id: self.id,
-            timer_id,
+            node_id,
+            timer_id: scheduled_id,
         };
-        ctx.sim
-            .schedule_at(fire_at, event, EventDiscriminant::timer(self.id));
-        self.timers.add_timer(timer_id, timer_id); // EventId not needed for cancellation
+        let event_id = ctx.sim.schedule_at(deadline, event, EventDiscriminant::timer(node_id));
+        // The previous event is the one being handled, so it is not canceled
+        ctx.sim.world.node_mut(node_id).timers[slot].reschedule(timer_id, scheduled_id, deadline, event_id);
+    }
+
+    /// Sets a watermark on node `node_id` at absolute sim time `at` (clamped
+    /// to now). See `ProtoCtx::set_watermark` for the ordering guarantee.
+    pub fn set_watermark(ctx: &mut EngineCtx, node_id: NodeId, at: SimTime) -> TimerId {
+        let fire_at = at.max(ctx.sim.now());
+        let timer_id = ctx.sim.id_gen.next_timer_id();
+        let event = Event::TimerFired { node_id, timer_id };
+        let event_id = ctx.sim
+            .schedule_at(fire_at, event, EventDiscriminant::watermark(node_id));
+        ctx.sim.world.node_mut(node_id).wheel_mut().add_watermark(timer_id, fire_at, event_id);
         timer_id
     }
 
-    /// Cancels a pending timer.
-    pub fn cancel_timer(&mut self, timer_id: TimerId) -> bool {
-        self.timers.cancel_timer(timer_id)
+    /// Pushes a pending timer's deadline back by `additional`, keeping its
+    /// ID. Returns `false` if the timer is not pending or the new deadline
+    /// overflows, in which case the old deadline stands.
+    pub fn extend_timer(ctx: &mut EngineCtx, node_id: NodeId, timer_id: TimerId, additional: SimTime) -> bool {
+        let timers = ctx.sim.world.node(node_id).wheel();
+        let (Some(fire_at), is_watermark) = (timers.fire_at(timer_id), timers.is_watermark(timer_id)) else {
+            return false;
+        };
+        let fire_at = match checked_add(fire_at, additional) {
+            Ok(fire_at) => fire_at,
+            Err(err) => {
+                ctx.time_overflow("node.extend_timer", err);
+                return false;
+            }
+        };
+        let scheduled_id = ctx.sim.id_gen.next_timer_id();
+        let event = Event::TimerFired {
+            node_id,
+            timer_id: scheduled_id,
+        };
+        let discriminant = if is_watermark {
+            EventDiscriminant::watermark(node_id)
+        } else {
+            EventDiscriminant::timer(node_id)
+        };
+        let event_id = ctx.sim.schedule_at(fire_at, event, discriminant);
+        let timers = ctx.sim.world.node_mut(node_id).wheel_mut();
+        match timers.reschedule(timer_id, scheduled_id, fire_at, event_id) {
+            Some(stale) => {
+                ctx.sim.cancel_event(stale);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Records that the flood valve held the event carrying timer
+    /// `scheduled_id` back until `fire_at`.
+    pub fn defer_timer(&mut self, scheduled_id: TimerId, fire_at: SimTime) {
+        self.timers.iter_mut().any(|wheel| wheel.defer(scheduled_id, fire_at));
+    }
+
+    /// Returns the time until a pending timer of the protocol whose
+    /// callback runs fires.
+    pub fn timer_remaining(&self, now: SimTime, timer_id: TimerId) -> Option<SimTime> {
+        self.wheel().remaining(timer_id, now)
+    }
+
+    /// Returns the pending timers of the protocol whose callback runs, with
+    /// their remaining time, soonest first.
+    pub fn pending_timers(&self, now: SimTime) -> Vec<(TimerId, SimTime)> {
+        self.wheel().pending(now)
+    }
+
+    /// Cancels a pending timer of the protocol whose callback runs,
+    /// returning the queue ID of its event.
+    pub fn cancel_timer(&mut self, timer_id: TimerId) -> Option<EventId> {
+        self.wheel_mut().cancel_timer(timer_id)
+    }
+
+    /// Returns how many times the node has been restarted.
+    pub fn incarnation(&self) -> u64 {
+        self.meta.get_u64(META_INCARNATION).unwrap_or(0)
+    }
+
+    /// Returns the node's metadata area.
+    pub fn meta(&self) -> &NodeMeta {
+        &self.meta
+    }
+
+    /// Moves the durable boundary in the node's metadata to the end of the
+    /// store's log, once everything written to it so far is durable.
+    pub(crate) fn mark_log_durable(&mut self) {
+        if let Some(len) = self.store.log_len() {
+            self.meta.put_u64(META_DURABLE_LOG_LEN, len);
+        }
+    }
+
+    /// Returns the node's metadata area for the engine to write.
+    pub(crate) fn meta_mut(&mut self) -> &mut NodeMeta {
+        &mut self.meta
     }
 
     /// Returns the list of peers.
@@ -5,61 +5,276 @@
 //! but for simplicity in this initial implementation, we will use a simpler
 //! `FxHashMap` to track active timers. A real implementation would use a more
 //! sophisticated data structure.
+//!
+//! Each protocol-visible `TimerId` maps to the ID carried by the currently
+//! scheduled `TimerFired` event. Extending a timer schedules a new event
+//! under a fresh event-side ID while the protocol keeps its original ID;
+//! events whose ID is no longer mapped are stale and ignored when they fire.
+//!
+//! Every pending timer also remembers the `EventId` of its queued event, and
+//! the operations that make an event stale hand that ID back so the engine
+//! can drop the event from its queue instead of popping it later.
+//!
+//! A timer may carry a protocol-defined payload. It lives in the timer's
+//! entry, so firing, canceling or clearing the timer frees it too.
+//!
+//! A periodic timer stays pending when it fires, and the node re-arms it
+//! with `reschedule` one period after its previous deadline. Only canceling
+//! it or clearing the wheel ends it.
+//!
+//! The flood valve may hold a timer's event back past its deadline. The
+//! wheel then reports the time the event actually fires, while a periodic
+//! timer keeps counting its periods from its deadline.
 
 use crate::prelude::*;
-use fxhash::{FxHashMap, FxHashSet};
+use bytes::Bytes;
+use fxhash::FxHashMap;
+
+/// A timer that has been scheduled and has not yet fired or been canceled.
+#[derive(Debug, Clone)]
+struct PendingTimer {
+    /// The simulation time at which the timer fires.
+    fire_at: SimTime,
+    /// The time the timer was due, before any deferral by the flood valve.
+    deadline: SimTime,
+    /// The ID carried by the scheduled `TimerFired` event.
+    scheduled_id: TimerId,
+    /// The queue ID of the scheduled `TimerFired` event.
+    event_id: EventId,
+    /// Whether the timer is a watermark and must be scheduled as one.
+    watermark: bool,
+    /// Handed to the protocol when the timer fires.
+    payload: Option<Bytes>,
+    /// The interval a periodic timer repeats at.
+    period: Option<SimTime>,
+}
 
 /// Manages timers for a single node.
+#[derive(Clone)]
 pub struct TimerWheel {
-    /// Maps a protocol-visible `TimerId` to a placeholder value.
-    /// We don't need the `EventId` for cancellation with the current strategy.
-    active_timers: FxHashMap<TimerId, TimerId>,
-    /// A set of `TimerId`s that have been canceled but not yet fired.
-    canceled_timers: FxHashSet<TimerId>,
+    /// Pending timers, keyed by their protocol-visible ID.
+    active_timers: FxHashMap<TimerId, PendingTimer>,
+    /// Maps the ID of each live scheduled event back to its protocol-visible ID.
+    scheduled: FxHashMap<TimerId, TimerId>,
 }
 
 impl TimerWheel {
     pub fn new() -> Self {
         Self {
             active_timers: FxHashMap::default(),
-            canceled_timers: FxHashSet::default(),
+            scheduled: FxHashMap::default(),
         }
     }
 
-    /// Adds a new timer to the wheel.
-    pub fn add_timer(&mut self, timer_id: TimerId, event_id: EventId) {
-        self.active_timers.insert(timer_id, event_id);
+    /// Adds a new timer to the wheel. The scheduled event carries `timer_id`
+    /// and was queued as `event_id`.
+    pub fn add_timer(&mut self, timer_id: TimerId, fire_at: SimTime, event_id: EventId) {
+        self.insert(timer_id, fire_at, event_id, false, None);
+    }
+
+    /// Like `add_timer`, for a timer that carries `payload`.
+    pub fn add_timer_with(&mut self, timer_id: TimerId, fire_at: SimTime, event_id: EventId, payload: Bytes) {
+        self.insert(timer_id, fire_at, event_id, false, Some(payload));
+    }
+
+    /// Adds a new watermark to the wheel. The scheduled event carries
+    /// `timer_id` and was queued as `event_id`.
+    pub fn add_watermark(&mut self, timer_id: TimerId, fire_at: SimTime, event_id: EventId) {
+        self.insert(timer_id, fire_at, event_id, true, None);
+    }
+
+    fn insert(&mut self, timer_id: TimerId, fire_at: SimTime, event_id: EventId, watermark: bool, payload: Option<Bytes>) {
+        self.active_timers.insert(
+            timer_id,
+            PendingTimer {
+                fire_at,
+                deadline: fire_at,
+                scheduled_id: timer_id,
+                event_id,
+                watermark,
+                payload,
+                period: None,
+            },
+        );
+        self.scheduled.insert(timer_id, timer_id);
     }
 
-    /// Marks a timer as canceled.
-    pub fn cancel_timer(&mut self, timer_id: TimerId) -> bool {
-        if self.active_timers.contains_key(&timer_id) {
-            self.canceled_timers.insert(timer_id);
-            true
-        } else {
-            false
+    /// Makes a pending timer repeat every `period` once it fires.
+    pub fn set_period(&mut self, timer_id: TimerId, period: SimTime) {
+        if let Some(pending) = self.active_timers.get_mut(&timer_id) {
+            pending.period = Some(period);
         }
     }
 
-    /// Called when a timer event fires. Checks if the timer was canceled.
-    /// Returns `true` if the timer is valid and should be dispatched.
-    pub fn fire_timer(&mut self, timer_id: TimerId) -> bool {
-        self.active_timers.remove(&timer_id);
-        // If the timer was in the canceled set, it's invalid.
-        !self.canceled_timers.remove(&timer_id)
+    /// Returns the period of a pending periodic timer.
+    pub fn period(&self, timer_id: TimerId) -> Option<SimTime> {
+        self.active_timers.get(&timer_id).and_then(|p| p.period)
+    }
+
+    /// Returns whether a pending timer is a watermark.
+    pub fn is_watermark(&self, timer_id: TimerId) -> bool {
+        self.active_timers.get(&timer_id).is_some_and(|p| p.watermark)
+    }
+
+    /// Moves a pending timer to a new event carrying `scheduled_id`, queued
+    /// as `event_id`, that fires at `fire_at`. Returns the queue ID of the
+    /// previously scheduled event, which is now stale, or `None` if the timer
+    /// is not pending.
+    pub fn reschedule(
+        &mut self,
+        timer_id: TimerId,
+        scheduled_id: TimerId,
+        fire_at: SimTime,
+        event_id: EventId,
+    ) -> Option<EventId> {
+        let pending = self.active_timers.get_mut(&timer_id)?;
+        self.scheduled.remove(&pending.scheduled_id);
+        pending.fire_at = fire_at;
+        pending.deadline = fire_at;
+        pending.scheduled_id = scheduled_id;
+        self.scheduled.insert(scheduled_id, timer_id);
+        Some(std::mem::replace(&mut pending.event_id, event_id))
+    }
+
+    /// Records that the event carrying `scheduled_id` was held back until
+    /// `fire_at`. Returns `false` if the event is stale.
+    pub fn defer(&mut self, scheduled_id: TimerId, fire_at: SimTime) -> bool {
+        let Some(pending) = self.scheduled.get(&scheduled_id).and_then(|id| self.active_timers.get_mut(id)) else {
+            return false;
+        };
+        pending.fire_at = fire_at;
+        true
+    }
+
+    /// Returns the fire time of a pending timer.
+    pub fn fire_at(&self, timer_id: TimerId) -> Option<SimTime> {
+        self.active_timers.get(&timer_id).map(|p| p.fire_at)
     }
 
-    /// Clears all pending timers, e.g., on a node crash.
-    pub fn clear(&mut self) {
-        // In a real system with event cancellation, we would unschedule events here.
-        // For now, we just clear our internal tracking. The events will still
-        // fire but will be ignored by `fire_timer`.
-        self.active_timers.clear();
-        self.canceled_timers.clear();
+    /// Returns the time a pending timer was due, which its event may fire
+    /// after if the flood valve deferred it.
+    pub fn deadline(&self, timer_id: TimerId) -> Option<SimTime> {
+        self.active_timers.get(&timer_id).map(|p| p.deadline)
     }
 
-    /// Returns the number of active (not canceled) timers.
+    /// Returns the time left until a pending timer fires, measured from `now`.
+    pub fn remaining(&self, timer_id: TimerId, now: SimTime) -> Option<SimTime> {
+        self.fire_at(timer_id).map(|at| at.saturating_sub(now))
+    }
+
+    /// Returns all pending timers with their remaining time, soonest first.
+    pub fn pending(&self, now: SimTime) -> Vec<(TimerId, SimTime)> {
+        let mut pending: Vec<_> = self
+            .active_timers
+            .iter()
+            .map(|(id, p)| (*id, p.fire_at.saturating_sub(now)))
+            .collect();
+        pending.sort_by_key(|&(id, remaining)| (remaining, id));
+        pending
+    }
+
+    /// Cancels a pending timer, returning the queue ID of its now stale
+    /// event, or `None` if the timer is not pending.
+    pub fn cancel_timer(&mut self, timer_id: TimerId) -> Option<EventId> {
+        let pending = self.active_timers.remove(&timer_id)?;
+        self.scheduled.remove(&pending.scheduled_id);
+        Some(pending.event_id)
+    }
+
+    /// Called when a timer event fires. Returns the protocol-visible ID to
+    /// dispatch and the timer's payload, or `None` if the event is stale
+    /// (canceled, rescheduled, or cleared by a crash). A periodic timer stays
+    /// pending, with no event scheduled until it is re-armed.
+    pub fn fire_timer(&mut self, scheduled_id: TimerId) -> Option<(TimerId, Option<Bytes>)> {
+        let timer_id = self.scheduled.remove(&scheduled_id)?;
+        let payload = match self.active_timers.get(&timer_id) {
+            Some(pending) if pending.period.is_some() => pending.payload.clone(),
+            _ => self.active_timers.remove(&timer_id).and_then(|p| p.payload),
+        };
+        Some((timer_id, payload))
+    }
+
+    /// Clears all pending timers, e.g., on a node crash. Returns the queue
+    /// IDs of their scheduled events, in timer order.
+    pub fn clear(&mut self) -> Vec<EventId> {
+        let mut events: Vec<(TimerId, EventId)> =
+            self.active_timers.drain().map(|(id, p)| (id, p.event_id)).collect();
+        events.sort_unstable();
+        self.scheduled.clear();
+        events.into_iter().map(|(_, event_id)| event_id).collect()
+    }
+
+    /// Returns the number of pending timers.
     pub fn active_timers(&self) -> usize {
-        self.active_timers.len() - self.canceled_timers.len()
+        self.active_timers.len()
+    }
+
+    /// Returns the total size of the payloads held for pending timers.
+    pub fn payload_bytes(&self) -> usize {
+        self.active_timers
+            .values()
+            .filter_map(|p| p.payload.as_ref())
+            .map(Bytes::len)
+            .sum()
+    }
+}
+
+impl Default for TimerWheel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remaining_after_partial_elapse() {
+        let mut wheel = TimerWheel::new();
+        wheel.add_timer(TimerId(1), 1_000, EventId(10));
+        wheel.add_timer(TimerId(2), 400, EventId(11));
+        assert_eq!(wheel.remaining(TimerId(1), 0), Some(1_000));
+        assert_eq!(wheel.remaining(TimerId(1), 250), Some(750));
+        assert_eq!(wheel.pending(250), vec![(TimerId(2), 150), (TimerId(1), 750)]);
+
+        assert_eq!(wheel.fire_timer(TimerId(2)), Some((TimerId(2), None)));
+        assert_eq!(wheel.remaining(TimerId(2), 400), None);
+        assert_eq!(wheel.cancel_timer(TimerId(1)), Some(EventId(10)));
+        assert_eq!(wheel.cancel_timer(TimerId(1)), None);
+        assert_eq!(wheel.remaining(TimerId(1), 400), None);
+        assert_eq!(wheel.fire_timer(TimerId(1)), None);
+    }
+
+    #[test]
+    fn test_reschedule_preserves_protocol_id() {
+        let mut wheel = TimerWheel::new();
+        wheel.add_timer(TimerId(1), 100, EventId(10));
+        assert_eq!(wheel.reschedule(TimerId(1), TimerId(9), 300, EventId(12)), Some(EventId(10)));
+        assert_eq!(wheel.remaining(TimerId(1), 50), Some(250));
+
+        // The original event is stale; the new one dispatches the original ID.
+        assert_eq!(wheel.fire_timer(TimerId(1)), None);
+        assert_eq!(wheel.fire_timer(TimerId(9)), Some((TimerId(1), None)));
+        assert_eq!(wheel.active_timers(), 0);
+        assert_eq!(wheel.reschedule(TimerId(1), TimerId(13), 400, EventId(14)), None);
+    }
+
+    #[test]
+    fn test_payloads_are_freed_with_their_timers() {
+        let mut wheel = TimerWheel::new();
+        wheel.add_timer_with(TimerId(1), 100, EventId(10), Bytes::from_static(b"election"));
+        wheel.add_timer_with(TimerId(2), 200, EventId(11), Bytes::from_static(b"beat"));
+        wheel.add_timer_with(TimerId(3), 300, EventId(12), Bytes::from_static(b"gc"));
+        assert_eq!(wheel.payload_bytes(), 14);
+
+        assert_eq!(wheel.cancel_timer(TimerId(2)), Some(EventId(11)));
+        assert_eq!(wheel.payload_bytes(), 10);
+        // A rescheduled timer keeps its payload
+        wheel.reschedule(TimerId(1), TimerId(4), 150, EventId(13));
+        assert_eq!(wheel.fire_timer(TimerId(4)), Some((TimerId(1), Some(Bytes::from_static(b"election")))));
+        assert_eq!(wheel.payload_bytes(), 2);
+        wheel.clear();
+        assert_eq!(wheel.payload_bytes(), 0);
     }
 }
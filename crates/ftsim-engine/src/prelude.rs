@@ -5,17 +5,22 @@
 //! workspace that depend on the engine.
 
 pub use crate::{
+    effective_config::EffectiveConfig,
     events::{Event, EventDiscriminant, Queued},
     net::{Net, NetLink},
     node::{Node, NodeStatus},
-    sim::Simulation,
+    report::SimulationReport,
+    sim::{SimState, Simulation, SimulationOutcome},
     store::{FaultyStoreView, MemStore, Store, StoreFaultModel, StoreView},
-    telemetry::{snapshot::Snapshot, TelemetryBus},
+    telemetry::{
+        snapshot::{EventSeverity, Snapshot},
+        TelemetryBus,
+    },
     world::World,
 };
 
 pub use ftsim_types::{
-    self, config::*, envelope::*, errors::*, id::*, metrics::*, scenario::*, time::*, topology::*,
+    self, client::*, config::*, envelope::*, errors::*, id::*, metrics::*, scenario::*, time::*, topology::*,
 };
 
 pub use ftsim_proto::{self, api::*, ctx_ext::*, FaultEvent, Protocol, ProtocolDyn};
@@ -0,0 +1,167 @@
+//! # ftsim-engine::report
+//!
+//! Defines `SimulationReport`, the structured summary returned by
+//! `Simulation::run` and `Simulation::run_until`. It carries everything a
+//! caller needs to judge a run without reaching into the telemetry bus, and
+//! serializes to JSON for `ftsim run --report-json`.
+
+use crate::conditions::ConditionOutcome;
+use crate::convergence::ConvergenceReport;
+use crate::interventions::Intervention;
+use crate::prelude::*;
+use crate::slo::SloReport;
+use crate::telemetry::snapshot::{MetricSample, MetricsSnapshot, StoreSnap};
+use crate::usage::ResourceUsage;
+use indexmap::IndexMap;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// The KV failure detectors, such as `protocols::failure_detector`, publish
+/// the nodes they suspect under, as a comma-separated list of ids.
+pub const SUSPECTS_KEY: &str = "suspects";
+
+/// The state of a run when `run` or `run_until` returned.
+#[derive(Serialize, Debug, Clone)]
+pub struct SimulationReport {
+    pub outcome: SimulationOutcome,
+    pub final_time: SimTime,
+    pub events_processed: u64,
+    /// Executed events by kind (`deliver`, `timer`, `fault`, `client`,
+    /// `send_failed`, `ui_tick`).
+    pub events_by_kind: BTreeMap<&'static str, u64>,
+    pub nodes: Vec<NodeReport>,
+    pub metrics: MetricsSnapshot,
+    /// RNG draws per call site, from the recorder.
+    pub rng_draws: BTreeMap<String, u64>,
+    /// The interventions scenario directives armed, and what each hit.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub interventions: Vec<Intervention>,
+    /// The events the flood valve deferred, per node. Non-empty marks a
+    /// run whose timing the valve changed.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub flood_deferrals: BTreeMap<NodeId, u64>,
+    /// Each condition's state at the end of the run, with the time it was
+    /// satisfied or failed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<ConditionOutcome>,
+    /// How client request latency did against the scenario's SLO.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slo: Option<SloReport>,
+    /// How far the nodes agree on the state hash anti-entropy protocols
+    /// publish under `convergence::REPLICA_HASH_KEY`, if any node published one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub convergence: Option<ConvergenceReport>,
+    /// What the run cost, when the caller attached it. `Simulation::report`
+    /// leaves it out so that reports stay deterministic.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<ResourceUsage>,
+}
+
+/// A node's final state.
+#[derive(Serialize, Debug, Clone)]
+pub struct NodeReport {
+    pub id: NodeId,
+    pub status: NodeStatus,
+    pub byzantine: bool,
+    pub incarnation: u64,
+    /// The node's engine-managed metadata, e.g. its `uuid`.
+    pub meta: BTreeMap<String, String>,
+    /// The protocol-specific KVs the node last published, e.g. `role`.
+    pub custom: IndexMap<String, Value>,
+    /// The retained samples of each numeric metric, e.g. `term`, oldest
+    /// first.
+    pub metrics: IndexMap<String, Vec<MetricSample>>,
+    pub store: Option<StoreSnap>,
+}
+
+impl SimulationReport {
+    /// Returns a published KV of a node as a string, if it is one.
+    pub fn node_kv(&self, node: NodeId, key: &str) -> Option<&str> {
+        self.nodes.get(node as usize)?.custom.get(key)?.as_str()
+    }
+
+    /// Returns a field of the state a node published with
+    /// `Ctx::publish_state`.
+    pub fn node_state(&self, node: NodeId, field: &str) -> Option<&Value> {
+        self.nodes.get(node as usize)?.custom.get(STATE_KEY)?.get(field)
+    }
+
+    /// Returns the latest value of a node's numeric metric.
+    pub fn node_metric(&self, node: NodeId, key: &str) -> Option<f64> {
+        self.nodes.get(node as usize)?.metrics.get(key)?.last().map(|s| s.value)
+    }
+
+    /// Compares the views of the failure detectors on the up nodes that
+    /// publish `SUSPECTS_KEY`.
+    pub fn suspicions(&self) -> SuspicionReport {
+        let views: BTreeMap<NodeId, BTreeSet<NodeId>> = self
+            .nodes
+            .iter()
+            .filter(|n| n.status == NodeStatus::Up)
+            .filter_map(|n| {
+                let list = n.custom.get(SUSPECTS_KEY)?.as_str()?;
+                Some((n.id, list.split(',').filter_map(|id| id.trim().parse().ok()).collect()))
+            })
+            .collect();
+        let disagreements = self
+            .nodes
+            .iter()
+            .filter_map(|subject| {
+                let (suspected_by, trusted_by): (Vec<NodeId>, Vec<NodeId>) = views
+                    .keys()
+                    .copied()
+                    .filter(|&observer| observer != subject.id)
+                    .partition(|observer| views[observer].contains(&subject.id));
+                (!suspected_by.is_empty() && !trusted_by.is_empty()).then_some(Disagreement {
+                    node: subject.id,
+                    suspected_by,
+                    trusted_by,
+                })
+            })
+            .collect();
+        SuspicionReport { views, disagreements }
+    }
+}
+
+/// How the failure detectors of different nodes see each other, from
+/// `SimulationReport::suspicions`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SuspicionReport {
+    /// The nodes each reporting node suspects.
+    pub views: BTreeMap<NodeId, BTreeSet<NodeId>>,
+    /// The nodes some detectors suspect and others do not, in id order.
+    pub disagreements: Vec<Disagreement>,
+}
+
+/// A node the failure detectors disagree about. A detector that does not
+/// watch the node, e.g. for lack of a link to it, counts as trusting it.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Disagreement {
+    pub node: NodeId,
+    pub suspected_by: Vec<NodeId>,
+    pub trusted_by: Vec<NodeId>,
+}
+
+impl SuspicionReport {
+    /// Returns whether `observer`'s detector suspects `node`.
+    pub fn suspects(&self, observer: NodeId, node: NodeId) -> bool {
+        self.views.get(&observer).is_some_and(|suspects| suspects.contains(&node))
+    }
+}
+
+impl fmt::Display for SuspicionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failure detectors", self.views.len())?;
+        if self.disagreements.is_empty() {
+            return write!(f, ", all in agreement");
+        }
+        for d in &self.disagreements {
+            write!(f, "\n  node {} suspected by {:?}, trusted by {:?}", d.node, d.suspected_by, d.trusted_by)?;
+        }
+        Ok(())
+    }
+}
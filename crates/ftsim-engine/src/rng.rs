@@ -1,70 +1,836 @@
 //! # ftsim-engine::rng
 //!
-//! Defines the discipline for using the master Random Number Generator.
-//! The `RngDiscipline` wrapper ensures that every use of the RNG is
-//! associated with a site label and recorded for auditing.
+//! Defines the discipline for using the simulation's Random Number
+//! Generators. The `RngDiscipline` wrapper ensures that every use of an RNG
+//! is associated with a call site and recorded for auditing.
+//!
+//! Determinism model: draws do not share one master stream. The seed is
+//! forked into independent `RngStream`s: one for the network, one for the
+//! engine itself, and one per node for each of its protocol and its store.
+//! A stream's sequence depends only on the seed and on the draws made from
+//! that stream, so an extra `rng_u64()` in node 2's protocol leaves node 3's
+//! protocol, every store and the network untouched. Runs are still
+//! reproducible from the seed alone, but traces recorded before streams
+//! were forked do not replay.
+//!
+//! Call sites are interned by the `Recorder` as `SiteId`s, keyed by a static
+//! category such as `store.fsync` and optionally the node the draw is made
+//! for. A site's label, e.g. `store.fsync.node[3]`, is built once, when the
+//! site is first seen.
+//!
+//! The `Recorder` can additionally capture an `EventTrace` of every executed
+//! event and the RNG draws made while handling it, or check a new execution
+//! against a previously captured trace, event by event. Independently, it can
+//! capture an `RngRecording` of every value drawn, in order, and check a new
+//! execution against it draw by draw, which also catches runs that draw as
+//! often as the recording but hand the values to different call sites.
 
-use rand::RngCore;
+use crate::{prelude::*, state_hash::StateHasher};
+use rand::{RngCore, SeedableRng};
 use rand_chacha::ChaCha20Rng;
-use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use fxhash::FxHashMap;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+/// An independent RNG stream forked from the seed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RngStream {
+    /// Link drops, delays and duplicates.
+    Net,
+    /// Draws not made for any one node.
+    Engine,
+    /// A node's protocol, through `rng_u64`.
+    Proto(NodeId),
+    /// A node's store faults and latency.
+    Store(NodeId),
+}
+
+impl RngStream {
+    /// Returns the stream draws at the site `category` on `node` come from.
+    fn for_site(category: &'static str, node: Option<NodeId>) -> Self {
+        match (category.split('.').next(), node) {
+            (Some("net"), _) => Self::Net,
+            (Some("store"), Some(node)) => Self::Store(node),
+            (_, Some(node)) => Self::Proto(node),
+            (_, None) => Self::Engine,
+        }
+    }
+}
+
+impl fmt::Display for RngStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Net => write!(f, "net"),
+            Self::Engine => write!(f, "engine"),
+            Self::Proto(node) => write!(f, "proto.node[{}]", node),
+            Self::Store(node) => write!(f, "store.node[{}]", node),
+        }
+    }
+}
+
+/// The RNG streams of one simulation, each forked from the seed the first
+/// time it is drawn from.
+#[derive(Clone)]
+pub struct RngStreams {
+    seed: u64,
+    streams: BTreeMap<RngStream, ChaCha20Rng>,
+}
+
+impl RngStreams {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            streams: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the RNG of `stream`, seeded from a hash of the master seed
+    /// and the stream's identity.
+    pub fn get(&mut self, stream: RngStream) -> &mut ChaCha20Rng {
+        let seed = self.seed;
+        self.streams.entry(stream).or_insert_with(|| {
+            let mut hasher = StateHasher::new();
+            hasher.write_u64(seed);
+            stream.hash(&mut hasher);
+            ChaCha20Rng::seed_from_u64(hasher.finish())
+        })
+    }
 
-/// A wrapper around the master RNG to enforce recording of its usage.
+    /// Feeds every stream's identity and position into a state hash.
+    pub fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_usize(self.streams.len());
+        for (stream, rng) in &self.streams {
+            stream.hash(hasher);
+            hasher.write_u128(rng.get_word_pos());
+        }
+    }
+}
+
+/// An interned RNG call site, only meaningful to the `Recorder` that
+/// issued it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SiteId(u32);
+
+/// Every call site a recorder has seen, with its label and draw count.
+#[derive(Default)]
+struct SiteTable {
+    ids: FxHashMap<(&'static str, Option<NodeId>), SiteId>,
+    labels: Vec<String>,
+    streams: Vec<RngStream>,
+    counts: Vec<u64>,
+}
+
+impl SiteTable {
+    fn intern(&mut self, category: &'static str, node: Option<NodeId>) -> SiteId {
+        if let Some(id) = self.ids.get(&(category, node)) {
+            return *id;
+        }
+        let id = SiteId(self.labels.len() as u32);
+        self.labels.push(match node {
+            Some(node) => format!("{}.node[{}]", category, node),
+            None => category.to_string(),
+        });
+        self.streams.push(RngStream::for_site(category, node));
+        self.counts.push(0);
+        self.ids.insert((category, node), id);
+        id
+    }
+}
+
+/// A wrapper around the RNG stream of a call site to enforce recording of
+/// its usage.
 pub struct RngDiscipline<'a> {
     rng: &'a mut ChaCha20Rng,
     recorder: &'a mut Recorder,
-    site_label: &'static str,
+    site: SiteId,
+    stream: RngStream,
 }
 
 impl<'a> RngDiscipline<'a> {
-    pub fn new(
-        rng: &'a mut ChaCha20Rng,
-        recorder: &'a mut Recorder,
-        site_label: &'static str,
-    ) -> Self {
+    pub fn new(streams: &'a mut RngStreams, recorder: &'a mut Recorder, site: SiteId) -> Self {
+        let stream = recorder.stream(site);
         Self {
-            rng,
+            rng: streams.get(stream),
             recorder,
-            site_label,
+            site,
+            stream,
         }
     }
+
+    /// Returns the stream this RNG draws from.
+    pub fn stream(&self) -> RngStream {
+        self.stream
+    }
 }
 
 /// Delegate the `RngCore` trait to the inner RNG, but record each call.
 impl<'a> RngCore for RngDiscipline<'a> {
     fn next_u32(&mut self) -> u32 {
-        self.recorder.record_draw(self.site_label);
-        self.rng.next_u32()
+        let value = self.rng.next_u32();
+        self.recorder.record_draw(self.site, value as u64);
+        value
     }
     fn next_u64(&mut self) -> u64 {
-        self.recorder.record_draw(self.site_label);
-        self.rng.next_u64()
+        let value = self.rng.next_u64();
+        self.recorder.record_draw(self.site, value);
+        value
     }
     fn fill_bytes(&mut self, dest: &mut [u8]) {
-        self.recorder.record_draw(self.site_label);
-        self.rng.fill_bytes(dest)
+        self.rng.fill_bytes(dest);
+        self.recorder.record_draw(self.site, bytes_value(dest));
     }
     fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
-        self.recorder.record_draw(self.site_label);
-        self.rng.try_fill_bytes(dest)
+        self.rng.try_fill_bytes(dest)?;
+        self.recorder.record_draw(self.site, bytes_value(dest));
+        Ok(())
+    }
+}
+
+/// 2^64, exactly representable as an `f64`.
+const TWO_POW_64: f64 = 18_446_744_073_709_551_616.0;
+
+/// Performs a Bernoulli trial: true with probability `p`. The probability
+/// becomes an integer threshold that one drawn `u64` is compared against,
+/// so the outcome involves no float arithmetic beyond scaling `p` by a
+/// power of two, which is exact, and one truncating cast, which rounds the
+/// same way on every platform. A `p` of 1 or more is true without a draw,
+/// and one of 0 or less, or NaN, never is. This is the same scheme as
+/// `rand`'s `gen_bool`, spelled out here so that a change in `rand` cannot
+/// silently change which trials succeed.
+pub fn bernoulli<R: RngCore + ?Sized>(rng: &mut R, p: f64) -> bool {
+    if p >= 1.0 {
+        return true;
+    }
+    let threshold = if p > 0.0 { (p * TWO_POW_64) as u64 } else { 0 };
+    rng.next_u64() < threshold
+}
+
+/// Condenses the bytes of a `fill_bytes` draw into one recorded value.
+fn bytes_value(bytes: &[u8]) -> u64 {
+    let mut hasher = StateHasher::new();
+    hasher.write_bytes(bytes);
+    hasher.finish()
+}
+
+/// One value drawn from an RNG.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RngDraw {
+    pub site: String,
+    /// The number of draws made at `site` before this one.
+    pub ordinal: u64,
+    /// The value returned; a hash of the bytes for `fill_bytes`.
+    pub value: u64,
+}
+
+impl fmt::Display for RngDraw {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} draw #{} = {:#018x}", self.site, self.ordinal, self.value)
+    }
+}
+
+/// Every value drawn during a run, in the order drawn.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RngRecording {
+    pub seed: u64,
+    pub draws: Vec<RngDraw>,
+}
+
+impl RngRecording {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            draws: Vec::new(),
+        }
+    }
+
+    #[cfg(feature = "trace-export")]
+    pub fn write_to<W: std::io::Write>(&self, out: W) -> std::io::Result<()> {
+        serde_json::to_writer(out, self).map_err(std::io::Error::from)
+    }
+
+    #[cfg(feature = "trace-export")]
+    pub fn read_from<R: std::io::Read>(input: R) -> std::io::Result<Self> {
+        serde_json::from_reader(input).map_err(std::io::Error::from)
+    }
+}
+
+/// The first draw at which a run differs from an `RngRecording`. A side is
+/// `None` when that run had made no more draws.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RngMismatch {
+    pub index: usize,
+    pub expected: Option<RngDraw>,
+    pub actual: Option<RngDraw>,
+}
+
+impl fmt::Display for RngMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side = |draw: &Option<RngDraw>| match draw {
+            Some(draw) => draw.to_string(),
+            None => "<no more draws>".to_string(),
+        };
+        writeln!(f, "RNG draws diverged at draw #{}", self.index)?;
+        writeln!(f, "  recorded: {}", side(&self.expected))?;
+        write!(f, "  replayed: {}", side(&self.actual))
+    }
+}
+
+/// Where a site's draws stand: how many it has made, a digest of every value
+/// drawn so far and the latest value.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SitePosition {
+    pub draws: u64,
+    pub digest: u64,
+    pub last: u64,
+}
+
+impl SitePosition {
+    fn record(&mut self, value: u64) {
+        let mut hasher = StateHasher::new();
+        hasher.write_u64(self.digest);
+        hasher.write_u64(value);
+        self.draws += 1;
+        self.digest = hasher.finish();
+        self.last = value;
+    }
+}
+
+impl fmt::Display for SitePosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} draws, last {:#018x}", self.draws, self.last)
+    }
+}
+
+/// The positions of the sites an event drew from, as of the end of the
+/// event. Sites it did not draw from keep their previous position.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PositionEntry {
+    /// `None` for draws made outside any event, e.g. while protocols start.
+    pub event_id: Option<EventId>,
+    pub time: SimTime,
+    pub kind: String,
+    pub sites: Vec<(String, SitePosition)>,
+}
+
+/// Per-event RNG draw positions of a run, delta-encoded: each entry lists
+/// only the sites that were drawn from since the previous entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DrawPositions {
+    pub entries: Vec<PositionEntry>,
+}
+
+/// The first event at which two runs' draws differ, in number or value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionDivergence {
+    /// The index of the entry in both runs; one run may have ended.
+    pub index: usize,
+    pub left: Option<PositionEntry>,
+    pub right: Option<PositionEntry>,
+    /// The sites whose positions differ after the entry, with the position
+    /// in each run; `None` if the site has not drawn in that run.
+    pub sites: Vec<(String, Option<SitePosition>, Option<SitePosition>)>,
+}
+
+impl DrawPositions {
+    #[cfg(feature = "trace-export")]
+    pub fn write_jsonl<W: std::io::Write>(&self, mut out: W) -> std::io::Result<()> {
+        for entry in &self.entries {
+            serde_json::to_writer(&mut out, entry)?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()
+    }
+
+    #[cfg(feature = "trace-export")]
+    pub fn read_jsonl<R: std::io::BufRead>(input: R) -> std::io::Result<Self> {
+        let mut entries = Vec::new();
+        for line in input.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    /// Returns, for each entry, the position of every site after it hashed
+    /// together with the entry's event id.
+    fn prefix_hashes(&self) -> Vec<u64> {
+        let mut positions: BTreeMap<&str, SitePosition> = BTreeMap::new();
+        self.entries
+            .iter()
+            .map(|entry| {
+                for (site, position) in &entry.sites {
+                    positions.insert(site, *position);
+                }
+                let mut hasher = StateHasher::new();
+                entry.event_id.hash(&mut hasher);
+                for (site, position) in &positions {
+                    hasher.write_bytes(site.as_bytes());
+                    hasher.write_u64(position.draws);
+                    hasher.write_u64(position.digest);
+                }
+                hasher.finish()
+            })
+            .collect()
+    }
+
+    /// Returns the position of every site after entry `index`.
+    fn positions_at(&self, index: usize) -> BTreeMap<String, SitePosition> {
+        let mut positions = BTreeMap::new();
+        for entry in self.entries.iter().take(index + 1) {
+            for (site, position) in &entry.sites {
+                positions.insert(site.clone(), *position);
+            }
+        }
+        positions
+    }
+
+    /// Finds the first entry after which the two runs' draws, or the events
+    /// they belong to, differ. Each site's digest chains every value it
+    /// drew, so once two runs diverge they stay diverged and the entries can
+    /// be bisected.
+    pub fn first_divergence(&self, other: &DrawPositions) -> Option<PositionDivergence> {
+        let (left, right) = (self.prefix_hashes(), other.prefix_hashes());
+        let common = left.len().min(right.len());
+        let (mut lo, mut hi) = (0, common);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if left[mid] == right[mid] {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        if lo == common && left.len() == right.len() {
+            return None;
+        }
+        let (left_positions, right_positions) = (self.positions_at(lo), other.positions_at(lo));
+        let mut names: Vec<&String> = left_positions.keys().chain(right_positions.keys()).collect();
+        names.sort();
+        names.dedup();
+        let sites = names
+            .into_iter()
+            .filter_map(|site| {
+                let (l, r) = (left_positions.get(site).copied(), right_positions.get(site).copied());
+                (l != r).then(|| (site.clone(), l, r))
+            })
+            .collect();
+        Some(PositionDivergence {
+            index: lo,
+            left: self.entries.get(lo).cloned(),
+            right: other.entries.get(lo).cloned(),
+            sites,
+        })
+    }
+}
+
+/// Collects `DrawPositions` as events execute.
+#[derive(Default)]
+struct PositionLog {
+    positions: DrawPositions,
+    /// The event currently executing.
+    event: Option<(EventId, SimTime, String)>,
+    /// Sites drawn from since the last entry.
+    dirty: Vec<SiteId>,
+    /// The position of every site, by id.
+    sites: Vec<SitePosition>,
+}
+
+/// Checks draws against a recording as they are made.
+struct RngCheck {
+    expected: RngRecording,
+    cursor: usize,
+    mismatch: Option<RngMismatch>,
+}
+
+/// One executed event and the RNG draws made while handling it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub event_id: EventId,
+    pub time: SimTime,
+    /// A short description of the event, e.g. `deliver 0->1 msg 7`.
+    pub kind: String,
+    /// The scheduling discriminant, as `(kind, node)`.
+    pub discriminant: (u8, NodeId),
+    /// RNG draws in order, with consecutive draws at one site merged.
+    pub draws: Vec<(String, u32)>,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "event {} at {}ns: {} (discriminant {:?}), draws [",
+            self.event_id, self.time, self.kind, self.discriminant
+        )?;
+        for (i, (site, count)) in self.draws.iter().enumerate() {
+            let sep = if i == 0 { "" } else { ", " };
+            write!(f, "{}{} x{}", sep, site, count)?;
+        }
+        write!(f, "]")
     }
 }
 
+/// The ordered trace of a whole run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EventTrace {
+    pub seed: u64,
+    pub events: Vec<TraceEntry>,
+}
+
+impl EventTrace {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            events: Vec::new(),
+        }
+    }
+
+    #[cfg(feature = "trace-export")]
+    pub fn write_to<W: std::io::Write>(&self, out: W) -> std::io::Result<()> {
+        serde_json::to_writer(out, self).map_err(std::io::Error::from)
+    }
+
+    #[cfg(feature = "trace-export")]
+    pub fn read_from<R: std::io::Read>(input: R) -> std::io::Result<Self> {
+        serde_json::from_reader(input).map_err(std::io::Error::from)
+    }
+}
+
+/// The first point at which a replayed execution differs from its trace.
+/// A side is `None` when that execution had already ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub index: usize,
+    pub expected: Option<TraceEntry>,
+    pub actual: Option<TraceEntry>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side = |entry: &Option<TraceEntry>| match entry {
+            Some(entry) => entry.to_string(),
+            None => "<end of run>".to_string(),
+        };
+        writeln!(f, "replay diverged at event #{}", self.index)?;
+        writeln!(f, "  recorded: {}", side(&self.expected))?;
+        write!(f, "  replayed: {}", side(&self.actual))
+    }
+}
+
+/// Checks events against a recorded trace as they execute.
+struct Replay {
+    expected: EventTrace,
+    cursor: usize,
+    divergence: Option<Divergence>,
+}
+
 /// Records all deterministic decisions made during a simulation.
 pub struct Recorder {
     seed: u64,
-    rng_sites: BTreeMap<&'static str, u64>,
+    sites: SiteTable,
+    /// The trace being captured, if recording is enabled.
+    trace: Option<EventTrace>,
+    /// The trace being checked against, if replaying.
+    replay: Option<Replay>,
+    /// The event currently executing, while recording or replaying.
+    current: Option<TraceEntry>,
+    /// The values drawn so far, if value recording is enabled.
+    values: Option<RngRecording>,
+    /// The recording draws are checked against, if verifying.
+    check: Option<RngCheck>,
+    /// Per-event draw positions, if enabled.
+    positions: Option<PositionLog>,
 }
 
 impl Recorder {
     pub fn new(seed: u64) -> Self {
         Self {
             seed,
-            rng_sites: BTreeMap::new(),
+            sites: SiteTable::default(),
+            trace: None,
+            replay: None,
+            current: None,
+            values: None,
+            check: None,
+            positions: None,
         }
     }
 
-    /// Records that a random number was drawn at a specific site.
-    pub fn record_draw(&mut self, site_label: &'static str) {
-        *self.rng_sites.entry(site_label).or_insert(0) += 1;
+    /// Starts recording the position of each site at the end of every event
+    /// that draws.
+    pub fn enable_positions(&mut self) {
+        self.positions.get_or_insert_with(PositionLog::default);
+    }
+
+    /// Returns the recorded draw positions, if enabled.
+    pub fn positions(&self) -> Option<&DrawPositions> {
+        self.positions.as_ref().map(|p| &p.positions)
+    }
+
+    /// Appends an entry for the sites drawn from since the last one.
+    fn flush_positions(&mut self, event: Option<(EventId, SimTime, String)>) {
+        let Some(log) = &mut self.positions else {
+            return;
+        };
+        if log.dirty.is_empty() {
+            return;
+        }
+        let (event_id, time, kind) = match event {
+            Some((id, time, kind)) => (Some(id), time, kind),
+            None => (None, 0, "outside events".to_string()),
+        };
+        let sites = log
+            .dirty
+            .drain(..)
+            .map(|site| (self.sites.labels[site.0 as usize].clone(), log.sites[site.0 as usize]))
+            .collect();
+        log.positions.entries.push(PositionEntry {
+            event_id,
+            time,
+            kind,
+            sites,
+        });
+    }
+
+    /// Starts recording the value of every draw.
+    pub fn enable_rng_recording(&mut self) {
+        self.values.get_or_insert_with(|| RngRecording::new(self.seed));
+    }
+
+    /// Returns the recorded draws, if value recording is enabled.
+    pub fn rng_recording(&self) -> Option<&RngRecording> {
+        self.values.as_ref()
+    }
+
+    /// Checks every following draw against `expected`.
+    pub fn start_rng_check(&mut self, expected: RngRecording) {
+        self.check = Some(RngCheck {
+            expected,
+            cursor: 0,
+            mismatch: None,
+        });
+    }
+
+    /// Returns the number of draws in the recording being checked against.
+    pub fn rng_check_len(&self) -> Option<usize> {
+        self.check.as_ref().map(|c| c.expected.draws.len())
+    }
+
+    /// Returns the number of recorded draws not yet checked, or `None` if
+    /// not checking.
+    pub fn rng_check_remaining(&self) -> Option<usize> {
+        self.check
+            .as_ref()
+            .map(|c| c.expected.draws.len().saturating_sub(c.cursor))
+    }
+
+    /// Returns the first mismatching draw found so far.
+    pub fn rng_mismatch(&self) -> Option<&RngMismatch> {
+        self.check.as_ref()?.mismatch.as_ref()
+    }
+
+    /// Ends a check, reporting a mismatch if the run stopped before making
+    /// every recorded draw.
+    pub fn finish_rng_check(&mut self) -> Option<RngMismatch> {
+        let check = self.check.take()?;
+        if check.mismatch.is_some() {
+            return check.mismatch;
+        }
+        check.expected.draws.get(check.cursor).map(|expected| RngMismatch {
+            index: check.cursor,
+            expected: Some(expected.clone()),
+            actual: None,
+        })
+    }
+
+    /// Starts capturing an event trace.
+    pub fn enable_trace(&mut self) {
+        self.trace.get_or_insert_with(|| EventTrace::new(self.seed));
+    }
+
+    /// Returns the captured trace, if recording is enabled.
+    pub fn trace(&self) -> Option<&EventTrace> {
+        self.trace.as_ref()
+    }
+
+    /// Checks every following event against `expected`.
+    pub fn start_replay(&mut self, expected: EventTrace) {
+        self.replay = Some(Replay {
+            expected,
+            cursor: 0,
+            divergence: None,
+        });
+    }
+
+    /// Returns the number of recorded events not yet replayed, or `None` if
+    /// not replaying.
+    pub fn replay_remaining(&self) -> Option<usize> {
+        self.replay
+            .as_ref()
+            .map(|r| r.expected.events.len().saturating_sub(r.cursor))
+    }
+
+    /// Returns the first divergence found so far.
+    pub fn divergence(&self) -> Option<&Divergence> {
+        self.replay.as_ref()?.divergence.as_ref()
+    }
+
+    /// Ends a replay, reporting a divergence if the replayed run stopped
+    /// before executing every recorded event.
+    pub fn finish_replay(&mut self) -> Option<Divergence> {
+        let replay = self.replay.take()?;
+        if replay.divergence.is_some() {
+            return replay.divergence;
+        }
+        replay.expected.events.get(replay.cursor).map(|expected| Divergence {
+            index: replay.cursor,
+            expected: Some(expected.clone()),
+            actual: None,
+        })
+    }
+
+    /// Marks the start of an event's execution.
+    pub fn begin_event(&mut self, event_id: EventId, time: SimTime, kind: String, discriminant: (u8, NodeId)) {
+        if self.positions.is_some() {
+            // Draws made before the first event get an entry of their own
+            self.flush_positions(None);
+            if let Some(log) = &mut self.positions {
+                log.event = Some((event_id, time, kind.clone()));
+            }
+        }
+        if self.trace.is_some() || self.replay.is_some() {
+            self.current = Some(TraceEntry {
+                event_id,
+                time,
+                kind,
+                discriminant,
+                draws: Vec::new(),
+            });
+        }
+    }
+
+    /// Marks the end of the current event's execution. When replaying,
+    /// returns `false` once the execution has diverged from the trace.
+    pub fn end_event(&mut self) -> bool {
+        if let Some(event) = self.positions.as_mut().and_then(|log| log.event.take()) {
+            self.flush_positions(Some(event));
+        }
+        let Some(entry) = self.current.take() else {
+            return true;
+        };
+        if let Some(replay) = &mut self.replay {
+            if replay.divergence.is_none() {
+                let expected = replay.expected.events.get(replay.cursor);
+                if expected != Some(&entry) {
+                    replay.divergence = Some(Divergence {
+                        index: replay.cursor,
+                        expected: expected.cloned(),
+                        actual: Some(entry.clone()),
+                    });
+                }
+                replay.cursor += 1;
+            }
+        }
+        if let Some(trace) = &mut self.trace {
+            trace.events.push(entry);
+        }
+        self.divergence().is_none()
+    }
+
+    /// Returns the seed this recorder was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns the number of RNG draws made at each call site, by label.
+    pub fn draw_counts(&self) -> BTreeMap<String, u64> {
+        let sites = &self.sites;
+        // Sites are interned when an RNG is handed out, which may not draw
+        sites
+            .labels
+            .iter()
+            .zip(&sites.counts)
+            .filter(|(_, count)| **count > 0)
+            .map(|(label, count)| (label.clone(), *count))
+            .collect()
+    }
+
+    /// Returns the id of the call site `category`, qualified by `node` if
+    /// the draw is made for one node.
+    pub fn site(&mut self, category: &'static str, node: Option<NodeId>) -> SiteId {
+        self.sites.intern(category, node)
+    }
+
+    /// Returns the stream the call site draws from.
+    pub fn stream(&self, site: SiteId) -> RngStream {
+        self.sites.streams[site.0 as usize]
+    }
+
+    /// Returns the number of RNG draws made from each stream.
+    pub fn stream_draw_counts(&self) -> BTreeMap<RngStream, u64> {
+        let mut counts = BTreeMap::new();
+        for (stream, count) in self.sites.streams.iter().zip(&self.sites.counts) {
+            if *count > 0 {
+                *counts.entry(*stream).or_insert(0) += count;
+            }
+        }
+        counts
+    }
+
+    /// Returns the number of distinct call sites seen so far.
+    pub fn site_count(&self) -> usize {
+        self.sites.labels.len()
+    }
+
+    /// Records that `value` was drawn at a specific site.
+    pub fn record_draw(&mut self, site: SiteId, value: u64) {
+        let ordinal = self.sites.counts[site.0 as usize];
+        self.sites.counts[site.0 as usize] += 1;
+        if let Some(log) = &mut self.positions {
+            if !log.dirty.contains(&site) {
+                log.dirty.push(site);
+            }
+            let index = site.0 as usize;
+            if log.sites.len() <= index {
+                log.sites.resize(index + 1, SitePosition { draws: 0, digest: 0, last: 0 });
+            }
+            log.sites[index].record(value);
+        }
+        if self.values.is_some() || self.check.is_some() {
+            let draw = RngDraw {
+                site: self.sites.labels[site.0 as usize].clone(),
+                ordinal,
+                value,
+            };
+            if let Some(check) = &mut self.check {
+                if check.mismatch.is_none() {
+                    let expected = check.expected.draws.get(check.cursor);
+                    if expected != Some(&draw) {
+                        check.mismatch = Some(RngMismatch {
+                            index: check.cursor,
+                            expected: expected.cloned(),
+                            actual: Some(draw.clone()),
+                        });
+                    }
+                    check.cursor += 1;
+                }
+            }
+            if let Some(values) = &mut self.values {
+                values.draws.push(draw);
+            }
+        }
+        if let Some(current) = &mut self.current {
+            let label = &self.sites.labels[site.0 as usize];
+            match current.draws.last_mut() {
+                Some((last, count)) if last == label => *count += 1,
+                _ => current.draws.push((label.clone(), 1)),
+            }
+        }
     }
 }
@@ -5,31 +5,113 @@
 
 use crate::{
     events::{Event, EventDiscriminant, FaultEventInternal, LinkModelChange},
+    expr::{self, Expr},
+    interventions::{Intervention, InterventionKind},
+    invariants,
     prelude::*,
     sim::Simulation,
+    telemetry::snapshot::{NodeSnap, Snapshot},
 };
 
-/// Schedules a scenario's directives in the simulation.
+/// Registers the built-in invariants a scenario names, failing on unknown
+/// names.
+pub fn register_invariants(sim: &mut Simulation, scenario: &Scenario) -> anyhow::Result<()> {
+    for name in &scenario.invariants {
+        let invariant = invariants::builtin(name).ok_or_else(|| {
+            anyhow::anyhow!(
+                "unknown invariant '{}'; expected one of {}",
+                name,
+                invariants::BUILTIN_INVARIANTS.join(", ")
+            )
+        })?;
+        sim.add_invariant(invariant);
+    }
+    if let Some(every) = scenario.invariant_check_every {
+        sim.set_invariant_interval(every);
+    }
+    Ok(())
+}
+
+/// Parses every phase expression of a scenario. With an `expr_schema`,
+/// each is also checked to read only the names it declares.
+pub fn check_expressions(scenario: &Scenario) -> anyhow::Result<()> {
+    let schema = scenario.expr_schema.as_ref().map(|declared| expr::Schema {
+        nodes: scenario.initial.nodes,
+        kvs: declared.kvs.iter().cloned().collect(),
+        metrics: declared.metrics.iter().cloned().collect(),
+        oracles: declared.oracles.iter().chain(&scenario.invariants).cloned().collect(),
+    });
+    for phase in &scenario.phases {
+        for check in &phase.expect {
+            let PhaseCheck::Expr(source) = check else {
+                continue;
+            };
+            let parsed = match &schema {
+                Some(schema) => Expr::parse_strict(source, schema),
+                None => Expr::parse(source),
+            };
+            parsed.map_err(|e| {
+                anyhow::anyhow!("Phase '{}' has an invalid expression:\n{}", phase.name, e.render(source))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Schedules a scenario's directives in the simulation, sets its cache
+/// limits and arms its SLO and restart policy, if it has them. Fails,
+/// scheduling nothing, if the scenario fails `Scenario::validate`, which
+/// checks every delay it will sample, or a phase expression does not parse.
 pub fn load_and_schedule(sim: &mut Simulation, scenario: &Scenario) -> anyhow::Result<()> {
+    scenario.validate().map_err(anyhow::Error::msg)?;
+    check_expressions(scenario)?;
+    sim.telemetry().set_slo(scenario.slo);
+    sim.set_restart_policy(scenario.restart_policy);
+    sim.set_caches(scenario.caches);
     let mut relative_time_base = 0;
-    for directive in &scenario.directives {
-        match directive {
-            Directive::At(time, action) => {
-                schedule(sim, *time, action.clone());
+    for DirectiveSpec { directive, label, announce_before } in &scenario.directives {
+        // Every run of the action, by time
+        let runs = match directive {
+            Directive::At(time, _) => vec![*time],
+            Directive::After { offset, .. } => {
+                relative_time_base = checked_add(relative_time_base, *offset)?;
+                vec![relative_time_base]
             }
-            Directive::After { offset, action } => {
-                relative_time_base += offset;
-                schedule(sim, relative_time_base, action.clone());
+            Directive::Every { period, repeats, .. } => (0..*repeats)
+                .map(|i| checked_add(relative_time_base, checked_mul(*period, i as u128)?))
+                .collect::<Result<_, _>>()?,
+        };
+        let action = directive.action();
+        let mut scheduled = Vec::new();
+        for (i, time) in runs.into_iter().enumerate() {
+            if let (Some(lead), Some(fault_kind)) = (announce_before, action.fault_kind()) {
+                // Scheduled first, so that an announcement made at the
+                // fault's own time still comes before it
+                // A run sooner than the lead is announced right away
+                let announce_at = time.checked_sub(*lead).map_or(sim.now(), |at| at.max(sim.now()));
+                let nodes = match action.node_id() {
+                    Some(node) => vec![node],
+                    None => (0..scenario.initial.nodes as NodeId).collect(),
+                };
+                for node_id in nodes {
+                    let ev = Event::Announce { node_id, fault_kind, at: time };
+                    sim.schedule_at(announce_at, ev, EventDiscriminant::fault());
+                }
             }
-            Directive::Every {
-                period,
-                repeats,
-                action,
-            } => {
-                for i in 0..*repeats {
-                    let time = relative_time_base + (i as u128 * *period);
-                    schedule(sim, time, action.clone());
+            let id = match (directive, action_to_event(action.clone())) {
+                // A workload keeps the protocols busy, so it is not idle
+                (Directive::Every { .. }, Event::ClientRequest { node_id, op }) => {
+                    let ev = Event::ClientRequest { node_id, op: op.instantiate(i as u64) };
+                    sim.schedule_at(time, ev, EventDiscriminant::fault())
                 }
+                (Directive::Every { .. }, ev) => sim.schedule_at(time, ev, EventDiscriminant::periodic_fault()),
+                (_, ev) => sim.schedule_at(time, ev, EventDiscriminant::fault()),
+            };
+            scheduled.push(id);
+        }
+        if let Some(label) = label {
+            for id in scheduled {
+                sim.label_event(id, label.clone());
             }
         }
     }
@@ -37,13 +119,87 @@ pub fn load_and_schedule(sim: &mut Simulation, scenario: &Scenario) -> anyhow::R
     Ok(())
 }
 
-fn schedule(sim: &mut Simulation, when: SimTime, action: Action) {
-    let ev = Event::Fault(action_to_internal(action));
-    sim.schedule_at(when, ev, EventDiscriminant::fault());
+/// Evaluates a phase's expectations against the world as it is now,
+/// returning a description of every check that does not hold.
+pub fn check_phase(sim: &Simulation, phase: &Phase) -> Vec<String> {
+    let snapshot = sim.telemetry().build_snapshot(sim.world(), sim.now());
+    let up = snapshot.nodes.iter().filter(|n| n.status == NodeStatus::Up);
+    let mut failures = Vec::new();
+    for check in &phase.expect {
+        match check {
+            PhaseCheck::LeaderExists => {
+                let leader = up.clone().any(|n| {
+                    n.custom
+                        .get("role")
+                        .and_then(|v| v.as_str())
+                        .is_some_and(|r| r.eq_ignore_ascii_case("leader") || r.eq_ignore_ascii_case("primary"))
+                });
+                if !leader {
+                    failures.push("no up node is leader".to_string());
+                }
+            }
+            PhaseCheck::MinAvailability(min) => {
+                let available = up.clone().count() as f64 / snapshot.nodes.len().max(1) as f64;
+                if available < *min {
+                    failures.push(format!("availability {:.2} is below {:.2}", available, min));
+                }
+            }
+            PhaseCheck::Metric { key, node, op, value } => {
+                let holds = |n: &NodeSnap| n.metric(key).is_some_and(|v| op.holds(v, *value));
+                let met = match node {
+                    Some(node) => snapshot.nodes.get(*node as usize).is_some_and(holds),
+                    None => up.clone().any(holds),
+                };
+                if !met {
+                    let on = node.map_or("any up node".to_string(), |n| format!("node {}", n));
+                    failures.push(format!("metric '{}' is not {} {} on {}", key, op.as_str(), value, on));
+                }
+            }
+            PhaseCheck::Expr(source) => match Expr::parse(source) {
+                Ok(expr) if expr.holds(&PhaseView { snapshot: &snapshot, sim }) => {}
+                Ok(_) => failures.push(format!("`{}` does not hold", source)),
+                Err(e) => failures.push(format!("`{}` is invalid: {}", source, e)),
+            },
+        }
+    }
+    failures
 }
 
-fn action_to_internal(action: Action) -> FaultEventInternal {
-    match action {
+/// The world at the end of a phase, as an expression reads it. An
+/// invariant holds unless it is the one that stopped the run.
+struct PhaseView<'a> {
+    snapshot: &'a Snapshot,
+    sim: &'a Simulation,
+}
+
+impl expr::Context for PhaseView<'_> {
+    fn num_nodes(&self) -> usize {
+        self.snapshot.nodes.len()
+    }
+
+    fn kv(&self, node: NodeId, key: &str) -> Option<expr::Value> {
+        self.snapshot.nodes.get(node as usize)?.custom.get(key).map(expr::Value::from_json)
+    }
+
+    fn metric(&self, node: NodeId, key: &str) -> Option<f64> {
+        self.snapshot.nodes.get(node as usize)?.metric(key)
+    }
+
+    fn status(&self, node: NodeId) -> Option<NodeStatus> {
+        self.snapshot.nodes.get(node as usize).map(|n| n.status)
+    }
+
+    fn oracle(&self, name: &str) -> Option<bool> {
+        let registered = self.sim.invariant_names().any(|n| n == name);
+        registered.then(|| self.sim.invariant_violation().map_or(true, |v| v.invariant != name))
+    }
+}
+
+/// Converts an action to the event that carries it out: a client request,
+/// or a fault.
+fn action_to_event(action: Action) -> Event {
+    let fault = match action {
+        Action::ClientRequest { node, op } => return Event::ClientRequest { node_id: node, op },
         Action::Crash { node, duration } => FaultEventInternal::Crash {
             node_id: node,
             duration,
@@ -63,6 +219,10 @@ fn action_to_internal(action: Action) -> FaultEventInternal {
             link_id: link,
             change: LinkModelChange::SetDrop(p),
         },
+        Action::LinkDuplicate { link, p } => FaultEventInternal::LinkModelUpdate {
+            link_id: link,
+            change: LinkModelChange::SetDuplicate(p),
+        },
         Action::BroadcastBytes { payload_hex, proto_tag } => FaultEventInternal::BroadcastBytes {
             payload_hex,
             proto_tag,
@@ -76,6 +236,18 @@ fn action_to_internal(action: Action) -> FaultEventInternal {
             node_id: node,
             enabled,
         },
+        Action::GrayFailure { node, ignore_from, duration } => FaultEventInternal::GrayFailure {
+            node_id: node,
+            ignore_from,
+            duration,
+        },
         Action::Custom { name, args } => FaultEventInternal::Custom { name, args },
-    }
+        Action::DropNth { src, dst, variant, n } => {
+            FaultEventInternal::Intervene(Intervention::new(src, dst, variant, n, InterventionKind::Drop))
+        }
+        Action::DelayNth { src, dst, variant, n, by } => {
+            FaultEventInternal::Intervene(Intervention::new(src, dst, variant, n, InterventionKind::Delay(by)))
+        }
+    };
+    Event::Fault(fault)
 }
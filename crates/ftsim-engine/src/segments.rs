@@ -0,0 +1,588 @@
+//! # ftsim-engine::segments
+//!
+//! Size-rotated JSONL outputs for long runs. A `SegmentWriter` given
+//! `messages.jsonl` writes `messages-0001.jsonl`, `messages-0002.jsonl` and
+//! so on, starting a new segment only between two records, and keeps a
+//! `messages.manifest.json` next to them that lists each segment's record
+//! count, size and sim-time range. Retention limits delete the oldest
+//! segments once there are too many or they take too much space, so a soak
+//! run stays within a disk budget however long it is.
+//!
+//! `SegmentSet` reads a rotated output back through its manifest, opening
+//! only the segments that overlap a time range when given one, and
+//! `open_input` reads either a rotated output or a single file, so the tools
+//! consuming outputs need not care which was written.
+//!
+//! A `.gz` or `.zst` extension asks for a gzip or zstd compressed output,
+//! and the segments of a rotated one are compressed one by one. The codecs
+//! are built with the `compression` feature; without it such paths are
+//! rejected up front rather than written uncompressed under a misleading
+//! name.
+
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+const MANIFEST_SUFFIX: &str = ".manifest.json";
+
+/// When a rotated output starts a new segment, and which old ones it keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RotationPolicy {
+    /// Start a new segment before a record that would take the current one
+    /// past this many bytes, counted before compression. A segment always
+    /// holds at least one record.
+    pub max_segment_bytes: u64,
+    /// Keep at most this many segments, deleting the oldest.
+    pub keep_segments: Option<usize>,
+    /// Keep at most this many bytes of segments, deleting the oldest. The
+    /// segment being written counts at its full size.
+    pub keep_bytes: Option<u64>,
+}
+
+impl RotationPolicy {
+    /// Rotates every `max_segment_bytes` and keeps every segment.
+    pub fn new(max_segment_bytes: u64) -> Self {
+        Self { max_segment_bytes, keep_segments: None, keep_bytes: None }
+    }
+}
+
+/// One segment file, as listed in the manifest.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SegmentInfo {
+    /// The file name, in the manifest's directory.
+    pub file: String,
+    pub records: u64,
+    /// The bytes written, before compression.
+    pub bytes: u64,
+    /// The earliest and latest sim time of the segment's records; `None`
+    /// while it has none.
+    pub first_time: Option<SimTime>,
+    pub last_time: Option<SimTime>,
+}
+
+impl SegmentInfo {
+    /// Returns whether the segment holds a record from `from` to `to`,
+    /// inclusive.
+    pub fn overlaps(&self, from: SimTime, to: SimTime) -> bool {
+        match (self.first_time, self.last_time) {
+            (Some(first), Some(last)) => first <= to && last >= from,
+            _ => false,
+        }
+    }
+}
+
+/// The segments of a rotated output, written next to them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// The segments on disk, oldest first. The last one is being written
+    /// until the writer finishes.
+    pub segments: Vec<SegmentInfo>,
+    /// How many older segments, and how many records in them, retention
+    /// deleted.
+    pub dropped_segments: u64,
+    pub dropped_records: u64,
+}
+
+impl Manifest {
+    pub fn records(&self) -> u64 {
+        self.segments.iter().map(|s| s.records).sum()
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.segments.iter().map(|s| s.bytes).sum()
+    }
+}
+
+/// Returns the manifest path of a rotated output written to `path`:
+/// `messages.manifest.json` for `messages.jsonl`.
+pub fn manifest_path(path: &Path) -> PathBuf {
+    let (stem, _) = split_name(path);
+    path.with_file_name(format!("{}{}", stem, MANIFEST_SUFFIX))
+}
+
+/// Returns the path of segment `seq` of a rotated output written to
+/// `path`: `messages-0001.jsonl` for `messages.jsonl`.
+fn segment_path(path: &Path, seq: u64) -> PathBuf {
+    let (stem, extension) = split_name(path);
+    path.with_file_name(format!("{}-{:04}{}", stem, seq, extension))
+}
+
+/// Splits a file name at its first dot, so that `events.jsonl.zst` keeps
+/// both extensions.
+fn split_name(path: &Path) -> (String, String) {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    match name.find('.') {
+        Some(dot) if dot > 0 => (name[..dot].to_string(), name[dot..].to_string()),
+        _ => (name, String::new()),
+    }
+}
+
+/// How an output is compressed, as the extension of its path asks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// `.gz` asks for gzip and `.zst` for zstd.
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Self::Gzip,
+            Some("zst") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+}
+
+/// Fails for a path whose extension asks for a compression codec this
+/// build lacks, so that a run can refuse it before it starts rather than
+/// when writing out.
+pub fn check_compression(path: &Path) -> io::Result<()> {
+    let codec = match Compression::of(path) {
+        Compression::None => return Ok(()),
+        _ if cfg!(feature = "compression") => return Ok(()),
+        Compression::Gzip => "gzip",
+        Compression::Zstd => "zstd",
+    };
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{}: {} compression needs a build with the `compression` feature", path.display(), codec),
+    ))
+}
+
+/// An output being written, compressed as its path asks. Call `finish`
+/// once written: a compressed output dropped unfinished is cut short.
+pub struct Output(Encoder);
+
+enum Encoder {
+    Plain(BufWriter<File>),
+    #[cfg(feature = "compression")]
+    Gzip(BufWriter<flate2::write::GzEncoder<File>>),
+    #[cfg(feature = "compression")]
+    Zstd(BufWriter<zstd::Encoder<'static, File>>),
+}
+
+impl Output {
+    /// Flushes the output and ends its compressed stream.
+    pub fn finish(self) -> io::Result<()> {
+        match self.0 {
+            Encoder::Plain(mut out) => out.flush(),
+            #[cfg(feature = "compression")]
+            Encoder::Gzip(out) => out.into_inner().map_err(io::IntoInnerError::into_error)?.finish().map(drop),
+            #[cfg(feature = "compression")]
+            Encoder::Zstd(out) => out.into_inner().map_err(io::IntoInnerError::into_error)?.finish().map(drop),
+        }
+    }
+
+    fn inner(&mut self) -> &mut dyn Write {
+        match &mut self.0 {
+            Encoder::Plain(out) => out,
+            #[cfg(feature = "compression")]
+            Encoder::Gzip(out) => out,
+            #[cfg(feature = "compression")]
+            Encoder::Zstd(out) => out,
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner().write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.inner().write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner().flush()
+    }
+}
+
+/// Creates `path` for writing an output, compressed as its extension asks.
+pub fn create_output(path: &Path) -> io::Result<Output> {
+    check_compression(path)?;
+    let file = File::create(path)?;
+    Ok(Output(match Compression::of(path) {
+        #[cfg(feature = "compression")]
+        Compression::Gzip => {
+            Encoder::Gzip(BufWriter::new(flate2::write::GzEncoder::new(file, flate2::Compression::default())))
+        }
+        #[cfg(feature = "compression")]
+        Compression::Zstd => Encoder::Zstd(BufWriter::new(zstd::Encoder::new(file, 0)?)),
+        _ => Encoder::Plain(BufWriter::new(file)),
+    }))
+}
+
+/// Opens the file at `path`, decompressing it as its extension asks.
+fn open_file(path: &Path) -> io::Result<Box<dyn Read>> {
+    check_compression(path)?;
+    let file = File::open(path)?;
+    Ok(match Compression::of(path) {
+        #[cfg(feature = "compression")]
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(file)),
+        #[cfg(feature = "compression")]
+        Compression::Zstd => Box::new(zstd::Decoder::new(file)?),
+        _ => Box::new(file),
+    })
+}
+
+/// Opens the output written to `path`, rotated or not. Of a rotated output,
+/// only the segments overlapping `range` are read, if given; the records
+/// of those segments outside it are still returned.
+pub fn open_input(path: &Path, range: Option<(SimTime, SimTime)>) -> io::Result<Box<dyn BufRead>> {
+    if let Some(set) = SegmentSet::open(path)? {
+        return Ok(Box::new(BufReader::new(set.reader(range))));
+    }
+    Ok(Box::new(BufReader::new(open_file(path)?)))
+}
+
+fn read_manifest(path: &Path) -> io::Result<Manifest> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+/// Writes records as JSON lines into size-rotated segments.
+pub struct SegmentWriter {
+    path: PathBuf,
+    policy: RotationPolicy,
+    /// A line repeated at the start of every segment, so each reads on its
+    /// own.
+    header: Option<Vec<u8>>,
+    manifest: Manifest,
+    next_seq: u64,
+    writer: Output,
+    line: Vec<u8>,
+}
+
+impl SegmentWriter {
+    /// Starts a rotated output at `path`, deleting the segments an earlier
+    /// one there listed in its manifest.
+    pub fn create(path: impl Into<PathBuf>, policy: RotationPolicy) -> io::Result<Self> {
+        let path = path.into();
+        check_compression(&path)?;
+        if let Ok(old) = read_manifest(&manifest_path(&path)) {
+            for segment in old.segments {
+                match fs::remove_file(path.with_file_name(&segment.file)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+        }
+        let first = segment_path(&path, 1);
+        let writer = Self {
+            writer: create_output(&first)?,
+            manifest: Manifest { segments: vec![new_segment(&first)], ..Manifest::default() },
+            path,
+            policy,
+            header: None,
+            next_seq: 2,
+            line: Vec::new(),
+        };
+        writer.write_manifest()?;
+        Ok(writer)
+    }
+
+    /// Writes `header` at the start of the current segment and of every one
+    /// after it. Call before appending records.
+    pub fn with_header(mut self, header: &impl Serialize) -> io::Result<Self> {
+        let mut line = serde_json::to_vec(header)?;
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+        self.current().bytes += line.len() as u64;
+        self.header = Some(line);
+        Ok(self)
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    fn current(&mut self) -> &mut SegmentInfo {
+        self.manifest.segments.last_mut().expect("a segment is always open")
+    }
+
+    /// Appends a record that happened at `time`, starting a new segment
+    /// first if the current one is full.
+    pub fn append(&mut self, time: SimTime, record: &impl Serialize) -> io::Result<()> {
+        self.line.clear();
+        serde_json::to_writer(&mut self.line, record)?;
+        self.line.push(b'\n');
+        let len = self.line.len() as u64;
+        let current = self.current();
+        if current.records > 0 && current.bytes + len > self.policy.max_segment_bytes {
+            self.rotate()?;
+        }
+        self.writer.write_all(&self.line)?;
+        let current = self.current();
+        current.records += 1;
+        current.bytes += len;
+        current.first_time = Some(current.first_time.map_or(time, |t| t.min(time)));
+        current.last_time = Some(current.last_time.map_or(time, |t| t.max(time)));
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let path = segment_path(&self.path, self.next_seq);
+        self.next_seq += 1;
+        std::mem::replace(&mut self.writer, create_output(&path)?).finish()?;
+        self.manifest.segments.push(new_segment(&path));
+        if let Some(header) = &self.header {
+            self.writer.write_all(header)?;
+            let len = header.len() as u64;
+            self.current().bytes += len;
+        }
+
+        let max_segment_bytes = self.policy.max_segment_bytes;
+        while self.manifest.segments.len() > 1 {
+            let segments = &self.manifest.segments;
+            let closed_bytes: u64 = segments[..segments.len() - 1].iter().map(|s| s.bytes).sum();
+            let too_many = self.policy.keep_segments.is_some_and(|keep| segments.len() > keep.max(1));
+            let too_big = self.policy.keep_bytes.is_some_and(|keep| closed_bytes + max_segment_bytes > keep);
+            if !too_many && !too_big {
+                break;
+            }
+            let oldest = self.manifest.segments.remove(0);
+            fs::remove_file(self.path.with_file_name(&oldest.file))?;
+            self.manifest.dropped_segments += 1;
+            self.manifest.dropped_records += oldest.records;
+        }
+        self.write_manifest()
+    }
+
+    fn write_manifest(&self) -> io::Result<()> {
+        write_manifest(&self.path, &self.manifest)
+    }
+
+    /// Writes out the current segment and the final manifest.
+    pub fn finish(self) -> io::Result<Manifest> {
+        self.writer.finish()?;
+        write_manifest(&self.path, &self.manifest)?;
+        Ok(self.manifest)
+    }
+}
+
+/// Replaces the manifest of the output written to `path` in one rename, so
+/// a reader never sees half of one.
+fn write_manifest(path: &Path, manifest: &Manifest) -> io::Result<()> {
+    let path = manifest_path(path);
+    let staged = path.with_extension("json.tmp");
+    fs::write(&staged, serde_json::to_vec_pretty(manifest)?)?;
+    fs::rename(staged, path)
+}
+
+fn new_segment(path: &Path) -> SegmentInfo {
+    SegmentInfo {
+        file: path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+        records: 0,
+        bytes: 0,
+        first_time: None,
+        last_time: None,
+    }
+}
+
+/// A rotated output, read through its manifest.
+#[derive(Debug, Clone)]
+pub struct SegmentSet {
+    dir: PathBuf,
+    manifest: Manifest,
+}
+
+impl SegmentSet {
+    /// Opens the rotated output written to `path`, or whose manifest `path`
+    /// is. Returns `None` if there is no manifest.
+    pub fn open(path: &Path) -> io::Result<Option<Self>> {
+        let is_manifest = path.file_name().is_some_and(|n| n.to_string_lossy().ends_with(MANIFEST_SUFFIX));
+        let manifest = if is_manifest { path.to_path_buf() } else { manifest_path(path) };
+        if !manifest.is_file() {
+            return Ok(None);
+        }
+        let dir = manifest.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(Some(Self { dir, manifest: read_manifest(&manifest)? }))
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Returns the segments holding a record from `from` to `to`, inclusive.
+    pub fn segments_between(&self, from: SimTime, to: SimTime) -> Vec<&SegmentInfo> {
+        self.manifest.segments.iter().filter(|s| s.overlaps(from, to)).collect()
+    }
+
+    /// Reads the segments overlapping `range`, or all of them, oldest first
+    /// as one stream. A segment deleted since the manifest was read reads
+    /// as empty.
+    pub fn reader(&self, range: Option<(SimTime, SimTime)>) -> SegmentReader {
+        let segments: Vec<&SegmentInfo> = match range {
+            Some((from, to)) => self.segments_between(from, to),
+            None => self.manifest.segments.iter().collect(),
+        };
+        SegmentReader { files: segments.iter().map(|s| self.dir.join(&s.file)).collect(), current: None }
+    }
+}
+
+/// The segments of a `SegmentSet`, read back to back. Every segment ends
+/// with a complete line, so lines never span two of them.
+pub struct SegmentReader {
+    files: VecDeque<PathBuf>,
+    current: Option<Box<dyn Read>>,
+}
+
+impl Read for SegmentReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(file) = &mut self.current {
+                let n = file.read(buf)?;
+                if n > 0 || buf.is_empty() {
+                    return Ok(n);
+                }
+                self.current = None;
+            }
+            let Some(path) = self.files.pop_front() else {
+                return Ok(0);
+            };
+            match open_file(&path) {
+                Ok(file) => self.current = Some(file),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Record {
+        time: SimTime,
+        text: String,
+    }
+
+    fn record(i: u64) -> Record {
+        Record { time: i as SimTime * 1_000, text: format!("record {} with some padding to give it a size", i) }
+    }
+
+    fn output_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ftsim-segments-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(path: &Path, policy: RotationPolicy, records: std::ops::Range<u64>) -> Manifest {
+        let mut writer = SegmentWriter::create(path, policy).unwrap();
+        for i in records {
+            let record = record(i);
+            writer.append(record.time, &record).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn read(path: &Path, range: Option<(SimTime, SimTime)>) -> Vec<Record> {
+        open_input(path, range).unwrap().lines().map(|line| serde_json::from_str(&line.unwrap()).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_segments_rotate_between_records_and_match_the_manifest() {
+        let dir = output_dir("rotate");
+        let path = dir.join("messages.jsonl");
+        let manifest = write(&path, RotationPolicy::new(4 * 1024), 0..1_000);
+
+        assert!(manifest.segments.len() > 5, "{} segments", manifest.segments.len());
+        assert_eq!(manifest.segments[0].file, "messages-0001.jsonl");
+        assert_eq!(manifest.records(), 1_000);
+        assert_eq!(SegmentSet::open(&manifest_path(&path)).unwrap().unwrap().manifest(), &manifest);
+        let mut next: SimTime = 0;
+        for segment in &manifest.segments {
+            let contents = fs::read_to_string(dir.join(&segment.file)).unwrap();
+            assert_eq!(contents.len() as u64, segment.bytes);
+            assert!(segment.bytes <= 4 * 1024);
+            // Every segment holds whole records, in order
+            let times: Vec<SimTime> =
+                contents.lines().map(|line| serde_json::from_str::<Record>(line).unwrap().time).collect();
+            assert_eq!(times.len() as u64, segment.records);
+            assert_eq!(segment.first_time, Some(next * 1_000));
+            assert_eq!(segment.last_time, times.last().copied());
+            next += segment.records as SimTime;
+        }
+        assert_eq!(read(&path, None), (0..1_000).map(record).collect::<Vec<_>>());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_range_reads_only_open_overlapping_segments() {
+        let dir = output_dir("range");
+        let path = dir.join("messages.jsonl");
+        let manifest = write(&path, RotationPolicy::new(4 * 1024), 0..1_000);
+
+        // A range across a segment boundary reads both segments whole
+        let boundary = manifest.segments[2].first_time.unwrap();
+        let (from, to) = (boundary - 2_000, boundary + 2_000);
+        let set = SegmentSet::open(&path).unwrap().unwrap();
+        let files: Vec<&str> = set.segments_between(from, to).iter().map(|s| s.file.as_str()).collect();
+        assert_eq!(files, ["messages-0002.jsonl", "messages-0003.jsonl"]);
+        let records = read(&path, Some((from, to)));
+        assert_eq!(records.len() as u64, manifest.segments[1].records + manifest.segments[2].records);
+        let in_range: Vec<SimTime> = records.iter().map(|r| r.time).filter(|t| (from..=to).contains(t)).collect();
+        assert_eq!(in_range, (from..=to).step_by(1_000).collect::<Vec<_>>());
+
+        // Segments outside the range are never opened
+        fs::remove_file(dir.join(&manifest.segments[0].file)).unwrap();
+        assert!(read(&path, Some((from, to))).len() > 1);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_retention_deletes_the_oldest_segments() {
+        let dir = output_dir("retention");
+        let path = dir.join("store.jsonl");
+        let policy = RotationPolicy { keep_segments: Some(3), ..RotationPolicy::new(4 * 1024) };
+        let manifest = write(&path, policy, 0..1_000);
+        assert_eq!(manifest.segments.len(), 3);
+        assert_eq!(manifest.records() + manifest.dropped_records, 1_000);
+        let kept = read(&path, None);
+        assert_eq!(kept.first().unwrap().time, (1_000 - manifest.records()) as SimTime * 1_000);
+        let on_disk =
+            fs::read_dir(&dir).unwrap().filter(|e| e.as_ref().unwrap().path() != manifest_path(&path)).count();
+        assert_eq!(on_disk, 3);
+
+        let policy = RotationPolicy { keep_bytes: Some(16 * 1024), ..RotationPolicy::new(4 * 1024) };
+        let manifest = write(&path, policy, 0..1_000);
+        assert!(manifest.bytes() <= 16 * 1024, "{} bytes", manifest.bytes());
+        assert!(manifest.dropped_segments > 0);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_compressed_outputs_read_back() {
+        let dir = output_dir("compressed");
+        for extension in [".jsonl.gz", ".jsonl.zst"] {
+            let path = dir.join(format!("messages{}", extension));
+            let manifest = write(&path, RotationPolicy::new(4 * 1024), 0..1_000);
+            assert_eq!(manifest.segments[0].file, format!("messages-0001{}", extension));
+            // Each segment is compressed on its own, and sized before it is
+            for segment in &manifest.segments {
+                assert!(fs::metadata(dir.join(&segment.file)).unwrap().len() < segment.bytes);
+            }
+            assert_eq!(read(&path, None), (0..1_000).map(record).collect::<Vec<_>>());
+
+            let single = dir.join(format!("single{}", extension));
+            let mut out = create_output(&single).unwrap();
+            for i in 0..100 {
+                serde_json::to_writer(&mut out, &record(i)).unwrap();
+                out.write_all(b"\n").unwrap();
+            }
+            out.finish().unwrap();
+            assert_eq!(read(&single, None), (0..100).map(record).collect::<Vec<_>>());
+        }
+        fs::remove_dir_all(&dir).ok();
+    }
+}
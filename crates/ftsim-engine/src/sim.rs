@@ -2,22 +2,46 @@
 //!
 //! This file contains the `Simulation` struct, which is the main entry point
 //! and orchestrator for the entire simulation. It holds the master clock,
-//! the event queue, the world state, and the deterministic RNG. The `step`
+//! the event queue, the world state, and the deterministic RNG streams. The `step`
 //! method forms the core of the discrete-event simulation loop.
 
 use crate::{
-    control::{ControlMsg, SimulationState},
+    cache::BoundedCache,
+    conditions::{Condition, ConditionStatus},
+    control::{Breakpoint, ControlMsg, SimulationState},
+    convergence::ConvergenceTracker,
+    crash_context::{self, EventContext},
+    effective_config::{self, EffectiveConfig, EngineConfig, LinkConfig, NodeConfig},
     events::{Event, EventDiscriminant, FaultEventInternal, Queued},
+    flow::FlowGraph,
     ids::IdGen,
+    interventions::{Intervention, InterventionKind},
+    invariants::{Invariant, InvariantViolation},
+    net::{Delivery, LinkFaultModel, MessageJournal, MessageRecord, SampledDelay, SenderView},
+    node::CodecFailure,
     prelude::*,
-    rng::{Recorder, RngDiscipline},
-    store::{StoreFaultModel, StoreView},
-    world::World,
+    report::NodeReport,
+    rng::{
+        bernoulli, Divergence, DrawPositions, EventTrace, Recorder, RngDiscipline, RngMismatch, RngRecording,
+        RngStreams,
+    },
+    slo::SloTracker,
+    state_hash::StateHasher,
+    store::{JournalingStoreView, QuotaDecision, StoreFaultModel, StoreJournal, StoreView},
+    timeline::{MarkerKind, Timeline},
+    usage::{ResourceUsage, UsageMeter},
+    world::{World, WorldCheckpoint, WorldState},
+};
+#[cfg(feature = "trace-export")]
+use crate::segments::{Manifest, RotationPolicy, SegmentWriter};
+use ftsim_proto::api::{LogIndex, LogRecord, SnapshotMeta};
+use fxhash::FxHashSet;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BinaryHeap},
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
 };
-use ftsim_proto::api::{LogIndex, LogRecord};
-use rand::SeedableRng;
-use rand_chacha::ChaCha20Rng;
-use std::collections::BinaryHeap;
 
 /// The main simulation controller.
 pub struct Simulation {
@@ -26,9 +50,9 @@ pub struct Simulation {
     /// The priority queue of all scheduled future events.
     queue: BinaryHeap<Queued<Event>>,
     /// The state of all nodes, the network, and storage.
-    world: World,
-    /// The central source of all randomness.
-    rng: ChaCha20Rng,
+    pub(crate) world: World,
+    /// The sources of all randomness, one stream per subsystem and node.
+    rng: RngStreams,
     /// A helper for generating unique, monotonic IDs.
     pub id_gen: IdGen,
     /// The bus for sending logs, metrics, and snapshots.
@@ -39,13 +63,358 @@ pub struct Simulation {
     state: SimulationState,
     /// Receiver for control messages from the TUI.
     control_rx: Option<crossbeam_channel::Receiver<ControlMsg>>,
+    /// Whether the controller can resume a paused run. Headless runs only
+    /// use the control channel to shut down.
+    resumable: bool,
+    /// Set by `ControlMsg::Shutdown`; every later tick ends the run.
+    shutdown_requested: bool,
+    /// How much wall-clock time the run may take; `None` is unlimited.
+    wall_timeout: Option<Duration>,
+    /// When the first run loop started, for the wall-clock timeout.
+    wall_started: Option<Instant>,
+    /// The number of events executed so far.
+    events_processed: u64,
+    /// `events_processed`, broken down by `Event::kind`.
+    events_by_kind: BTreeMap<&'static str, u64>,
+    /// `events_processed` when the current step or run-until request began.
+    request_start: u64,
+    /// Node status transitions and notable moments, for the run summary.
+    timeline: Timeline,
+    /// The id of the event currently being executed.
+    current_event: EventId,
+    /// Every store mutation, when store journaling is enabled.
+    store_journal: Option<StoreJournal>,
+    /// Safety properties checked while the run progresses.
+    invariants: Vec<Box<dyn Invariant>>,
+    /// Invariants are checked after this many non-fault events.
+    invariant_interval: u64,
+    /// Non-fault events executed since invariants were last checked.
+    events_since_check: u64,
+    /// The violation that stopped the run.
+    invariant_violation: Option<InvariantViolation>,
+    /// Every sent message and the sender's view at send time, when enabled.
+    message_journal: Option<MessageJournal>,
+    /// The rotated outputs the journals are written to as the run goes.
+    #[cfg(feature = "trace-export")]
+    journal_streams: JournalStreams,
+    /// Who sent how much to whom, when enabled.
+    flow_graph: Option<FlowGraph>,
+    /// How protocol message decode errors are handled.
+    codec_error_policy: CodecErrorPolicy,
+    /// Decode errors per (protocol tag, destination node), under
+    /// `CodecErrorPolicy::CountAndContinue`.
+    codec_errors: BTreeMap<(ProtoTag, NodeId), u64>,
+    /// Messages dropped per (protocol tag, destination node) because no
+    /// protocol on the destination has their tag.
+    unroutable: BTreeMap<(ProtoTag, NodeId), u64>,
+    /// The decode error that stopped the run, under `CodecErrorPolicy::Fail`.
+    codec_failure: Option<CodecFailure>,
+    /// How events at the same sim time are ordered.
+    scheduling: SchedulingPolicy,
+    /// How messages stamped in the receiver's future are handled.
+    future_message_policy: Option<FutureMessagePolicy>,
+    /// Messages from the future, dropped or flagged, per receiving node.
+    future_messages: BTreeMap<NodeId, u64>,
+    /// Defers the events of a node flooding one instant, when set.
+    flood_valve: Option<FloodValve>,
+    /// What the flood valve has counted and deferred.
+    flood: FloodTally,
+    /// Staggers the restarts that timed crashes schedule, when set.
+    restart_policy: Option<RestartPolicy>,
+    /// The sim time of the latest restart, for counting simultaneous ones.
+    last_restart: Option<SimTime>,
+    /// The labels of scheduled directive events that have yet to run.
+    event_labels: BTreeMap<EventId, String>,
+    /// The message being handled by `on_message`.
+    delivering: Option<MessageMeta>,
+    /// The client request being handled by `on_client_request`.
+    pub(crate) serving_client: Option<ClientRequestId>,
+    /// Client requests a protocol answered with `ClientResponse::Pending`
+    /// and has yet to respond to, as many as `CachesSpec` allows.
+    pub(crate) pending_clients: BoundedCache<ClientRequestId, PendingClientRequest>,
+    /// Sim nanoseconds allowed per wall-clock nanosecond; `None` is unlimited.
+    speed: Option<f32>,
+    /// The wall-clock and sim time pacing is measured from.
+    pacing_anchor: Option<(Instant, SimTime)>,
+    /// Conditions that pause the run right before a matching event.
+    breakpoints: Vec<Breakpoint>,
+    /// The event a breakpoint last paused before, which may run on resume.
+    break_skip: Option<EventId>,
+    /// The breakpoint that most recently paused the run.
+    breakpoint_hit: Option<Breakpoint>,
+    /// Stop once this many events have been executed in total.
+    max_events: Option<u64>,
+    /// Stop once only idle events remain queued.
+    stop_on_quiescence: bool,
+    /// Queued events other than UI ticks and periodic directive faults.
+    active_events: usize,
+    /// Queued events that were canceled and must not run.
+    canceled: FxHashSet<EventId>,
+    /// Events that were not scheduled because their time overflowed.
+    time_overflows: u64,
+    /// Publish `state_hash` to telemetry after every this many events.
+    state_hash_interval: Option<u64>,
+    /// Interventions armed by `DropNth` and `DelayNth` directives, in the
+    /// order they were armed.
+    interventions: Vec<Intervention>,
+    /// Wall-clock time and throughput of the run loop.
+    usage: UsageMeter,
+}
+
+/// A complete copy of a simulation's deterministic state, taken by
+/// `Simulation::save_state`. Loading it back rewinds the run so another
+/// future can be explored from the same point.
+pub struct SimState {
+    clock: SimTime,
+    queue: BinaryHeap<Queued<Event>>,
+    world: WorldState,
+    rng: RngStreams,
+    id_gen: IdGen,
+    events_processed: u64,
+    events_by_kind: BTreeMap<&'static str, u64>,
+    active_events: usize,
+    canceled: FxHashSet<EventId>,
+    events_since_check: u64,
+    invariant_violation: Option<InvariantViolation>,
+    codec_failure: Option<CodecFailure>,
+    codec_errors: BTreeMap<(ProtoTag, NodeId), u64>,
+    unroutable: BTreeMap<(ProtoTag, NodeId), u64>,
+    future_messages: BTreeMap<NodeId, u64>,
+    time_overflows: u64,
+    interventions: Vec<Intervention>,
+    flood: FloodTally,
+    last_restart: Option<SimTime>,
+    event_labels: BTreeMap<EventId, String>,
+    pending_clients: BoundedCache<ClientRequestId, PendingClientRequest>,
+    conditions: ConditionStatus,
+    slo: Option<SloTracker>,
+    convergence: ConvergenceTracker,
+    flow_graph: Option<FlowGraph>,
+}
+
+impl SimState {
+    /// Returns the sim time the state was saved at.
+    pub fn time(&self) -> SimTime {
+        self.clock
+    }
+}
+
+/// The flood valve's counts: the events each node has tried to run at the
+/// current instant, and the events deferred so far per node.
+#[derive(Debug, Clone, Default)]
+struct FloodTally {
+    time: SimTime,
+    at_time: BTreeMap<NodeId, u64>,
+    deferred: BTreeMap<NodeId, u64>,
+    /// The event at the head of the queue the valve has counted and let
+    /// through, until it runs.
+    admitted: Option<EventId>,
+}
+
+impl FloodTally {
+    fn hash_state<H: std::hash::Hasher>(&self, hasher: &mut H) {
+        hasher.write_u128(self.time);
+        for counts in [&self.at_time, &self.deferred] {
+            hasher.write_usize(counts.len());
+            for (node, count) in counts {
+                hasher.write_u32(*node);
+                hasher.write_u64(*count);
+            }
+        }
+        hasher.write_u64(self.admitted.map_or(u64::MAX, |id| id.0));
+    }
+}
+
+/// A client request that is waiting for its node's answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PendingClientRequest {
+    pub node_id: NodeId,
+    /// `ClientOp::kind` of the operation.
+    pub kind: &'static str,
+    pub issued_at: SimTime,
+}
+
+/// Streamed message records pile up to this many before the settled ones
+/// among them are written out, since finding them scans the event queue.
+#[cfg(feature = "trace-export")]
+const JOURNAL_SETTLE_BATCH: usize = 4096;
+
+/// The rotated outputs journals are written to while the run goes, so that
+/// a long run need not hold them in memory until it ends.
+#[cfg(feature = "trace-export")]
+struct JournalStreams {
+    store: Option<SegmentWriter>,
+    messages: Vec<SegmentWriter>,
+    /// `JOURNAL_SETTLE_BATCH`, unless a test wants it smaller.
+    settle_batch: usize,
+    /// How many records the message journal holds when the settled ones
+    /// are next looked for.
+    next_settle: usize,
+    /// The first write that failed. Nothing is written after it.
+    error: Option<std::io::Error>,
+}
+
+#[cfg(feature = "trace-export")]
+impl Default for JournalStreams {
+    fn default() -> Self {
+        Self { store: None, messages: Vec::new(), settle_batch: JOURNAL_SETTLE_BATCH, next_settle: 0, error: None }
+    }
+}
+
+#[cfg(feature = "trace-export")]
+impl JournalStreams {
+    fn is_empty(&self) -> bool {
+        self.store.is_none() && self.messages.is_empty()
+    }
+
+    /// Writes out the store entries journaled since the last call and, once
+    /// enough message records have piled up, those of the messages `queue`
+    /// holds no copy of. With `all`, writes every message record.
+    fn write(
+        &mut self,
+        store: Option<&mut StoreJournal>,
+        messages: Option<&mut MessageJournal>,
+        queue: &BinaryHeap<Queued<Event>>,
+        all: bool,
+    ) {
+        if self.error.is_none() {
+            if let Err(e) = self.try_write(store, messages, queue, all) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    fn try_write(
+        &mut self,
+        store: Option<&mut StoreJournal>,
+        messages: Option<&mut MessageJournal>,
+        queue: &BinaryHeap<Queued<Event>>,
+        all: bool,
+    ) -> std::io::Result<()> {
+        if let (Some(out), Some(journal)) = (&mut self.store, store) {
+            for entry in journal.take_entries() {
+                out.append(entry.time, &entry)?;
+            }
+        }
+        let Some(journal) = messages.filter(|_| !self.messages.is_empty()) else {
+            return Ok(());
+        };
+        if !all && journal.records().len() < self.next_settle {
+            return Ok(());
+        }
+        // A message with no copy left to deliver or depart gets no more
+        // deliveries
+        let in_flight = if all {
+            FxHashSet::default()
+        } else {
+            queue
+                .iter()
+                .filter_map(|queued| match &queued.payload {
+                    Event::Deliver { env, .. } | Event::Depart { env, .. } => Some(env.msg_id),
+                    _ => None,
+                })
+                .collect()
+        };
+        let settled = journal.take_settled(&in_flight);
+        self.next_settle = journal.records().len() + self.settle_batch;
+        for record in &settled {
+            for out in &mut self.messages {
+                out.append(record.sent_at, record)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The queue is compacted once at least this many events are canceled...
+const COMPACT_MIN_CANCELED: usize = 1024;
+
+/// ...and they make up more than this share of it.
+const COMPACT_CANCELED_FRACTION: f64 = 0.5;
+
+/// The run loop checks the wall-clock timeout once per this many ticks, and
+/// after every wait.
+const WALL_CHECK_INTERVAL: u64 = 1024;
+
+/// Why `run` or `run_until` returned.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "reason", content = "value", rename_all = "snake_case")]
+pub enum SimulationOutcome {
+    /// No events remain.
+    QueueExhausted,
+    /// The next event is scheduled after the stop time.
+    StopTime(SimTime),
+    /// The configured maximum number of events has been executed.
+    MaxEvents(u64),
+    /// Only UI ticks and periodic scenario directives remain queued.
+    Quiescent,
+    /// A breakpoint matched in a run without a control channel.
+    Breakpoint,
+    /// A registered invariant failed.
+    InvariantViolated,
+    /// A codec failure or replay divergence stopped the run.
+    Halted,
+    /// The controller requested a shutdown.
+    Shutdown,
+    /// The run took longer than the wall-clock timeout, in milliseconds.
+    WallTimeout(u64),
+}
+
+impl SimulationOutcome {
+    /// Returns the outcome's serialized name, without its value.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            SimulationOutcome::QueueExhausted => "queue_exhausted",
+            SimulationOutcome::StopTime(_) => "stop_time",
+            SimulationOutcome::MaxEvents(_) => "max_events",
+            SimulationOutcome::Quiescent => "quiescent",
+            SimulationOutcome::Breakpoint => "breakpoint",
+            SimulationOutcome::InvariantViolated => "invariant_violated",
+            SimulationOutcome::Halted => "halted",
+            SimulationOutcome::Shutdown => "shutdown",
+            SimulationOutcome::WallTimeout(_) => "wall_timeout",
+        }
+    }
+}
+
+impl std::fmt::Display for SimulationOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimulationOutcome::QueueExhausted => write!(f, "event queue exhausted"),
+            SimulationOutcome::StopTime(time) => write!(f, "reached stop time t={}", time),
+            SimulationOutcome::MaxEvents(max) => write!(f, "processed the maximum of {} events", max),
+            SimulationOutcome::Quiescent => write!(f, "protocols went quiescent"),
+            SimulationOutcome::Breakpoint => write!(f, "hit a breakpoint"),
+            SimulationOutcome::InvariantViolated => write!(f, "violated an invariant"),
+            SimulationOutcome::Halted => write!(f, "halted on an error"),
+            SimulationOutcome::Shutdown => write!(f, "shut down on request"),
+            SimulationOutcome::WallTimeout(ms) => write!(f, "exceeded the wall-clock timeout of {}ms", ms),
+        }
+    }
+}
+
+/// The outcome of one iteration of the run loop.
+enum Tick {
+    /// An event was executed.
+    Stepped,
+    /// Nothing was executed; wait this long before trying again.
+    Wait(Duration),
+    /// Control messages arrived while the run was ending; try again.
+    Again,
+    /// The run is over.
+    Done(SimulationOutcome),
 }
 
 impl Simulation {
     /// Creates a new simulation instance.
-    pub fn new(seed: u64, world: World, telemetry: TelemetryBus) -> Self {
-        let rng = ChaCha20Rng::seed_from_u64(seed);
+    pub fn new(seed: u64, mut world: World, telemetry: TelemetryBus) -> Self {
+        for node in &mut world.nodes {
+            let uuid = crate::node::meta::node_uuid(seed, node.id);
+            node.meta_mut().put(META_UUID, uuid);
+        }
+        let rng = RngStreams::new(seed);
         let recorder = Recorder::new(seed);
+        let timeline = Timeline::new(world.nodes.len());
 
         Self {
             clock: SIM_EPOCH,
@@ -57,62 +426,473 @@ impl Simulation {
             recorder,
             state: SimulationState::Running,
             control_rx: None,
+            resumable: false,
+            shutdown_requested: false,
+            wall_timeout: None,
+            wall_started: None,
+            events_processed: 0,
+            events_by_kind: BTreeMap::new(),
+            request_start: 0,
+            timeline,
+            current_event: EventId(0),
+            store_journal: None,
+            message_journal: None,
+            #[cfg(feature = "trace-export")]
+            journal_streams: JournalStreams::default(),
+            flow_graph: None,
+            invariants: Vec::new(),
+            invariant_interval: 1,
+            events_since_check: 0,
+            invariant_violation: None,
+            codec_error_policy: CodecErrorPolicy::default(),
+            codec_errors: BTreeMap::new(),
+            unroutable: BTreeMap::new(),
+            codec_failure: None,
+            scheduling: SchedulingPolicy::default(),
+            future_message_policy: None,
+            future_messages: BTreeMap::new(),
+            flood_valve: None,
+            flood: FloodTally::default(),
+            restart_policy: None,
+            last_restart: None,
+            event_labels: BTreeMap::new(),
+            delivering: None,
+            serving_client: None,
+            pending_clients: BoundedCache::new("pending_clients", CachesSpec::default().pending_clients),
+            speed: None,
+            pacing_anchor: None,
+            breakpoints: Vec::new(),
+            break_skip: None,
+            breakpoint_hit: None,
+            max_events: None,
+            stop_on_quiescence: false,
+            active_events: 0,
+            canceled: FxHashSet::default(),
+            time_overflows: 0,
+            state_hash_interval: None,
+            interventions: Vec::new(),
+            usage: UsageMeter::default(),
         }
     }
 
     /// Sets the control channel receiver for receiving messages from the TUI.
     pub fn set_control_channel(&mut self, rx: crossbeam_channel::Receiver<ControlMsg>) {
         self.control_rx = Some(rx);
+        self.resumable = true;
+    }
+
+    /// Sets a control channel for a run nobody steers interactively, such as
+    /// a headless run that shuts down on Ctrl-C. Messages are handled as
+    /// usual, but since nothing is expected to resume the run, a breakpoint
+    /// still ends it.
+    pub fn set_shutdown_channel(&mut self, rx: crossbeam_channel::Receiver<ControlMsg>) {
+        self.control_rx = Some(rx);
+        self.resumable = false;
+    }
+
+    /// Ends the run with `SimulationOutcome::WallTimeout` once it has taken
+    /// `timeout` of wall-clock time, counted from the first `run` or
+    /// `run_until` call.
+    pub fn set_wall_timeout(&mut self, timeout: Option<Duration>) {
+        self.wall_timeout = timeout;
     }
 
-    /// Initializes all protocol instances on all nodes.
+    /// Initializes all protocol instances on all nodes, then starts them.
     /// This must be called after the simulation is created but before `run`.
     pub fn init(&mut self) {
         let node_ids: Vec<NodeId> = (0..self.world.nodes.len() as u32).collect();
-        for nid in node_ids {
+        for &nid in &node_ids {
             // Use a separate scope to ensure borrows don't overlap
-            self.init_node(nid);
+            self.init_node(nid, false);
+        }
+        for &nid in &node_ids {
+            self.init_node(nid, true);
         }
     }
 
-    fn init_node(&mut self, node_id: NodeId) {
-        // Use raw pointers to avoid borrow conflicts
-        let node_ptr = self.world.node_mut(node_id) as *mut crate::node::runtime::Node;
-        let sim_ptr = self as *mut Simulation;
-
-        unsafe {
-            let mut ctx = EngineCtx {
-                sim: &mut *sim_ptr,
-                current_node_id: Some(node_id),
-            };
-
-            (*node_ptr).init(&mut ctx);
+    /// Runs a node's `init` (or `start`, if `start` is set) lifecycle hook.
+    fn init_node(&mut self, node_id: NodeId, start: bool) {
+        let mut ctx = EngineCtx {
+            sim: self,
+            current_node_id: Some(node_id),
+            store_delay: 0,
+            last_sent: None,
+        };
+        if start {
+            Node::start(&mut ctx, node_id);
+        } else {
+            Node::init(&mut ctx, node_id);
         }
     }
 
     /// Executes a single event from the queue, advances the clock, and returns the new time.
-    /// Returns `None` if the event queue is empty.
+    /// Returns `None` if the event queue is empty, if a replay has diverged
+    /// from its trace, or if a codec error stopped the run.
     pub fn step(&mut self) -> Option<SimTime> {
+        if self.recorder.divergence().is_some()
+            || self.recorder.rng_mismatch().is_some()
+            || self.codec_failure.is_some()
+            || self.invariant_violation.is_some()
+        {
+            return None;
+        }
+        self.admit_head()?;
         let queued_event = self.queue.pop()?;
+        self.flood.admitted = None;
+        if !queued_event.is_idle() {
+            self.active_events -= 1;
+        }
+        // Keep a live event at the head so that peeking finds the next event
+        // that will actually run.
+        self.discard_canceled();
+        let description = queued_event.payload.describe();
+        crash_context::enter_event(EventContext {
+            seed: self.recorder.seed(),
+            time: queued_event.time,
+            event_id: queued_event.id,
+            event: description.clone(),
+            events_processed: self.events_processed,
+            queue_len: self.queue.len(),
+            active_events: self.active_events,
+            canceled_events: self.canceled.len(),
+        });
+        self.recorder.begin_event(
+            queued_event.id,
+            queued_event.time,
+            description,
+            queued_event.discriminant.parts(),
+        );
+        let is_fault = matches!(queued_event.payload, Event::Fault(_));
+        let (kind, started) = (queued_event.payload.kind(), self.usage.start());
+        self.execute(queued_event);
+        self.usage.charge(kind, started);
+        crash_context::leave_event();
+        if !self.recorder.end_event() {
+            return None;
+        }
+        let started = self.usage.start();
+        self.check_invariants(is_fault);
+        self.usage.charge("invariants", started);
+        let started = self.usage.start();
+        self.telemetry.advance_conditions(self.clock);
+        self.telemetry.advance_slo(self.clock);
+        self.usage.charge("telemetry", started);
+        if self
+            .state_hash_interval
+            .is_some_and(|every| self.events_processed % every == 0)
+        {
+            let hash = self.state_hash();
+            self.telemetry.log_event(
+                "STATE_HASH".to_string(),
+                format!("{:016x} after {} events", hash, self.events_processed),
+                None,
+                EventSeverity::Debug,
+            );
+        }
+        #[cfg(feature = "trace-export")]
+        if !self.journal_streams.is_empty() {
+            let (store, messages) = (self.store_journal.as_mut(), self.message_journal.as_mut());
+            self.journal_streams.write(store, messages, &self.queue, false);
+        }
+        Some(self.clock)
+    }
+
+    /// Runs the flood valve over the head of the queue until it lets one
+    /// through, so that the event peeked at next, by the stop time check
+    /// and breakpoints alike, is the one that runs next. Returns `None` if
+    /// the queue runs dry.
+    fn admit_head(&mut self) -> Option<()> {
+        loop {
+            let head = self.queue.peek()?;
+            if self.flood.admitted == Some(head.id) {
+                return Some(());
+            }
+            let (id, time, node) = (head.id, head.time, Self::flooding_node(&head.payload));
+            if !self.flood_valve_defers(time, node) {
+                self.flood.admitted = Some(id);
+                return Some(());
+            }
+            let deferred = self.queue.pop()?;
+            self.defer_flooding(deferred);
+        }
+    }
+
+    /// The node an event runs on behalf of, which the flood valve counts it
+    /// against.
+    fn flooding_node(event: &Event) -> Option<NodeId> {
+        match event {
+            Event::Deliver { env, .. } => Some(env.dst),
+            Event::Depart { env, .. } => Some(env.src),
+            Event::TimerFired { node_id, .. }
+            | Event::ClientRequest { node_id, .. }
+            | Event::SendFailed { node_id, .. } => Some(*node_id),
+            Event::Fault(_) | Event::Announce { .. } | Event::UiSnapshotTick => None,
+        }
+    }
+
+    /// Counts an event of `node` against its budget for the instant `time`
+    /// it is scheduled at, and returns whether the flood valve defers it.
+    /// Only events run on behalf of a node count.
+    fn flood_valve_defers(&mut self, time: SimTime, node: Option<NodeId>) -> bool {
+        let (Some(valve), Some(node)) = (self.flood_valve, node) else {
+            return false;
+        };
+        if time != self.flood.time {
+            self.flood.time = time;
+            self.flood.at_time.clear();
+        }
+        let seen = self.flood.at_time.entry(node).or_default();
+        *seen += 1;
+        if *seen <= valve.threshold {
+            return false;
+        }
+        if *seen == valve.threshold + 1 {
+            tracing::warn!(
+                node_id = node,
+                time = %time,
+                threshold = valve.threshold,
+                quantum = %valve.quantum,
+                "Flood valve deferring events"
+            );
+            self.telemetry.log_event(
+                "FLOOD_VALVE".to_string(),
+                format!(
+                    "node {} ran {} events at t={}; deferring the rest by {}ns",
+                    node, valve.threshold, time, valve.quantum
+                ),
+                Some(node),
+                EventSeverity::Warn,
+            );
+        }
+        *self.flood.deferred.entry(node).or_default() += 1;
+        true
+    }
+
+    /// Requeues an event the flood valve deferred, one quantum later and
+    /// after the events already queued for then, keeping its id so that it
+    /// can still be canceled. A deferred timer's node learns when it now
+    /// fires. An event whose time would overflow is dropped.
+    fn defer_flooding(&mut self, mut event: Queued<Event>) {
+        let quantum = self.flood_valve.expect("the flood valve is set").quantum;
+        match checked_add(event.time, quantum) {
+            Ok(time) => {
+                if let Event::TimerFired { node_id, timer_id } = event.payload {
+                    self.world.node_mut(node_id).defer_timer(timer_id, time);
+                }
+                event.time = time;
+                event.insert_seq = self.id_gen.next_insertion_seq();
+                self.queue.push(event);
+            }
+            Err(err) => {
+                // Deferred events are never idle
+                self.active_events -= 1;
+                self.report_time_overflow("flood_valve", None, err);
+            }
+        }
+        self.discard_canceled();
+    }
+
+    /// Digests the simulation state: the clock, the id counters, the RNG
+    /// stream positions, every node's status, clock skew, byzantine flag,
+    /// incarnation, durable log boundary and store contents, every link's
+    /// fault model in link id order, the interventions' progress and the
+    /// flood valve's tally.
+    /// The event queue is left out, since its heap layout is not part of the
+    /// state. Runs that reach the same state produce the same hash on every
+    /// platform.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::new();
+        hasher.write_u128(self.clock);
+        self.id_gen.hash(&mut hasher);
+        self.rng.hash_state(&mut hasher);
+        hasher.write_usize(self.world.nodes.len());
+        for node in &self.world.nodes {
+            hasher.write_u32(node.id);
+            node.status.hash(&mut hasher);
+            hasher.write_i128(node.clock_skew_ns);
+            hasher.write_u8(node.byzantine() as u8);
+            hasher.write_u64(node.incarnation());
+            hasher.write_u64(node.meta().get_u64(META_DURABLE_LOG_LEN).unwrap_or(0));
+            node.store().hash_state(&mut hasher);
+            if let Some(quota) = node.write_quota() {
+                quota.hash_state(&mut hasher);
+            }
+        }
+        let links = self.world.net.sorted_link_ids();
+        hasher.write_usize(links.len());
+        for id in links {
+            let link = &self.world.net.links[&id];
+            hasher.write_u64(link.id);
+            hasher.write_u32(link.src);
+            hasher.write_u32(link.dst);
+            link.faults.hash_state(&mut hasher);
+        }
+        for intervention in &self.interventions {
+            intervention.hash_state(&mut hasher);
+        }
+        self.flood.hash_state(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Logs `state_hash` as a `STATE_HASH` telemetry event after every
+    /// `every` events, or never with `None`.
+    pub fn set_state_hash_interval(&mut self, every: Option<u64>) {
+        self.state_hash_interval = every.filter(|every| *every > 0);
+    }
+
+    /// Registers an invariant, checked from the next event on.
+    pub fn add_invariant(&mut self, invariant: Box<dyn Invariant>) {
+        self.invariants.push(invariant);
+    }
+
+    /// Adds a condition, checked from now on against the signals it reads.
+    pub fn add_condition(&mut self, condition: Condition) {
+        let statuses: Vec<NodeStatus> = self.world.nodes.iter().map(|n| n.status).collect();
+        self.telemetry.add_condition(condition, &statuses);
+    }
+
+    /// Checks invariants after every `every` non-fault events (at least 1).
+    pub fn set_invariant_interval(&mut self, every: u64) {
+        self.invariant_interval = every.max(1);
+    }
+
+    /// The names of the registered invariants.
+    pub fn invariant_names(&self) -> impl Iterator<Item = &str> {
+        self.invariants.iter().map(|i| i.name())
+    }
+
+    /// Returns the invariant violation that stopped the run, if any.
+    pub fn invariant_violation(&self) -> Option<&InvariantViolation> {
+        self.invariant_violation.as_ref()
+    }
+
+    /// Evaluates invariants if a fault just ran or the interval has elapsed,
+    /// recording the first violation.
+    fn check_invariants(&mut self, after_fault: bool) {
+        if self.invariants.is_empty() {
+            return;
+        }
+        self.events_since_check += 1;
+        if !after_fault && self.events_since_check < self.invariant_interval {
+            return;
+        }
+        self.events_since_check = 0;
+        let node_kvs = self.telemetry.node_kvs(self.world.nodes.len());
+        for invariant in &mut self.invariants {
+            let checked = invariant.check(&self.world, &node_kvs, self.clock);
+            self.telemetry.record_oracle(invariant.name(), checked.is_ok());
+            if let Err(message) = checked {
+                let violation = InvariantViolation {
+                    invariant: invariant.name().to_string(),
+                    time: self.clock,
+                    event_id: self.current_event,
+                    message,
+                };
+                tracing::error!(%violation, "Invariant violated");
+                self.telemetry.log_event(
+                    "INVARIANT_VIOLATED".to_string(),
+                    violation.to_string(),
+                    None,
+                    EventSeverity::Fault,
+                );
+                self.timeline.record_marker(self.clock, None, MarkerKind::Violation);
+                self.invariant_violation = Some(violation);
+                return;
+            }
+        }
+    }
+
+    /// Advances the clock to a dequeued event and handles it.
+    fn execute(&mut self, queued_event: Queued<Event>) {
         let event = queued_event.payload;
 
         assert!(queued_event.time >= self.clock, "Time went backwards!");
         self.clock = queued_event.time;
+        self.events_processed += 1;
+        *self.events_by_kind.entry(event.kind()).or_insert(0) += 1;
 
         let event_id = queued_event.id;
+        self.current_event = event_id;
         self.telemetry.set_current_time(self.clock, event_id);
+        if let Some(label) = self.event_labels.remove(&event_id) {
+            tracing::info!(target: "events", %label, "🏷️ Directive fired");
+            self.telemetry.log_event(
+                "DIRECTIVE".to_string(),
+                format!("Directive '{}' fired", label),
+                None,
+                EventSeverity::Info,
+            );
+        }
 
         let mut ctx = EngineCtx {
             sim: self,
             current_node_id: None,
+            store_delay: 0,
+            last_sent: None,
         };
         match event {
-            Event::Deliver { env, link_id: _ } => {
+            Event::Deliver { env, link_id } => {
                 let dst = env.dst;
                 ctx.current_node_id = Some(dst);
 
+                // Fragments are held until the whole message has arrived.
+                let env = if env.fragment.is_some() {
+                    let now = ctx.sim.clock;
+                    let node = ctx.sim.world.node_mut(dst);
+                    if node.status != NodeStatus::Up {
+                        // A down node has nowhere to hold them, so the
+                        // message can no longer complete
+                        tracing::debug!(node_id = dst, msg_id = %env.msg_id, "Fragment dropped, node is down");
+                        return;
+                    }
+                    match node.reassemble(now, env) {
+                        Some(whole) => whole,
+                        None => return,
+                    }
+                } else {
+                    env
+                };
+
                 // Check if this is a fault-injected message (src = u32::MAX)
                 let is_fault_injected = env.src == u32::MAX;
+
+                // Compare the sender's clock at send time with ours
+                let local_now = ProtoCtx::now(&ctx);
+                // A limit past the end of time admits every send time
+                let future_policy = ctx.sim.future_message_policy.filter(|policy| {
+                    !is_fault_injected
+                        && checked_add(local_now, policy.max_skew).is_ok_and(|limit| env.sent_at_local > limit)
+                });
+                if let Some(policy) = future_policy {
+                    *ctx.sim.future_messages.entry(dst).or_insert(0) += 1;
+                    if policy.action == FutureMessageAction::Drop {
+                        tracing::info!(
+                            src = env.src,
+                            dst,
+                            msg_id = %env.msg_id,
+                            sent_at = env.sent_at_local,
+                            local_now,
+                            "⏭️ Message from the future dropped"
+                        );
+                        ctx.sim.telemetry.log_event(
+                            "FUTURE_MESSAGE_DROPPED".to_string(),
+                            format!(
+                                "Message {} from node {} to node {} sent at {} on the sender's clock, {} on the receiver's",
+                                env.msg_id, env.src, dst, env.sent_at_local, local_now
+                            ),
+                            Some(dst),
+                            EventSeverity::Warn
+                        );
+                        ::metrics::counter!(
+                            ftsim_types::metrics::MET_NET_MSG_DROPPED,
+                            ftsim_types::metrics::LBL_REASON => "future_message",
+                            ftsim_types::metrics::LBL_SRC => env.src.to_string(),
+                            ftsim_types::metrics::LBL_DST => dst.to_string()
+                        ).increment(1);
+                        ctx.sim.telemetry.record_drop(dst, Some(link_id));
+                        return;
+                    }
+                }
                 let payload_preview = if env.payload.len() <= 50 {
                     String::from_utf8_lossy(&env.payload).to_string()
                 } else {
@@ -122,13 +902,13 @@ impl Simulation {
                 if is_fault_injected {
                     tracing::info!(
                         dst = env.dst,
-                        msg_id = env.msg_id,
+                        msg_id = %env.msg_id,
                         payload_len = env.payload.len(),
                         payload_preview = %payload_preview,
                         "📨 Fault-injected message delivered to node"
                     );
                 } else {
-                    tracing::info!(target: "events", src = env.src, dst = env.dst, msg_id = env.msg_id, "📨 Message delivered");
+                    tracing::info!(target: "events", src = env.src, dst = env.dst, msg_id = %env.msg_id, "📨 Message delivered");
                 }
 
                 ctx.sim.telemetry.log_event(
@@ -138,36 +918,97 @@ impl Simulation {
                     } else {
                         format!("Message {} from node {} to node {}", env.msg_id, env.src, env.dst)
                     },
-                    Some(dst)
+                    Some(dst),
+                    if is_fault_injected { EventSeverity::Fault } else { EventSeverity::Debug }
                 );
                 ctx.sim.telemetry.increment_metric("messages_delivered");
 
-                // Use raw pointer to avoid double borrow
-                let node_ptr = ctx.sim.world.node_mut(dst) as *mut crate::node::runtime::Node;
-                unsafe {
-                    (*node_ptr).handle_message(&mut ctx, env);
+                let was_up = ctx.sim.world.node(dst).status == NodeStatus::Up;
+                let latency = ctx.sim.clock.saturating_sub(env.create_time);
+                ctx.sim.telemetry.record_delivery(dst, link_id, latency, was_up);
+                let journaled = ctx.sim.message_journal.is_some().then(|| env.clone());
+                // Classified before delivery, which consumes the payload
+                let flow = ctx.sim.flow_graph.as_ref().filter(|_| was_up && !is_fault_injected).map(|graph| {
+                    let variant = graph
+                        .by_variant()
+                        .then(|| ctx.sim.world.node(dst).message_variant(env.proto_tag, &env.payload))
+                        .flatten();
+                    (env.src, env.payload.len() as u64, variant)
+                });
+                ctx.sim.delivering = Some(MessageMeta {
+                    msg_id: env.msg_id,
+                    sent_at: env.sent_at_local,
+                    from_future: future_policy.is_some(),
+                    trace_id: env.trace_id,
+                });
+                let accepted = Node::handle_message(&mut ctx, env);
+                ctx.sim.delivering = None;
+
+                if let (Some((src, bytes, variant)), Some(graph)) = (flow, &mut ctx.sim.flow_graph) {
+                    graph.record(ctx.sim.clock, src, dst, bytes, variant.as_deref());
+                }
+
+                if let Some(env) = journaled.filter(|_| was_up) {
+                    let delivery = Delivery {
+                        time: ctx.sim.clock,
+                        across_partition: ctx.sim.world.net.links.get(&link_id)
+                            .is_some_and(|l| l.faults.partitioned),
+                        decode_failed: !accepted,
+                    };
+                    if let Some(journal) = &mut ctx.sim.message_journal {
+                        journal.record_delivery(&env, delivery);
+                    }
                 }
             }
+            Event::Depart { mut env, incarnation } => {
+                let node = ctx.sim.world.node(env.src);
+                if node.status != NodeStatus::Up || node.incarnation() != incarnation {
+                    tracing::debug!(node_id = env.src, msg_id = %env.msg_id, "Delayed send dropped, node crashed since");
+                    return;
+                }
+                ctx.current_node_id = Some(env.src);
+                env.create_time = ctx.sim.clock;
+                env.sent_at_local = ctx.now();
+                ctx.depart(env);
+            }
             Event::TimerFired { node_id, timer_id } => {
                 ctx.current_node_id = Some(node_id);
                 tracing::info!(target: "events", %node_id, %timer_id, "⏰ Timer fired");
                 ctx.sim.telemetry.log_event(
                     "TIMER_FIRED".to_string(),
                     format!("Timer {} fired on node {}", timer_id, node_id),
-                    Some(node_id)
+                    Some(node_id),
+                    EventSeverity::Debug
                 );
                 ctx.sim.telemetry.increment_metric("timers_fired");
-                // Use raw pointer to avoid double borrow
-                let node_ptr = ctx.sim.world.node_mut(node_id) as *mut crate::node::runtime::Node;
-                unsafe {
-                    (*node_ptr).handle_timer(&mut ctx, timer_id);
-                }
+                Node::handle_timer(&mut ctx, node_id, timer_id);
+            }
+            Event::SendFailed { node_id, incarnation, proto_tag, dst, msg_id, reason } => {
+                ctx.current_node_id = Some(node_id);
+                ctx.sim.telemetry.log_event(
+                    "SEND_FAILED".to_string(),
+                    format!("Message {} from node {} to node {} failed: {}", msg_id, node_id, dst, reason),
+                    Some(node_id),
+                    EventSeverity::Info
+                );
+                Node::handle_send_failed(&mut ctx, node_id, incarnation, proto_tag, dst, msg_id, reason);
+            }
+            Event::Announce { node_id, fault_kind, at } => {
+                ctx.current_node_id = Some(node_id);
+                tracing::info!(target: "events", %node_id, %fault_kind, at, "📅 Fault announced");
+                ctx.sim.telemetry.log_event(
+                    "FAULT_ANNOUNCED".to_string(),
+                    format!("Node {} told of a {} at {}", node_id, fault_kind, at),
+                    Some(node_id),
+                    EventSeverity::Info,
+                );
+                Node::announce(&mut ctx, node_id, fault_kind, at);
             }
             Event::Fault(fault) => {
                 tracing::warn!(target: "events", ?fault, "💥 Fault injected");
                 let fault_desc = match &fault {
                     FaultEventInternal::Crash { node_id, duration } => {
-                        format!("Node {} crashed for {}ns", node_id, duration)
+                        format!("Node {} crashed for {}", node_id, duration)
                     },
                     FaultEventInternal::Restart { node_id } => {
                         format!("Node {} restarted", node_id)
@@ -181,31 +1022,34 @@ impl Simulation {
                 ctx.sim.telemetry.log_event(
                     "FAULT_INJECTED".to_string(),
                     fault_desc,
-                    None
+                    None,
+                    EventSeverity::Fault
                 );
                 ctx.sim.telemetry.increment_metric("faults_injected");
-                // Use a helper method to avoid borrow issues
-                let sim_ptr = &mut *ctx.sim as *mut Simulation;
-                unsafe {
-                    (*sim_ptr).handle_fault(&mut ctx, fault);
-                }
+                Simulation::handle_fault(&mut ctx, fault);
+            }
+            Event::ClientRequest { node_id, op } => {
+                ctx.current_node_id = Some(node_id);
+                tracing::info!(target: "events", %node_id, ?op, "📥 Client request");
+                ctx.sim.telemetry.increment_metric("client_requests");
+                Node::handle_client_request(&mut ctx, node_id, &op);
             }
             Event::UiSnapshotTick => {
                 let snap = self.telemetry.build_snapshot(&self.world, self.clock);
                 self.telemetry.send_snapshot(snap);
-                self.schedule_at(
-                    self.clock + sim_from_ms(50),
-                    Event::UiSnapshotTick,
-                    EventDiscriminant::ui(),
-                );
+                match checked_add(self.clock, sim_from_ms(50)) {
+                    Ok(next_tick) => {
+                        self.schedule_at(next_tick, Event::UiSnapshotTick, EventDiscriminant::ui());
+                    }
+                    Err(err) => self.report_time_overflow("ui.tick", None, err),
+                }
             }
         }
-
-        Some(self.clock)
     }
 
-    /// Processes any pending control messages from the TUI.
-    fn process_control_messages(&mut self) {
+    /// Processes any pending control messages from the TUI. Returns whether
+    /// there were any.
+    fn process_control_messages(&mut self) -> bool {
         // Collect messages first to avoid borrow issues
         let messages: Vec<ControlMsg> = if let Some(ref rx) = self.control_rx {
             let mut msgs = Vec::new();
@@ -217,10 +1061,43 @@ impl Simulation {
             Vec::new()
         };
 
-        // Now process them
+        let any = !messages.is_empty();
         for msg in messages {
             self.handle_control_message(msg);
         }
+        any
+    }
+
+    /// Waits up to `delay` for the loop to have something to do. With a
+    /// control channel the wait ends as soon as a message arrives, which is
+    /// handled right away.
+    fn wait(&mut self, delay: Duration) {
+        let Some(rx) = &self.control_rx else {
+            std::thread::sleep(delay);
+            return;
+        };
+        match rx.recv_timeout(delay) {
+            Ok(msg) => self.handle_control_message(msg),
+            Err(crossbeam_channel::RecvTimeoutError::Timeout) => {}
+            // Nothing can resume the run any more; avoid spinning
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected) => std::thread::sleep(delay),
+        }
+    }
+
+    /// Ends the run with `outcome`, unless control messages arrived since the
+    /// tick began. Those may schedule work or pause the run, so the tick is
+    /// retried instead.
+    fn end_run(&mut self, outcome: SimulationOutcome) -> Tick {
+        if self.process_control_messages() {
+            return Tick::Again;
+        }
+        if matches!(
+            outcome,
+            SimulationOutcome::QueueExhausted | SimulationOutcome::InvariantViolated | SimulationOutcome::Halted
+        ) {
+            self.state = SimulationState::Completed;
+        }
+        Tick::Done(outcome)
     }
 
     /// Handles a control message from the TUI.
@@ -229,6 +1106,7 @@ impl Simulation {
             ControlMsg::Pause => {
                 tracing::info!("Simulation paused by user");
                 self.state = SimulationState::Paused;
+                self.pacing_anchor = None;
             }
             ControlMsg::Resume => {
                 tracing::info!("Simulation resumed by user");
@@ -236,7 +1114,15 @@ impl Simulation {
             }
             ControlMsg::Step => {
                 tracing::info!("Single step requested");
-                self.state = SimulationState::Stepping;
+                self.begin_request(SimulationState::Stepping { remaining: 1 });
+            }
+            ControlMsg::StepN(count) => {
+                tracing::info!("Stepping {} events", count);
+                self.begin_request(SimulationState::Stepping { remaining: count });
+            }
+            ControlMsg::RunUntil(target) => {
+                tracing::info!(target, "Running until requested time");
+                self.begin_request(SimulationState::RunningUntil(target));
             }
             ControlMsg::KillNode(node_id) => {
                 tracing::info!("Killing node {} by user request", node_id);
@@ -244,7 +1130,7 @@ impl Simulation {
                     self.clock,
                     Event::Fault(FaultEventInternal::Crash {
                         node_id,
-                        duration: MAX_SIM_TIME, // Permanent crash
+                        duration: SimDuration::Forever,
                     }),
                     EventDiscriminant::fault(),
                 );
@@ -274,142 +1160,1010 @@ impl Simulation {
                 );
             }
             ControlMsg::SetSpeed(speed) => {
-                tracing::info!("Speed adjustment to {}x not yet implemented", speed);
-                // TODO: Implement speed control
+                tracing::info!("Simulation speed set to {}x", speed);
+                self.set_speed(speed);
             }
-        }
-    }
-
-    /// Runs the simulation until the event queue is empty or a stop condition is met.
-    pub fn run(&mut self) {
-        loop {
-            // Process control messages
-            self.process_control_messages();
-
-            // Check if we should pause
-            if self.state == SimulationState::Paused {
-                std::thread::sleep(std::time::Duration::from_millis(50));
-                continue;
+            ControlMsg::SetBreakpoint(breakpoint) => {
+                tracing::info!("Breakpoint set: {}", breakpoint);
+                self.add_breakpoint(breakpoint);
             }
-
-            // Step the simulation
-            if self.step().is_none() {
-                self.state = SimulationState::Completed;
-                break;
+            ControlMsg::ClearBreakpoint(breakpoint) => {
+                tracing::info!("Breakpoint cleared: {}", breakpoint);
+                self.clear_breakpoint(&breakpoint);
             }
-
-            // If we're in stepping mode, pause after this step
-            if self.state == SimulationState::Stepping {
-                self.state = SimulationState::Paused;
+            ControlMsg::Shutdown => {
+                tracing::info!("Shutdown requested");
+                self.shutdown_requested = true;
             }
         }
-        tracing::info!("Simulation finished.");
     }
 
-    /// Runs the simulation until a specific time is reached.
-    pub fn run_until(&mut self, stop_at: SimTime) {
-        loop {
-            // Process control messages
-            self.process_control_messages();
+    /// Limits how fast sim time advances relative to wall-clock time. A speed
+    /// of zero, or an infinite or NaN speed, runs as fast as possible.
+    /// Pacing only sleeps between events, so it never affects the outcome.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = (speed.is_finite() && speed > 0.0).then_some(speed);
+        self.pacing_anchor = None;
+    }
 
-            // Check if we should pause
-            if self.state == SimulationState::Paused {
-                std::thread::sleep(std::time::Duration::from_millis(50));
-                continue;
-            }
+    /// Returns the current speed limit, or `None` if unlimited.
+    pub fn speed(&self) -> Option<f32> {
+        self.speed
+    }
 
-            // Check if we've reached the stop time
-            if let Some(queued_event) = self.queue.peek() {
-                if queued_event.time > stop_at {
-                    break;
-                }
-            }
+    /// Returns how long to wait before the next event may execute without
+    /// sim time running ahead of the configured speed.
+    fn pacing_delay(&mut self) -> Option<Duration> {
+        let speed = self.speed?;
+        let next = self.queue.peek()?.time;
+        let clock = self.clock;
+        let (wall, sim) = *self.pacing_anchor.get_or_insert_with(|| (Instant::now(), clock));
+        let due = Duration::from_secs_f64(next.saturating_sub(sim) as f64 / 1e9 / speed as f64);
+        due.checked_sub(wall.elapsed()).filter(|d| !d.is_zero())
+    }
 
-            // Step the simulation
-            if self.step().is_none() {
-                self.state = SimulationState::Completed;
-                break;
-            }
+    /// Starts a step or run-until request, which pauses the run once satisfied.
+    fn begin_request(&mut self, state: SimulationState) {
+        self.state = state;
+        self.request_start = self.events_processed;
+    }
 
-            // If we're in stepping mode, pause after this step
-            if self.state == SimulationState::Stepping {
-                self.state = SimulationState::Paused;
+    /// Pauses the run if the active step or run-until request is satisfied,
+    /// and publishes a snapshot recording how many events it executed.
+    /// Returns whether the run was paused.
+    fn finish_request(&mut self) -> bool {
+        let done = match self.state {
+            SimulationState::Stepping { remaining } => remaining == 0,
+            SimulationState::RunningUntil(target) => {
+                self.queue.peek().map_or(true, |next| next.time > target)
             }
+            _ => false,
+        };
+        if done {
+            let stepped = self.events_processed - self.request_start;
+            tracing::info!(time = self.clock, stepped, "Paused after request");
+            self.state = SimulationState::Paused;
+            self.pacing_anchor = None;
+            let mut snap = self.telemetry.build_snapshot(&self.world, self.clock);
+            snap.stepped = Some(stepped);
+            self.telemetry.send_snapshot(snap);
         }
-        tracing::info!(stop_time = stop_at, "Simulation paused at time limit.");
+        done
     }
 
-    /// Schedules a new event to occur at a future time.
-    pub fn schedule_at(
-        &mut self,
-        when: SimTime,
-        ev: Event,
-        discriminant: EventDiscriminant,
-    ) -> EventId {
-        let event_id = self.id_gen.next_event_id();
-        let queued_event = Queued::new(
-            event_id,
-            when,
-            self.id_gen.next_insertion_seq(),
-            discriminant,
-            ev,
+    /// Pauses the run right before any event matching `breakpoint` executes.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    /// Removes every breakpoint equal to `breakpoint`.
+    pub fn clear_breakpoint(&mut self, breakpoint: &Breakpoint) {
+        self.breakpoints.retain(|b| b != breakpoint);
+    }
+
+    /// Returns the breakpoint that most recently paused the run.
+    pub fn breakpoint_hit(&self) -> Option<&Breakpoint> {
+        self.breakpoint_hit.as_ref()
+    }
+
+    /// Pauses the run if the next event matches a breakpoint, leaving the
+    /// event queued. Resuming executes that event without re-checking it.
+    /// Returns whether the run was paused.
+    fn check_breakpoints(&mut self) -> bool {
+        let Some(next) = self.queue.peek() else {
+            return false;
+        };
+        if self.break_skip == Some(next.id) {
+            return false;
+        }
+        let Some(breakpoint) = self.breakpoints.iter().find(|b| b.matches(next.time, &next.payload)) else {
+            return false;
+        };
+        let breakpoint = breakpoint.clone();
+        let details = format!(
+            "Breakpoint '{}' hit before {} at t={}",
+            breakpoint,
+            next.payload.describe(),
+            next.time
         );
-        self.queue.push(queued_event);
-        event_id
+        self.break_skip = Some(next.id);
+        tracing::info!(time = self.clock, "{}", details);
+        self.telemetry.log_event("BREAKPOINT_HIT".to_string(), details, breakpoint.node, EventSeverity::Info);
+        self.state = SimulationState::Paused;
+        self.pacing_anchor = None;
+        let mut snap = self.telemetry.build_snapshot(&self.world, self.clock);
+        snap.breakpoint = Some(breakpoint.to_string());
+        self.telemetry.send_snapshot(snap);
+        self.breakpoint_hit = Some(breakpoint);
+        true
     }
 
-    /// Returns the current simulation time.
-    pub fn now(&self) -> SimTime {
-        self.clock
+    /// Returns the current execution state.
+    pub fn state(&self) -> SimulationState {
+        self.state
     }
 
-    /// Returns a reference to the telemetry bus.
-    pub fn telemetry(&self) -> &TelemetryBus {
-        &self.telemetry
+    /// Performs one iteration of the run loop: applies pending control
+    /// messages, then executes the next event unless the run is paused, held
+    /// back by the speed limit, or would pass `stop_at`. A run paused with an
+    /// empty queue only ends once resumed and the queue is still empty.
+    fn tick(&mut self, stop_at: SimTime) -> Tick {
+        self.process_control_messages();
+        if self.shutdown_requested {
+            return Tick::Done(SimulationOutcome::Shutdown);
+        }
+
+        if self.finish_request() || self.state == SimulationState::Paused {
+            self.pacing_anchor = None;
+            return Tick::Wait(Duration::from_millis(50));
+        }
+
+        // Check if we've reached the stop time, by the event that runs next
+        self.admit_head();
+        if self.queue.peek().is_some_and(|next| next.time > stop_at) {
+            return self.end_run(SimulationOutcome::StopTime(stop_at));
+        }
+        if let Some(max) = self.max_events.filter(|max| self.events_processed >= *max) {
+            return self.end_run(SimulationOutcome::MaxEvents(max));
+        }
+        if self.stop_on_quiescence && self.active_events == 0 && !self.queue.is_empty() {
+            return self.end_run(SimulationOutcome::Quiescent);
+        }
+
+        // Without an interactive controller nothing can resume the run, so
+        // a breakpoint ends it
+        if self.check_breakpoints() {
+            return if self.resumable {
+                Tick::Wait(Duration::from_millis(50))
+            } else {
+                Tick::Done(SimulationOutcome::Breakpoint)
+            };
+        }
+
+        // Hold back events until wall-clock time catches up with the speed limit
+        if let Some(delay) = self.pacing_delay() {
+            return Tick::Wait(delay.min(Duration::from_millis(50)));
+        }
+
+        if self.step().is_none() {
+            let halted = self.recorder.divergence().is_some() || self.codec_failure.is_some();
+            return self.end_run(if self.invariant_violation.is_some() {
+                SimulationOutcome::InvariantViolated
+            } else if halted {
+                SimulationOutcome::Halted
+            } else {
+                SimulationOutcome::QueueExhausted
+            });
+        }
+        if self.invariant_violation.is_some() {
+            return self.end_run(SimulationOutcome::InvariantViolated);
+        }
+        if let SimulationState::Stepping { remaining } = &mut self.state {
+            *remaining = remaining.saturating_sub(1);
+        }
+        Tick::Stepped
     }
 
-    /// Returns a reference to the world state.
-    pub fn world(&self) -> &World {
-        &self.world
+    /// Runs the simulation until the event queue is empty or a stop condition is met.
+    pub fn run(&mut self) -> SimulationReport {
+        let outcome = self.run_loop(MAX_SIM_TIME);
+        tracing::info!(%outcome, "Simulation finished.");
+        self.finish(outcome)
+    }
+
+    /// Ends the run with `outcome`: calls `on_shutdown` on every up node,
+    /// checks the invariants against what the hooks published, and
+    /// summarizes the run. An invariant the hooks break ends it with
+    /// `InvariantViolated` instead. A run resumed afterwards calls the hooks
+    /// again when it next ends.
+    pub fn finish(&mut self, mut outcome: SimulationOutcome) -> SimulationReport {
+        let node_ids: Vec<NodeId> = (0..self.world.nodes.len() as NodeId).collect();
+        for nid in node_ids {
+            if self.world.node(nid).status != NodeStatus::Up {
+                continue;
+            }
+            let mut ctx = EngineCtx {
+                sim: self,
+                current_node_id: Some(nid),
+                store_delay: 0,
+                last_sent: None,
+            };
+            Node::shutdown(&mut ctx, nid);
+        }
+        if self.invariant_violation.is_none() {
+            self.check_invariants(true);
+            if self.invariant_violation.is_some() {
+                self.state = SimulationState::Completed;
+                outcome = SimulationOutcome::InvariantViolated;
+            }
+        }
+        self.report(outcome)
+    }
+
+    /// Tells snapshot consumers the run is over: writes out the event spill,
+    /// if any, and publishes a last snapshot carrying `outcome`.
+    pub fn publish_finished(&self, outcome: &SimulationOutcome) {
+        self.telemetry.flush_event_spill();
+        let mut snap = self.telemetry.build_snapshot(&self.world, self.clock);
+        snap.finished = Some(outcome.to_string());
+        self.telemetry.send_snapshot(snap);
+    }
+
+    /// Runs the simulation until a specific time is reached. Stopping there
+    /// only pauses the run, so `on_shutdown` is not called unless the run
+    /// ended first for another reason; see `run_to_end`.
+    pub fn run_until(&mut self, stop_at: SimTime) -> SimulationReport {
+        let outcome = self.run_loop(stop_at);
+        tracing::info!(stop_time = stop_at, %outcome, "Simulation paused at time limit.");
+        match outcome {
+            SimulationOutcome::StopTime(_) => self.report(outcome),
+            _ => self.finish(outcome),
+        }
+    }
+
+    /// Runs the simulation until `stop_at` and ends the run there, or
+    /// earlier if it ends for another reason.
+    pub fn run_to_end(&mut self, stop_at: SimTime) -> SimulationReport {
+        let outcome = self.run_loop(stop_at);
+        tracing::info!(stop_time = stop_at, %outcome, "Simulation finished.");
+        self.finish(outcome)
+    }
+
+    /// Summarizes the run so far, as ended by `outcome`.
+    pub fn report(&self, outcome: SimulationOutcome) -> SimulationReport {
+        let snapshot = self.telemetry.build_snapshot(&self.world, self.clock);
+        let nodes = snapshot
+            .nodes
+            .into_iter()
+            .map(|n| NodeReport {
+                id: n.id,
+                status: n.status,
+                byzantine: n.byzantine,
+                incarnation: self.world.node(n.id).incarnation(),
+                meta: self.world.node(n.id).meta().entries().clone(),
+                custom: n.custom,
+                metrics: n.metrics,
+                store: n.store,
+            })
+            .collect();
+        SimulationReport {
+            outcome,
+            final_time: self.clock,
+            events_processed: self.events_processed,
+            events_by_kind: self.events_by_kind.clone(),
+            nodes,
+            metrics: snapshot.metrics,
+            rng_draws: self.recorder.draw_counts(),
+            interventions: self.interventions.clone(),
+            flood_deferrals: self.flood.deferred.clone(),
+            conditions: self.telemetry.condition_outcomes(self.clock),
+            // Nothing runs between the last event and the stop time
+            slo: self.telemetry.slo_report(match outcome {
+                SimulationOutcome::StopTime(stop_at) => stop_at.max(self.clock),
+                _ => self.clock,
+            }),
+            convergence: self.telemetry.convergence_report(),
+            usage: None,
+        }
+    }
+
+    /// Starts timing how long each kind of event, invariant checks and
+    /// telemetry upkeep take, for `resource_usage`.
+    pub fn enable_profiling(&mut self) {
+        self.usage.enable_profile();
+    }
+
+    /// Returns what the run loop has cost so far. Wall-clock based, so it
+    /// differs between runs of the same seed; `report` leaves it out.
+    pub fn resource_usage(&self) -> ResourceUsage {
+        self.usage.usage(self.events_processed)
+    }
+
+    /// Returns the configuration the simulation is actually running with,
+    /// after the scenario, its defaults and any overrides have been applied.
+    pub fn effective_config(&self) -> EffectiveConfig {
+        let nodes = self
+            .world
+            .nodes
+            .iter()
+            .map(|node| NodeConfig {
+                id: node.id,
+                protocol: node.proto_name(),
+                proto_tag: node.proto_tag(),
+                other_protocols: node.protocols().split_off(1),
+                peers: node.peers().to_vec(),
+                byzantine: node.byzantine(),
+                clock_skew_ns: node.clock_skew_ns,
+                store: node.store().config(),
+                store_latency: node.store_latency(),
+                store_faults: node.store_fault_model(),
+                write_quota: node.write_quota().map(|q| q.spec().clone()),
+                reassembly_timeout: node.reassembly().timeout(),
+                max_reassemblies: node.reassembly().max_pending(),
+            })
+            .collect();
+        let mut links: Vec<LinkConfig> = self
+            .world
+            .net
+            .links
+            .values()
+            .map(|link| LinkConfig {
+                id: link.id,
+                src: link.src,
+                dst: link.dst,
+                faults: link.faults.clone(),
+            })
+            .collect();
+        links.sort_by_key(|link| link.id);
+        EffectiveConfig {
+            seed: self.recorder.seed(),
+            engine: EngineConfig {
+                codec_error_policy: self.codec_error_policy,
+                scheduling: self.scheduling,
+                max_events: self.max_events,
+                stop_on_quiescence: self.stop_on_quiescence,
+                speed: self.speed,
+                wall_timeout_ms: self.wall_timeout.map(|t| t.as_millis() as u64),
+                state_hash_every: self.state_hash_interval,
+                future_message_policy: self.future_message_policy,
+                flood_valve: self.flood_valve,
+                restart_policy: self.restart_policy,
+                caches: CachesSpec { pending_clients: self.pending_clients.spec() },
+                slo: self.telemetry.slo_spec(),
+                invariants: self.invariants.iter().map(|i| i.name().to_string()).collect(),
+                invariant_check_every: self.invariant_interval,
+                breakpoints: self.breakpoints.iter().map(|b| b.to_string()).collect(),
+                store_journal: self.store_journal.is_some(),
+                message_journal: self.message_journal.is_some(),
+                flow_graph: self.flow_graph.as_ref().map(FlowGraph::by_variant),
+                journal_sampling: self.message_journal.as_ref().and_then(|j| j.sampling().cloned()),
+            },
+            telemetry: self.telemetry.spec(),
+            nodes,
+            links,
+            features: effective_config::enabled_features(),
+        }
+    }
+
+    fn run_loop(&mut self, stop_at: SimTime) -> SimulationOutcome {
+        let started = self.usage.begin(self.events_processed);
+        let outcome = self.drive(stop_at);
+        self.usage.end(started, self.events_processed);
+        outcome
+    }
+
+    fn drive(&mut self, stop_at: SimTime) -> SimulationOutcome {
+        let deadline = self
+            .wall_timeout
+            .map(|timeout| (*self.wall_started.get_or_insert_with(Instant::now), timeout));
+        let timed_out = |(started, timeout): (Instant, Duration)| {
+            (started.elapsed() >= timeout).then_some(SimulationOutcome::WallTimeout(timeout.as_millis() as u64))
+        };
+        let mut ticks = 0u64;
+        loop {
+            ticks += 1;
+            if ticks % WALL_CHECK_INTERVAL == 0 {
+                self.usage.sample(self.events_processed);
+                if let Some(outcome) = deadline.and_then(timed_out) {
+                    return outcome;
+                }
+            }
+            match self.tick(stop_at) {
+                Tick::Stepped | Tick::Again => {}
+                Tick::Wait(delay) => {
+                    self.wait(delay);
+                    if let Some(outcome) = deadline.and_then(timed_out) {
+                        return outcome;
+                    }
+                }
+                Tick::Done(outcome) => return outcome,
+            }
+        }
+    }
+
+    /// Stops the run once `max` events have been executed in total.
+    pub fn set_max_events(&mut self, max: Option<u64>) {
+        self.max_events = max;
+    }
+
+    /// Stops the run once the only queued events are UI ticks and faults
+    /// from periodic scenario directives, i.e. the protocols have gone idle.
+    pub fn set_stop_on_quiescence(&mut self, enabled: bool) {
+        self.stop_on_quiescence = enabled;
+    }
+
+    /// Schedules a new event to occur at a future time.
+    pub fn schedule_at(
+        &mut self,
+        when: SimTime,
+        ev: Event,
+        discriminant: EventDiscriminant,
+    ) -> EventId {
+        let event_id = self.id_gen.next_event_id();
+        let queued_event = Queued::new(
+            event_id,
+            when,
+            self.id_gen.next_insertion_seq(),
+            discriminant,
+            self.scheduling,
+            ev,
+        );
+        if !queued_event.is_idle() {
+            self.active_events += 1;
+        }
+        self.queue.push(queued_event);
+        event_id
+    }
+
+    /// Cancels a queued event so that it never runs. Canceled events do not
+    /// advance the clock or count as processed; they are dropped when they
+    /// reach the head of the queue, or earlier when enough of them pile up.
+    pub fn cancel_event(&mut self, id: EventId) {
+        self.canceled.insert(id);
+        self.discard_canceled();
+        if self.canceled.len() >= COMPACT_MIN_CANCELED
+            && self.canceled.len() as f64 > self.queue.len() as f64 * COMPACT_CANCELED_FRACTION
+        {
+            self.compact_queue();
+        }
+    }
+
+    /// Pops canceled events off the head of the queue.
+    fn discard_canceled(&mut self) {
+        while let Some(head) = self.queue.peek() {
+            if !self.canceled.remove(&head.id) {
+                break;
+            }
+            if let Some(head) = self.queue.pop() {
+                if !head.is_idle() {
+                    self.active_events -= 1;
+                }
+            }
+        }
+    }
+
+    /// Rebuilds the queue without its canceled events.
+    fn compact_queue(&mut self) {
+        let canceled = std::mem::take(&mut self.canceled);
+        let mut removed_active = 0;
+        self.queue.retain(|queued| {
+            let keep = !canceled.contains(&queued.id);
+            if !keep && !queued.is_idle() {
+                removed_active += 1;
+            }
+            keep
+        });
+        self.active_events -= removed_active;
+    }
+
+    /// Returns the current simulation time.
+    pub fn now(&self) -> SimTime {
+        self.clock
+    }
+
+    /// Returns the ID of the event being executed, or of the last one.
+    pub(crate) fn current_event(&self) -> EventId {
+        self.current_event
+    }
+
+    /// Returns the number of events executed so far.
+    pub fn events_processed(&self) -> u64 {
+        self.events_processed
+    }
+
+    /// Returns a reference to the telemetry bus.
+    pub fn telemetry(&self) -> &TelemetryBus {
+        &self.telemetry
+    }
+
+    /// Returns a reference to the world state.
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    /// Sets how protocol message decode errors are handled.
+    pub fn set_codec_error_policy(&mut self, policy: CodecErrorPolicy) {
+        self.codec_error_policy = policy;
+    }
+
+    pub fn codec_error_policy(&self) -> CodecErrorPolicy {
+        self.codec_error_policy
+    }
+
+    /// Returns the interventions armed so far, with the message each hit.
+    pub fn interventions(&self) -> &[Intervention] {
+        &self.interventions
+    }
+
+    /// Counts `env` against every armed intervention watching its link and
+    /// returns what the first one it is the target of does to it.
+    fn intervene(&mut self, env: &Envelope) -> Option<InterventionKind> {
+        if !self.interventions.iter().any(|i| i.watches(env.src, env.dst)) {
+            return None;
+        }
+        // Decoding is only worth it when some intervention filters on kind
+        let variant = self
+            .interventions
+            .iter()
+            .any(|i| i.watches(env.src, env.dst) && i.variant.is_some())
+            .then(|| self.world.node(env.dst).message_variant(env.proto_tag, &env.payload))
+            .flatten();
+        let mut action = None;
+        for intervention in self.interventions.iter_mut().filter(|i| i.watches(env.src, env.dst)) {
+            if intervention.observe(env, variant.as_deref(), self.clock) && action.is_none() {
+                tracing::info!(msg_id = %env.msg_id, %intervention, "🎯 Intervention hit");
+                self.telemetry.log_event(
+                    "INTERVENTION".to_string(),
+                    format!("{}: message {}", intervention, env.msg_id),
+                    Some(env.src),
+                    EventSeverity::Fault,
+                );
+                action = Some(intervention.kind);
+            }
+        }
+        action
+    }
+
+    /// Returns decode error counts per (protocol tag, destination node).
+    pub fn codec_error_counts(&self) -> &BTreeMap<(ProtoTag, NodeId), u64> {
+        &self.codec_errors
+    }
+
+    /// Returns unroutable message counts per (protocol tag, destination
+    /// node).
+    pub fn unroutable_counts(&self) -> &BTreeMap<(ProtoTag, NodeId), u64> {
+        &self.unroutable
+    }
+
+    /// Sets how events at the same sim time are ordered. Events already
+    /// queued are reordered under the new policy.
+    pub fn set_scheduling_policy(&mut self, policy: SchedulingPolicy) {
+        self.scheduling = policy;
+        self.queue = std::mem::take(&mut self.queue)
+            .into_iter()
+            .map(|mut queued| {
+                queued.policy = policy;
+                queued
+            })
+            .collect();
+    }
+
+    /// Sets how messages stamped too far in the receiver's future are
+    /// handled; `None` delivers them like any other.
+    pub fn set_future_message_policy(&mut self, policy: Option<FutureMessagePolicy>) {
+        self.future_message_policy = policy;
+    }
+
+    /// Sets the flood valve; `None` turns it off.
+    pub fn set_flood_valve(&mut self, valve: Option<FloodValve>) {
+        self.flood_valve = valve;
+    }
+
+    /// Names the scheduled event `id` after the directive that scheduled it,
+    /// so that its run is logged under `label`.
+    pub fn label_event(&mut self, id: EventId, label: String) {
+        self.event_labels.insert(id, label);
+    }
+
+    /// Sets how the restarts of timed crashes are staggered; `None`
+    /// restarts nodes exactly when their crash ends.
+    pub fn set_restart_policy(&mut self, policy: Option<RestartPolicy>) {
+        self.restart_policy = policy;
+    }
+
+    /// Sets the limits of the engine's bounded caches. Requests already
+    /// pending are dropped, so this belongs before the run starts.
+    pub fn set_caches(&mut self, caches: CachesSpec) {
+        self.pending_clients = BoundedCache::new("pending_clients", caches.pending_clients);
+    }
+
+    /// Holds client request `request` until its node responds. Makes room
+    /// by giving up on the requests pending longest, if need be.
+    pub(crate) fn track_pending_client(&mut self, request: ClientRequestId, pending: PendingClientRequest) {
+        let now = self.clock;
+        let expired = self.pending_clients.expire(now);
+        let evicted = self.pending_clients.insert(request, pending, now);
+        for (request, pending) in expired.into_iter().chain(evicted) {
+            tracing::debug!(node_id = pending.node_id, %request, "Giving up on a pending client request");
+        }
+    }
+
+    /// Returns the events the flood valve deferred, per node. Empty unless
+    /// the run needed the valve.
+    pub fn flood_deferrals(&self) -> &BTreeMap<NodeId, u64> {
+        &self.flood.deferred
+    }
+
+    /// Returns the number of messages from the future, dropped or flagged,
+    /// per receiving node.
+    pub fn future_message_counts(&self) -> &BTreeMap<NodeId, u64> {
+        &self.future_messages
+    }
+
+    /// Returns how many events were not scheduled because their time would
+    /// have overflowed `SimTime`.
+    pub fn time_overflows(&self) -> u64 {
+        self.time_overflows
+    }
+
+    /// Records that an event was not scheduled because computing its time
+    /// overflowed. The run continues without it.
+    pub(crate) fn report_time_overflow(&mut self, site: &'static str, node_id: Option<NodeId>, err: SimError) {
+        tracing::error!(site, node = ?node_id, error = %err, "Event not scheduled: sim time overflow");
+        self.time_overflows += 1;
+        self.telemetry.log_event(
+            "TIME_OVERFLOW".to_string(),
+            format!("{} not scheduled: {}", site, err),
+            node_id,
+            EventSeverity::Warn,
+        );
+    }
+
+    /// Returns the decode error that stopped the run, if any.
+    pub fn codec_failure(&self) -> Option<&CodecFailure> {
+        self.codec_failure.as_ref()
+    }
+
+    /// Counts a message the protocol with `tag` on node `dst` failed to
+    /// decode. The only count of them, whatever the codec error policy.
+    pub(crate) fn count_codec_error(&mut self, tag: ProtoTag, dst: NodeId) {
+        ::metrics::counter!(
+            ftsim_types::metrics::MET_CODEC_ERRORS,
+            ftsim_types::metrics::LBL_NODE => dst.to_string(),
+            ftsim_types::metrics::LBL_PROTO => tag.0.to_string()
+        ).increment(1);
+        *self.codec_errors.entry((tag, dst)).or_insert(0) += 1;
+    }
+
+    pub(crate) fn count_unroutable(&mut self, tag: ProtoTag, dst: NodeId) {
+        *self.unroutable.entry((tag, dst)).or_insert(0) += 1;
+    }
+
+    pub(crate) fn halt_on_codec_error(&mut self, failure: CodecFailure) {
+        self.codec_failure.get_or_insert(failure);
+    }
+
+    /// Starts capturing a trace of every executed event and its RNG draws.
+    pub fn record_trace(&mut self) {
+        self.recorder.enable_trace();
+    }
+
+    /// Returns the captured event trace, if recording is enabled.
+    pub fn event_trace(&self) -> Option<&EventTrace> {
+        self.recorder.trace()
+    }
+
+    /// Re-executes events until every event in `trace` has been checked,
+    /// stopping at the first event that differs. The simulation must have
+    /// been set up exactly like the recorded run, including its seed.
+    pub fn replay(&mut self, trace: EventTrace) -> Result<usize, Box<Divergence>> {
+        let total = trace.events.len();
+        self.recorder.start_replay(trace);
+        while self.recorder.replay_remaining().unwrap_or(0) > 0 && self.step().is_some() {}
+        match self.recorder.finish_replay() {
+            Some(divergence) => Err(Box::new(divergence)),
+            None => Ok(total),
+        }
+    }
+
+    /// Starts recording the value of every RNG draw. Enable before `init`
+    /// to capture draws made while protocols start.
+    pub fn record_rng(&mut self) {
+        self.recorder.enable_rng_recording();
+    }
+
+    /// Returns the recorded RNG draws, if recording is enabled.
+    pub fn rng_recording(&self) -> Option<&RngRecording> {
+        self.recorder.rng_recording()
+    }
+
+    /// Starts recording, for every event that draws, the cumulative draw
+    /// count of the sites it drew from. Enable before `init` to capture
+    /// draws made while protocols start.
+    pub fn record_draw_positions(&mut self) {
+        self.recorder.enable_positions();
+    }
+
+    /// Returns the recorded draw positions, if enabled.
+    pub fn draw_positions(&self) -> Option<&DrawPositions> {
+        self.recorder.positions()
+    }
+
+    /// Checks every following RNG draw against `recording`, stopping the
+    /// run at the first draw that differs. Call before `init`, on a
+    /// simulation set up exactly like the recorded run.
+    pub fn check_rng(&mut self, recording: RngRecording) {
+        self.recorder.start_rng_check(recording);
+    }
+
+    /// Executes events until every draw in the recording passed to
+    /// `check_rng` has been checked, returning how many were.
+    pub fn replay_rng(&mut self) -> Result<usize, Box<RngMismatch>> {
+        let total = self.recorder.rng_check_len().unwrap_or(0);
+        while self.recorder.rng_check_remaining().unwrap_or(0) > 0 && self.step().is_some() {}
+        match self.recorder.finish_rng_check() {
+            Some(mismatch) => Err(Box::new(mismatch)),
+            None => Ok(total),
+        }
+    }
+
+    /// Starts recording every store mutation into an in-memory journal.
+    /// Enable before `init` to capture writes made during initialization.
+    pub fn enable_store_journal(&mut self) {
+        self.store_journal.get_or_insert_with(StoreJournal::new);
+    }
+
+    /// Returns the store journal, if journaling is enabled.
+    pub fn store_journal(&self) -> Option<&StoreJournal> {
+        self.store_journal.as_ref()
+    }
+
+    /// Starts recording every sent message and its deliveries.
+    pub fn enable_message_journal(&mut self) {
+        self.message_journal.get_or_insert_with(MessageJournal::new);
+    }
+
+    /// Starts recording a deterministic sample of sent messages, plus every
+    /// message in the configured always-included categories.
+    pub fn enable_sampled_message_journal(&mut self, sampling: JournalSampling) {
+        let seed = self.recorder.seed();
+        self.message_journal.get_or_insert_with(|| MessageJournal::sampled(sampling, seed));
+    }
+
+    /// Records sent messages into `journal`, replacing any journal already
+    /// enabled.
+    pub fn set_message_journal(&mut self, journal: MessageJournal) {
+        self.message_journal = Some(journal);
+    }
+
+    /// Returns the message journal, if enabled.
+    pub fn message_journal(&self) -> Option<&MessageJournal> {
+        self.message_journal.as_ref()
+    }
+
+    /// Writes the store journal into size-rotated segments at `path` as the
+    /// run goes, rather than holding it until the run ends. Enables store
+    /// journaling; `store_journal` then holds only entries not written yet.
+    #[cfg(feature = "trace-export")]
+    pub fn stream_store_journal(&mut self, path: &std::path::Path, policy: RotationPolicy) -> std::io::Result<()> {
+        self.enable_store_journal();
+        self.journal_streams.store = Some(SegmentWriter::create(path, policy)?);
+        Ok(())
+    }
+
+    /// Writes the message journal into size-rotated segments at `path` as
+    /// the run goes. A record is written once no copy of its message is in
+    /// flight, so no delivery is added to it afterwards, and
+    /// `message_journal` then holds only records not written yet. Enable
+    /// the journal first. Every path streamed to gets every record.
+    #[cfg(feature = "trace-export")]
+    pub fn stream_message_journal(&mut self, path: &std::path::Path, policy: RotationPolicy) -> std::io::Result<()> {
+        let journal = self
+            .message_journal
+            .as_ref()
+            .ok_or_else(|| std::io::Error::other("the message journal is not enabled"))?;
+        self.journal_streams.messages.push(journal.create_segments(path, policy)?);
+        Ok(())
+    }
+
+    /// Writes out what the streamed journals still hold, including the
+    /// records of messages in flight, and closes their segments. Returns
+    /// the manifest of the store journal's output, if streamed, and those
+    /// of the message journal's outputs in the order they were started.
+    #[cfg(feature = "trace-export")]
+    pub fn finish_journal_streams(&mut self) -> std::io::Result<(Option<Manifest>, Vec<Manifest>)> {
+        let (store, messages) = (self.store_journal.as_mut(), self.message_journal.as_mut());
+        self.journal_streams.write(store, messages, &self.queue, true);
+        let streams = std::mem::take(&mut self.journal_streams);
+        if let Some(e) = streams.error {
+            return Err(e);
+        }
+        let store = streams.store.map(SegmentWriter::finish).transpose()?;
+        let messages = streams.messages.into_iter().map(SegmentWriter::finish).collect::<std::io::Result<_>>()?;
+        Ok((store, messages))
+    }
+
+    /// Starts aggregating delivered messages into a flow graph over the
+    /// world's nodes, split by message variant if `by_variant`, with a
+    /// sub-graph per phase.
+    pub fn enable_flow_graph(&mut self, by_variant: bool, phases: &[Phase]) {
+        let nodes = self.world.nodes.iter().map(|n| n.id);
+        self.flow_graph = Some(FlowGraph::new(nodes, by_variant).with_phases(phases));
+    }
+
+    /// Returns the flow graph, if enabled.
+    pub fn flow_graph(&self) -> Option<&FlowGraph> {
+        self.flow_graph.as_ref()
+    }
+
+    /// Returns the fault/event timeline recorded so far.
+    pub fn timeline(&self) -> &Timeline {
+        &self.timeline
+    }
+
+    /// Marks an invariant violation on the timeline.
+    pub fn record_violation(&mut self, node: Option<NodeId>) {
+        self.timeline.record_marker(self.clock, node, MarkerKind::Violation);
+    }
+
+    /// Captures node stores and metadata, node statuses, clock skews, and
+    /// link fault models. The event queue is not included.
+    pub fn checkpoint_world(&self) -> WorldCheckpoint {
+        self.world.checkpoint()
+    }
+
+    /// Restores state captured by `checkpoint_world`.
+    pub fn restore_world(&mut self, checkpoint: WorldCheckpoint) {
+        self.world.restore(checkpoint);
+    }
+
+    /// Saves everything that determines how the run continues: the clock, the
+    /// event queue, the world including every protocol's state, the RNG
+    /// streams, the id generator, the conditions', the SLO's and the
+    /// convergence tracker's progress and the flow graph. Fails if a protocol
+    /// does not implement `snapshot_state`. Other telemetry, journals, the
+    /// timeline and recorded traces are not part of the state and keep
+    /// accumulating across loads.
+    pub fn save_state(&self) -> Result<SimState, SimError> {
+        Ok(SimState {
+            clock: self.clock,
+            queue: self.queue.clone(),
+            world: self.world.save_state()?,
+            rng: self.rng.clone(),
+            id_gen: self.id_gen.clone(),
+            events_processed: self.events_processed,
+            events_by_kind: self.events_by_kind.clone(),
+            active_events: self.active_events,
+            canceled: self.canceled.clone(),
+            events_since_check: self.events_since_check,
+            invariant_violation: self.invariant_violation.clone(),
+            codec_failure: self.codec_failure.clone(),
+            codec_errors: self.codec_errors.clone(),
+            unroutable: self.unroutable.clone(),
+            future_messages: self.future_messages.clone(),
+            time_overflows: self.time_overflows,
+            interventions: self.interventions.clone(),
+            flood: self.flood.clone(),
+            last_restart: self.last_restart,
+            event_labels: self.event_labels.clone(),
+            pending_clients: self.pending_clients.clone(),
+            conditions: self.telemetry.save_conditions(),
+            slo: self.telemetry.save_slo(),
+            convergence: self.telemetry.save_convergence(),
+            flow_graph: self.flow_graph.clone(),
+        })
+    }
+
+    /// Rewinds the simulation to a state captured by `save_state`. The state
+    /// must come from a simulation of the same world. If it cannot be loaded,
+    /// the simulation is left as it was.
+    pub fn load_state(&mut self, state: SimState) -> Result<(), SimError> {
+        self.world.restore_state(state.world)?;
+        self.clock = state.clock;
+        self.queue = state.queue;
+        self.rng = state.rng;
+        self.id_gen = state.id_gen;
+        self.events_processed = state.events_processed;
+        self.events_by_kind = state.events_by_kind;
+        self.active_events = state.active_events;
+        self.canceled = state.canceled;
+        self.events_since_check = state.events_since_check;
+        self.invariant_violation = state.invariant_violation;
+        self.codec_failure = state.codec_failure;
+        self.codec_errors = state.codec_errors;
+        self.unroutable = state.unroutable;
+        self.future_messages = state.future_messages;
+        self.time_overflows = state.time_overflows;
+        self.interventions = state.interventions;
+        self.flood = state.flood;
+        self.last_restart = state.last_restart;
+        self.event_labels = state.event_labels;
+        self.pending_clients = state.pending_clients;
+        self.telemetry.restore_conditions(state.conditions);
+        self.telemetry.restore_slo(state.slo);
+        self.telemetry.restore_convergence(state.convergence);
+        self.flow_graph = state.flow_graph;
+        self.break_skip = None;
+        self.pacing_anchor = None;
+        Ok(())
     }
 
     /// Handles an internal fault event, modifying the world state.
-    fn handle_fault(&mut self, ctx: &mut EngineCtx, fault: FaultEventInternal) {
+    fn handle_fault(ctx: &mut EngineCtx, fault: FaultEventInternal) {
+        ctx.sim.timeline.record_fault(ctx.sim.clock, &fault);
+        let target = fault.node_id();
+        Self::apply_fault(ctx, fault);
+        if let Some(node_id) = target {
+            let status = ctx.sim.world.node(node_id).status;
+            let last = ctx.sim.timeline.transitions.iter().rev().find(|t| t.node == node_id);
+            if last.map_or(status != NodeStatus::Up, |t| t.status != status) {
+                ctx.sim.timeline.record_status(ctx.sim.clock, node_id, status);
+            }
+        }
+    }
+
+    /// Delays the restart of `node_id` due at `restart_time` by a draw from
+    /// the restart policy's stagger, if there is one, recording the offset
+    /// as the node's `restart_stagger_ms` metric. Returns `None` if the
+    /// staggered time overflows.
+    fn stagger_restart(ctx: &mut EngineCtx, node_id: NodeId, restart_time: SimTime) -> Option<SimTime> {
+        let Some(policy) = ctx.sim.restart_policy else {
+            return Some(restart_time);
+        };
+        // Drawn from the engine's stream, so the protocols' draws are the
+        // same with or without a policy
+        let offset = ctx.sample_delay("fault.restart_stagger", None, &policy.stagger);
+        let staggered = match checked_add(restart_time, offset) {
+            Ok(staggered) => staggered,
+            Err(err) => {
+                ctx.sim.report_time_overflow("fault.restart_stagger", Some(node_id), err);
+                return None;
+            }
+        };
+        let telemetry = ctx.sim.telemetry();
+        telemetry.log_node_metric(node_id, "restart_stagger_ms".to_string(), offset as f64 / 1e6);
+        telemetry.log_event(
+            "RestartStaggered".to_string(),
+            format!("restart at t={} instead of t={}", staggered, restart_time),
+            Some(node_id),
+            EventSeverity::Info,
+        );
+        Some(staggered)
+    }
+
+    /// Counts a restart that happens in the same millisecond as the one
+    /// before it.
+    fn count_simultaneous_restart(ctx: &mut EngineCtx) {
+        let now = ctx.sim.clock;
+        let previous = ctx.sim.last_restart.replace(now);
+        if previous.is_some_and(|at| at / 1_000_000 == now / 1_000_000) {
+            ::metrics::counter!(ftsim_types::metrics::MET_SIMULTANEOUS_RESTARTS).increment(1);
+            ctx.sim.telemetry().increment_metric("simultaneous_restarts");
+        }
+    }
+
+    fn apply_fault(ctx: &mut EngineCtx, fault: FaultEventInternal) {
         match fault {
             FaultEventInternal::Crash { node_id, duration } => {
                 ctx.current_node_id = Some(node_id);
-                self.world
-                    .node_mut(node_id)
-                    .apply_fault(ctx, fault.clone());
-                // Schedule the restart if duration is not infinite
-                if duration < MAX_SIM_TIME {
-                    let restart_time = self.clock + duration;
-                    self.schedule_at(
-                        restart_time,
-                        Event::Fault(FaultEventInternal::Restart { node_id }),
-                        EventDiscriminant::fault(),
-                    );
+                Node::apply_fault(ctx, node_id, fault.clone());
+                // Schedule the restart unless the crash is permanent
+                match duration.end_after(ctx.sim.clock) {
+                    Ok(Some(restart_time)) => {
+                        let Some(restart_time) = Self::stagger_restart(ctx, node_id, restart_time) else {
+                            return;
+                        };
+                        ctx.sim.schedule_at(
+                            restart_time,
+                            Event::Fault(FaultEventInternal::Restart { node_id }),
+                            EventDiscriminant::fault(),
+                        );
+                    }
+                    Ok(None) => {}
+                    Err(err) => ctx.sim.report_time_overflow("fault.restart", Some(node_id), err),
                 }
             }
             FaultEventInternal::Restart { node_id } => {
                 ctx.current_node_id = Some(node_id);
-                self.world.node_mut(node_id).apply_fault(ctx, fault);
+                Self::count_simultaneous_restart(ctx);
+                Node::apply_fault(ctx, node_id, fault);
             }
             FaultEventInternal::Partition { sets } => {
-                self.world.net.set_partition(sets);
+                let cut = ctx.sim.world.net.set_partition(sets);
+                tracing::debug!(?cut, "Links partitioned");
             }
             FaultEventInternal::HealPartition => {
-                self.world.net.heal_partition();
+                ctx.sim.world.net.heal_partition();
             }
             FaultEventInternal::ClockSkew { node_id, .. } => {
                 ctx.current_node_id = Some(node_id);
-                self.world.node_mut(node_id).apply_fault(ctx, fault);
+                Node::apply_fault(ctx, node_id, fault);
             }
             FaultEventInternal::StoreFault { node_id, kind, rate } => {
                 // Set the node context
                 ctx.current_node_id = Some(node_id);
                 // Update the store fault model
-                let node = self.world.node_mut(node_id);
+                let node = ctx.sim.world.node_mut(node_id);
                 match kind {
                     StoreFaultKind::FsyncFail => {
                         node.store_faults().fsync_fail_rate = rate;
@@ -429,20 +2183,34 @@ impl Simulation {
                     StoreFaultKind::StaleRead => {
                         node.store_faults().stale_read_rate = rate;
                     }
+                    StoreFaultKind::BitRot => {
+                        node.store_faults().bit_rot_rate = rate;
+                    }
                 }
                 // Propagate the fault to the protocol
-                self.world.node_mut(node_id).apply_fault(ctx, fault);
+                Node::apply_fault(ctx, node_id, fault);
             }
             FaultEventInternal::ByzantineFlip { node_id, enabled } => {
                 ctx.current_node_id = Some(node_id);
                 // Propagate the fault to the protocol and update node state
-                self.world.node_mut(node_id).apply_fault(ctx, fault);
+                Node::apply_fault(ctx, node_id, fault);
                 tracing::info!(node_id, enabled, "Byzantine mode toggled");
             }
+            FaultEventInternal::GrayFailure { node_id, ignore_from, duration } => {
+                ctx.current_node_id = Some(node_id);
+                let until = duration.end_after(ctx.sim.clock).unwrap_or_else(|err| {
+                    ctx.sim.report_time_overflow("fault.gray_failure", Some(node_id), err);
+                    None
+                });
+                tracing::info!(node_id, ?ignore_from, ?until, "Gray failure started");
+                // The node cannot tell it is gray-failing, so unlike the
+                // other node faults this one is not passed to its protocols
+                ctx.sim.world.node_mut(node_id).set_gray_failure(ignore_from, until);
+            }
             FaultEventInternal::LinkModelUpdate { link_id, change } => {
                 use crate::events::LinkModelChange;
 
-                if let Some(link) = self.world.net.links.get_mut(&link_id) {
+                if let Some(link) = ctx.sim.world.net.links.get_mut(&link_id) {
                     match change {
                         LinkModelChange::SetDelay(spec) => {
                             link.faults.base_delay = spec;
@@ -481,31 +2249,33 @@ impl Simulation {
                         );
 
                         // Send to all nodes in the simulation
-                        let node_count = self.world.nodes.len();
+                        let node_count = ctx.sim.world.nodes.len();
                         tracing::info!(target_nodes = node_count, "📡 Broadcasting to all nodes in simulation");
 
                         for node_id in 0..node_count as u32 {
                             // Create an envelope to deliver the raw bytes
-                            // Use the provided proto_tag, defaulting to 0 if none specified
-                            let msg_id = self.id_gen.next_msg_id();
+                            // Use the provided proto_tag, defaulting to the node's first protocol
+                            let msg_id = ctx.sim.id_gen.next_msg_id();
                             let env = Envelope {
                                 src: u32::MAX, // Use max u32 to indicate system/fault injection
                                 dst: node_id,
-                                proto_tag: proto_tag.unwrap_or(ProtoTag(0)),
+                                proto_tag: proto_tag.unwrap_or_else(|| ctx.sim.world.node(node_id).proto_tag()),
                                 payload: payload_bytes.clone(),
                                 msg_id,
-                                create_time: self.clock,
+                                create_time: ctx.sim.clock,
+                                sent_at_local: ctx.sim.clock,
                                 trace_id: 0,
+                                fragment: None,
                             };
 
                             // Schedule immediate delivery
-                            self.schedule_at(
-                                self.clock,
+                            ctx.sim.schedule_at(
+                                ctx.sim.clock,
                                 Event::Deliver { env, link_id: 0 },
                                 EventDiscriminant::delivery(u32::MAX),
                             );
 
-                            tracing::info!(dst = node_id, msg_id, payload_len = payload_bytes.len(), "📨 Scheduled message delivery to node");
+                            tracing::info!(dst = node_id, %msg_id, payload_len = payload_bytes.len(), "📨 Scheduled message delivery to node");
                         }
 
                         tracing::info!(
@@ -514,22 +2284,32 @@ impl Simulation {
                             node_count
                         );
 
-                        self.telemetry.log_event(
+                        ctx.sim.telemetry.log_event(
                             "BROADCAST_BYTES_SUCCESS".to_string(),
                             format!("Successfully broadcasted {} bytes ('{}') to {} nodes", payload_bytes.len(), payload_str.trim(), node_count),
-                            None
+                            None,
+                            EventSeverity::Info
                         );
                     }
                     Err(err) => {
                         tracing::error!(error = %err, payload_hex = %payload_hex, "❌ Failed to decode hex payload for BroadcastBytes");
-                        self.telemetry.log_event(
+                        ctx.sim.telemetry.log_event(
                             "BROADCAST_BYTES_ERROR".to_string(),
                             format!("Failed to decode hex payload: {}", err),
-                            None
+                            None,
+                            EventSeverity::Warn
                         );
                     }
                 }
             }
+            FaultEventInternal::Intervene(intervention) => {
+                tracing::info!(%intervention, "Intervention armed");
+                ctx.sim.interventions.push(intervention);
+            }
+            #[cfg(any(test, feature = "testutil"))]
+            FaultEventInternal::Custom { name, .. } if name == crash_context::TEST_PANIC_FAULT => {
+                panic!("injected {} fault", name);
+            }
             // Other custom faults are handled here.
             FaultEventInternal::Custom { name, args } => {
                 tracing::warn!(name, ?args, "Custom fault handling not implemented for this type");
@@ -564,201 +2344,4527 @@ fn decode_hex(hex_str: &str) -> Result<bytes::Bytes, String> {
 pub struct EngineCtx<'a> {
     pub sim: &'a mut Simulation,
     pub current_node_id: Option<NodeId>,
+    /// Simulated store latency accumulated so far by the current handler.
+    /// Handlers run synchronously, so sends and timers issued after store
+    /// operations are shifted later by this amount.
+    pub store_delay: SimTime,
+    /// The ID of the last message the current handler sent.
+    pub last_sent: Option<MsgId>,
 }
 
 impl<'a> EngineCtx<'a> {
-    /// Provides a disciplined way to access the master RNG.
-    pub fn rng(&mut self, site_label: &'static str) -> RngDiscipline {
-        RngDiscipline::new(&mut self.sim.rng, &mut self.sim.recorder, site_label)
+    /// Converts sim time `time` to node `node_id`'s clock. A clock skewed
+    /// past either end of the range reads as that end.
+    pub fn node_clock(&self, node_id: NodeId, time: SimTime) -> SimTime {
+        let skew = self.sim.world.node(node_id).clock_skew_ns;
+        match checked_skew(time, skew) {
+            Ok(time) => time,
+            Err(SimError::TimeUnderflow { .. }) => SIM_EPOCH,
+            Err(_) => MAX_SIM_TIME,
+        }
     }
-}
 
-/// Implementation of the `ProtoCtx` trait that the engine provides to protocols.
-/// This is the bridge between the protocol's world and the engine's world.
-impl<'a> ProtoCtx for EngineCtx<'a> {
-    fn send_raw(&mut self, dst: NodeId, proto_tag: ProtoTag, bytes: bytes::Bytes) {
-        let src = self
-            .current_node_id
-            .expect("Cannot send without a source node context");
-        let msg_id = self.sim.id_gen.next_msg_id();
-        let env = Envelope {
-            src,
-            dst,
-            proto_tag,
-            payload: bytes,
-            msg_id,
-            create_time: self.sim.clock,
-            trace_id: 0, // TODO: Implement tracing correlation
-        };
-        tracing::debug!(src, dst, msg_id, "📤 Sending message");
-        self.sim.telemetry.log_event(
-            "MESSAGE_SENT".to_string(),
-            format!("Message {} sent from node {} to node {}", msg_id, src, dst),
-            Some(src)
-        );
-        self.sim.telemetry.increment_metric("messages_sent");
-        // Use raw pointer to avoid double borrow
-        let net_ptr = &mut self.sim.world.net as *mut crate::net::Net;
-        unsafe {
-            (*net_ptr).send(self, env);
-        }
+    /// Returns the time at which side effects issued now take effect: the
+    /// current clock plus any store latency accumulated by this handler.
+    pub fn busy_until(&self) -> Result<SimTime, SimError> {
+        checked_add(self.sim.now(), self.store_delay)
     }
 
-    fn broadcast_raw(
-        &mut self,
-        proto_tag: ProtoTag,
-        bytes: bytes::Bytes,
-        filter: Option<&dyn Fn(NodeId) -> bool>,
-    ) {
-        let src = self
+    /// Reports that an event `site` was about to schedule was dropped
+    /// because its time overflowed.
+    pub(crate) fn time_overflow(&mut self, site: &'static str, err: SimError) {
+        let node_id = self.current_node_id;
+        self.sim.report_time_overflow(site, node_id, err);
+    }
+
+    /// Samples a store operation's latency and charges it to this handler.
+    fn charge_store_latency(&mut self, site_label: &'static str, spec: &DelaySpec) {
+        let node_id = self.node_id();
+        let delay = self.sample_delay(site_label, Some(node_id), spec);
+        self.charge_store_delay(delay);
+    }
+
+    /// Charges `delay` of store time to this handler.
+    fn charge_store_delay(&mut self, delay: SimTime) {
+        if delay == 0 {
+            return;
+        }
+        let node_id = self.node_id();
+        self.store_delay = match checked_add(self.store_delay, delay) {
+            Ok(store_delay) => store_delay,
+            Err(err) => {
+                // Everything this handler schedules from now on overflows too
+                self.time_overflow("store.latency", err);
+                MAX_SIM_TIME
+            }
+        };
+        ::metrics::counter!(
+            ftsim_types::metrics::MET_STORE_TIME,
+            ftsim_types::metrics::LBL_NODE => node_id.to_string()
+        )
+        .increment(delay as u64);
+        self.sim.telemetry.add_store_time(delay);
+    }
+
+    /// Provides a disciplined way to access the RNG stream of `site`,
+    /// recording draws under it.
+    pub fn rng(&mut self, site: &'static str) -> RngDiscipline {
+        let site = self.sim.recorder.site(site, None);
+        RngDiscipline::new(&mut self.sim.rng, &mut self.sim.recorder, site)
+    }
+
+    /// Like `rng`, but records draws under `site` on `node_id`.
+    pub fn node_rng(&mut self, site: &'static str, node_id: NodeId) -> RngDiscipline {
+        let site = self.sim.recorder.site(site, Some(node_id));
+        RngDiscipline::new(&mut self.sim.rng, &mut self.sim.recorder, site)
+    }
+
+    /// Draws a delay from `spec` at `site`, on `node`'s stream if given.
+    /// Draws clamped into the spec's bounds are counted as `delay_clamped`,
+    /// so a misconfigured distribution does not go unnoticed.
+    pub fn sample_delay(&mut self, site: &'static str, node: Option<NodeId>, spec: &DelaySpec) -> SimTime {
+        let rng = match node {
+            Some(node_id) => self.node_rng(site, node_id),
+            None => self.rng(site),
+        };
+        let sample = crate::net::sample_delay(rng, spec);
+        count_clamped(&self.sim.telemetry, site, spec, sample)
+    }
+
+    /// Borrows the fault model of `link_id` alongside the engine's RNG.
+    pub(crate) fn link_sampler(&mut self, link_id: LinkId) -> LinkSampler<'_> {
+        let sim = &mut *self.sim;
+        LinkSampler {
+            model: &sim.world.net.links[&link_id].faults,
+            rng: &mut sim.rng,
+            recorder: &mut sim.recorder,
+            telemetry: &sim.telemetry,
+        }
+    }
+}
+
+/// A link's fault model and the engine's RNG, borrowed together so that a
+/// message can be sampled against the model without copying it.
+pub(crate) struct LinkSampler<'a> {
+    pub model: &'a LinkFaultModel,
+    rng: &'a mut RngStreams,
+    recorder: &'a mut Recorder,
+    telemetry: &'a TelemetryBus,
+}
+
+impl LinkSampler<'_> {
+    /// Like `EngineCtx::rng`.
+    pub fn rng(&mut self, site: &'static str) -> RngDiscipline {
+        let site = self.recorder.site(site, None);
+        RngDiscipline::new(self.rng, self.recorder, site)
+    }
+
+    /// Like `EngineCtx::sample_delay` on the engine's stream.
+    pub fn sample_delay(&mut self, site: &'static str, spec: &DelaySpec) -> SimTime {
+        let sample = crate::net::sample_delay(self.rng(site), spec);
+        count_clamped(self.telemetry, site, spec, sample)
+    }
+}
+
+/// Counts `sample` as `delay_clamped` if it was clamped into `spec`'s
+/// bounds, so a misconfigured distribution does not go unnoticed, and
+/// returns the delay.
+fn count_clamped(telemetry: &TelemetryBus, site: &'static str, spec: &DelaySpec, sample: SampledDelay) -> SimTime {
+    if sample.clamped {
+        tracing::debug!(site, delay = sample.delay, ?spec, "Sampled delay clamped into its bounds");
+        ::metrics::counter!(
+            ftsim_types::metrics::MET_DELAY_CLAMPED,
+            ftsim_types::metrics::LBL_KIND => site
+        )
+        .increment(1);
+        telemetry.increment_metric("delay_clamped");
+    }
+    sample.delay
+}
+
+impl EngineCtx<'_> {
+    /// Feeds a published role to the timeline, from the `role` KV or the
+    /// `role` field of a published state.
+    fn observe_role(&mut self, key: &str, val: &serde_json::Value) {
+        let role = match key {
+            "role" => val.as_str(),
+            STATE_KEY => val.get("role").and_then(|role| role.as_str()),
+            _ => None,
+        };
+        if let Some(role) = role {
+            let (now, node_id) = (self.sim.clock, self.node_id());
+            self.sim.timeline.observe_role(now, node_id, role);
+        }
+    }
+
+    /// Sends a message that leaves the sender `after` from now. Its ID is
+    /// assigned now, but its create time, the sender's clock stamp and the
+    /// link's faults are those of when it leaves, and it never leaves if
+    /// the sender crashes first.
+    fn send_message(&mut self, dst: NodeId, proto_tag: ProtoTag, bytes: bytes::Bytes, trace_id: u64, after: SimTime) {
+        let src = self
             .current_node_id
-            .expect("Cannot broadcast without a source node context");
-        let peers = self.sim.world.node(src).peers().to_vec(); // Avoid borrow issues
-        for dst in peers {
-            if dst != src && filter.map_or(true, |f| f(dst)) {
-                self.send_raw(dst, proto_tag, bytes.clone());
+            .expect("Cannot send without a source node context");
+        let departs_at = match checked_add(self.sim.clock, after) {
+            Ok(time) => time,
+            Err(err) => {
+                self.time_overflow("net.send_after", err);
+                return;
+            }
+        };
+        let msg_id = self.sim.id_gen.next_msg_id();
+        self.last_sent = Some(msg_id);
+        let env = Envelope {
+            src,
+            dst,
+            proto_tag,
+            payload: bytes,
+            msg_id,
+            create_time: self.sim.clock,
+            sent_at_local: self.now(),
+            trace_id,
+            fragment: None,
+        };
+        if after == 0 {
+            return self.depart(env);
+        }
+        let incarnation = self.sim.world.node(src).incarnation();
+        self.sim.schedule_at(departs_at, Event::Depart { env, incarnation }, EventDiscriminant::timer(src));
+    }
+
+    /// Sends `env` on its way now, through any intervention and the link's
+    /// faults.
+    fn depart(&mut self, env: Envelope) {
+        let (src, dst, msg_id, proto_tag) = (env.src, env.dst, env.msg_id, env.proto_tag);
+        let node = self.sim.world.node(src);
+        let net = &self.sim.world.net;
+        let view = SenderView {
+            status: node.status,
+            any_link_partitioned: net.any_partitioned(src),
+            partitioned_from_dst: net.link_between(src, dst).is_some_and(|l| l.faults.partitioned),
+            byzantine: node.byzantine(),
+            incarnation: node.incarnation(),
+            leader: self.sim.timeline.is_leader(src),
+        };
+        tracing::debug!(
+            src,
+            dst,
+            %msg_id,
+            status = ?view.status,
+            partitioned = view.any_link_partitioned,
+            byzantine = view.byzantine,
+            incarnation = view.incarnation,
+            "📤 Sending message"
+        );
+        self.sim.telemetry.log_event(
+            "MESSAGE_SENT".to_string(),
+            format!(
+                "Message {} sent from node {} to node {} (status {:?}, partitioned {}, byzantine {}, incarnation {})",
+                msg_id, src, dst, view.status, view.any_link_partitioned, view.byzantine, view.incarnation
+            ),
+            Some(src),
+            EventSeverity::Debug
+        );
+        let (sent_at, event_id) = (env.create_time, self.sim.current_event);
+        if let Some(journal) = &mut self.sim.message_journal {
+            journal.record_send(MessageRecord {
+                msg_id,
+                src,
+                dst,
+                proto_tag,
+                sent_at,
+                event_id: Some(event_id),
+                view: Some(view),
+                sampled: true,
+                deliveries: Vec::new(),
+                bytes: env.payload.len() as u64,
+                variant: self.sim.world.node(dst).message_variant(proto_tag, &env.payload),
+                payload: journal.keeps_payloads().then(|| env.payload.clone()),
+            });
+        }
+        self.sim.telemetry.increment_metric("messages_sent");
+        self.sim.telemetry.record_send(src);
+        match self.sim.intervene(&env) {
+            Some(InterventionKind::Drop) => {
+                ::metrics::counter!(
+                    ftsim_types::metrics::MET_NET_MSG_DROPPED,
+                    ftsim_types::metrics::LBL_REASON => "intervention",
+                    ftsim_types::metrics::LBL_SRC => src.to_string(),
+                    ftsim_types::metrics::LBL_DST => dst.to_string()
+                ).increment(1);
+                let link_id = self.sim.world.net.link_between(src, dst).map(|l| l.id);
+                self.sim.telemetry.record_drop(dst, link_id);
             }
+            Some(InterventionKind::Delay(by)) => Net::send(self, env, by),
+            None => Net::send(self, env, 0),
+        }
+    }
+
+    /// Broadcasts to this node's peers, each message leaving `after` from
+    /// now.
+    fn broadcast_message(
+        &mut self,
+        proto_tag: ProtoTag,
+        bytes: bytes::Bytes,
+        after: SimTime,
+        filter: Option<&dyn Fn(NodeId) -> bool>,
+    ) {
+        let src = self
+            .current_node_id
+            .expect("Cannot broadcast without a source node context");
+        // Destinations go out in ascending order whatever order the peers
+        // list is in, so message ids and RNG draws do not depend on it
+        let dsts: Vec<NodeId> = self
+            .peers()
+            .into_iter()
+            .filter(|&dst| filter.map_or(true, |f| f(dst)))
+            .collect();
+        tracing::debug!(src, ?dsts, "📣 Broadcasting message");
+        self.sim.telemetry.log_event(
+            "BROADCAST".to_string(),
+            format!("Node {} broadcast {} bytes to nodes {:?}", src, bytes.len(), dsts),
+            Some(src),
+            EventSeverity::Debug,
+        );
+        for dst in dsts {
+            self.send_message(dst, proto_tag, bytes.clone(), 0, after);
         }
     }
+}
+
+/// Implementation of the `ProtoCtx` trait that the engine provides to protocols.
+/// This is the bridge between the protocol's world and the engine's world.
+impl<'a> ProtoCtx for EngineCtx<'a> {
+    fn send_raw(&mut self, dst: NodeId, proto_tag: ProtoTag, bytes: bytes::Bytes) {
+        self.send_message(dst, proto_tag, bytes, 0, 0);
+    }
+
+    fn send_traced_raw(&mut self, dst: NodeId, proto_tag: ProtoTag, bytes: bytes::Bytes, trace_id: u64) {
+        self.send_message(dst, proto_tag, bytes, trace_id, 0);
+    }
+
+    fn send_raw_after(&mut self, dst: NodeId, proto_tag: ProtoTag, bytes: bytes::Bytes, delay: SimTime) {
+        self.send_message(dst, proto_tag, bytes, 0, delay);
+    }
+
+    fn broadcast_raw(
+        &mut self,
+        proto_tag: ProtoTag,
+        bytes: bytes::Bytes,
+        filter: Option<&dyn Fn(NodeId) -> bool>,
+    ) {
+        self.broadcast_message(proto_tag, bytes, 0, filter);
+    }
+
+    fn broadcast_raw_after(
+        &mut self,
+        proto_tag: ProtoTag,
+        bytes: bytes::Bytes,
+        delay: SimTime,
+        filter: Option<&dyn Fn(NodeId) -> bool>,
+    ) {
+        self.broadcast_message(proto_tag, bytes, delay, filter);
+    }
 
-    fn set_timer(&mut self, after: SimTime) -> TimerId {
+    fn set_timer_raw(&mut self, after: SimTime, payload: Option<bytes::Bytes>) -> TimerId {
         let node_id = self
             .current_node_id
             .expect("Cannot set a timer without a node context");
-        // Use raw pointer to avoid double borrow
-        let node_ptr = self.sim.world.node_mut(node_id) as *mut crate::node::runtime::Node;
-        unsafe {
-            (*node_ptr).set_timer(self, after)
-        }
+        Node::set_timer(self, node_id, after, payload)
+    }
+
+    fn set_periodic_timer_raw(&mut self, period: SimTime, payload: Option<bytes::Bytes>) -> TimerId {
+        let node_id = self
+            .current_node_id
+            .expect("Cannot set a timer without a node context");
+        Node::set_periodic_timer(self, node_id, period, payload)
+    }
+
+    fn set_watermark(&mut self, at: SimTime) -> TimerId {
+        let node_id = self
+            .current_node_id
+            .expect("Cannot set a watermark without a node context");
+        // `at` is on the node's clock; undo the skew to get sim time. Times
+        // before the epoch are clamped to now by `set_watermark`.
+        let skew = self.sim.world.node(node_id).clock_skew_ns;
+        let at = match checked_skew(at, -skew) {
+            Ok(at) => at,
+            Err(SimError::TimeUnderflow { .. }) => SIM_EPOCH,
+            Err(err) => {
+                self.time_overflow("node.watermark", err);
+                return self.sim.id_gen.next_timer_id();
+            }
+        };
+        Node::set_watermark(self, node_id, at)
     }
 
     fn cancel_timer(&mut self, timer_id: TimerId) -> bool {
         let node_id = self
             .current_node_id
             .expect("Cannot cancel a timer without a node context");
-        self.sim.world.node_mut(node_id).cancel_timer(timer_id)
+        match self.sim.world.node_mut(node_id).cancel_timer(timer_id) {
+            Some(event_id) => {
+                self.sim.cancel_event(event_id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn timer_remaining(&self, timer_id: TimerId) -> Option<SimTime> {
+        let node_id = self.node_id();
+        self.sim.world.node(node_id).timer_remaining(self.sim.clock, timer_id)
+    }
+
+    fn pending_timers(&self) -> Vec<(TimerId, SimTime)> {
+        let node_id = self.node_id();
+        self.sim.world.node(node_id).pending_timers(self.sim.clock)
+    }
+
+    fn extend_timer(&mut self, timer_id: TimerId, additional: SimTime) -> bool {
+        let node_id = self
+            .current_node_id
+            .expect("Cannot extend a timer without a node context");
+        Node::extend_timer(self, node_id, timer_id, additional)
     }
 
     fn now(&self) -> SimTime {
         let node_id = self
             .current_node_id
             .expect("Cannot get time without a node context");
-        let base = self.sim.clock;
-        let skew = self.sim.world.node(node_id).clock_skew_ns;
-
-        if skew >= 0 {
-            base.saturating_add(skew as u128)
-        } else {
-            base.saturating_sub((-skew) as u128)
-        }
+        self.node_clock(node_id, self.sim.clock)
     }
 
     fn node_id(&self) -> NodeId {
         self.current_node_id.expect("No node context")
     }
 
-    fn store(&mut self) -> Box<dyn ftsim_proto::api::StoreView + '_> {
+    fn peers(&self) -> Vec<NodeId> {
         let node_id = self.node_id();
-        // Use raw pointers to avoid double mutable borrow
-        let node_ptr = self.sim.world.node_mut(node_id) as *mut crate::node::runtime::Node;
-        unsafe {
-            // Get raw pointers to the store components from the node
-            let view_ptr = (*node_ptr).store_view() as *mut dyn StoreView;
-            let faults_ptr = (*node_ptr).store_faults() as *mut StoreFaultModel;
-            Box::new(EngineStoreWrapper {
-                view: &mut *view_ptr,
-                faults: &mut *faults_ptr,
-                ctx: self,
-                node_id,
-            })
+        let mut peers: Vec<NodeId> = self
+            .sim
+            .world
+            .node(node_id)
+            .peers()
+            .iter()
+            .copied()
+            .filter(|&peer| peer != node_id)
+            .collect();
+        peers.sort_unstable();
+        peers.dedup();
+        peers
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.sim.world.nodes.len()
+    }
+
+    fn message_meta(&self) -> Option<MessageMeta> {
+        self.sim.delivering
+    }
+
+    fn last_sent_msg_id(&self) -> Option<MsgId> {
+        self.last_sent
+    }
+
+    fn node_meta_get(&self, key: &str) -> Option<String> {
+        self.sim.world.node(self.node_id()).meta().get(key).map(str::to_string)
+    }
+
+    fn client_request_id(&self) -> Option<ClientRequestId> {
+        self.sim.serving_client
+    }
+
+    fn respond_to_client(&mut self, request: ClientRequestId, response: ClientResponse) {
+        let node_id = self.node_id();
+        match self.sim.pending_clients.get(&request) {
+            Some(pending) if pending.node_id == node_id && response != ClientResponse::Pending => {
+                let pending = self.sim.pending_clients.remove(&request).expect("checked above");
+                Node::answer_client(self, request, pending, response);
+            }
+            _ => tracing::debug!(node_id, %request, ?response, "Ignoring a response to a request that is not pending here"),
         }
     }
 
+    fn pending_maintenance(&self) -> Vec<Maintenance> {
+        let node_id = self.node_id();
+        let node = self.sim.world.node(node_id);
+        node.pending_maintenance(self.sim.clock)
+            .map(|m| Maintenance { at: self.node_clock(node_id, m.at), ..m })
+            .collect()
+    }
+
+    fn store(&mut self) -> Box<dyn ftsim_proto::api::StoreView + '_> {
+        let node_id = self.node_id();
+        let node = self.sim.world.node(node_id);
+        Box::new(EngineStoreWrapper {
+            faults: node.store_fault_model(),
+            latency: node.store_latency(),
+            ctx: self,
+            node_id,
+        })
+    }
+
     fn rng_u64(&mut self) -> u64 {
         use rand::Rng;
-        let site = Box::leak(format!("proto.node[{}]", self.node_id()).into_boxed_str());
-        self.rng(site).gen()
+        let node_id = self.node_id();
+        self.node_rng("proto", node_id).gen()
+    }
+
+    fn rng_range(&mut self, range: std::ops::RangeInclusive<u64>) -> u64 {
+        use rand::Rng;
+        let node_id = self.node_id();
+        self.node_rng("proto.range", node_id).gen_range(range)
+    }
+
+    fn rng_bool(&mut self, p: f64) -> bool {
+        let node_id = self.node_id();
+        bernoulli(&mut self.node_rng("proto.bool", node_id), p)
+    }
+
+    fn rng_pick(&mut self, len: usize) -> usize {
+        use rand::Rng;
+        let node_id = self.node_id();
+        self.node_rng("proto.choose", node_id).gen_range(0..len)
+    }
+
+    fn rng_permutation(&mut self, len: usize) -> Vec<usize> {
+        use rand::seq::SliceRandom;
+        let node_id = self.node_id();
+        let mut order: Vec<usize> = (0..len).collect();
+        order.shuffle(&mut self.node_rng("proto.shuffle", node_id));
+        order
     }
 
     fn log_kv(&mut self, key: &'static str, val: &str) {
         // Convert the string to a JSON value for consistency with telemetry
-        let json_val = serde_json::Value::String(val.to_string());
-        self.sim.telemetry.log_node_kv(self.node_id(), key.to_string(), json_val);
+        self.log_kv_value(key, serde_json::Value::String(val.to_string()));
+    }
+
+    fn log_kv_pinned(&mut self, key: &'static str, val: &str) {
+        self.log_kv_value_pinned(key, serde_json::Value::String(val.to_string()));
+    }
+
+    fn log_kv_value(&mut self, key: &'static str, val: serde_json::Value) {
+        self.observe_role(key, &val);
+        self.sim.telemetry.log_node_kv(self.node_id(), key.to_string(), val);
+    }
+
+    fn log_kv_value_pinned(&mut self, key: &'static str, val: serde_json::Value) {
+        self.observe_role(key, &val);
+        self.sim
+            .telemetry
+            .log_node_kv_pinned(self.node_id(), key.to_string(), val);
+    }
+
+    fn log_metric(&mut self, key: &'static str, value: f64) {
+        self.sim.telemetry.log_node_metric(self.node_id(), key.to_string(), value);
+    }
+
+    fn log_histogram(&mut self, key: &'static str, value: f64) {
+        self.sim.telemetry.record_histogram(self.node_id(), key, value);
     }
 }
 
 /// A simple wrapper that bridges the engine's StoreView to the protocol's StoreView.
+/// It reaches the node's store through `ctx` on every operation, since the
+/// context already borrows the node.
 struct EngineStoreWrapper<'a, 'b> {
-    view: &'a mut dyn StoreView,
-    faults: &'a mut StoreFaultModel,
+    faults: StoreFaultModel,
+    latency: Option<StoreLatencySpec>,
     ctx: &'a mut EngineCtx<'b>,
     node_id: NodeId,
 }
 
+impl EngineStoreWrapper<'_, '_> {
+    /// Returns the node's store, journaled if the simulation keeps a store
+    /// journal.
+    fn view(&mut self) -> Box<dyn StoreView + '_> {
+        let sim = &mut *self.ctx.sim;
+        let view = sim.world.node_mut(self.node_id).store_view();
+        match &mut sim.store_journal {
+            Some(journal) => Box::new(JournalingStoreView::new(
+                view,
+                journal,
+                sim.clock,
+                sim.current_event,
+                self.node_id,
+            )),
+            None => Box::new(view),
+        }
+    }
+
+    fn charge_read(&mut self) {
+        if let Some(latency) = self.latency {
+            self.ctx.charge_store_latency("store.latency.read", &latency.read);
+        }
+    }
+
+    fn charge_write(&mut self) {
+        if let Some(latency) = self.latency {
+            self.ctx.charge_store_latency("store.latency.write", &latency.write);
+        }
+    }
+
+    /// Charges a write of `bytes` against the node's quota, delaying the
+    /// handler or failing the write if the quota is exhausted.
+    fn charge_quota(&mut self, bytes: usize) -> Result<(), StoreError> {
+        // A handler whose store time overflowed, already reported, charges
+        // at the clock: the end of time would refill the bucket for good
+        let now = self.ctx.busy_until().unwrap_or_else(|_| self.ctx.sim.now());
+        let Some(quota) = self.ctx.sim.world.node_mut(self.node_id).write_quota_mut() else {
+            return Ok(());
+        };
+        let decision = quota.charge(now, bytes as u64);
+        if decision == QuotaDecision::Admit {
+            return Ok(());
+        }
+        let node_id = self.node_id;
+        ::metrics::counter!(
+            ftsim_types::metrics::MET_STORE_THROTTLED,
+            ftsim_types::metrics::LBL_NODE => node_id.to_string()
+        )
+        .increment(1);
+        match decision {
+            QuotaDecision::Delay(wait) => {
+                self.ctx.charge_store_delay(wait);
+                Ok(())
+            }
+            _ => {
+                tracing::debug!(%node_id, bytes, "Throttling store write over quota");
+                Err(StoreError::Throttled { bytes: bytes as u64 })
+            }
+        }
+    }
+
+    fn charge_fsync(&mut self) {
+        if let Some(latency) = self.latency {
+            self.ctx.charge_store_latency("store.latency.fsync", &latency.fsync);
+        }
+    }
+}
+
 impl ftsim_proto::api::StoreView for EngineStoreWrapper<'_, '_> {
     fn append_log(&mut self, rec: LogRecord) -> Result<LogIndex, StoreError> {
-        use rand::Rng;
         let node_id = self.node_id;
+        self.charge_write();
 
-        if self.faults.write_error_rate > 0.0 {
-            let site = Box::leak(format!("store.append.write_error.node[{}]", node_id).into_boxed_str());
-            if self.ctx.rng(site).gen_bool(self.faults.write_error_rate) {
-                tracing::warn!(%node_id, "Injecting write error in append_log");
-                return Err(StoreError::FaultInjected);
-            }
+        if self.faults.write_error_rate > 0.0
+            && bernoulli(&mut self.ctx.node_rng("store.append.write_error", node_id), self.faults.write_error_rate)
+        {
+            tracing::warn!(%node_id, "Injecting write error in append_log");
+            return Err(StoreError::FaultInjected);
         }
 
-        if self.faults.torn_write_rate > 0.0 {
-            let site = Box::leak(format!("store.append.torn_write.node[{}]", node_id).into_boxed_str());
-            if self.ctx.rng(site).gen_bool(self.faults.torn_write_rate) {
-                tracing::warn!(%node_id, "Injecting torn write in append_log");
-                return Err(StoreError::FaultInjected);
-            }
+        if self.faults.torn_write_rate > 0.0
+            && bernoulli(&mut self.ctx.node_rng("store.append.torn_write", node_id), self.faults.torn_write_rate)
+        {
+            tracing::warn!(%node_id, "Injecting torn write in append_log");
+            return Err(StoreError::FaultInjected);
         }
 
-        self.view.append_log(rec)
+        self.charge_quota(rec.data.len())?;
+        let index = self.view().append_log(rec)?;
+        let node = self.ctx.sim.world.node_mut(node_id);
+        if node.store().config().map_or(true, |config| config.durability == Durability::AlwaysDurable) {
+            node.mark_log_durable();
+        }
+        Ok(index)
     }
 
     fn read_log(&mut self, idx: LogIndex) -> Result<Option<LogRecord>, StoreError> {
-        use rand::Rng;
         let node_id = self.node_id;
+        self.charge_read();
 
-        if self.faults.read_error_rate > 0.0 {
-            let site = Box::leak(format!("store.read.read_error.node[{}]", node_id).into_boxed_str());
-            if self.ctx.rng(site).gen_bool(self.faults.read_error_rate) {
-                tracing::warn!(%node_id, "Injecting read error in read_log");
-                return Err(StoreError::FaultInjected);
-            }
+        if self.faults.read_error_rate > 0.0
+            && bernoulli(&mut self.ctx.node_rng("store.read.read_error", node_id), self.faults.read_error_rate)
+        {
+            tracing::warn!(%node_id, "Injecting read error in read_log");
+            return Err(StoreError::FaultInjected);
         }
 
-        if self.faults.stale_read_rate > 0.0 {
-            let site = Box::leak(format!("store.read.stale_read.node[{}]", node_id).into_boxed_str());
-            if self.ctx.rng(site).gen_bool(self.faults.stale_read_rate) {
-                tracing::warn!(%node_id, "Injecting stale read in read_log");
-                return Ok(None);
-            }
+        if self.faults.stale_read_rate > 0.0
+            && bernoulli(&mut self.ctx.node_rng("store.read.stale_read", node_id), self.faults.stale_read_rate)
+        {
+            tracing::warn!(%node_id, "Injecting stale read in read_log");
+            return Ok(None);
         }
 
-        self.view.read_log(idx)
+        let rec = self.view().read_log(idx)?;
+
+        if let Some(rec) = rec {
+            if self.faults.bit_rot_rate > 0.0 {
+                let mut rng = self.ctx.node_rng("store.read.bit_rot", node_id);
+                if bernoulli(&mut rng, self.faults.bit_rot_rate) {
+                    tracing::warn!(%node_id, idx, "Injecting bit rot in read_log");
+                    return Ok(Some(crate::store::rot_record(&mut rng, rec)));
+                }
+            }
+            return Ok(Some(rec));
+        }
+        Ok(None)
     }
 
     fn kv_put(&mut self, k: bytes::Bytes, v: bytes::Bytes) -> Result<(), StoreError> {
-        self.view.kv_put(k, v)
+        self.charge_write();
+        self.charge_quota(k.len() + v.len())?;
+        self.view().kv_put(k, v)
     }
 
     fn kv_get(&mut self, k: &[u8]) -> Result<Option<bytes::Bytes>, StoreError> {
-        self.view.kv_get(k)
+        self.charge_read();
+        self.view().kv_get(k)
     }
 
     fn fsync(&mut self) -> Result<(), StoreError> {
         // Inject faults like FaultyStoreView does
-        use rand::Rng;
         let node_id = self.node_id;
-        let site = Box::leak(format!("store.fsync.node[{}]", node_id).into_boxed_str());
-        if self.ctx.rng(site).gen_bool(self.faults.fsync_fail_rate) {
+        self.charge_fsync();
+        if bernoulli(&mut self.ctx.node_rng("store.fsync", node_id), self.faults.fsync_fail_rate) {
             tracing::warn!(%node_id, "Injecting fsync failure");
             return Err(StoreError::FaultInjected);
         }
-        self.view.fsync()
+        self.view().fsync()?;
+        self.ctx.sim.world.node_mut(node_id).mark_log_durable();
+        Ok(())
+    }
+
+    fn write_snapshot(&mut self, meta: SnapshotMeta, data: bytes::Bytes) -> Result<(), StoreError> {
+        let node_id = self.node_id;
+        self.charge_write();
+
+        if self.faults.write_error_rate > 0.0
+            && bernoulli(&mut self.ctx.node_rng("store.snapshot.write_error", node_id), self.faults.write_error_rate)
+        {
+            tracing::warn!(%node_id, "Injecting write error in write_snapshot");
+            return Err(StoreError::FaultInjected);
+        }
+
+        if self.faults.torn_write_rate > 0.0
+            && bernoulli(&mut self.ctx.node_rng("store.snapshot.torn_write", node_id), self.faults.torn_write_rate)
+        {
+            tracing::warn!(%node_id, "Injecting torn write in write_snapshot");
+            return Err(StoreError::FaultInjected);
+        }
+
+        self.charge_quota(data.len())?;
+        self.view().write_snapshot(meta, data)?;
+        self.ctx
+            .log_kv_pinned("snapshot_index", &meta.last_included_index.to_string());
+        Ok(())
+    }
+
+    fn read_snapshot(&mut self) -> Result<Option<(SnapshotMeta, bytes::Bytes)>, StoreError> {
+        self.charge_read();
+        self.view().read_snapshot()
+    }
+
+    fn compact_log_up_to(&mut self, idx: LogIndex) -> Result<(), StoreError> {
+        self.charge_write();
+        self.view().compact_log_up_to(idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::{Harness, Script, SCRIPT_TAG};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    /// Arms a zero-delay timer on start.
+    fn timer_at_start() -> Script<()> {
+        Script::new().on_start(|_, ctx| {
+            ctx.set_timer(0);
+        })
+    }
+
+    /// Node 0 sends one message to node 1, from either `init` or `start`,
+    /// and every node counts what it receives.
+    fn send_from(in_init: bool, received: &Arc<AtomicUsize>) -> Script<u8> {
+        let send = |_: &mut (), ctx: &mut Ctx<u8>| {
+            if ctx.node_id() == 0 {
+                ctx.send(1, &0).unwrap();
+            }
+        };
+        let received = received.clone();
+        let script = Script::new().on_message(move |_, _, _, _| {
+            received.fetch_add(1, Ordering::SeqCst);
+        });
+        if in_init {
+            script.on_init(send)
+        } else {
+            script.on_start(send)
+        }
+    }
+
+    fn test_sim(protos: Vec<Box<dyn ProtocolDyn>>) -> Simulation {
+        let num_nodes = protos.len();
+        let mut protos = protos.into_iter();
+        let world = World::full_mesh(num_nodes, |_| protos.next().unwrap());
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let telemetry = TelemetryBus::new(tx, num_nodes, &TelemetrySpec::default());
+        Simulation::new(7, world, telemetry)
+    }
+
+    /// Runs `script` on each of `nodes` nodes of a `test_sim`.
+    fn script_sim<M, S>(nodes: usize, script: &Script<M, S>) -> Simulation
+    where
+        M: serde::de::DeserializeOwned + serde::Serialize + std::fmt::Debug + Send + 'static,
+        S: Clone + Send + 'static,
+    {
+        test_sim((0..nodes).map(|_| script.boxed()).collect())
+    }
+
+    fn run_send_from(in_init: bool) -> usize {
+        let received = Arc::new(AtomicUsize::new(0));
+        let mut sim = script_sim(2, &send_from(in_init, &received));
+        sim.init();
+        while sim.step().is_some() {}
+        received.load(Ordering::SeqCst)
+    }
+
+    /// Runs three nodes where node 0's store takes 1µs per write and every
+    /// link 5ms. On start, node 0 makes `writes` writes and then broadcasts.
+    /// Returns when the other nodes received the broadcast.
+    fn broadcast_arrivals(writes: usize) -> Vec<SimTime> {
+        let arrivals = Arc::new(Mutex::new(Vec::new()));
+        let seen = arrivals.clone();
+        let leader = Script::<u8>::new()
+            .on_start(move |_, ctx| {
+                if ctx.node_id() == 0 {
+                    for i in 0..writes {
+                        let key = bytes::Bytes::from(format!("k{}", i));
+                        ctx.store().kv_put(key, bytes::Bytes::from_static(b"v")).unwrap();
+                    }
+                    ctx.broadcast(&0, None).unwrap();
+                }
+            })
+            .on_message(move |_, ctx, _, _| seen.lock().unwrap().push(ctx.now()));
+        let mut sim = script_sim(3, &leader);
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(5_000_000);
+            link.faults.jitter = DelaySpec::Const(0);
+        }
+        let node = sim.world.node_mut(0);
+        node.set_peers(vec![1, 2]);
+        node.set_store_latency(Some(StoreLatencySpec {
+            read: DelaySpec::Const(0),
+            write: DelaySpec::Const(1_000),
+            fsync: DelaySpec::Const(0),
+        }));
+        sim.init();
+        sim.run();
+        let arrivals = arrivals.lock().unwrap().clone();
+        arrivals
+    }
+
+    #[test]
+    fn test_store_latency_delays_subsequent_effects() {
+        // Each write holds the broadcast back by another microsecond
+        assert_eq!(broadcast_arrivals(1), [5_001_000; 2]);
+        assert_eq!(broadcast_arrivals(10), [5_010_000; 2]);
+    }
+
+    /// Answers a `Put` after as many store writes as its value says.
+    fn slow_put() -> Script<()> {
+        Script::new().on_client_request(|_, ctx, op| {
+            let ClientOp::Put { key, value } = op else {
+                return None;
+            };
+            for _ in 0..value.parse().unwrap_or(0) {
+                ctx.store().kv_put(bytes::Bytes::from(key.clone()), bytes::Bytes::from_static(b"v")).unwrap();
+            }
+            Some(ClientResponse::Ok)
+        })
+    }
+
+    #[test]
+    fn test_latency_percentiles_match_constant_delays() {
+        use crate::telemetry::snapshot::LatencyPercentiles;
+        use ftsim_proto::protocols::primary_backup::PrimaryBackup;
+        let world = World::full_mesh(3, |_| boxed_dyn(PrimaryBackup::new()));
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let mut sim = Simulation::new(7, world, TelemetryBus::new(tx, 3, &TelemetrySpec::default()));
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(3_000_000);
+            link.faults.jitter = DelaySpec::Const(0);
+        }
+        // The primary answers a Put once a backup acknowledged it, a round
+        // trip after it was issued
+        let put = ClientOp::Put { key: "k".to_string(), value: "v".to_string() };
+        let scenario = (1..=10)
+            .fold(Scenario::builder("round_trips", 3, ProtoTag(2)), |builder, i| {
+                builder.at(sim_from_ms(10 * i), Action::ClientRequest { node: 0, op: put.clone() })
+            })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.init();
+        let report = sim.run_until(sim_from_ms(200));
+        let delivery = report.metrics.delivery_latency;
+        assert_eq!(delivery.count, report.metrics.messages_delivered);
+        assert!(delivery.count > 0);
+        assert_eq!((delivery.p50, delivery.p95, delivery.p99), (3_000_000, 3_000_000, 3_000_000));
+        assert_eq!(report.metrics.client_responses, 10);
+        assert_eq!(
+            report.metrics.client_request_latency,
+            LatencyPercentiles { count: 10, p50: 6_000_000, p95: 6_000_000, p99: 6_000_000 }
+        );
+
+        // A request answered at once still waits out its store time, 4ms per
+        // write here
+        let mut sim = script_sim(1, &slow_put());
+        sim.world.node_mut(0).set_store_latency(Some(StoreLatencySpec {
+            read: DelaySpec::Const(0),
+            write: DelaySpec::Const(4_000_000),
+            fsync: DelaySpec::Const(0),
+        }));
+        let put = ClientOp::Put { key: "k".to_string(), value: "1".to_string() };
+        let scenario = (0..20)
+            .fold(Scenario::builder("latency", 1, SCRIPT_TAG), |builder, i| {
+                builder.at(sim_from_ms(10 * i), Action::ClientRequest { node: 0, op: put.clone() })
+            })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.init();
+        let client = sim.run_until(sim_from_ms(300)).metrics.client_request_latency;
+        assert_eq!(client, LatencyPercentiles { count: 20, p50: 4_000_000, p95: 4_000_000, p99: 4_000_000 });
+        assert_eq!(client.to_string(), "p50 4.000 ms, p95 4.000 ms, p99 4.000 ms over 20");
+    }
+
+    #[test]
+    fn test_pending_client_requests_go_unanswered_when_their_node_crashes() {
+        use ftsim_proto::protocols::primary_backup::PrimaryBackup;
+        let world = World::full_mesh(3, |_| boxed_dyn(PrimaryBackup::new()));
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let mut sim = Simulation::new(7, world, TelemetryBus::new(tx, 3, &TelemetrySpec::default()));
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(3_000_000);
+            link.faults.jitter = DelaySpec::Const(0);
+        }
+        // The primary crashes before the backups' acknowledgments arrive
+        let put = ClientOp::Put { key: "k".to_string(), value: "v".to_string() };
+        let scenario = Scenario::builder("unanswered", 3, ProtoTag(2))
+            .at(sim_from_ms(10), Action::ClientRequest { node: 0, op: put })
+            .at(sim_from_ms(14), Action::Crash { node: 0, duration: SimDuration::Forever })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.init();
+        sim.run_until(sim_from_ms(12));
+        assert_eq!(sim.pending_clients.len(), 1);
+        let report = sim.run_until(sim_from_ms(100));
+        assert_eq!((report.metrics.client_requests, report.metrics.client_responses), (1, 0));
+        assert!(sim.pending_clients.is_empty());
+    }
+
+    #[test]
+    fn test_a_long_run_of_unanswered_requests_keeps_the_pending_cache_bounded() {
+        use crate::cache::CacheStats;
+        const REQUESTS: u64 = 100_000;
+        let never_answers = Script::<()>::new().on_client_request(|_, _, _| Some(ClientResponse::Pending));
+        let put = ClientOp::Put { key: "k".to_string(), value: "v".to_string() };
+        // One request per ms: a 50ms age limit holds 51, a 32-entry one 32
+        let limits = [(usize::MAX, sim_from_ms(50), 51), (32, sim_from_ms(50), 32)];
+        for (max_entries, max_age, held) in limits {
+            let mut sim = script_sim(1, &never_answers);
+            let mut scenario = Scenario::builder("unanswered", 1, SCRIPT_TAG).build().unwrap();
+            scenario.caches.pending_clients = CacheSpec { max_entries, max_age: Some(max_age) };
+            let action = Action::ClientRequest { node: 0, op: put.clone() };
+            scenario.directives.push(Directive::Every { period: sim_from_ms(1), repeats: REQUESTS, action }.into());
+            crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+            sim.init();
+            for chunk in 1..=10 {
+                sim.run_until(sim_from_ms(chunk * REQUESTS / 10));
+                assert!(sim.pending_clients.len() <= held, "{} pending", sim.pending_clients.len());
+            }
+            let evicted = REQUESTS - held as u64;
+            let stats = sim.pending_clients.stats();
+            let expected = match max_entries {
+                usize::MAX => CacheStats { inserts: REQUESTS, evicted_full: 0, evicted_age: evicted },
+                _ => CacheStats { inserts: REQUESTS, evicted_full: evicted, evicted_age: 0 },
+            };
+            assert_eq!((stats, sim.pending_clients.len()), (expected, held));
+        }
+    }
+
+    #[test]
+    fn test_traffic_counts_follow_a_one_way_link_failure() {
+        use crate::telemetry::snapshot::NodeTraffic;
+        use ftsim_proto::protocols::ping::Ping;
+        let world = World::full_mesh(2, |_| boxed_dyn(Ping::new()));
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let mut sim = Simulation::new(7, world, TelemetryBus::new(tx, 2, &TelemetrySpec::default()));
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(1_000_000);
+            link.faults.jitter = DelaySpec::Const(0);
+            if (link.src, link.dst) == (0, 1) {
+                link.faults.drop = Bernoulli(1.0);
+            }
+        }
+        let (lost, up) = (sim.world.net.link_between(0, 1).unwrap().id, sim.world.net.link_between(1, 0).unwrap().id);
+        sim.init();
+        sim.run_until(sim_from_ms(110));
+
+        // Node 1's pings reach node 0, but neither node 0's pings nor its
+        // pongs get back
+        let snap = sim.telemetry.build_snapshot(&sim.world, sim.now());
+        let pings = snap.nodes[1].traffic.sent;
+        assert!(pings > 0);
+        assert_eq!(snap.nodes[0].traffic, NodeTraffic { sent: 2 * pings, received: pings, dropped: 0 });
+        assert_eq!(snap.nodes[1].traffic, NodeTraffic { sent: pings, received: 0, dropped: 2 * pings });
+        let links: Vec<_> = snap.links.iter().map(|l| (l.id, l.delivered, l.dropped)).collect();
+        let mut expected = vec![(lost, 0, 2 * pings), (up, pings, 0)];
+        expected.sort_unstable();
+        assert_eq!(links, expected);
+        assert_eq!(snap.metrics.messages_sent, 3 * pings);
+        assert_eq!(snap.metrics.messages_delivered, pings);
+    }
+
+    #[test]
+    fn test_link_duplicate_delivers_each_message_twice() {
+        let received = Arc::new(AtomicUsize::new(0));
+        let counter = received.clone();
+        // Node 0 sends node 1 one message, once the link duplicates
+        let script = Script::<u8>::new()
+            .on_start(|_, ctx| {
+                if ctx.node_id() == 0 {
+                    ctx.set_timer(sim_from_ms(10));
+                }
+            })
+            .on_timer(|_, ctx, _| ctx.send(1, &0).unwrap())
+            .on_message(move |_, _, _, _| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+        let mut sim = script_sim(2, &script);
+        let link = sim.world.net.link_between(0, 1).unwrap().id;
+        let scenario = Scenario::builder("duplicate", 2, SCRIPT_TAG)
+            .at(sim_from_ms(1), Action::LinkDuplicate { link, p: 1.0 })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.init();
+        sim.run();
+        assert_eq!(received.load(Ordering::SeqCst), 2);
+        assert_eq!(sim.world.net.links[&link].faults.duplicate.0, 1.0);
+    }
+
+    #[test]
+    fn test_protocol_histograms_reach_the_report() {
+        use crate::telemetry::snapshot::LatencyPercentiles;
+        use ftsim_proto::protocols::ping::{Ping, RTT_HISTOGRAM};
+        let world = World::full_mesh(2, |_| boxed_dyn(Ping::new()));
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let mut sim = Simulation::new(7, world, TelemetryBus::new(tx, 2, &TelemetrySpec::default()));
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(1_000_000);
+            link.faults.jitter = DelaySpec::Const(0);
+        }
+        sim.init();
+        let report = sim.run_until(sim_from_ms(110));
+        let rtt = report.metrics.histograms[RTT_HISTOGRAM];
+        assert!(rtt.count > 0);
+        assert_eq!(rtt, LatencyPercentiles { count: rtt.count, p50: 2_000_000, p95: 2_000_000, p99: 2_000_000 });
+    }
+
+    #[test]
+    fn test_slo_judges_p99_per_window() {
+        let mut sim = script_sim(1, &slow_put());
+        // Every write takes a millisecond, so a request's latency is its
+        // value in milliseconds
+        sim.world.node_mut(0).set_store_latency(Some(StoreLatencySpec {
+            read: DelaySpec::Const(0),
+            write: DelaySpec::Const(1_000_000),
+            fsync: DelaySpec::Const(0),
+        }));
+        let slo = Slo { p99_ms: 50.0, window: sim_from_ms(100), warmup: sim_from_ms(100) };
+        // (issued at, latency) in ms
+        let requests = [
+            // Completes during warm-up
+            (10, 60),
+            (110, 40),
+            (120, 30),
+            (210, 60),
+            // Nothing completes in [300, 400)
+            (400, 45),
+            (405, 51),
+            (410, 49),
+            // Exactly on target
+            (500, 50),
+            // Still open when the run ends
+            (650, 90),
+        ];
+        let scenario = requests
+            .iter()
+            .fold(Scenario::builder("slo", 1, SCRIPT_TAG).slo(slo), |builder, &(at, latency)| {
+                let op = ClientOp::Put { key: "k".to_string(), value: latency.to_string() };
+                builder.at(sim_from_ms(at), Action::ClientRequest { node: 0, op })
+            })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.init();
+
+        let report = sim.run_to_end(sim_from_ms(700));
+        let slo_report = report.slo.expect("no SLO report");
+        let judged: Vec<_> = slo_report
+            .windows
+            .iter()
+            .map(|w| (w.start / sim_from_ms(1), w.requests, w.p99.map(|p99| p99 / sim_from_ms(1)), w.violated))
+            .collect();
+        assert_eq!(
+            judged,
+            [
+                (100, 2, Some(40), false),
+                (200, 1, Some(60), true),
+                (300, 0, None, false),
+                (400, 3, Some(51), true),
+                (500, 1, Some(50), false),
+            ]
+        );
+        assert_eq!(slo_report.attainment, Some(50.0));
+
+        let snapshot = sim.telemetry().build_snapshot(sim.world(), sim.now());
+        let alerts: Vec<_> = snapshot.recent_events.iter().filter(|e| e.event_type == "SLO_VIOLATION").collect();
+        assert_eq!(alerts.len(), 2);
+        assert!(alerts[1].details.starts_with("p99 51.000 ms over 3 requests"), "{}", alerts[1].details);
+        // The last judged window met the SLO, so no banner is up
+        assert_eq!(snapshot.slo_violation, None);
+    }
+
+    #[test]
+    fn test_slo_judges_a_pending_request_when_it_is_answered() {
+        // Answers each Put its value in milliseconds after it was issued
+        let script = Script::<(), BTreeMap<TimerId, ClientRequestId>>::with_state(BTreeMap::new())
+            .on_client_request(|pending, ctx, op| {
+                let ClientOp::Put { value, .. } = op else {
+                    return None;
+                };
+                let timer = ctx.set_timer(sim_from_ms(value.parse().unwrap()));
+                pending.insert(timer, ctx.client_request_id().unwrap());
+                Some(ClientResponse::Pending)
+            })
+            .on_timer(|pending, ctx, timer| {
+                if let Some(request) = pending.remove(&timer) {
+                    ctx.respond_to_client(request, ClientResponse::Ok);
+                }
+            });
+        let mut sim = script_sim(1, &script);
+        let slo = Slo { p99_ms: 50.0, window: sim_from_ms(100), warmup: 0 };
+        let put = |value: &str| ClientOp::Put { key: "k".to_string(), value: value.to_string() };
+        // The second request only keeps the run going past 300ms
+        let scenario = Scenario::builder("slo", 1, SCRIPT_TAG)
+            .slo(slo)
+            .at(sim_from_ms(50), Action::ClientRequest { node: 0, op: put("120") })
+            .at(sim_from_ms(400), Action::ClientRequest { node: 0, op: put("0") })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.init();
+
+        let report = sim.run_to_end(sim_from_ms(300)).slo.expect("no SLO report");
+        let judged: Vec<_> = report.windows.iter().map(|w| (w.start / sim_from_ms(1), w.requests, w.p99)).collect();
+        // Answered at 170ms, it counts with its whole latency towards the
+        // window it completed in
+        assert_eq!(judged, [(0, 0, None), (100, 1, Some(sim_from_ms(120))), (200, 0, None)]);
+        assert!(report.windows[1].violated);
+    }
+
+    #[test]
+    fn test_loading_a_state_rewinds_the_slo() {
+        use ftsim_proto::protocols::primary_backup::PrimaryBackup;
+        let world = World::full_mesh(3, |_| boxed_dyn(PrimaryBackup::new()));
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let mut sim = Simulation::new(7, world, TelemetryBus::new(tx, 3, &TelemetrySpec::default()));
+        // A Put takes a 60ms round trip to the backups
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(30_000_000);
+            link.faults.jitter = DelaySpec::Const(0);
+        }
+        let slo = Slo { p99_ms: 50.0, window: sim_from_ms(100), warmup: 0 };
+        let put = ClientOp::Put { key: "k".to_string(), value: "v".to_string() };
+        // The second request only keeps the run going past 200ms
+        let scenario = Scenario::builder("slo", 3, ProtoTag(2))
+            .slo(slo)
+            .at(sim_from_ms(10), Action::ClientRequest { node: 0, op: put.clone() })
+            .at(sim_from_ms(300), Action::ClientRequest { node: 0, op: put })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.init();
+        let saved = sim.save_state().unwrap();
+        let report = sim.run_until(sim_from_ms(200));
+        assert_eq!(report.slo.unwrap().attainment, Some(0.0));
+
+        sim.load_state(saved).unwrap();
+        assert_eq!(sim.telemetry().slo_report(sim.now()).unwrap().windows, []);
+        let report = sim.run_until(sim_from_ms(200));
+        let windows: Vec<_> = report.slo.unwrap().windows.iter().map(|w| w.requests).collect();
+        // Judged once, not again on top of the windows judged before the load
+        assert_eq!(windows, [1, 0]);
+    }
+
+    #[test]
+    fn test_loading_a_state_rewinds_the_convergence_tracker() {
+        use crate::convergence::REPLICA_HASH_KEY;
+        use ftsim_proto::protocols::primary_backup::PrimaryBackup;
+        let world = World::full_mesh(3, |_| boxed_dyn(PrimaryBackup::new()));
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let mut sim = Simulation::new(7, world, TelemetryBus::new(tx, 3, &TelemetrySpec::default()));
+        let publish =
+            |node, hash: &str| sim.telemetry().log_node_kv_pinned(node, REPLICA_HASH_KEY.to_string(), hash.into());
+        publish(0, "a");
+        let saved = sim.save_state().unwrap();
+        publish(1, "b");
+        let diverged = sim.telemetry().convergence_report().unwrap();
+        assert_eq!((diverged.nodes, diverged.converged_nodes, diverged.converged_at), (2, 1, None));
+
+        sim.load_state(saved).unwrap();
+        let report = sim.telemetry().convergence_report().unwrap();
+        assert_eq!((report.nodes, report.converged_nodes, report.converged_at), (1, 1, Some(0)));
+        assert_eq!(report.diverged_at, None);
+    }
+
+    /// Elects a five-node RaftLite leader, then lets one follower persist
+    /// only 10KB/s and schedules a 100-byte `Put` on the leader every
+    /// millisecond for the next 200ms. Returns the harness, the leader and
+    /// the throttled follower.
+    fn raft_under_write_quota(on_exceeded: QuotaAction) -> (Harness, NodeId, NodeId) {
+        let (mut harness, leader) = elected_raft_sim(&[]);
+        let throttled = (leader + 1) % 5;
+        let sim = harness.sim_mut();
+        // A follower that fails a write is sent the entries again right
+        // away, so round trips take a realistic millisecond
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(1_000_000);
+        }
+        sim.world.node_mut(throttled).set_write_quota(Some(WriteQuotaSpec {
+            bytes_per_sec: 10_000,
+            burst_bytes: Some(1_000),
+            on_exceeded,
+            nodes: vec![throttled],
+        }));
+        let now = sim.now();
+        let put = ClientOp::Put { key: "k".into(), value: "x".repeat(100) };
+        let scenario = (1..=200)
+            .fold(Scenario::builder("throughput", 5, ProtoTag(1)), |builder, i| {
+                builder.at(now + sim_from_ms(i), Action::ClientRequest { node: leader, op: put.clone() })
+            })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(sim, &scenario).unwrap();
+        (harness, leader, throttled)
+    }
+
+    /// How many entries of `leader`'s log it knows each follower holds.
+    fn published_match_index(report: &SimulationReport, leader: NodeId) -> BTreeMap<NodeId, u64> {
+        let match_index = report.node_state(leader, "match_index").and_then(|m| m.as_object()).expect("no match_index");
+        match_index.iter().map(|(node, index)| (node.parse().unwrap(), index.as_u64().unwrap())).collect()
+    }
+
+    /// How far behind `leader`'s log it knows each follower is.
+    fn replication_lag(report: &SimulationReport, leader: NodeId) -> BTreeMap<NodeId, u64> {
+        let log_len = report.node_state(leader, "log_len").and_then(|l| l.as_u64()).unwrap();
+        published_match_index(report, leader).into_iter().map(|(node, index)| (node, log_len - index)).collect()
+    }
+
+    #[test]
+    fn test_write_quota_delayed_follower_lags_behind_commit() {
+        let (mut harness, leader, throttled) = raft_under_write_quota(QuotaAction::Delay);
+        let start = harness.sim().now();
+        // The delayed follower's acks trail the log until a heartbeat, which
+        // writes nothing, is answered right away
+        let mut max_lag = BTreeMap::new();
+        for ms in (10..=200).step_by(10) {
+            let report = harness.sim_mut().run_until(start + sim_from_ms(ms));
+            for (node, lag) in replication_lag(&report, leader) {
+                let max = max_lag.entry(node).or_insert(0);
+                *max = lag.max(*max);
+            }
+        }
+        for (&node, &lag) in &max_lag {
+            if node == throttled {
+                assert!(lag >= 20, "throttled follower lagged {} at most", lag);
+            } else {
+                assert!(lag <= 3, "follower {} lagged {}", node, lag);
+            }
+        }
+        // The other three followers make the majority
+        let report = harness.sim_mut().run_until(start + sim_from_ms(250));
+        let committed = report.node_metric(leader, "commit_index").unwrap() as u64;
+        assert!(committed > 200, "commit index {}", committed);
+
+        let snapshot = harness.sim().telemetry().build_snapshot(harness.sim().world(), harness.sim().now());
+        let store = snapshot.nodes[throttled as usize].store.as_ref().unwrap();
+        assert!(store.throttled_writes > 100, "{:?}", store);
+        assert!(store.throttle_delay_ns > 0);
+        assert_eq!(snapshot.nodes[leader as usize].store.as_ref().unwrap().throttled_writes, 0);
+    }
+
+    #[test]
+    fn test_write_quota_throttle_fails_writes() {
+        let (mut harness, leader, throttled) = raft_under_write_quota(QuotaAction::Throttle);
+        let start = harness.sim().now();
+        let report = harness.sim_mut().run_until(start + sim_from_ms(250));
+        let committed = report.node_metric(leader, "commit_index").unwrap() as u64;
+        assert!(committed > 200, "commit index {}", committed);
+        let lag = replication_lag(&report, leader);
+        for (&node, &lag) in &lag {
+            if node != throttled {
+                assert_eq!(lag, 0, "follower {}", node);
+            }
+        }
+        // The burst plus 10KB/s, about 20 entries, got through
+        let matched = published_match_index(&report, leader)[&throttled];
+        assert!((10..committed / 4).contains(&matched), "throttled follower at {} of {}", matched, committed);
+
+        let snapshot = harness.sim().telemetry().build_snapshot(harness.sim().world(), harness.sim().now());
+        let store = snapshot.nodes[throttled as usize].store.as_ref().unwrap();
+        // A follower acknowledges only what it persisted
+        assert!(store.log_len >= matched, "{:?}", store);
+        assert!(store.throttled_writes > 0, "{:?}", store);
+        assert_eq!(store.throttle_delay_ns, 0);
+    }
+
+    #[test]
+    fn test_send_in_start_succeeds() {
+        assert_eq!(run_send_from(false), 1);
+    }
+
+    #[test]
+    fn test_report_counts_match_scenario() {
+        let mut sim = script_sim(2, &send_from(false, &Arc::default()));
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(1_000_000);
+            link.faults.jitter = DelaySpec::Const(0);
+        }
+        sim.init();
+        // Node 0 sends once on start and again when it restarts at 15ms.
+        let scenario = Scenario::builder("report", 2, SCRIPT_TAG)
+            .at(sim_from_ms(5), Action::Crash { node: 0, duration: SimDuration::Finite(sim_from_ms(10)) })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+
+        let report = sim.run();
+        assert_eq!(report.outcome, SimulationOutcome::QueueExhausted);
+        assert_eq!(report.final_time, sim_from_ms(16));
+        assert_eq!(report.events_processed, 4);
+        assert_eq!(report.events_by_kind, BTreeMap::from([("deliver", 2), ("fault", 2)]));
+        assert_eq!(report.metrics.messages_sent, 2);
+        assert_eq!(report.metrics.messages_delivered, 2);
+        assert_eq!(report.metrics.timers_fired, 0);
+        assert_eq!(report.metrics.faults_injected, 2);
+        let nodes: Vec<_> = report.nodes.iter().map(|n| (n.id, n.status, n.incarnation)).collect();
+        assert_eq!(nodes, vec![(0, NodeStatus::Up, 1), (1, NodeStatus::Up, 0)]);
+        // One drop and one duplication trial per transmitted message.
+        assert_eq!(
+            report.rng_draws,
+            BTreeMap::from([("net.drop".to_string(), 2), ("net.duplicate".to_string(), 2)])
+        );
+        assert!(serde_json::to_string(&report).unwrap().contains("\"reason\":\"queue_exhausted\""));
+    }
+
+    #[test]
+    fn test_resource_usage_accumulates_across_runs() {
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        assert!(sim.report(SimulationOutcome::Shutdown).usage.is_none());
+        sim.run_until(sim_from_ms(500));
+        let first = sim.resource_usage();
+        assert_eq!(first.events_processed, sim.events_processed());
+        assert!(first.events_processed > 0 && first.wall_ns > 0);
+        assert!(first.events_per_sec > 0.0 && first.peak_events_per_sec >= first.events_per_sec);
+        if cfg!(target_os = "linux") {
+            assert!(first.peak_rss_bytes.is_some_and(|rss| rss > 0));
+        }
+
+        sim.run_until(sim_from_ms(1_000));
+        let second = sim.resource_usage();
+        assert!(second.events_processed > first.events_processed);
+        assert!(second.wall_ns > first.wall_ns);
+        assert!(second.peak_rss_bytes >= first.peak_rss_bytes);
+        assert!(second.profile.is_empty());
+    }
+
+    #[test]
+    fn test_profiling_times_each_event_kind() {
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        sim.enable_profiling();
+        sim.run_until(sim_from_ms(500));
+        let usage = sim.resource_usage();
+        for category in ["deliver", "timer", "invariants", "telemetry"] {
+            assert!(usage.profile.get(category).is_some_and(|&ns| ns > 0), "no time for {}", category);
+        }
+        let top = usage.top_categories(2);
+        assert_eq!(top.len(), 2);
+        assert!(top[0].1 >= top[1].1);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "during init")]
+    fn test_send_in_init_rejected() {
+        run_send_from(true);
+    }
+
+    #[test]
+    fn test_legacy_init_shim_allows_send() {
+        let received = Arc::new(AtomicUsize::new(0));
+        let script = send_from(true, &received);
+        let mut sim = test_sim((0..2).map(|_| ftsim_proto::api::legacy_init(script.boxed())).collect());
+        sim.init();
+        while sim.step().is_some() {}
+        assert_eq!(received.load(Ordering::SeqCst), 1);
+    }
+
+    /// Node 0 sends `count` payloads of `len` bytes to node 1 on start, and
+    /// every node counts what it receives.
+    fn blast(count: usize, len: usize, received: &Arc<AtomicUsize>) -> Script<Vec<u8>> {
+        let received = received.clone();
+        Script::new()
+            .on_start(move |_, ctx| {
+                if ctx.node_id() == 0 {
+                    for _ in 0..count {
+                        ctx.send(1, &vec![7u8; len]).unwrap();
+                    }
+                }
+            })
+            .on_message(move |_, _, _, payload| {
+                assert_eq!(payload.len(), len);
+                received.fetch_add(1, Ordering::SeqCst);
+            })
+    }
+
+    #[test]
+    fn test_fragmentation_amplifies_message_loss() {
+        const COUNT: usize = 1_000;
+        let received = Arc::new(AtomicUsize::new(0));
+        // Ten 1KB fragments, the length prefix included
+        let mut sim = script_sim(2, &blast(COUNT, 10 * 1024 - 2, &received));
+        sim.world.net.set_mtu(Some(1024), OversizePolicy::Fragment);
+        // All messages are in flight at once, so hold every partial message.
+        sim.world.node_mut(1).set_reassembly(sim_from_ms(1_000), COUNT);
+        for link in sim.world.net.links.values_mut() {
+            link.faults.drop = Bernoulli(0.1);
+        }
+        sim.init();
+        while sim.step().is_some() {}
+
+        // Ten fragments must all survive a 10% drop: 0.9^10 ~= 0.35.
+        let delivered = received.load(Ordering::SeqCst) as f64 / COUNT as f64;
+        assert!((0.30..0.40).contains(&delivered), "delivered fraction {}", delivered);
+        let reassembly = sim.world.node(1).reassembly();
+        assert!(reassembly.pending() <= COUNT);
+    }
+
+    #[test]
+    fn test_fragments_reaching_a_down_node_are_not_reassembled() {
+        let received = Arc::new(AtomicUsize::new(0));
+        let mut sim = script_sim(2, &blast(1, 10 * 1024 - 2, &received));
+        sim.world.net.set_mtu(Some(1024), OversizePolicy::Fragment);
+        // The fragments arrive over ten milliseconds
+        for link in sim.world.net.links.values_mut() {
+            link.faults.jitter = DelaySpec::Uniform { lo: 0, hi: 10_000_000 };
+        }
+        // Node 1 misses the first half of them
+        let scenario = Scenario::builder("fragments", 2, SCRIPT_TAG)
+            .at(0, Action::Crash { node: 1, duration: SimDuration::Finite(sim_from_ms(5)) })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.init();
+        while sim.step().is_some() {}
+
+        // The second half alone is left waiting for the reassembly timeout
+        assert_eq!(received.load(Ordering::SeqCst), 0);
+        assert_eq!(sim.world.node(1).reassembly().pending(), 1);
+    }
+
+    /// The node-local time and the long timer's remaining time.
+    type Observation = Arc<Mutex<Option<(SimTime, Option<SimTime>)>>>;
+
+    /// Arms a long and a short timer on start; when the short one fires,
+    /// records the node-local time and the long timer's remaining time.
+    fn skew_probe(observed: &Observation) -> Script<(), Option<TimerId>> {
+        let observed = observed.clone();
+        Script::with_state(None)
+            .on_start(|long, ctx| {
+                *long = Some(ctx.set_timer(1_000));
+                ctx.set_timer(400);
+            })
+            .on_timer(move |long, ctx, timer| {
+                if Some(timer) != *long {
+                    let remaining = ctx.timer_remaining(long.unwrap());
+                    *observed.lock().unwrap() = Some((ctx.now(), remaining));
+                }
+            })
+    }
+
+    #[test]
+    fn test_timer_remaining_under_clock_skew() {
+        for skew in [0i128, 5_000, -200] {
+            let observed = Observation::default();
+            let mut sim = script_sim(1, &skew_probe(&observed));
+            sim.world.node_mut(0).clock_skew_ns = skew;
+            sim.init();
+            sim.step();
+
+            let (local_now, remaining) = observed.lock().unwrap().unwrap();
+            assert_eq!(remaining, Some(600));
+            // The long timer fires at sim time 1000, i.e. 1000 + skew on the node's clock.
+            assert_eq!(local_now as i128 + 600, 1_000 + skew);
+        }
+    }
+
+    /// What `identity_probe` read from its node's metadata on each start:
+    /// (node, incarnation, uuid, durable log length).
+    type SeenIdentity = Arc<Mutex<Vec<(NodeId, String, String, Option<String>)>>>;
+
+    /// Records its node's metadata on every start, then appends a record.
+    fn identity_probe(seen: &SeenIdentity) -> Script<()> {
+        let seen = seen.clone();
+        Script::new().on_start(move |_, ctx| {
+            let identity = (
+                ctx.node_id(),
+                ctx.node_meta_get(META_INCARNATION).unwrap(),
+                ctx.node_meta_get(META_UUID).unwrap(),
+                ctx.node_meta_get(META_DURABLE_LOG_LEN),
+            );
+            seen.lock().unwrap().push(identity);
+            ctx.store().append_log(LogRecord::new(1, bytes::Bytes::from_static(b"x"))).ok();
+        })
+    }
+
+    #[test]
+    fn test_node_meta_survives_restarts_when_the_store_fails_every_write() {
+        let seen = SeenIdentity::default();
+        let mut sim = script_sim(2, &identity_probe(&seen));
+        sim.init();
+        // Node 0's first record lands; every write after 1ms fails
+        let crash = Action::Crash { node: 0, duration: SimDuration::Finite(sim_from_ms(5)) };
+        let scenario = Scenario::builder("identity", 2, SCRIPT_TAG)
+            .at(sim_from_ms(1), Action::StoreFault { node: 0, kind: StoreFaultKind::WriteError, rate: 1.0 })
+            .at(sim_from_ms(5), crash.clone())
+            .at(sim_from_ms(20), crash)
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        let checkpoint = sim.checkpoint_world();
+        let report = sim.run();
+
+        let seen = seen.lock().unwrap().clone();
+        let node0: Vec<_> = seen.iter().filter(|s| s.0 == 0).map(|s| (s.1.as_str(), s.3.as_deref())).collect();
+        assert_eq!(node0, vec![("0", Some("0")), ("1", Some("1")), ("2", Some("1"))]);
+        let uuid = crate::node::meta::node_uuid(7, 0);
+        assert!(seen.iter().filter(|s| s.0 == 0).all(|s| s.2 == uuid));
+        assert_ne!(crate::node::meta::node_uuid(7, 1), uuid);
+        assert_ne!(crate::node::meta::node_uuid(8, 0), uuid);
+
+        let meta = &report.nodes[0].meta;
+        assert_eq!(meta.get(META_INCARNATION).map(String::as_str), Some("2"));
+        assert_eq!(meta.get(META_UUID), Some(&uuid));
+        assert_eq!(report.nodes[0].incarnation, 2);
+        assert_eq!(report.nodes[0].store.as_ref().unwrap().log_len, 1);
+
+        // A world checkpoint carries the metadata along with the store
+        sim.restore_world(checkpoint);
+        assert_eq!(sim.world.node(0).incarnation(), 0);
+        assert_eq!(sim.world.node(0).meta().get(META_DURABLE_LOG_LEN), Some("1"));
+        assert_eq!(sim.world.node(0).meta().get(META_UUID), Some(uuid.as_str()));
+    }
+
+    #[test]
+    fn test_a_crash_cuts_a_buffered_log_back_to_the_boundary_in_the_meta() {
+        use crate::{net::Net, store::MemStore};
+        // Syncs after the second of three records
+        let writer = Script::<()>::new().on_start(|_, ctx| {
+            let mut store = ctx.store();
+            for term in 1..=3 {
+                store.append_log(LogRecord::new(term, bytes::Bytes::from_static(b"x"))).unwrap();
+                if term == 2 {
+                    store.fsync().unwrap();
+                }
+            }
+        });
+        let store = Box::new(MemStore::with_durability(Durability::BufferedUntilFsync));
+        let net = Net::from_topology(1, &TopologySpec::FullMesh);
+        let world = World { nodes: vec![Node::new(0, writer.boxed(), store)], net };
+        let mut sim = Simulation::new(7, world, TelemetryBus::detached(1, &TelemetrySpec::default()));
+        sim.init();
+        assert_eq!(sim.world.node(0).meta().get(META_DURABLE_LOG_LEN), Some("2"));
+        assert_eq!(sim.world.node(0).store().log_len(), Some(3));
+
+        // A boundary the engine moved back cuts the log back with it
+        sim.world.node_mut(0).meta_mut().put_u64(META_DURABLE_LOG_LEN, 1);
+        let scenario = Scenario::builder("cut", 1, SCRIPT_TAG)
+            .at(sim_from_ms(1), Action::Crash { node: 0, duration: SimDuration::Forever })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.run();
+        assert_eq!(sim.world.node(0).store().log_len(), Some(1));
+    }
+
+    /// Deliveries seen by `meta_probe`: (src, dst, meta).
+    type SeenMeta = Arc<Mutex<Vec<(NodeId, NodeId, MessageMeta)>>>;
+
+    /// Broadcasts one message on start and records the meta of every
+    /// message it receives.
+    fn meta_probe(seen: &SeenMeta) -> Script<u8> {
+        let seen = seen.clone();
+        Script::new()
+            .on_start(|_, ctx| {
+                assert_eq!(ctx.message_meta(), None);
+                ctx.broadcast(&0, None).unwrap();
+            })
+            .on_message(move |_, ctx, src, _| {
+                let meta = ctx.message_meta().expect("delivery without meta");
+                seen.lock().unwrap().push((src, ctx.node_id(), meta));
+            })
+    }
+
+    /// Runs three `meta_probe`s whose clocks are 2s ahead, 2s behind and on
+    /// a common 10s offset, under a 500ms future message policy.
+    fn run_skewed_probes(action: FutureMessageAction) -> (Simulation, Vec<(NodeId, NodeId, MessageMeta)>) {
+        let seen = SeenMeta::default();
+        let mut sim = script_sim(3, &meta_probe(&seen));
+        let base = sim_from_ms(10_000) as i128;
+        for (node, skew) in [(0, base + 2_000_000_000), (1, base - 2_000_000_000), (2, base)] {
+            sim.world.node_mut(node).clock_skew_ns = skew;
+        }
+        sim.set_future_message_policy(Some(FutureMessagePolicy { max_skew: sim_from_ms(500), action }));
+        sim.init();
+        while sim.step().is_some() {}
+        let mut seen = seen.lock().unwrap().clone();
+        seen.sort_by_key(|(src, dst, _)| (*src, *dst));
+        (sim, seen)
+    }
+
+    #[test]
+    fn test_future_message_policy_drops_messages_from_ahead() {
+        let (sim, seen) = run_skewed_probes(FutureMessageAction::Drop);
+        // Only messages to a clock ahead of the sender's survive
+        let pairs: Vec<(NodeId, NodeId)> = seen.iter().map(|(src, dst, _)| (*src, *dst)).collect();
+        assert_eq!(pairs, vec![(1, 0), (1, 2), (2, 0)]);
+        assert!(seen.iter().all(|(_, _, meta)| !meta.from_future));
+        assert_eq!(sim.future_message_counts(), &BTreeMap::from([(1, 2), (2, 1)]));
+        let report = sim.report(SimulationOutcome::QueueExhausted);
+        assert_eq!(report.metrics.messages_sent, 6);
+        assert_eq!(report.metrics.messages_delivered, 3);
+    }
+
+    #[test]
+    fn test_future_message_policy_flags_messages_from_ahead() {
+        let (sim, seen) = run_skewed_probes(FutureMessageAction::DeliverWithFlag);
+        assert_eq!(seen.len(), 6);
+        let flagged: Vec<(NodeId, NodeId)> = seen
+            .iter()
+            .filter(|(_, _, meta)| meta.from_future)
+            .map(|(src, dst, _)| (*src, *dst))
+            .collect();
+        assert_eq!(flagged, vec![(0, 1), (0, 2), (2, 1)]);
+        // The send time is read off the sender's clock
+        let (_, _, meta) = seen.iter().find(|(src, dst, _)| (*src, *dst) == (0, 1)).unwrap();
+        assert_eq!(meta.sent_at, sim_from_ms(12_000));
+        assert_eq!(sim.future_message_counts(), &BTreeMap::from([(1, 2), (2, 1)]));
+    }
+
+    #[test]
+    fn test_future_message_policy_with_an_endless_skew_admits_everything() {
+        let seen = SeenMeta::default();
+        let mut sim = script_sim(3, &meta_probe(&seen));
+        sim.world.node_mut(0).clock_skew_ns = sim_from_ms(2_000) as i128;
+        let policy = FutureMessagePolicy { max_skew: MAX_SIM_TIME, action: FutureMessageAction::Drop };
+        sim.set_future_message_policy(Some(policy));
+        sim.init();
+        while sim.step().is_some() {}
+        assert_eq!(seen.lock().unwrap().len(), 6);
+        assert!(sim.future_message_counts().is_empty());
+    }
+
+    #[test]
+    fn test_watermark_runs_after_same_time_deliveries() {
+        // Node 0 arms a watermark and a plain timer for t=10 and records what
+        // it observes; node 1 sends it three messages that also land at t=10
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (on_message, on_timer) = (seen.clone(), seen.clone());
+        let script = Script::<u8, Option<TimerId>>::with_state(None)
+            .on_start(|watermark, ctx| {
+                if ctx.node_id() == 0 {
+                    *watermark = Some(ctx.set_watermark(10));
+                    ctx.set_timer(10);
+                } else {
+                    for _ in 0..3 {
+                        ctx.send(0, &0).unwrap();
+                    }
+                }
+            })
+            .on_message(move |_, _, _, _| on_message.lock().unwrap().push("deliver"))
+            .on_timer(move |watermark, _, timer| {
+                let kind = if Some(timer) == *watermark { "watermark" } else { "timer" };
+                on_timer.lock().unwrap().push(kind);
+            });
+        let mut sim = script_sim(2, &script);
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(10);
+            link.faults.jitter = DelaySpec::Const(0);
+        }
+        sim.init();
+        while sim.step().is_some() {}
+
+        assert_eq!(sim.now(), 10);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            ["timer", "deliver", "deliver", "deliver", "watermark"]
+        );
+    }
+
+    /// Every millisecond, arms and cancels `per_tick` long timeouts, the way
+    /// a protocol guards requests that are answered right away, for `ticks`
+    /// milliseconds.
+    fn timeout_churn(ticks: usize, per_tick: usize) -> Script<(), usize> {
+        Script::with_state(ticks)
+            .on_start(|_, ctx| {
+                ctx.set_timer(1_000_000);
+            })
+            .on_timer(move |ticks, ctx, _| {
+                for _ in 0..per_tick {
+                    let timeout = ctx.set_timer(10_000_000_000);
+                    assert!(ctx.cancel_timer(timeout));
+                }
+                *ticks -= 1;
+                if *ticks > 0 {
+                    ctx.set_timer(1_000_000);
+                }
+            })
+    }
+
+    #[test]
+    fn test_canceled_timers_do_not_accumulate_in_queue() {
+        let mut sim = script_sim(1, &timeout_churn(1_000, 100));
+        sim.init();
+        let mut max_queued = 0;
+        while sim.step().is_some() {
+            max_queued = max_queued.max(sim.queue.len());
+        }
+
+        // 100k timeouts were armed and canceled, none of which ran
+        assert_eq!(sim.events_processed, 1_000);
+        assert_eq!(sim.now(), 1_000_000_000);
+        assert!(max_queued <= 2 * COMPACT_MIN_CANCELED, "queue grew to {}", max_queued);
+        assert!(sim.queue.is_empty());
+        assert_eq!(sim.active_events, 0);
+    }
+
+    /// The time and payload of every firing a `metronome` saw.
+    type Firings = Arc<Mutex<Vec<(SimTime, Vec<u8>)>>>;
+
+    /// Arms one periodic 10ms timer with a payload on its first start, and
+    /// cancels it from its own handler after `cancel_after` firings.
+    fn metronome(cancel_after: usize, fired: &Firings) -> Script<(), bool> {
+        let fired = fired.clone();
+        Script::with_state(false)
+            .on_start(|armed, ctx| {
+                if !*armed {
+                    *armed = true;
+                    ctx.set_periodic_timer_with(sim_from_ms(10), b"tick").unwrap();
+                }
+            })
+            .on_timer(|_, _, _| panic!("a timer with a payload fired without it"))
+            .on_timer_payload(move |_, ctx, timer, payload| {
+                let mut fired = fired.lock().unwrap();
+                fired.push((ctx.now(), payload.to_vec()));
+                if fired.len() == cancel_after {
+                    assert!(ctx.cancel_timer(timer));
+                }
+            })
+    }
+
+    fn metronome_sim(cancel_after: usize) -> (Simulation, Firings) {
+        let fired = Firings::default();
+        let mut sim = script_sim(1, &metronome(cancel_after, &fired));
+        sim.init();
+        (sim, fired)
+    }
+
+    fn fire_times(fired: &Firings) -> Vec<SimTime> {
+        fired.lock().unwrap().iter().map(|(at, _)| *at).collect()
+    }
+
+    #[test]
+    fn test_periodic_timer_repeats_until_canceled() {
+        let (mut sim, fired) = metronome_sim(3);
+        sim.run_until(sim_from_ms(25));
+        assert_eq!(fire_times(&fired), vec![sim_from_ms(10), sim_from_ms(20)]);
+        // Between firings the timer is still pending, with its payload
+        assert_eq!(sim.world.node(0).timers_len(), 1);
+        assert_eq!(sim.world.node(0).timer_payload_bytes(), 4);
+
+        let report = sim.run();
+        assert_eq!(report.outcome, SimulationOutcome::QueueExhausted);
+        assert_eq!(fire_times(&fired), vec![sim_from_ms(10), sim_from_ms(20), sim_from_ms(30)]);
+        assert!(fired.lock().unwrap().iter().all(|(_, payload)| payload == b"tick"));
+        assert_eq!(sim.world.node(0).timers_len(), 0);
+        assert_eq!(sim.world.node(0).timer_payload_bytes(), 0);
+        assert_eq!(sim.active_events, 0);
+    }
+
+    #[test]
+    fn test_crash_ends_periodic_timer_for_good() {
+        let (mut sim, fired) = metronome_sim(usize::MAX);
+        let scenario = Scenario::builder("crash", 1, SCRIPT_TAG)
+            .at(sim_from_ms(25), Action::Crash { node: 0, duration: SimDuration::Finite(sim_from_ms(10)) })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.run_until(sim_from_ms(100));
+
+        assert_eq!(sim.world.node(0).status, NodeStatus::Up);
+        assert_eq!(fire_times(&fired), vec![sim_from_ms(10), sim_from_ms(20)]);
+        assert_eq!(sim.world.node(0).timers_len(), 0);
+    }
+
+    /// Runs two nodes starting 100ns before the end of time, with 1µs link
+    /// delays. Node 0 sets a timer `after` from now, tries to extend it by
+    /// `extend_by` and sends to node 1. Returns what happened, in order,
+    /// and the overflow count.
+    fn run_overflow_probe(after: SimTime, extend_by: SimTime) -> (Vec<&'static str>, u64) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (on_start, on_message, on_timer) = (seen.clone(), seen.clone(), seen.clone());
+        let script = Script::<u8>::new()
+            .on_start(move |_, ctx| {
+                if ctx.node_id() == 0 {
+                    let timer = ctx.set_timer(after);
+                    if !ctx.extend_timer(timer, extend_by) {
+                        on_start.lock().unwrap().push("not extended");
+                    }
+                    ctx.send(1, &0).unwrap();
+                }
+            })
+            .on_message(move |_, _, _, _| on_message.lock().unwrap().push("delivered"))
+            .on_timer(move |_, _, _| on_timer.lock().unwrap().push("fired"));
+        let mut sim = script_sim(2, &script);
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(1_000);
+            link.faults.jitter = DelaySpec::Const(0);
+        }
+        sim.clock = MAX_SIM_TIME - 100;
+        sim.init();
+        while sim.step().is_some() {}
+        let seen = seen.lock().unwrap().clone();
+        (seen, sim.time_overflows())
+    }
+
+    #[test]
+    fn test_overflowing_timers_and_deliveries_are_not_scheduled() {
+        // The timer fits but its extension does not, so it fires on time;
+        // the delivery overflows and is dropped.
+        assert_eq!(run_overflow_probe(50, MAX_SIM_TIME), (vec!["not extended", "fired"], 2));
+        // Right at the end of time everything fits.
+        assert_eq!(run_overflow_probe(100, 0).1, 1);
+        // The timer overflows, so there is nothing to extend or fire.
+        assert_eq!(run_overflow_probe(101, 0), (vec!["not extended"], 2));
+    }
+
+    #[test]
+    fn test_skewed_clocks_clamp_at_the_ends_of_time() {
+        let mut sim = script_sim(1, &Script::<()>::new());
+        sim.world.node_mut(0).clock_skew_ns = i128::MAX;
+        sim.clock = 1 << 127;
+        let mut ctx = EngineCtx {
+            sim: &mut sim,
+            current_node_id: Some(0),
+            store_delay: 0,
+            last_sent: None,
+        };
+        assert_eq!(ctx.now(), MAX_SIM_TIME);
+        // Undoing the skew for a watermark past the end of time overflows
+        ctx.sim.world.node_mut(0).clock_skew_ns = -1;
+        ctx.set_watermark(MAX_SIM_TIME);
+        assert_eq!(ctx.sim.time_overflows(), 1);
+        ctx.sim.world.node_mut(0).clock_skew_ns = i128::MIN;
+        ctx.sim.clock = 5;
+        assert_eq!(ctx.now(), SIM_EPOCH);
+    }
+
+    #[test]
+    fn test_crash_durations() {
+        let run = |duration: SimDuration| {
+            let mut sim = script_sim(1, &timer_at_start());
+            sim.init();
+            let scenario = Scenario::builder("crash", 1, SCRIPT_TAG)
+                .at(sim_from_ms(5), Action::Crash { node: 0, duration })
+                .build()
+                .unwrap();
+            crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+            let report = sim.run();
+            (sim.world.node(0).status, report.final_time, sim.time_overflows())
+        };
+        let restarted = run(SimDuration::Finite(sim_from_ms(10)));
+        assert_eq!(restarted, (NodeStatus::Up, sim_from_ms(15), 0));
+        assert_eq!(run(SimDuration::Forever), (NodeStatus::Down, sim_from_ms(5), 0));
+        // A restart past the end of time is reported and never happens
+        assert_eq!(run(SimDuration::Finite(MAX_SIM_TIME)), (NodeStatus::Down, sim_from_ms(5), 1));
+    }
+
+    #[test]
+    fn test_overflowing_directive_offsets_are_rejected() {
+        let mut sim = script_sim(1, &timer_at_start());
+        let mut scenario = Scenario::builder("offsets", 1, SCRIPT_TAG).build().unwrap();
+        scenario.directives = vec![
+            Directive::After { offset: MAX_SIM_TIME, action: Action::HealPartition }.into(),
+            Directive::After { offset: 1, action: Action::HealPartition }.into(),
+        ];
+        let err = crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap_err();
+        assert!(err.to_string().contains("overflow"), "{}", err);
+
+        let period = MAX_SIM_TIME / 2 + 1;
+        scenario.directives = vec![Directive::Every { period, repeats: 3, action: Action::HealPartition }.into()];
+        let err = crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap_err();
+        assert_eq!(err.to_string(), format!("Simulation time overflow: {} * 2", period));
+    }
+
+    #[test]
+    fn test_a_scenario_with_an_unsampleable_delay_schedules_nothing() {
+        let mut sim = script_sim(2, &timer_at_start());
+        let mut scenario = Scenario::builder("delays", 2, SCRIPT_TAG).build().unwrap();
+        let dist = DelaySpec::Pareto { scale: 1.0, shape: 0.0, min: None, max: None };
+        scenario.directives = vec![Directive::At(5, Action::LinkDelay { link: 0, dist }).into()];
+        let err = crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap_err();
+        assert_eq!(err.to_string(), "Directive 0: Pareto delay has shape 0; it must be positive");
+        assert!(sim.queue.is_empty());
+    }
+
+    #[test]
+    fn test_rng_sites_are_interned_once_per_node() {
+        let fsyncer = Script::<()>::new().on_start(|_, ctx| {
+            let mut store = ctx.store();
+            for _ in 0..500_000 {
+                store.fsync().unwrap();
+            }
+        });
+        let mut sim = script_sim(2, &fsyncer);
+        sim.init();
+        assert_eq!(sim.recorder.site_count(), 2);
+        assert_eq!(
+            sim.recorder.draw_counts(),
+            BTreeMap::from([
+                ("store.fsync.node[0]".to_string(), 500_000),
+                ("store.fsync.node[1]".to_string(), 500_000),
+            ])
+        );
+    }
+
+    /// Values a `drawer` drew after its extra draws, per node.
+    type Drawn = Arc<Mutex<BTreeMap<NodeId, Vec<u64>>>>;
+
+    /// Node 0 makes `extra` throwaway draws on start, then every node
+    /// records ten draws and pings its neighbour so the network draws too.
+    fn drawer(extra: usize, drawn: &Drawn) -> Script<u8> {
+        let drawn = drawn.clone();
+        Script::new().on_start(move |_, ctx| {
+            if ctx.node_id() == 0 {
+                for _ in 0..extra {
+                    ctx.rng_u64();
+                }
+            }
+            let values = (0..10).map(|_| ctx.rng_u64()).collect();
+            drawn.lock().unwrap().insert(ctx.node_id(), values);
+            let dst = (ctx.node_id() + 1) % 3;
+            ctx.send(dst, &0).unwrap();
+        })
+    }
+
+    /// Runs three `drawer`s, node 0 making `extra` throwaway draws, and
+    /// returns what each drew and when each ping arrived.
+    fn run_drawers(extra: usize) -> (BTreeMap<NodeId, Vec<u64>>, Vec<SimTime>, Simulation) {
+        let drawn = Drawn::default();
+        let mut sim = script_sim(3, &drawer(extra, &drawn));
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Uniform { lo: 1_000, hi: 1_000_000 };
+        }
+        sim.enable_message_journal();
+        sim.init();
+        while sim.step().is_some() {}
+        let mut arrivals: Vec<(NodeId, SimTime)> = sim
+            .message_journal()
+            .unwrap()
+            .records()
+            .iter()
+            .flat_map(|r| r.deliveries.iter().map(move |d| (r.dst, d.time)))
+            .collect();
+        arrivals.sort_unstable();
+        let drawn = drawn.lock().unwrap().clone();
+        (drawn, arrivals.into_iter().map(|(_, time)| time).collect(), sim)
+    }
+
+    #[test]
+    fn test_extra_draws_on_one_node_leave_other_streams_alone() {
+        let (base, base_arrivals, base_sim) = run_drawers(0);
+        let (extra, extra_arrivals, extra_sim) = run_drawers(5);
+        // Node 0's own sequence moves on by the five extra draws...
+        assert_ne!(base[&0], extra[&0]);
+        assert_eq!(base[&0][5..], extra[&0][..5]);
+        // ...while the other nodes and the network see the same values
+        assert_eq!(base[&1], extra[&1]);
+        assert_eq!(base[&2], extra[&2]);
+        assert_eq!(base_arrivals, extra_arrivals);
+        // The nodes draw from distinct streams
+        assert_ne!(base[&0], base[&1]);
+
+        let streams = extra_sim.recorder.stream_draw_counts();
+        assert_eq!(streams[&crate::rng::RngStream::Proto(0)], 15);
+        assert_eq!(streams[&crate::rng::RngStream::Proto(1)], 10);
+        assert_eq!(
+            streams[&crate::rng::RngStream::Net],
+            base_sim.recorder.stream_draw_counts()[&crate::rng::RngStream::Net]
+        );
+    }
+
+    #[test]
+    fn test_clamped_delays_are_counted() {
+        let mut sim = script_sim(3, &drawer(0, &Drawn::default()));
+        for link in sim.world.net.links.values_mut() {
+            // Eleven days, were it not for the clamp
+            link.faults.base_delay = DelaySpec::Pareto { scale: 1e15, shape: 1.5, min: None, max: Some(5_000_000) };
+            link.faults.jitter = DelaySpec::Const(0);
+        }
+        sim.enable_message_journal();
+        sim.init();
+        let report = sim.run_until(sim_from_ms(100));
+        assert_eq!(report.metrics.delay_clamped, 3);
+        let records = sim.message_journal().unwrap().records();
+        assert!(records.iter().all(|r| r.deliveries.iter().all(|d| d.time == 5_000_000)));
+    }
+
+    /// Appends, puts and fsyncs on randomly spaced timers, five times.
+    fn journal_run() -> Vec<crate::store::JournalEntry> {
+        journal_sim(None).store_journal().unwrap().entries().to_vec()
+    }
+
+    /// The simulation `journal_run` runs, streaming the journal to `stream`
+    /// if given.
+    fn journal_sim(stream: Option<&std::path::Path>) -> Simulation {
+        let scribe = Script::<(), usize>::with_state(5)
+            .on_start(|_, ctx| {
+                let delay = (ctx.rng_u64() % 1_000) as SimTime;
+                ctx.set_timer(delay);
+            })
+            .on_timer(|rounds, ctx, _| {
+                let value = bytes::Bytes::from(ctx.rng_u64().to_le_bytes().to_vec());
+                let mut store = ctx.store();
+                store.append_log(LogRecord::new(1, value.clone())).unwrap();
+                store.kv_put(bytes::Bytes::from_static(b"last"), value).unwrap();
+                store.fsync().unwrap();
+                drop(store);
+                *rounds -= 1;
+                if *rounds > 0 {
+                    let delay = (ctx.rng_u64() % 1_000) as SimTime;
+                    ctx.set_timer(delay);
+                }
+            });
+        let mut sim = script_sim(3, &scribe);
+        match stream {
+            Some(path) => sim.stream_store_journal(path, RotationPolicy::new(1024)).unwrap(),
+            None => sim.enable_store_journal(),
+        }
+        sim.init();
+        while sim.step().is_some() {}
+        sim
+    }
+
+    #[test]
+    fn test_store_journal_follows_event_order() {
+        let entries = journal_run();
+        assert_eq!(entries.len(), 3 * 5 * 3);
+        assert!(entries.windows(2).all(|w| w[0].time <= w[1].time));
+        // Each event's mutations are contiguous and in append, put, fsync order.
+        for chunk in entries.chunks(3) {
+            assert!(chunk.iter().all(|e| e.event_id == chunk[0].event_id && e.node == chunk[0].node));
+            assert!(matches!(chunk[0].op, crate::store::StoreOp::AppendLog { .. }));
+            assert!(matches!(chunk[2].op, crate::store::StoreOp::Fsync));
+        }
+        assert_eq!(entries, journal_run());
+    }
+
+    #[test]
+    fn test_streamed_store_journal_is_written_as_the_run_goes() {
+        let dir = std::env::temp_dir().join(format!("ftsim-streamed-store-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("store.jsonl");
+        let mut sim = journal_sim(Some(&path));
+        assert!(sim.store_journal().unwrap().entries().is_empty());
+        let (manifest, _) = sim.finish_journal_streams().unwrap();
+        let manifest = manifest.unwrap();
+        assert!(manifest.segments.len() > 1, "{:?}", manifest);
+
+        let lines: Vec<String> =
+            std::io::BufRead::lines(crate::segments::open_input(&path, None).unwrap()).map(Result::unwrap).collect();
+        let expected: Vec<String> = journal_run().iter().map(|e| serde_json::to_string(e).unwrap()).collect();
+        assert_eq!(lines, expected);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// A three-node RaftLite cluster, initialized.
+    fn raft_cluster() -> Harness {
+        use ftsim_proto::protocols::raft_lite::RaftLite;
+        Harness::cluster(3, 7, || boxed_dyn(RaftLite::default()))
+    }
+
+    /// Runs a three-node RaftLite cluster for a second with `action`
+    /// directed at the start.
+    fn raft_with_action(action: Option<Action>) -> Harness {
+        let mut harness = raft_cluster();
+        if let Some(action) = action {
+            let scenario = Scenario::builder("intervene", 3, ProtoTag(1)).at(0, action).build().unwrap();
+            crate::scenario::load_and_schedule(harness.sim_mut(), &scenario).unwrap();
+        }
+        harness.run_until_ms(1_000);
+        harness
+    }
+
+    /// Returns the journaled messages from `src` to `dst` of kind `variant`.
+    fn journaled(harness: &Harness, src: NodeId, dst: NodeId, variant: &str) -> Vec<MessageRecord> {
+        let sim = harness.sim();
+        sim.message_journal()
+            .unwrap()
+            .records()
+            .iter()
+            .filter(|r| r.src == src && r.dst == dst)
+            .filter(|r| sim.world.node(dst).message_variant(r.proto_tag, r.payload.as_ref().unwrap()).as_deref() == Some(variant))
+            .cloned()
+            .collect()
+    }
+
+    /// The node that leads the undisturbed three-node RaftLite run.
+    fn raft_leader() -> NodeId {
+        let harness = raft_with_action(None);
+        (0..3).find(|&n| harness.kv(n, "role").is_some_and(|r| r == "Leader")).expect("no leader elected")
+    }
+
+    #[test]
+    fn test_published_state_keeps_field_types() {
+        let harness = raft_with_action(None);
+        let report = harness.sim().report(SimulationOutcome::StopTime(harness.sim().now()));
+        let leader = raft_leader();
+        harness.expect_kv(leader, "role", "Leader");
+        for node in 0..3 {
+            let term = report.node_state(node, "term").and_then(|t| t.as_u64()).expect("a numeric term");
+            assert_eq!(Some(term as f64), report.node_metric(node, "term"));
+            let role = report.node_state(node, "role").and_then(|r| r.as_str());
+            assert_eq!(role, report.node_kv(node, "role"));
+            assert_eq!(role == Some("Leader"), node == leader);
+        }
+        assert!(report.node_state(leader, "voted_for").is_some_and(|v| v.as_u64() == Some(leader as u64)));
+    }
+
+    #[test]
+    fn test_raft_publishes_only_what_changed() {
+        // A node coming up publishes its role in lower case, as it always has
+        let fresh = raft_cluster();
+        for node in 0..3 {
+            fresh.expect_kv(node, "role", "follower");
+        }
+
+        let harness = raft_with_action(None);
+        let report = harness.sim().report(SimulationOutcome::StopTime(harness.sim().now()));
+        // Twenty heartbeat rounds reach every node, but after the election
+        // little changes
+        for node in &report.nodes {
+            let samples = node.metrics["term"].len();
+            assert!(samples < 10, "node {} sampled its term {} times", node.id, samples);
+        }
+    }
+
+    #[test]
+    fn test_primary_backup_publishes_its_state() {
+        let mut sim = pb_sim();
+        sim.run_until(sim_from_ms(50));
+        let report = sim.report(SimulationOutcome::StopTime(sim.now()));
+        for node in 0..3 {
+            // The role KV keeps its case beside the published state
+            let role = if node == 0 { "primary" } else { "backup" };
+            assert_eq!(report.node_kv(node, "role"), Some(role));
+            assert_eq!(report.node_state(node, "role").and_then(|r| r.as_str()), Some(role));
+            assert_eq!(report.node_state(node, "primary").and_then(|p| p.as_u64()), Some(0));
+            assert_eq!(report.node_state(node, "data_entries").and_then(|n| n.as_u64()), Some(1));
+            assert_eq!(report.node_state(node, "last_key").and_then(|k| k.as_str()), Some("k"));
+            assert_eq!(report.node_state(node, "crashed").and_then(|c| c.as_bool()), Some(false));
+        }
+    }
+
+    #[test]
+    fn test_on_shutdown_runs_on_up_nodes_when_the_run_ends() {
+        use ftsim_proto::protocols::raft_lite::FINAL_LOG_HASH_KEY;
+        let mut harness = raft_cluster();
+        let scenario = Scenario::builder("shutdown", 3, ProtoTag(1))
+            .at(sim_from_ms(1), Action::Crash { node: 2, duration: SimDuration::Forever })
+            .build()
+            .unwrap();
+        let sim = harness.sim_mut();
+        crate::scenario::load_and_schedule(sim, &scenario).unwrap();
+
+        // Pausing at a time limit does not end the run
+        let paused = sim.run_until(sim_from_ms(500));
+        assert_eq!(paused.node_kv(0, FINAL_LOG_HASH_KEY), None);
+
+        let report = sim.run_to_end(sim_from_ms(1_000));
+        let hash = report.node_kv(0, FINAL_LOG_HASH_KEY).expect("node 0 published no log hash");
+        assert_eq!(report.node_kv(1, FINAL_LOG_HASH_KEY), Some(hash));
+        assert_eq!(report.node_kv(2, FINAL_LOG_HASH_KEY), None);
+        // The survivors elected a leader, whose first entry both committed
+        assert_ne!(hash, format!("{:016x}", 0xcbf2_9ce4_8422_2325u64));
+        assert!(report.node_metric(0, "commit_index").is_some_and(|c| c >= 1.0), "{:?}", report.nodes[0]);
+    }
+
+    /// Fails once any node has published the KV it names.
+    struct Unpublished(&'static str);
+
+    impl Invariant for Unpublished {
+        fn name(&self) -> &str {
+            "unpublished"
+        }
+
+        fn check(&mut self, _world: &World, node_kvs: &[indexmap::IndexMap<String, serde_json::Value>], _time: SimTime) -> Result<(), String> {
+            match node_kvs.iter().position(|kvs| kvs.contains_key(self.0)) {
+                Some(node) => Err(format!("node {} published {}", node, self.0)),
+                None => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_invariants_check_what_on_shutdown_published() {
+        let script = Script::<()>::new()
+            .on_start(|_, ctx| {
+                ctx.set_timer(sim_from_ms(10));
+            })
+            .on_shutdown(|_, ctx| ctx.log_kv("summary", "done"));
+        let mut sim = script_sim(2, &script);
+        sim.add_invariant(Box::new(Unpublished("summary")));
+        sim.init();
+        assert_eq!(sim.run_until(sim_from_ms(1)).outcome, SimulationOutcome::StopTime(sim_from_ms(1)));
+        assert!(sim.invariant_violation().is_none());
+
+        let report = sim.run_to_end(sim_from_ms(2));
+        assert_eq!(report.outcome, SimulationOutcome::InvariantViolated);
+        let violation = sim.invariant_violation().expect("a violation");
+        assert_eq!((violation.invariant.as_str(), violation.message.as_str()), ("unpublished", "node 0 published summary"));
+    }
+
+    #[test]
+    fn test_causal_delivery_fails_on_malformed_entries() {
+        let script = Script::<()>::new().on_start(|_, ctx| {
+            ctx.publish_state(&serde_json::json!({ "delivered": [{ "origin": 0, "clock": { "0": "one" } }] }));
+        });
+        let mut sim = script_sim(1, &script);
+        sim.add_invariant(crate::invariants::builtin("causal_delivery").unwrap());
+        sim.init();
+        assert_eq!(sim.run_to_end(sim_from_ms(1)).outcome, SimulationOutcome::InvariantViolated);
+        let violation = sim.invariant_violation().expect("a violation");
+        assert!(violation.message.starts_with("node 0 delivered a message with a malformed clock"), "{}", violation);
+    }
+
+    #[test]
+    fn test_drop_nth_drops_exactly_that_occurrence() {
+        let leader = raft_leader();
+        let follower = (leader + 1) % 3;
+        let drop = Action::DropNth { src: leader, dst: follower, variant: Some("AppendEntries".to_string()), n: 2 };
+        let harness = raft_with_action(Some(drop));
+        let sim = harness.sim();
+
+        let records = journaled(&harness, leader, follower, "AppendEntries");
+        assert!(records.len() > 2);
+        let hit = sim.interventions()[0].hit.expect("intervention never hit");
+        assert_eq!(hit.msg_id, records[1].msg_id);
+        assert_eq!(hit.time, records[1].sent_at);
+        for (i, record) in records.iter().enumerate() {
+            assert_eq!(record.deliveries.is_empty(), i == 1, "occurrence {}", i + 1);
+        }
+        // Other kinds on the same link are not counted
+        assert_eq!(sim.interventions()[0].seen, 2);
+        assert!(journaled(&harness, leader, follower, "RequestVote").iter().all(|r| !r.deliveries.is_empty()));
+        let report = sim.report(SimulationOutcome::StopTime(sim.now()));
+        assert_eq!(report.interventions[0].hit, Some(hit));
+    }
+
+    #[test]
+    fn test_delay_nth_holds_back_only_that_occurrence() {
+        let leader = raft_leader();
+        let follower = (leader + 2) % 3;
+        let by = sim_from_ms(40);
+        let delay = Action::DelayNth { src: leader, dst: follower, variant: Some("AppendEntries".to_string()), n: 2, by };
+        let harness = raft_with_action(Some(delay));
+        let sim = harness.sim();
+
+        let records = journaled(&harness, leader, follower, "AppendEntries");
+        assert_eq!(sim.interventions()[0].hit.unwrap().msg_id, records[1].msg_id);
+        for (i, record) in records.iter().enumerate() {
+            let latency = record.deliveries[0].time - record.sent_at;
+            assert_eq!(latency >= by, i == 1, "occurrence {} took {}", i + 1, latency);
+        }
+    }
+
+    /// Runs three raft nodes that never fsync their vote on `durability`
+    /// stores for two seconds. Nodes 0 and 1 are cut off from each other, so
+    /// each candidate needs node 2's vote, and every link takes 20ms, so a
+    /// second candidate's request reaches node 2 only after it crashed and
+    /// came back right after casting its first vote. Seed 8 has node 1 time
+    /// out first and node 0 18ms later, both before node 2 does.
+    fn revote_run(durability: Durability) -> Harness {
+        use crate::{net::Net, store::MemStore};
+        use ftsim_proto::{api::STATE_KEY, protocols::raft_lite::RaftLite};
+        let mut net = Net::from_topology(3, &TopologySpec::FullMesh);
+        net.disconnect(0, 1);
+        net.disconnect(1, 0);
+        for link in net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(20_000_000);
+            link.faults.jitter = DelaySpec::Const(0);
+        }
+        let nodes = (0..3)
+            .map(|id| {
+                let store = Box::new(MemStore::with_durability(durability));
+                let mut node = Node::new(id, boxed_dyn(RaftLite::without_vote_fsync()), store);
+                node.set_peers(net.peers_of(id).collect());
+                node
+            })
+            .collect();
+        let telemetry = TelemetryBus::detached(3, &TelemetrySpec::default());
+        let mut harness = Harness::new(Simulation::new(8, World { nodes, net }, telemetry));
+        harness.sim_mut().add_invariant(crate::invariants::builtin("single_leader_per_term").unwrap());
+
+        let voted = |h: &Harness| h.kv(2, STATE_KEY).is_some_and(|s| s["voted_for"].as_u64().is_some_and(|v| v != 2));
+        while !voted(&harness) && harness.sim().now() < sim_from_ms(2_000) {
+            harness.sim_mut().step();
+        }
+        assert!(voted(&harness), "node 2 never voted for a candidate");
+        let sim = harness.sim_mut();
+        let scenario = Scenario::builder("revote", 3, ProtoTag(1))
+            .at(sim.now(), Action::Crash { node: 2, duration: SimDuration::Finite(sim_from_ms(1)) })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(sim, &scenario).unwrap();
+        harness.run_until_ms(2_000);
+        harness
+    }
+
+    #[test]
+    fn test_unsynced_vote_is_cast_twice_after_a_crash() {
+        // The vote is lost with the crash, so node 2 votes again in the
+        // same term and both candidates win it
+        let buffered = revote_run(Durability::BufferedUntilFsync);
+        let violation = buffered.sim().invariant_violation().expect("no node voted twice");
+        assert_eq!(violation.invariant, "single_leader_per_term");
+
+        // Kept however it was written, the vote is not cast again
+        let durable = revote_run(Durability::AlwaysDurable);
+        assert!(durable.sim().invariant_violation().is_none());
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug)]
+    enum EchoMsg {
+        Ping(u64),
+        Pong(u64),
+    }
+
+    /// What the requesting `echo` node saw: replies, timeouts and plain messages.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum EchoOutcome {
+        Reply(RequestId, u64),
+        Timeout(RequestId),
+        Message(u64),
+    }
+
+    type EchoLog = Arc<Mutex<(Vec<RequestId>, Vec<EchoOutcome>)>>;
+
+    /// Node 0 pings node 1 three times, 10ms apart, each with a 50ms
+    /// timeout; node 1 echoes every ping back with `reply`.
+    fn echo(log: &EchoLog) -> Script<EchoMsg> {
+        let (on_message, on_reply, on_timeout, on_timer) = (log.clone(), log.clone(), log.clone(), log.clone());
+        Script::new()
+            .on_start(|_, ctx| {
+                if ctx.node_id() == 0 {
+                    ctx.set_timer(sim_from_ms(10));
+                }
+            })
+            .on_message(move |_, ctx, _, msg| match msg {
+                EchoMsg::Ping(n) => assert!(ctx.reply(&EchoMsg::Pong(n)).unwrap()),
+                EchoMsg::Pong(n) => on_message.lock().unwrap().1.push(EchoOutcome::Message(n)),
+            })
+            .on_reply(move |_, _, req, src, msg| {
+                assert_eq!(src, 1);
+                let EchoMsg::Pong(n) = msg else { panic!("unexpected reply {:?}", msg) };
+                on_reply.lock().unwrap().1.push(EchoOutcome::Reply(req, n));
+            })
+            .on_request_timeout(move |_, _, req| on_timeout.lock().unwrap().1.push(EchoOutcome::Timeout(req)))
+            .on_timer(move |_, ctx, _| {
+                let mut log = on_timer.lock().unwrap();
+                let n = log.0.len() as u64;
+                log.0.push(ctx.request(1, &EchoMsg::Ping(n), sim_from_ms(50)).unwrap());
+                if n < 2 {
+                    ctx.set_timer(sim_from_ms(10));
+                }
+            })
+    }
+
+    /// Runs two `echo` nodes with `action` directed at the start and returns
+    /// the request IDs node 0 got back and what it saw, in order.
+    fn echo_run(action: Action) -> (Vec<RequestId>, Vec<EchoOutcome>) {
+        let log = EchoLog::default();
+        let mut sim = script_sim(2, &echo(&log));
+        sim.init();
+        let scenario = Scenario::builder("echo", 2, SCRIPT_TAG).at(0, action).build().unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.run_until(sim_from_ms(1_000));
+        let log = log.lock().unwrap();
+        (log.0.clone(), log.1.clone())
+    }
+
+    #[test]
+    fn test_dropped_reply_times_out_exactly_once() {
+        let (reqs, outcomes) = echo_run(Action::DropNth { src: 1, dst: 0, variant: None, n: 2 });
+        assert_eq!(reqs.len(), 3);
+        assert_eq!(
+            outcomes,
+            vec![EchoOutcome::Reply(reqs[0], 0), EchoOutcome::Reply(reqs[2], 2), EchoOutcome::Timeout(reqs[1])]
+        );
+    }
+
+    #[test]
+    fn test_late_reply_arrives_as_a_plain_message() {
+        let by = sim_from_ms(100);
+        let (reqs, outcomes) = echo_run(Action::DelayNth { src: 1, dst: 0, variant: None, n: 2, by });
+        assert_eq!(
+            outcomes,
+            vec![
+                EchoOutcome::Reply(reqs[0], 0),
+                EchoOutcome::Reply(reqs[2], 2),
+                EchoOutcome::Timeout(reqs[1]),
+                EchoOutcome::Message(1),
+            ]
+        );
+    }
+
+    /// Node 0 asks node 1 for a `Pong` at start. Stateless, but branchable.
+    struct BranchedEcho {
+        log: Arc<Mutex<Vec<EchoOutcome>>>,
+    }
+
+    impl Protocol<EchoMsg> for BranchedEcho {
+        fn name(&self) -> &'static str {
+            "branched_echo"
+        }
+
+        fn proto_tag(&self) -> ProtoTag {
+            SCRIPT_TAG
+        }
+
+        fn init(&mut self, _ctx: &mut Ctx<EchoMsg>) {}
+
+        fn start(&mut self, ctx: &mut Ctx<EchoMsg>) {
+            if ctx.node_id() == 0 {
+                ctx.request(1, &EchoMsg::Ping(0), sim_from_ms(50)).unwrap();
+            }
+        }
+
+        fn on_message(&mut self, ctx: &mut Ctx<EchoMsg>, _src: NodeId, msg: EchoMsg) {
+            match msg {
+                EchoMsg::Ping(n) => assert!(ctx.reply(&EchoMsg::Pong(n)).unwrap()),
+                EchoMsg::Pong(n) => self.log.lock().unwrap().push(EchoOutcome::Message(n)),
+            }
+        }
+
+        fn on_reply(&mut self, _ctx: &mut Ctx<EchoMsg>, req: RequestId, _src: NodeId, msg: EchoMsg) {
+            let EchoMsg::Pong(n) = msg else { panic!("unexpected reply {:?}", msg) };
+            self.log.lock().unwrap().push(EchoOutcome::Reply(req, n));
+        }
+
+        fn on_request_timeout(&mut self, _ctx: &mut Ctx<EchoMsg>, req: RequestId) {
+            self.log.lock().unwrap().push(EchoOutcome::Timeout(req));
+        }
+
+        fn on_timer(&mut self, _ctx: &mut Ctx<EchoMsg>, _timer: TimerId) {}
+
+        fn on_fault(&mut self, _ctx: &mut Ctx<EchoMsg>, _fault: FaultEvent) {}
+
+        fn snapshot_state(&self) -> Option<Vec<u8>> {
+            Some(Vec::new())
+        }
+
+        fn restore_state(&mut self, _state: &[u8]) -> Result<(), CodecError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_requests_in_flight_survive_a_saved_state() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let world = World::full_mesh(2, |_| boxed_dyn(BranchedEcho { log: log.clone() }));
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let mut sim = Simulation::new(7, world, TelemetryBus::new(tx, 2, &TelemetrySpec::default()));
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(5_000_000);
+            link.faults.jitter = DelaySpec::Const(0);
+        }
+        sim.init();
+        sim.run_until(sim_from_ms(1));
+        let saved = sim.save_state().unwrap();
+        sim.run_until(sim_from_ms(100));
+        let first = log.lock().unwrap().clone();
+        assert!(matches!(first[..], [EchoOutcome::Reply(_, 0)]), "{:?}", first);
+
+        // Replayed from before the reply, it is a reply again
+        sim.load_state(saved).unwrap();
+        sim.run_until(sim_from_ms(100));
+        assert_eq!(*log.lock().unwrap(), [first[0].clone(), first[0].clone()]);
+    }
+
+    #[test]
+    fn test_delay_past_the_end_of_time_is_reported() {
+        let received = Arc::new(Mutex::new(0));
+        let on_message = received.clone();
+        let sender = Script::<EchoMsg>::new()
+            .on_start(|_, ctx| {
+                if ctx.node_id() == 0 {
+                    ctx.set_timer(sim_from_ms(10));
+                }
+            })
+            .on_timer(|_, ctx, _| ctx.send_after(1, &EchoMsg::Ping(0), sim_from_ms(1)).unwrap())
+            .on_message(move |_, _, _, _| *on_message.lock().unwrap() += 1);
+        let mut sim = script_sim(2, &sender);
+        sim.init();
+        let by = MAX_SIM_TIME;
+        let scenario = Scenario::builder("late", 2, SCRIPT_TAG)
+            .at(0, Action::DelayNth { src: 0, dst: 1, variant: None, n: 1, by })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.run_until(sim_from_ms(1_000));
+        assert_eq!(*received.lock().unwrap(), 0);
+        assert_eq!(sim.time_overflows(), 1);
+    }
+
+    /// Runs two `echo` nodes with a flow graph split by variant, one phase
+    /// per ping, and a journal to rebuild the graph from.
+    fn echo_flow() -> Simulation {
+        let mut sim = script_sim(2, &echo(&EchoLog::default()));
+        let phases: Vec<Phase> = (0..3)
+            .map(|i| Phase {
+                name: format!("ping {}", i),
+                start: sim_from_ms(10 * (i + 1)),
+                end: sim_from_ms(10 * (i + 2)),
+                expect: Vec::new(),
+            })
+            .collect();
+        sim.enable_flow_graph(true, &phases);
+        sim.enable_message_journal();
+        sim.init();
+        sim.run_until(sim_from_ms(1_000));
+        sim
+    }
+
+    #[test]
+    fn test_flow_graph_weighs_edges_by_variant() {
+        let sim = echo_flow();
+        let graph = sim.flow_graph().unwrap();
+        let weights: Vec<(NodeId, NodeId, Option<&str>, u64, u64)> = graph
+            .edges()
+            .iter()
+            .map(|(k, w)| (k.src, k.dst, k.variant.as_deref(), w.messages, w.bytes))
+            .collect();
+        assert_eq!(weights, [(0, 1, Some("Ping"), 3, 6), (1, 0, Some("Pong"), 3, 6)]);
+        for phase in graph.phases() {
+            assert_eq!(phase.edges.values().map(|w| w.messages).sum::<u64>(), 2, "{}", phase.name);
+        }
+
+        let journal = sim.message_journal().unwrap();
+        let rebuilt = crate::flow::FlowGraph::from_journal(journal, true, &[]);
+        assert_eq!(rebuilt.edges(), graph.edges());
+    }
+
+    #[test]
+    fn test_flow_graph_dot_is_stable_for_a_seed() {
+        let dot = echo_flow().flow_graph().unwrap().to_dot();
+        assert_eq!(dot, echo_flow().flow_graph().unwrap().to_dot());
+        assert!(dot.contains("n0 -> n1 [label=\"Ping: 3 msgs"), "{}", dot);
+        assert!(dot.contains("subgraph cluster_p2"), "{}", dot);
+    }
+
+    /// A delivery seen by `delayed_sender`: receiver, label, receiver's time
+    /// and the sender's clock stamp.
+    type Arrivals = Arc<Mutex<Vec<(NodeId, u64, SimTime, SimTime)>>>;
+
+    /// On start, node 0 sends label 0 to node 1 right away, label 1 to node
+    /// 1 after 5ms and broadcasts label 2 after 7ms.
+    fn delayed_sender(arrivals: &Arrivals) -> Script<u64> {
+        let arrivals = arrivals.clone();
+        Script::new()
+            .on_start(|_, ctx| {
+                if ctx.node_id() == 0 {
+                    ctx.send(1, &0).unwrap();
+                    ctx.send_after(1, &1, sim_from_ms(5)).unwrap();
+                    ctx.broadcast_after(&2, sim_from_ms(7), None).unwrap();
+                }
+            })
+            .on_message(move |_, ctx, _, label| {
+                let sent_at = ctx.message_meta().unwrap().sent_at;
+                arrivals.lock().unwrap().push((ctx.node_id(), label, ctx.now(), sent_at));
+            })
+    }
+
+    /// Runs three `delayed_sender` nodes on zero-latency links that drop
+    /// with probability `drop`, with `faults` scheduled, and returns the
+    /// deliveries in order.
+    fn delayed_send_run(drop: f64, faults: Vec<(SimTime, Action)>) -> (Simulation, Vec<(NodeId, u64, SimTime, SimTime)>) {
+        let arrivals = Arrivals::default();
+        let mut sim = script_sim(3, &delayed_sender(&arrivals));
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(0);
+            link.faults.jitter = DelaySpec::Const(0);
+            link.faults.drop = Bernoulli(drop);
+        }
+        sim.set_message_journal(MessageJournal::new());
+        sim.init();
+        let scenario = faults
+            .into_iter()
+            .fold(Scenario::builder("delayed", 3, SCRIPT_TAG), |builder, (at, action)| builder.at(at, action))
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.run();
+        let arrivals = arrivals.lock().unwrap().clone();
+        (sim, arrivals)
+    }
+
+    #[test]
+    fn test_delayed_sends_arrive_that_much_later() {
+        let (sim, arrivals) = delayed_send_run(0.0, Vec::new());
+        let (ms5, ms7) = (sim_from_ms(5), sim_from_ms(7));
+        assert_eq!(arrivals, [(1, 0, 0, 0), (1, 1, ms5, ms5), (1, 2, ms7, ms7), (2, 2, ms7, ms7)]);
+
+        // The journal accounts a delayed message as sent when it leaves
+        let journal = sim.message_journal().unwrap();
+        let sent: Vec<SimTime> = journal.records().iter().map(|r| r.sent_at).collect();
+        assert_eq!(sent, [0, ms5, ms7, ms7]);
+        assert!(journal.records().iter().all(|r| r.deliveries.iter().all(|d| d.time == r.sent_at)));
+
+        // The link still drops them like any other message
+        let (sim, arrivals) = delayed_send_run(1.0, Vec::new());
+        assert!(arrivals.is_empty());
+        assert_eq!(sim.message_journal().unwrap().records().len(), 4);
+    }
+
+    #[test]
+    fn test_delayed_sends_meet_the_network_as_it_is_when_they_leave() {
+        let (ms2, ms5, ms7) = (sim_from_ms(2), sim_from_ms(5), sim_from_ms(7));
+        // Slowed after the sends were made, the link slows the delayed ones
+        let slow = Action::LinkDelay { link: 0, dist: DelaySpec::Const(2_000_000) };
+        let (sim, arrivals) = delayed_send_run(0.0, vec![(sim_from_ms(3), slow)]);
+        assert_eq!((sim.world.net.links[&0].src, sim.world.net.links[&0].dst), (0, 1));
+        assert_eq!(arrivals, [(1, 0, 0, 0), (1, 1, ms7, ms5), (2, 2, ms7, ms7), (1, 2, ms7 + ms2, ms7)]);
+
+        // Nor does a sender that crashed before they left send them
+        let crash = Action::Crash { node: 0, duration: SimDuration::Forever };
+        let (sim, arrivals) = delayed_send_run(0.0, vec![(sim_from_ms(6), crash)]);
+        assert_eq!(arrivals, [(1, 0, 0, 0), (1, 1, ms5, ms5)]);
+        assert_eq!(sim.message_journal().unwrap().records().len(), 2);
+    }
+
+    /// The sends `nack_sender` made and the failures reported for them, as
+    /// (time, message id, reason).
+    type Nacks = Arc<Mutex<(Vec<MsgId>, Vec<(SimTime, MsgId, SendFailure)>)>>;
+
+    /// On start, node 0 sends one message to node 1, and hears about failed
+    /// sends after 3ms.
+    fn nack_sender(nacks: &Nacks) -> Script<u64> {
+        let (sent, failed) = (nacks.clone(), nacks.clone());
+        Script::new()
+            .on_start(move |_, ctx| {
+                if ctx.node_id() == 0 {
+                    ctx.send(1, &7).unwrap();
+                    sent.lock().unwrap().0.push(ctx.last_sent_msg_id().unwrap());
+                }
+            })
+            .on_send_failed(sim_from_ms(3), move |_, ctx, dst, msg_id, reason| {
+                assert_eq!(dst, 1);
+                failed.lock().unwrap().1.push((ctx.now(), msg_id, reason));
+            })
+    }
+
+    /// Runs two `nack_sender` nodes whose links are partitioned or drop
+    /// with probability `drop`, and returns the sends and failures.
+    fn nack_run(partitioned: bool, drop: f64) -> (Vec<MsgId>, Vec<(SimTime, MsgId, SendFailure)>) {
+        let nacks = Nacks::default();
+        let mut sim = script_sim(2, &nack_sender(&nacks));
+        for link in sim.world.net.links.values_mut() {
+            link.faults.partitioned = partitioned;
+            link.faults.drop = Bernoulli(drop);
+        }
+        sim.init();
+        sim.run();
+        let nacks = nacks.lock().unwrap().clone();
+        nacks
+    }
+
+    #[test]
+    fn test_sends_into_a_partition_are_reported_once() {
+        let (sent, failed) = nack_run(true, 0.0);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(failed, [(sim_from_ms(3), sent[0], SendFailure::Partitioned)]);
+
+        // A lossy link drops the message silently
+        let (sent, failed) = nack_run(false, 1.0);
+        assert_eq!(sent.len(), 1);
+        assert!(failed.is_empty());
+    }
+
+    /// Runs two nodes with `seed`, node 0 drawing `ranges` values from
+    /// `rng_range(1..=10)` on start and then one from each other RNG
+    /// helper. Returns node 0's draws and the draws made per site.
+    fn helper_draws(seed: u64, ranges: usize) -> (Vec<u64>, BTreeMap<String, u64>) {
+        let drawn = Arc::new(Mutex::new(Vec::new()));
+        let record = drawn.clone();
+        let script = Script::<()>::new().on_start(move |_, ctx| {
+            if ctx.node_id() != 0 {
+                return;
+            }
+            let mut drawn: Vec<u64> = (0..ranges).map(|_| ctx.rng_range(1..=10)).collect();
+            drawn.push(ctx.rng_bool(0.5) as u64);
+            drawn.push(*ctx.rng_choose(&[10, 20, 30]));
+            let mut order: Vec<u64> = (0..8).collect();
+            ctx.rng_shuffle(&mut order);
+            drawn.extend(order);
+            *record.lock().unwrap() = drawn;
+        });
+        let mut harness = Harness::cluster(2, seed, || script.boxed());
+        let report = harness.sim_mut().run();
+        let drawn = drawn.lock().unwrap().clone();
+        (drawn, report.rng_draws)
+    }
+
+    #[test]
+    fn test_rng_helpers_are_deterministic_per_seed() {
+        let (drawn, sites) = helper_draws(11, 20);
+        assert_eq!(helper_draws(11, 20), (drawn.clone(), sites.clone()));
+        assert_ne!(helper_draws(12, 20).0, drawn);
+
+        let (ranges, rest) = drawn.split_at(20);
+        assert!(ranges.iter().all(|v| (1..=10).contains(v)));
+        assert!(rest[0] <= 1 && [10, 20, 30].contains(&rest[1]));
+        let mut order = rest[2..].to_vec();
+        order.sort_unstable();
+        assert_eq!(order, (0..8).collect::<Vec<_>>());
+        // Each helper draws under its own site, labelled by node
+        for site in ["proto.range", "proto.bool", "proto.choose", "proto.shuffle"] {
+            assert!(sites.get(&format!("{}.node[0]", site)).is_some_and(|&n| n > 0), "{:?}", sites);
+        }
+    }
+
+    #[test]
+    fn test_rng_range_is_unbiased() {
+        const DRAWS: usize = 100_000;
+        let (drawn, _) = helper_draws(13, DRAWS);
+        let mut counts = [0u64; 10];
+        for &value in &drawn[..DRAWS] {
+            counts[value as usize - 1] += 1;
+        }
+        let expected = DRAWS as f64 / 10.0;
+        let chi_squared: f64 = counts.iter().map(|&c| (c as f64 - expected).powi(2) / expected).sum();
+        // The 0.1% critical value for 9 degrees of freedom
+        assert!(chi_squared < 27.88, "chi-squared {} for counts {:?}", chi_squared, counts);
+    }
+
+    /// Floods node 0 from t=0 and schedules a crash of node 1 at 1ms, then
+    /// runs 50k events. Returns the simulation and when node 1 crashed.
+    fn flood_run(valve: Option<FloodValve>) -> (Simulation, Option<SimTime>) {
+        // Re-arms a zero-delay timer each time it fires, so that the clock
+        // never moves past the instant it started at
+        let flooder = Script::<()>::new()
+            .on_start(|_, ctx| {
+                if ctx.node_id() == 0 {
+                    ctx.set_timer(0);
+                }
+            })
+            .on_timer(|_, ctx, _| {
+                ctx.set_timer(0);
+            });
+        let mut sim = script_sim(2, &flooder);
+        sim.set_flood_valve(valve);
+        sim.set_max_events(Some(50_000));
+        let scenario = Scenario::builder("flood", 2, SCRIPT_TAG)
+            .at(sim_from_ms(1), Action::Crash { node: 1, duration: SimDuration::Forever })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.init();
+        sim.run();
+        let crashed = sim.timeline.transitions.iter().find(|t| t.node == 1).map(|t| t.time);
+        (sim, crashed)
+    }
+
+    #[test]
+    fn test_flood_valve_lets_scenario_faults_through() {
+        // Without the valve the flood holds the clock at zero
+        let (sim, crashed) = flood_run(None);
+        assert_eq!((sim.now(), crashed), (0, None));
+        assert!(sim.flood_deferrals().is_empty());
+
+        let quantum = sim_from_us(10);
+        let (sim, crashed) = flood_run(Some(FloodValve { threshold: 100, quantum }));
+        let crashed = crashed.expect("the crash ran");
+        assert!((sim_from_ms(1)..=sim_from_ms(1) + quantum).contains(&crashed), "crashed at {}", crashed);
+        assert_eq!(sim.world.node(1).status, NodeStatus::Down);
+        let report = sim.report(SimulationOutcome::MaxEvents(50_000));
+        assert_eq!(report.flood_deferrals.keys().collect::<Vec<_>>(), [&0]);
+
+        // The same seed and valve defer the same events
+        let (again, _) = flood_run(Some(FloodValve { threshold: 100, quantum }));
+        assert_eq!((again.now(), again.flood_deferrals()), (sim.now(), sim.flood_deferrals()));
+    }
+
+    /// Node 0 sets a one-shot timer and then a periodic one with a 10µs
+    /// period, both due at 10µs, and a probe timer at 20µs, under a valve
+    /// that lets one event per node and instant through and defers the
+    /// rest by 15µs. Runs to 55µs and returns when the periodic timer
+    /// fired and how long the probe saw it still had to go.
+    fn deferred_periodic_run() -> (Vec<SimTime>, Option<SimTime>) {
+        let firings = Arc::new(Mutex::new(Vec::new()));
+        let probed = Arc::new(Mutex::new(None));
+        let (fired, seen) = (firings.clone(), probed.clone());
+        let script = Script::<(), Option<(TimerId, TimerId)>>::with_state(None)
+            .on_start(|timers, ctx| {
+                ctx.set_timer(sim_from_us(10));
+                let periodic = ctx.set_periodic_timer(sim_from_us(10));
+                *timers = Some((periodic, ctx.set_timer(sim_from_us(20))));
+            })
+            .on_timer(move |timers, ctx, timer_id| match *timers {
+                Some((periodic, _)) if periodic == timer_id => fired.lock().unwrap().push(ctx.now()),
+                Some((periodic, probe)) if probe == timer_id => *seen.lock().unwrap() = ctx.timer_remaining(periodic),
+                _ => {}
+            });
+        let mut sim = script_sim(1, &script);
+        sim.set_flood_valve(Some(FloodValve { threshold: 1, quantum: sim_from_us(15) }));
+        sim.init();
+        sim.run_until(sim_from_us(55));
+        let firings = firings.lock().unwrap().clone();
+        let probed = *probed.lock().unwrap();
+        (firings, probed)
+    }
+
+    #[test]
+    fn test_deferred_timer_reports_when_it_now_fires() {
+        // The periodic timer's first firing was held back from 10µs to 25µs
+        let (_, probed) = deferred_periodic_run();
+        assert_eq!(probed, Some(sim_from_us(5)));
+    }
+
+    #[test]
+    fn test_deferred_periodic_timer_keeps_its_period_grid() {
+        // The late firing skips the 20µs deadline it was held past, and the
+        // timer carries on from 30µs rather than from when it ran
+        let (firings, _) = deferred_periodic_run();
+        let expected: Vec<SimTime> = [25, 30, 40, 50].into_iter().map(sim_from_us).collect();
+        assert_eq!(firings, expected);
+    }
+
+    #[test]
+    fn test_state_hash_covers_the_flood_tally() {
+        let quantum = sim_from_us(10);
+        let (mut sim, _) = flood_run(Some(FloodValve { threshold: 100, quantum }));
+        let hash = sim.state_hash();
+        sim.flood.deferred.clear();
+        assert_ne!(sim.state_hash(), hash);
+    }
+
+    /// Node 1 sends node 0 a message over a 10µs link while node 0 sets a
+    /// timer for the same instant, and the valve lets one event per node
+    /// and instant through, deferring the delivery to 25µs. Runs headless
+    /// under `breakpoint` and returns the outcome and the head of the
+    /// queue.
+    fn deferred_delivery_run(breakpoint: Breakpoint) -> (SimulationOutcome, Option<SimTime>) {
+        let script = Script::<u8>::new().on_start(|_, ctx| {
+            if ctx.node_id() == 0 {
+                ctx.set_timer(sim_from_us(10));
+            } else {
+                ctx.send(0, &0).unwrap();
+            }
+        });
+        let mut sim = script_sim(2, &script);
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(10_000);
+            link.faults.jitter = DelaySpec::Const(0);
+        }
+        sim.set_flood_valve(Some(FloodValve { threshold: 1, quantum: sim_from_us(15) }));
+        sim.add_breakpoint(breakpoint);
+        sim.init();
+        let outcome = sim.run().outcome;
+        (outcome, sim.queue.peek().map(|next| next.time))
+    }
+
+    #[test]
+    fn test_breakpoints_see_events_after_the_flood_valve() {
+        let delivery = Breakpoint {
+            kind: Some(crate::control::BreakKind::Deliver),
+            ..Breakpoint::default()
+        };
+        // The delivery was due at 10µs but runs at 25µs, so a breakpoint
+        // on the earlier instant never fires
+        let early = Breakpoint { until: Some(sim_from_us(10)), ..delivery.clone() };
+        assert_ne!(deferred_delivery_run(early).0, SimulationOutcome::Breakpoint);
+        let (outcome, next) = deferred_delivery_run(delivery);
+        assert_eq!((outcome, next), (SimulationOutcome::Breakpoint, Some(sim_from_us(25))));
+    }
+
+    /// Crashes four idle nodes together at 1ms for 10ms under `policy` and
+    /// runs to the end. Returns the report and when each node came back up.
+    fn crash_storm(seed: u64, policy: Option<RestartPolicy>) -> (SimulationReport, Vec<SimTime>) {
+        let mut harness = Harness::cluster(4, seed, || Script::<()>::new().boxed());
+        let mut builder = Scenario::builder("crash_storm", 4, SCRIPT_TAG);
+        for node in 0..4 {
+            builder = builder.at(sim_from_ms(1), Action::Crash { node, duration: SimDuration::Finite(sim_from_ms(10)) });
+        }
+        if let Some(policy) = policy {
+            builder = builder.restart_policy(policy);
+        }
+        crate::scenario::load_and_schedule(harness.sim_mut(), &builder.build().unwrap()).unwrap();
+        let report = harness.run_until_ms(100);
+        let restarts = (0..4)
+            .map(|node| {
+                let transitions = &harness.sim().timeline.transitions;
+                let up = transitions.iter().filter(|t| t.node == node && t.status == NodeStatus::Up);
+                up.last().expect("the node restarted").time
+            })
+            .collect();
+        (report, restarts)
+    }
+
+    #[test]
+    fn test_restarts_are_simultaneous_without_a_policy() {
+        let (report, restarts) = crash_storm(7, None);
+        assert_eq!(restarts, vec![sim_from_ms(11); 4]);
+        assert_eq!(report.metrics.simultaneous_restarts, 3);
+        assert!((0..4).all(|node| report.node_metric(node, "restart_stagger_ms").is_none()));
+    }
+
+    #[test]
+    fn test_restart_policy_staggers_timed_crashes() {
+        let policy = RestartPolicy { stagger: DelaySpec::Uniform { lo: 0, hi: sim_from_ms(50) as u64 } };
+        let (report, restarts) = crash_storm(7, Some(policy));
+        for (node, &at) in restarts.iter().enumerate() {
+            assert!((sim_from_ms(11)..=sim_from_ms(61)).contains(&at), "node {} restarted at {}", node, at);
+            let offset = report.node_metric(node as NodeId, "restart_stagger_ms").expect("the offset is recorded");
+            assert_eq!(sim_from_ms(11) + (offset * 1e6).round() as SimTime, at);
+        }
+        assert!(restarts.iter().any(|&at| at != restarts[0]));
+        assert!(report.metrics.simultaneous_restarts < 3);
+
+        // The offsets are a function of the seed
+        assert_eq!(crash_storm(7, Some(policy)).1, restarts);
+        assert_ne!(crash_storm(8, Some(policy)).1, restarts);
+    }
+
+    /// Runs loop iterations until a pending request pauses the simulation.
+    fn tick_until_paused(sim: &mut Simulation) {
+        for _ in 0..1_000_000 {
+            if let Tick::Wait(_) = sim.tick(MAX_SIM_TIME) {
+                if sim.state() == SimulationState::Paused {
+                    return;
+                }
+            }
+        }
+        panic!("simulation never paused");
+    }
+
+    #[test]
+    fn test_step_n_and_run_until_controls() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        sim.set_control_channel(rx);
+
+        tx.send(ControlMsg::Pause).unwrap();
+        tick_until_paused(sim);
+        assert_eq!(sim.events_processed(), 0);
+
+        tx.send(ControlMsg::StepN(25)).unwrap();
+        tick_until_paused(sim);
+        assert_eq!(sim.events_processed(), 25);
+
+        let target = sim.now() + sim_from_ms(500);
+        tx.send(ControlMsg::RunUntil(target)).unwrap();
+        tick_until_paused(sim);
+        assert!(sim.events_processed() > 25);
+        assert!(sim.now() <= target);
+        assert!(sim.queue.peek().unwrap().time > target);
+
+        // A request that is already satisfied pauses without executing anything.
+        let before = sim.events_processed();
+        tx.send(ControlMsg::RunUntil(sim.now())).unwrap();
+        tick_until_paused(sim);
+        tx.send(ControlMsg::StepN(0)).unwrap();
+        tick_until_paused(sim);
+        assert_eq!(sim.events_processed(), before);
+    }
+
+    #[test]
+    fn test_paused_run_with_empty_queue_waits_for_resume() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let mut sim = script_sim(1, &timer_at_start());
+        sim.init();
+        sim.set_control_channel(rx);
+        tx.send(ControlMsg::Pause).unwrap();
+        tick_until_paused(&mut sim);
+        while sim.step().is_some() {}
+
+        // Paused, an empty queue does not complete the run
+        assert!(matches!(sim.tick(MAX_SIM_TIME), Tick::Wait(_)));
+        assert_eq!(sim.state(), SimulationState::Paused);
+
+        // Work sent along with the resume runs before the run ends
+        tx.send(ControlMsg::Resume).unwrap();
+        tx.send(ControlMsg::KillNode(0)).unwrap();
+        assert!(matches!(sim.tick(MAX_SIM_TIME), Tick::Stepped));
+        assert_eq!(sim.world.node(0).status, NodeStatus::Down);
+        assert!(matches!(sim.tick(MAX_SIM_TIME), Tick::Done(SimulationOutcome::QueueExhausted)));
+        assert_eq!(sim.state(), SimulationState::Completed);
+    }
+
+    #[test]
+    fn test_control_messages_arriving_as_the_run_ends_are_applied() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        sim.set_control_channel(rx);
+        let stop_at = sim.now();
+        assert!(sim.queue.peek().unwrap().time > stop_at);
+
+        // A kill that arrives after the tick drained the channel, right as it
+        // decides the next event is past the stop time
+        tx.send(ControlMsg::KillNode(1)).unwrap();
+        assert!(matches!(sim.end_run(SimulationOutcome::StopTime(stop_at)), Tick::Again));
+        assert!(matches!(sim.tick(stop_at), Tick::Stepped));
+        assert_eq!(sim.world.node(1).status, NodeStatus::Down);
+        assert!(matches!(sim.tick(stop_at), Tick::Done(SimulationOutcome::StopTime(_))));
+
+        // A pause that arrives as the queue runs dry keeps the run going
+        let mut sim = script_sim(1, &timer_at_start());
+        sim.init();
+        let (tx, rx) = crossbeam_channel::unbounded();
+        sim.set_control_channel(rx);
+        while sim.step().is_some() {}
+        tx.send(ControlMsg::Pause).unwrap();
+        assert!(matches!(sim.end_run(SimulationOutcome::QueueExhausted), Tick::Again));
+        assert_eq!(sim.state(), SimulationState::Paused);
+        assert!(matches!(sim.tick(MAX_SIM_TIME), Tick::Wait(_)));
+    }
+
+    #[test]
+    fn test_wait_returns_as_soon_as_a_control_message_arrives() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        sim.set_control_channel(rx);
+        tx.send(ControlMsg::Pause).unwrap();
+        tick_until_paused(sim);
+
+        let sender = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            tx.send(ControlMsg::Resume).unwrap();
+        });
+        let started = Instant::now();
+        sim.wait(Duration::from_secs(30));
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(sim.state(), SimulationState::Running);
+        sender.join().unwrap();
+    }
+
+    fn endless_sim() -> Simulation {
+        let mut sim = script_sim(1, &timeout_churn(usize::MAX, 0));
+        sim.init();
+        sim
+    }
+
+    #[test]
+    fn test_shutdown_ends_an_endless_run_with_a_report() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let mut sim = endless_sim();
+        sim.set_shutdown_channel(rx);
+        let sender = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            tx.send(ControlMsg::Shutdown).unwrap();
+        });
+        let report = sim.run();
+        sender.join().unwrap();
+        assert_eq!(report.outcome, SimulationOutcome::Shutdown);
+        assert!(report.events_processed > 0);
+        assert_eq!(report.events_processed, sim.events_processed());
+        assert_eq!(report.metrics.timers_fired, report.events_processed);
+
+        // The shutdown sticks, so later phases end right away
+        let report = sim.run();
+        assert_eq!(report.outcome, SimulationOutcome::Shutdown);
+        assert_eq!(report.events_processed, sim.events_processed());
+    }
+
+    #[test]
+    fn test_shutdown_ends_a_paused_run() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        sim.set_control_channel(rx);
+        tx.send(ControlMsg::Pause).unwrap();
+        tick_until_paused(sim);
+        tx.send(ControlMsg::Shutdown).unwrap();
+        assert!(matches!(sim.tick(MAX_SIM_TIME), Tick::Done(SimulationOutcome::Shutdown)));
+    }
+
+    #[test]
+    fn test_wall_timeout_ends_an_endless_run() {
+        let mut sim = endless_sim();
+        sim.set_wall_timeout(Some(Duration::from_millis(50)));
+        let started = Instant::now();
+        let report = sim.run();
+        assert_eq!(report.outcome, SimulationOutcome::WallTimeout(50));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(report.events_processed > 0);
+        assert_eq!(sim.effective_config().engine.wall_timeout_ms, Some(50));
+    }
+
+    #[test]
+    fn test_breakpoint_ends_a_run_with_only_a_shutdown_channel() {
+        let (_tx, rx) = crossbeam_channel::unbounded();
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        sim.set_shutdown_channel(rx);
+        sim.add_breakpoint(Breakpoint::default());
+        assert_eq!(sim.run().outcome, SimulationOutcome::Breakpoint);
+    }
+
+    #[test]
+    fn test_breakpoint_pauses_before_matching_event() {
+        // Followers keep extending their election timers while the leader
+        // sends heartbeats, so break on the leader's timers
+        let leader = raft_leader();
+        let breakpoint = Breakpoint {
+            kind: Some(crate::control::BreakKind::TimerFired),
+            node: Some(leader),
+            ..Breakpoint::default()
+        };
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        sim.set_control_channel(rx);
+        tx.send(ControlMsg::SetBreakpoint(breakpoint.clone())).unwrap();
+        tick_until_paused(sim);
+
+        let processed = sim.events_processed();
+        let next = sim.queue.peek().unwrap();
+        assert!(matches!(next.payload, Event::TimerFired { node_id, .. } if node_id == leader));
+        assert_eq!(sim.breakpoint_hit(), Some(&breakpoint));
+        let paused_on = next.id;
+
+        // Resuming runs the event the breakpoint paused before.
+        tx.send(ControlMsg::Resume).unwrap();
+        assert!(matches!(sim.tick(MAX_SIM_TIME), Tick::Stepped));
+        assert_eq!(sim.events_processed(), processed + 1);
+        assert_ne!(sim.queue.peek().unwrap().id, paused_on);
+
+        // Headless, a breakpoint ends the run with the event still queued.
+        let mut harness = raft_cluster();
+        let headless = harness.sim_mut();
+        headless.add_breakpoint(breakpoint);
+        headless.run_until(MAX_SIM_TIME);
+        assert_eq!(headless.events_processed(), processed);
+        assert_eq!(headless.queue.peek().unwrap().id, paused_on);
+    }
+
+    #[test]
+    fn test_replay_matches_recorded_trace() {
+        let mut harness = raft_cluster();
+        let recorded = harness.sim_mut();
+        recorded.record_trace();
+        recorded.run_until(sim_from_ms(2_000));
+        let trace = recorded.event_trace().unwrap().clone();
+        assert!(trace.events.iter().any(|e| !e.draws.is_empty()));
+
+        assert_eq!(raft_cluster().sim_mut().replay(trace.clone()), Ok(trace.events.len()));
+
+        let mut tampered = trace.clone();
+        let index = tampered.events.len() / 2;
+        tampered.events[index].draws.push(("injected".to_string(), 1));
+        let divergence = raft_cluster().sim_mut().replay(tampered).unwrap_err();
+        assert_eq!(divergence.index, index);
+        assert_eq!(divergence.actual.as_ref(), Some(&trace.events[index]));
+    }
+
+    #[test]
+    fn test_same_seed_runs_record_identical_traces() {
+        use ftsim_proto::protocols::raft_lite::RaftLite;
+        let trace = |seed| {
+            let mut harness = Harness::cluster(3, seed, || boxed_dyn(RaftLite::default()));
+            let scenario = Scenario::builder("raft_partition", 3, ProtoTag(1))
+                .at(sim_from_ms(300), Action::Partition { sets: vec![vec![0], vec![1]] })
+                .at(sim_from_ms(800), Action::HealPartition)
+                .build()
+                .unwrap();
+            crate::scenario::load_and_schedule(harness.sim_mut(), &scenario).unwrap();
+            let sim = harness.sim_mut();
+            sim.record_trace();
+            sim.run_until(sim_from_ms(1_500));
+            sim.event_trace().unwrap().clone()
+        };
+        let first = trace(7);
+        assert!(first.events.len() > 100, "{} events", first.events.len());
+        assert_eq!(trace(7), first);
+        assert_ne!(trace(8).events, first.events);
+    }
+
+    /// Three nodes that draw once every 10ns for five rounds, node 1
+    /// drawing an extra value in round `extra_at`.
+    fn ticker_sim(extra_at: Option<usize>) -> Simulation {
+        let ticker = Script::<(), usize>::with_state(0)
+            .on_start(|_, ctx| {
+                ctx.set_timer(10);
+            })
+            .on_timer(move |round, ctx, _| {
+                if ctx.node_id() == 1 && extra_at == Some(*round) {
+                    ctx.rng_u64();
+                }
+                ctx.rng_u64();
+                *round += 1;
+                if *round < 5 {
+                    ctx.set_timer(10);
+                }
+            });
+        script_sim(3, &ticker)
+    }
+
+    #[test]
+    fn test_rng_check_reports_the_first_misattributed_draw() {
+        let mut recorded = ticker_sim(None);
+        recorded.record_rng();
+        recorded.init();
+        while recorded.step().is_some() {}
+        let recording = recorded.rng_recording().unwrap().clone();
+        assert_eq!(recording.draws.len(), 15);
+
+        let mut same = ticker_sim(None);
+        same.check_rng(recording.clone());
+        same.init();
+        assert_eq!(same.replay_rng(), Ok(15));
+
+        // The extra draw in round 2 takes the value node 1's regular draw
+        // would have had, so the regular draw is the first to differ.
+        let mut perturbed = ticker_sim(Some(2));
+        perturbed.check_rng(recording.clone());
+        perturbed.init();
+        let mismatch = perturbed.replay_rng().unwrap_err();
+        let actual = mismatch.actual.as_ref().unwrap();
+        assert_eq!((actual.site.as_str(), actual.ordinal), ("proto.node[1]", 3));
+        let expected = mismatch.expected.as_ref().unwrap();
+        assert_eq!((expected.site.as_str(), expected.ordinal), ("proto.node[2]", 2));
+        let previous = &recording.draws[mismatch.index - 1];
+        assert_eq!((previous.site.as_str(), previous.ordinal), ("proto.node[1]", 2));
+    }
+
+    fn ticker_positions(extra_at: Option<usize>) -> crate::rng::DrawPositions {
+        let mut sim = ticker_sim(extra_at);
+        sim.record_draw_positions();
+        sim.init();
+        while sim.step().is_some() {}
+        sim.draw_positions().unwrap().clone()
+    }
+
+    #[test]
+    fn test_draw_positions_bisect_to_the_diverging_event() {
+        let base = ticker_positions(None);
+        // Each timer draws at one site, so each entry lists only that site
+        assert_eq!(base.entries.len(), 15);
+        assert!(base.entries.iter().all(|e| e.sites.len() == 1 && e.event_id.is_some()));
+        assert_eq!(base.first_divergence(&ticker_positions(None)), None);
+
+        let perturbed = ticker_positions(Some(2));
+        let divergence = base.first_divergence(&perturbed).unwrap();
+        let (left, right) = (divergence.left.unwrap(), divergence.right.unwrap());
+        assert_eq!(left.event_id, right.event_id);
+        assert_eq!(left.time, 30);
+        assert_eq!((left.sites[0].0.as_str(), left.sites[0].1.draws), ("proto.node[1]", 3));
+        assert_eq!((right.sites[0].0.as_str(), right.sites[0].1.draws), ("proto.node[1]", 4));
+        let [(site, Some(l), Some(r))] = &divergence.sites[..] else { panic!("{:?}", divergence.sites) };
+        assert_eq!((site.as_str(), l.draws, r.draws), ("proto.node[1]", 3, 4));
+        // Every earlier event matches
+        assert_eq!(base.entries[..divergence.index], perturbed.entries[..divergence.index]);
+
+        // Runs that draw equally often but different values diverge too
+        let mut reseeded = base.clone();
+        let entry = &mut reseeded.entries[7].sites[0].1;
+        entry.digest ^= 1;
+        entry.last ^= 1;
+        let divergence = base.first_divergence(&reseeded).unwrap();
+        assert_eq!(divergence.index, 7);
+        let [(_, Some(l), Some(r))] = &divergence.sites[..] else { panic!("{:?}", divergence.sites) };
+        assert_eq!(l.draws, r.draws);
+        assert_ne!(l.last, r.last);
+
+        // A run that stops early diverges where it ends
+        let mut truncated = base.clone();
+        truncated.entries.truncate(10);
+        let divergence = base.first_divergence(&truncated).unwrap();
+        assert_eq!((divergence.index, divergence.right), (10, None));
+    }
+
+    fn run_with_codec_policy(policy: CodecErrorPolicy) -> Harness {
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        sim.set_codec_error_policy(policy);
+        // Not a valid postcard encoding of any raft message.
+        let fault = FaultEventInternal::BroadcastBytes {
+            payload_hex: "ffffffffffffffffffff".to_string(),
+            proto_tag: Some(ProtoTag(1)),
+        };
+        sim.schedule_at(sim_from_ms(1), Event::Fault(fault), EventDiscriminant::fault());
+        harness.run_until_ms(100);
+        harness
+    }
+
+    /// Three primary-backup nodes that replicate one write at t=1ms, plus a
+    /// no-op periodic directive that keeps the queue non-empty for 1s.
+    fn pb_sim() -> Simulation {
+        use ftsim_proto::protocols::primary_backup::PrimaryBackup;
+        let protos = (0..3).map(|_| boxed_dyn(PrimaryBackup::new())).collect();
+        let mut sim = test_sim(protos);
+        for node in 0..3 {
+            let peers = sim.world.net.peers_of(node).collect();
+            sim.world.node_mut(node).set_peers(peers);
+        }
+        sim.init();
+        let mut scenario = Scenario::builder("pb_idle", 3, ProtoTag(2))
+            .at(
+                sim_from_ms(1),
+                Action::BroadcastBytes {
+                    // postcard encoding of `WriteRequest { key: "k", value: "v" }`
+                    payload_hex: "00016b0176".to_string(),
+                    proto_tag: Some(ProtoTag(2)),
+                },
+            )
+            .build()
+            .unwrap();
+        scenario.directives.push(Directive::Every {
+            period: sim_from_ms(10),
+            repeats: 100,
+            action: Action::LinkDrop { link: 0, p: 0.0 },
+        }.into());
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim
+    }
+
+    #[test]
+    fn test_metric_phase_checks_compare_numerically() {
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        sim.run_until(sim_from_ms(1_000));
+        let term = |node: NodeId| {
+            let snapshot = sim.telemetry().build_snapshot(sim.world(), sim.now());
+            snapshot.nodes[node as usize].metric("term").unwrap()
+        };
+        let check: PhaseCheck =
+            serde_json::from_str(r#"{"Metric": {"key": "term", "op": ">=", "value": 1}}"#).unwrap();
+        assert_eq!(
+            check,
+            PhaseCheck::Metric { key: "term".into(), node: None, op: Comparison::Ge, value: 1.0 }
+        );
+        let phase = |expect: Vec<PhaseCheck>| Phase { name: "p".into(), start: 0, end: sim.now(), expect };
+        assert!(crate::scenario::check_phase(sim, &phase(vec![check])).is_empty());
+
+        // Terms are compared as numbers, not as strings: "10" > "9"
+        let exact = term(1);
+        let metric = |node, op, value| PhaseCheck::Metric { key: "term".into(), node, op, value };
+        let holding = vec![
+            metric(Some(1), Comparison::Eq, exact),
+            metric(Some(1), Comparison::Lt, exact + 9.0),
+            metric(Some(1), Comparison::Gt, exact - 0.5),
+        ];
+        assert!(crate::scenario::check_phase(sim, &phase(holding)).is_empty());
+        let failing = vec![
+            metric(Some(1), Comparison::Ne, exact),
+            metric(None, Comparison::Gt, 1_000.0),
+            PhaseCheck::Metric { key: "missing".into(), node: None, op: Comparison::Ge, value: 0.0 },
+        ];
+        let failures = crate::scenario::check_phase(sim, &phase(failing));
+        assert_eq!(
+            failures,
+            [
+                format!("metric 'term' is not != {} on node 1", exact),
+                "metric 'term' is not > 1000 on any up node".to_string(),
+                "metric 'missing' is not >= 0 on any up node".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expression_phase_checks() {
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        sim.run_until(sim_from_ms(1_000));
+        let phase = |sources: &[&str]| Phase {
+            name: "p".into(),
+            start: 0,
+            end: sim.now(),
+            expect: sources.iter().map(|s| PhaseCheck::Expr(s.to_string())).collect(),
+        };
+        let holding = [
+            r#"count(nodes where role == "Leader") == 1"#,
+            r#"all(nodes where status == "Up" && term >= 1)"#,
+            r#"metric(0, "term") == metric(1, "term")"#,
+        ];
+        assert_eq!(crate::scenario::check_phase(sim, &phase(&holding)), Vec::<String>::new());
+        let failures = crate::scenario::check_phase(sim, &phase(&[r#"any(nodes where status == "Down")"#, "1 <"]));
+        assert_eq!(
+            failures,
+            [
+                r#"`any(nodes where status == "Down")` does not hold"#.to_string(),
+                "`1 <` is invalid: expected an expression, found the end at column 4".to_string(),
+            ]
+        );
+
+        // A declared schema rejects names it does not list when the
+        // scenario loads
+        let mut scenario = Scenario::builder("strict", 3, ProtoTag(1)).build().unwrap();
+        scenario.phases.push(phase(&[r#"kv(0, "rol") == "Leader""#]));
+        crate::scenario::check_expressions(&scenario).unwrap();
+        scenario.expr_schema = Some(ExprSchema { kvs: vec!["role".into()], ..Default::default() });
+        let err = crate::scenario::check_expressions(&scenario).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "Phase 'p' has an invalid expression:\nkv(0, \"rol\") == \"Leader\"\n      ^ unknown KV 'rol'"
+        );
+    }
+
+    /// Three nodes that go idle once node 0 has sent the others a message
+    /// on start, plus a no-op periodic directive that keeps the queue
+    /// non-empty for 1s. Primary-backup heartbeats, so it never goes idle.
+    fn idle_sim() -> Simulation {
+        let mut sim = script_sim(3, &fanout());
+        sim.init();
+        let mut scenario = Scenario::builder("idle", 3, SCRIPT_TAG).build().unwrap();
+        scenario.directives.push(Directive::Every {
+            period: sim_from_ms(10),
+            repeats: 100,
+            action: Action::LinkDrop { link: 0, p: 0.0 },
+        }.into());
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim
+    }
+
+    #[test]
+    fn test_stop_conditions() {
+        let mut exhausted = idle_sim();
+        assert_eq!(exhausted.run().outcome, SimulationOutcome::QueueExhausted);
+        assert_eq!(exhausted.now(), sim_from_ms(990));
+
+        let mut quiescent = idle_sim();
+        quiescent.set_stop_on_quiescence(true);
+        let report = quiescent.run();
+        assert_eq!(report.outcome, SimulationOutcome::Quiescent);
+        assert!(quiescent.now() < sim_from_ms(2));
+        assert_eq!(report.metrics.messages_delivered, 2);
+
+        let mut bounded = pb_sim();
+        bounded.set_max_events(Some(5));
+        assert_eq!(bounded.run().outcome, SimulationOutcome::MaxEvents(5));
+        assert_eq!(bounded.events_processed(), 5);
+
+        assert_eq!(pb_sim().run_until(sim_from_ms(50)).outcome, SimulationOutcome::StopTime(sim_from_ms(50)));
+    }
+
+    /// The protocol state of every node, as `snapshot_state` encodes it.
+    fn protocol_states(sim: &Simulation) -> Vec<Vec<bytes::Bytes>> {
+        sim.world.nodes.iter().map(|n| n.protocol_state().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_loading_a_state_rewinds_the_flow_graph() {
+        let mut sim = pb_sim();
+        sim.enable_flow_graph(false, &[]);
+        sim.run_until(sim_from_ms(1) - 1);
+        let saved = sim.save_state().unwrap();
+        let before = sim.flow_graph().unwrap().edges().clone();
+
+        sim.run_until(sim_from_ms(50));
+        assert_ne!(*sim.flow_graph().unwrap().edges(), before);
+        sim.load_state(saved).unwrap();
+        assert_eq!(*sim.flow_graph().unwrap().edges(), before);
+    }
+
+    #[test]
+    fn test_branching_a_primary_backup_run() {
+        let mut straight = pb_sim();
+        straight.run_until(sim_from_ms(50));
+
+        // Branch just before the write at 1ms
+        let mut sim = pb_sim();
+        sim.run_until(sim_from_ms(1) - 1);
+        let saved = sim.save_state().unwrap();
+        let saved_at = saved.time();
+        assert_eq!(saved_at, sim.now());
+        let before = protocol_states(&sim);
+
+        sim.run_until(sim_from_ms(50));
+        assert_eq!(sim.state_hash(), straight.state_hash());
+        assert_eq!(protocol_states(&sim), protocol_states(&straight));
+        assert_ne!(protocol_states(&sim), before);
+
+        // Crashing the primary first leaves the backups empty
+        sim.load_state(saved).unwrap();
+        assert_eq!(sim.now(), saved_at);
+        assert_eq!(protocol_states(&sim), before);
+        let saved = sim.save_state().unwrap();
+        let scenario = Scenario::builder("crash_primary", 3, ProtoTag(2))
+            .at(sim.now(), Action::Crash { node: 0, duration: SimDuration::Forever })
+            .at(sim.now(), Action::ByzantineFlip { node: 2, enabled: true })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.run_until(sim_from_ms(50));
+        assert_eq!(sim.world.node(0).status, NodeStatus::Down);
+        assert!(sim.world.node(2).byzantine());
+        assert_ne!(sim.state_hash(), straight.state_hash());
+        assert_eq!(protocol_states(&sim)[1..], before[1..]);
+
+        // The original future replays exactly after loading again
+        sim.load_state(saved).unwrap();
+        sim.run_until(sim_from_ms(50));
+        assert_eq!(sim.state_hash(), straight.state_hash());
+        assert_eq!(protocol_states(&sim), protocol_states(&straight));
+        assert_eq!(sim.world.node(0).status, NodeStatus::Up);
+        assert!(!sim.world.node(2).byzantine());
+    }
+
+    #[test]
+    fn test_primary_backup_fails_over_to_the_lowest_backup() {
+        use ftsim_proto::protocols::primary_backup::PrimaryBackup;
+        let mut harness = Harness::cluster(3, 7, || boxed_dyn(PrimaryBackup::new()));
+        harness.run_until_ms(100);
+        harness.expect_kv(0, "role", "primary");
+        let put = ClientOp::Put { key: "k".into(), value: "v".into() };
+        let scenario = Scenario::builder("pb_failover", 3, ProtoTag(2))
+            .at(sim_from_ms(100), Action::Crash { node: 0, duration: SimDuration::Forever })
+            .at(sim_from_ms(300), Action::ClientRequest { node: 1, op: put })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(harness.sim_mut(), &scenario).unwrap();
+
+        // A heartbeat period after the failover timeout, node 1 has taken over
+        harness.run_until_ms(100 + 150);
+        harness.expect_kv(1, "role", "primary");
+        harness.expect_kv(2, "role", "backup");
+        let report = harness.run_until_ms(400);
+        assert_eq!(report.metrics.client_responses, 1);
+        assert_eq!(report.node_metric(2, "data_entries"), Some(1.0));
+    }
+
+    #[test]
+    fn test_scheduling_policy_reorders_queued_events() {
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        let timer = sim.schedule_at(1, Event::TimerFired { node_id: 0, timer_id: TimerId(999) }, EventDiscriminant::timer(0));
+        let fault = sim.schedule_at(1, Event::Fault(FaultEventInternal::Restart { node_id: 0 }), EventDiscriminant::fault());
+        assert_eq!(sim.queue.peek().map(|q| q.id), Some(timer));
+        sim.set_scheduling_policy(SchedulingPolicy::Priority);
+        assert_eq!(sim.queue.peek().map(|q| q.id), Some(fault));
+        assert_eq!(sim.effective_config().engine.scheduling, SchedulingPolicy::Priority);
+    }
+
+    #[test]
+    fn test_loading_a_state_rewinds_the_counters() {
+        let mut sim = pb_sim();
+        let saved = sim.save_state().unwrap();
+        sim.count_codec_error(ProtoTag(2), 1);
+        sim.count_unroutable(ProtoTag(9), 2);
+        sim.report_time_overflow("test", None, SimError::IdOverflow);
+        *sim.future_messages.entry(0).or_insert(0) += 1;
+
+        sim.load_state(saved).unwrap();
+        assert!(sim.codec_error_counts().is_empty());
+        assert!(sim.unroutable_counts().is_empty());
+        assert!(sim.future_message_counts().is_empty());
+        assert_eq!(sim.time_overflows(), 0);
+    }
+
+    #[test]
+    fn test_save_state_requires_protocol_snapshots() {
+        assert!(matches!(
+            raft_cluster().sim().save_state(),
+            Err(SimError::SnapshotUnsupported { node: 0, protocol: "raft_lite" })
+        ));
+    }
+
+    #[test]
+    fn test_codec_error_policies() {
+        // Every policy counts the messages it drops
+        let expected: BTreeMap<_, _> = (0..3).map(|n| ((ProtoTag(1), n), 1)).collect();
+        let dropped = run_with_codec_policy(CodecErrorPolicy::Drop);
+        let dropped = dropped.sim();
+        assert_eq!(dropped.codec_error_counts(), &expected);
+        assert!(dropped.codec_failure().is_none());
+
+        let counted = run_with_codec_policy(CodecErrorPolicy::CountAndContinue);
+        let counted = counted.sim();
+        assert_eq!(counted.codec_error_counts(), &expected);
+        assert_eq!(counted.events_processed(), dropped.events_processed());
+
+        let failed = run_with_codec_policy(CodecErrorPolicy::Fail);
+        let failed = failed.sim();
+        let failure = failed.codec_failure().unwrap();
+        assert_eq!((failure.src, failure.dst), (u32::MAX, 0));
+        assert_eq!(failure.payload_hex, "ffffffffffffffffffff");
+        assert_eq!(failure.protocol, "raft_lite");
+        assert!(failed.events_processed() < dropped.events_processed());
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    enum Quiet {
+        Hello,
+    }
+
+    /// Sends nothing and keeps every fault it is told about.
+    fn fault_log(log: &Arc<Mutex<Vec<FaultEvent>>>) -> Script<Quiet> {
+        let log = log.clone();
+        Script::new().on_fault(move |_, _, fault| log.lock().unwrap().push(fault))
+    }
+
+    #[test]
+    fn test_undecodable_messages_are_reported_to_the_protocol() {
+        let logs: Vec<_> = (0..3).map(|_| Arc::new(Mutex::new(Vec::new()))).collect();
+        let protos = logs.iter().map(|log| fault_log(log).boxed()).collect();
+        let mut sim = test_sim(protos);
+        sim.init();
+        // Not a valid postcard encoding of any `Quiet`
+        let fault = FaultEventInternal::BroadcastBytes {
+            payload_hex: "ffffffffffffffffffff".to_string(),
+            proto_tag: Some(SCRIPT_TAG),
+        };
+        sim.schedule_at(sim_from_ms(1), Event::Fault(fault), EventDiscriminant::fault());
+        sim.run_until(sim_from_ms(100));
+
+        for (node, log) in logs.iter().enumerate() {
+            let malformed: Vec<_> = log
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|fault| match fault {
+                    FaultEvent::MalformedMessage { src, len, .. } => Some((*src, *len)),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(malformed, [(u32::MAX, 10)], "node {}", node);
+        }
+        assert_eq!(sim.codec_error_counts().values().sum::<u64>(), 3);
+    }
+
+    /// Keeps every announcement it is told about: when it came, what it
+    /// announced, and the node's pending maintenance right then.
+    type Announcements = Vec<(SimTime, FaultKind, SimTime, Vec<Maintenance>)>;
+
+    fn announcement_log(log: &Arc<Mutex<Announcements>>) -> Script<Quiet> {
+        let log = log.clone();
+        Script::new().on_fault(move |_, ctx, fault| {
+            if let FaultEvent::Scheduled { fault_kind, at } = fault {
+                log.lock().unwrap().push((ctx.now(), fault_kind, at, ctx.pending_maintenance()));
+            }
+        })
+    }
+
+    #[test]
+    fn test_announced_faults_are_told_ahead_of_time() {
+        let logs: Vec<_> = (0..3).map(|_| Arc::new(Mutex::new(Vec::new()))).collect();
+        let protos = logs.iter().map(|log| announcement_log(log).boxed()).collect();
+        let mut sim = test_sim(protos);
+        sim.init();
+        let crash = |node| Action::Crash { node, duration: SimDuration::Finite(sim_from_ms(10)) };
+        let scenario = Scenario::builder("maintenance", 3, SCRIPT_TAG)
+            .announced(sim_from_ms(100), sim_from_ms(30), crash(1))
+            .announced(sim_from_ms(200), sim_from_ms(20), Action::HealPartition)
+            // Unannounced, so node 2 hears nothing before it crashes
+            .at(sim_from_ms(150), crash(2))
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.run_until(sim_from_ms(300));
+
+        let crash_at = Maintenance { fault_kind: FaultKind::Crash, at: sim_from_ms(100) };
+        let heal_at = Maintenance { fault_kind: FaultKind::HealPartition, at: sim_from_ms(200) };
+        let heal = (sim_from_ms(180), FaultKind::HealPartition, sim_from_ms(200), vec![heal_at]);
+        // Node 1 hears of its own crash 30ms ahead; the crash is no longer
+        // pending once it has happened
+        assert_eq!(
+            *logs[1].lock().unwrap(),
+            [(sim_from_ms(70), FaultKind::Crash, sim_from_ms(100), vec![crash_at]), heal.clone()]
+        );
+        // A fault without a node goes to every node
+        assert_eq!(*logs[0].lock().unwrap(), [heal.clone()]);
+        assert_eq!(*logs[2].lock().unwrap(), [heal]);
+    }
+
+    #[test]
+    fn test_announcements_use_the_node_clock() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut sim = script_sim(1, &announcement_log(&log));
+        sim.init();
+        let scenario = Scenario::builder("skewed", 1, SCRIPT_TAG)
+            // Announced from the start, since it is only 30ms away
+            .announced(sim_from_ms(30), sim_from_ms(50), Action::ByzantineFlip { node: 0, enabled: true })
+            .at(sim_from_ms(1), Action::ClockSkew { node: 0, skew: sim_from_ms(5) as i128 })
+            .announced(sim_from_ms(60), sim_from_ms(50), Action::Restart { node: 0 })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.run_until(sim_from_ms(100));
+
+        let flip = Maintenance { fault_kind: FaultKind::ByzantineFlip, at: sim_from_ms(30) };
+        let restart = Maintenance { fault_kind: FaultKind::Restart, at: sim_from_ms(65) };
+        // Once the clock is 5ms ahead, so are the times the node is told
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                (0, FaultKind::ByzantineFlip, sim_from_ms(30), vec![flip]),
+                (
+                    sim_from_ms(15),
+                    FaultKind::Restart,
+                    sim_from_ms(65),
+                    vec![Maintenance { at: sim_from_ms(35), ..flip }, restart]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_every_run_of_an_announced_directive_is_announced() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut sim = script_sim(1, &announcement_log(&log));
+        sim.init();
+        let mut scenario = Scenario::builder("rolling", 1, SCRIPT_TAG).build().unwrap();
+        scenario.directives.push(DirectiveSpec {
+            directive: Directive::Every { period: sim_from_ms(40), repeats: 3, action: Action::Restart { node: 0 } },
+            label: None,
+            announce_before: Some(sim_from_ms(10)),
+        });
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.run_until(sim_from_ms(100));
+
+        let told: Vec<_> = log.lock().unwrap().iter().map(|&(now, kind, at, _)| (now, kind, at)).collect();
+        // The first run is sooner than the lead, so it is announced right away
+        assert_eq!(
+            told,
+            [
+                (0, FaultKind::Restart, 0),
+                (sim_from_ms(30), FaultKind::Restart, sim_from_ms(40)),
+                (sim_from_ms(70), FaultKind::Restart, sim_from_ms(80)),
+            ]
+        );
+    }
+
+    /// Two versions of a ping protocol, for mixed-version runs. Version 2
+    /// adds a `Hint` variant and reads version 1 through `decode_compat`.
+    mod versioned {
+        use super::*;
+        use serde::{Deserialize, Serialize};
+        use std::sync::{Arc, Mutex};
+
+        pub const TAG: ProtoTag = ProtoTag(0xE2);
+
+        #[derive(Debug, Serialize, Deserialize)]
+        pub enum V1 {
+            Ping(u64),
+            Pong(u64),
+        }
+
+        #[derive(Debug, Serialize, Deserialize)]
+        pub enum V2 {
+            Ping(u64),
+            Pong(u64),
+            Hint(String),
+        }
+
+        /// Pings node 1 on start and keeps the pongs.
+        pub struct PingerV1 {
+            pub pongs: Arc<Mutex<Vec<u64>>>,
+        }
+
+        impl Protocol<V1> for PingerV1 {
+            fn name(&self) -> &'static str {
+                "pinger"
+            }
+
+            fn proto_tag(&self) -> ProtoTag {
+                TAG
+            }
+
+            fn wire_version(&self) -> u16 {
+                1
+            }
+
+            fn decode_compat(&self, version: u16, bytes: &[u8]) -> Result<V1, CodecError> {
+                // Later versions only append variants
+                if version < 1 {
+                    return Err(CodecError(format!("unknown wire version {}", version)));
+                }
+                decode_message(bytes)
+            }
+
+            fn init(&mut self, _ctx: &mut Ctx<V1>) {}
+
+            fn start(&mut self, ctx: &mut Ctx<V1>) {
+                if ctx.node_id() == 0 {
+                    ctx.send(1, &V1::Ping(7)).unwrap();
+                }
+            }
+
+            fn on_message(&mut self, _ctx: &mut Ctx<V1>, _src: NodeId, msg: V1) {
+                if let V1::Pong(n) = msg {
+                    self.pongs.lock().unwrap().push(n);
+                }
+            }
+
+            fn on_timer(&mut self, _ctx: &mut Ctx<V1>, _timer: TimerId) {}
+
+            fn on_fault(&mut self, _ctx: &mut Ctx<V1>, _fault: FaultEvent) {}
+        }
+
+        /// Answers pings with a pong and a hint.
+        pub struct PingerV2 {
+            pub pings: Arc<Mutex<Vec<u64>>>,
+        }
+
+        impl Protocol<V2> for PingerV2 {
+            fn name(&self) -> &'static str {
+                "pinger"
+            }
+
+            fn proto_tag(&self) -> ProtoTag {
+                TAG
+            }
+
+            fn wire_version(&self) -> u16 {
+                2
+            }
+
+            fn decode_compat(&self, version: u16, bytes: &[u8]) -> Result<V2, CodecError> {
+                if version != 1 {
+                    return Err(CodecError(format!("unknown wire version {}", version)));
+                }
+                Ok(match decode_message::<V1>(bytes)? {
+                    V1::Ping(n) => V2::Ping(n),
+                    V1::Pong(n) => V2::Pong(n),
+                })
+            }
+
+            fn init(&mut self, _ctx: &mut Ctx<V2>) {}
+
+            fn on_message(&mut self, ctx: &mut Ctx<V2>, src: NodeId, msg: V2) {
+                if let V2::Ping(n) = msg {
+                    self.pings.lock().unwrap().push(n);
+                    ctx.send(src, &V2::Pong(n + 1)).unwrap();
+                    ctx.send(src, &V2::Hint("upgrade".to_string())).unwrap();
+                }
+            }
+
+            fn on_timer(&mut self, _ctx: &mut Ctx<V2>, _timer: TimerId) {}
+
+            fn on_fault(&mut self, _ctx: &mut Ctx<V2>, _fault: FaultEvent) {}
+        }
+    }
+
+    #[test]
+    fn test_mixed_wire_versions_decode_through_compat() {
+        use versioned::{PingerV1, PingerV2, TAG};
+        let pongs = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let pings = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let protos = vec![
+            boxed_dyn(PingerV1 { pongs: pongs.clone() }),
+            boxed_dyn(PingerV2 { pings: pings.clone() }),
+        ];
+        let mut sim = test_sim(protos);
+        sim.set_codec_error_policy(CodecErrorPolicy::CountAndContinue);
+        sim.init();
+        sim.run_until(sim_from_ms(100));
+
+        assert_eq!(*pings.lock().unwrap(), [7]);
+        assert_eq!(*pongs.lock().unwrap(), [8]);
+        // Version 1 cannot read the variant version 2 added, and counts it
+        let expected: BTreeMap<_, _> = [((TAG, 0), 1)].into_iter().collect();
+        assert_eq!(sim.codec_error_counts(), &expected);
+    }
+
+    #[test]
+    fn test_two_protocols_share_nodes_without_interfering() {
+        use ftsim_proto::protocols::{primary_backup::PrimaryBackup, raft_lite::RaftLite};
+        let protos = (0..3).map(|_| boxed_dyn(RaftLite::default())).collect();
+        let mut sim = test_sim(protos);
+        for node in 0..3 {
+            let peers = sim.world.net.peers_of(node).collect();
+            let node = sim.world.node_mut(node);
+            node.set_peers(peers);
+            node.add_protocol(boxed_dyn(PrimaryBackup::new()));
+        }
+        assert_eq!(sim.world.node(0).protocols(), [("raft_lite", ProtoTag(1)), ("primary_backup", ProtoTag(2))]);
+        sim.init();
+        let broadcast = |payload_hex: &str, tag| Action::BroadcastBytes {
+            payload_hex: payload_hex.to_string(),
+            proto_tag: Some(ProtoTag(tag)),
+        };
+        let scenario = Scenario::builder("shared", 3, ProtoTag(1))
+            // postcard encoding of `WriteRequest { key: "k", value: "v" }`
+            .at(sim_from_ms(1), broadcast("00016b0176", 2))
+            .at(sim_from_ms(2), broadcast("00", 9))
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.run_until(sim_from_ms(1_000));
+
+        let report = sim.report(SimulationOutcome::StopTime(sim.now()));
+        assert_eq!((0..3).filter(|&n| report.node_kv(n, "role") == Some("Leader")).count(), 1);
+        let snapshot = sim.telemetry().build_snapshot(sim.world(), sim.now());
+        assert!(snapshot.nodes.iter().all(|n| n.metric("data_entries") == Some(1.0)));
+        assert!(sim.codec_error_counts().is_empty());
+        let expected: BTreeMap<_, _> = (0..3).map(|n| ((ProtoTag(9), n), 1)).collect();
+        assert_eq!(sim.unroutable_counts(), &expected);
+    }
+
+    #[test]
+    fn test_failed_load_leaves_every_protocol_of_a_node_unchanged() {
+        use ftsim_proto::protocols::{gossip::Gossip, lease_kv::LeaseKv, primary_backup::PrimaryBackup};
+        let two = |second| {
+            let mut sim = test_sim(vec![boxed_dyn(Gossip::new())]);
+            sim.world.node_mut(0).add_protocol(second);
+            sim.init();
+            sim
+        };
+        let mut written = two(boxed_dyn(PrimaryBackup::new()));
+        let put = ClientOp::Put { key: "k".to_string(), value: "v".to_string() };
+        let scenario = Scenario::builder("write", 1, ProtoTag(0))
+            .at(sim_from_ms(1), Action::ClientRequest { node: 0, op: put })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut written, &scenario).unwrap();
+        written.run_until(sim_from_ms(10));
+        let saved = written.save_state().unwrap();
+
+        // The gossip state restores before the primary-backup one fails
+        let mut sim = two(boxed_dyn(LeaseKv::new()));
+        let expected = sim.world.node(0).protocol_state();
+        assert_ne!(written.world.node(0).protocol_state().unwrap()[0], expected.as_ref().unwrap()[0]);
+        assert!(matches!(sim.load_state(saved), Err(SimError::RestoreFailed { node: 0, .. })));
+        assert_eq!(sim.world.node(0).protocol_state(), expected);
+
+        // Nor does a node take the state of a node running other protocols
+        let saved = test_sim(vec![boxed_dyn(Gossip::new())]).save_state().unwrap();
+        let mut sim = two(boxed_dyn(PrimaryBackup::new()));
+        assert!(matches!(sim.load_state(saved), Err(SimError::RestoreFailed { node: 0, .. })));
+    }
+
+    #[test]
+    fn test_conditions_follow_a_raft_election() {
+        use crate::conditions::{ConditionState, Predicate, Signal, Temporal};
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        let stable = Temporal::HoldsFor {
+            duration: sim_from_ms(500),
+            within: Some(sim_from_ms(2_000)),
+        };
+        sim.add_condition(Condition::new("stable leader", Predicate::single_leader(3), stable));
+        let all_up = Predicate::new((0..3).map(Signal::Status).collect(), |view| {
+            (0..3).all(|node| view.status(node) == Some(NodeStatus::Up))
+        });
+        sim.add_condition(Condition::new("all up", all_up, Temporal::AlwaysWithin { window: sim_from_ms(2_000) }));
+        let scenario = Scenario::builder("crash", 3, ProtoTag(1))
+            .at(sim_from_ms(1_500), Action::Crash { node: 2, duration: SimDuration::Forever })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(sim, &scenario).unwrap();
+        let report = sim.run_until(sim_from_ms(3_000));
+
+        let ConditionState::Satisfied(at) = report.conditions[0].state else {
+            panic!("{}", report.conditions[0]);
+        };
+        assert!(at >= sim_from_ms(500) && at < sim_from_ms(2_000), "{}", report.conditions[0]);
+        assert_eq!(report.conditions[1].state, ConditionState::Failed(sim_from_ms(1_500)));
+    }
+
+    #[test]
+    fn test_loading_a_state_rewinds_the_conditions() {
+        use crate::conditions::{ConditionState, Predicate, Signal, Temporal};
+        use ftsim_proto::protocols::gossip::Gossip;
+        let mut sim = test_sim(vec![boxed_dyn(Gossip::new())]);
+        let up = Predicate::new(vec![Signal::Status(0)], |view| view.status(0) == Some(NodeStatus::Up));
+        sim.add_condition(Condition::new("up", up, Temporal::AlwaysWithin { window: sim_from_ms(10) }));
+        let scenario = Scenario::builder("crash", 1, ProtoTag(0))
+            .at(sim_from_ms(1), Action::Crash { node: 0, duration: SimDuration::Forever })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        sim.init();
+        sim.run_until(sim_from_us(500));
+        let saved = sim.save_state().unwrap();
+        let report = sim.run_until(sim_from_ms(2));
+        assert_eq!(report.conditions[0].state, ConditionState::Failed(sim_from_ms(1)));
+
+        sim.load_state(saved).unwrap();
+        assert_eq!(sim.report(SimulationOutcome::QueueExhausted).conditions[0].state, ConditionState::Pending);
+        let report = sim.run_until(sim_from_ms(2));
+        assert_eq!(report.conditions[0].state, ConditionState::Failed(sim_from_ms(1)));
+    }
+
+    #[test]
+    fn test_gray_failure_splits_the_failure_detectors() {
+        use ftsim_proto::protocols::{failure_detector::FailureDetector, raft_lite::RaftLite};
+        let world = World::full_mesh(5, |_| boxed_dyn(RaftLite::default()));
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let spec = TelemetrySpec {
+            include_fault_details: true,
+            ..TelemetrySpec::default()
+        };
+        let mut sim = Simulation::new(7, world, TelemetryBus::new(tx, 5, &spec));
+        for node in 0..5 {
+            let peers = sim.world.net.peers_of(node).collect();
+            let node = sim.world.node_mut(node);
+            node.set_peers(peers);
+            node.add_protocol(boxed_dyn(FailureDetector::new()));
+        }
+        sim.init();
+        let report = sim.run_until(sim_from_ms(1_000));
+        let leader = (0..5).find(|&n| report.node_kv(n, "role") == Some("Leader")).expect("a leader");
+        let gray = (leader + 1) % 5;
+        assert!(report.suspicions().views.values().all(|suspects| suspects.is_empty()));
+
+        let gray_for = sim_from_ms(500);
+        let scenario = Scenario::builder("gray", 5, ProtoTag(1))
+            .at(
+                sim.now() + 1,
+                Action::GrayFailure {
+                    node: gray,
+                    ignore_from: GrayFilter::Peers(vec![leader]),
+                    duration: SimDuration::Finite(gray_for),
+                },
+            )
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(&mut sim, &scenario).unwrap();
+        let report = sim.run_until(sim.now() + sim_from_ms(300));
+
+        // The leader's pings go unanswered, so it sees the node as dead,
+        // while everyone else still hears from it
+        let suspicions = report.suspicions();
+        assert!(suspicions.suspects(leader, gray), "{}", suspicions);
+        let others: Vec<NodeId> = (0..5).filter(|&n| n != leader && n != gray).collect();
+        assert!(others.iter().all(|&n| !suspicions.suspects(n, gray)), "{}", suspicions);
+        let disagreement = suspicions.disagreements.iter().find(|d| d.node == gray).expect("a disagreement");
+        assert_eq!((disagreement.suspected_by.clone(), disagreement.trusted_by.clone()), (vec![leader], others));
+        // The gray node ignores the leader's pongs as well
+        assert!(suspicions.suspects(gray, leader));
+
+        // The network saw nothing wrong
+        assert!(report.metrics.gray_failure_ignored > 0);
+        let snapshot = sim.telemetry().build_snapshot(sim.world(), sim.now());
+        assert!(snapshot.links.iter().all(|l| !l.is_partitioned));
+        let state = snapshot.nodes[gray as usize].gray_failure.clone().expect("gray failure state");
+        assert_eq!(state.ignore_from, GrayFilter::Peers(vec![leader]));
+        assert_eq!(state.ignored, report.metrics.gray_failure_ignored);
+        assert!(snapshot.nodes.iter().filter(|n| n.id != gray).all(|n| n.gray_failure.is_none()));
+
+        // Once the failure ends the detectors agree again
+        let report = sim.run_until(sim.now() + gray_for);
+        assert!(report.suspicions().disagreements.is_empty(), "{}", report.suspicions());
+        let snapshot = sim.telemetry().build_snapshot(sim.world(), sim.now());
+        assert!(snapshot.nodes[gray as usize].gray_failure.is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "already runs a protocol with tag 1")]
+    fn test_duplicate_protocol_tags_are_rejected() {
+        use ftsim_proto::protocols::raft_lite::RaftLite;
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        sim.world.node_mut(0).add_protocol(boxed_dyn(RaftLite::default()));
+    }
+
+    #[test]
+    fn test_panicking_event_is_left_as_the_current_event() {
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        sim.step().unwrap();
+        assert_eq!(crash_context::current_event(), None);
+
+        let at = sim.now() + 1;
+        let fault = FaultEventInternal::Custom {
+            name: crash_context::TEST_PANIC_FAULT.to_string(),
+            args: ftsim_types::scenario::CustomArgs::Table(Default::default()),
+        };
+        let id = sim.schedule_at(at, Event::Fault(fault), EventDiscriminant::fault());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| sim.run()));
+        assert!(result.is_err());
+        let context = crash_context::current_event().expect("no current event after the panic");
+        assert_eq!(context.event_id, id);
+        assert_eq!(context.time, at);
+        assert_eq!(context.seed, 7);
+        assert!(context.event.contains("test_panic"), "{}", context.event);
+    }
+
+    /// Collects the `STATE_HASH` events logged on a bus.
+    struct HashSink(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl crate::telemetry::sink::TelemetrySink for HashSink {
+        fn on_event(&mut self, event: &crate::telemetry::snapshot::LogSnap) {
+            if event.event_type == "STATE_HASH" {
+                self.0.lock().unwrap().push(event.details.clone());
+            }
+        }
+    }
+
+    /// Runs a raft cluster with `seed` and returns the state hashes published
+    /// along the way and the final one.
+    fn state_hashes(seed: u64) -> (Vec<String>, u64) {
+        use ftsim_proto::protocols::raft_lite::RaftLite;
+        let world = World::full_mesh(3, |_| boxed_dyn(RaftLite::default()));
+        let hashes = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let telemetry = TelemetryBus::detached(3, &TelemetrySpec::default());
+        telemetry.add_sink(Box::new(HashSink(hashes.clone())));
+        let mut sim = Simulation::new(seed, world, telemetry);
+        sim.set_state_hash_interval(Some(5));
+        sim.init();
+        sim.run_until(sim_from_ms(1_000));
+        let published = hashes.lock().unwrap().clone();
+        (published, sim.state_hash())
+    }
+
+    #[test]
+    fn test_state_hash_is_reproducible_and_depends_on_the_seed() {
+        let (published, last) = state_hashes(3);
+        assert!(published.len() >= 5, "{:?}", published);
+        assert_eq!(state_hashes(3), (published.clone(), last));
+
+        let (other, other_last) = state_hashes(4);
+        assert_ne!(other_last, last);
+        assert_ne!(other, published);
+    }
+
+    #[test]
+    fn test_state_hash_tracks_links_and_stores() {
+        let mut harness = raft_cluster();
+        let sim = harness.sim_mut();
+        let before = sim.state_hash();
+        assert_eq!(sim.state_hash(), before);
+        sim.world.net.set_partition(vec![vec![0], vec![1, 2]]);
+        let partitioned = sim.state_hash();
+        assert_ne!(partitioned, before);
+        let store = sim.world.nodes[1].store_view();
+        store.kv_put(bytes::Bytes::from_static(b"k"), bytes::Bytes::from_static(b"v")).unwrap();
+        assert_ne!(sim.state_hash(), partitioned);
+    }
+
+    #[test]
+    fn test_speed_paces_wall_clock() {
+        let run = |speed: f32| {
+            let mut sim = script_sim(1, &timer_at_start());
+            sim.set_speed(speed);
+            sim.init();
+            let heal = Event::Fault(FaultEventInternal::HealPartition);
+            sim.schedule_at(sim_from_ms(200), heal, EventDiscriminant::fault());
+            let started = Instant::now();
+            sim.run();
+            (started.elapsed(), sim.now())
+        };
+        let (paced, paced_end) = run(1.0);
+        assert!(paced >= Duration::from_millis(190), "ran in {:?}", paced);
+        let (unpaced, unpaced_end) = run(0.0);
+        assert!(unpaced < Duration::from_millis(150), "ran in {:?}", unpaced);
+        assert_eq!(paced_end, unpaced_end);
+    }
+
+    /// Node 0 sends one message to every other node on start.
+    fn fanout() -> Script<u8> {
+        Script::new().on_start(|_, ctx| {
+            if ctx.node_id() == 0 {
+                for dst in 1..3 {
+                    ctx.send(dst, &b'x').unwrap();
+                }
+            }
+        })
+    }
+
+    /// Node 0 broadcasts to every peer but node 2 on start.
+    fn broadcaster() -> Script<u8> {
+        Script::new().on_start(|_, ctx| {
+            if ctx.node_id() == 0 {
+                ctx.broadcast(&b'b', Some(&|dst| dst != 2)).unwrap();
+            }
+        })
+    }
+
+    #[test]
+    fn test_broadcast_sends_in_ascending_destination_order() {
+        let broadcast = |peers: Vec<NodeId>| {
+            let mut sim = script_sim(5, &broadcaster());
+            sim.world.node_mut(0).set_peers(peers);
+            sim.enable_message_journal();
+            sim.init();
+            let sends: Vec<(MsgId, NodeId)> =
+                sim.message_journal().unwrap().records().iter().map(|r| (r.msg_id, r.dst)).collect();
+            let events = sim.telemetry.build_snapshot(&sim.world, sim.now()).recent_events;
+            let event = events.iter().find(|e| e.event_type == "BROADCAST").unwrap().details.clone();
+            (sends, event)
+        };
+        let (sends, event) = broadcast(vec![1, 2, 3, 4]);
+        assert_eq!(sends.iter().map(|(_, dst)| *dst).collect::<Vec<_>>(), [1, 3, 4]);
+        assert!(sends.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(event, "Node 0 broadcast 1 bytes to nodes [1, 3, 4]");
+        // Reordered, duplicated and self-including peer lists send the same way
+        assert_eq!(broadcast(vec![4, 0, 3, 1, 3, 2]), (sends, event));
+    }
+
+    #[test]
+    fn test_message_journal_flags_deliveries_across_partition() {
+        let mut sim = script_sim(3, &fanout());
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(10_000_000);
+            link.faults.jitter = DelaySpec::Const(0);
+        }
+        sim.enable_message_journal();
+        sim.init();
+        // Node 2 is cut off while both messages are in flight.
+        sim.world.net.set_partition(vec![vec![0, 1], vec![2]]);
+        while sim.step().is_some() {}
+
+        let journal = sim.message_journal().unwrap();
+        assert_eq!(journal.records().len(), 2);
+        assert!(journal.records().iter().all(|r| {
+            r.view.is_some_and(|v| v.status == NodeStatus::Up && !v.any_link_partitioned && v.incarnation == 0)
+        }));
+        let suspects = journal.suspects();
+        assert_eq!(suspects.len(), 1);
+        assert_eq!(suspects[0].record.dst, 2);
+        assert_eq!(suspects[0].reasons, vec![crate::net::SuspectReason::DeliveredAcrossPartition]);
+
+        let mut jsonl = Vec::new();
+        journal.write_jsonl(&mut jsonl).unwrap();
+        let read = crate::net::MessageJournal::read_jsonl(jsonl.as_slice()).unwrap();
+        assert_eq!(read.records(), journal.records());
+    }
+
+    /// Runs a 5-node raft cluster for a second, cutting node 4 off halfway,
+    /// and returns its message journal.
+    fn raft_journal(sampling: Option<JournalSampling>) -> crate::net::MessageJournal {
+        raft_journal_sim(sampling, None).message_journal.take().unwrap()
+    }
+
+    /// The simulation `raft_journal` runs, streaming the journal to `stream`
+    /// if given.
+    fn raft_journal_sim(sampling: Option<JournalSampling>, stream: Option<&std::path::Path>) -> Simulation {
+        use ftsim_proto::protocols::raft_lite::RaftLite;
+        let protos = (0..5).map(|_| boxed_dyn(RaftLite::default())).collect();
+        let mut sim = test_sim(protos);
+        for node in 0..5 {
+            let peers = sim.world.net.peers_of(node).collect();
+            sim.world.node_mut(node).set_peers(peers);
+        }
+        for link in sim.world.net.links.values_mut() {
+            link.faults.base_delay = DelaySpec::Const(5_000_000);
+            link.faults.jitter = DelaySpec::Const(0);
+        }
+        match sampling {
+            Some(sampling) => sim.enable_sampled_message_journal(sampling),
+            None => sim.enable_message_journal(),
+        }
+        if let Some(path) = stream {
+            sim.stream_message_journal(path, RotationPolicy::new(16 * 1024)).unwrap();
+            sim.journal_streams.settle_batch = 16;
+        }
+        sim.init();
+        sim.run_until(sim_from_ms(500));
+        sim.world.net.set_partition(vec![vec![0, 1, 2, 3], vec![4]]);
+        sim.run_until(sim_from_ms(1_000));
+        sim
+    }
+
+    #[test]
+    fn test_journal_sampling_is_deterministic_and_keeps_categories() {
+        let sampling = JournalSampling {
+            rate: 0.1,
+            always_include: vec![JournalCategory::Faults, JournalCategory::Leadership],
+        };
+        let full = raft_journal(None);
+        let first = raft_journal(Some(sampling.clone()));
+        let second = raft_journal(Some(sampling));
+        assert_eq!(first.records(), second.records());
+
+        let kept: std::collections::HashSet<MsgId> = first.records().iter().map(|r| r.msg_id).collect();
+        assert!(kept.len() < full.records().len());
+        let mut categorized = 0;
+        for record in full.records() {
+            let categories = record.categories();
+            let always = categories.iter().any(|c| *c != JournalCategory::Errors);
+            categorized += usize::from(always);
+            assert_eq!(
+                kept.contains(&record.msg_id),
+                first.is_sampled(record.msg_id) || always,
+                "message {} ({:?})",
+                record.msg_id,
+                categories
+            );
+        }
+        assert!(categorized > 0);
+        let sampled = first.records().iter().filter(|r| r.sampled).count();
+        assert!(sampled > 0 && sampled < full.records().len() / 5);
+    }
+
+    #[test]
+    fn test_streamed_message_journal_is_written_as_the_run_goes() {
+        let dir = std::env::temp_dir().join(format!("ftsim-streamed-journal-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("messages.jsonl");
+        let full = raft_journal(None);
+        let mut sim = raft_journal_sim(None, Some(&path));
+        // Settled records left memory during the run; the rest are in flight
+        let held = sim.message_journal().unwrap().records().len();
+        assert!(held < full.records().len() / 4, "{} of {} records held", held, full.records().len());
+        let manifest = crate::segments::SegmentSet::open(&path).unwrap().unwrap().manifest().clone();
+        assert!(manifest.segments.len() > 1, "{:?}", manifest);
+
+        let (_, manifests) = sim.finish_journal_streams().unwrap();
+        assert_eq!(manifests[0].records(), full.records().len() as u64);
+        assert!(sim.message_journal().unwrap().records().is_empty());
+        // Records are written as they settle, not in send order
+        let input = crate::segments::open_input(&path, None).unwrap();
+        let mut streamed = crate::net::MessageJournal::read_jsonl(input).unwrap().records().to_vec();
+        streamed.sort_by_key(|record| record.msg_id);
+        let mut expected = full.records().to_vec();
+        expected.sort_by_key(|record| record.msg_id);
+        assert_eq!(streamed, expected);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Five raft nodes with their peers set, run until a leader is elected.
+    /// Returns the harness and the leader.
+    fn elected_raft_sim(invariants: &[&str]) -> (Harness, NodeId) {
+        use ftsim_proto::protocols::raft_lite::RaftLite;
+        let mut harness = Harness::cluster(5, 7, || boxed_dyn(RaftLite::default()));
+        for name in invariants {
+            harness.sim_mut().add_invariant(crate::invariants::builtin(name).unwrap());
+        }
+        for _ in 0..100_000 {
+            harness.sim_mut().step();
+            let leader = (0..5).find(|&n| harness.kv(n, "role").is_some_and(|r| r == "Leader"));
+            if let Some(leader) = leader {
+                return (harness, leader);
+            }
+        }
+        panic!("no leader elected");
+    }
+
+    /// Schedules a partition without a scenario, whose validation only
+    /// accepts partitions of a strict subset of the nodes.
+    fn schedule_partition(sim: &mut Simulation, at: SimTime, sets: Vec<Vec<NodeId>>) {
+        sim.schedule_at(at, Event::Fault(FaultEventInternal::Partition { sets }), EventDiscriminant::fault());
+    }
+
+    #[test]
+    fn test_isolated_leader_violates_strict_invariant() {
+        let (mut harness, leader) = elected_raft_sim(&["single_leader_per_term", "at_most_one_leader"]);
+        let sim = harness.sim_mut();
+        // Cut the leader off and slow its clock so it never notices the new term.
+        let now = sim.now();
+        let others: Vec<NodeId> = (0..5).filter(|n| *n != leader).collect();
+        let scenario = Scenario::builder("isolated_leader", 5, ProtoTag(1))
+            .at(now + sim_from_ms(1), Action::ClockSkew { node: leader, skew: -(sim_from_ms(500) as i128) })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(sim, &scenario).unwrap();
+        schedule_partition(sim, now + sim_from_ms(1), vec![vec![leader], others]);
+
+        let report = sim.run_until(now + sim_from_ms(5_000));
+        assert_eq!(report.outcome, SimulationOutcome::InvariantViolated);
+        let violation = sim.invariant_violation().unwrap();
+        assert_eq!(violation.invariant, "at_most_one_leader");
+        assert!(violation.message.contains(&leader.to_string()), "{}", violation);
+        assert!(sim.step().is_none());
+        let snapshot = sim.telemetry.build_snapshot(&sim.world, sim.now());
+        assert!(snapshot.recent_events.iter().any(|e| e.event_type == "INVARIANT_VIOLATED"));
+        // The old leader never heard of the new term
+        harness.expect_kv(leader, "role", "Leader");
+        harness.expect_status(leader, NodeStatus::Up);
+    }
+
+    #[test]
+    fn test_all_nodes_up_checked_after_faults() {
+        let (mut harness, leader) = elected_raft_sim(&["all_nodes_up"]);
+        let sim = harness.sim_mut();
+        // A long interval would skip the crash if faults were not always checked.
+        sim.set_invariant_interval(u64::MAX);
+        let crash_at = sim.now() + sim_from_ms(10);
+        let scenario = Scenario::builder("crash", 5, ProtoTag(1))
+            .at(crash_at, Action::Crash { node: 3, duration: SimDuration::Finite(sim_from_ms(10)) })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(sim, &scenario).unwrap();
+
+        assert_eq!(sim.run().outcome, SimulationOutcome::InvariantViolated);
+        let violation = sim.invariant_violation().unwrap();
+        assert_eq!((violation.time, violation.message.as_str()), (crash_at, "node 3 is Down"));
+        harness.expect_status(3, NodeStatus::Down);
+        // Followers only ever talk to candidates and the leader
+        let peer = (0..5).find(|&n| n != 3 && n != leader).unwrap();
+        harness.expect_no_message(3, peer, |_: &ftsim_proto::protocols::raft_lite::Message| true).so_far();
+    }
+
+    #[test]
+    fn test_raft_logs_match_across_a_partition_and_heal() {
+        let (mut harness, leader) = elected_raft_sim(&["single_leader_per_term", "log_matching"]);
+        let sim = harness.sim_mut();
+        sim.set_invariant_interval(1);
+        let now = sim.now();
+        let buddy = (leader + 1) % 5;
+        let majority: Vec<NodeId> = (0..5).filter(|&n| n != leader && n != buddy).collect();
+        let put = |key: &str| ClientOp::Put { key: key.into(), value: "v".into() };
+        // The leader takes a write it can commit, then one it cannot once cut
+        // off with a single follower. Every node of the majority is asked
+        // for a write, which only their new leader takes.
+        let mut builder = Scenario::builder("log_matching", 5, ProtoTag(1))
+            .at(now + sim_from_ms(1), Action::ClientRequest { node: leader, op: put("committed") })
+            .at(now + sim_from_ms(100), Action::ClientRequest { node: leader, op: put("lost") });
+        for &node in &majority {
+            builder = builder.at(now + sim_from_ms(800), Action::ClientRequest { node, op: put("won") });
+        }
+        let scenario = builder.at(now + sim_from_ms(1_000), Action::HealPartition).build().unwrap();
+        crate::scenario::load_and_schedule(sim, &scenario).unwrap();
+        schedule_partition(sim, now + sim_from_ms(50), vec![vec![leader, buddy], majority.clone()]);
+
+        let split = sim.run_until(now + sim_from_ms(900));
+        let commit_index = |report: &SimulationReport, node| report.node_metric(node, "commit_index");
+        assert!(commit_index(&split, majority[0]) > commit_index(&split, leader), "{:?}", split.nodes);
+        assert_ne!(harness.kv(leader, "log_hash"), harness.kv(majority[0], "log_hash"));
+        assert_eq!(harness.kv(leader, "log_hash"), harness.kv(buddy, "log_hash"));
+
+        // The old leader's uncommitted entry gives way to the new leader's log
+        let healed = harness.sim_mut().run_until(now + sim_from_ms(1_500));
+        assert_eq!(harness.sim().invariant_violation(), None);
+        assert_eq!(healed.outcome, SimulationOutcome::StopTime(now + sim_from_ms(1_500)));
+        let hash = harness.kv(majority[0], "log_hash");
+        for node in 0..5 {
+            assert_eq!(harness.kv(node, "log_hash"), hash, "node {}", node);
+            assert_eq!(commit_index(&healed, node), commit_index(&healed, majority[0]), "node {}", node);
+            assert_eq!(harness.kv(node, "applied_index"), harness.kv(node, "last_log_index"), "node {}", node);
+        }
+        assert!(harness.kv(leader, "role").is_some_and(|r| r != "Leader"));
+    }
+
+    #[test]
+    fn test_crdt_convergence_expected_only_once_faults_stop() {
+        use crate::convergence::REPLICA_HASH_KEY;
+        use ftsim_proto::protocols::crdt::Crdt;
+        let mut harness = Harness::cluster(3, 7, || boxed_dyn(Crdt::new()));
+        let sim = harness.sim_mut();
+        sim.add_invariant(crate::invariants::builtin("crdt_convergence_after_quiescence").unwrap());
+        let incr = ClientOp::Put { key: "incr".into(), value: "1".into() };
+        let scenario = Scenario::builder("crdt_split", 3, ProtoTag(9))
+            .at(sim_from_ms(10), Action::ClientRequest { node: 0, op: incr })
+            .at(sim_from_ms(1_000), Action::HealPartition)
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(sim, &scenario).unwrap();
+        schedule_partition(sim, sim_from_ms(1), vec![vec![0], vec![1, 2]]);
+
+        // A partition is a fault, so the split is allowed however long it lasts
+        let split = sim.run_until(sim_from_ms(990));
+        assert_eq!(split.outcome, SimulationOutcome::StopTime(sim_from_ms(990)));
+        assert_ne!(harness.kv(0, REPLICA_HASH_KEY), harness.kv(1, REPLICA_HASH_KEY));
+
+        // The heal lets the replicas merge well within the window
+        let settled = sim_from_ms(1_000) + 2 * crate::invariants::QUIESCENCE_WINDOW;
+        assert_eq!(harness.sim_mut().run_until(settled).outcome, SimulationOutcome::StopTime(settled));
+        let hash = harness.kv(0, REPLICA_HASH_KEY);
+        assert!(hash.is_some());
+        assert!((1..3).all(|node| harness.kv(node, REPLICA_HASH_KEY) == hash));
+
+        // A replica that stops merging is caught once the quiet cluster has
+        // had the window to agree
+        let sim = harness.sim_mut();
+        sim.telemetry().log_node_kv_pinned(0, REPLICA_HASH_KEY.to_string(), "stale".into());
+        assert_eq!(sim.run_until(settled * 2).outcome, SimulationOutcome::InvariantViolated);
+        let violation = sim.invariant_violation().unwrap();
+        assert_eq!(violation.invariant, "crdt_convergence_after_quiescence");
+        assert!(violation.time >= settled + crate::invariants::QUIESCENCE_WINDOW, "{}", violation);
+        assert!(violation.message.contains("[0] hold"), "{}", violation);
+    }
+
+    #[test]
+    fn test_lease_kv_sends_followers_new_writes_only_and_reloads_them_after_a_crash() {
+        use ftsim_proto::protocols::lease_kv::{LeaseKv, Message};
+        let mut harness = Harness::cluster(3, 7, || boxed_dyn(LeaseKv::new()));
+        harness.run_until_ms(500);
+        let leader = (0..3).find(|&n| harness.kv(n, "role").is_some_and(|r| r == "leader")).expect("no leader");
+        let follower = (leader + 1) % 3;
+        let put = |key: &str| ClientOp::Put { key: key.into(), value: "v".into() };
+        let scenario = Scenario::builder("lease_kv", 3, ProtoTag(10))
+            .at(sim_from_ms(510), Action::ClientRequest { node: leader, op: put("a") })
+            .at(sim_from_ms(610), Action::ClientRequest { node: leader, op: put("b") })
+            .at(sim_from_ms(700), Action::Crash { node: follower, duration: SimDuration::Finite(sim_from_ms(50)) })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(harness.sim_mut(), &scenario).unwrap();
+
+        let only_b = |msg: &Message| {
+            matches!(msg, Message::Heartbeat { since: Some(_), entries, .. } if entries.keys().eq(["b"]))
+        };
+        harness.expect_message(leader, follower, only_b).within_ms(200);
+
+        // The restarted follower still holds both writes, so it is never
+        // sent the whole map again
+        let whole_map = |msg: &Message| matches!(msg, Message::Heartbeat { since: None, .. });
+        harness.expect_no_message(leader, follower, whole_map).within_ms(300);
+        harness.expect_status(follower, NodeStatus::Up);
+        harness.expect_kv(leader, "role", "leader");
+    }
+
+    #[test]
+    fn test_raft_leader_holds_without_faults() {
+        let (mut harness, leader) = elected_raft_sim(&["single_leader_per_term"]);
+        let end = harness.sim().now() + sim_from_ms(5_000);
+        while harness.sim().now() < end {
+            harness.sim_mut().step();
+            for node in 0..5 {
+                let is_leader = harness.kv(node, "role").is_some_and(|r| r == "Leader");
+                assert_eq!(is_leader, node == leader, "node {} at t={}", node, harness.sim().now());
+            }
+        }
+        // No follower ever campaigned
+        let report = harness.sim_mut().run_until(end);
+        let term = report.node_metric(leader, "term");
+        assert!((0..5).all(|node| report.node_metric(node, "term") == term), "{:?}", report.nodes);
+        assert_eq!(harness.sim().invariant_violation(), None);
+    }
+
+    #[test]
+    fn test_raft_leader_steps_down_in_a_minority_partition() {
+        let (mut harness, leader) = elected_raft_sim(&["single_leader_per_term"]);
+        let sim = harness.sim_mut();
+        let now = sim.now();
+        let buddy = (leader + 1) % 5;
+        let majority: Vec<NodeId> = (0..5).filter(|&n| n != leader && n != buddy).collect();
+        schedule_partition(sim, now + sim_from_ms(1), vec![vec![leader, buddy], majority.clone()]);
+
+        // Within an election timeout and a heartbeat of losing its quorum
+        sim.run_until(now + sim_from_ms(400));
+        assert!(harness.kv(leader, "role").is_some_and(|r| r != "Leader"));
+        harness.sim_mut().run_until(now + sim_from_ms(2_000));
+        let leaders: Vec<NodeId> =
+            (0..5).filter(|&n| harness.kv(n, "role").is_some_and(|r| r == "Leader")).collect();
+        assert_eq!(leaders.len(), 1);
+        assert!(majority.contains(&leaders[0]), "{:?}", leaders);
     }
 }
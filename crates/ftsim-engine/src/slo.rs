@@ -0,0 +1,238 @@
+//! # ftsim-engine::slo
+//!
+//! Judges client request latency against a scenario's `Slo`. Each answered
+//! request counts towards the window its response completes in, and a
+//! window is judged once sim time reaches its end: it violates the
+//! objective if its p99 latency exceeds the target. A window in which no
+//! request completed has no p99 and neither meets nor violates it; runs of
+//! such windows are judged as one. The run's attainment is the share of
+//! judged windows with requests that met the objective.
+
+use crate::{histogram::Histogram, prelude::*};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt};
+
+/// One judged window `[start, end)`, or a run of empty ones.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SloWindow {
+    pub start: SimTime,
+    pub end: SimTime,
+    /// The requests that completed in the window.
+    pub requests: u64,
+    /// `None` if no request completed in the window.
+    pub p99: Option<SimTime>,
+    pub violated: bool,
+}
+
+impl fmt::Display for SloWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.p99 {
+            Some(p99) => write!(f, "p99 {:.3} ms over {} requests", p99 as f64 / 1e6, self.requests)?,
+            None => write!(f, "no requests")?,
+        }
+        write!(f, " in [{:.3} s, {:.3} s)", self.start as f64 / 1e9, self.end as f64 / 1e9)
+    }
+}
+
+/// How a run did against its SLO, as listed in `SimulationReport`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SloReport {
+    pub slo: Slo,
+    /// Every judged window, in order.
+    pub windows: Vec<SloWindow>,
+    /// The percentage of judged windows with requests that met the
+    /// objective, or `None` if no such window was judged.
+    pub attainment: Option<f64>,
+}
+
+impl SloReport {
+    pub fn violations(&self) -> impl Iterator<Item = &SloWindow> {
+        self.windows.iter().filter(|w| w.violated)
+    }
+}
+
+/// Collects request latencies per window and judges the windows as time
+/// passes. Cloning it saves its progress.
+#[derive(Debug, Clone)]
+pub struct SloTracker {
+    slo: Slo,
+    /// The windows with requests that have not been judged yet, by index.
+    open: BTreeMap<u128, Histogram>,
+    /// The index of the first window not judged yet.
+    next: u128,
+    judged: Vec<SloWindow>,
+}
+
+impl SloTracker {
+    pub fn new(slo: Slo) -> Self {
+        Self {
+            slo,
+            open: BTreeMap::new(),
+            next: 0,
+            judged: Vec::new(),
+        }
+    }
+
+    pub fn slo(&self) -> Slo {
+        self.slo
+    }
+
+    /// Counts a request that completed at `completed_at` after `latency`.
+    /// Requests completing during warm-up are ignored.
+    pub fn record(&mut self, completed_at: SimTime, latency: SimTime) {
+        let Some(since_warmup) = completed_at.checked_sub(self.slo.warmup) else {
+            return;
+        };
+        let index = since_warmup / self.slo.window;
+        let latency = u64::try_from(latency).unwrap_or(u64::MAX);
+        self.open.entry(index).or_default().record(latency);
+    }
+
+    /// Judges the windows that end at or before `now`, returning those that
+    /// violated the objective.
+    pub fn advance_to(&mut self, now: SimTime) -> Vec<SloWindow> {
+        let Some(since_warmup) = now.checked_sub(self.slo.warmup) else {
+            return Vec::new();
+        };
+        // Every window before this one has ended, so none of their bounds
+        // lie past `now`
+        let ended = since_warmup / self.slo.window;
+        let mut violations = Vec::new();
+        while self.next < ended {
+            let start = self.bound(self.next);
+            let window = match self.open.first_entry() {
+                Some(entry) if *entry.key() == self.next => {
+                    let histogram = entry.remove();
+                    let p99 = histogram.percentile(99.0).unwrap_or(0) as SimTime;
+                    self.next += 1;
+                    SloWindow {
+                        start,
+                        end: self.bound(self.next),
+                        requests: histogram.count(),
+                        p99: Some(p99),
+                        violated: p99 > self.slo.target(),
+                    }
+                }
+                // Nothing completed from here until the next window with
+                // requests, or until now
+                first => {
+                    self.next = first.map_or(ended, |entry| (*entry.key()).min(ended));
+                    SloWindow {
+                        start,
+                        end: self.bound(self.next),
+                        requests: 0,
+                        p99: None,
+                        violated: false,
+                    }
+                }
+            };
+            if window.violated {
+                violations.push(window);
+            }
+            self.judged.push(window);
+        }
+        violations
+    }
+
+    /// The start of window `index`, which must not lie past the end of
+    /// time.
+    fn bound(&self, index: u128) -> SimTime {
+        index
+            .checked_mul(self.slo.window)
+            .and_then(|offset| offset.checked_add(self.slo.warmup))
+            .expect("SLO window bound past the end of time")
+    }
+
+    /// The last judged window with requests, if it violated the objective.
+    pub fn current_violation(&self) -> Option<SloWindow> {
+        self.judged.iter().rev().find(|w| w.requests > 0).copied().filter(|w| w.violated)
+    }
+
+    pub fn report(&self) -> SloReport {
+        let busy = self.judged.iter().filter(|w| w.requests > 0).count();
+        let met = self.judged.iter().filter(|w| w.requests > 0 && !w.violated).count();
+        SloReport {
+            slo: self.slo,
+            windows: self.judged.clone(),
+            attainment: (busy > 0).then(|| met as f64 * 100.0 / busy as f64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slo() -> Slo {
+        Slo {
+            p99_ms: 50.0,
+            window: sim_from_ms(1_000),
+            warmup: sim_from_ms(500),
+        }
+    }
+
+    #[test]
+    fn test_windows_are_judged_once_time_reaches_their_end() {
+        let mut tracker = SloTracker::new(slo());
+        // Warm-up requests never count, however slow
+        tracker.record(sim_from_ms(400), sim_from_ms(900));
+        tracker.record(sim_from_ms(600), sim_from_ms(10));
+        assert_eq!(tracker.advance_to(sim_from_ms(1_499)), []);
+        assert!(tracker.report().windows.is_empty());
+
+        tracker.record(sim_from_ms(1_499), sim_from_ms(60));
+        let violations = tracker.advance_to(sim_from_ms(1_500));
+        assert_eq!(violations.len(), 1);
+        assert_eq!((violations[0].start, violations[0].end), (sim_from_ms(500), sim_from_ms(1_500)));
+        assert_eq!(tracker.current_violation(), Some(violations[0]));
+        assert_eq!(tracker.report().attainment, Some(0.0));
+    }
+
+    #[test]
+    fn test_empty_windows_are_judged_without_counting_towards_attainment() {
+        let mut tracker = SloTracker::new(slo());
+        assert_eq!(tracker.advance_to(sim_from_ms(2_500)), []);
+        let report = tracker.report();
+        // Both quiet windows are judged as one
+        assert_eq!(
+            report.windows,
+            [SloWindow { start: sim_from_ms(500), end: sim_from_ms(2_500), requests: 0, p99: None, violated: false }]
+        );
+        assert_eq!(report.attainment, None);
+        assert_eq!(report.windows[0].to_string(), "no requests in [0.500 s, 2.500 s)");
+
+        tracker.record(sim_from_ms(2_600), sim_from_ms(60));
+        tracker.record(sim_from_ms(5_000), sim_from_ms(10));
+        tracker.advance_to(sim_from_ms(6_000));
+        let windows: Vec<_> = tracker.report().windows.iter().map(|w| (w.start / sim_from_ms(1), w.end / sim_from_ms(1), w.p99)).collect();
+        assert_eq!(
+            windows,
+            [(500, 2_500, None), (2_500, 3_500, Some(sim_from_ms(60))), (3_500, 4_500, None), (4_500, 5_500, Some(sim_from_ms(10)))]
+        );
+        // Quiet windows neither meet nor hide a violation
+        assert_eq!(tracker.report().attainment, Some(50.0));
+        assert_eq!(tracker.current_violation(), None);
+        tracker.advance_to(sim_from_ms(9_000));
+        assert_eq!(tracker.report().attainment, Some(50.0));
+        assert_eq!(tracker.current_violation(), None);
+    }
+
+    #[test]
+    fn test_a_quiet_window_keeps_the_last_violation_up() {
+        let mut tracker = SloTracker::new(slo());
+        tracker.record(sim_from_ms(600), sim_from_ms(60));
+        tracker.advance_to(sim_from_ms(3_000));
+        assert_eq!(tracker.report().windows.len(), 2);
+        assert_eq!(tracker.current_violation().map(|w| w.start), Some(sim_from_ms(500)));
+    }
+
+    #[test]
+    fn test_windows_ending_past_the_end_of_time_are_never_judged() {
+        let window = SimTime::MAX / 2 + 1;
+        let mut tracker = SloTracker::new(Slo { p99_ms: 50.0, window, warmup: 0 });
+        tracker.record(SimTime::MAX, sim_from_ms(60));
+        assert_eq!(tracker.advance_to(SimTime::MAX), []);
+        let windows: Vec<_> = tracker.report().windows.iter().map(|w| (w.start, w.end, w.requests)).collect();
+        assert_eq!(windows, [(0, window, 0)]);
+    }
+}
@@ -0,0 +1,122 @@
+//! # ftsim-engine::state_hash
+//!
+//! A fixed, platform-independent hasher for `Simulation::state_hash`. Two runs
+//! that reach the same state produce the same digest, whichever machine they
+//! ran on, so digests can be compared across runs to catch nondeterminism.
+
+use std::hash::Hasher;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// 64-bit FNV-1a with no random state. Integers are hashed as little-endian
+/// bytes and `usize` always as 8 bytes, so digests do not depend on the
+/// platform's pointer width or byte order.
+#[derive(Debug, Clone)]
+pub struct StateHasher(u64);
+
+impl StateHasher {
+    pub fn new() -> Self {
+        Self(FNV_OFFSET)
+    }
+
+    /// Hashes a float by its bit pattern.
+    pub fn write_f64(&mut self, value: f64) {
+        self.write_u64(value.to_bits());
+    }
+
+    /// Hashes a length-prefixed byte string, so adjacent strings cannot run
+    /// into each other.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_usize(bytes.len());
+        self.write(bytes);
+    }
+}
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for StateHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::Hash;
+
+    #[test]
+    fn test_digests_are_fixed() {
+        let digest = |f: &dyn Fn(&mut StateHasher)| {
+            let mut hasher = StateHasher::new();
+            f(&mut hasher);
+            hasher.finish()
+        };
+        // FNV-1a test vectors
+        assert_eq!(digest(&|h| h.write(b"")), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(digest(&|h| h.write(b"a")), 0xaf63_dc4c_8601_ec8c);
+        // usize hashes like u64 on every platform
+        assert_eq!(digest(&|h| 7usize.hash(h)), digest(&|h| 7u64.hash(h)));
+        assert_ne!(
+            digest(&|h| {
+                h.write_bytes(b"ab");
+                h.write_bytes(b"c");
+            }),
+            digest(&|h| {
+                h.write_bytes(b"a");
+                h.write_bytes(b"bc");
+            })
+        );
+    }
+}
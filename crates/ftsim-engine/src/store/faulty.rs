@@ -2,14 +2,15 @@
 //!
 //! A wrapper store that injects faults around an inner `Store` implementation.
 //! It uses the master RNG to decide when to inject failures like I/O errors,
-//! torn writes, or fsync failures, based on configured rates.
+//! torn writes, fsync failures, or bit rot, based on configured rates.
 
-use crate::{prelude::*, sim::EngineCtx};
-use ftsim_proto::api::{LogIndex, LogRecord, StoreView as ProtoStoreView};
+use crate::{prelude::*, rng::bernoulli, sim::EngineCtx};
+use ftsim_proto::api::{LogIndex, LogRecord, SnapshotMeta, StoreView as ProtoStoreView};
 use rand::Rng;
+use serde::Serialize;
 
 /// The configuration for fault injection on a store.
-#[derive(Default, Clone, Copy)]
+#[derive(Serialize, Debug, Default, Clone, Copy)]
 pub struct StoreFaultModel {
     pub fsync_fail_rate: f64,
     pub fsync_delay_rate: f64,
@@ -17,6 +18,24 @@ pub struct StoreFaultModel {
     pub read_error_rate: f64,
     pub torn_write_rate: f64,
     pub stale_read_rate: f64,
+    pub bit_rot_rate: f64,
+}
+
+/// Flips between one and four bytes of a record's data. The record's
+/// checksum, if any, is left untouched so that `LogRecord::verify` can
+/// detect the damage.
+pub(crate) fn rot_record<R: Rng>(rng: &mut R, mut rec: LogRecord) -> LogRecord {
+    if rec.data.is_empty() {
+        return rec;
+    }
+    let mut data = rec.data.to_vec();
+    let flips = rng.gen_range(1..=data.len().min(4));
+    for _ in 0..flips {
+        let pos = rng.gen_range(0..data.len());
+        data[pos] ^= rng.gen_range(1..=u8::MAX);
+    }
+    rec.data = bytes::Bytes::from(data);
+    rec
 }
 
 /// A temporary view that wraps a `StoreView` to inject faults deterministically.
@@ -43,23 +62,21 @@ impl ProtoStoreView for FaultyStoreView<'_, '_> {
         let node_id = self.ctx.node_id();
 
         // Check for write error fault
-        if self.model.write_error_rate > 0.0 {
-            let site = Box::leak(format!("store.append_log.write_error.node[{}]", node_id).into_boxed_str());
-            if self.ctx.rng(site).gen_bool(self.model.write_error_rate) {
-                tracing::warn!(%node_id, "Injecting write error in append_log");
-                return Err(StoreError::FaultInjected);
-            }
+        if self.model.write_error_rate > 0.0
+            && bernoulli(&mut self.ctx.node_rng("store.append_log.write_error", node_id), self.model.write_error_rate)
+        {
+            tracing::warn!(%node_id, "Injecting write error in append_log");
+            return Err(StoreError::FaultInjected);
         }
 
         // Check for torn write fault (partial write)
-        if self.model.torn_write_rate > 0.0 {
-            let site = Box::leak(format!("store.append_log.torn_write.node[{}]", node_id).into_boxed_str());
-            if self.ctx.rng(site).gen_bool(self.model.torn_write_rate) {
-                tracing::warn!(%node_id, "Injecting torn write in append_log");
-                // For torn writes, we could partially corrupt the record, but for simplicity,
-                // we'll just return an error to indicate the write was incomplete
-                return Err(StoreError::FaultInjected);
-            }
+        if self.model.torn_write_rate > 0.0
+            && bernoulli(&mut self.ctx.node_rng("store.append_log.torn_write", node_id), self.model.torn_write_rate)
+        {
+            tracing::warn!(%node_id, "Injecting torn write in append_log");
+            // For torn writes, we could partially corrupt the record, but for simplicity,
+            // we'll just return an error to indicate the write was incomplete
+            return Err(StoreError::FaultInjected);
         }
 
         self.inner.append_log(rec)
@@ -69,26 +86,37 @@ impl ProtoStoreView for FaultyStoreView<'_, '_> {
         let node_id = self.ctx.node_id();
 
         // Check for read error fault
-        if self.model.read_error_rate > 0.0 {
-            let site = Box::leak(format!("store.read_log.read_error.node[{}]", node_id).into_boxed_str());
-            if self.ctx.rng(site).gen_bool(self.model.read_error_rate) {
-                tracing::warn!(%node_id, "Injecting read error in read_log");
-                return Err(StoreError::FaultInjected);
-            }
+        if self.model.read_error_rate > 0.0
+            && bernoulli(&mut self.ctx.node_rng("store.read_log.read_error", node_id), self.model.read_error_rate)
+        {
+            tracing::warn!(%node_id, "Injecting read error in read_log");
+            return Err(StoreError::FaultInjected);
         }
 
         // Check for stale read fault (return outdated data)
-        if self.model.stale_read_rate > 0.0 {
-            let site = Box::leak(format!("store.read_log.stale_read.node[{}]", node_id).into_boxed_str());
-            if self.ctx.rng(site).gen_bool(self.model.stale_read_rate) {
-                tracing::warn!(%node_id, "Injecting stale read in read_log");
-                // For stale reads, we could return an older version of data,
-                // but for simplicity, we'll return None to simulate missing data
-                return Ok(None);
-            }
+        if self.model.stale_read_rate > 0.0
+            && bernoulli(&mut self.ctx.node_rng("store.read_log.stale_read", node_id), self.model.stale_read_rate)
+        {
+            tracing::warn!(%node_id, "Injecting stale read in read_log");
+            // For stale reads, we could return an older version of data,
+            // but for simplicity, we'll return None to simulate missing data
+            return Ok(None);
         }
 
-        self.inner.read_log(idx)
+        let rec = self.inner.read_log(idx)?;
+
+        // Check for bit rot (silently corrupted data)
+        if let Some(rec) = rec {
+            if self.model.bit_rot_rate > 0.0 {
+                let mut rng = self.ctx.node_rng("store.read_log.bit_rot", node_id);
+                if bernoulli(&mut rng, self.model.bit_rot_rate) {
+                    tracing::warn!(%node_id, idx, "Injecting bit rot in read_log");
+                    return Ok(Some(rot_record(&mut rng, rec)));
+                }
+            }
+            return Ok(Some(rec));
+        }
+        Ok(None)
     }
 
     fn kv_put(&mut self, k: bytes::Bytes, v: bytes::Bytes) -> Result<(), StoreError> {
@@ -101,11 +129,76 @@ impl ProtoStoreView for FaultyStoreView<'_, '_> {
 
     fn fsync(&mut self) -> Result<(), StoreError> {
         let node_id = self.ctx.node_id();
-        let site = Box::leak(format!("store.fsync.node[{}]", node_id).into_boxed_str());
-        if self.ctx.rng(site).gen_bool(self.model.fsync_fail_rate) {
+        if bernoulli(&mut self.ctx.node_rng("store.fsync", node_id), self.model.fsync_fail_rate) {
             tracing::warn!(%node_id, "Injecting fsync failure");
             return Err(StoreError::FaultInjected);
         }
         self.inner.fsync()
     }
+
+    fn write_snapshot(&mut self, meta: SnapshotMeta, data: bytes::Bytes) -> Result<(), StoreError> {
+        let node_id = self.ctx.node_id();
+
+        if self.model.write_error_rate > 0.0
+            && bernoulli(
+                &mut self.ctx.node_rng("store.write_snapshot.write_error", node_id),
+                self.model.write_error_rate,
+            )
+        {
+            tracing::warn!(%node_id, "Injecting write error in write_snapshot");
+            return Err(StoreError::FaultInjected);
+        }
+
+        if self.model.torn_write_rate > 0.0
+            && bernoulli(&mut self.ctx.node_rng("store.write_snapshot.torn_write", node_id), self.model.torn_write_rate)
+        {
+            tracing::warn!(%node_id, "Injecting torn write in write_snapshot");
+            return Err(StoreError::FaultInjected);
+        }
+
+        self.inner.write_snapshot(meta, data)
+    }
+
+    fn read_snapshot(&mut self) -> Result<Option<(SnapshotMeta, bytes::Bytes)>, StoreError> {
+        self.inner.read_snapshot()
+    }
+
+    fn compact_log_up_to(&mut self, idx: LogIndex) -> Result<(), StoreError> {
+        self.inner.compact_log_up_to(idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemStore;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    fn append_and_rot(store: &mut MemStore) -> LogRecord {
+        let idx = store
+            .append_log(LogRecord::new(3, bytes::Bytes::from_static(b"set x=1")))
+            .unwrap();
+        let rec = store.read_log(idx).unwrap().unwrap();
+        rot_record(&mut ChaCha20Rng::seed_from_u64(42), rec)
+    }
+
+    #[test]
+    fn test_bit_rot_detected_with_checksums() {
+        let mut store = MemStore::new().with_checksums(true);
+        let rotted = append_and_rot(&mut store);
+        assert_ne!(rotted.data.as_ref(), b"set x=1");
+        assert!(rotted.checksum.is_some());
+        assert!(!rotted.verify());
+        assert!(store.read_log(0).unwrap().unwrap().verify());
+    }
+
+    #[test]
+    fn test_bit_rot_silent_without_checksums() {
+        let mut store = MemStore::new();
+        let rotted = append_and_rot(&mut store);
+        assert_ne!(rotted.data.as_ref(), b"set x=1");
+        assert!(rotted.checksum.is_none());
+        assert!(rotted.verify());
+    }
 }
@@ -0,0 +1,162 @@
+//! # ftsim-engine::store::journal
+//!
+//! An optional write-ahead recorder of store mutations. When enabled, the
+//! engine interposes a `JournalingStoreView` between protocols and each
+//! node's store, so every successful mutation is appended to a single
+//! engine-owned `StoreJournal` tagged with its sim time and event id. The
+//! journal can be dumped as JSONL to diff what two runs persisted.
+
+use crate::prelude::*;
+use ftsim_proto::api::{LogIndex, LogRecord, SnapshotMeta, StoreView as ProtoStoreView};
+use super::StoreView;
+use serde::Serialize;
+
+/// A single store mutation.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum StoreOp {
+    AppendLog { index: LogIndex, term: u64, len: usize, digest: u64 },
+    KvPut { key: String, len: usize, digest: u64 },
+    Fsync,
+    WriteSnapshot { last_included_index: LogIndex, last_included_term: u64, len: usize, digest: u64 },
+    CompactLog { up_to: LogIndex },
+}
+
+/// A journaled mutation and where in the run it happened.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub time: SimTime,
+    pub event_id: EventId,
+    pub node: NodeId,
+    #[serde(flatten)]
+    pub op: StoreOp,
+}
+
+/// All store mutations of a run, in the order they were applied.
+#[derive(Debug, Default, Clone)]
+pub struct StoreJournal {
+    entries: Vec<JournalEntry>,
+}
+
+impl StoreJournal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Writes one JSON object per line.
+    #[cfg(feature = "trace-export")]
+    pub fn write_jsonl<W: std::io::Write>(&self, mut out: W) -> std::io::Result<()> {
+        for entry in &self.entries {
+            serde_json::to_writer(&mut out, entry)?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()
+    }
+
+    /// Removes and returns the entries journaled so far, for writing them
+    /// out as the run goes.
+    pub fn take_entries(&mut self) -> Vec<JournalEntry> {
+        std::mem::take(&mut self.entries)
+    }
+}
+
+/// FNV-1a over `bytes`, used to fingerprint payloads without copying them.
+fn digest(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// A store view that records every successful mutation into a journal.
+/// Reads pass straight through.
+pub struct JournalingStoreView<'a> {
+    inner: &'a mut dyn StoreView,
+    journal: &'a mut StoreJournal,
+    time: SimTime,
+    event_id: EventId,
+    node: NodeId,
+}
+
+impl<'a> JournalingStoreView<'a> {
+    pub fn new(
+        inner: &'a mut dyn StoreView,
+        journal: &'a mut StoreJournal,
+        time: SimTime,
+        event_id: EventId,
+        node: NodeId,
+    ) -> Self {
+        Self {
+            inner,
+            journal,
+            time,
+            event_id,
+            node,
+        }
+    }
+
+    fn record(&mut self, op: StoreOp) {
+        self.journal.entries.push(JournalEntry {
+            time: self.time,
+            event_id: self.event_id,
+            node: self.node,
+            op,
+        });
+    }
+}
+
+impl ProtoStoreView for JournalingStoreView<'_> {
+    fn append_log(&mut self, rec: LogRecord) -> Result<LogIndex, StoreError> {
+        let (term, len, hash) = (rec.term, rec.data.len(), digest(&rec.data));
+        let index = self.inner.append_log(rec)?;
+        self.record(StoreOp::AppendLog { index, term, len, digest: hash });
+        Ok(index)
+    }
+
+    fn read_log(&mut self, idx: LogIndex) -> Result<Option<LogRecord>, StoreError> {
+        self.inner.read_log(idx)
+    }
+
+    fn kv_put(&mut self, k: bytes::Bytes, v: bytes::Bytes) -> Result<(), StoreError> {
+        let key = String::from_utf8_lossy(&k).into_owned();
+        let (len, hash) = (v.len(), digest(&v));
+        self.inner.kv_put(k, v)?;
+        self.record(StoreOp::KvPut { key, len, digest: hash });
+        Ok(())
+    }
+
+    fn kv_get(&mut self, k: &[u8]) -> Result<Option<bytes::Bytes>, StoreError> {
+        self.inner.kv_get(k)
+    }
+
+    fn fsync(&mut self) -> Result<(), StoreError> {
+        self.inner.fsync()?;
+        self.record(StoreOp::Fsync);
+        Ok(())
+    }
+
+    fn write_snapshot(&mut self, meta: SnapshotMeta, data: bytes::Bytes) -> Result<(), StoreError> {
+        let (len, hash) = (data.len(), digest(&data));
+        self.inner.write_snapshot(meta, data)?;
+        self.record(StoreOp::WriteSnapshot {
+            last_included_index: meta.last_included_index,
+            last_included_term: meta.last_included_term,
+            len,
+            digest: hash,
+        });
+        Ok(())
+    }
+
+    fn read_snapshot(&mut self) -> Result<Option<(SnapshotMeta, bytes::Bytes)>, StoreError> {
+        self.inner.read_snapshot()
+    }
+
+    fn compact_log_up_to(&mut self, idx: LogIndex) -> Result<(), StoreError> {
+        self.inner.compact_log_up_to(idx)?;
+        self.record(StoreOp::CompactLog { up_to: idx });
+        Ok(())
+    }
+}
@@ -3,44 +3,220 @@
 //! A simple, deterministic, in-memory storage implementation.
 //! It uses `BTreeMap` to ensure that any iteration (not exposed in the API,
 //! but good practice for determinism) is ordered.
+//!
+//! With `Durability::BufferedUntilFsync`, writes are visible immediately but
+//! an undo log is kept until the next `fsync`, so that a crash can roll the
+//! store back to its last durable state.
+//!
+//! The store holds at most one snapshot. Compacting the log drops a prefix of
+//! entries while keeping the indices of the remaining entries stable. Both are
+//! buffered like any other write, so a crash before the next `fsync` brings
+//! back the previous snapshot and the compacted entries.
 
-use crate::prelude::*;
+use crate::{
+    prelude::*,
+    state_hash::StateHasher,
+    store::{StoreCheckpoint, StoreConfig},
+    telemetry::snapshot::StoreSnap,
+};
 use bytes::Bytes;
-use ftsim_proto::api::{LogIndex, LogRecord, StoreView as ProtoStoreView};
-use std::collections::BTreeMap;
+use ftsim_proto::api::{LogIndex, LogRecord, SnapshotMeta, StoreView as ProtoStoreView};
+use std::{
+    collections::BTreeMap,
+    hash::Hasher,
+};
 
-/// An in-memory key-value and log store.
-#[derive(Default)]
+/// An in-memory key-value and log store. Cloning is cheap: keys, values and
+/// record payloads are reference-counted `Bytes`.
+#[derive(Default, Clone)]
 pub struct MemStore {
     kv: BTreeMap<Bytes, Bytes>,
     log: Vec<LogRecord>,
+    /// The index of `log[0]`; every entry below it has been compacted.
+    log_start: LogIndex,
+    snapshot: Option<(SnapshotMeta, Bytes)>,
+    durability: Durability,
+    /// The value each key held at the last `fsync` (`None` if it was absent),
+    /// recorded on the first unsynced write to that key.
+    kv_undo: BTreeMap<Bytes, Option<Bytes>>,
+    /// The snapshot at the last `fsync`, recorded on the first unsynced
+    /// `write_snapshot`.
+    snapshot_undo: Option<Option<(SnapshotMeta, Bytes)>>,
+    /// `log_start` at the last `fsync`.
+    durable_log_start: LogIndex,
+    /// Entries dropped by compactions since the last `fsync`, in index order
+    /// from `durable_log_start`.
+    compacted_undo: Vec<LogRecord>,
+    /// Whether appended records are stamped with a checksum.
+    checksums: bool,
 }
 
 impl MemStore {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Creates a store with the given durability semantics.
+    pub fn with_durability(durability: Durability) -> Self {
+        Self {
+            durability,
+            ..Self::default()
+        }
+    }
+
+    /// Enables or disables checksumming of appended log records.
+    pub fn with_checksums(mut self, checksums: bool) -> Self {
+        self.checksums = checksums;
+        self
+    }
+
+    fn buffered(&self) -> bool {
+        self.durability == Durability::BufferedUntilFsync
+    }
 }
 
 impl Store for MemStore {
     fn as_view(&mut self) -> &mut dyn super::StoreView {
         self
     }
+
+    fn discard_unsynced(&mut self, durable_log_len: LogIndex) {
+        if !self.buffered() {
+            return;
+        }
+        for (k, prev) in std::mem::take(&mut self.kv_undo) {
+            match prev {
+                Some(v) => self.kv.insert(k, v),
+                None => self.kv.remove(&k),
+            };
+        }
+        if let Some(snapshot) = self.snapshot_undo.take() {
+            self.snapshot = snapshot;
+        }
+        let compacted = std::mem::take(&mut self.compacted_undo);
+        self.log_start = self.durable_log_start;
+        self.log.splice(..0, compacted);
+        self.log.truncate(durable_log_len.saturating_sub(self.log_start) as usize);
+    }
+
+    fn log_len(&self) -> Option<LogIndex> {
+        Some(self.log_start + self.log.len() as LogIndex)
+    }
+
+    fn checkpoint(&self) -> StoreCheckpoint {
+        StoreCheckpoint::new(self.clone())
+    }
+
+    fn restore(&mut self, checkpoint: StoreCheckpoint) {
+        *self = checkpoint
+            .into_inner::<MemStore>()
+            .expect("checkpoint was not taken from a MemStore");
+    }
+
+    fn summary(&self, max_keys: Option<usize>) -> Option<StoreSnap> {
+        let log_bytes: usize = self.log.iter().map(|r| 8 + r.data.len()).sum();
+        let kv_bytes: usize = self.kv.iter().map(|(k, v)| k.len() + v.len()).sum();
+        let snapshot_bytes = self.snapshot.as_ref().map_or(0, |(_, data)| data.len());
+        Some(StoreSnap {
+            log_len: self.log_start + self.log.len() as LogIndex,
+            last_log_term: self
+                .log
+                .last()
+                .map(|r| r.term)
+                .or(self.snapshot.as_ref().map(|(meta, _)| meta.last_included_term)),
+            kv_keys: self.kv.len(),
+            approx_bytes: log_bytes + kv_bytes + snapshot_bytes,
+            keys: max_keys.map(|n| {
+                self.kv
+                    .keys()
+                    .take(n)
+                    .map(|k| String::from_utf8_lossy(k).into_owned())
+                    .collect()
+            }),
+            throttled_writes: 0,
+            throttle_delay_ns: 0,
+        })
+    }
+
+    fn config(&self) -> Option<StoreConfig> {
+        Some(StoreConfig {
+            kind: "mem",
+            durability: self.durability,
+            checksums: self.checksums,
+        })
+    }
+
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_u64(self.log_start);
+        hasher.write_usize(self.log.len());
+        for record in &self.log {
+            hasher.write_u64(record.term);
+            hasher.write_bytes(&record.data);
+            hasher.write_u8(record.checksum.is_some() as u8);
+            hasher.write_u64(record.checksum.unwrap_or(0));
+        }
+        hasher.write_usize(self.kv.len());
+        for (key, value) in &self.kv {
+            hasher.write_bytes(key);
+            hasher.write_bytes(value);
+        }
+        match &self.snapshot {
+            Some((meta, data)) => {
+                hasher.write_u8(1);
+                hasher.write_u64(meta.last_included_index);
+                hasher.write_u64(meta.last_included_term);
+                hasher.write_bytes(data);
+            }
+            None => hasher.write_u8(0),
+        }
+        // What a crash would roll back to, but for the log's durable length
+        hasher.write_usize(self.kv_undo.len());
+        for (key, value) in &self.kv_undo {
+            hasher.write_bytes(key);
+            hasher.write_u8(value.is_some() as u8);
+            hasher.write_bytes(value.as_deref().unwrap_or_default());
+        }
+        match &self.snapshot_undo {
+            Some(Some((meta, data))) => {
+                hasher.write_u8(2);
+                hasher.write_u64(meta.last_included_index);
+                hasher.write_u64(meta.last_included_term);
+                hasher.write_bytes(data);
+            }
+            Some(None) => hasher.write_u8(1),
+            None => hasher.write_u8(0),
+        }
+        hasher.write_u64(self.durable_log_start);
+        hasher.write_usize(self.compacted_undo.len());
+        for record in &self.compacted_undo {
+            hasher.write_u64(record.term);
+            hasher.write_bytes(&record.data);
+        }
+    }
 }
 
 impl ProtoStoreView for MemStore {
-    fn append_log(&mut self, rec: LogRecord) -> Result<LogIndex, StoreError> {
-        let index = self.log.len() as LogIndex;
+    fn append_log(&mut self, mut rec: LogRecord) -> Result<LogIndex, StoreError> {
+        if self.checksums {
+            rec.seal();
+        }
+        let index = self.log_start + self.log.len() as LogIndex;
         self.log.push(rec);
         Ok(index)
     }
 
     fn read_log(&mut self, idx: LogIndex) -> Result<Option<LogRecord>, StoreError> {
-        Ok(self.log.get(idx as usize).cloned())
+        if idx < self.log_start {
+            return Err(StoreError::Compacted(idx));
+        }
+        Ok(self.log.get((idx - self.log_start) as usize).cloned())
     }
 
     fn kv_put(&mut self, k: Bytes, v: Bytes) -> Result<(), StoreError> {
-        self.kv.insert(k, v);
+        let prev = self.kv.insert(k.clone(), v);
+        if self.buffered() {
+            self.kv_undo.entry(k).or_insert(prev);
+        }
         Ok(())
     }
 
@@ -49,7 +225,150 @@ impl ProtoStoreView for MemStore {
     }
 
     fn fsync(&mut self) -> Result<(), StoreError> {
-        // In-memory store, fsync is a no-op.
+        // Everything written so far becomes durable.
+        self.kv_undo.clear();
+        self.durable_log_start = self.log_start;
+        self.snapshot_undo = None;
+        self.compacted_undo.clear();
+        Ok(())
+    }
+
+    fn write_snapshot(&mut self, meta: SnapshotMeta, data: Bytes) -> Result<(), StoreError> {
+        let prev = self.snapshot.replace((meta, data));
+        if self.buffered() && self.snapshot_undo.is_none() {
+            self.snapshot_undo = Some(prev);
+        }
         Ok(())
     }
+
+    fn read_snapshot(&mut self) -> Result<Option<(SnapshotMeta, Bytes)>, StoreError> {
+        Ok(self.snapshot.clone())
+    }
+
+    fn compact_log_up_to(&mut self, idx: LogIndex) -> Result<(), StoreError> {
+        if idx < self.log_start {
+            return Ok(());
+        }
+        let drop = ((idx - self.log_start + 1) as usize).min(self.log.len());
+        let buffered = self.buffered();
+        let dropped = self.log.drain(..drop);
+        if buffered {
+            // The store cannot tell which entries are durable, so it keeps
+            // them all, and a crash cuts them back with the rest of the log
+            self.compacted_undo.extend(dropped);
+        }
+        self.log_start += drop as LogIndex;
+        if !buffered {
+            self.durable_log_start = self.log_start;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rec(term: u64) -> LogRecord {
+        LogRecord::new(term, Bytes::from_static(b"x"))
+    }
+
+    #[test]
+    fn test_always_durable_survives_crash() {
+        let mut store = MemStore::new();
+        store.kv_put(Bytes::from_static(b"voted_for"), Bytes::from_static(b"1")).unwrap();
+        store.append_log(rec(1)).unwrap();
+        store.discard_unsynced(0);
+
+        assert_eq!(store.kv_get(b"voted_for").unwrap(), Some(Bytes::from_static(b"1")));
+        assert!(store.read_log(0).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_buffered_loses_unsynced_writes() {
+        let mut store = MemStore::with_durability(Durability::BufferedUntilFsync);
+        store.kv_put(Bytes::from_static(b"term"), Bytes::from_static(b"1")).unwrap();
+        store.append_log(rec(1)).unwrap();
+        store.fsync().unwrap();
+        // The boundary the engine records once the fsync succeeds
+        let durable = store.log_len().unwrap();
+
+        // A vote persisted without fsync, plus an overwrite and an append.
+        store.kv_put(Bytes::from_static(b"voted_for"), Bytes::from_static(b"2")).unwrap();
+        store.kv_put(Bytes::from_static(b"term"), Bytes::from_static(b"2")).unwrap();
+        store.kv_put(Bytes::from_static(b"term"), Bytes::from_static(b"3")).unwrap();
+        store.append_log(rec(2)).unwrap();
+        store.discard_unsynced(durable);
+
+        // After the crash the node has forgotten its vote and can vote again.
+        assert_eq!(store.kv_get(b"voted_for").unwrap(), None);
+        assert_eq!(store.kv_get(b"term").unwrap(), Some(Bytes::from_static(b"1")));
+        assert_eq!(store.read_log(0).unwrap().map(|r| r.term), Some(1));
+        assert!(store.read_log(1).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_compacted_read_is_distinguishable() {
+        let mut store = MemStore::new();
+        for term in 1..=5 {
+            store.append_log(rec(term)).unwrap();
+        }
+        store.compact_log_up_to(2).unwrap();
+
+        assert!(matches!(store.read_log(2), Err(StoreError::Compacted(2))));
+        assert_eq!(store.read_log(3).unwrap().map(|r| r.term), Some(4));
+        assert!(store.read_log(5).unwrap().is_none());
+        assert_eq!(store.append_log(rec(6)).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_buffered_snapshot_and_compaction_roll_back() {
+        let mut store = MemStore::with_durability(Durability::BufferedUntilFsync);
+        let meta = |index| SnapshotMeta {
+            last_included_index: index,
+            last_included_term: 1,
+        };
+        for term in 1..=4 {
+            store.append_log(rec(term)).unwrap();
+        }
+        store.write_snapshot(meta(0), Bytes::from_static(b"old")).unwrap();
+        store.fsync().unwrap();
+        let durable = store.log_len().unwrap();
+
+        store.append_log(rec(5)).unwrap();
+        store.write_snapshot(meta(4), Bytes::from_static(b"new")).unwrap();
+        store.compact_log_up_to(4).unwrap();
+        assert!(matches!(store.read_log(3), Err(StoreError::Compacted(3))));
+        store.discard_unsynced(durable);
+
+        // The crash undoes both, but not the synced entries
+        assert_eq!(store.read_snapshot().unwrap(), Some((meta(0), Bytes::from_static(b"old"))));
+        assert_eq!(store.read_log(0).unwrap().map(|r| r.term), Some(1));
+        assert_eq!(store.read_log(3).unwrap().map(|r| r.term), Some(4));
+        assert!(store.read_log(4).unwrap().is_none());
+        assert_eq!(store.log_len(), Some(4));
+
+        // Once synced they stick
+        store.write_snapshot(meta(2), Bytes::from_static(b"new")).unwrap();
+        store.compact_log_up_to(2).unwrap();
+        store.fsync().unwrap();
+        store.discard_unsynced(store.log_len().unwrap());
+        assert_eq!(store.read_snapshot().unwrap().map(|(m, _)| m), Some(meta(2)));
+        assert!(matches!(store.read_log(2), Err(StoreError::Compacted(2))));
+        assert_eq!(store.read_log(3).unwrap().map(|r| r.term), Some(4));
+    }
+
+    #[test]
+    fn test_snapshot_round_trips() {
+        let mut store = MemStore::new();
+        assert!(store.read_snapshot().unwrap().is_none());
+
+        let meta = SnapshotMeta {
+            last_included_index: 7,
+            last_included_term: 2,
+        };
+        let data = Bytes::from_static(&[0, 1, 2, 0xFF, 0xFE]);
+        store.write_snapshot(meta, data.clone()).unwrap();
+        assert_eq!(store.read_snapshot().unwrap(), Some((meta, data)));
+    }
 }
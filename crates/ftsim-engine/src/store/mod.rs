@@ -4,11 +4,18 @@
 //! persistent storage, along with several implementations:
 //! - `MemStore`: A simple, deterministic in-memory store.
 //! - `FaultyStoreView`: A wrapper that injects storage failures around another store view.
+//! - `JournalingStoreView`: A wrapper that records every mutation into a `StoreJournal`.
+//! - `WriteQuota`: A token bucket limiting how fast a node may write.
 
 mod faulty;
+mod journal;
 mod mem;
+mod quota;
 mod r#trait;
 
+pub(crate) use faulty::rot_record;
 pub use faulty::{FaultyStoreView, StoreFaultModel};
+pub use journal::{JournalEntry, JournalingStoreView, StoreJournal, StoreOp};
 pub use mem::MemStore;
-pub use r#trait::{Store, StoreView};
+pub use quota::{QuotaDecision, WriteQuota};
+pub use r#trait::{Store, StoreCheckpoint, StoreConfig, StoreView};
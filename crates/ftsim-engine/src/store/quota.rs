@@ -0,0 +1,170 @@
+//! # ftsim-engine::store::quota
+//!
+//! A per-node store write-rate quota, enforced with a token bucket that
+//! refills with the sim time elapsed between operations. All arithmetic is on
+//! integers, so the same writes at the same times are always admitted, failed
+//! or delayed the same way.
+
+use crate::prelude::*;
+
+/// Tokens are kept in byte-nanoseconds so that refilling at
+/// `bytes_per_sec` loses nothing to rounding.
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+/// What a quota decided about one write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaDecision {
+    Admit,
+    /// Fail the write with `StoreError::Throttled`.
+    Throttle,
+    /// Admit the write once this much sim time has passed.
+    Delay(SimTime),
+}
+
+/// The token bucket of one node's write quota.
+#[derive(Debug, Clone)]
+pub struct WriteQuota {
+    spec: WriteQuotaSpec,
+    /// Available byte-nanoseconds. Negative while delayed writes are still
+    /// paying off what they borrowed.
+    tokens: i128,
+    /// The sim time `tokens` was last refilled to.
+    refilled_at: SimTime,
+    throttled_writes: u64,
+    delay_ns: u64,
+}
+
+impl WriteQuota {
+    /// Creates a quota whose bucket starts full.
+    pub fn new(spec: WriteQuotaSpec) -> Self {
+        let mut quota = Self {
+            spec,
+            tokens: 0,
+            refilled_at: SIM_EPOCH,
+            throttled_writes: 0,
+            delay_ns: 0,
+        };
+        quota.tokens = quota.capacity();
+        quota
+    }
+
+    pub fn spec(&self) -> &WriteQuotaSpec {
+        &self.spec
+    }
+
+    /// Writes throttled or delayed so far.
+    pub fn throttled_writes(&self) -> u64 {
+        self.throttled_writes
+    }
+
+    /// Total sim time writes have been delayed by.
+    pub fn delay_ns(&self) -> u64 {
+        self.delay_ns
+    }
+
+    fn capacity(&self) -> i128 {
+        self.spec.burst() as i128 * NANOS_PER_SEC
+    }
+
+    fn refill(&mut self, now: SimTime) {
+        if now <= self.refilled_at {
+            return;
+        }
+        let elapsed = (now - self.refilled_at) as i128;
+        let rate = self.spec.bytes_per_sec as i128;
+        // Too long a wait to count refills any bucket
+        self.tokens = elapsed
+            .checked_mul(rate)
+            .and_then(|refill| refill.checked_add(self.tokens))
+            .map_or(self.capacity(), |tokens| tokens.min(self.capacity()));
+        self.refilled_at = now;
+    }
+
+    /// Charges a write of `bytes` issued at `now`. Under
+    /// `QuotaAction::Throttle`, a write the bucket cannot cover is refused
+    /// and costs nothing; under `QuotaAction::Delay` it borrows from future
+    /// refills and waits until they have paid it off. A `now` before the
+    /// time the bucket was last refilled to, as when an earlier handler's
+    /// writes were delayed past it, refills nothing.
+    pub fn charge(&mut self, now: SimTime, bytes: u64) -> QuotaDecision {
+        self.refill(now);
+        let cost = bytes as i128 * NANOS_PER_SEC;
+        if self.tokens >= cost {
+            self.tokens -= cost;
+            return QuotaDecision::Admit;
+        }
+        self.throttled_writes += 1;
+        match self.spec.on_exceeded {
+            QuotaAction::Throttle => QuotaDecision::Throttle,
+            QuotaAction::Delay => {
+                self.tokens -= cost;
+                let rate = self.spec.bytes_per_sec.max(1) as i128;
+                // The debt is paid off counting from when the bucket was
+                // refilled to, which may be later than `now`
+                let wait = (-self.tokens + rate - 1) / rate;
+                let wait = SimTime::try_from(wait)
+                    .ok()
+                    .and_then(|wait| wait.checked_add(self.refilled_at - now))
+                    .unwrap_or(MAX_SIM_TIME);
+                self.delay_ns = self.delay_ns.saturating_add(u64::try_from(wait).unwrap_or(u64::MAX));
+                QuotaDecision::Delay(wait)
+            }
+        }
+    }
+
+    /// Hashes the bucket's state for `Simulation::state_hash`.
+    pub fn hash_state<H: std::hash::Hasher>(&self, hasher: &mut H) {
+        hasher.write_i128(self.tokens);
+        hasher.write_u128(self.refilled_at);
+        hasher.write_u64(self.throttled_writes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quota(on_exceeded: QuotaAction) -> WriteQuota {
+        WriteQuota::new(WriteQuotaSpec {
+            bytes_per_sec: 1_000,
+            burst_bytes: Some(500),
+            on_exceeded,
+            nodes: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn test_throttle_refuses_writes_until_refilled() {
+        let mut q = quota(QuotaAction::Throttle);
+        assert_eq!(q.charge(0, 300), QuotaDecision::Admit);
+        assert_eq!(q.charge(0, 300), QuotaDecision::Throttle);
+        // 100ms refills 100 bytes on top of the 200 left
+        assert_eq!(q.charge(sim_from_ms(100), 300), QuotaDecision::Admit);
+        assert_eq!(q.charge(sim_from_ms(100), 1), QuotaDecision::Throttle);
+        // The bucket never holds more than the burst
+        assert_eq!(q.charge(sim_from_ms(10_000), 501), QuotaDecision::Throttle);
+        assert_eq!(q.charge(sim_from_ms(10_000), 500), QuotaDecision::Admit);
+        assert_eq!(q.throttled_writes(), 3);
+    }
+
+    #[test]
+    fn test_delay_borrows_from_future_refills() {
+        let mut q = quota(QuotaAction::Delay);
+        assert_eq!(q.charge(0, 500), QuotaDecision::Admit);
+        // 1 byte at 1000 B/s takes 1ms to refill
+        assert_eq!(q.charge(0, 1), QuotaDecision::Delay(sim_from_ms(1)));
+        assert_eq!(q.charge(0, 1), QuotaDecision::Delay(sim_from_ms(2)));
+        assert_eq!(q.charge(sim_from_ms(2), 1), QuotaDecision::Delay(sim_from_ms(1)));
+        assert_eq!(q.delay_ns(), sim_from_ms(4) as u64);
+    }
+
+    #[test]
+    fn test_delay_counts_from_where_the_bucket_was_refilled_to() {
+        let mut q = quota(QuotaAction::Delay);
+        // A delayed handler wrote at 10ms, ahead of the clock
+        assert_eq!(q.charge(sim_from_ms(10), 500), QuotaDecision::Admit);
+        // The next write, issued at 0, is not covered before 11ms
+        assert_eq!(q.charge(0, 1), QuotaDecision::Delay(sim_from_ms(11)));
+        assert_eq!(q.charge(sim_from_ms(11), 1), QuotaDecision::Delay(sim_from_ms(1)));
+    }
+}
@@ -4,12 +4,77 @@
 //! This abstraction allows different storage backends (in-memory, file-based,
 //! faulty) to be used interchangeably.
 
-use ftsim_proto::api::StoreView as ProtoStoreView;
+use crate::{state_hash::StateHasher, telemetry::snapshot::StoreSnap};
+use ftsim_proto::api::{LogIndex, StoreView as ProtoStoreView};
+use ftsim_types::scenario::Durability;
+use serde::Serialize;
+use std::any::Any;
+
+/// An opaque, point-in-time copy of a store's contents, produced by
+/// `Store::checkpoint` and only meaningful to the store type that created it.
+pub struct StoreCheckpoint(Box<dyn Any + Send>);
+
+impl StoreCheckpoint {
+    pub fn new<T: Any + Send>(state: T) -> Self {
+        Self(Box::new(state))
+    }
+
+    /// Recovers the captured state, or `None` if it was taken from a
+    /// different store type.
+    pub fn into_inner<T: Any>(self) -> Option<T> {
+        self.0.downcast().ok().map(|b| *b)
+    }
+}
+
+/// How a store backend is configured, for `Simulation::effective_config`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoreConfig {
+    /// The backend type, e.g. `mem`.
+    pub kind: &'static str,
+    pub durability: Durability,
+    pub checksums: bool,
+}
 
 /// The main trait for a storage backend. It must be `Send` to be used in nodes.
 pub trait Store: Send {
     /// Provides a view into the store, which is what protocols interact with.
     fn as_view(&mut self) -> &mut dyn StoreView;
+
+    /// Drops any writes that have not been made durable, cutting the log
+    /// back to `durable_log_len`: the boundary between its durable and
+    /// volatile parts, which the engine keeps in the node's metadata. Called
+    /// by the engine when the owning node crashes.
+    fn discard_unsynced(&mut self, _durable_log_len: LogIndex) {}
+
+    /// Returns the index one past the log's last entry, counting compacted
+    /// entries. Stores that cannot tell return `None`.
+    fn log_len(&self) -> Option<LogIndex> {
+        None
+    }
+
+    /// Captures the store's full contents, including unsynced writes.
+    fn checkpoint(&self) -> StoreCheckpoint;
+
+    /// Replaces the store's contents with a checkpoint taken earlier from a
+    /// store of the same type.
+    fn restore(&mut self, checkpoint: StoreCheckpoint);
+
+    /// Summarizes the store's contents for snapshots, listing up to
+    /// `max_keys` KV keys if given. Stores that cannot be inspected return `None`.
+    fn summary(&self, _max_keys: Option<usize>) -> Option<StoreSnap> {
+        None
+    }
+
+    /// Describes the store's configuration. Stores that have none to report
+    /// return `None`.
+    fn config(&self) -> Option<StoreConfig> {
+        None
+    }
+
+    /// Feeds the store's full contents into a state hash, including writes
+    /// that would be lost on a crash. Stores that cannot be inspected add
+    /// nothing.
+    fn hash_state(&self, _hasher: &mut StateHasher) {}
 }
 
 /// A trait that combines the protocol-facing `StoreView` with engine-side requirements.
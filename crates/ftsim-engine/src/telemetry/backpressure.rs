@@ -0,0 +1,123 @@
+//! # ftsim-engine::telemetry::backpressure
+//!
+//! A test harness that runs the same simulation against snapshot consumers
+//! with different backpressure behaviors and checks that the engine's event
+//! stream and final state are identical. Consumer speed must never be visible
+//! to the simulation: a failed `try_send` has to do exactly the same work as
+//! a successful one.
+
+use crate::{prelude::*, sim::Simulation};
+use crossbeam_channel::{Receiver, Sender};
+use ftsim_proto::{api::boxed_dyn, protocols::raft_lite::RaftLite};
+use rand::Rng;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+const SEED: u64 = 0xB4C7;
+const NUM_NODES: usize = 3;
+const STOP_AT_MS: u64 = 3_000;
+
+/// How the consumer on the other end of the snapshot channel behaves.
+#[derive(Debug, Clone, Copy)]
+enum ConsumerBehavior {
+    /// Unbounded channel, drained promptly.
+    Prompt,
+    /// Zero-capacity channel that is never drained: every send fails.
+    AlwaysFull,
+    /// Single-slot channel drained at random intervals.
+    RandomlyFull,
+    /// Single-slot channel drained with a fixed delay per snapshot.
+    SlowDrain,
+}
+
+impl ConsumerBehavior {
+    const ALL: [ConsumerBehavior; 4] = [
+        ConsumerBehavior::Prompt,
+        ConsumerBehavior::AlwaysFull,
+        ConsumerBehavior::RandomlyFull,
+        ConsumerBehavior::SlowDrain,
+    ];
+
+    /// Creates the snapshot sender and spawns the matching consumer. The
+    /// returned receiver (if any) must be kept alive for the run.
+    fn spawn(self) -> (Sender<Snapshot>, Option<Receiver<Snapshot>>, Option<JoinHandle<()>>) {
+        match self {
+            ConsumerBehavior::Prompt => {
+                let (tx, rx) = crossbeam_channel::unbounded();
+                (tx, None, Some(thread::spawn(move || for _ in rx {})))
+            }
+            ConsumerBehavior::AlwaysFull => {
+                let (tx, rx) = crossbeam_channel::bounded(0);
+                (tx, Some(rx), None)
+            }
+            ConsumerBehavior::RandomlyFull => {
+                let (tx, rx) = crossbeam_channel::bounded(1);
+                let handle = thread::spawn(move || {
+                    let mut rng = rand::thread_rng();
+                    for _ in rx {
+                        thread::sleep(Duration::from_micros(rng.gen_range(0..500)));
+                    }
+                });
+                (tx, None, Some(handle))
+            }
+            ConsumerBehavior::SlowDrain => {
+                let (tx, rx) = crossbeam_channel::bounded(1);
+                let handle = thread::spawn(move || {
+                    for _ in rx {
+                        thread::sleep(Duration::from_millis(2));
+                    }
+                });
+                (tx, None, Some(handle))
+            }
+        }
+    }
+}
+
+/// Runs a small Raft cluster with UI snapshot ticks enabled and returns a
+/// digest of every step time plus the final world snapshot.
+fn run_with_consumer(behavior: ConsumerBehavior) -> u64 {
+    let (snapshot_tx, _rx, consumer) = behavior.spawn();
+
+    let world = World::full_mesh(NUM_NODES, |_| boxed_dyn(RaftLite::default()));
+
+    let telemetry = TelemetryBus::new(snapshot_tx, NUM_NODES, &TelemetrySpec::default());
+    let mut sim = Simulation::new(SEED, world, telemetry);
+    sim.init();
+    sim.schedule_at(0, Event::UiSnapshotTick, EventDiscriminant::ui());
+
+    let mut hasher = DefaultHasher::new();
+    let stop_at = sim_from_ms(STOP_AT_MS);
+    while let Some(time) = sim.step() {
+        time.hash(&mut hasher);
+        if time >= stop_at {
+            break;
+        }
+    }
+    sim.events_processed().hash(&mut hasher);
+    let final_snap = sim.telemetry().build_snapshot(sim.world(), sim.now());
+    format!("{:?}", final_snap).hash(&mut hasher);
+
+    // Dropping the simulation drops the sender, which ends the consumer.
+    drop(sim);
+    if let Some(handle) = consumer {
+        handle.join().unwrap();
+    }
+    hasher.finish()
+}
+
+#[test]
+fn test_consumer_backpressure_does_not_affect_simulation() {
+    let baseline = run_with_consumer(ConsumerBehavior::Prompt);
+    for behavior in ConsumerBehavior::ALL {
+        assert_eq!(
+            run_with_consumer(behavior),
+            baseline,
+            "simulation diverged with {:?} consumer",
+            behavior
+        );
+    }
+}
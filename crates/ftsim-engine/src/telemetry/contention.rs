@@ -0,0 +1,107 @@
+//! # ftsim-engine::telemetry::contention
+//!
+//! A stress test for concurrent readers of the bus. A reader thread builds
+//! snapshots in a tight loop while the simulation runs, as the status
+//! endpoint and metrics exporters do, and checks that every snapshot it sees
+//! is consistent, that the run finishes without deadlocking, and that the
+//! reader leaves the simulation's outcome unchanged.
+
+use crate::{prelude::*, sim::Simulation};
+use ftsim_proto::{api::boxed_dyn, protocols::raft_lite::RaftLite};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+const SEED: u64 = 0xC0_4E;
+const NUM_NODES: usize = 5;
+const STOP_AT_MS: u64 = 3_000;
+
+/// How long the whole run may take before it counts as deadlocked.
+const DEADLINE: Duration = Duration::from_secs(60);
+
+fn world() -> World {
+    World::full_mesh(NUM_NODES, |_| boxed_dyn(RaftLite::default()))
+}
+
+/// Checks a snapshot against itself and against the one read before it.
+fn check_snapshot(snap: &Snapshot, prev: Option<&Snapshot>) {
+    let m = &snap.metrics;
+    assert!(m.messages_delivered <= m.messages_sent, "{:?}", m);
+    assert!(m.client_responses <= m.client_requests, "{:?}", m);
+    // Up to the buffer's capacity of chatter, and as many warnings and faults
+    assert!(snap.recent_events.len() <= 2 * TelemetrySpec::default().event_buffer);
+    assert!(snap.recent_events.windows(2).all(|w| w[0].time <= w[1].time));
+    for node in &snap.nodes {
+        for samples in node.metrics.values() {
+            assert!(samples.windows(2).all(|w| w[0].time < w[1].time));
+        }
+    }
+    if let Some(prev) = prev {
+        let p = &prev.metrics;
+        assert!(m.messages_sent >= p.messages_sent && m.messages_delivered >= p.messages_delivered);
+        assert!(m.timers_fired >= p.timers_fired && m.faults_injected >= p.faults_injected);
+        if let (Some(last), Some(prev_last)) = (snap.recent_events.last(), prev.recent_events.last()) {
+            assert!(last.time >= prev_last.time);
+        }
+    }
+}
+
+/// Runs a Raft cluster to completion, with a reader hammering the bus if
+/// `hammer` is set, and returns the final snapshot's contents.
+fn run(hammer: bool) -> String {
+    let telemetry = TelemetryBus::detached(NUM_NODES, &TelemetrySpec::default());
+    let mut sim = Simulation::new(SEED, world(), telemetry.clone());
+    sim.init();
+
+    let done = Arc::new(AtomicBool::new(false));
+    let reader = hammer.then(|| {
+        let done = done.clone();
+        thread::spawn(move || {
+            // The reader has no access to the simulation's world; the
+            // telemetry it copies is the same for any world of this shape.
+            let world = world();
+            let mut prev: Option<Snapshot> = None;
+            let mut reads = 0u64;
+            while !done.load(Ordering::Acquire) {
+                let snap = telemetry.build_snapshot(&world, 0);
+                check_snapshot(&snap, prev.as_ref());
+                telemetry.node_kvs(NUM_NODES);
+                telemetry.try_recent_events();
+                prev = Some(snap);
+                reads += 1;
+            }
+            reads
+        })
+    });
+
+    let stop_at = sim_from_ms(STOP_AT_MS);
+    while let Some(time) = sim.step() {
+        if time >= stop_at {
+            break;
+        }
+    }
+    done.store(true, Ordering::Release);
+    if let Some(reader) = reader {
+        assert!(reader.join().expect("reader saw an inconsistent snapshot") > 0);
+    }
+
+    let snap = sim.telemetry().build_snapshot(sim.world(), sim.now());
+    format!("{:?}", snap)
+}
+
+#[test]
+fn test_concurrent_snapshots_do_not_deadlock_or_perturb_the_run() {
+    let (tx, rx) = crossbeam_channel::bounded(1);
+    thread::spawn(move || {
+        let quiet = run(false);
+        let hammered = run(true);
+        let _ = tx.send((quiet, hammered));
+    });
+    let (quiet, hammered) = rx.recv_timeout(DEADLINE).expect("run with a concurrent reader deadlocked");
+    assert_eq!(quiet, hammered);
+}
@@ -0,0 +1,399 @@
+//! # ftsim-engine::telemetry::delta
+//!
+//! What changed between two snapshots, for consumers that would rather not
+//! compare whole `Snapshot`s themselves. A `SnapshotDelta` lists the node
+//! fields and links that differ, the events logged in between and the run
+//! counters if any moved; everything else is left out. Sim time passing is
+//! not a change by itself.
+
+use super::snapshot::{LinkSnap, LogSnap, MetricSample, MetricsSnapshot, NodeSnap, NodeTraffic, Snapshot, StoreSnap};
+use crate::{node::GrayFailure, prelude::*, slo::SloWindow};
+use indexmap::IndexMap;
+use serde::Serialize;
+use serde_json::Value;
+
+/// The changes from one snapshot to a later one.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct SnapshotDelta {
+    /// The time of the older snapshot.
+    pub from: SimTime,
+    /// The time of the newer snapshot.
+    pub time: SimTime,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub nodes: Vec<NodeDelta>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<LinkDelta>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub links_removed: Vec<LinkId>,
+    /// Events logged since the older snapshot that the newer one still
+    /// holds, oldest first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<LogSnap>,
+    /// The newer snapshot's counters, if any differ.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<MetricsSnapshot>,
+    /// The SLO violation in effect, if that changed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slo_violation: Option<Option<SloWindow>>,
+    /// The newer snapshot's markers, which are never carried over.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stepped: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breakpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished: Option<String>,
+}
+
+/// The fields of one node that changed, each `None` if it did not. A node
+/// missing from the older snapshot has every field set.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct NodeDelta {
+    pub id: NodeId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<NodeStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timers: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byzantine: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gray_failure: Option<Option<GrayFailure>>,
+    /// Custom KVs that were set or changed, and `None` for those removed.
+    #[serde(skip_serializing_if = "IndexMap::is_empty")]
+    pub custom: IndexMap<String, Option<Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub evicted_kvs: Option<u64>,
+    /// The latest sample of each metric that has a new one.
+    #[serde(skip_serializing_if = "IndexMap::is_empty")]
+    pub metrics: IndexMap<String, MetricSample>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<Option<StoreSnap>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub traffic: Option<NodeTraffic>,
+}
+
+/// A link that was added or changed. A new link has every field set.
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LinkDelta {
+    pub id: LinkId,
+    pub src: NodeId,
+    pub dst: NodeId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_partitioned: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delivered: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dropped: Option<u64>,
+}
+
+impl Snapshot {
+    /// Returns what changed from `old` to `new`.
+    pub fn diff(old: &Snapshot, new: &Snapshot) -> SnapshotDelta {
+        let nodes = new
+            .nodes
+            .iter()
+            .filter_map(|node| {
+                let delta = match old.nodes.iter().find(|n| n.id == node.id) {
+                    Some(before) => NodeDelta::between(before, node),
+                    None => NodeDelta::added(node),
+                };
+                (!delta.is_empty()).then_some(delta)
+            })
+            .collect();
+        let links = new
+            .links
+            .iter()
+            .filter_map(|link| {
+                let delta = match old.links.iter().find(|l| l.id == link.id) {
+                    Some(before) => LinkDelta::between(before, link),
+                    None => LinkDelta::added(link),
+                };
+                (!delta.is_empty()).then_some(delta)
+            })
+            .collect();
+        let links_removed =
+            old.links.iter().filter(|l| !new.links.iter().any(|n| n.id == l.id)).map(|l| l.id).collect();
+        // Events are numbered in the order they were logged, so the new ones
+        // are those numbered past the older snapshot's newest
+        let newest = old.recent_events.iter().map(|e| e.seq).max();
+        let events = new.recent_events.iter().filter(|e| newest.map_or(true, |seq| e.seq > seq)).cloned().collect();
+        SnapshotDelta {
+            from: old.time,
+            time: new.time,
+            nodes,
+            links,
+            links_removed,
+            events,
+            metrics: (old.metrics != new.metrics).then(|| new.metrics.clone()),
+            slo_violation: (old.slo_violation != new.slo_violation).then_some(new.slo_violation),
+            stepped: new.stepped,
+            breakpoint: new.breakpoint.clone(),
+            finished: new.finished.clone(),
+        }
+    }
+}
+
+impl SnapshotDelta {
+    /// Whether nothing but the time changed.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+            && self.links.is_empty()
+            && self.links_removed.is_empty()
+            && self.events.is_empty()
+            && self.metrics.is_none()
+            && self.slo_violation.is_none()
+            && self.stepped.is_none()
+            && self.breakpoint.is_none()
+            && self.finished.is_none()
+    }
+}
+
+/// `Some(new)` if it differs from `old`.
+fn changed<T: PartialEq + Clone>(old: &T, new: &T) -> Option<T> {
+    (old != new).then(|| new.clone())
+}
+
+impl NodeDelta {
+    fn between(old: &NodeSnap, new: &NodeSnap) -> Self {
+        let mut custom: IndexMap<String, Option<Value>> = new
+            .custom
+            .iter()
+            .filter(|(key, val)| old.custom.get(*key) != Some(val))
+            .map(|(key, val)| (key.clone(), Some(val.clone())))
+            .collect();
+        custom.extend(old.custom.keys().filter(|key| !new.custom.contains_key(*key)).map(|key| (key.clone(), None)));
+        let metrics = new
+            .metrics
+            .iter()
+            .filter_map(|(key, samples)| {
+                let latest = samples.last()?;
+                let before = old.metrics.get(key).and_then(|s| s.last());
+                (before != Some(latest)).then(|| (key.clone(), *latest))
+            })
+            .collect();
+        Self {
+            id: new.id,
+            status: changed(&old.status, &new.status),
+            timers: changed(&old.timers, &new.timers),
+            byzantine: changed(&old.byzantine, &new.byzantine),
+            gray_failure: changed(&old.gray_failure, &new.gray_failure),
+            custom,
+            evicted_kvs: changed(&old.evicted_kvs, &new.evicted_kvs),
+            metrics,
+            store: changed(&old.store, &new.store),
+            traffic: changed(&old.traffic, &new.traffic),
+        }
+    }
+
+    fn added(node: &NodeSnap) -> Self {
+        Self {
+            id: node.id,
+            status: Some(node.status),
+            timers: Some(node.timers),
+            byzantine: Some(node.byzantine),
+            gray_failure: Some(node.gray_failure.clone()),
+            custom: node.custom.iter().map(|(key, val)| (key.clone(), Some(val.clone()))).collect(),
+            evicted_kvs: Some(node.evicted_kvs),
+            metrics: node
+                .metrics
+                .iter()
+                .filter_map(|(key, samples)| Some((key.clone(), *samples.last()?)))
+                .collect(),
+            store: Some(node.store.clone()),
+            traffic: Some(node.traffic),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.status.is_none()
+            && self.timers.is_none()
+            && self.byzantine.is_none()
+            && self.gray_failure.is_none()
+            && self.custom.is_empty()
+            && self.evicted_kvs.is_none()
+            && self.metrics.is_empty()
+            && self.store.is_none()
+            && self.traffic.is_none()
+    }
+}
+
+impl LinkDelta {
+    fn between(old: &LinkSnap, new: &LinkSnap) -> Self {
+        Self {
+            id: new.id,
+            src: new.src,
+            dst: new.dst,
+            is_partitioned: changed(&old.is_partitioned, &new.is_partitioned),
+            delivered: changed(&old.delivered, &new.delivered),
+            dropped: changed(&old.dropped, &new.dropped),
+        }
+    }
+
+    fn added(link: &LinkSnap) -> Self {
+        Self {
+            id: link.id,
+            src: link.src,
+            dst: link.dst,
+            is_partitioned: Some(link.is_partitioned),
+            delivered: Some(link.delivered),
+            dropped: Some(link.dropped),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.is_partitioned.is_none() && self.delivered.is_none() && self.dropped.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn node(id: NodeId) -> NodeSnap {
+        NodeSnap {
+            id,
+            status: NodeStatus::Up,
+            timers: 1,
+            byzantine: false,
+            gray_failure: None,
+            custom: IndexMap::from([("role".to_string(), json!("follower"))]),
+            evicted_kvs: 0,
+            metrics: IndexMap::new(),
+            store: None,
+            traffic: NodeTraffic::default(),
+        }
+    }
+
+    fn link(id: LinkId, src: NodeId, dst: NodeId) -> LinkSnap {
+        LinkSnap { id, src, dst, is_partitioned: false, delivered: 0, dropped: 0 }
+    }
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            time: 1_000,
+            nodes: vec![node(0), node(1)],
+            links: vec![link(0, 0, 1), link(1, 1, 0)],
+            recent_events: Vec::new(),
+            metrics: MetricsSnapshot::default(),
+            slo_violation: None,
+            stepped: None,
+            breakpoint: None,
+            finished: None,
+        }
+    }
+
+    fn event(seq: u64) -> LogSnap {
+        LogSnap {
+            seq,
+            event_id: EventId(seq / 2),
+            time: 1_000,
+            event_type: "TEST".into(),
+            details: format!("event {}", seq),
+            node_id: None,
+            severity: EventSeverity::Info,
+        }
+    }
+
+    #[test]
+    fn test_diff_of_unchanged_snapshots_is_empty() {
+        let old = snapshot();
+        let mut new = old.clone();
+        new.time = 2_000;
+        let delta = Snapshot::diff(&old, &new);
+        assert!(delta.is_empty(), "{:?}", delta);
+        assert_eq!((delta.from, delta.time), (1_000, 2_000));
+    }
+
+    #[test]
+    fn test_diff_reports_node_status_flips() {
+        let old = snapshot();
+        let mut new = old.clone();
+        new.nodes[1].status = NodeStatus::Down;
+        let delta = Snapshot::diff(&old, &new);
+        assert_eq!(delta.nodes, [NodeDelta { id: 1, status: Some(NodeStatus::Down), ..NodeDelta::default() }]);
+        assert!(delta.links.is_empty() && delta.events.is_empty() && delta.metrics.is_none());
+
+        // And back
+        let delta = Snapshot::diff(&new, &old);
+        assert_eq!(delta.nodes[0].status, Some(NodeStatus::Up));
+    }
+
+    #[test]
+    fn test_diff_reports_link_partition_flips() {
+        let old = snapshot();
+        let mut new = old.clone();
+        new.links[0].is_partitioned = true;
+        new.links[1].dropped = 3;
+        let delta = Snapshot::diff(&old, &new);
+        assert_eq!(
+            delta.links,
+            [
+                LinkDelta { id: 0, src: 0, dst: 1, is_partitioned: Some(true), ..LinkDelta::default() },
+                LinkDelta { id: 1, src: 1, dst: 0, dropped: Some(3), ..LinkDelta::default() },
+            ]
+        );
+        assert!(delta.nodes.is_empty());
+
+        // A link removed, and another added in its place
+        new.links.remove(1);
+        new.links.push(link(7, 1, 0));
+        let delta = Snapshot::diff(&old, &new);
+        assert_eq!(delta.links_removed, [1]);
+        assert_eq!(delta.links[1], LinkDelta::added(&new.links[1]));
+    }
+
+    #[test]
+    fn test_diff_reports_custom_kv_changes() {
+        let mut old = snapshot();
+        old.nodes[0].custom.insert("term".into(), json!(1));
+        let mut new = old.clone();
+        new.nodes[0].custom.insert("role".into(), json!("leader"));
+        new.nodes[0].custom.shift_remove("term");
+        new.nodes[0].custom.insert("commit".into(), json!(4));
+        new.nodes[1].custom.insert("role".into(), json!("follower"));
+        let delta = Snapshot::diff(&old, &new);
+        assert_eq!(delta.nodes.len(), 1, "{:?}", delta.nodes);
+        assert_eq!(
+            delta.nodes[0].custom,
+            IndexMap::from([
+                ("role".to_string(), Some(json!("leader"))),
+                ("commit".to_string(), Some(json!(4))),
+                ("term".to_string(), None),
+            ])
+        );
+        assert_eq!(
+            serde_json::to_value(&delta.nodes[0]).unwrap(),
+            json!({ "id": 0, "custom": { "role": "leader", "commit": 4, "term": null } })
+        );
+    }
+
+    #[test]
+    fn test_diff_lists_only_new_events() {
+        let mut old = snapshot();
+        old.recent_events = vec![event(3), event(4), event(5)];
+        let mut new = old.clone();
+        // The buffer evicted the oldest two and took three more
+        new.recent_events = vec![event(5), event(6), event(7), event(8)];
+        let delta = Snapshot::diff(&old, &new);
+        let seqs: Vec<u64> = delta.events.iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, [6, 7, 8]);
+
+        // From an empty buffer, every event is new
+        let delta = Snapshot::diff(&snapshot(), &new);
+        assert_eq!(delta.events.len(), 4);
+    }
+
+    #[test]
+    fn test_diff_carries_changed_metrics_and_markers() {
+        let old = snapshot();
+        let mut new = old.clone();
+        new.metrics.messages_sent = 5;
+        new.finished = Some("stopped".into());
+        new.nodes[0].metrics.insert("lag".into(), vec![MetricSample { time: 900, value: 2.0 }]);
+        let delta = Snapshot::diff(&old, &new);
+        assert_eq!(delta.metrics.as_ref().map(|m| m.messages_sent), Some(5));
+        assert_eq!(delta.finished.as_deref(), Some("stopped"));
+        assert_eq!(delta.nodes[0].metrics["lag"], MetricSample { time: 900, value: 2.0 });
+        assert!(!delta.is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+//! # ftsim-engine::telemetry::events_out
+//!
+//! An optional file with every event the bus logs, one JSON object per line,
+//! for runs whose events are read after the fact rather than watched. Unlike
+//! the spill, which only keeps what the recent-events ring evicts within a
+//! size budget, this writes each event once, in order, however long the run.
+//! Each line is a `LogSnap`, whose `seq` counts the events logged before
+//! it, so a gap in `seq` marks dropped events.
+//!
+//! Writing happens on a thread of its own, fed through a bounded channel, so
+//! a slow disk never stalls the simulation: when the channel is full the
+//! event is dropped and counted instead. Given a `RotationPolicy`, the
+//! writer splits the file into size-rotated segments as it goes, placing
+//! each event by its sim time.
+
+use super::snapshot::LogSnap;
+use crate::segments::{self, Manifest, Output, RotationPolicy, SegmentWriter};
+use crossbeam_channel::{Sender, TrySendError};
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    thread::JoinHandle,
+};
+
+/// The number of events that can wait for the writer before new ones are
+/// dropped.
+pub const EVENTS_OUT_CAPACITY: usize = 8192;
+
+/// What the simulation thread hands the writer. `Finish` asks it to flush
+/// and exit.
+enum Command {
+    Event(LogSnap),
+    Finish,
+}
+
+/// Where the writer puts events.
+enum Sink {
+    File(Output),
+    Segments(SegmentWriter),
+}
+
+impl Sink {
+    fn write(&mut self, event: &LogSnap) -> io::Result<()> {
+        match self {
+            Sink::File(out) => {
+                serde_json::to_writer(&mut *out, event)?;
+                out.write_all(b"\n")
+            }
+            Sink::Segments(out) => out.append(event.time, event),
+        }
+    }
+
+    fn finish(self) -> io::Result<Option<Manifest>> {
+        match self {
+            Sink::File(out) => out.finish().map(|()| None),
+            Sink::Segments(out) => out.finish().map(Some),
+        }
+    }
+}
+
+/// Streams logged events to a JSONL file from a writer thread.
+pub struct EventsOut {
+    path: PathBuf,
+    tx: Sender<Command>,
+    dropped: AtomicU64,
+    /// The writer, which counts what it wrote but not what was dropped.
+    writer: Mutex<Option<JoinHandle<io::Result<EventsOutSummary>>>>,
+}
+
+/// What was written once the writer finished.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventsOutSummary {
+    pub written: u64,
+    pub dropped: u64,
+    /// The segments written, if the file was rotated.
+    pub manifest: Option<Manifest>,
+}
+
+impl EventsOut {
+    /// Creates or truncates the file at `path`, or the first segment of it
+    /// if `rotation` is given, and starts its writer.
+    pub fn create(path: impl Into<PathBuf>, rotation: Option<RotationPolicy>) -> io::Result<Self> {
+        Self::with_capacity(path, rotation, EVENTS_OUT_CAPACITY)
+    }
+
+    /// Like `create`, with room for `capacity` events between the
+    /// simulation and the writer.
+    pub fn with_capacity(
+        path: impl Into<PathBuf>,
+        rotation: Option<RotationPolicy>,
+        capacity: usize,
+    ) -> io::Result<Self> {
+        let path = path.into();
+        let mut sink = match rotation {
+            Some(policy) => Sink::Segments(SegmentWriter::create(&path, policy)?),
+            None => Sink::File(segments::create_output(&path)?),
+        };
+        let (tx, rx) = crossbeam_channel::bounded(capacity);
+        let writer = std::thread::Builder::new()
+            .name("ftsim-events-out".into())
+            .spawn(move || {
+                let mut written = 0;
+                for command in rx {
+                    match command {
+                        Command::Event(event) => {
+                            sink.write(&event)?;
+                            written += 1;
+                        }
+                        Command::Finish => break,
+                    }
+                }
+                Ok(EventsOutSummary { written, dropped: 0, manifest: sink.finish()? })
+            })?;
+        Ok(Self {
+            path,
+            tx,
+            dropped: AtomicU64::new(0),
+            writer: Mutex::new(Some(writer)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Queues an event for writing without blocking. The event is dropped
+    /// and counted if the writer is behind, has failed or has finished.
+    pub fn send(&self, event: &LogSnap) {
+        match self.tx.try_send(Command::Event(event.clone())) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Returns the events dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Writes out the queued events, flushes the file and stops the writer.
+    /// Events sent afterwards are dropped. Returns `None` if the writer
+    /// already finished.
+    pub fn finish(&self) -> Option<io::Result<EventsOutSummary>> {
+        let writer = self.writer.lock().unwrap_or_else(|p| p.into_inner()).take()?;
+        // A writer that failed has hung up, which ends it just as well
+        let _ = self.tx.send(Command::Finish);
+        let written = match writer.join() {
+            Ok(written) => written,
+            Err(_) => Err(io::Error::other("events-out writer panicked")),
+        };
+        Some(written.map(|summary| EventsOutSummary { dropped: self.dropped(), ..summary }))
+    }
+}
+
+impl Drop for EventsOut {
+    fn drop(&mut self) {
+        if let Some(Err(e)) = self.finish() {
+            tracing::warn!(error = %e, path = %self.path.display(), "Events file could not be written");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{EventId, EventSeverity};
+    use std::io::BufRead;
+
+    fn event(id: u64) -> LogSnap {
+        LogSnap {
+            seq: id,
+            event_id: EventId(id),
+            time: u128::from(id) * 1_000,
+            event_type: "TEST".into(),
+            details: format!("event {}", id),
+            node_id: Some(0),
+            severity: EventSeverity::Info,
+        }
+    }
+
+    #[test]
+    fn test_events_out_writes_every_event_in_order() {
+        let path = std::env::temp_dir().join(format!("ftsim-events-out-{}.jsonl", std::process::id()));
+        let out = EventsOut::create(&path, None).unwrap();
+        for id in 0..500 {
+            out.send(&event(id));
+        }
+        assert_eq!(out.finish().unwrap().unwrap(), EventsOutSummary { written: 500, dropped: 0, manifest: None });
+        // Events after the writer finished are counted, not written
+        out.send(&event(500));
+        assert_eq!(out.dropped(), 1);
+        assert!(out.finish().is_none());
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        let seqs: Vec<u64> = lines.iter().map(|line| line["seq"].as_u64().unwrap()).collect();
+        assert_eq!(seqs, (0..500).collect::<Vec<_>>());
+        let event: LogSnap = serde_json::from_value(lines[7].clone()).unwrap();
+        assert_eq!((event.event_id, event.details.as_str()), (EventId(7), "event 7"));
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_events_out_rotates_while_writing() {
+        let dir = std::env::temp_dir().join(format!("ftsim-events-out-rotated-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.jsonl");
+        let out = EventsOut::create(&path, Some(RotationPolicy::new(4 * 1024))).unwrap();
+        for id in 0..500 {
+            out.send(&event(id));
+        }
+        let manifest = out.finish().unwrap().unwrap().manifest.unwrap();
+        assert!(manifest.segments.len() > 5, "{} segments", manifest.segments.len());
+        assert_eq!(manifest.records(), 500);
+
+        let lines = segments::open_input(&path, None).unwrap().lines();
+        let seqs: Vec<u64> = lines.map(|line| serde_json::from_str::<LogSnap>(&line.unwrap()).unwrap().seq).collect();
+        assert_eq!(seqs, (0..500).collect::<Vec<_>>());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -2,133 +2,1018 @@
 //!
 //! The observability subsystem. It is responsible for collecting and
 //! dispatching logs, metrics, and state snapshots.
+//!
+//! The bus is written by the simulation thread on every event and read
+//! concurrently by snapshot consumers and the tracing layer, so it has no
+//! single lock: the clock and the run counters are atomics, and each node's
+//! KVs and metrics have their own lock. Logged events are queued without a
+//! lock and moved into the recent-events buffer in batches, by the
+//! simulation thread once a buffer's worth has built up and by readers
+//! before they copy the buffer. A reader therefore never holds up more of
+//! the simulation than the one piece it is copying.
+//!
+//! The buffer keeps warnings and faults in a ring of their own, so the
+//! chatter of deliveries and timers never evicts them. Events either ring
+//! evicts are lost unless an `EventSpill` is attached, which
+//! keeps them on disk within a size budget for post-run review. A bus built
+//! `with_events_out` also streams every event, evicted or not, to a JSONL
+//! file from a writer thread of its own.
+//!
+//! Snapshots that differ from the last one sent only in their time are not
+//! sent, except for a heartbeat every `SNAPSHOT_HEARTBEAT` of wall time, so
+//! a paused run still shows it is alive. A consumer that wants to know what
+//! changed, not just the new state, can attach a channel for the
+//! `SnapshotDelta` between each pair sent. A bus that neither skips nor
+//! feeds such a channel never compares snapshots at all.
+//!
+//! The bus also feeds the run's conditions: every KV, metric, status and
+//! invariant result it records is forwarded to the `ConditionEngine`, which
+//! drops those no condition reads. Client request latencies likewise feed
+//! the scenario's SLO, if it has one, and the state hashes anti-entropy
+//! protocols publish feed the convergence tracker.
 
-use crate::{prelude::*, world::World};
-use crossbeam_channel::Sender;
+use crate::{
+    conditions::{Condition, ConditionEngine, ConditionOutcome, ConditionStatus, Signal, SignalValue},
+    convergence::{ConvergenceReport, ConvergenceTracker, REPLICA_HASH_KEY},
+    histogram::{AtomicHistogram, Histogram},
+    prelude::*,
+    slo::{SloReport, SloTracker},
+    world::World,
+};
+use sink::TelemetrySink;
+use crossbeam_channel::{Receiver, Sender};
 use indexmap::IndexMap;
 use serde_json::Value;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
 use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// The number of store KV keys listed per node when key listing is enabled.
+const SNAPSHOT_STORE_KEYS: usize = 16;
 
+/// What `TracingContext::node` holds while no protocol is running. No
+/// `NodeId` converts to it.
+const NO_NODE: u64 = u64::MAX;
+
+/// How long, in wall time, the bus goes without sending a snapshot while
+/// nothing changes. The snapshot it then sends anyway tells consumers the run
+/// is alive and where its clock is, however slowly that clock moves.
+pub const SNAPSHOT_HEARTBEAT: Duration = Duration::from_secs(1);
+
+/// Set once a global `metrics` recorder is installed. Until then the bus
+/// skips its per-message counters rather than build labels for a recorder
+/// that drops them.
+static METRICS_RECORDER: AtomicBool = AtomicBool::new(false);
+
+/// Tells every bus a global `metrics` recorder is installed, so sends and
+/// deliveries are counted through it too. `prometheus::install` calls this;
+/// an embedder that installs a recorder of its own should as well.
+pub fn enable_message_metrics() {
+    METRICS_RECORDER.store(true, Ordering::Release);
+}
+
+pub mod delta;
+pub mod events_out;
+#[cfg(feature = "metrics_prom")]
+pub mod prometheus;
+pub mod sink;
 pub mod snapshot;
+pub mod spill;
+#[cfg(feature = "tracing-layer")]
 pub mod tracing_layer;
 
+#[cfg(test)]
+mod backpressure;
+#[cfg(test)]
+mod contention;
+
 /// A central bus for telemetry data.
 /// It uses channels to communicate with external consumers (like the TUI)
 /// and a shared state for contextual logging.
 #[derive(Clone)]
 pub struct TelemetryBus {
     snapshot_tx: Sender<Snapshot>,
+    // The last snapshot sent, which the next one is compared against, and
+    // when it was sent.
+    last_sent: Arc<Mutex<Option<(Snapshot, Instant)>>>,
+    // Whether snapshots that changed only in their time are skipped.
+    skip_unchanged: bool,
+    // Where the changes between sent snapshots go, if anywhere.
+    delta_tx: Arc<Mutex<Option<Sender<delta::SnapshotDelta>>>>,
+    // Consumers attached from outside the engine.
+    sinks: Arc<Mutex<Vec<Box<dyn TelemetrySink>>>>,
+    // Set once a sink is attached, so runs without sinks never lock `sinks`.
+    has_sinks: Arc<AtomicBool>,
+    // The conditions checked against what the bus records.
+    conditions: Arc<Mutex<ConditionEngine>>,
+    // Set once a condition is added, so runs without conditions never lock
+    // `conditions`.
+    has_conditions: Arc<AtomicBool>,
+    // The latency objective client responses are judged against.
+    slo: Arc<Mutex<Option<SloTracker>>>,
+    // Set once an SLO is armed, so runs without one never lock `slo`.
+    has_slo: Arc<AtomicBool>,
+    // How far the nodes agree on the state hashes they publish.
+    convergence: Arc<Mutex<ConvergenceTracker>>,
+    // Where every logged event is written, if anywhere.
+    events_out: Option<Arc<events_out::EventsOut>>,
     // Shared state for the tracing layer to access simulation context.
-    context: Arc<Mutex<TracingContext>>,
+    context: Arc<TracingContext>,
 }
 
-#[derive(Default)]
-struct TracingContext {
-    time: SimTime,
-    event_id: EventId,
+pub(crate) struct TracingContext {
+    // Sim time of the current event, saturated to u64
+    time: AtomicU64,
+    event_id: AtomicU64,
+    // The node whose protocol is running, or `NO_NODE`
+    node: AtomicU64,
     // Per-node custom KVs from protocols
-    node_kvs: Vec<IndexMap<String, Value>>,
-    // Recent events for visualization (keep last 100)
-    recent_events: VecDeque<snapshot::LogSnap>,
+    node_kvs: Vec<Mutex<NodeKvs>>,
+    // Maximum number of unpinned KVs retained per node
+    max_node_kvs: usize,
+    // Per-node numeric metrics from protocols
+    node_metrics: Vec<Mutex<NodeMetrics>>,
+    // Maximum number of samples retained per metric
+    max_metric_samples: usize,
+    // Whether snapshots list each node's store keys
+    include_store_keys: bool,
+    // Whether snapshots include fault state hidden from the node
+    include_fault_details: bool,
+    // Recent events for visualization
+    recent_events: Mutex<RecentEvents>,
+    // Events logged since `recent_events` was last brought up to date
+    queued_events: (Sender<snapshot::LogSnap>, Receiver<snapshot::LogSnap>),
+    // How many events may queue up before the logging thread moves them
+    // over itself; the capacity of each of the buffer's rings
+    event_buffer: usize,
+    // The number the next logged event gets
+    next_event_seq: AtomicU64,
+    // Where events evicted from `recent_events` go, if anywhere
+    spill: Mutex<Option<spill::EventSpill>>,
     // Running metrics
-    metrics: snapshot::MetricsSnapshot,
+    metrics: Counters,
+    // The latency distributions behind the percentiles in `MetricsSnapshot`
+    latencies: Latencies,
+    // Message counts per node, by node id
+    node_traffic: Vec<TrafficCounters>,
+    // Delivered and dropped counts per link, by link id. A topology links
+    // each ordered pair of nodes at most once, so its ids stay below
+    // n * (n - 1); a link a test adds later under a new id goes uncounted.
+    link_traffic: Vec<LinkTrafficCounters>,
+}
+
+/// A node's message counts, one atomic per `NodeTraffic` field.
+#[derive(Default)]
+struct TrafficCounters {
+    sent: AtomicU64,
+    received: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl TrafficCounters {
+    fn load(&self) -> snapshot::NodeTraffic {
+        let get = |counter: &AtomicU64| counter.load(Ordering::Acquire);
+        // Receipts and drops before sends, as in `Counters::load`
+        let (received, dropped) = (get(&self.received), get(&self.dropped));
+        snapshot::NodeTraffic { sent: get(&self.sent), received, dropped }
+    }
+}
+
+/// A link's message counts.
+#[derive(Default)]
+struct LinkTrafficCounters {
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl LinkTrafficCounters {
+    /// Returns the delivered and dropped counts.
+    fn load(&self) -> (u64, u64) {
+        (self.delivered.load(Ordering::Acquire), self.dropped.load(Ordering::Acquire))
+    }
+}
+
+/// The latency histograms the bus keeps next to its counters. The engine's
+/// own are recorded on every delivery or response, so they take no lock.
+#[derive(Default)]
+struct Latencies {
+    delivery: AtomicHistogram,
+    client_request: AtomicHistogram,
+    /// The histograms protocols record, by key, in the order first recorded.
+    protocol: Mutex<IndexMap<&'static str, Histogram>>,
+}
+
+impl TracingContext {
+    /// Returns the sim time of the event being executed.
+    pub(crate) fn time(&self) -> SimTime {
+        SimTime::from(self.time.load(Ordering::Relaxed))
+    }
+
+    /// Returns the id of the event being executed.
+    pub(crate) fn event_id(&self) -> EventId {
+        EventId(self.event_id.load(Ordering::Relaxed))
+    }
+
+    /// Returns the node whose protocol is running, if any.
+    #[cfg(feature = "tracing-layer")]
+    pub(crate) fn node(&self) -> Option<NodeId> {
+        NodeId::try_from(self.node.load(Ordering::Relaxed)).ok()
+    }
+
+    /// Locks the recent-events buffer, bringing it up to date with the
+    /// queued events first.
+    fn recent_events(&self) -> MutexGuard<'_, RecentEvents> {
+        let mut recent = lock(&self.recent_events);
+        self.move_queued_events(&mut recent);
+        recent
+    }
+
+    /// Moves the queued events into `recent`, in the order they were
+    /// logged, and the events that evicts into the spill, if any.
+    fn move_queued_events(&self, recent: &mut RecentEvents) {
+        let mut spill = None;
+        for event in self.queued_events.1.try_iter() {
+            let Some(evicted) = recent.push(event) else {
+                continue;
+            };
+            let spill = spill.get_or_insert_with(|| lock(&self.spill));
+            if let Some(Err(e)) = spill.as_mut().map(|s| s.append(&evicted)) {
+                tracing::warn!(error = %e, "Event spill failed; evicted events are no longer kept");
+                **spill = None;
+            }
+        }
+    }
+
+    /// Returns the run counters, with the percentiles of the latency
+    /// histograms as recorded after them.
+    fn load_metrics(&self) -> snapshot::MetricsSnapshot {
+        let mut metrics = self.metrics.load();
+        let latencies = &self.latencies;
+        metrics.delivery_latency = snapshot::LatencyPercentiles::of(&latencies.delivery.load());
+        metrics.client_request_latency = snapshot::LatencyPercentiles::of(&latencies.client_request.load());
+        metrics.histograms = lock(&latencies.protocol)
+            .iter()
+            .map(|(key, histogram)| (key.to_string(), snapshot::LatencyPercentiles::of(histogram)))
+            .collect();
+        metrics
+    }
+}
+
+/// The recent-events buffer: two rings of the same capacity, one for
+/// warnings and faults and one for everything else, so chatter only ever
+/// evicts chatter. It merges the rings back into one list by the events'
+/// numbers.
+struct RecentEvents {
+    capacity: usize,
+    chatter: VecDeque<snapshot::LogSnap>,
+    retained: VecDeque<snapshot::LogSnap>,
+}
+
+impl RecentEvents {
+    fn new(capacity: usize) -> Self {
+        Self { capacity, chatter: VecDeque::new(), retained: VecDeque::new() }
+    }
+
+    /// Adds an event to its ring. Returns the event it evicted, if any.
+    fn push(&mut self, event: snapshot::LogSnap) -> Option<snapshot::LogSnap> {
+        let ring = if event.severity.is_retained() { &mut self.retained } else { &mut self.chatter };
+        let evicted = if ring.len() >= self.capacity { ring.pop_front() } else { None };
+        ring.push_back(event);
+        evicted
+    }
+
+    /// Returns both rings' events, oldest first.
+    fn to_vec(&self) -> Vec<snapshot::LogSnap> {
+        let mut events = Vec::with_capacity(self.chatter.len() + self.retained.len());
+        let (mut chatter, mut retained) = (self.chatter.iter().peekable(), self.retained.iter().peekable());
+        loop {
+            let next = match (chatter.peek(), retained.peek()) {
+                (Some(c), Some(r)) if c.seq < r.seq => chatter.next(),
+                (Some(_), Some(_)) | (None, Some(_)) => retained.next(),
+                (Some(_), None) => chatter.next(),
+                (None, None) => break,
+            };
+            events.extend(next.cloned());
+        }
+        events
+    }
+}
+
+/// Locks `mutex`, recovering the data if a thread panicked while holding it.
+/// Every critical section on the bus leaves its data consistent, so a
+/// poisoned lock only means some other thread failed.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The run counters behind `MetricsSnapshot`, one atomic per field.
+///
+/// Increments release and loads acquire, and `load` reads each counter
+/// before the ones the simulation bumps ahead of it (a delivery after its
+/// send, a response after its request), so a concurrent reader never sees
+/// an effect counted without its cause.
+#[derive(Default)]
+struct Counters {
+    messages_sent: AtomicU64,
+    messages_delivered: AtomicU64,
+    timers_fired: AtomicU64,
+    faults_injected: AtomicU64,
+    store_time_ns: AtomicU64,
+    delay_clamped: AtomicU64,
+    client_requests: AtomicU64,
+    client_responses: AtomicU64,
+    client_request_latency_ns: AtomicU64,
+    gray_failure_ignored: AtomicU64,
+    simultaneous_restarts: AtomicU64,
+}
+
+impl Counters {
+    fn add(counter: &AtomicU64, n: u64) {
+        let _ = counter.fetch_update(Ordering::Release, Ordering::Relaxed, |v| Some(v.saturating_add(n)));
+    }
+
+    fn load(&self) -> snapshot::MetricsSnapshot {
+        let get = |counter: &AtomicU64| counter.load(Ordering::Acquire);
+        let client_responses = get(&self.client_responses);
+        let client_request_latency_ns = get(&self.client_request_latency_ns);
+        let messages_delivered = get(&self.messages_delivered);
+        snapshot::MetricsSnapshot {
+            messages_sent: get(&self.messages_sent),
+            messages_delivered,
+            timers_fired: get(&self.timers_fired),
+            faults_injected: get(&self.faults_injected),
+            store_time_ns: get(&self.store_time_ns),
+            delay_clamped: get(&self.delay_clamped),
+            client_requests: get(&self.client_requests),
+            client_responses,
+            client_request_latency_ns,
+            gray_failure_ignored: get(&self.gray_failure_ignored),
+            simultaneous_restarts: get(&self.simultaneous_restarts),
+            // Filled in from the histograms by `TracingContext::load_metrics`
+            ..Default::default()
+        }
+    }
+}
+
+/// The custom KVs published by a single node.
+///
+/// Unpinned entries are kept in update order (most recently updated last), so
+/// eviction always removes the least recently updated key regardless of how
+/// the keys hash. Pinned entries are never evicted and do not count towards
+/// the limit.
+#[derive(Default, Clone)]
+struct NodeKvs {
+    pinned: IndexMap<String, Value>,
+    entries: IndexMap<String, Value>,
+    evicted: u64,
+}
+
+impl NodeKvs {
+    fn put(&mut self, key: String, val: Value, max_entries: usize) {
+        if let Some(slot) = self.pinned.get_mut(&key) {
+            *slot = val;
+            return;
+        }
+        // Re-inserting moves the key to the back of the update order.
+        self.entries.shift_remove(&key);
+        self.entries.insert(key, val);
+        while self.entries.len() > max_entries {
+            self.entries.shift_remove_index(0);
+            self.evicted += 1;
+        }
+    }
+
+    fn put_pinned(&mut self, key: String, val: Value) {
+        self.entries.shift_remove(&key);
+        self.pinned.insert(key, val);
+    }
+
+    /// Returns all entries, pinned keys first, then unpinned keys in update order.
+    fn merged(&self) -> IndexMap<String, Value> {
+        self.pinned
+            .iter()
+            .chain(self.entries.iter())
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+}
+
+/// The numeric metrics published by a single node, each a series of its
+/// most recent samples, oldest first. Keys stay in first-published order.
+#[derive(Default, Clone)]
+struct NodeMetrics {
+    series: IndexMap<String, VecDeque<snapshot::MetricSample>>,
+}
+
+impl NodeMetrics {
+    /// Appends a sample, dropping the oldest beyond `max_samples`. A second
+    /// sample at the same time replaces the first, so a series holds one
+    /// value per instant.
+    fn record(&mut self, key: String, sample: snapshot::MetricSample, max_samples: usize) {
+        let series = self.series.entry(key).or_default();
+        match series.back_mut() {
+            Some(last) if last.time == sample.time => *last = sample,
+            _ => series.push_back(sample),
+        }
+        while series.len() > max_samples {
+            series.pop_front();
+        }
+    }
+
+    fn to_map(&self) -> IndexMap<String, Vec<snapshot::MetricSample>> {
+        self.series
+            .iter()
+            .map(|(k, samples)| (k.clone(), samples.iter().copied().collect()))
+            .collect()
+    }
 }
 
 impl TelemetryBus {
-    pub fn new(snapshot_tx: Sender<Snapshot>, num_nodes: usize) -> Self {
+    pub fn new(snapshot_tx: Sender<Snapshot>, num_nodes: usize, spec: &TelemetrySpec) -> Self {
         Self {
             snapshot_tx,
-            context: Arc::new(Mutex::new(TracingContext {
-                time: 0,
-                event_id: 0,
-                node_kvs: vec![IndexMap::new(); num_nodes],
-                recent_events: VecDeque::with_capacity(100),
-                metrics: snapshot::MetricsSnapshot::default(),
-            })),
+            last_sent: Arc::new(Mutex::new(None)),
+            skip_unchanged: !spec.send_unchanged_snapshots,
+            delta_tx: Arc::new(Mutex::new(None)),
+            sinks: Arc::new(Mutex::new(Vec::new())),
+            has_sinks: Arc::new(AtomicBool::new(false)),
+            conditions: Arc::new(Mutex::new(ConditionEngine::new())),
+            has_conditions: Arc::new(AtomicBool::new(false)),
+            slo: Arc::new(Mutex::new(None)),
+            has_slo: Arc::new(AtomicBool::new(false)),
+            convergence: Arc::new(Mutex::new(ConvergenceTracker::new())),
+            events_out: None,
+            context: Arc::new(TracingContext {
+                time: AtomicU64::new(0),
+                event_id: AtomicU64::new(0),
+                node: AtomicU64::new(NO_NODE),
+                node_kvs: (0..num_nodes).map(|_| Mutex::default()).collect(),
+                max_node_kvs: spec.max_node_kvs,
+                node_metrics: (0..num_nodes).map(|_| Mutex::default()).collect(),
+                max_metric_samples: spec.max_metric_samples.max(1),
+                include_store_keys: spec.include_store_keys,
+                include_fault_details: spec.include_fault_details,
+                recent_events: Mutex::new(RecentEvents::new(spec.event_buffer.max(1))),
+                queued_events: crossbeam_channel::unbounded(),
+                event_buffer: spec.event_buffer.max(1),
+                next_event_seq: AtomicU64::new(0),
+                spill: Mutex::new(None),
+                metrics: Counters::default(),
+                latencies: Latencies::default(),
+                node_traffic: (0..num_nodes).map(|_| TrafficCounters::default()).collect(),
+                link_traffic: (0..num_nodes * num_nodes.saturating_sub(1))
+                    .map(|_| LinkTrafficCounters::default())
+                    .collect(),
+            }),
+        }
+    }
+
+    /// Creates a bus that also writes every logged event to the JSONL file at
+    /// `events_out`, if given, creating or truncating it. The file is split
+    /// into segments as `rotation` says, if given.
+    pub fn with_events_out(
+        snapshot_tx: Sender<Snapshot>,
+        num_nodes: usize,
+        spec: &TelemetrySpec,
+        events_out: Option<std::path::PathBuf>,
+        rotation: Option<crate::segments::RotationPolicy>,
+    ) -> std::io::Result<Self> {
+        let mut bus = Self::new(snapshot_tx, num_nodes, spec);
+        if let Some(path) = events_out {
+            bus.events_out = Some(Arc::new(events_out::EventsOut::create(path, rotation)?));
         }
+        Ok(bus)
+    }
+
+    /// Creates a bus whose snapshots have no consumer, for runs that only
+    /// read the final report. Sinks can still be attached.
+    pub fn detached(num_nodes: usize, spec: &TelemetrySpec) -> Self {
+        let (snapshot_tx, _) = crossbeam_channel::bounded(0);
+        Self::new(snapshot_tx, num_nodes, spec)
     }
 
+    /// Attaches a consumer that sees every snapshot and logged event.
+    pub fn add_sink(&self, sink: Box<dyn TelemetrySink>) {
+        self.sinks.lock().unwrap().push(sink);
+        self.has_sinks.store(true, Ordering::Release);
+    }
+
+    /// Also sends, from now on, what changed between each snapshot sent
+    /// and the one before it over `delta_tx`. The first delta of a run is
+    /// against an empty snapshot, so it lists every node and link.
+    pub fn set_delta_channel(&self, delta_tx: Sender<delta::SnapshotDelta>) {
+        *lock(&self.delta_tx) = Some(delta_tx);
+    }
+
+    /// Sends a snapshot to the sinks and consumers, unless nothing but the
+    /// time changed since the last one sent, and that less than a
+    /// `SNAPSHOT_HEARTBEAT` ago.
     pub fn send_snapshot(&self, snap: Snapshot) {
-        // Try sending, but don't block if the TUI is not consuming.
+        let delta_tx = lock(&self.delta_tx).clone();
+        if self.skip_unchanged || delta_tx.is_some() {
+            let mut last_sent = lock(&self.last_sent);
+            let delta = match last_sent.as_ref() {
+                Some((last, sent_at)) => {
+                    let delta = Snapshot::diff(last, &snap);
+                    if self.skip_unchanged && delta.is_empty() && sent_at.elapsed() < SNAPSHOT_HEARTBEAT {
+                        return;
+                    }
+                    delta
+                }
+                None => Snapshot::diff(&Snapshot::default(), &snap),
+            };
+            // Like snapshots, deltas are dropped rather than waited for
+            if let Some(delta_tx) = delta_tx {
+                let _ = delta_tx.try_send(delta);
+            }
+            *last_sent = Some((snap.clone(), Instant::now()));
+        }
+
+        if self.has_sinks.load(Ordering::Acquire) {
+            for sink in self.sinks.lock().unwrap().iter_mut() {
+                sink.on_snapshot(&snap);
+            }
+        }
+        // Try sending, but don't block if the TUI is not consuming. A full or
+        // disconnected channel must not change what the engine does next, so
+        // the result is deliberately ignored.
         let _ = self.snapshot_tx.try_send(snap);
     }
 
     pub fn set_current_time(&self, time: SimTime, event_id: EventId) {
-        let mut ctx = self.context.lock().unwrap();
-        ctx.time = time;
-        ctx.event_id = event_id;
+        let time = u64::try_from(time).unwrap_or(u64::MAX);
+        self.context.time.store(time, Ordering::Relaxed);
+        self.context.event_id.store(event_id.0, Ordering::Relaxed);
+    }
+
+    /// Sets the node whose protocol is running, which stamps what it logs,
+    /// and returns the one set before.
+    pub(crate) fn set_current_node(&self, node: Option<NodeId>) -> Option<NodeId> {
+        let node = node.map_or(NO_NODE, u64::from);
+        NodeId::try_from(self.context.node.swap(node, Ordering::Relaxed)).ok()
     }
 
     pub fn log_node_kv(&self, node_id: NodeId, key: String, val: Value) {
-        let mut ctx = self.context.lock().unwrap();
-        if let Some(map) = ctx.node_kvs.get_mut(node_id as usize) {
-            map.insert(key, val);
+        self.notify_conditions(|| Signal::Kv { node: node_id, key: key.clone() }, || SignalValue::kv(val.clone()));
+        self.track_convergence(node_id, &key, &val);
+        let ctx = &self.context;
+        if let Some(kvs) = ctx.node_kvs.get(node_id as usize) {
+            lock(kvs).put(key, val, ctx.max_node_kvs);
+        }
+    }
+
+    /// Records a custom KV that is exempt from eviction.
+    pub fn log_node_kv_pinned(&self, node_id: NodeId, key: String, val: Value) {
+        self.notify_conditions(|| Signal::Kv { node: node_id, key: key.clone() }, || SignalValue::kv(val.clone()));
+        self.track_convergence(node_id, &key, &val);
+        if let Some(kvs) = self.context.node_kvs.get(node_id as usize) {
+            lock(kvs).put_pinned(key, val);
+        }
+    }
+
+    /// Records a sample of a node's numeric metric at the current time.
+    /// Non-finite values are dropped.
+    pub fn log_node_metric(&self, node_id: NodeId, key: String, value: f64) {
+        if !value.is_finite() {
+            tracing::debug!(node_id, key, value, "Ignoring non-finite metric sample");
+            return;
+        }
+        self.notify_conditions(|| Signal::Metric { node: node_id, key: key.clone() }, || SignalValue::Metric(value));
+        let ctx = &self.context;
+        if let Some(metrics) = ctx.node_metrics.get(node_id as usize) {
+            let sample = snapshot::MetricSample { time: ctx.time(), value };
+            lock(metrics).record(key, sample, ctx.max_metric_samples);
         }
     }
 
-    pub(crate) fn context(&self) -> Arc<Mutex<TracingContext>> {
+    /// Records a node's status, for the conditions that read it.
+    pub fn record_status(&self, node_id: NodeId, status: NodeStatus) {
+        self.notify_conditions(|| Signal::Status(node_id), || SignalValue::Status(status));
+        let now = self.context.time();
+        let change = lock(&self.convergence).record_status(node_id, status == NodeStatus::Up, now);
+        self.log_convergence(change);
+    }
+
+    /// Forwards a state hash published under `REPLICA_HASH_KEY` to the convergence
+    /// tracker.
+    fn track_convergence(&self, node_id: NodeId, key: &str, val: &Value) {
+        if key != REPLICA_HASH_KEY {
+            return;
+        }
+        let hash = val.as_str().map_or_else(|| val.to_string(), str::to_string);
+        let change = lock(&self.convergence).record_hash(node_id, hash, self.context.time());
+        self.log_convergence(change);
+    }
+
+    /// Logs a `CONVERGED` or `DIVERGED` event for a change the convergence
+    /// tracker reported.
+    fn log_convergence(&self, change: Option<bool>) {
+        let Some(converged) = change else {
+            return;
+        };
+        let Some(report) = lock(&self.convergence).report() else {
+            return;
+        };
+        let event_type = if converged { "CONVERGED" } else { "DIVERGED" };
+        let severity = if converged { EventSeverity::Info } else { EventSeverity::Warn };
+        self.log_event(event_type.to_string(), report.to_string(), None, severity);
+    }
+
+    /// Returns how far the nodes agree on their state hashes, if any node
+    /// published one.
+    pub fn convergence_report(&self) -> Option<ConvergenceReport> {
+        lock(&self.convergence).report()
+    }
+
+    /// Returns the convergence tracker's state for `restore_convergence`.
+    pub fn save_convergence(&self) -> ConvergenceTracker {
+        lock(&self.convergence).clone()
+    }
+
+    /// Rewinds the convergence tracker to state saved by `save_convergence`.
+    pub fn restore_convergence(&self, convergence: ConvergenceTracker) {
+        *lock(&self.convergence) = convergence;
+    }
+
+    /// Records whether an invariant held when last checked, for the
+    /// conditions that read it.
+    pub fn record_oracle(&self, name: &str, holds: bool) {
+        self.notify_conditions(|| Signal::Oracle(name.to_string()), || SignalValue::Oracle(holds));
+    }
+
+    /// Forwards a signal's new value to the conditions at the current time.
+    /// The signal and value are only built if some condition exists.
+    fn notify_conditions(&self, signal: impl FnOnce() -> Signal, value: impl FnOnce() -> SignalValue) {
+        if self.has_conditions.load(Ordering::Acquire) {
+            let mut conditions = lock(&self.conditions);
+            let signal = signal();
+            if conditions.wants(&signal) {
+                conditions.update(signal, value(), self.context.time());
+            }
+        }
+    }
+
+    /// Adds a condition, starting now. `statuses` are the nodes' current
+    /// statuses; the KVs and metrics it reads start at their latest values.
+    pub fn add_condition(&self, condition: Condition, statuses: &[NodeStatus]) {
+        let ctx = &self.context;
+        let seed = |signal: &Signal| match signal {
+            Signal::Kv { node, key } => {
+                let kvs = lock(ctx.node_kvs.get(*node as usize)?);
+                kvs.pinned.get(key).or_else(|| kvs.entries.get(key)).cloned().map(SignalValue::kv)
+            }
+            Signal::Metric { node, key } => {
+                let metrics = lock(ctx.node_metrics.get(*node as usize)?);
+                Some(SignalValue::Metric(metrics.series.get(key)?.back()?.value))
+            }
+            Signal::Status(node) => statuses.get(*node as usize).copied().map(SignalValue::Status),
+            Signal::Oracle(_) => None,
+        };
+        lock(&self.conditions).add(condition, ctx.time(), seed);
+        self.has_conditions.store(true, Ordering::Release);
+    }
+
+    /// Settles the conditions' deadlines due at or before `now`.
+    pub fn advance_conditions(&self, now: SimTime) {
+        if self.has_conditions.load(Ordering::Acquire) {
+            lock(&self.conditions).advance_to(now);
+        }
+    }
+
+    /// Returns every condition's state as of `now`, in the order they were
+    /// added.
+    pub fn condition_outcomes(&self, now: SimTime) -> Vec<ConditionOutcome> {
+        if !self.has_conditions.load(Ordering::Acquire) {
+            return Vec::new();
+        }
+        let mut conditions = lock(&self.conditions);
+        conditions.advance_to(now);
+        conditions.outcomes()
+    }
+
+    /// Returns the conditions' progress, for a saved simulation state.
+    pub fn save_conditions(&self) -> ConditionStatus {
+        lock(&self.conditions).save()
+    }
+
+    /// Rewinds the conditions to progress saved by `save_conditions`.
+    pub fn restore_conditions(&self, status: ConditionStatus) {
+        lock(&self.conditions).restore(status);
+    }
+
+    /// Judges client responses against `slo` from now on, replacing any
+    /// earlier objective and its windows.
+    pub fn set_slo(&self, slo: Option<Slo>) {
+        *lock(&self.slo) = slo.map(SloTracker::new);
+        self.has_slo.store(slo.is_some(), Ordering::Release);
+    }
+
+    /// Judges the SLO windows that end at or before `now`, logging an
+    /// `SLO_VIOLATION` event for each that violated it.
+    pub fn advance_slo(&self, now: SimTime) {
+        if !self.has_slo.load(Ordering::Acquire) {
+            return;
+        }
+        let violations = lock(&self.slo).as_mut().map(|slo| slo.advance_to(now)).unwrap_or_default();
+        for window in violations {
+            tracing::warn!(%window, "SLO violated");
+            self.log_event("SLO_VIOLATION".to_string(), window.to_string(), None, EventSeverity::Warn);
+        }
+    }
+
+    /// Returns how the run did against its SLO as of `now`, if it has one.
+    pub fn slo_report(&self, now: SimTime) -> Option<SloReport> {
+        if !self.has_slo.load(Ordering::Acquire) {
+            return None;
+        }
+        self.advance_slo(now);
+        lock(&self.slo).as_ref().map(SloTracker::report)
+    }
+
+    /// Returns the SLO's progress for `restore_slo`.
+    pub fn save_slo(&self) -> Option<SloTracker> {
+        lock(&self.slo).clone()
+    }
+
+    /// Rewinds the SLO to progress saved by `save_slo`.
+    pub fn restore_slo(&self, slo: Option<SloTracker>) {
+        self.has_slo.store(slo.is_some(), Ordering::Release);
+        *lock(&self.slo) = slo;
+    }
+
+    pub fn slo_spec(&self) -> Option<Slo> {
+        if !self.has_slo.load(Ordering::Acquire) {
+            return None;
+        }
+        lock(&self.slo).as_ref().map(SloTracker::slo)
+    }
+
+    #[cfg(feature = "tracing-layer")]
+    pub(crate) fn context(&self) -> Arc<TracingContext> {
         self.context.clone()
     }
 
-    /// Logs a simulation event for visualization.
-    pub fn log_event(&self, event_type: String, details: String, node_id: Option<NodeId>) {
-        let mut ctx = self.context.lock().unwrap();
+    /// Logs a simulation event for visualization. Warnings and faults are
+    /// kept apart from other events in the recent-events buffer. Events are
+    /// numbered in the order they are logged.
+    pub fn log_event(&self, event_type: String, details: String, node_id: Option<NodeId>, severity: EventSeverity) {
         let log_snap = snapshot::LogSnap {
-            event_id: ctx.event_id,
-            time: ctx.time,
+            seq: self.context.next_event_seq.fetch_add(1, Ordering::Relaxed),
+            event_id: self.context.event_id(),
+            time: self.context.time(),
             event_type,
             details,
             node_id,
+            severity,
         };
-        
-        // Keep only the last 100 events
-        if ctx.recent_events.len() >= 100 {
-            ctx.recent_events.pop_front();
+        if self.has_sinks.load(Ordering::Acquire) {
+            for sink in self.sinks.lock().unwrap().iter_mut() {
+                sink.on_event(&log_snap);
+            }
+        }
+        if let Some(out) = &self.events_out {
+            out.send(&log_snap);
+        }
+
+        let (queue, queued) = &self.context.queued_events;
+        // The receiver lives as long as the sender, so this cannot fail
+        let _ = queue.send(log_snap);
+        if queued.len() >= self.context.event_buffer {
+            drop(self.context.recent_events());
+        }
+    }
+
+    /// Keeps the events evicted from the recent-events ring in `spill` from
+    /// now on.
+    pub fn set_event_spill(&self, spill: spill::EventSpill) {
+        *lock(&self.context.spill) = Some(spill);
+    }
+
+    /// Writes the spilled events out, so that a `SpillReader` sees all of
+    /// them. Returns the spill directory, if events are being spilled.
+    pub fn flush_event_spill(&self) -> Option<std::path::PathBuf> {
+        drop(self.context.recent_events());
+        let mut spill = lock(&self.context.spill);
+        let flushed = spill.as_mut()?.flush();
+        if let Err(e) = flushed {
+            tracing::warn!(error = %e, "Event spill could not be flushed");
         }
-        ctx.recent_events.push_back(log_snap);
+        spill.as_ref().map(|s| s.dir().to_path_buf())
+    }
+
+    /// Writes out the events still queued for the events file and closes
+    /// it. Returns the file's path and what was written and dropped, if the
+    /// bus has one that was not closed yet.
+    pub fn finish_events_out(&self) -> Option<(std::path::PathBuf, events_out::EventsOutSummary)> {
+        let out = self.events_out.as_ref()?;
+        match out.finish()? {
+            Ok(summary) => Some((out.path().to_path_buf(), summary)),
+            Err(e) => {
+                tracing::warn!(error = %e, path = %out.path().display(), "Events file could not be written");
+                None
+            }
+        }
+    }
+
+    /// Returns the most recently logged events, oldest first, without
+    /// blocking. Returns `None` if the recent-events buffer is locked, as it
+    /// is when the calling thread panicked while moving events into it.
+    pub fn try_recent_events(&self) -> Option<Vec<snapshot::LogSnap>> {
+        let mut recent = match self.context.recent_events.try_lock() {
+            Ok(recent) => recent,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return None,
+        };
+        self.context.move_queued_events(&mut recent);
+        Some(recent.to_vec())
     }
 
     /// Increments a metric counter.
     pub fn increment_metric(&self, metric: &str) {
-        let mut ctx = self.context.lock().unwrap();
-        match metric {
-            "messages_sent" => ctx.metrics.messages_sent += 1,
-            "messages_delivered" => ctx.metrics.messages_delivered += 1,
-            "timers_fired" => ctx.metrics.timers_fired += 1,
-            "faults_injected" => ctx.metrics.faults_injected += 1,
-            _ => {}, // Unknown metric, ignore
+        let counters = &self.context.metrics;
+        let counter = match metric {
+            "messages_sent" => &counters.messages_sent,
+            "messages_delivered" => &counters.messages_delivered,
+            "timers_fired" => &counters.timers_fired,
+            "faults_injected" => &counters.faults_injected,
+            "delay_clamped" => &counters.delay_clamped,
+            "client_requests" => &counters.client_requests,
+            "gray_failure_ignored" => &counters.gray_failure_ignored,
+            "simultaneous_restarts" => &counters.simultaneous_restarts,
+            _ => return, // Unknown metric, ignore
+        };
+        Counters::add(counter, 1);
+    }
+
+    /// Counts a client response that completed at `completed_at`, `latency`
+    /// after its request was issued, judging it against the SLO, if any.
+    pub fn record_client_response(&self, completed_at: SimTime, latency: SimTime) {
+        let counters = &self.context.metrics;
+        let latency_ns = u64::try_from(latency).unwrap_or(u64::MAX);
+        self.context.latencies.client_request.record(latency_ns);
+        Counters::add(&counters.client_request_latency_ns, latency_ns);
+        Counters::add(&counters.client_responses, 1);
+        if self.has_slo.load(Ordering::Acquire) {
+            if let Some(slo) = lock(&self.slo).as_mut() {
+                slo.record(completed_at, latency);
+            }
+        }
+    }
+
+    /// Records `value` into the protocol histogram `key` on behalf of
+    /// `node`. The bus keeps whole units; negative values count as zero.
+    pub fn record_histogram(&self, node: NodeId, key: &'static str, value: f64) {
+        ::metrics::histogram!(key, ftsim_types::metrics::LBL_NODE => node.to_string()).record(value);
+        // `as` saturates, and takes NaN to zero
+        lock(&self.context.latencies.protocol).entry(key).or_default().record(value as u64);
+    }
+
+    /// Counts a message `src` sent.
+    pub fn record_send(&self, src: NodeId) {
+        if METRICS_RECORDER.load(Ordering::Acquire) {
+            ::metrics::counter!(
+                ftsim_types::metrics::MET_NET_MSG_SENT,
+                ftsim_types::metrics::LBL_NODE => src.to_string()
+            ).increment(1);
+        }
+        if let Some(traffic) = self.context.node_traffic.get(src as usize) {
+            Counters::add(&traffic.sent, 1);
+        }
+    }
+
+    /// Counts a message delivered to `dst` over `link_id` after `latency`
+    /// of sim time since it was created. A node that was down when it
+    /// arrived counts it as dropped rather than received.
+    pub fn record_delivery(&self, dst: NodeId, link_id: LinkId, latency: SimTime, was_up: bool) {
+        if METRICS_RECORDER.load(Ordering::Acquire) {
+            ::metrics::counter!(
+                ftsim_types::metrics::MET_NET_MSG_DELIVERED,
+                ftsim_types::metrics::LBL_NODE => dst.to_string(),
+                ftsim_types::metrics::LBL_LINK => link_id.to_string()
+            ).increment(1);
+        }
+        let ctx = &self.context;
+        ctx.latencies.delivery.record(u64::try_from(latency).unwrap_or(u64::MAX));
+        if let Some(traffic) = self.link_traffic(link_id) {
+            Counters::add(&traffic.delivered, 1);
+        }
+        if let Some(traffic) = ctx.node_traffic.get(dst as usize) {
+            Counters::add(if was_up { &traffic.received } else { &traffic.dropped }, 1);
+        }
+    }
+
+    /// Counts a message to `dst` that was lost on the way, on `link_id` if
+    /// it got as far as a link.
+    pub fn record_drop(&self, dst: NodeId, link_id: Option<LinkId>) {
+        if let Some(traffic) = link_id.and_then(|link_id| self.link_traffic(link_id)) {
+            Counters::add(&traffic.dropped, 1);
+        }
+        if let Some(traffic) = self.context.node_traffic.get(dst as usize) {
+            Counters::add(&traffic.dropped, 1);
+        }
+    }
+
+    fn link_traffic(&self, link_id: LinkId) -> Option<&LinkTrafficCounters> {
+        self.context.link_traffic.get(usize::try_from(link_id).ok()?)
+    }
+
+    /// Adds simulated store latency to the running total.
+    pub fn add_store_time(&self, delay: SimTime) {
+        Counters::add(&self.context.metrics.store_time_ns, delay as u64);
+    }
+
+    /// Returns the custom KVs last published by each node, followed by the
+    /// latest value of each of its numeric metrics.
+    pub fn node_kvs(&self, num_nodes: usize) -> Vec<IndexMap<String, Value>> {
+        let ctx = &self.context;
+        (0..num_nodes)
+            .map(|i| {
+                let mut kvs = ctx.node_kvs.get(i).map(|kvs| lock(kvs).merged()).unwrap_or_default();
+                if let Some(metrics) = ctx.node_metrics.get(i) {
+                    for (key, samples) in lock(metrics).series.iter() {
+                        if let Some(sample) = samples.back() {
+                            kvs.insert(key.clone(), Value::from(sample.value));
+                        }
+                    }
+                }
+                kvs
+            })
+            .collect()
+    }
+
+    /// Returns the settings the bus was created with.
+    pub fn spec(&self) -> TelemetrySpec {
+        let ctx = &self.context;
+        TelemetrySpec {
+            max_node_kvs: ctx.max_node_kvs,
+            include_store_keys: ctx.include_store_keys,
+            include_fault_details: ctx.include_fault_details,
+            max_metric_samples: ctx.max_metric_samples,
+            event_buffer: ctx.event_buffer,
+            send_unchanged_snapshots: !self.skip_unchanged,
         }
     }
 
     /// Builds a snapshot of the world, enriching it with telemetry context.
     pub fn build_snapshot(&self, world: &World, time: SimTime) -> Snapshot {
-        let ctx = self.context.lock().unwrap();
+        let ctx = &self.context;
+        // Counters first: everything copied after them is at least as new.
+        let metrics = ctx.load_metrics();
+        let max_store_keys = ctx.include_store_keys.then_some(SNAPSHOT_STORE_KEYS);
         let nodes = world
             .nodes
             .iter()
             .enumerate()
             .map(|(i, n)| {
-                let kv = ctx.node_kvs.get(i).cloned().unwrap_or_default();
+                let (custom, evicted_kvs) = ctx
+                    .node_kvs
+                    .get(i)
+                    .map(|kvs| {
+                        let kvs = lock(kvs);
+                        (kvs.merged(), kvs.evicted)
+                    })
+                    .unwrap_or_default();
                 snapshot::NodeSnap {
                     id: n.id,
                     status: n.status,
                     timers: n.timers_len(),
                     byzantine: n.byzantine(),
-                    custom: kv,
+                    gray_failure: n.gray_failure(time).filter(|_| ctx.include_fault_details).cloned(),
+                    custom,
+                    evicted_kvs,
+                    metrics: ctx.node_metrics.get(i).map(|m| lock(m).to_map()).unwrap_or_default(),
+                    store: n.store().summary(max_store_keys).map(|mut store| {
+                        if let Some(quota) = n.write_quota() {
+                            store.throttled_writes = quota.throttled_writes();
+                            store.throttle_delay_ns = quota.delay_ns();
+                        }
+                        store
+                    }),
+                    traffic: ctx.node_traffic.get(i).map(TrafficCounters::load).unwrap_or_default(),
                 }
             })
             .collect();
 
         let links = world
             .net
-            .links
-            .values()
-            .map(|l| snapshot::LinkSnap {
-                id: l.id,
-                src: l.src,
-                dst: l.dst,
-                is_partitioned: l.faults.partitioned,
+            .sorted_link_ids()
+            .iter()
+            .map(|id| &world.net.links[id])
+            .map(|l| {
+                let (delivered, dropped) = usize::try_from(l.id)
+                    .ok()
+                    .and_then(|i| ctx.link_traffic.get(i))
+                    .map(LinkTrafficCounters::load)
+                    .unwrap_or_default();
+                snapshot::LinkSnap {
+                    id: l.id,
+                    src: l.src,
+                    dst: l.dst,
+                    is_partitioned: l.faults.partitioned,
+                    delivered,
+                    dropped,
+                }
             })
             .collect();
 
@@ -136,8 +1021,302 @@ impl TelemetryBus {
             time,
             nodes,
             links,
-            recent_events: ctx.recent_events.iter().cloned().collect(),
-            metrics: ctx.metrics.clone(),
+            recent_events: ctx.recent_events().to_vec(),
+            metrics,
+            slo_violation: self
+                .has_slo
+                .load(Ordering::Acquire)
+                .then(|| lock(&self.slo).as_ref().and_then(SloTracker::current_violation))
+                .flatten(),
+            stepped: None,
+            breakpoint: None,
+            finished: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_bus(max_node_kvs: usize) -> TelemetryBus {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        TelemetryBus::new(
+            tx,
+            1,
+            &TelemetrySpec {
+                max_node_kvs,
+                ..TelemetrySpec::default()
+            },
+        )
+    }
+
+    fn node_kvs(bus: &TelemetryBus) -> NodeKvs {
+        bus.context.node_kvs[0].lock().unwrap().clone()
+    }
+
+    #[test]
+    fn test_kv_cap_evicts_oldest_updated() {
+        let bus = test_bus(100);
+        for i in 0..10_000 {
+            bus.log_node_kv(0, format!("key_{}", i), Value::from(i));
+        }
+
+        let kvs = node_kvs(&bus);
+        assert_eq!(kvs.entries.len(), 100);
+        assert_eq!(kvs.evicted, 9_900);
+        let keys: Vec<&String> = kvs.entries.keys().collect();
+        assert_eq!(keys.first().unwrap().as_str(), "key_9900");
+        assert_eq!(keys.last().unwrap().as_str(), "key_9999");
+    }
+
+    #[test]
+    fn test_evicted_events_are_spilled_in_order() {
+        let bus = test_bus(100);
+        let dir = std::env::temp_dir().join(format!("ftsim-bus-spill-{}", std::process::id()));
+        bus.set_event_spill(spill::EventSpill::create(&dir, 1 << 20).unwrap());
+        for i in 0..250u64 {
+            bus.set_current_time(u128::from(i), EventId(i));
+            bus.log_event("TEST".into(), format!("event {}", i), None, EventSeverity::Info);
+        }
+        assert_eq!(bus.flush_event_spill(), Some(dir.clone()));
+
+        let reader = spill::SpillReader::open(&dir).unwrap();
+        let spilled: Vec<u64> = (0..reader.segment_count())
+            .flat_map(|i| reader.load_segment(i).unwrap())
+            .map(|e| e.event_id.0)
+            .collect();
+        assert_eq!(spilled, (0..150).collect::<Vec<_>>());
+        let recent = bus.try_recent_events().unwrap();
+        assert_eq!(recent.first().map(|e| e.event_id), Some(EventId(150)));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_event_buffer_evicts_oldest_chatter_first() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let bus = TelemetryBus::new(tx, 1, &TelemetrySpec { event_buffer: 5, ..TelemetrySpec::default() });
+        for i in 0..8u64 {
+            bus.set_current_time(u128::from(i), EventId(i));
+            bus.log_event("TEST".into(), format!("event {}", i), None, EventSeverity::Debug);
+        }
+        let ids: Vec<u64> = bus.try_recent_events().unwrap().iter().map(|e| e.event_id.0).collect();
+        assert_eq!(ids, [3, 4, 5, 6, 7]);
+        assert_eq!(bus.spec().event_buffer, 5);
+    }
+
+    #[test]
+    fn test_logging_locks_the_event_buffer_once_per_batch() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let bus = TelemetryBus::new(tx, 1, &TelemetrySpec { event_buffer: 5, ..TelemetrySpec::default() });
+        // A reader holding the buffer does not hold up the first four events
+        let held = lock(&bus.context.recent_events);
+        for i in 0..4u64 {
+            bus.set_current_time(u128::from(i), EventId(i));
+            bus.log_event("TEST".into(), format!("event {}", i), None, EventSeverity::Debug);
+        }
+        assert!(held.chatter.is_empty());
+        drop(held);
+        let seqs: Vec<u64> = bus.try_recent_events().unwrap().iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, [0, 1, 2, 3]);
+
+        // The fifth fills a batch, which the logging thread moves over
+        for i in 4..9u64 {
+            bus.log_event("TEST".into(), format!("event {}", i), None, EventSeverity::Debug);
+        }
+        assert_eq!(bus.context.queued_events.1.len(), 0);
+        assert_eq!(lock(&bus.context.recent_events).chatter.back().map(|e| e.seq), Some(8));
+    }
+
+    #[test]
+    fn test_faults_outlive_chatter_in_the_event_buffer() {
+        use ftsim_proto::{api::boxed_dyn, protocols::raft_lite::RaftLite};
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let bus = TelemetryBus::new(tx, 1, &TelemetrySpec { event_buffer: 3, ..TelemetrySpec::default() });
+        let log = |i: u64, severity: EventSeverity| {
+            bus.set_current_time(u128::from(i), EventId(i));
+            bus.log_event(severity.to_string(), format!("event {}", i), None, severity);
+        };
+        log(0, EventSeverity::Fault);
+        log(1, EventSeverity::Warn);
+        for i in 2..1_000 {
+            log(i, if i % 2 == 0 { EventSeverity::Debug } else { EventSeverity::Info });
+        }
+        // The fault and the warning survive 998 later events, in their place
+        let recent = bus.try_recent_events().unwrap();
+        let ids: Vec<u64> = recent.iter().map(|e| e.event_id.0).collect();
+        assert_eq!(ids, [0, 1, 997, 998, 999]);
+        assert_eq!(recent[0].severity, EventSeverity::Fault);
+
+        // Faults and warnings evict only each other, oldest first
+        for i in 1_000..1_002 {
+            log(i, EventSeverity::Fault);
         }
+        let world = World::single_node(boxed_dyn(RaftLite::default()));
+        let snap = bus.build_snapshot(&world, 0);
+        let ids: Vec<u64> = snap.recent_events.iter().map(|e| e.event_id.0).collect();
+        assert_eq!(ids, [1, 997, 998, 999, 1_000, 1_001]);
+    }
+
+    #[test]
+    fn test_unchanged_snapshots_are_skipped_until_the_heartbeat() {
+        use ftsim_proto::{api::boxed_dyn, protocols::raft_lite::RaftLite};
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let (delta_tx, delta_rx) = crossbeam_channel::unbounded();
+        let bus = TelemetryBus::new(tx, 1, &TelemetrySpec::default());
+        bus.set_delta_channel(delta_tx);
+        let world = World::single_node(boxed_dyn(RaftLite::default()));
+        let tick = sim_from_ms(50);
+        for i in 0..20 {
+            bus.send_snapshot(bus.build_snapshot(&world, i * tick));
+        }
+        // Only the first made it; the first delta lists the whole world
+        assert_eq!(rx.try_iter().map(|s| s.time).collect::<Vec<_>>(), [0]);
+        let first = delta_rx.try_recv().unwrap();
+        assert_eq!((first.nodes.len(), first.nodes[0].status), (1, Some(NodeStatus::Up)));
+        assert!(delta_rx.try_recv().is_err());
+
+        // A heartbeat with no changes once a heartbeat of wall time has
+        // passed, then a status flip right after it
+        if let Some((_, sent_at)) = lock(&bus.last_sent).as_mut() {
+            *sent_at -= SNAPSHOT_HEARTBEAT;
+        }
+        bus.send_snapshot(bus.build_snapshot(&world, 20 * tick));
+        let mut snap = bus.build_snapshot(&world, 21 * tick);
+        snap.nodes[0].status = NodeStatus::Down;
+        bus.send_snapshot(snap);
+        assert_eq!(rx.try_iter().count(), 2);
+        let deltas: Vec<_> = delta_rx.try_iter().collect();
+        assert!(deltas[0].is_empty() && deltas[0].time == 20 * tick, "{:?}", deltas[0]);
+        assert_eq!(deltas[1].nodes[0].status, Some(NodeStatus::Down));
+        assert_eq!(deltas[1].from, 20 * tick);
+    }
+
+    #[test]
+    fn test_a_bus_sending_unchanged_snapshots_never_compares_them() {
+        use ftsim_proto::{api::boxed_dyn, protocols::raft_lite::RaftLite};
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let spec = TelemetrySpec { send_unchanged_snapshots: true, ..TelemetrySpec::default() };
+        let bus = TelemetryBus::new(tx, 1, &spec);
+        let world = World::single_node(boxed_dyn(RaftLite::default()));
+        for time in 0..3 {
+            bus.send_snapshot(bus.build_snapshot(&world, time));
+        }
+        assert_eq!(rx.try_iter().count(), 3);
+        assert!(lock(&bus.last_sent).is_none());
+    }
+
+    #[test]
+    fn test_metric_retention_keeps_latest_samples() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let spec = TelemetrySpec {
+            max_metric_samples: 3,
+            ..TelemetrySpec::default()
+        };
+        let bus = TelemetryBus::new(tx, 1, &spec);
+        for i in 0..10u32 {
+            bus.set_current_time(u128::from(i) * 10, EventId(0));
+            bus.log_node_metric(0, "term".into(), f64::from(i));
+        }
+        // A second sample at the same instant replaces the first
+        bus.log_node_metric(0, "term".into(), 42.0);
+        bus.log_node_metric(0, "term".into(), f64::NAN);
+
+        let metrics = bus.context.node_metrics[0].lock().unwrap();
+        let samples = &metrics.series["term"];
+        let samples: Vec<(SimTime, f64)> = samples.iter().map(|s| (s.time, s.value)).collect();
+        assert_eq!(samples, [(70, 7.0), (80, 8.0), (90, 42.0)]);
+    }
+
+    #[test]
+    fn test_snapshot_carries_metric_series() {
+        let bus = test_bus(10);
+        bus.log_node_kv_pinned(0, "role".into(), Value::from("leader"));
+        bus.log_node_metric(0, "term".into(), 2.0);
+        bus.set_current_time(5, EventId(0));
+        bus.log_node_metric(0, "term".into(), 3.0);
+        bus.log_node_metric(0, "commit_index".into(), 12.5);
+
+        let world = World::single_node(ftsim_proto::api::boxed_dyn(
+            ftsim_proto::protocols::raft_lite::RaftLite::default(),
+        ));
+        let node = bus.build_snapshot(&world, 5).nodes.remove(0);
+        // Metrics stay out of the categorical KVs
+        assert_eq!(node.custom.keys().collect::<Vec<_>>(), ["role"]);
+        assert_eq!(node.metrics.keys().collect::<Vec<_>>(), ["term", "commit_index"]);
+        assert_eq!(
+            node.metrics["term"],
+            [snapshot::MetricSample { time: 0, value: 2.0 }, snapshot::MetricSample { time: 5, value: 3.0 }]
+        );
+        assert_eq!(node.metric("term"), Some(3.0));
+        assert_eq!(node.metric("commit_index"), Some(12.5));
+        assert_eq!(node.metric("missing"), None);
+
+        // Invariants see the latest value of each metric next to the KVs
+        let kvs = bus.node_kvs(1).remove(0);
+        assert_eq!(kvs["role"], Value::from("leader"));
+        assert_eq!(kvs["term"], Value::from(3.0));
+    }
+
+    #[test]
+    fn test_kv_update_refreshes_eviction_order() {
+        let bus = test_bus(2);
+        bus.log_node_kv(0, "a".into(), Value::from(1));
+        bus.log_node_kv(0, "b".into(), Value::from(2));
+        bus.log_node_kv(0, "a".into(), Value::from(3));
+        bus.log_node_kv(0, "c".into(), Value::from(4));
+
+        let merged = node_kvs(&bus).merged();
+        assert_eq!(merged.keys().collect::<Vec<_>>(), ["a", "c"]);
+        assert_eq!(merged["a"], Value::from(3));
+    }
+
+    #[test]
+    fn test_pinned_kvs_survive_eviction() {
+        let bus = test_bus(10);
+        bus.log_node_kv_pinned(0, "role".into(), Value::from("leader"));
+        bus.log_node_kv_pinned(0, "term".into(), Value::from("7"));
+        for i in 0..10_000 {
+            bus.log_node_kv(0, format!("key_{}", i), Value::from(i));
+        }
+        // An unpinned write to a pinned key updates it in place.
+        bus.log_node_kv(0, "term".into(), Value::from("8"));
+
+        let kvs = node_kvs(&bus);
+        assert_eq!(kvs.entries.len(), 10);
+        let merged = kvs.merged();
+        assert_eq!(merged["role"], Value::from("leader"));
+        assert_eq!(merged["term"], Value::from("8"));
+        assert_eq!(merged.len(), 12);
+    }
+
+    #[test]
+    fn test_snapshot_reflects_store_appends() {
+        use ftsim_proto::{api::boxed_dyn, protocols::raft_lite::RaftLite};
+
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let spec = TelemetrySpec {
+            include_store_keys: true,
+            ..TelemetrySpec::default()
+        };
+        let bus = TelemetryBus::new(tx, 1, &spec);
+        let mut world = World::single_node(boxed_dyn(RaftLite::default()));
+
+        let store = |bus: &TelemetryBus, world: &World| {
+            bus.build_snapshot(world, 0).nodes[0].store.clone().unwrap()
+        };
+        assert_eq!(store(&bus, &world).log_len, 0);
+
+        let view = world.node_mut(0).store_view();
+        view.append_log(LogRecord::new(3, bytes::Bytes::from_static(b"abc"))).unwrap();
+        view.kv_put(bytes::Bytes::from_static(b"voted_for"), bytes::Bytes::from_static(b"1"))
+            .unwrap();
+
+        let snap = store(&bus, &world);
+        assert_eq!(snap.log_len, 1);
+        assert_eq!(snap.last_log_term, Some(3));
+        assert_eq!(snap.kv_keys, 1);
+        assert_eq!(snap.approx_bytes, 8 + 3 + 9 + 1);
+        assert_eq!(snap.keys, Some(vec!["voted_for".to_string()]));
     }
 }
@@ -0,0 +1,45 @@
+//! # ftsim-engine::telemetry::prometheus
+//!
+//! A Prometheus scrape endpoint for the counters and histograms the engine
+//! emits through the `metrics` facade. Without a recorder those calls are
+//! no-ops, so the engine runs the same whether or not this is installed;
+//! the per-message counters are not even labelled until it is.
+//!
+//! Labels are attached where each metric is emitted; `describe` only gives
+//! the metrics their units and help text, which also names the labels each
+//! one carries.
+
+use ftsim_types::metrics::*;
+use metrics::{describe_counter, describe_histogram, Unit};
+use metrics_exporter_prometheus::{BuildError, PrometheusBuilder};
+use std::net::SocketAddr;
+
+/// Installs the Prometheus exporter as the global `metrics` recorder, with
+/// an HTTP listener on `addr` serving the scrape endpoint from a thread of
+/// its own. Fails if the address cannot be bound or another recorder is
+/// already installed.
+pub fn install(addr: SocketAddr) -> Result<(), BuildError> {
+    PrometheusBuilder::new().with_http_listener(addr).install()?;
+    describe();
+    super::enable_message_metrics();
+    Ok(())
+}
+
+/// Describes every metric the engine emits to the installed recorder.
+pub fn describe() {
+    describe_counter!(MET_NET_MSG_SENT, Unit::Count, "Messages sent, by sending node");
+    describe_counter!(MET_NET_MSG_DELIVERED, Unit::Count, "Messages delivered, by receiving node and link");
+    describe_counter!(MET_NET_MSG_DROPPED, Unit::Count, "Messages lost on the way, by reason, src and dst");
+    describe_counter!(MET_NET_FRAGMENTS, Unit::Count, "Fragments sent over links with an MTU, by src and dst");
+    describe_counter!(MET_NET_REASSEMBLY_FAILED, Unit::Count, "Fragmented messages never reassembled, by reason");
+    describe_counter!(MET_TIMER_FIRED, Unit::Count, "Timers fired, by node");
+    describe_counter!(MET_SIMULTANEOUS_RESTARTS, Unit::Count, "Node restarts within the same millisecond as another");
+    describe_counter!(MET_GRAY_FAILURE_IGNORED, Unit::Count, "Messages a gray failure made a node ignore, by node and src");
+    describe_counter!(MET_STORE_TIME, Unit::Nanoseconds, "Sim time spent in store operations, by node");
+    describe_counter!(MET_STORE_THROTTLED, Unit::Count, "Store writes throttled, by node");
+    describe_counter!(MET_DELAY_CLAMPED, Unit::Count, "Sampled delays clamped into range, by kind, the sampling site");
+    describe_counter!(MET_CODEC_ERRORS, Unit::Count, "Messages a protocol failed to decode, by node and proto");
+    describe_counter!(MET_CLIENT_REQUESTS, Unit::Count, "Client requests, by node and kind");
+    describe_counter!(MET_CACHE_EVICTED, Unit::Count, "Entries bounded engine caches evicted, by cache and reason");
+    describe_histogram!(MET_CLIENT_LATENCY_HISTO, Unit::Nanoseconds, "Client request latency in sim time");
+}
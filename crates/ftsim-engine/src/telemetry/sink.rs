@@ -0,0 +1,18 @@
+//! # ftsim-engine::telemetry::sink
+//!
+//! The extension point for telemetry consumers. Exporters that are not part
+//! of the core engine (log shippers, metrics exporters, trace writers)
+//! implement `TelemetrySink` and attach themselves with
+//! `TelemetryBus::add_sink`, so the engine never depends on them.
+
+use super::snapshot::{LogSnap, Snapshot};
+
+/// Receives telemetry as the simulation produces it. Sinks are called
+/// synchronously on the simulation thread and must not block.
+pub trait TelemetrySink: Send {
+    /// Called for every snapshot sent on the bus.
+    fn on_snapshot(&mut self, _snapshot: &Snapshot) {}
+
+    /// Called for every event logged on the bus.
+    fn on_event(&mut self, _event: &LogSnap) {}
+}
@@ -3,18 +3,29 @@
 //! Defines the stable `Snapshot` struct used to communicate the state of the
 //! simulation world to external consumers like the TUI.
 
-use crate::prelude::*;
+use crate::{histogram::Histogram, node::GrayFailure, prelude::*, slo::SloWindow};
 use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::fmt;
 
 /// A point-in-time snapshot of the entire simulation state.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct Snapshot {
     pub time: SimTime,
     pub nodes: Vec<NodeSnap>,
     pub links: Vec<LinkSnap>,
     pub recent_events: Vec<LogSnap>,
     pub metrics: MetricsSnapshot,
+    /// The last judged SLO window, while it is one that violated the SLO.
+    pub slo_violation: Option<SloWindow>,
+    /// Set on the snapshot published when a step or run-until request pauses
+    /// the run: the number of events the request executed.
+    pub stepped: Option<u64>,
+    /// Set on the snapshot published when a breakpoint pauses the run.
+    pub breakpoint: Option<String>,
+    /// Set on the last snapshot of a run: how it ended.
+    pub finished: Option<String>,
 }
 
 /// A snapshot of a single node's state.
@@ -24,8 +35,62 @@ pub struct NodeSnap {
     pub status: NodeStatus,
     pub timers: usize,
     pub byzantine: bool,
+    /// The gray failure in effect, when `telemetry.include_fault_details`
+    /// is set.
+    pub gray_failure: Option<GrayFailure>,
     /// Protocol-specific state exposed for visualization.
     pub custom: IndexMap<String, Value>,
+    /// The number of custom KV entries evicted to stay within the per-node limit.
+    pub evicted_kvs: u64,
+    /// Recent samples of each numeric metric, oldest first.
+    pub metrics: IndexMap<String, Vec<MetricSample>>,
+    /// A summary of what the node has persisted, if its store supports it.
+    pub store: Option<StoreSnap>,
+    /// The messages the node sent and received, and those sent to it that
+    /// were lost.
+    pub traffic: NodeTraffic,
+}
+
+/// Message counts of a node over the run.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NodeTraffic {
+    pub sent: u64,
+    /// Messages delivered to the node while it was up.
+    pub received: u64,
+    /// Messages to the node that never reached it: lost on the way, or
+    /// delivered while it was down.
+    pub dropped: u64,
+}
+
+impl NodeSnap {
+    /// Returns the latest value of a numeric metric.
+    pub fn metric(&self, key: &str) -> Option<f64> {
+        self.metrics.get(key)?.last().map(|s| s.value)
+    }
+}
+
+/// One sample of a numeric metric.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct MetricSample {
+    pub time: SimTime,
+    pub value: f64,
+}
+
+/// A summary of a node's persisted state.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct StoreSnap {
+    /// One past the index of the last log entry (compacted entries included).
+    pub log_len: u64,
+    pub last_log_term: Option<u64>,
+    pub kv_keys: usize,
+    /// Approximate payload bytes held by the log, KV map and snapshot.
+    pub approx_bytes: usize,
+    /// The first KV keys in key order, when `telemetry.include_store_keys` is set.
+    pub keys: Option<Vec<String>>,
+    /// Writes the node's write quota throttled or delayed.
+    pub throttled_writes: u64,
+    /// Sim time the node's write quota delayed writes by.
+    pub throttle_delay_ns: u64,
 }
 
 /// A snapshot of a single network link's state.
@@ -35,23 +100,131 @@ pub struct LinkSnap {
     pub src: NodeId,
     pub dst: NodeId,
     pub is_partitioned: bool,
+    /// Messages delivered over the link, duplicates included.
+    pub delivered: u64,
+    /// Messages the link dropped: cut by a partition, lost to its drop
+    /// probability, too large for it or dropped by an intervention.
+    pub dropped: u64,
 }
 
 /// A snapshot of a recent simulation event.
-#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct LogSnap {
+    /// The number of events logged on the bus before this one. Unlike
+    /// `event_id`, which names the simulation event that logged it, this
+    /// tells apart the events one simulation event logs.
+    #[serde(default)]
+    pub seq: u64,
     pub event_id: EventId,
     pub time: SimTime,
     pub event_type: String,
     pub details: String,
     pub node_id: Option<NodeId>,
+    /// Events spilled before severities existed read back as `Info`.
+    #[serde(default)]
+    pub severity: EventSeverity,
+}
+
+/// How much a logged event matters when looking back over a run, from
+/// per-message chatter up to injected faults and violations.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSeverity {
+    /// Per-message and per-timer events.
+    Debug,
+    #[default]
+    Info,
+    /// Something the run worked around, such as a deferred flood or a
+    /// dropped message from the future.
+    Warn,
+    /// Injected faults and invariant violations.
+    Fault,
+}
+
+impl EventSeverity {
+    /// Whether events of this severity are kept apart from the chatter in
+    /// the recent-events buffer, so chatter never evicts them.
+    pub fn is_retained(self) -> bool {
+        self >= Self::Warn
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Warn => "warn",
+            Self::Fault => "fault",
+        }
+    }
+}
+
+impl fmt::Display for EventSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// A snapshot of current metric values.
-#[derive(Clone, Debug, Default)]
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
 pub struct MetricsSnapshot {
     pub messages_sent: u64,
     pub messages_delivered: u64,
     pub timers_fired: u64,
     pub faults_injected: u64,
+    /// Total simulated time spent in store operations across all nodes.
+    pub store_time_ns: u64,
+    /// Sampled delays that fell outside their `DelaySpec` bounds.
+    pub delay_clamped: u64,
+    /// Client operations issued by the scenario, including to down nodes.
+    pub client_requests: u64,
+    /// Client operations a protocol answered.
+    pub client_responses: u64,
+    /// Total sim time from request to response over answered operations.
+    pub client_request_latency_ns: u64,
+    /// Messages delivered to a gray-failing node that it ignored.
+    pub gray_failure_ignored: u64,
+    /// Restarts that happened in the same millisecond as the one before.
+    pub simultaneous_restarts: u64,
+    /// Sim time from a message's creation to its delivery.
+    pub delivery_latency: LatencyPercentiles,
+    /// Sim time from a client request to its response.
+    pub client_request_latency: LatencyPercentiles,
+    /// The histograms protocols record through `log_histogram`, by key,
+    /// across all nodes, in the units the protocol records.
+    pub histograms: IndexMap<String, LatencyPercentiles>,
+}
+
+/// Percentiles of a latency distribution, in nanoseconds of sim time, read
+/// from a `Histogram`. All zero while nothing was recorded. Protocol
+/// histograms reuse it in their own units.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LatencyPercentiles {
+    pub count: u64,
+    pub p50: u64,
+    pub p95: u64,
+    pub p99: u64,
+}
+
+impl LatencyPercentiles {
+    pub fn of(histogram: &Histogram) -> Self {
+        let percentile = |q| histogram.percentile(q).unwrap_or(0);
+        Self { count: histogram.count(), p50: percentile(50.0), p95: percentile(95.0), p99: percentile(99.0) }
+    }
+}
+
+impl fmt::Display for LatencyPercentiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.count == 0 {
+            return write!(f, "none recorded");
+        }
+        let ms = |ns: u64| ns as f64 / 1e6;
+        write!(
+            f,
+            "p50 {:.3} ms, p95 {:.3} ms, p99 {:.3} ms over {}",
+            ms(self.p50),
+            ms(self.p95),
+            ms(self.p99),
+            self.count
+        )
+    }
 }
@@ -0,0 +1,235 @@
+//! # ftsim-engine::telemetry::spill
+//!
+//! An optional on-disk extension of the recent-events ring. Events the ring
+//! evicts are appended, one JSON object per line, to numbered segment files
+//! in a spill directory. Once the segments together exceed the configured
+//! size, the oldest are deleted, so the spill stays within a fixed disk
+//! budget however long the run is. `SpillReader` reads the segments back,
+//! one at a time, for post-run review.
+
+use super::snapshot::LogSnap;
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// The number of segments the size budget is split into. The spill holds
+/// between `SEGMENTS - 1` and `SEGMENTS` segments' worth of events once full.
+const SEGMENTS: u64 = 8;
+
+/// The smallest segment size, so tiny budgets do not rotate on every event.
+const MIN_SEGMENT_BYTES: u64 = 4 * 1024;
+
+const SEGMENT_PREFIX: &str = "events-";
+const SEGMENT_SUFFIX: &str = ".jsonl";
+
+/// Appends evicted events to a capped set of segment files.
+pub struct EventSpill {
+    dir: PathBuf,
+    max_bytes: u64,
+    segment_bytes: u64,
+    /// The sequence numbers and sizes of the segments on disk, oldest first.
+    /// The last one is being written.
+    segments: Vec<(u64, u64)>,
+    writer: BufWriter<File>,
+}
+
+impl EventSpill {
+    /// Starts a spill in `dir` that keeps at most about `max_bytes` of
+    /// events, creating the directory and deleting the segments of any
+    /// earlier spill in it.
+    pub fn create(dir: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        for (_, path) in segment_files(&dir)? {
+            fs::remove_file(path)?;
+        }
+        let segment_bytes = (max_bytes / SEGMENTS).max(MIN_SEGMENT_BYTES);
+        let writer = BufWriter::new(File::create(segment_path(&dir, 0))?);
+        Ok(Self {
+            dir,
+            max_bytes: max_bytes.max(segment_bytes),
+            segment_bytes,
+            segments: vec![(0, 0)],
+            writer,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the bytes the spill's segments take on disk, buffered writes
+    /// included.
+    pub fn len_bytes(&self) -> u64 {
+        self.segments.iter().map(|(_, len)| len).sum()
+    }
+
+    /// Appends an event, starting a new segment first if the current one is
+    /// full and deleting the oldest ones beyond the size budget.
+    pub fn append(&mut self, event: &LogSnap) -> io::Result<()> {
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+        let &(seq, len) = self.segments.last().expect("a segment is always open");
+        if len > 0 && len + line.len() as u64 > self.segment_bytes {
+            self.rotate(seq + 1)?;
+        }
+        self.writer.write_all(&line)?;
+        self.segments.last_mut().expect("a segment is always open").1 += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self, seq: u64) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer = BufWriter::new(File::create(segment_path(&self.dir, seq))?);
+        self.segments.push((seq, 0));
+        // The new segment counts towards the budget at its full size, so
+        // the spill never outgrows it while the segment fills
+        while self.segments.len() > 1 && self.len_bytes() + self.segment_bytes > self.max_bytes {
+            let (oldest, _) = self.segments.remove(0);
+            fs::remove_file(segment_path(&self.dir, oldest))?;
+        }
+        Ok(())
+    }
+
+    /// Writes buffered events out, so that a `SpillReader` sees them.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads the segments of a spill directory, oldest first. Segments are
+/// listed when the reader is opened and loaded one at a time on demand.
+pub struct SpillReader {
+    segments: Vec<PathBuf>,
+}
+
+impl SpillReader {
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let segments = segment_files(dir.as_ref())?.into_iter().map(|(_, path)| path).collect();
+        Ok(Self { segments })
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Loads the events of segment `index`, oldest first. A segment
+    /// deleted since the reader was opened reads as empty.
+    pub fn load_segment(&self, index: usize) -> io::Result<Vec<LogSnap>> {
+        let file = match File::open(&self.segments[index]) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        BufReader::new(file)
+            .lines()
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect()
+    }
+}
+
+fn segment_path(dir: &Path, seq: u64) -> PathBuf {
+    dir.join(format!("{}{:08}{}", SEGMENT_PREFIX, seq, SEGMENT_SUFFIX))
+}
+
+/// Lists the segment files in `dir` by sequence number.
+fn segment_files(dir: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let seq = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(SEGMENT_PREFIX)?.strip_suffix(SEGMENT_SUFFIX)?.parse().ok());
+        if let Some(seq) = seq {
+            segments.push((seq, path));
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{EventId, EventSeverity, SimTime};
+
+    fn event(event_id: u64) -> LogSnap {
+        LogSnap {
+            seq: event_id,
+            event_id: EventId(event_id),
+            time: event_id as SimTime * 1_000,
+            event_type: "MESSAGE_DELIVERED".to_string(),
+            details: format!("event {} with some padding to give it a size", event_id),
+            node_id: Some((event_id % 3) as u32),
+            severity: EventSeverity::Debug,
+        }
+    }
+
+    fn spill_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ftsim-spill-{}-{}", name, std::process::id()))
+    }
+
+    fn read_all(dir: &Path) -> Vec<u64> {
+        let reader = SpillReader::open(dir).unwrap();
+        (0..reader.segment_count())
+            .flat_map(|i| reader.load_segment(i).unwrap())
+            .map(|e| e.event_id.0)
+            .collect()
+    }
+
+    #[test]
+    fn test_segments_rotate_and_read_back_in_order() {
+        let dir = spill_dir("rotate");
+        let mut spill = EventSpill::create(&dir, 1 << 20).unwrap();
+        for id in 0..2_000 {
+            spill.append(&event(id)).unwrap();
+        }
+        spill.flush().unwrap();
+        let reader = SpillReader::open(&dir).unwrap();
+        assert!(reader.segment_count() > 1, "{} segments", reader.segment_count());
+        // Each segment is full before the next starts
+        let first = reader.load_segment(0).unwrap();
+        assert!(first.len() > 1);
+        assert_eq!(read_all(&dir), (0..2_000).collect::<Vec<_>>());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_spill_stays_within_its_size_budget() {
+        let dir = spill_dir("capped");
+        let max_bytes = 64 * 1024;
+        let mut spill = EventSpill::create(&dir, max_bytes).unwrap();
+        for id in 0..20_000 {
+            spill.append(&event(id)).unwrap();
+            assert!(spill.len_bytes() <= max_bytes, "{} bytes after event {}", spill.len_bytes(), id);
+        }
+        spill.flush().unwrap();
+        let on_disk: u64 = segment_files(&dir).unwrap().iter().map(|(_, p)| fs::metadata(p).unwrap().len()).sum();
+        assert_eq!(on_disk, spill.len_bytes());
+
+        // Only the oldest events were deleted: what is left is the tail
+        let kept = read_all(&dir);
+        assert!(kept.len() < 20_000 && kept.len() > 20_000 / 100);
+        assert_eq!(kept, (20_000 - kept.len() as u64..20_000).collect::<Vec<_>>());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_create_replaces_an_earlier_spill() {
+        let dir = spill_dir("replace");
+        let mut spill = EventSpill::create(&dir, 1 << 20).unwrap();
+        spill.append(&event(1)).unwrap();
+        spill.flush().unwrap();
+        fs::write(dir.join("notes.txt"), "kept").unwrap();
+
+        let mut spill = EventSpill::create(&dir, 1 << 20).unwrap();
+        spill.append(&event(2)).unwrap();
+        spill.flush().unwrap();
+        assert_eq!(read_all(&dir), [2]);
+        assert!(dir.join("notes.txt").exists());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
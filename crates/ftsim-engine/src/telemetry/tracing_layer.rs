@@ -2,16 +2,44 @@
 //!
 //! A custom `tracing::Layer` that enriches log records with simulation-specific
 //! context, such as the current simulation time, event ID, and node ID.
+//!
+//! Layers added before a formatting layer see each event first, so this one
+//! stamps the event with the bus's clock and the node it concerns and leaves
+//! the stamp in a thread-local for the formatter to take with `take_stamp`.
+//! The node is the event's own `node_id` field if it has one, then that of
+//! the innermost span carrying one, and failing both the node whose protocol
+//! the engine is running, which the bus keeps alongside its clock.
 
 use super::{TelemetryBus, TracingContext};
+use crate::prelude::{EventId, SimTime};
 use ftsim_types::id::NodeId;
-use std::sync::{Arc, Mutex};
+use std::{cell::Cell, fmt, sync::Arc};
 use tracing::{field::Field, span, Subscriber};
 use tracing_subscriber::layer::Context;
 use tracing_subscriber::Layer;
 
+thread_local! {
+    static CURRENT: Cell<Option<SimStamp>> = const { Cell::new(None) };
+}
+
+/// The simulation context of the event being formatted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimStamp {
+    pub sim_time: SimTime,
+    pub event_id: EventId,
+    pub node_id: Option<NodeId>,
+}
+
+/// Takes the stamp `SimContextLayer` put on the last event dispatched on
+/// this thread, which is the one being formatted when called from a
+/// formatter layered after it. Taking it leaves none behind, so an event
+/// that never passed the layer gets `None` rather than another's stamp.
+pub fn take_stamp() -> Option<SimStamp> {
+    CURRENT.with(Cell::take)
+}
+
 pub struct SimContextLayer {
-    context: Arc<Mutex<TracingContext>>,
+    context: Arc<TracingContext>,
 }
 
 impl SimContextLayer {
@@ -40,22 +68,24 @@ where
     }
 
     fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, S>) {
-        let sim_ctx = self.context.lock().unwrap();
-
-        // Find the node_id from the current span or its parents.
-        let node_id = ctx.current_span().id().and_then(|id| {
-            ctx.span_scope(id).and_then(|scope| {
-                scope.from_root().find_map(|span| {
-                    span.extensions().get::<NodeIdExtension>().map(|ext| ext.0)
-                })
+        let mut visitor = NodeIdVisitor::default();
+        event.record(&mut visitor);
+        // Failing that, the innermost span with a node_id, then the node
+        // whose protocol is running
+        let node_id = visitor
+            .node_id
+            .or_else(|| {
+                ctx.event_scope(event)?
+                    .find_map(|span| span.extensions().get::<NodeIdExtension>().map(|ext| ext.0))
             })
-        });
-
-        // The actual injection of fields is handled by the formatting layer,
-        // which can access this context. This layer's primary job is to
-        // propagate the node_id via span extensions.
-        // For direct field injection (less common), one would need a more
-        // complex visitor setup.
+            .or_else(|| self.context.node());
+        // Reading the clock takes no lock, so logging never waits on the bus.
+        let stamp = SimStamp {
+            sim_time: self.context.time(),
+            event_id: self.context.event_id(),
+            node_id,
+        };
+        CURRENT.with(|current| current.set(Some(stamp)));
     }
 }
 
@@ -69,10 +99,14 @@ struct NodeIdVisitor {
 impl tracing::field::Visit for NodeIdVisitor {
     fn record_u64(&mut self, field: &Field, value: u64) {
         if field.name() == "node_id" {
-            self.node_id = Some(value as NodeId);
+            self.node_id = NodeId::try_from(value).ok();
+        }
+    }
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if field.name() == "node_id" {
+            self.node_id = NodeId::try_from(value).ok();
         }
     }
-    fn record_i64(&mut self, _field: &Field, _value: i64) {}
     fn record_bool(&mut self, _field: &Field, _value: bool) {}
     fn record_str(&mut self, _field: &Field, _value: &str) {}
     fn record_error(
@@ -81,7 +115,30 @@ impl tracing::field::Visit for NodeIdVisitor {
         _value: &(dyn std::error::Error + 'static),
     ) {
     }
-    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+    /// Fields recorded with `%node_id` or `?node_id` arrive here.
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "node_id" {
+            let mut digits = NodeIdDigits::default();
+            self.node_id = fmt::write(&mut digits, format_args!("{:?}", value)).ok().and(digits.0);
+        }
+    }
+}
+
+/// Reads a `NodeId` digit by digit as it is formatted, so parsing a field
+/// needs no buffer. Anything but a digit, or a value past `NodeId::MAX`,
+/// fails the write.
+#[derive(Default)]
+struct NodeIdDigits(Option<NodeId>);
+
+impl fmt::Write for NodeIdDigits {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let digit = c.to_digit(10).ok_or(fmt::Error)?;
+            let value = self.0.unwrap_or(0).checked_mul(10).and_then(|v| v.checked_add(digit));
+            self.0 = Some(value.ok_or(fmt::Error)?);
+        }
+        Ok(())
+    }
 }
 
 struct NodeIdExtension(NodeId);
@@ -0,0 +1,788 @@
+//! # ftsim-engine::testkit
+//!
+//! Fluent assertions for protocol tests. A `Harness` wraps a simulation whose
+//! message journal keeps payloads, so expectations can decode the recorded
+//! deliveries with the protocol's codec and match them against a predicate:
+//!
+//! ```
+//! # use ftsim_engine::{prelude::*, testkit::Harness};
+//! # use ftsim_proto::protocols::raft_lite::{Message, RaftLite};
+//! let mut harness = Harness::cluster(3, 7, || boxed_dyn(RaftLite::default()));
+//! harness.run_until_ms(1_000);
+//! let leader = (0..3).find(|&n| harness.kv(n, "role") == Some("Leader".into())).unwrap();
+//! let follower = (leader + 1) % 3;
+//! harness
+//!     .expect_message(leader, follower, |m: &Message| matches!(m, Message::AppendEntries(_)))
+//!     .within_ms(200);
+//! harness
+//!     .expect_no_message(follower, leader, |m: &Message| matches!(m, Message::AppendEntries(_)))
+//!     .so_far();
+//! ```
+//!
+//! A failed expectation panics with the traffic to and from the two nodes
+//! around the window it checked.
+//!
+//! A `Script` is a protocol built from closures, for tests that only need
+//! nodes to send, arm timers or touch their store in a particular way.
+//!
+//! `assert_wire_compat` checks that a new version of a protocol decodes the
+//! payloads a harness recorded from the old one, and that the two versions
+//! decode each other's `sample_messages`.
+
+use crate::{
+    net::{MessageJournal, MessageRecord},
+    prelude::*,
+    report::SimulationReport,
+};
+use ftsim_proto::compat::check_wire_compat;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    fmt::{Debug, Write},
+    marker::PhantomData,
+    sync::Arc,
+};
+
+/// How far around the checked window failure messages show traffic.
+const CONTEXT_WINDOW: SimTime = 50_000_000;
+
+/// The most deliveries a failure message lists.
+const MAX_CONTEXT_LINES: usize = 20;
+
+/// A simulation under test.
+pub struct Harness {
+    sim: Simulation,
+}
+
+impl Harness {
+    /// Wraps a simulation that has not been initialized yet, installs a
+    /// message journal that keeps payloads, and initializes it.
+    pub fn new(mut sim: Simulation) -> Self {
+        sim.set_message_journal(MessageJournal::new().with_payloads());
+        sim.init();
+        Self { sim }
+    }
+
+    /// Builds a `World::full_mesh` of `nodes` nodes, each running a
+    /// protocol made by `factory`.
+    #[cfg(any(test, feature = "testutil"))]
+    pub fn cluster(nodes: usize, seed: u64, factory: impl Fn() -> Box<dyn ProtocolDyn>) -> Self {
+        let world = World::full_mesh(nodes, |_| factory());
+        let telemetry = TelemetryBus::detached(nodes, &TelemetrySpec::default());
+        Self::new(Simulation::new(seed, world, telemetry))
+    }
+
+    pub fn sim(&self) -> &Simulation {
+        &self.sim
+    }
+
+    pub fn sim_mut(&mut self) -> &mut Simulation {
+        &mut self.sim
+    }
+
+    /// Runs the simulation up to `ms` milliseconds of sim time.
+    pub fn run_until_ms(&mut self, ms: u64) -> SimulationReport {
+        self.sim.run_until(sim_from_ms(ms))
+    }
+
+    /// Returns the value a node last published for `key`.
+    pub fn kv(&self, node: NodeId, key: &str) -> Option<Value> {
+        let kvs = self.sim.telemetry().node_kvs(self.sim.world().nodes.len());
+        kvs.get(node as usize).and_then(|kv| kv.get(key)).cloned()
+    }
+
+    /// Expects a message from `from` to `to`, decoded as `M`, for which
+    /// `matching` returns true to have been delivered.
+    pub fn expect_message<M, F>(&mut self, from: NodeId, to: NodeId, matching: F) -> MessageExpectation<'_, M, F>
+    where
+        M: DeserializeOwned + Debug,
+        F: Fn(&M) -> bool,
+    {
+        MessageExpectation::new(self, from, to, matching, true)
+    }
+
+    /// Expects no such message to have been delivered.
+    pub fn expect_no_message<M, F>(&mut self, from: NodeId, to: NodeId, matching: F) -> MessageExpectation<'_, M, F>
+    where
+        M: DeserializeOwned + Debug,
+        F: Fn(&M) -> bool,
+    {
+        MessageExpectation::new(self, from, to, matching, false)
+    }
+
+    /// Asserts that a node last published `value` for `key`.
+    #[track_caller]
+    pub fn expect_kv(&self, node: NodeId, key: &str, value: impl Into<Value>) {
+        let value = value.into();
+        let actual = self.kv(node, key);
+        assert!(
+            actual.as_ref() == Some(&value),
+            "expected node {} to publish {} = {} at t={}, found {}",
+            node,
+            key,
+            value,
+            self.sim.now(),
+            actual.map_or("nothing".to_string(), |v| v.to_string())
+        );
+    }
+
+    /// Asserts that a node is in `status`.
+    #[track_caller]
+    pub fn expect_status(&self, node: NodeId, status: NodeStatus) {
+        let actual = self.sim.world().node(node).status;
+        assert_eq!(actual, status, "status of node {} at t={}", node, self.sim.now());
+    }
+
+    /// Returns the payloads of every recorded message of protocol `tag`,
+    /// as samples for `assert_wire_compat`.
+    pub fn recorded_payloads(&self, tag: ProtoTag) -> Vec<Vec<u8>> {
+        self.records()
+            .iter()
+            .filter(|r| r.proto_tag == tag)
+            .filter_map(|r| r.payload.as_ref().map(|p| p.to_vec()))
+            .collect()
+    }
+
+    fn records(&self) -> &[MessageRecord] {
+        self.sim
+            .message_journal()
+            .expect("harness simulations always journal messages")
+            .records()
+    }
+}
+
+/// A pending message expectation. Choose the window with `within_ms` or
+/// `so_far` to check it.
+#[must_use = "an expectation is only checked by `within_ms` or `so_far`"]
+pub struct MessageExpectation<'h, M, F> {
+    harness: &'h mut Harness,
+    from: NodeId,
+    to: NodeId,
+    matching: F,
+    /// Whether a matching message is expected, as opposed to forbidden.
+    present: bool,
+    _msg: PhantomData<fn() -> M>,
+}
+
+impl<'h, M, F> MessageExpectation<'h, M, F>
+where
+    M: DeserializeOwned + Debug,
+    F: Fn(&M) -> bool,
+{
+    fn new(harness: &'h mut Harness, from: NodeId, to: NodeId, matching: F, present: bool) -> Self {
+        Self {
+            harness,
+            from,
+            to,
+            matching,
+            present,
+            _msg: PhantomData,
+        }
+    }
+
+    /// Runs the simulation `ms` milliseconds further and checks the
+    /// deliveries made in that time.
+    #[track_caller]
+    pub fn within_ms(self, ms: u64) {
+        let start = self.harness.sim.now();
+        let end = start + sim_from_ms(ms);
+        self.harness.sim.run_until(end);
+        self.check(start, end, &format!("within {}ms of t={}", ms, start));
+    }
+
+    /// Checks every delivery made so far.
+    #[track_caller]
+    pub fn so_far(self) {
+        let now = self.harness.sim.now();
+        self.check(SIM_EPOCH, now, &format!("by t={}", now));
+    }
+
+    #[track_caller]
+    fn check(&self, start: SimTime, end: SimTime, window: &str) {
+        let found = self
+            .harness
+            .records()
+            .iter()
+            .filter(|r| r.src == self.from && r.dst == self.to)
+            .flat_map(|r| r.deliveries.iter().map(move |d| (r, d.time)))
+            .find(|(r, time)| {
+                (start..=end).contains(time) && decode::<M>(r).is_some_and(|m| (self.matching)(&m))
+            });
+        let (from, to) = (self.from, self.to);
+        match (self.present, found) {
+            (true, None) => panic!(
+                "expected a matching message from {} to {} {}, but none was delivered\n{}",
+                from,
+                to,
+                window,
+                self.traffic(start, end)
+            ),
+            (false, Some((record, time))) => panic!(
+                "expected no matching message from {} to {} {}, but message {} was delivered at t={}: {}\n{}",
+                from,
+                to,
+                window,
+                record.msg_id,
+                time,
+                describe::<M>(record),
+                self.traffic(start, end)
+            ),
+            _ => {}
+        }
+    }
+
+    /// Lists the deliveries to or from either node around the window.
+    fn traffic(&self, start: SimTime, end: SimTime) -> String {
+        let (lo, hi) = (start.saturating_sub(CONTEXT_WINDOW), end.saturating_add(CONTEXT_WINDOW));
+        let nodes = [self.from, self.to];
+        let mut deliveries: Vec<(SimTime, &MessageRecord)> = self
+            .harness
+            .records()
+            .iter()
+            .filter(|r| nodes.contains(&r.src) || nodes.contains(&r.dst))
+            .flat_map(|r| r.deliveries.iter().map(move |d| (d.time, r)))
+            .filter(|(time, _)| (lo..=hi).contains(time))
+            .collect();
+        deliveries.sort_by_key(|(time, r)| (*time, r.msg_id));
+
+        let mut out = format!("traffic involving nodes {} and {} from t={} to t={}:", self.from, self.to, lo, hi);
+        if deliveries.is_empty() {
+            out.push_str("\n  (none)");
+        }
+        for (time, record) in deliveries.iter().take(MAX_CONTEXT_LINES) {
+            let _ = write!(
+                out,
+                "\n  t={} #{} {} -> {}: {}",
+                time,
+                record.msg_id,
+                record.src,
+                record.dst,
+                describe::<M>(record)
+            );
+        }
+        if deliveries.len() > MAX_CONTEXT_LINES {
+            let _ = write!(out, "\n  ... and {} more", deliveries.len() - MAX_CONTEXT_LINES);
+        }
+        out
+    }
+}
+
+/// Asserts that the protocol versions made by `old` and `new` can decode
+/// each other's messages, listing the variants that fail if not. `samples`
+/// are payloads recorded from the old version, e.g. with
+/// `Harness::recorded_payloads`.
+#[track_caller]
+pub fn assert_wire_compat(
+    old: impl Fn() -> Box<dyn ProtocolDyn>,
+    new: impl Fn() -> Box<dyn ProtocolDyn>,
+    samples: &[Vec<u8>],
+) {
+    let report = check_wire_compat(old, new, samples);
+    assert!(report.is_compatible(), "wire incompatible: {}", report);
+}
+
+/// The tag `Script` protocols send their messages with.
+pub const SCRIPT_TAG: ProtoTag = ProtoTag(0xF0);
+
+type Hook<S, M> = Arc<dyn Fn(&mut S, &mut Ctx<M>) + Send + Sync>;
+type MessageHook<S, M> = Arc<dyn Fn(&mut S, &mut Ctx<M>, NodeId, M) + Send + Sync>;
+type ReplyHook<S, M> = Arc<dyn Fn(&mut S, &mut Ctx<M>, RequestId, NodeId, M) + Send + Sync>;
+type TimerHook<S, M> = Arc<dyn Fn(&mut S, &mut Ctx<M>, TimerId) + Send + Sync>;
+type PayloadHook<S, M> = Arc<dyn Fn(&mut S, &mut Ctx<M>, TimerId, &[u8]) + Send + Sync>;
+type FaultHook<S, M> = Arc<dyn Fn(&mut S, &mut Ctx<M>, FaultEvent) + Send + Sync>;
+type SendFailedHook<S, M> = Arc<dyn Fn(&mut S, &mut Ctx<M>, NodeId, MsgId, SendFailure) + Send + Sync>;
+type ClientHook<S, M> = Arc<dyn Fn(&mut S, &mut Ctx<M>, &ClientOp) -> Option<ClientResponse> + Send + Sync>;
+
+/// A protocol made of closures, for tests that need a node to do a few
+/// specific things rather than run a real protocol:
+///
+/// ```
+/// # use ftsim_engine::{prelude::*, testkit::{Harness, Script}};
+/// # use std::sync::{Arc, Mutex};
+/// let seen = Arc::new(Mutex::new(Vec::new()));
+/// let log = seen.clone();
+/// let script = Script::<u64>::new()
+///     .on_start(|_, ctx| if ctx.node_id() == 0 { ctx.send(1, &7).unwrap() })
+///     .on_message(move |_, _, src, msg| log.lock().unwrap().push((src, msg)));
+/// let mut harness = Harness::cluster(2, 7, || script.boxed());
+/// harness.run_until_ms(100);
+/// assert_eq!(*seen.lock().unwrap(), [(0, 7)]);
+/// ```
+///
+/// Every hook gets the node's own copy of the state `S` first, the way a
+/// protocol gets `&mut self`. Hooks left unset do nothing, except that
+/// replies, request timeouts and timers with a payload fall back to
+/// `on_message` and `on_timer` as they do for any `Protocol`. Clones share
+/// the hooks but not the state, so each node made from one starts afresh.
+pub struct Script<M, S = ()> {
+    name: &'static str,
+    state: S,
+    init: Option<Hook<S, M>>,
+    start: Option<Hook<S, M>>,
+    shutdown: Option<Hook<S, M>>,
+    message: Option<MessageHook<S, M>>,
+    reply: Option<ReplyHook<S, M>>,
+    timer: Option<TimerHook<S, M>>,
+    timer_payload: Option<PayloadHook<S, M>>,
+    request_timeout: Option<TimerHook<S, M>>,
+    fault: Option<FaultHook<S, M>>,
+    send_failure_delay: Option<SimTime>,
+    send_failed: Option<SendFailedHook<S, M>>,
+    client_request: Option<ClientHook<S, M>>,
+}
+
+impl<M> Script<M> {
+    /// A script without state that does nothing until given hooks.
+    pub fn new() -> Self {
+        Self::with_state(())
+    }
+}
+
+impl<M> Default for Script<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M, S> Script<M, S> {
+    /// A script whose nodes each start with a copy of `state`.
+    pub fn with_state(state: S) -> Self {
+        Self {
+            name: "script",
+            state,
+            init: None,
+            start: None,
+            shutdown: None,
+            message: None,
+            reply: None,
+            timer: None,
+            timer_payload: None,
+            request_timeout: None,
+            fault: None,
+            send_failure_delay: None,
+            send_failed: None,
+            client_request: None,
+        }
+    }
+
+    /// Names the protocol, as reports and logs show it.
+    pub fn named(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn on_init(mut self, f: impl Fn(&mut S, &mut Ctx<M>) + Send + Sync + 'static) -> Self {
+        self.init = Some(Arc::new(f));
+        self
+    }
+
+    pub fn on_start(mut self, f: impl Fn(&mut S, &mut Ctx<M>) + Send + Sync + 'static) -> Self {
+        self.start = Some(Arc::new(f));
+        self
+    }
+
+    pub fn on_shutdown(mut self, f: impl Fn(&mut S, &mut Ctx<M>) + Send + Sync + 'static) -> Self {
+        self.shutdown = Some(Arc::new(f));
+        self
+    }
+
+    pub fn on_message(mut self, f: impl Fn(&mut S, &mut Ctx<M>, NodeId, M) + Send + Sync + 'static) -> Self {
+        self.message = Some(Arc::new(f));
+        self
+    }
+
+    pub fn on_reply(
+        mut self,
+        f: impl Fn(&mut S, &mut Ctx<M>, RequestId, NodeId, M) + Send + Sync + 'static,
+    ) -> Self {
+        self.reply = Some(Arc::new(f));
+        self
+    }
+
+    pub fn on_timer(mut self, f: impl Fn(&mut S, &mut Ctx<M>, TimerId) + Send + Sync + 'static) -> Self {
+        self.timer = Some(Arc::new(f));
+        self
+    }
+
+    pub fn on_timer_payload(
+        mut self,
+        f: impl Fn(&mut S, &mut Ctx<M>, TimerId, &[u8]) + Send + Sync + 'static,
+    ) -> Self {
+        self.timer_payload = Some(Arc::new(f));
+        self
+    }
+
+    pub fn on_request_timeout(mut self, f: impl Fn(&mut S, &mut Ctx<M>, RequestId) + Send + Sync + 'static) -> Self {
+        self.request_timeout = Some(Arc::new(f));
+        self
+    }
+
+    pub fn on_fault(mut self, f: impl Fn(&mut S, &mut Ctx<M>, FaultEvent) + Send + Sync + 'static) -> Self {
+        self.fault = Some(Arc::new(f));
+        self
+    }
+
+    /// Hears about failed sends `delay` after they fail, as
+    /// `Protocol::send_failure_delay` opts in to.
+    pub fn on_send_failed(
+        mut self,
+        delay: SimTime,
+        f: impl Fn(&mut S, &mut Ctx<M>, NodeId, MsgId, SendFailure) + Send + Sync + 'static,
+    ) -> Self {
+        self.send_failure_delay = Some(delay);
+        self.send_failed = Some(Arc::new(f));
+        self
+    }
+
+    pub fn on_client_request(
+        mut self,
+        f: impl Fn(&mut S, &mut Ctx<M>, &ClientOp) -> Option<ClientResponse> + Send + Sync + 'static,
+    ) -> Self {
+        self.client_request = Some(Arc::new(f));
+        self
+    }
+}
+
+impl<M, S> Script<M, S>
+where
+    M: DeserializeOwned + Serialize + Debug + Send + 'static,
+    S: Clone + Send + 'static,
+{
+    /// A node's protocol, for `Harness::cluster` or `World::full_mesh`.
+    pub fn boxed(&self) -> Box<dyn ProtocolDyn> {
+        boxed_dyn(self.clone())
+    }
+}
+
+impl<M, S: Clone> Clone for Script<M, S> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name,
+            state: self.state.clone(),
+            init: self.init.clone(),
+            start: self.start.clone(),
+            shutdown: self.shutdown.clone(),
+            message: self.message.clone(),
+            reply: self.reply.clone(),
+            timer: self.timer.clone(),
+            timer_payload: self.timer_payload.clone(),
+            request_timeout: self.request_timeout.clone(),
+            fault: self.fault.clone(),
+            send_failure_delay: self.send_failure_delay,
+            send_failed: self.send_failed.clone(),
+            client_request: self.client_request.clone(),
+        }
+    }
+}
+
+impl<M, S> Protocol<M> for Script<M, S>
+where
+    M: DeserializeOwned + Serialize + Debug + Send + 'static,
+    S: Send + 'static,
+{
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn proto_tag(&self) -> ProtoTag {
+        SCRIPT_TAG
+    }
+
+    fn init(&mut self, ctx: &mut Ctx<M>) {
+        if let Some(f) = &self.init {
+            f(&mut self.state, ctx);
+        }
+    }
+
+    fn start(&mut self, ctx: &mut Ctx<M>) {
+        if let Some(f) = &self.start {
+            f(&mut self.state, ctx);
+        }
+    }
+
+    fn on_shutdown(&mut self, ctx: &mut Ctx<M>) {
+        if let Some(f) = &self.shutdown {
+            f(&mut self.state, ctx);
+        }
+    }
+
+    fn on_message(&mut self, ctx: &mut Ctx<M>, src: NodeId, msg: M) {
+        if let Some(f) = &self.message {
+            f(&mut self.state, ctx, src, msg);
+        }
+    }
+
+    fn on_reply(&mut self, ctx: &mut Ctx<M>, req: RequestId, src: NodeId, msg: M) {
+        match &self.reply {
+            Some(f) => f(&mut self.state, ctx, req, src, msg),
+            None => self.on_message(ctx, src, msg),
+        }
+    }
+
+    fn on_timer(&mut self, ctx: &mut Ctx<M>, timer: TimerId) {
+        if let Some(f) = &self.timer {
+            f(&mut self.state, ctx, timer);
+        }
+    }
+
+    fn on_timer_payload(&mut self, ctx: &mut Ctx<M>, timer: TimerId, payload: &[u8]) {
+        match &self.timer_payload {
+            Some(f) => f(&mut self.state, ctx, timer, payload),
+            None => self.on_timer(ctx, timer),
+        }
+    }
+
+    fn on_request_timeout(&mut self, ctx: &mut Ctx<M>, req: RequestId) {
+        match &self.request_timeout {
+            Some(f) => f(&mut self.state, ctx, req),
+            None => self.on_timer(ctx, req),
+        }
+    }
+
+    fn on_fault(&mut self, ctx: &mut Ctx<M>, fault: FaultEvent) {
+        if let Some(f) = &self.fault {
+            f(&mut self.state, ctx, fault);
+        }
+    }
+
+    fn send_failure_delay(&self) -> Option<SimTime> {
+        self.send_failure_delay
+    }
+
+    fn on_send_failed(&mut self, ctx: &mut Ctx<M>, dst: NodeId, msg_id: MsgId, reason: SendFailure) {
+        if let Some(f) = &self.send_failed {
+            f(&mut self.state, ctx, dst, msg_id, reason);
+        }
+    }
+
+    fn on_client_request(&mut self, ctx: &mut Ctx<M>, op: &ClientOp) -> Option<ClientResponse> {
+        self.client_request.as_ref().and_then(|f| f(&mut self.state, ctx, op))
+    }
+}
+
+/// Decodes a record's payload with the protocol codec.
+fn decode<M: DeserializeOwned>(record: &MessageRecord) -> Option<M> {
+    record.payload.as_ref().and_then(|p| decode_message(p).ok())
+}
+
+/// Describes a record's payload, decoded if it is an `M`.
+fn describe<M: DeserializeOwned + Debug>(record: &MessageRecord) -> String {
+    match (decode::<M>(record), &record.payload) {
+        (Some(msg), _) => format!("{:?}", msg),
+        (None, Some(payload)) => format!("<tag {}, {} bytes>", record.proto_tag.0, payload.len()),
+        (None, None) => format!("<tag {}>", record.proto_tag.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ftsim_proto::protocols::raft_lite::{Message, RaftLite};
+
+    fn raft_cluster() -> Harness {
+        Harness::cluster(5, 7, || boxed_dyn(RaftLite::default()))
+    }
+
+    fn leader(harness: &Harness) -> Option<NodeId> {
+        let nodes = harness.sim().world().nodes.len() as NodeId;
+        (0..nodes).find(|&n| harness.kv(n, "role") == Some(Value::from("Leader")))
+    }
+
+    #[test]
+    fn test_raft_leader_heartbeats_every_interval() {
+        let mut harness = raft_cluster();
+        harness.run_until_ms(1_000);
+        let leader = leader(&harness).expect("no leader elected");
+        let follower = (leader + 1) % 5;
+        // The heartbeat timer fires every 50ms with nothing to replicate
+        for _ in 0..10 {
+            harness
+                .expect_message(leader, follower, |m: &Message| matches!(m, Message::AppendEntries(a) if a.entries.is_empty()))
+                .within_ms(60);
+        }
+    }
+
+    /// Runs a RaftLite cluster of `nodes` nodes with the last `crashed` of
+    /// them down from the start, returning the leader elected, if any.
+    fn elect_with_crashed(nodes: usize, crashed: usize) -> Option<NodeId> {
+        let mut harness = Harness::cluster(nodes, 7, || boxed_dyn(RaftLite::default()));
+        let mut builder = Scenario::builder("crashed", nodes, ProtoTag(1));
+        for node in (nodes - crashed..nodes).map(|n| n as NodeId) {
+            builder = builder.at(1, Action::Crash { node, duration: SimDuration::Finite(sim_from_ms(60_000)) });
+        }
+        crate::scenario::load_and_schedule(harness.sim_mut(), &builder.build().unwrap()).unwrap();
+        harness.run_until_ms(2_000);
+        leader(&harness)
+    }
+
+    #[test]
+    fn test_expectations_on_raft_election() {
+        let mut harness = raft_cluster();
+        harness.run_until_ms(1_000);
+        let leader = leader(&harness).expect("no leader elected");
+        harness.expect_kv(leader, "role", "Leader");
+        let term = harness.kv(leader, "term").unwrap().as_f64().unwrap() as u64;
+        for follower in (0..5).filter(|&n| n != leader) {
+            harness.expect_status(follower, NodeStatus::Up);
+            harness
+                .expect_message(follower, leader, |m: &Message| matches!(m, Message::AppendEntriesReply(r) if r.success))
+                .so_far();
+            harness
+                .expect_no_message(follower, leader, |m: &Message| matches!(m, Message::AppendEntries(a) if a.term >= term))
+                .so_far();
+        }
+
+        // A crashed node neither receives nor sends anything.
+        let crashed = (leader + 1) % 5;
+        let crash_at = harness.sim().now() + 1;
+        let scenario = Scenario::builder("crash", 5, ProtoTag(1))
+            .at(crash_at, Action::Crash { node: crashed, duration: SimDuration::Finite(sim_from_ms(60_000)) })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(harness.sim_mut(), &scenario).unwrap();
+        harness.sim_mut().run_until(crash_at);
+        harness.expect_no_message(crashed, leader, |_: &Message| true).within_ms(1_000);
+        harness.expect_status(crashed, NodeStatus::Down);
+        // The rest of the cluster still hears the leader's heartbeats.
+        let other = (leader + 2) % 5;
+        harness
+            .expect_message(leader, other, |m: &Message| matches!(m, Message::AppendEntries(a) if a.term == term))
+            .within_ms(1_000);
+    }
+
+    #[test]
+    fn test_raft_quorum_follows_cluster_size() {
+        // Seven nodes need four votes
+        assert!(elect_with_crashed(7, 3).is_some());
+        assert_eq!(elect_with_crashed(7, 4), None);
+        // Three nodes need two
+        assert!(elect_with_crashed(3, 1).is_some());
+        assert_eq!(elect_with_crashed(3, 2), None);
+    }
+
+    #[test]
+    fn test_failed_expectation_lists_nearby_traffic() {
+        let mut harness = raft_cluster();
+        harness.run_until_ms(1_000);
+        let leader = leader(&harness).expect("no leader elected");
+        let follower = (leader + 1) % 5;
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            harness.expect_no_message(leader, follower, |m: &Message| matches!(m, Message::AppendEntries(_))).so_far();
+        }));
+        let panic = result.unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.contains(&format!("no matching message from {} to {}", leader, follower)), "{}", message);
+        assert!(message.contains("AppendEntries(AppendEntries {"), "{}", message);
+        assert!(message.contains(&format!("traffic involving nodes {} and {}", leader, follower)), "{}", message);
+        assert!(message.contains(&format!("{} -> {}", follower, leader)), "{}", message);
+    }
+
+    #[test]
+    fn test_raft_lite_is_wire_compatible_with_itself() {
+        let mut harness = raft_cluster();
+        harness.run_until_ms(1_000);
+        let samples = harness.recorded_payloads(ProtoTag(1));
+        assert!(!samples.is_empty());
+        assert_wire_compat(|| boxed_dyn(RaftLite::default()), || boxed_dyn(RaftLite::default()), &samples);
+    }
+
+    #[test]
+    fn test_script_nodes_keep_their_own_state() {
+        // Node 0 sends 1, 2 and 3 to node 1, which adds them up and passes
+        // each on to node 2 tenfold
+        let script = Script::<u64, u64>::with_state(0)
+            .on_start(|_, ctx| {
+                if ctx.node_id() == 0 {
+                    (1..=3).for_each(|n| ctx.send(1, &n).unwrap());
+                }
+            })
+            .on_message(|sum, ctx, src, n| {
+                *sum += n;
+                ctx.log_kv("sum", &sum.to_string());
+                if src == 0 {
+                    ctx.send(2, &(n * 10)).unwrap();
+                }
+            });
+        let mut harness = Harness::cluster(3, 7, || script.boxed());
+        harness.run_until_ms(10);
+        harness.expect_message(1, 2, |&n: &u64| n == 30).so_far();
+        harness.expect_kv(1, "sum", "6");
+        harness.expect_kv(2, "sum", "60");
+        assert_eq!(harness.kv(0, "sum"), None);
+        assert_eq!(harness.sim().world().node(0).protocols(), [("script", SCRIPT_TAG)]);
+    }
+
+    /// Two versions of a greeting protocol; v2 turned `Hello`'s `id` from a
+    /// number into a string but left `Bye` alone.
+    mod greeter {
+        use ftsim_proto::{Ctx, FaultEvent, Protocol};
+        use ftsim_types::{envelope::ProtoTag, id::{NodeId, TimerId}};
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, Serialize, Deserialize)]
+        pub enum V1 {
+            Hello { id: u64 },
+            Bye,
+        }
+
+        #[derive(Debug, Serialize, Deserialize)]
+        pub enum V2 {
+            Hello { id: String },
+            Bye,
+        }
+
+        pub struct Greeter<M> {
+            pub samples: fn() -> Vec<M>,
+        }
+
+        impl<M> Protocol<M> for Greeter<M>
+        where
+            M: serde::de::DeserializeOwned + Serialize + std::fmt::Debug + Send + 'static,
+        {
+            fn name(&self) -> &'static str {
+                "greeter"
+            }
+
+            fn proto_tag(&self) -> ProtoTag {
+                ProtoTag(0x6E)
+            }
+
+            fn init(&mut self, _ctx: &mut Ctx<M>) {}
+
+            fn on_message(&mut self, _ctx: &mut Ctx<M>, _src: NodeId, _msg: M) {}
+
+            fn on_timer(&mut self, _ctx: &mut Ctx<M>, _timer: TimerId) {}
+
+            fn on_fault(&mut self, _ctx: &mut Ctx<M>, _fault: FaultEvent) {}
+
+            fn sample_messages(&self) -> Vec<M> {
+                (self.samples)()
+            }
+        }
+    }
+
+    #[test]
+    fn test_wire_compat_names_the_changed_variant() {
+        use ftsim_proto::compat::{check_wire_compat, CompatDirection};
+        use greeter::{Greeter, V1, V2};
+
+        let v1 = || boxed_dyn(Greeter { samples: || vec![V1::Hello { id: 300 }, V1::Bye] });
+        let v2 = || boxed_dyn(Greeter { samples: || vec![V2::Hello { id: "a-much-longer-id".into() }, V2::Bye] });
+        // A payload recorded from v1
+        let recorded = vec![ftsim_proto::api::encode_message(&V1::Hello { id: 7 }).unwrap()];
+
+        let report = check_wire_compat(v1, v2, &recorded);
+        assert_eq!(report.checked, 5);
+        assert_eq!(report.failing_variants(CompatDirection::OldToNew), ["Hello"]);
+        assert_eq!(report.failures[0].count, 2);
+        // v1 reads v2's string length as the id, so only one way fails
+        assert!(report.failing_variants(CompatDirection::NewToOld).is_empty());
+
+        let result = std::panic::catch_unwind(|| assert_wire_compat(v1, v2, &recorded));
+        let panic = result.unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.contains("old -> new Hello failed 2 time(s)"), "{}", message);
+
+        assert!(check_wire_compat(v1, v1, &recorded).is_compatible());
+    }
+}
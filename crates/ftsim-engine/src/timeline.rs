@@ -0,0 +1,315 @@
+//! # ftsim-engine::timeline
+//!
+//! Collects node status transitions and notable moments (faults, leader
+//! changes, invariant violations) during a run, and renders them as a compact
+//! ASCII timeline: one row per node, one column per bucket of sim time.
+//!
+//! ```text
+//! time   0s                  1s                  2s
+//! n0     ----------xxxxxxxxxx~---------
+//! n1     --------L-----------!---------
+//! net    ----!-----------------!-------
+//! ```
+
+use crate::{events::FaultEventInternal, prelude::*};
+use std::fmt::Write;
+
+/// Default maximum width, in characters, of a rendered timeline.
+pub const DEFAULT_TIMELINE_WIDTH: usize = 100;
+
+/// Width of the row label column ("n12   ").
+const LABEL_WIDTH: usize = 7;
+
+/// A node changing status at a point in sim time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusChange {
+    pub time: SimTime,
+    pub node: NodeId,
+    pub status: NodeStatus,
+}
+
+/// The kind of a timeline marker, in increasing order of display priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MarkerKind {
+    Fault,
+    LeaderChange,
+    Violation,
+}
+
+impl MarkerKind {
+    fn glyph(self) -> char {
+        match self {
+            MarkerKind::Fault => '!',
+            MarkerKind::LeaderChange => 'L',
+            MarkerKind::Violation => 'V',
+        }
+    }
+}
+
+/// A notable moment. Markers without a node are drawn on the `net` row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Marker {
+    pub time: SimTime,
+    pub node: Option<NodeId>,
+    pub kind: MarkerKind,
+}
+
+/// Everything a timeline is rendered from.
+#[derive(Debug, Clone, Default)]
+pub struct Timeline {
+    pub num_nodes: usize,
+    pub transitions: Vec<StatusChange>,
+    pub markers: Vec<Marker>,
+    /// Whether each node last reported itself as leader.
+    leaders: Vec<bool>,
+}
+
+/// How to lay out a rendered timeline.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderOpts {
+    /// Sim time covered by one column. `None` fits the run into `width`.
+    pub bucket: Option<SimTime>,
+    /// Maximum line width in characters, including the row labels.
+    pub width: usize,
+}
+
+impl Default for RenderOpts {
+    fn default() -> Self {
+        Self {
+            bucket: None,
+            width: DEFAULT_TIMELINE_WIDTH,
+        }
+    }
+}
+
+impl Timeline {
+    pub fn new(num_nodes: usize) -> Self {
+        Self {
+            num_nodes,
+            leaders: vec![false; num_nodes],
+            ..Self::default()
+        }
+    }
+
+    /// Records a node status change.
+    pub fn record_status(&mut self, time: SimTime, node: NodeId, status: NodeStatus) {
+        self.transitions.push(StatusChange { time, node, status });
+    }
+
+    /// Records a marker.
+    pub fn record_marker(&mut self, time: SimTime, node: Option<NodeId>, kind: MarkerKind) {
+        self.markers.push(Marker { time, node, kind });
+    }
+
+    /// Records an injected fault against the node it targets, if any.
+    pub fn record_fault(&mut self, time: SimTime, fault: &FaultEventInternal) {
+        self.record_marker(time, fault.node_id(), MarkerKind::Fault);
+    }
+
+    /// Returns whether the node's last published role was leader (or primary).
+    pub fn is_leader(&self, node: NodeId) -> bool {
+        self.leaders.get(node as usize).copied().unwrap_or(false)
+    }
+
+    /// Observes a node's published `role`, marking the moment it becomes
+    /// leader (or primary).
+    pub fn observe_role(&mut self, time: SimTime, node: NodeId, role: &str) {
+        let is_leader = role.eq_ignore_ascii_case("leader") || role.eq_ignore_ascii_case("primary");
+        if let Some(was_leader) = self.leaders.get_mut(node as usize) {
+            if is_leader && !*was_leader {
+                self.markers.push(Marker {
+                    time,
+                    node: Some(node),
+                    kind: MarkerKind::LeaderChange,
+                });
+            }
+            *was_leader = is_leader;
+        }
+    }
+}
+
+/// Renders a timeline covering `[0, end]`. This is a pure function of its
+/// inputs so that it can be tested against golden output.
+pub fn render(timeline: &Timeline, end: SimTime, opts: &RenderOpts) -> String {
+    let max_cols = opts.width.saturating_sub(LABEL_WIDTH).max(1);
+    let span = end.max(1);
+    let min_bucket = span.div_ceil(max_cols as SimTime);
+    let bucket = opts.bucket.unwrap_or(min_bucket).max(min_bucket).max(1);
+    let cols = (span.div_ceil(bucket) as usize).max(1);
+    let col_of = |time: SimTime| ((time / bucket) as usize).min(cols - 1);
+
+    let mut out = String::new();
+    out.push_str(&time_axis(bucket, cols));
+
+    let mut transitions = timeline.transitions.clone();
+    transitions.sort_by_key(|t| t.time);
+    for node in 0..timeline.num_nodes as NodeId {
+        // Each column shows the worst status the node had during that bucket.
+        let mut row = vec![status_glyph(NodeStatus::Up); cols];
+        let mut status = NodeStatus::Up;
+        let mut from = 0;
+        for change in transitions.iter().filter(|t| t.node == node) {
+            fill(&mut row, from, col_of(change.time), status);
+            status = change.status;
+            from = col_of(change.time);
+        }
+        fill(&mut row, from, cols - 1, status);
+        overlay(&mut row, timeline, Some(node), &col_of);
+        let _ = writeln!(out, "{:<w$}{}", format!("n{}", node), row.iter().collect::<String>(), w = LABEL_WIDTH);
+    }
+
+    let mut net = vec![status_glyph(NodeStatus::Up); cols];
+    overlay(&mut net, timeline, None, &col_of);
+    let _ = writeln!(out, "{:<w$}{}", "net", net.iter().collect::<String>(), w = LABEL_WIDTH);
+    out.push_str("legend: - up  x down  ~ recovering  ! fault  L new leader  V violation\n");
+    out
+}
+
+fn status_glyph(status: NodeStatus) -> char {
+    match status {
+        NodeStatus::Up => '-',
+        NodeStatus::Down => 'x',
+        NodeStatus::Recovering => '~',
+    }
+}
+
+fn severity(glyph: char) -> u8 {
+    match glyph {
+        'x' => 2,
+        '~' => 1,
+        _ => 0,
+    }
+}
+
+/// Paints `status` over columns `from..=to`, keeping any worse status already there.
+fn fill(row: &mut [char], from: usize, to: usize, status: NodeStatus) {
+    let glyph = status_glyph(status);
+    for cell in &mut row[from..=to] {
+        if severity(glyph) >= severity(*cell) {
+            *cell = glyph;
+        }
+    }
+}
+
+/// Draws the markers for one row, highest-priority marker winning per column.
+fn overlay(row: &mut [char], timeline: &Timeline, node: Option<NodeId>, col_of: &dyn Fn(SimTime) -> usize) {
+    let mut best: Vec<Option<MarkerKind>> = vec![None; row.len()];
+    for marker in timeline.markers.iter().filter(|m| m.node == node) {
+        let slot = &mut best[col_of(marker.time)];
+        *slot = (*slot).max(Some(marker.kind));
+    }
+    for (cell, kind) in row.iter_mut().zip(best) {
+        if let Some(kind) = kind {
+            *cell = kind.glyph();
+        }
+    }
+}
+
+/// Builds the header line, labelling roughly every tenth column.
+fn time_axis(bucket: SimTime, cols: usize) -> String {
+    let mut axis = vec![' '; cols];
+    let step = 10;
+    let mut col = 0;
+    while col < cols {
+        let label = format_time(col as SimTime * bucket);
+        if col + label.len() <= cols {
+            for (i, c) in label.chars().enumerate() {
+                axis[col + i] = c;
+            }
+        }
+        col += step.max(label.len() + 1);
+    }
+    let axis: String = axis.into_iter().collect();
+    format!("{:<w$}{}\n", "time", axis.trim_end(), w = LABEL_WIDTH)
+}
+
+fn format_time(time: SimTime) -> String {
+    if time == 0 {
+        "0s".into()
+    } else if time % 1_000_000_000 == 0 {
+        format!("{}s", time / 1_000_000_000)
+    } else if time >= 1_000_000_000 {
+        format!("{:.1}s", time as f64 / 1e9)
+    } else if time % 1_000_000 == 0 {
+        format!("{}ms", time / 1_000_000)
+    } else {
+        format!("{}us", time / 1_000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::{Harness, Script, SCRIPT_TAG};
+    use ftsim_types::scenario::{Action, Scenario};
+
+    const SEC: SimTime = 1_000_000_000;
+
+    /// Each node's published role changes, as (sim time in ms, role).
+    const ROLES: [&[(u64, &str)]; 3] = [
+        &[(500, "leader"), (3_100, "follower"), (7_000, "leader")],
+        &[(3_200, "leader")],
+        &[],
+    ];
+
+    /// Publishes each node's `ROLES` on time, from a timer armed for the next one.
+    fn role_script() -> Script<(), usize> {
+        fn arm(next: usize, ctx: &mut Ctx<()>) {
+            if let Some(&(at, _)) = ROLES[ctx.node_id() as usize].get(next) {
+                ctx.set_timer(sim_from_ms(at) - ctx.now());
+            }
+        }
+        Script::with_state(0)
+            .on_start(|next, ctx| arm(*next, ctx))
+            .on_timer(|next, ctx, _| {
+                ctx.log_kv("role", ROLES[ctx.node_id() as usize][*next].1);
+                *next += 1;
+                arm(*next, ctx);
+            })
+    }
+
+    #[test]
+    fn test_render_golden() {
+        // Node 2 is down from 3s to 5s; leadership moves to node 1 at 3.2s,
+        // node 0 is cut off at 6s and claims leadership back at 7s, which
+        // stops the run.
+        let script = role_script();
+        let mut harness = Harness::cluster(3, 7, || script.boxed());
+        let scenario = Scenario::builder("timeline", 3, SCRIPT_TAG)
+            .at(3 * SEC, Action::Crash { node: 2, duration: SimDuration::Finite(2 * SEC) })
+            .at(6 * SEC, Action::Partition { sets: vec![vec![0]] })
+            .build()
+            .unwrap();
+        crate::scenario::load_and_schedule(harness.sim_mut(), &scenario).unwrap();
+        harness.sim_mut().add_invariant(crate::invariants::builtin("at_most_one_leader").unwrap());
+        harness.run_until_ms(10_000);
+
+        let opts = RenderOpts {
+            bucket: Some(SEC / 4),
+            width: 80,
+        };
+        let expected = "\
+time   0s        2.5s      5s        7.5s
+n0     --L-------------------------L-----------
+n1     ------------L---------------------------
+n2     ------------!xxxxxxx!-------------------
+net    ------------------------!---V-----------
+legend: - up  x down  ~ recovering  ! fault  L new leader  V violation
+";
+        assert_eq!(render(harness.sim().timeline(), 10 * SEC, &opts), expected);
+    }
+
+    #[test]
+    fn test_render_fits_width() {
+        let mut timeline = Timeline::new(1);
+        timeline.record_status(SEC, 0, NodeStatus::Down);
+        let opts = RenderOpts {
+            bucket: Some(1),
+            width: 40,
+        };
+        let rendered = render(&timeline, 100 * SEC, &opts);
+        assert!(rendered.lines().take(3).all(|line| line.len() <= 40));
+        // A one-bucket outage is still visible after widening the buckets.
+        assert!(rendered.lines().nth(1).unwrap().contains('x'));
+    }
+}
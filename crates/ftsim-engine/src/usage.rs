@@ -0,0 +1,193 @@
+//! # ftsim-engine::usage
+//!
+//! Measures what a run costs the machine running it: wall-clock time spent in
+//! the run loop, event throughput and peak resident memory. None of it is
+//! deterministic, so it is kept apart from the rest of `SimulationReport` and
+//! only attached when a caller asks for it with `Simulation::resource_usage`.
+
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+/// The shortest wall-clock window a peak events/sec sample is taken over;
+/// shorter windows are dominated by timer noise.
+const MIN_RATE_WINDOW: Duration = Duration::from_millis(100);
+
+/// What a run has cost so far.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ResourceUsage {
+    /// Wall-clock time spent in the run loop, including time paused.
+    pub wall_ns: u64,
+    pub events_processed: u64,
+    /// Events per wall-clock second, over the whole run.
+    pub events_per_sec: f64,
+    /// The best events/sec sustained over a window of at least 100ms, or
+    /// the average if the run was shorter.
+    pub peak_events_per_sec: f64,
+    /// The process's peak resident set size. Only known on Linux.
+    pub peak_rss_bytes: Option<u64>,
+    /// Wall-clock nanoseconds per profiler category, when profiling: each
+    /// kind of event handled, invariant checks and telemetry upkeep.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub profile: BTreeMap<String, u64>,
+}
+
+impl ResourceUsage {
+    /// Returns the `n` profiler categories that took longest, longest first.
+    pub fn top_categories(&self, n: usize) -> Vec<(&str, u64)> {
+        let mut categories: Vec<(&str, u64)> = self.profile.iter().map(|(name, ns)| (name.as_str(), *ns)).collect();
+        categories.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        categories.truncate(n);
+        categories
+    }
+}
+
+impl std::fmt::Display for ResourceUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "processed {} events in {}, {} events/s",
+            si(self.events_processed as f64),
+            duration(self.wall_ns),
+            si(self.events_per_sec)
+        )?;
+        if let Some(rss) = self.peak_rss_bytes {
+            write!(f, ", peak RSS {}MB", rss / (1024 * 1024))?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats a count the way people read it: `2.1M`, `147k`, `3.5k`, `12`.
+fn si(value: f64) -> String {
+    if value >= 1e6 {
+        format!("{:.1}M", value / 1e6)
+    } else if value >= 1e4 {
+        format!("{:.0}k", value / 1e3)
+    } else if value >= 1e3 {
+        format!("{:.1}k", value / 1e3)
+    } else {
+        format!("{:.0}", value)
+    }
+}
+
+fn duration(ns: u64) -> String {
+    if ns >= 1_000_000_000 {
+        format!("{:.1}s", ns as f64 / 1e9)
+    } else {
+        format!("{}ms", ns / 1_000_000)
+    }
+}
+
+/// Accumulates wall-clock time and throughput across calls to the run loop.
+#[derive(Debug, Default)]
+pub(crate) struct UsageMeter {
+    wall: Duration,
+    /// When the current rate window started, and the event count then.
+    window: Option<(Instant, u64)>,
+    peak_events_per_sec: f64,
+    /// Wall-clock time per profiler category, when profiling.
+    profile: Option<BTreeMap<&'static str, Duration>>,
+}
+
+impl UsageMeter {
+    /// Starts timing profiler categories.
+    pub fn enable_profile(&mut self) {
+        self.profile.get_or_insert_with(BTreeMap::new);
+    }
+
+    /// Starts timing a section to `charge` to a category; `None` unless
+    /// profiling, so that unprofiled runs do not read the clock.
+    pub fn start(&self) -> Option<Instant> {
+        self.profile.as_ref().map(|_| Instant::now())
+    }
+
+    /// Adds the time since `started` to `category`.
+    pub fn charge(&mut self, category: &'static str, started: Option<Instant>) {
+        if let (Some(profile), Some(started)) = (&mut self.profile, started) {
+            *profile.entry(category).or_default() += started.elapsed();
+        }
+    }
+
+    /// Starts timing a run loop that has executed `events` so far.
+    pub fn begin(&mut self, events: u64) -> Instant {
+        let now = Instant::now();
+        self.window = Some((now, events));
+        now
+    }
+
+    /// Closes the current rate window if it is long enough to count.
+    pub fn sample(&mut self, events: u64) {
+        let Some((started, at)) = self.window else {
+            return;
+        };
+        let elapsed = started.elapsed();
+        if elapsed >= MIN_RATE_WINDOW {
+            let rate = (events - at) as f64 / elapsed.as_secs_f64();
+            self.peak_events_per_sec = self.peak_events_per_sec.max(rate);
+            self.window = Some((Instant::now(), events));
+        }
+    }
+
+    /// Stops timing the run loop that `begin` returned `started` for.
+    pub fn end(&mut self, started: Instant, events: u64) {
+        self.sample(events);
+        self.window = None;
+        self.wall += started.elapsed();
+    }
+
+    pub fn usage(&self, events_processed: u64) -> ResourceUsage {
+        let secs = self.wall.as_secs_f64();
+        let events_per_sec = if secs > 0.0 { events_processed as f64 / secs } else { 0.0 };
+        ResourceUsage {
+            wall_ns: self.wall.as_nanos() as u64,
+            events_processed,
+            events_per_sec,
+            peak_events_per_sec: self.peak_events_per_sec.max(events_per_sec),
+            peak_rss_bytes: peak_rss_bytes(),
+            profile: self
+                .profile
+                .iter()
+                .flatten()
+                .map(|(category, time)| (category.to_string(), time.as_nanos() as u64))
+                .collect(),
+        }
+    }
+}
+
+/// Reads the peak resident set size (`VmHWM`) from `/proc/self/status`.
+#[cfg(target_os = "linux")]
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn peak_rss_bytes() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_line() {
+        let usage = ResourceUsage {
+            wall_ns: 14_300_000_000,
+            events_processed: 2_100_000,
+            events_per_sec: 146_853.0,
+            peak_events_per_sec: 150_000.0,
+            peak_rss_bytes: Some(312 * 1024 * 1024),
+            profile: BTreeMap::new(),
+        };
+        assert_eq!(
+            usage.to_string(),
+            "processed 2.1M events in 14.3s, 147k events/s, peak RSS 312MB"
+        );
+    }
+}
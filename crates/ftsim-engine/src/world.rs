@@ -3,7 +3,26 @@
 //! Defines the `World` struct, which is the top-level container for the
 //! simulation's state, including all nodes and the network that connects them.
 
-use crate::{net::Net, node::Node, prelude::*};
+use crate::{
+    net::{LinkFaultModel, Net},
+    node::{Node, NodeCheckpoint, NodeState},
+    prelude::*,
+};
+use std::collections::BTreeMap;
+
+/// A saved copy of every node's store, metadata and fault state plus every link's fault
+/// model. Protocol state and the event queue are not captured.
+pub struct WorldCheckpoint {
+    nodes: Vec<NodeCheckpoint>,
+    links: BTreeMap<LinkId, LinkFaultModel>,
+}
+
+/// A saved copy of every node's complete state, protocol included, plus
+/// every link's fault model.
+pub struct WorldState {
+    nodes: Vec<NodeState>,
+    links: BTreeMap<LinkId, LinkFaultModel>,
+}
 
 /// Represents the entire state of the simulated distributed system.
 pub struct World {
@@ -12,13 +31,143 @@ pub struct World {
 }
 
 impl World {
-    /// Creates an empty world (primarily for testing).
-    #[cfg(test)]
-    pub fn new() -> Self {
-        Self {
-            nodes: Vec::new(),
-            net: Net::from_topology(0, &TopologySpec::FullMesh),
+    /// Captures all node stores and metadata, node statuses, clock skews, and link fault models.
+    pub fn checkpoint(&self) -> WorldCheckpoint {
+        WorldCheckpoint {
+            nodes: self.nodes.iter().map(Node::checkpoint).collect(),
+            links: self.link_faults(),
+        }
+    }
+
+    /// Restores state captured by `checkpoint`.
+    pub fn restore(&mut self, checkpoint: WorldCheckpoint) {
+        for (node, saved) in self.nodes.iter_mut().zip(checkpoint.nodes) {
+            node.restore(saved);
+        }
+        self.restore_link_faults(checkpoint.links);
+    }
+
+    /// Captures everything `checkpoint` does plus protocol state, timers and
+    /// reassembly buffers. Fails if any node's protocol cannot be snapshotted.
+    pub fn save_state(&self) -> Result<WorldState, SimError> {
+        Ok(WorldState {
+            nodes: self.nodes.iter().map(Node::save_state).collect::<Result<_, _>>()?,
+            links: self.link_faults(),
+        })
+    }
+
+    /// Restores state captured by `save_state`. If a node fails to restore,
+    /// the nodes restored before it are rolled back, so the world is left
+    /// as it was.
+    pub fn restore_state(&mut self, state: WorldState) -> Result<(), SimError> {
+        let current = self.save_state()?;
+        if let Err(err) = self.restore_nodes(state.nodes) {
+            self.restore_nodes(current.nodes)
+                .expect("a node fails to restore the state it just saved");
+            return Err(err);
+        }
+        self.restore_link_faults(state.links);
+        Ok(())
+    }
+
+    fn restore_nodes(&mut self, nodes: Vec<NodeState>) -> Result<(), SimError> {
+        for (node, saved) in self.nodes.iter_mut().zip(nodes) {
+            node.restore_state(saved)?;
+        }
+        Ok(())
+    }
+
+    fn link_faults(&self) -> BTreeMap<LinkId, LinkFaultModel> {
+        self.net
+            .links
+            .iter()
+            .map(|(id, link)| (*id, link.faults.clone()))
+            .collect()
+    }
+
+    fn restore_link_faults(&mut self, links: BTreeMap<LinkId, LinkFaultModel>) {
+        for (id, faults) in links {
+            if let Some(link) = self.net.links.get_mut(&id) {
+                link.faults = faults;
+            }
+        }
+    }
+
+    /// Builds a world on `net` with one node per network node, each running
+    /// the protocol `proto` makes for it on an in-memory store, with its
+    /// outgoing links as peers.
+    ///
+    /// A two-node world with a lossy link:
+    ///
+    /// ```
+    /// # use ftsim_engine::{net::LinkFaultModel, prelude::*};
+    /// # use ftsim_proto::protocols::raft_lite::RaftLite;
+    /// let mut net = Net::empty(2);
+    /// net.connect(0, 1, LinkFaultModel { drop: Bernoulli(0.3), ..Default::default() })?;
+    /// let world = World::with_net(net, |_| boxed_dyn(RaftLite::default()));
+    /// assert_eq!(world.node(0).peers(), [1]);
+    /// # Ok::<(), String>(())
+    /// ```
+    #[cfg(any(test, feature = "testutil"))]
+    pub fn with_net(net: Net, mut proto: impl FnMut(NodeId) -> Box<dyn ProtocolDyn>) -> Self {
+        let nodes = (0..net.node_count() as NodeId)
+            .map(|id| Node::new(id, proto(id), Box::new(MemStore::new())))
+            .collect();
+        let mut world = Self { nodes, net };
+        for id in 0..world.nodes.len() as NodeId {
+            world.refresh_peers(id);
+        }
+        world
+    }
+
+    /// A world of a single node without any links.
+    #[cfg(any(test, feature = "testutil"))]
+    pub fn single_node(proto: Box<dyn ProtocolDyn>) -> Self {
+        let mut proto = Some(proto);
+        Self::with_net(Net::empty(1), |_| proto.take().unwrap())
+    }
+
+    /// A world of `n` nodes where each node is linked both ways to the nodes
+    /// before and after it.
+    #[cfg(any(test, feature = "testutil"))]
+    pub fn linear_chain(n: usize, proto: impl FnMut(NodeId) -> Box<dyn ProtocolDyn>) -> Self {
+        let mut net = Net::empty(n);
+        for id in 1..n as NodeId {
+            net.connect(id - 1, id, LinkFaultModel::default()).unwrap();
+            net.connect(id, id - 1, LinkFaultModel::default()).unwrap();
         }
+        Self::with_net(net, proto)
+    }
+
+    /// A world of `n` nodes linked to each other in both directions.
+    #[cfg(any(test, feature = "testutil"))]
+    pub fn full_mesh(n: usize, proto: impl FnMut(NodeId) -> Box<dyn ProtocolDyn>) -> Self {
+        Self::with_net(Net::from_topology(n, &TopologySpec::FullMesh), proto)
+    }
+
+    /// Adds or replaces the link from `src` to `dst`, updating `src`'s peers.
+    /// Fails as `Net::connect` does.
+    #[cfg(any(test, feature = "testutil"))]
+    pub fn connect(&mut self, src: NodeId, dst: NodeId, faults: LinkFaultModel) -> Result<LinkId, String> {
+        let id = self.net.connect(src, dst, faults)?;
+        self.refresh_peers(src);
+        Ok(id)
+    }
+
+    /// Removes the link from `src` to `dst`, updating `src`'s peers.
+    #[cfg(any(test, feature = "testutil"))]
+    pub fn disconnect(&mut self, src: NodeId, dst: NodeId) -> Option<crate::net::NetLink> {
+        let link = self.net.disconnect(src, dst);
+        self.refresh_peers(src);
+        link
+    }
+
+    /// Sets a node's peers to the destinations of its outgoing links.
+    #[cfg(any(test, feature = "testutil"))]
+    fn refresh_peers(&mut self, id: NodeId) {
+        let mut peers: Vec<NodeId> = self.net.peers_of(id).collect();
+        peers.sort_unstable();
+        self.node_mut(id).set_peers(peers);
     }
 
     /// Returns a reference to a node by its ID. Panics if the ID is invalid.
@@ -31,3 +180,95 @@ impl World {
         &mut self.nodes[id as usize]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use ftsim_proto::{api::boxed_dyn, protocols::raft_lite::RaftLite};
+
+    fn contents(world: &mut World) -> Vec<(Option<Bytes>, Vec<Bytes>)> {
+        world
+            .nodes
+            .iter_mut()
+            .map(|n| {
+                let view = n.store_view();
+                let kv = view.kv_get(b"k").unwrap();
+                let log = (0..4)
+                    .filter_map(|i| view.read_log(i).unwrap())
+                    .map(|r| r.data)
+                    .collect();
+                (kv, log)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_checkpoint_restore_is_byte_identical() {
+        let mut world = World::full_mesh(2, |_| boxed_dyn(RaftLite::default()));
+        for node in &mut world.nodes {
+            let view = node.store_view();
+            view.kv_put(Bytes::from_static(b"k"), Bytes::from_static(b"before")).unwrap();
+            view.append_log(LogRecord::new(1, Bytes::from_static(b"\x00\x01"))).unwrap();
+        }
+        let expected = contents(&mut world);
+        let checkpoint = world.checkpoint();
+
+        for node in &mut world.nodes {
+            let view = node.store_view();
+            view.kv_put(Bytes::from_static(b"k"), Bytes::from_static(b"after")).unwrap();
+            view.append_log(LogRecord::new(2, Bytes::from_static(b"new"))).unwrap();
+            node.status = NodeStatus::Down;
+            node.clock_skew_ns = 5;
+        }
+        for link in world.net.links.values_mut() {
+            link.faults.partitioned = true;
+        }
+
+        world.restore(checkpoint);
+        assert_eq!(contents(&mut world), expected);
+        assert!(world.nodes.iter().all(|n| n.status == NodeStatus::Up && n.clock_skew_ns == 0));
+        assert!(world.net.links.values().all(|l| !l.faults.partitioned));
+    }
+
+    #[test]
+    fn test_failed_restore_leaves_the_world_unchanged() {
+        use ftsim_proto::protocols::{lease_kv::LeaseKv, primary_backup::PrimaryBackup};
+        let saved = World::full_mesh(3, |_| boxed_dyn(PrimaryBackup::new())).save_state().unwrap();
+        // Node 2 cannot decode a primary-backup state
+        let mut world = World::full_mesh(3, |id| match id {
+            2 => boxed_dyn(LeaseKv::new()),
+            _ => boxed_dyn(PrimaryBackup::new()),
+        });
+        for node in &mut world.nodes {
+            node.store_view().kv_put(Bytes::from_static(b"k"), Bytes::from_static(b"kept")).unwrap();
+        }
+        let expected = contents(&mut world);
+
+        // Nodes 0 and 1 restored fine before node 2 failed, and are rolled back
+        assert!(matches!(world.restore_state(saved), Err(SimError::RestoreFailed { node: 2, .. })));
+        assert_eq!(contents(&mut world), expected);
+    }
+
+    #[test]
+    fn test_topology_builders_set_links_and_peers() {
+        let raft = |_: NodeId| boxed_dyn(RaftLite::default());
+        let single = World::single_node(raft(0));
+        assert_eq!(single.nodes.len(), 1);
+        assert!(single.net.links.is_empty());
+
+        let mut chain = World::linear_chain(4, raft);
+        assert_eq!(chain.net.links.len(), 6);
+        let peers: Vec<&[NodeId]> = chain.nodes.iter().map(|n| n.peers()).collect();
+        assert_eq!(peers, [&[1][..], &[0, 2], &[1, 3], &[2]]);
+
+        chain.connect(3, 0, LinkFaultModel::default()).unwrap();
+        assert_eq!(chain.node(3).peers(), [0, 2]);
+        assert_eq!(chain.disconnect(1, 0).unwrap().dst, 0);
+        assert_eq!(chain.node(1).peers(), [2]);
+
+        let mesh = World::full_mesh(3, raft);
+        assert_eq!(mesh.net.links.len(), 6);
+        assert_eq!(mesh.node(1).peers(), [0, 2]);
+    }
+}
@@ -0,0 +1,74 @@
+//! Checks the RNG and fault sampling pipeline against committed outputs for
+//! a fixed seed, so that a platform, compiler or dependency that maps a draw
+//! differently fails here rather than as a replay mismatch. Set
+//! `FTSIM_BLESS_VECTORS=1` to rewrite the file after an intended change.
+
+use ftsim_engine::{
+    net::sample_delay,
+    rng::{bernoulli, Recorder, RngDiscipline, RngStreams},
+};
+use ftsim_types::scenario::DelaySpec;
+use rand::{Rng, RngCore};
+use serde_json::{json, Value};
+
+const SEED: u64 = 0x5eed_f00d;
+const DRAWS: usize = 16;
+const VECTORS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/determinism_vectors.json");
+
+fn vectors() -> Value {
+    let mut streams = RngStreams::new(SEED);
+    let mut recorder = Recorder::new(SEED);
+    let site = recorder.site("net.delay.base", None);
+    // A fresh discipline per draw, as the engine makes them
+    let raw: Vec<u64> = (0..DRAWS).map(|_| RngDiscipline::new(&mut streams, &mut recorder, site).next_u64()).collect();
+
+    let specs = [
+        DelaySpec::Const(5_000_000),
+        DelaySpec::Uniform { lo: 1_000_000, hi: 50_000_000 },
+        DelaySpec::Normal { mu: 20_000_000.0, sigma: 5_000_000.0, min: None, max: None },
+        DelaySpec::Normal { mu: 1_234.9, sigma: 0.7, min: Some(1_000), max: Some(2_000) },
+        DelaySpec::Pareto { scale: 1e15, shape: 1.5, min: None, max: Some(5_000_000) },
+    ];
+    let delays: Vec<Value> = specs
+        .iter()
+        .map(|spec| {
+            let values: Vec<String> = (0..DRAWS)
+                .map(|_| sample_delay(RngDiscipline::new(&mut streams, &mut recorder, site), spec).delay.to_string())
+                .collect();
+            json!({ "spec": format!("{:?}", spec), "values": values })
+        })
+        .collect();
+
+    let ranges: Vec<u64> =
+        (0..DRAWS).map(|_| RngDiscipline::new(&mut streams, &mut recorder, site).gen_range(0..=999)).collect();
+
+    let trials: Vec<Value> = [0.0, 1e-6, 0.01, 0.1, 0.5, 0.9, 0.999_999, 1.0]
+        .iter()
+        .map(|&p| {
+            let outcomes: String = (0..64)
+                .map(|_| bernoulli(&mut RngDiscipline::new(&mut streams, &mut recorder, site), p))
+                .map(|hit| if hit { '1' } else { '0' })
+                .collect();
+            json!({ "p": p, "outcomes": outcomes })
+        })
+        .collect();
+
+    json!({ "seed": SEED, "raw_u64": raw, "delays": delays, "ranges": ranges, "trials": trials })
+}
+
+#[test]
+fn test_sampling_matches_committed_vectors() {
+    let actual = vectors();
+    if std::env::var_os("FTSIM_BLESS_VECTORS").is_some() {
+        std::fs::write(VECTORS, serde_json::to_string_pretty(&actual).unwrap() + "\n").unwrap();
+        return;
+    }
+    let expected: Value = serde_json::from_str(&std::fs::read_to_string(VECTORS).unwrap()).unwrap();
+    for section in ["seed", "raw_u64", "delays", "ranges", "trials"] {
+        assert_eq!(
+            actual[section], expected[section],
+            "`{}` differs from {}; this platform samples differently, which breaks replays across machines",
+            section, VECTORS
+        );
+    }
+}
@@ -20,6 +20,8 @@ mod tests {
     */
 
     #[test]
+    // Asserts a constant on purpose, as a placeholder
+    #[allow(clippy::assertions_on_constants)]
     fn placeholder_test() {
         // This test ensures the crate compiles.
         assert!(true);
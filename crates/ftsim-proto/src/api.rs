@@ -5,13 +5,15 @@
 //! trait object API (`ProtocolDyn`).
 
 use ftsim_types::{
+    client::{ClientOp, ClientResponse},
     envelope::ProtoTag,
     errors::CodecError,
-    id::{NodeId, TimerId},
-    scenario::StoreFaultKind,
+    id::{ClientRequestId, MsgId, NodeId, RequestId, TimerId},
+    scenario::{FaultKind, StoreFaultKind},
 };
 use serde::{de::DeserializeOwned, Serialize};
-use std::fmt::Debug;
+use std::{collections::BTreeMap, fmt::Debug, ops::RangeInclusive};
+use super::ctx_ext::Correlation;
 
 // --- Engine-Facing Trait ---
 
@@ -24,9 +26,22 @@ pub trait ProtocolDyn: Send {
     /// Returns the unique tag for this protocol's messages.
     fn proto_tag(&self) -> ProtoTag;
 
-    /// Called once when the node is initialized.
+    /// Called once when the node is initialized. The context is an
+    /// [`InitCtx`]: sending messages and setting timers is rejected here and
+    /// belongs in `start`.
     fn init(&mut self, ctx: &mut dyn ProtoCtx);
 
+    /// Called when the node begins participating, after `init`. This is where
+    /// protocols send their first messages and arm their first timers.
+    fn start(&mut self, _ctx: &mut dyn ProtoCtx) {}
+
+    /// Called on every up node when the engine ends the run, before the
+    /// invariants are checked a last time and the final report is taken.
+    /// Protocols publish end-of-run summaries here. Messages sent and timers
+    /// set are queued as usual, so they only take effect if the run is
+    /// resumed.
+    fn on_shutdown(&mut self, _ctx: &mut dyn ProtoCtx) {}
+
     /// Called when a message is received from another node.
     fn on_message(
         &mut self,
@@ -38,8 +53,64 @@ pub trait ProtocolDyn: Send {
     /// Called when a previously set timer fires.
     fn on_timer(&mut self, ctx: &mut dyn ProtoCtx, timer: TimerId);
 
+    /// Called instead of `on_timer` when a timer set with a payload fires.
+    /// Ignores the payload and calls `on_timer` by default.
+    fn on_timer_payload(&mut self, ctx: &mut dyn ProtoCtx, timer: TimerId, _payload: &[u8]) {
+        self.on_timer(ctx, timer);
+    }
+
     /// Called when a fault is injected into the node by the simulator.
     fn on_fault(&mut self, ctx: &mut dyn ProtoCtx, fault: FaultEvent);
+
+    /// How long after one of its sends fails the protocol hears about it
+    /// through `on_send_failed`, or `None`, the default, to not be told.
+    fn send_failure_delay(&self) -> Option<ftsim_types::time::SimTime> {
+        None
+    }
+
+    /// Called, if `send_failure_delay` opts in, when a message the protocol
+    /// sent to `dst` was dropped for `reason`. Not called if the node has
+    /// crashed since the send.
+    fn on_send_failed(&mut self, _ctx: &mut dyn ProtoCtx, _dst: NodeId, _msg_id: MsgId, _reason: SendFailure) {}
+
+    /// Called when a scenario issues a client operation to the node.
+    /// Returns the response, or `None` if the protocol does not answer it.
+    /// `ClientResponse::Pending` answers later, with
+    /// `ProtoCtx::respond_to_client`.
+    fn on_client_request(&mut self, _ctx: &mut dyn ProtoCtx, _op: &ClientOp) -> Option<ClientResponse> {
+        None
+    }
+
+    /// Serializes the protocol's in-memory state for `Simulation::save_state`.
+    /// Returns `None` if the protocol does not support snapshots.
+    fn snapshot_state(&self) -> Option<bytes::Bytes> {
+        None
+    }
+
+    /// Replaces the protocol's in-memory state with bytes produced by
+    /// `snapshot_state`.
+    fn restore_state(&mut self, _state: &[u8]) -> Result<(), CodecError> {
+        Err(CodecError(format!("{} does not support state snapshots", self.name())))
+    }
+
+    /// Names the kind of an encoded message, e.g. `AppendEntries`, so that
+    /// scenario interventions can target one kind. Returns `None` if the
+    /// bytes are not a message of this protocol.
+    fn message_variant(&self, _bytes: &[u8]) -> Option<String> {
+        None
+    }
+
+    /// Decodes `bytes` the way `on_message` would, without handling them,
+    /// for wire compatibility checks.
+    fn check_message(&self, _bytes: &[u8]) -> Result<(), CodecError> {
+        Err(CodecError(format!("{} cannot decode messages outside on_message", self.name())))
+    }
+
+    /// Returns encoded examples of the messages the protocol sends, for
+    /// wire compatibility checks.
+    fn sample_messages(&self) -> Vec<bytes::Bytes> {
+        Vec::new()
+    }
 }
 
 // --- Protocol-Author-Facing Trait ---
@@ -56,17 +127,144 @@ where
     /// Returns the unique tag for this protocol's messages.
     fn proto_tag(&self) -> ProtoTag;
 
-    /// Called once when the node is initialized.
+    /// Called once when the node is initialized. Must not send messages or
+    /// set timers; do that in `start`.
     fn init(&mut self, ctx: &mut super::ctx_ext::Ctx<M>);
 
+    /// Called when the node begins participating, after `init`.
+    fn start(&mut self, _ctx: &mut super::ctx_ext::Ctx<M>) {}
+
+    /// Called on every up node when the run ends, before the final
+    /// invariant check and report, e.g. to publish a summary with
+    /// `Ctx::log_kv`.
+    fn on_shutdown(&mut self, _ctx: &mut super::ctx_ext::Ctx<M>) {}
+
     /// Called when a message is received and successfully deserialized.
     fn on_message(&mut self, ctx: &mut super::ctx_ext::Ctx<M>, src: NodeId, msg: M);
 
     /// Called when a previously set timer fires.
     fn on_timer(&mut self, ctx: &mut super::ctx_ext::Ctx<M>, timer: TimerId);
 
+    /// Called instead of `on_message` when the reply to a request sent with
+    /// `Ctx::request` arrives before its timeout. Replies that come after
+    /// the timeout, or a second time, go to `on_message`. Calls
+    /// `on_message` by default.
+    fn on_reply(&mut self, ctx: &mut super::ctx_ext::Ctx<M>, _req: RequestId, src: NodeId, msg: M) {
+        self.on_message(ctx, src, msg);
+    }
+
+    /// Called instead of `on_timer` when a request sent with `Ctx::request`
+    /// got no reply within its timeout. Calls `on_timer` with the request's
+    /// ID, which is its timeout timer's ID, by default.
+    fn on_request_timeout(&mut self, ctx: &mut super::ctx_ext::Ctx<M>, req: RequestId) {
+        self.on_timer(ctx, req);
+    }
+
+    /// Called instead of `on_timer` when a timer set with
+    /// `Ctx::set_timer_with` fires, with the encoded payload; decode it
+    /// with `decode_message`. Calls `on_timer` by default.
+    fn on_timer_payload(&mut self, ctx: &mut super::ctx_ext::Ctx<M>, timer: TimerId, _payload: &[u8]) {
+        self.on_timer(ctx, timer);
+    }
+
     /// Called when a fault is injected into the node by the simulator.
     fn on_fault(&mut self, ctx: &mut super::ctx_ext::Ctx<M>, fault: FaultEvent);
+
+    /// Opts in to `on_send_failed`: how long after a send fails, for want
+    /// of a usable link, the protocol hears about it, as a TCP connection
+    /// would report an error. `None`, the default, opts out.
+    fn send_failure_delay(&self) -> Option<ftsim_types::time::SimTime> {
+        None
+    }
+
+    /// Called when a message sent to `dst` was dropped for `reason`, once
+    /// `send_failure_delay` has passed. `msg_id` is the one
+    /// `Ctx::last_sent_msg_id` returned right after the send. Never called
+    /// for probabilistic drops, or if the node crashed in the meantime.
+    fn on_send_failed(&mut self, _ctx: &mut super::ctx_ext::Ctx<M>, _dst: NodeId, _msg_id: MsgId, _reason: SendFailure) {}
+
+    /// Called when a scenario issues a client operation to the node.
+    /// Returns the response, or `None` if the protocol does not answer it.
+    /// To answer later, e.g. once a write is replicated, keep
+    /// `Ctx::client_request_id` and return `ClientResponse::Pending`.
+    fn on_client_request(&mut self, _ctx: &mut super::ctx_ext::Ctx<M>, _op: &ClientOp) -> Option<ClientResponse> {
+        None
+    }
+
+    /// Serializes the protocol's state, usually with `encode_message(self)`.
+    /// Protocols that return `None` cannot be branched with `save_state`.
+    fn snapshot_state(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Restores state produced by `snapshot_state`.
+    fn restore_state(&mut self, _state: &[u8]) -> Result<(), CodecError> {
+        Err(CodecError(format!("{} does not support state snapshots", self.name())))
+    }
+
+    /// Returns one example of each kind of message the protocol sends, for
+    /// `compat::check_wire_compat` to decode with other versions.
+    fn sample_messages(&self) -> Vec<M> {
+        Vec::new()
+    }
+
+    /// The version of the protocol's wire format. Versions from 1 up prefix
+    /// every message with the version, so that a node can tell which
+    /// layout a peer running another version sent; 0, the default, sends
+    /// bare messages. Versions of a protocol that exchange messages must
+    /// all be versioned.
+    fn wire_version(&self) -> u16 {
+        0
+    }
+
+    /// Decodes a message sent at wire `version`, which is not this
+    /// protocol's own, e.g. by reading the old layout and converting it.
+    /// Rejects other versions by default.
+    fn decode_compat(&self, version: u16, _bytes: &[u8]) -> Result<M, CodecError> {
+        Err(CodecError(format!(
+            "{} speaks wire version {}, not {}",
+            self.name(),
+            self.wire_version(),
+            version
+        )))
+    }
+}
+
+// --- Message Codec ---
+
+/// Encodes a message the way `Ctx::send` puts it on the wire.
+pub fn encode_message<M: Serialize>(msg: &M) -> Result<Vec<u8>, CodecError> {
+    postcard::to_allocvec(msg).map_err(|e| CodecError(format!("Serialization failed: {}", e)))
+}
+
+/// Decodes a payload the way `Protocol<M>` implementations receive it.
+pub fn decode_message<M: DeserializeOwned>(bytes: &[u8]) -> Result<M, CodecError> {
+    postcard::from_bytes(bytes).map_err(|e| CodecError(format!("Deserialization failed: {}", e)))
+}
+
+/// Encodes a message for wire version `version`: as `(version, msg)`, or
+/// bare for version 0.
+pub fn encode_versioned<M: Serialize>(version: u16, msg: &M) -> Result<Vec<u8>, CodecError> {
+    if version == 0 {
+        return encode_message(msg);
+    }
+    encode_message(&(version, msg))
+}
+
+/// Splits a payload encoded by `encode_versioned` with a nonzero version
+/// into the version and the encoded message.
+pub fn split_version(bytes: &[u8]) -> Result<(u16, &[u8]), CodecError> {
+    postcard::take_from_bytes(bytes).map_err(|e| CodecError(format!("Missing wire version: {}", e)))
+}
+
+/// Returns the variant name a message's `Debug` output starts with, e.g.
+/// `AppendEntries` for `AppendEntries(AppendEntries { term: 1, .. })`.
+pub fn message_variant<M: Debug>(msg: &M) -> String {
+    let debug = format!("{:?}", msg);
+    let end = debug
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(debug.len());
+    debug[..end].to_string()
 }
 
 // --- Adapter to bridge Protocol<M> to ProtocolDyn ---
@@ -77,9 +275,34 @@ where
     M: DeserializeOwned + Serialize + Debug + Send + 'static,
 {
     inner: P,
+    requests: super::ctx_ext::Requests,
     _phantom: std::marker::PhantomData<M>,
 }
 
+impl<P, M> ProtocolAdapter<P, M>
+where
+    P: Protocol<M>,
+    M: DeserializeOwned + Serialize + Debug + Send + 'static,
+{
+    /// The tag and wire version the protocol's messages are sent with.
+    fn wire(&self) -> (ProtoTag, u16) {
+        (self.inner.proto_tag(), self.inner.wire_version())
+    }
+
+    /// Decodes a payload, handing ones sent at another wire version to
+    /// `Protocol::decode_compat`.
+    fn decode(&self, bytes: &[u8]) -> Result<M, CodecError> {
+        let current = self.inner.wire_version();
+        if current == 0 {
+            return decode_message(bytes);
+        }
+        match split_version(bytes)? {
+            (version, payload) if version == current => decode_message(payload),
+            (version, payload) => self.inner.decode_compat(version, payload),
+        }
+    }
+}
+
 impl<P, M> ProtocolDyn for ProtocolAdapter<P, M>
 where
     P: Protocol<M> + Send,
@@ -94,36 +317,126 @@ where
     }
 
     fn init(&mut self, ctx: &mut dyn ProtoCtx) {
-        let tag = self.inner.proto_tag();
-        let mut wrapped_ctx = super::ctx_ext::Ctx::<M>::new(ctx, tag);
+        let (tag, version) = self.wire();
+        let mut wrapped_ctx = super::ctx_ext::Ctx::<M>::new(ctx, tag, version, &mut self.requests);
         self.inner.init(&mut wrapped_ctx);
     }
 
+    fn start(&mut self, ctx: &mut dyn ProtoCtx) {
+        let (tag, version) = self.wire();
+        let mut wrapped_ctx = super::ctx_ext::Ctx::<M>::new(ctx, tag, version, &mut self.requests);
+        self.inner.start(&mut wrapped_ctx);
+    }
+
+    fn on_shutdown(&mut self, ctx: &mut dyn ProtoCtx) {
+        let (tag, version) = self.wire();
+        let mut wrapped_ctx = super::ctx_ext::Ctx::<M>::new(ctx, tag, version, &mut self.requests);
+        self.inner.on_shutdown(&mut wrapped_ctx);
+    }
+
     fn on_message(
         &mut self,
         ctx: &mut dyn ProtoCtx,
         src: NodeId,
         bytes: &[u8],
     ) -> Result<(), CodecError> {
-        let msg: M = postcard::from_bytes(bytes)
-            .map_err(|e| CodecError(format!("Deserialization failed: {}", e)))?;
-        let tag = self.inner.proto_tag();
-        let mut wrapped_ctx = super::ctx_ext::Ctx::<M>::new(ctx, tag);
-        self.inner.on_message(&mut wrapped_ctx, src, msg);
+        let msg = self.decode(bytes)?;
+        let (tag, version) = self.wire();
+        let correlation = ctx.message_meta().and_then(|meta| Correlation::decode(meta.trace_id));
+        match correlation {
+            Some(Correlation::Reply(req)) if self.requests.complete(req, src) => {
+                ctx.cancel_timer(req);
+                let mut wrapped_ctx = super::ctx_ext::Ctx::<M>::new(ctx, tag, version, &mut self.requests);
+                self.inner.on_reply(&mut wrapped_ctx, req, src, msg);
+            }
+            _ => {
+                self.requests.replying_to = match correlation {
+                    Some(Correlation::Request(req)) => Some((src, req)),
+                    _ => None,
+                };
+                let mut wrapped_ctx = super::ctx_ext::Ctx::<M>::new(ctx, tag, version, &mut self.requests);
+                self.inner.on_message(&mut wrapped_ctx, src, msg);
+                self.requests.replying_to = None;
+            }
+        }
         Ok(())
     }
 
     fn on_timer(&mut self, ctx: &mut dyn ProtoCtx, timer: TimerId) {
-        let tag = self.inner.proto_tag();
-        let mut wrapped_ctx = super::ctx_ext::Ctx::<M>::new(ctx, tag);
-        self.inner.on_timer(&mut wrapped_ctx, timer);
+        let (tag, version) = self.wire();
+        let timed_out = self.requests.pending.remove(&timer).is_some();
+        let mut wrapped_ctx = super::ctx_ext::Ctx::<M>::new(ctx, tag, version, &mut self.requests);
+        if timed_out {
+            self.inner.on_request_timeout(&mut wrapped_ctx, timer);
+        } else {
+            self.inner.on_timer(&mut wrapped_ctx, timer);
+        }
+    }
+
+    fn on_timer_payload(&mut self, ctx: &mut dyn ProtoCtx, timer: TimerId, payload: &[u8]) {
+        let (tag, version) = self.wire();
+        let mut wrapped_ctx = super::ctx_ext::Ctx::<M>::new(ctx, tag, version, &mut self.requests);
+        self.inner.on_timer_payload(&mut wrapped_ctx, timer, payload);
     }
 
     fn on_fault(&mut self, ctx: &mut dyn ProtoCtx, fault: FaultEvent) {
-        let tag = self.inner.proto_tag();
-        let mut wrapped_ctx = super::ctx_ext::Ctx::<M>::new(ctx, tag);
+        if matches!(fault, FaultEvent::NodeCrashed) {
+            // A crash drops the timeout timers, so nothing can complete.
+            self.requests.pending.clear();
+        }
+        let (tag, version) = self.wire();
+        let mut wrapped_ctx = super::ctx_ext::Ctx::<M>::new(ctx, tag, version, &mut self.requests);
         self.inner.on_fault(&mut wrapped_ctx, fault);
     }
+
+    fn send_failure_delay(&self) -> Option<ftsim_types::time::SimTime> {
+        self.inner.send_failure_delay()
+    }
+
+    fn on_send_failed(&mut self, ctx: &mut dyn ProtoCtx, dst: NodeId, msg_id: MsgId, reason: SendFailure) {
+        let (tag, version) = self.wire();
+        let mut wrapped_ctx = super::ctx_ext::Ctx::<M>::new(ctx, tag, version, &mut self.requests);
+        self.inner.on_send_failed(&mut wrapped_ctx, dst, msg_id, reason);
+    }
+
+    fn on_client_request(&mut self, ctx: &mut dyn ProtoCtx, op: &ClientOp) -> Option<ClientResponse> {
+        let (tag, version) = self.wire();
+        let mut wrapped_ctx = super::ctx_ext::Ctx::<M>::new(ctx, tag, version, &mut self.requests);
+        self.inner.on_client_request(&mut wrapped_ctx, op)
+    }
+
+    /// The protocol's own state, followed by its requests in flight, so that
+    /// replies to them still reach `on_reply` after a restore.
+    fn snapshot_state(&self) -> Option<bytes::Bytes> {
+        let state = self.inner.snapshot_state()?;
+        encode_message(&(state, &self.requests.pending)).ok().map(Into::into)
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), CodecError> {
+        let (state, pending): (Vec<u8>, BTreeMap<RequestId, NodeId>) = decode_message(state)?;
+        self.inner.restore_state(&state)?;
+        self.requests.pending = pending;
+        self.requests.replying_to = None;
+        Ok(())
+    }
+
+    fn message_variant(&self, bytes: &[u8]) -> Option<String> {
+        self.decode(bytes).ok().map(|msg| message_variant(&msg))
+    }
+
+    fn check_message(&self, bytes: &[u8]) -> Result<(), CodecError> {
+        self.decode(bytes).map(|_| ())
+    }
+
+    fn sample_messages(&self) -> Vec<bytes::Bytes> {
+        let version = self.inner.wire_version();
+        self.inner
+            .sample_messages()
+            .iter()
+            .filter_map(|msg| encode_versioned(version, msg).ok())
+            .map(Into::into)
+            .collect()
+    }
 }
 
 /// A helper function to erase the concrete message type of a `Protocol<M>`
@@ -135,10 +448,96 @@ where
 {
     Box::new(ProtocolAdapter {
         inner: p,
+        requests: Default::default(),
         _phantom: std::marker::PhantomData,
     })
 }
 
+/// Compatibility shim for protocols written before the init/start split that
+/// send messages or set timers from `init`. The wrapped protocol's `init` is
+/// deferred to `start`, where side effects are allowed.
+pub fn legacy_init(p: Box<dyn ProtocolDyn>) -> Box<dyn ProtocolDyn> {
+    Box::new(LegacyInit { inner: p })
+}
+
+struct LegacyInit {
+    inner: Box<dyn ProtocolDyn>,
+}
+
+impl ProtocolDyn for LegacyInit {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn proto_tag(&self) -> ProtoTag {
+        self.inner.proto_tag()
+    }
+
+    fn init(&mut self, _ctx: &mut dyn ProtoCtx) {}
+
+    fn start(&mut self, ctx: &mut dyn ProtoCtx) {
+        self.inner.init(ctx);
+        self.inner.start(ctx);
+    }
+
+    fn on_shutdown(&mut self, ctx: &mut dyn ProtoCtx) {
+        self.inner.on_shutdown(ctx);
+    }
+
+    fn on_message(
+        &mut self,
+        ctx: &mut dyn ProtoCtx,
+        src: NodeId,
+        bytes: &[u8],
+    ) -> Result<(), CodecError> {
+        self.inner.on_message(ctx, src, bytes)
+    }
+
+    fn on_timer(&mut self, ctx: &mut dyn ProtoCtx, timer: TimerId) {
+        self.inner.on_timer(ctx, timer);
+    }
+
+    fn on_timer_payload(&mut self, ctx: &mut dyn ProtoCtx, timer: TimerId, payload: &[u8]) {
+        self.inner.on_timer_payload(ctx, timer, payload);
+    }
+
+    fn on_fault(&mut self, ctx: &mut dyn ProtoCtx, fault: FaultEvent) {
+        self.inner.on_fault(ctx, fault);
+    }
+
+    fn send_failure_delay(&self) -> Option<ftsim_types::time::SimTime> {
+        self.inner.send_failure_delay()
+    }
+
+    fn on_send_failed(&mut self, ctx: &mut dyn ProtoCtx, dst: NodeId, msg_id: MsgId, reason: SendFailure) {
+        self.inner.on_send_failed(ctx, dst, msg_id, reason);
+    }
+
+    fn on_client_request(&mut self, ctx: &mut dyn ProtoCtx, op: &ClientOp) -> Option<ClientResponse> {
+        self.inner.on_client_request(ctx, op)
+    }
+
+    fn snapshot_state(&self) -> Option<bytes::Bytes> {
+        self.inner.snapshot_state()
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), CodecError> {
+        self.inner.restore_state(state)
+    }
+
+    fn message_variant(&self, bytes: &[u8]) -> Option<String> {
+        self.inner.message_variant(bytes)
+    }
+
+    fn check_message(&self, bytes: &[u8]) -> Result<(), CodecError> {
+        self.inner.check_message(bytes)
+    }
+
+    fn sample_messages(&self) -> Vec<bytes::Bytes> {
+        self.inner.sample_messages()
+    }
+}
+
 // --- Engine-Provided Context Trait ---
 
 /// This trait defines the interface that the simulation engine provides to protocols.
@@ -146,19 +545,378 @@ where
 /// that a protocol can invoke (side effects).
 pub trait ProtoCtx {
     fn send_raw(&mut self, dst: NodeId, proto_tag: ProtoTag, bytes: bytes::Bytes);
+    /// Like `send_raw`, stamping the envelope's `trace_id`, which the
+    /// receiver reads back from `MessageMeta`. Contexts that do not carry
+    /// trace IDs send the message without it.
+    fn send_traced_raw(&mut self, dst: NodeId, proto_tag: ProtoTag, bytes: bytes::Bytes, _trace_id: u64) {
+        self.send_raw(dst, proto_tag, bytes);
+    }
     fn broadcast_raw(
         &mut self,
         proto_tag: ProtoTag,
         bytes: bytes::Bytes,
         filter: Option<&dyn Fn(NodeId) -> bool>,
     );
-    fn set_timer(&mut self, after: ftsim_types::time::SimTime) -> TimerId;
+    /// Like `send_raw`, with the message leaving the sender `delay` from
+    /// now. The link's faults apply as they are when it leaves, and it never
+    /// leaves if the node crashes first. Contexts that cannot hold messages
+    /// back send them immediately.
+    fn send_raw_after(&mut self, dst: NodeId, proto_tag: ProtoTag, bytes: bytes::Bytes, _delay: ftsim_types::time::SimTime) {
+        self.send_raw(dst, proto_tag, bytes);
+    }
+    /// Like `broadcast_raw`, with every message leaving `delay` from now.
+    fn broadcast_raw_after(
+        &mut self,
+        proto_tag: ProtoTag,
+        bytes: bytes::Bytes,
+        _delay: ftsim_types::time::SimTime,
+        filter: Option<&dyn Fn(NodeId) -> bool>,
+    ) {
+        self.broadcast_raw(proto_tag, bytes, filter);
+    }
+    /// Sets a timer that fires after `after`. A `payload` is handed to
+    /// `on_timer_payload` when it fires; the engine holds it until then, or
+    /// until the timer is canceled or the node crashes.
+    fn set_timer_raw(&mut self, after: ftsim_types::time::SimTime, payload: Option<bytes::Bytes>) -> TimerId;
+    fn set_timer(&mut self, after: ftsim_types::time::SimTime) -> TimerId {
+        self.set_timer_raw(after, None)
+    }
+    /// Sets a timer that fires every `period`, first after one period, until
+    /// it is canceled or the node crashes. Every firing keeps the same ID and
+    /// `payload`. A zero period fires once.
+    fn set_periodic_timer_raw(&mut self, period: ftsim_types::time::SimTime, payload: Option<bytes::Bytes>) -> TimerId;
     fn cancel_timer(&mut self, timer: TimerId) -> bool;
+    /// Returns the time left until a pending timer fires, or `None` if it is
+    /// unknown, canceled, or has already fired. Clock skew shifts `now()` and
+    /// the fire time alike, so the remaining time is the same in every clock domain.
+    fn timer_remaining(&self, timer: TimerId) -> Option<ftsim_types::time::SimTime>;
+    /// Returns all of this node's pending timers with their remaining time, soonest first.
+    fn pending_timers(&self) -> Vec<(TimerId, ftsim_types::time::SimTime)>;
+    /// Pushes a pending timer's deadline back by `additional`, keeping its
+    /// ID. Returns `false` if the timer is not pending.
+    fn extend_timer(&mut self, timer: TimerId, additional: ftsim_types::time::SimTime) -> bool;
+    /// Sets a watermark: a timer that fires at `at`, a time on this node's
+    /// clock as returned by `now()`, and is delivered through `on_timer`.
+    ///
+    /// Guarantee: every message to this node whose delivery time is `<= at`
+    /// and that was sent before the watermark fires is dispatched before
+    /// the watermark, including deliveries at exactly `at` that were
+    /// scheduled after the watermark was set. Messages sent from the
+    /// watermark's own handler, or later, are not covered. Times in the past
+    /// are clamped to now. The returned ID can be canceled or extended like
+    /// any timer.
+    fn set_watermark(&mut self, at: ftsim_types::time::SimTime) -> TimerId;
     fn now(&self) -> ftsim_types::time::SimTime;
     fn node_id(&self) -> NodeId;
+    /// Returns the nodes this node has links to, in ascending order and
+    /// without itself. Contexts that do not know the topology return none.
+    fn peers(&self) -> Vec<NodeId> {
+        Vec::new()
+    }
+    /// Returns the number of nodes in the cluster, this one included. By
+    /// default, this node and its peers.
+    fn cluster_size(&self) -> usize {
+        self.peers().len() + 1
+    }
     fn store(&mut self) -> Box<dyn StoreView + '_>;
     fn rng_u64(&mut self) -> u64;
+    /// Returns a uniform draw from `range`. Panics if it is empty.
+    ///
+    /// By default, draws from `rng_u64` until one falls below the largest
+    /// multiple of the range's size, so that no value is favored.
+    fn rng_range(&mut self, range: RangeInclusive<u64>) -> u64 {
+        let (lo, hi) = range.into_inner();
+        assert!(lo <= hi, "empty range {}..={}", lo, hi);
+        let Some(size) = (hi - lo).checked_add(1) else {
+            return self.rng_u64();
+        };
+        let limit = u64::MAX - u64::MAX % size;
+        loop {
+            let draw = self.rng_u64();
+            if draw < limit {
+                return lo + draw % size;
+            }
+        }
+    }
+    /// Returns `true` with probability `p`. Panics if `p` is outside 0..=1.
+    fn rng_bool(&mut self, p: f64) -> bool {
+        assert!((0.0..=1.0).contains(&p), "probability {} is outside 0..=1", p);
+        // The top 53 bits, as a uniform draw from [0, 1)
+        ((self.rng_u64() >> 11) as f64) / ((1u64 << 53) as f64) < p
+    }
+    /// Returns a uniform index below `len`, to pick one of `len` items.
+    /// Panics if `len` is 0.
+    fn rng_pick(&mut self, len: usize) -> usize {
+        assert!(len > 0, "cannot pick from no items");
+        self.rng_range(0..=len as u64 - 1) as usize
+    }
+    /// Returns a uniformly shuffled order of `0..len`.
+    fn rng_permutation(&mut self, len: usize) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..len).collect();
+        for i in (1..len).rev() {
+            order.swap(i, self.rng_pick(i + 1));
+        }
+        order
+    }
     fn log_kv(&mut self, key: &'static str, val: &str);
+    fn log_kv_pinned(&mut self, key: &'static str, val: &str);
+    /// Like `log_kv`, but keeps the value's JSON type, so that numbers and
+    /// booleans need no parsing. By default, logs the value as text.
+    fn log_kv_value(&mut self, key: &'static str, val: serde_json::Value) {
+        match val {
+            serde_json::Value::String(text) => self.log_kv(key, &text),
+            other => self.log_kv(key, &other.to_string()),
+        }
+    }
+    /// Like `log_kv_pinned`, but keeps the value's JSON type. By default,
+    /// logs the value as text.
+    fn log_kv_value_pinned(&mut self, key: &'static str, val: serde_json::Value) {
+        match val {
+            serde_json::Value::String(text) => self.log_kv_pinned(key, &text),
+            other => self.log_kv_pinned(key, &other.to_string()),
+        }
+    }
+    /// Records a sample of a numeric metric, such as a term or a commit
+    /// index, at the current sim time. Non-finite values are ignored.
+    fn log_metric(&mut self, key: &'static str, value: f64);
+    /// Records an observation, such as a latency, into the histogram metric
+    /// `key`, labeled with this node. Unlike `log_metric`, only the
+    /// distribution is kept, not the samples over time. Contexts without
+    /// metrics ignore it.
+    fn log_histogram(&mut self, _key: &'static str, _value: f64) {}
+    /// Returns what the engine knows about the message being handled, or
+    /// `None` outside `on_message`.
+    fn message_meta(&self) -> Option<MessageMeta> {
+        None
+    }
+    /// Returns the ID of the last message this node sent, for matching
+    /// `on_send_failed` reports to sends. Contexts that do not assign IDs
+    /// return `None`.
+    fn last_sent_msg_id(&self) -> Option<MsgId> {
+        None
+    }
+    /// Returns the faults announced to this node that have not happened
+    /// yet, soonest first. Contexts without a scenario return none.
+    fn pending_maintenance(&self) -> Vec<Maintenance> {
+        Vec::new()
+    }
+    /// Reads the node's engine-managed metadata, such as `META_INCARNATION`.
+    /// It lives apart from the store, survives crashes and is untouched by
+    /// store faults; protocols cannot write it. Contexts without a node
+    /// return `None`.
+    fn node_meta_get(&self, _key: &str) -> Option<String> {
+        None
+    }
+    /// Returns the ID of the client request being handled, or `None`
+    /// outside `on_client_request` and in contexts that cannot answer
+    /// requests later.
+    fn client_request_id(&self) -> Option<ClientRequestId> {
+        None
+    }
+    /// Answers client request `request`, which this node's
+    /// `on_client_request` left pending. Requests that are not pending on
+    /// this node, e.g. because it has crashed since, are ignored, as they
+    /// are by contexts without client requests.
+    fn respond_to_client(&mut self, _request: ClientRequestId, _response: ClientResponse) {}
+}
+
+/// Engine-side facts about a delivered message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageMeta {
+    pub msg_id: MsgId,
+    /// The sender's clock, skew included, when the message was sent.
+    pub sent_at: ftsim_types::time::SimTime,
+    /// Set when `sent_at` is further ahead of this node's clock than the
+    /// scenario's `future_message_policy` allows and the policy delivers
+    /// such messages anyway.
+    pub from_future: bool,
+    /// The envelope's trace ID, zero if the sender set none. `Ctx::request`
+    /// uses it to match replies to requests.
+    pub trace_id: u64,
+}
+
+/// The custom KV that `Ctx::publish_state` stores a protocol's structured
+/// state under.
+pub const STATE_KEY: &str = "state";
+
+/// The custom KV anti-entropy protocols publish the hash of their
+/// replicated state under. The engine tracks whether the nodes agree on it.
+pub const REPLICA_HASH_KEY: &str = "replica_hash";
+
+/// The node metadata key of how many times the node has restarted, in
+/// decimal.
+pub const META_INCARNATION: &str = "incarnation";
+
+/// The node metadata key of the node's UUID, which the seed and the node's
+/// ID determine.
+pub const META_UUID: &str = "uuid";
+
+/// The node metadata key of the boundary between the durable and the
+/// volatile part of the node's store log, as a log length in decimal. The
+/// engine moves it on every durable write, and a crash cuts the log back to
+/// it. Absent for stores that cannot report their log length.
+pub const META_DURABLE_LOG_LEN: &str = "store.durable_log_len";
+
+/// The timer ID handed back when `set_timer` is rejected during init. It
+/// never fires.
+pub const REJECTED_TIMER: TimerId = TimerId::MAX;
+
+/// The restricted context passed to `init`. Storage, RNG and KV logging are
+/// forwarded, but sending messages and setting timers are rejected: they
+/// panic in debug builds and are logged and dropped in release builds.
+pub struct InitCtx<'a> {
+    inner: &'a mut dyn ProtoCtx,
+}
+
+impl<'a> InitCtx<'a> {
+    pub fn new(inner: &'a mut dyn ProtoCtx) -> Self {
+        Self { inner }
+    }
+
+    fn reject(&self, op: &'static str) {
+        let node_id = self.inner.node_id();
+        if cfg!(debug_assertions) {
+            panic!("node {} attempted {} during init; move it to start()", node_id, op);
+        }
+        tracing::error!(node_id, op, "Side effect rejected during init; move it to start()");
+    }
+}
+
+impl ProtoCtx for InitCtx<'_> {
+    fn send_raw(&mut self, _dst: NodeId, _proto_tag: ProtoTag, _bytes: bytes::Bytes) {
+        self.reject("send");
+    }
+
+    fn send_traced_raw(&mut self, _dst: NodeId, _proto_tag: ProtoTag, _bytes: bytes::Bytes, _trace_id: u64) {
+        self.reject("send");
+    }
+
+    fn broadcast_raw(
+        &mut self,
+        _proto_tag: ProtoTag,
+        _bytes: bytes::Bytes,
+        _filter: Option<&dyn Fn(NodeId) -> bool>,
+    ) {
+        self.reject("broadcast");
+    }
+
+    fn send_raw_after(&mut self, _dst: NodeId, _proto_tag: ProtoTag, _bytes: bytes::Bytes, _delay: ftsim_types::time::SimTime) {
+        self.reject("send_after");
+    }
+
+    fn broadcast_raw_after(
+        &mut self,
+        _proto_tag: ProtoTag,
+        _bytes: bytes::Bytes,
+        _delay: ftsim_types::time::SimTime,
+        _filter: Option<&dyn Fn(NodeId) -> bool>,
+    ) {
+        self.reject("broadcast_after");
+    }
+
+    fn set_timer_raw(&mut self, _after: ftsim_types::time::SimTime, _payload: Option<bytes::Bytes>) -> TimerId {
+        self.reject("set_timer");
+        REJECTED_TIMER
+    }
+
+    fn set_periodic_timer_raw(&mut self, _period: ftsim_types::time::SimTime, _payload: Option<bytes::Bytes>) -> TimerId {
+        self.reject("set_periodic_timer");
+        REJECTED_TIMER
+    }
+
+    fn cancel_timer(&mut self, timer: TimerId) -> bool {
+        self.inner.cancel_timer(timer)
+    }
+
+    fn set_watermark(&mut self, _at: ftsim_types::time::SimTime) -> TimerId {
+        self.reject("set_watermark");
+        REJECTED_TIMER
+    }
+
+    fn timer_remaining(&self, timer: TimerId) -> Option<ftsim_types::time::SimTime> {
+        self.inner.timer_remaining(timer)
+    }
+
+    fn pending_timers(&self) -> Vec<(TimerId, ftsim_types::time::SimTime)> {
+        self.inner.pending_timers()
+    }
+
+    fn extend_timer(&mut self, _timer: TimerId, _additional: ftsim_types::time::SimTime) -> bool {
+        self.reject("extend_timer");
+        false
+    }
+
+    fn now(&self) -> ftsim_types::time::SimTime {
+        self.inner.now()
+    }
+
+    fn node_id(&self) -> NodeId {
+        self.inner.node_id()
+    }
+
+    fn peers(&self) -> Vec<NodeId> {
+        self.inner.peers()
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.inner.cluster_size()
+    }
+
+    fn store(&mut self) -> Box<dyn StoreView + '_> {
+        self.inner.store()
+    }
+
+    fn rng_u64(&mut self) -> u64 {
+        self.inner.rng_u64()
+    }
+
+    fn rng_range(&mut self, range: RangeInclusive<u64>) -> u64 {
+        self.inner.rng_range(range)
+    }
+
+    fn rng_bool(&mut self, p: f64) -> bool {
+        self.inner.rng_bool(p)
+    }
+
+    fn rng_pick(&mut self, len: usize) -> usize {
+        self.inner.rng_pick(len)
+    }
+
+    fn rng_permutation(&mut self, len: usize) -> Vec<usize> {
+        self.inner.rng_permutation(len)
+    }
+
+    fn log_kv(&mut self, key: &'static str, val: &str) {
+        self.inner.log_kv(key, val);
+    }
+
+    fn log_kv_pinned(&mut self, key: &'static str, val: &str) {
+        self.inner.log_kv_pinned(key, val);
+    }
+
+    fn log_kv_value(&mut self, key: &'static str, val: serde_json::Value) {
+        self.inner.log_kv_value(key, val);
+    }
+
+    fn log_kv_value_pinned(&mut self, key: &'static str, val: serde_json::Value) {
+        self.inner.log_kv_value_pinned(key, val);
+    }
+
+    fn log_metric(&mut self, key: &'static str, value: f64) {
+        self.inner.log_metric(key, value);
+    }
+
+    fn log_histogram(&mut self, key: &'static str, value: f64) {
+        self.inner.log_histogram(key, value);
+    }
+
+    fn pending_maintenance(&self) -> Vec<Maintenance> {
+        self.inner.pending_maintenance()
+    }
+
+    fn node_meta_get(&self, key: &str) -> Option<String> {
+        self.inner.node_meta_get(key)
+    }
 }
 
 /// A view into the node's persistent storage.
@@ -172,19 +930,115 @@ pub trait StoreView {
     ) -> Result<(), ftsim_types::errors::StoreError>;
     fn kv_get(&mut self, k: &[u8]) -> Result<Option<bytes::Bytes>, ftsim_types::errors::StoreError>;
     fn fsync(&mut self) -> Result<(), ftsim_types::errors::StoreError>;
+    /// Replaces the stored snapshot. A store holds at most one snapshot. Like
+    /// any other write, it only survives a crash once fsynced.
+    fn write_snapshot(
+        &mut self,
+        meta: SnapshotMeta,
+        data: bytes::Bytes,
+    ) -> Result<(), ftsim_types::errors::StoreError>;
+    fn read_snapshot(
+        &mut self,
+    ) -> Result<Option<(SnapshotMeta, bytes::Bytes)>, ftsim_types::errors::StoreError>;
+    /// Atomically drops all log entries with an index `<= idx`. Reading a
+    /// dropped index afterwards returns `StoreError::Compacted`. A crash
+    /// before the next fsync brings the durable entries back.
+    fn compact_log_up_to(&mut self, idx: LogIndex) -> Result<(), ftsim_types::errors::StoreError>;
+}
+
+impl<S: StoreView + ?Sized> StoreView for &mut S {
+    fn append_log(&mut self, rec: LogRecord) -> Result<LogIndex, ftsim_types::errors::StoreError> {
+        (**self).append_log(rec)
+    }
+    fn read_log(&mut self, idx: LogIndex) -> Result<Option<LogRecord>, ftsim_types::errors::StoreError> {
+        (**self).read_log(idx)
+    }
+    fn kv_put(
+        &mut self,
+        k: bytes::Bytes,
+        v: bytes::Bytes,
+    ) -> Result<(), ftsim_types::errors::StoreError> {
+        (**self).kv_put(k, v)
+    }
+    fn kv_get(&mut self, k: &[u8]) -> Result<Option<bytes::Bytes>, ftsim_types::errors::StoreError> {
+        (**self).kv_get(k)
+    }
+    fn fsync(&mut self) -> Result<(), ftsim_types::errors::StoreError> {
+        (**self).fsync()
+    }
+    fn write_snapshot(
+        &mut self,
+        meta: SnapshotMeta,
+        data: bytes::Bytes,
+    ) -> Result<(), ftsim_types::errors::StoreError> {
+        (**self).write_snapshot(meta, data)
+    }
+    fn read_snapshot(
+        &mut self,
+    ) -> Result<Option<(SnapshotMeta, bytes::Bytes)>, ftsim_types::errors::StoreError> {
+        (**self).read_snapshot()
+    }
+    fn compact_log_up_to(&mut self, idx: LogIndex) -> Result<(), ftsim_types::errors::StoreError> {
+        (**self).compact_log_up_to(idx)
+    }
+}
+
+/// Describes the log prefix covered by a snapshot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SnapshotMeta {
+    pub last_included_index: LogIndex,
+    pub last_included_term: u64,
 }
 
 #[derive(Clone, Debug)]
 pub struct LogRecord {
     pub term: u64,
     pub data: bytes::Bytes,
+    /// Checksum over `term` and `data`, set by stores with checksums enabled.
+    pub checksum: Option<u64>,
+}
+
+impl LogRecord {
+    /// Creates a record without a checksum.
+    pub fn new(term: u64, data: bytes::Bytes) -> Self {
+        Self {
+            term,
+            data,
+            checksum: None,
+        }
+    }
+
+    /// Computes the checksum of this record's contents (64-bit FNV-1a).
+    pub fn compute_checksum(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+        self.term
+            .to_le_bytes()
+            .iter()
+            .chain(self.data.iter())
+            .fold(FNV_OFFSET, |hash, b| (hash ^ *b as u64).wrapping_mul(FNV_PRIME))
+    }
+
+    /// Stamps the record with the checksum of its current contents.
+    pub fn seal(&mut self) {
+        self.checksum = Some(self.compute_checksum());
+    }
+
+    /// Returns `false` if the record carries a checksum that does not match
+    /// its contents. Records without a checksum always verify.
+    pub fn verify(&self) -> bool {
+        self.checksum.map_or(true, |c| c == self.compute_checksum())
+    }
 }
 pub type LogIndex = u64;
 
 // --- Fault Events ---
 
 /// An event representing a fault injected by the simulator.
+/// New kinds of faults may be added, so protocols must ignore those they
+/// do not know.
 #[derive(Clone, Debug)]
+#[non_exhaustive]
 pub enum FaultEvent {
     NodeCrashed,
     NodeRecovered,
@@ -193,4 +1047,45 @@ pub enum FaultEvent {
     ClockSkewed { skew_ns: i128 },
     StoreFaulted { kind: StoreFaultKind },
     ByzantineEnabled(bool),
+    /// A message from `src` failed to decode and was dropped, e.g. after
+    /// corruption or from a byzantine sender. `len` is its size in bytes.
+    MalformedMessage { src: NodeId, msg_id: MsgId, len: usize },
+    /// A fault of kind `fault_kind`, e.g. a crash, will hit this node at
+    /// `at` on its clock. Only faults the scenario announces ahead of time
+    /// are reported.
+    Scheduled { fault_kind: FaultKind, at: ftsim_types::time::SimTime },
+}
+
+/// An announced fault that has not happened yet, as returned by
+/// `ProtoCtx::pending_maintenance`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Maintenance {
+    /// The fault's kind, e.g. a crash.
+    pub fault_kind: FaultKind,
+    /// When the fault hits, on this node's clock.
+    pub at: ftsim_types::time::SimTime,
+}
+
+/// Why a message a protocol sent was dropped, as reported to
+/// `on_send_failed`. Only drops a real transport would notice are
+/// reported; probabilistic link drops never are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SendFailure {
+    /// The link to the destination is partitioned.
+    Partitioned,
+    /// The sender has no link to the destination.
+    NoLink,
+    /// The message exceeds the link's MTU and the link rejects oversize
+    /// messages.
+    TooLarge,
+}
+
+impl std::fmt::Display for SendFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendFailure::Partitioned => write!(f, "partitioned"),
+            SendFailure::NoLink => write!(f, "no link"),
+            SendFailure::TooLarge => write!(f, "too large"),
+        }
+    }
 }
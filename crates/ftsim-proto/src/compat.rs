@@ -0,0 +1,135 @@
+//! # ftsim-proto::compat
+//!
+//! Checks whether two versions of a protocol can decode each other's
+//! messages, for rolling-upgrade experiments. Payloads encoded by the old
+//! version, recorded in a message journal or listed by its
+//! `sample_messages`, are fed to the new version's decoder, and the new
+//! version's `sample_messages` are fed to the old one's. Failures are
+//! reported per message variant, as named by the encoding side.
+
+use crate::api::ProtocolDyn;
+use std::fmt;
+
+/// Which way a payload crossed between versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CompatDirection {
+    /// Encoded by the old version, decoded by the new one.
+    OldToNew,
+    /// Encoded by the new version, decoded by the old one.
+    NewToOld,
+}
+
+impl fmt::Display for CompatDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompatDirection::OldToNew => write!(f, "old -> new"),
+            CompatDirection::NewToOld => write!(f, "new -> old"),
+        }
+    }
+}
+
+/// A message variant that failed to decode in one direction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatFailure {
+    pub direction: CompatDirection,
+    /// The variant as named by the encoding version, or `<unknown>` for a
+    /// recorded payload the old version cannot name either.
+    pub variant: String,
+    /// How many payloads of this variant failed.
+    pub count: usize,
+    /// The decode error of the first of them.
+    pub error: String,
+}
+
+/// The outcome of `check_wire_compat`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WireCompatReport {
+    pub old: &'static str,
+    pub new: &'static str,
+    /// Payloads decoded, in both directions.
+    pub checked: usize,
+    /// Failing variants, in the order they were first seen.
+    pub failures: Vec<CompatFailure>,
+}
+
+impl WireCompatReport {
+    pub fn is_compatible(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Returns the variants that failed in `direction`.
+    pub fn failing_variants(&self, direction: CompatDirection) -> Vec<&str> {
+        self.failures
+            .iter()
+            .filter(|f| f.direction == direction)
+            .map(|f| f.variant.as_str())
+            .collect()
+    }
+
+    fn record(&mut self, direction: CompatDirection, variant: String, error: String) {
+        match self.failures.iter_mut().find(|f| f.direction == direction && f.variant == variant) {
+            Some(failure) => failure.count += 1,
+            None => self.failures.push(CompatFailure {
+                direction,
+                variant,
+                count: 1,
+                error,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for WireCompatReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}: {} payloads checked", self.old, self.new, self.checked)?;
+        if self.is_compatible() {
+            return write!(f, ", all compatible");
+        }
+        for failure in &self.failures {
+            write!(
+                f,
+                "\n  {} {} failed {} time(s): {}",
+                failure.direction, failure.variant, failure.count, failure.error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes payloads across two versions of a protocol. `samples` are
+/// payloads recorded from the old version; each version's
+/// `sample_messages` are checked against the other as well.
+pub fn check_wire_compat(
+    old_factory: impl Fn() -> Box<dyn ProtocolDyn>,
+    new_factory: impl Fn() -> Box<dyn ProtocolDyn>,
+    samples: &[Vec<u8>],
+) -> WireCompatReport {
+    let (old, new) = (old_factory(), new_factory());
+    let mut report = WireCompatReport {
+        old: old.name(),
+        new: new.name(),
+        checked: 0,
+        failures: Vec::new(),
+    };
+
+    let old_samples = old.sample_messages();
+    let new_samples = new.sample_messages();
+    let crossings = samples
+        .iter()
+        .map(|s| s.as_slice())
+        .chain(old_samples.iter().map(|s| s.as_ref()))
+        .map(|payload| (CompatDirection::OldToNew, payload))
+        .chain(new_samples.iter().map(|s| (CompatDirection::NewToOld, s.as_ref())));
+    for (direction, payload) in crossings {
+        let (encoder, decoder) = match direction {
+            CompatDirection::OldToNew => (&old, &new),
+            CompatDirection::NewToOld => (&new, &old),
+        };
+        report.checked += 1;
+        if let Err(e) = decoder.check_message(payload) {
+            let variant = encoder.message_variant(payload).unwrap_or_else(|| "<unknown>".to_string());
+            report.record(direction, variant, e.0);
+        }
+    }
+    report
+}
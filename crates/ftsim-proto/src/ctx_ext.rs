@@ -5,42 +5,108 @@
 //! provides typed, convenient methods for common operations like sending
 //! messages and setting timers.
 
-use crate::api::{ProtoCtx, StoreView};
+use crate::api::{encode_message, encode_versioned, Maintenance, MessageMeta, ProtoCtx, StoreView, STATE_KEY};
 use ftsim_types::{
+    client::ClientResponse,
     envelope::ProtoTag,
     errors::CodecError,
-    id::{NodeId, TimerId},
+    id::{ClientRequestId, MsgId, NodeId, RequestId, TimerId},
     time::SimTime,
 };
 use serde::{de::DeserializeOwned, Serialize};
-use std::{fmt::Debug, marker::PhantomData};
+use std::{collections::BTreeMap, fmt::Debug, marker::PhantomData, ops::RangeInclusive};
 
 /// A typed context wrapper provided to `Protocol<M>` implementations.
 pub struct Ctx<'a, M> {
     inner: &'a mut dyn ProtoCtx,
     proto_tag: ProtoTag,
+    /// The wire version messages are encoded with.
+    wire_version: u16,
+    requests: &'a mut Requests,
     _p: PhantomData<M>,
 }
 
 impl<'a, M> Ctx<'a, M> {
-    pub(crate) fn new(inner: &'a mut dyn ProtoCtx, proto_tag: ProtoTag) -> Self {
+    pub(crate) fn new(
+        inner: &'a mut dyn ProtoCtx,
+        proto_tag: ProtoTag,
+        wire_version: u16,
+        requests: &'a mut Requests,
+    ) -> Self {
         Self {
             inner,
             proto_tag,
+            wire_version,
+            requests,
             _p: PhantomData,
         }
     }
 }
 
+/// The requests a protocol has in flight, kept by its adapter.
+#[derive(Default, Debug)]
+pub(crate) struct Requests {
+    /// Requests awaiting a reply, with the node each was sent to.
+    pub pending: BTreeMap<RequestId, NodeId>,
+    /// The sender and ID of the request `on_message` is handling, which
+    /// `Ctx::reply` answers.
+    pub replying_to: Option<(NodeId, RequestId)>,
+}
+
+impl Requests {
+    /// Retires the pending request `req` if `src` is the node it was sent
+    /// to. Returns `false` for replies to unknown or expired requests.
+    pub fn complete(&mut self, req: RequestId, src: NodeId) -> bool {
+        if self.pending.get(&req) != Some(&src) {
+            return false;
+        }
+        self.pending.remove(&req);
+        true
+    }
+}
+
+/// How a message relates to a request, as carried in the envelope's
+/// `trace_id`. The top two bits say which it is; the rest is the request ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Correlation {
+    Request(RequestId),
+    Reply(RequestId),
+}
+
+impl Correlation {
+    const REQUEST: u64 = 1 << 63;
+    const REPLY: u64 = 1 << 62;
+    const ID_MASK: u64 = Self::REPLY - 1;
+
+    pub fn encode(self) -> u64 {
+        match self {
+            Correlation::Request(req) => Self::REQUEST | (req.0 & Self::ID_MASK),
+            Correlation::Reply(req) => Self::REPLY | (req.0 & Self::ID_MASK),
+        }
+    }
+
+    pub fn decode(trace_id: u64) -> Option<Self> {
+        let req = trace_id & Self::ID_MASK;
+        match trace_id & !Self::ID_MASK {
+            Self::REQUEST => Some(Correlation::Request(TimerId(req))),
+            Self::REPLY => Some(Correlation::Reply(TimerId(req))),
+            _ => None,
+        }
+    }
+}
+
 impl<'a, M> Ctx<'a, M>
 where
     M: Serialize + DeserializeOwned + Debug + Send + 'static,
 {
+    fn encode(&self, msg: &M) -> Result<Vec<u8>, CodecError> {
+        encode_versioned(self.wire_version, msg)
+    }
+
     /// Sends a typed message to a specific destination node.
     /// The message will be serialized using `postcard`.
     pub fn send(&mut self, dst: NodeId, msg: &M) -> Result<(), CodecError> {
-        let bytes = postcard::to_allocvec(msg)
-            .map_err(|e| CodecError(format!("Serialization failed: {}", e)))?;
+        let bytes = self.encode(msg)?;
         self.inner.send_raw(dst, self.proto_tag, bytes.into());
         Ok(())
     }
@@ -51,34 +117,188 @@ where
         msg: &M,
         filter: Option<&dyn Fn(NodeId) -> bool>,
     ) -> Result<(), CodecError> {
-        let bytes = postcard::to_allocvec(msg)
-            .map_err(|e| CodecError(format!("Serialization failed: {}", e)))?;
+        let bytes = self.encode(msg)?;
         self.inner
             .broadcast_raw(self.proto_tag, bytes.into(), filter);
         Ok(())
     }
 
+    /// Sends `msg` to `dst` once `delay` has passed, without a timer. The
+    /// message is subject to the link's faults as they are then, and is not
+    /// sent if the node crashes in the meantime.
+    pub fn send_after(&mut self, dst: NodeId, msg: &M, delay: SimTime) -> Result<(), CodecError> {
+        let bytes = self.encode(msg)?;
+        self.inner.send_raw_after(dst, self.proto_tag, bytes.into(), delay);
+        Ok(())
+    }
+
+    /// Broadcasts `msg` once `delay` has passed, as `send_after` does.
+    pub fn broadcast_after(
+        &mut self,
+        msg: &M,
+        delay: SimTime,
+        filter: Option<&dyn Fn(NodeId) -> bool>,
+    ) -> Result<(), CodecError> {
+        let bytes = self.encode(msg)?;
+        self.inner.broadcast_raw_after(self.proto_tag, bytes.into(), delay, filter);
+        Ok(())
+    }
+
+    /// Sends `msg` to `dst` as a request and arms a timeout for it. The
+    /// receiver answers with `reply`; the first answer within `timeout` is
+    /// delivered to `Protocol::on_reply`, and if none comes,
+    /// `Protocol::on_request_timeout` is called once instead.
+    pub fn request(&mut self, dst: NodeId, msg: &M, timeout: SimTime) -> Result<RequestId, CodecError> {
+        let bytes = self.encode(msg)?;
+        let req = self.inner.set_timer(timeout);
+        self.requests.pending.insert(req, dst);
+        let trace_id = Correlation::Request(req).encode();
+        self.inner.send_traced_raw(dst, self.proto_tag, bytes.into(), trace_id);
+        Ok(req)
+    }
+
+    /// Answers the request being handled in `on_message`. Returns `false`
+    /// without sending if the message was not sent with `request`, or has
+    /// already been answered.
+    pub fn reply(&mut self, msg: &M) -> Result<bool, CodecError> {
+        let Some((src, req)) = self.requests.replying_to else {
+            return Ok(false);
+        };
+        let bytes = self.encode(msg)?;
+        self.requests.replying_to = None;
+        let trace_id = Correlation::Reply(req).encode();
+        self.inner.send_traced_raw(src, self.proto_tag, bytes.into(), trace_id);
+        Ok(true)
+    }
+
+    /// Gives up on a pending request: its timeout will not fire, and a reply
+    /// that still arrives goes to `on_message`. Returns `false` if it was
+    /// not pending.
+    pub fn cancel_request(&mut self, req: RequestId) -> bool {
+        if self.requests.pending.remove(&req).is_none() {
+            return false;
+        }
+        self.inner.cancel_timer(req);
+        true
+    }
+
     /// Sets a timer that will fire after the specified duration.
     /// Returns a `TimerId` that can be used to cancel it.
     pub fn set_timer(&mut self, after: SimTime) -> TimerId {
         self.inner.set_timer(after)
     }
 
+    /// Sets a timer carrying `payload`, which is handed back encoded to
+    /// `Protocol::on_timer_payload` when it fires. This saves keeping a
+    /// table from `TimerId` to what each timer is for.
+    pub fn set_timer_with<T: Serialize>(&mut self, after: SimTime, payload: &T) -> Result<TimerId, CodecError> {
+        let bytes = encode_message(payload)?;
+        Ok(self.inner.set_timer_raw(after, Some(bytes.into())))
+    }
+
+    /// Sets a timer that fires every `period` until canceled, keeping its
+    /// ID, so a heartbeat need not be re-armed in every `on_timer`. A crash
+    /// stops it for good; the restarted node starts with no timers.
+    pub fn set_periodic_timer(&mut self, period: SimTime) -> TimerId {
+        self.inner.set_periodic_timer_raw(period, None)
+    }
+
+    /// Like `set_periodic_timer`, handing `payload` to
+    /// `Protocol::on_timer_payload` on every firing.
+    pub fn set_periodic_timer_with<T: Serialize>(&mut self, period: SimTime, payload: &T) -> Result<TimerId, CodecError> {
+        let bytes = encode_message(payload)?;
+        Ok(self.inner.set_periodic_timer_raw(period, Some(bytes.into())))
+    }
+
+    /// Sets a watermark at `at` on this node's clock: a timer that fires only
+    /// after every delivery to this node due at or before `at` has been
+    /// dispatched. See `ProtoCtx::set_watermark` for the precise guarantee.
+    pub fn set_watermark(&mut self, at: SimTime) -> TimerId {
+        self.inner.set_watermark(at)
+    }
+
     /// Cancels a pending timer. Returns `true` if the timer was found and canceled.
     pub fn cancel_timer(&mut self, timer: TimerId) -> bool {
         self.inner.cancel_timer(timer)
     }
 
+    /// Returns the time left until a pending timer fires, or `None` if it is
+    /// unknown, canceled, or has already fired.
+    pub fn timer_remaining(&self, timer: TimerId) -> Option<SimTime> {
+        self.inner.timer_remaining(timer)
+    }
+
+    /// Returns all pending timers with their remaining time, soonest first.
+    pub fn pending_timers(&self) -> Vec<(TimerId, SimTime)> {
+        self.inner.pending_timers()
+    }
+
+    /// Pushes a pending timer's deadline back by `additional` without changing
+    /// its ID. Returns `false` if the timer is not pending.
+    pub fn extend_timer(&mut self, timer: TimerId, additional: SimTime) -> bool {
+        self.inner.extend_timer(timer, additional)
+    }
+
     /// Returns the current simulation time, adjusted for this node's clock skew.
     pub fn now(&self) -> SimTime {
         self.inner.now()
     }
 
+    /// Returns what the engine knows about the message being handled, or
+    /// `None` outside `on_message`.
+    pub fn message_meta(&self) -> Option<MessageMeta> {
+        self.inner.message_meta()
+    }
+
+    /// Returns the ID of the message this node sent last, which
+    /// `Protocol::on_send_failed` reports if the send fails. After a
+    /// broadcast, it is the ID of the message to the highest peer.
+    pub fn last_sent_msg_id(&self) -> Option<MsgId> {
+        self.inner.last_sent_msg_id()
+    }
+
+    /// Returns the faults announced to this node that have not happened
+    /// yet, soonest first.
+    pub fn pending_maintenance(&self) -> Vec<Maintenance> {
+        self.inner.pending_maintenance()
+    }
+
+    /// Reads the node's engine-managed metadata, which survives crashes and
+    /// store faults. Example: `ctx.node_meta_get(META_INCARNATION)`.
+    pub fn node_meta_get(&self, key: &str) -> Option<String> {
+        self.inner.node_meta_get(key)
+    }
+
+    /// Returns the ID of the client request being handled, to answer it
+    /// later with `respond_to_client`, or `None` outside
+    /// `Protocol::on_client_request`.
+    pub fn client_request_id(&self) -> Option<ClientRequestId> {
+        self.inner.client_request_id()
+    }
+
+    /// Answers a client request that `Protocol::on_client_request` left
+    /// pending. Its latency runs from when it was issued until then.
+    pub fn respond_to_client(&mut self, request: ClientRequestId, response: ClientResponse) {
+        self.inner.respond_to_client(request, response);
+    }
+
     /// Returns the ID of the current node.
     pub fn node_id(&self) -> NodeId {
         self.inner.node_id()
     }
 
+    /// Returns the nodes this node can send to, in ascending order and
+    /// without itself.
+    pub fn peers(&self) -> Vec<NodeId> {
+        self.inner.peers()
+    }
+
+    /// Returns the number of nodes in the cluster, this one included. Use it
+    /// rather than a constant to size quorums.
+    pub fn cluster_size(&self) -> usize {
+        self.inner.cluster_size()
+    }
+
     /// Provides temporary mutable access to the node's persistent storage.
     pub fn store(&mut self) -> Box<dyn StoreView + '_> {
         self.inner.store()
@@ -90,6 +310,41 @@ where
         self.inner.rng_u64()
     }
 
+    /// Returns a uniform draw from `range`, e.g. an election timeout in
+    /// `150..=300` ms. Unlike `rng_u64() % n`, every value is equally likely.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is empty.
+    pub fn rng_range(&mut self, range: RangeInclusive<u64>) -> u64 {
+        self.inner.rng_range(range)
+    }
+
+    /// Returns `true` with probability `p`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `p` is outside `0.0..=1.0`.
+    pub fn rng_bool(&mut self, p: f64) -> bool {
+        self.inner.rng_bool(p)
+    }
+
+    /// Returns one of `items`, each equally likely.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `items` is empty.
+    pub fn rng_choose<'i, T>(&mut self, items: &'i [T]) -> &'i T {
+        &items[self.inner.rng_pick(items.len())]
+    }
+
+    /// Shuffles `items` in place, every order equally likely.
+    pub fn rng_shuffle<T>(&mut self, items: &mut Vec<T>) {
+        let order = self.inner.rng_permutation(items.len());
+        let mut old: Vec<Option<T>> = items.drain(..).map(Some).collect();
+        items.extend(order.into_iter().map(|i| old[i].take().expect("a permutation")));
+    }
+
     /// Attaches a key-value pair to the current logging span.
     /// This is useful for exposing protocol-specific state to the TUI and logs.
     /// Example: `ctx.log_kv("role", "leader")`.
@@ -97,6 +352,47 @@ where
         self.inner.log_kv(key, val);
     }
 
+    /// Like `log_kv`, but the key is never evicted when the node exceeds its
+    /// custom KV limit. Use this for core state such as `role`.
+    pub fn log_kv_pinned(&mut self, key: &'static str, val: &str) {
+        self.inner.log_kv_pinned(key, val);
+    }
+
+    /// Publishes a number under `key`, kept as a JSON number.
+    pub fn log_kv_u64(&mut self, key: &'static str, val: u64) {
+        self.inner.log_kv_value(key, val.into());
+    }
+
+    /// Publishes a flag under `key`, kept as a JSON boolean.
+    pub fn log_kv_bool(&mut self, key: &'static str, val: bool) {
+        self.inner.log_kv_value(key, val.into());
+    }
+
+    /// Publishes the protocol's visible state as a whole, serialized to a
+    /// JSON value under the reserved `STATE_KEY`, replacing what was
+    /// published before. Fields keep their types, so readers need not parse
+    /// strings. As with any KV, protocols sharing a node overwrite each
+    /// other's state. States that fail to serialize are ignored.
+    pub fn publish_state<T: Serialize>(&mut self, state: &T) {
+        match serde_json::to_value(state) {
+            Ok(value) => self.inner.log_kv_value_pinned(STATE_KEY, value),
+            Err(e) => tracing::warn!("Unserializable protocol state: {}", e),
+        }
+    }
+
+    /// Records a sample of a numeric metric, which the TUI charts and phase
+    /// checks can compare. Use `log_kv` for categorical values like `role`.
+    /// Example: `ctx.log_metric("term", 5.0)`.
+    pub fn log_metric(&mut self, key: &'static str, value: f64) {
+        self.inner.log_metric(key, value);
+    }
+
+    /// Records an observation into a histogram metric, for distributions
+    /// like latencies. Example: `ctx.log_histogram("ping_rtt", rtt as f64)`.
+    pub fn log_histogram(&mut self, key: &'static str, value: f64) {
+        self.inner.log_histogram(key, value);
+    }
+
     /// Helper method to log serializable values by converting them to JSON strings.
     pub fn log_kv_json<T: Serialize>(&mut self, key: &'static str, val: &T) {
         if let Ok(json_str) = serde_json::to_string(val) {
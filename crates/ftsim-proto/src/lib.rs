@@ -8,8 +8,9 @@
 #![forbid(unsafe_code)]
 
 pub mod api;
+pub mod compat;
 pub mod ctx_ext;
 pub mod protocols;
 
-pub use api::{FaultEvent, Protocol, ProtocolDyn};
+pub use api::{FaultEvent, Protocol, ProtocolDyn, SendFailure};
 pub use ctx_ext::Ctx;
@@ -0,0 +1,147 @@
+//! # ftsim-proto::protocols::batch_replicate
+//!
+//! An example of simulation-aware batching with watermarks. Every node other
+//! than the leader (node 0) periodically sends a write to the leader. The
+//! leader opens a batching window when the first write of a batch arrives,
+//! by setting a watermark at the end of the window, and replicates all
+//! writes received within the window as one batch when the watermark fires.
+//! Because a watermark runs after every delivery due at or before its time,
+//! writes that arrive exactly at the window's end still join the batch.
+
+use crate::{api::LogRecord, Ctx, FaultEvent, Protocol};
+use ftsim_types::{
+    envelope::ProtoTag,
+    id::{NodeId, TimerId},
+    time::sim_from_ms,
+};
+use serde::{Deserialize, Serialize};
+
+const TAG: ProtoTag = ProtoTag(3);
+
+/// How often each non-leader node issues a write, in milliseconds.
+const WRITE_PERIOD_MS: u64 = 3;
+
+/// How long the leader collects writes before replicating them, in milliseconds.
+const BATCH_WINDOW_MS: u64 = 5;
+
+const LEADER: NodeId = 0;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Message {
+    Write { key: String, value: String },
+    Batch { seq: u64, entries: Vec<(String, String)> },
+}
+
+#[derive(Default)]
+pub struct BatchReplicate {
+    id: NodeId,
+    /// Writes received by the leader in the current window.
+    pending: Vec<(String, String)>,
+    /// The watermark closing the current window, if one is open.
+    window: Option<TimerId>,
+    /// The periodic write timer of a non-leader node.
+    write_timer: Option<TimerId>,
+    writes_issued: u64,
+    batches: u64,
+    applied: u64,
+}
+
+impl BatchReplicate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn flush(&mut self, ctx: &mut Ctx<Message>) {
+        self.window = None;
+        let entries = std::mem::take(&mut self.pending);
+        if entries.is_empty() {
+            return;
+        }
+        self.batches += 1;
+        let batch = Message::Batch {
+            seq: self.batches,
+            entries,
+        };
+        if let Ok(bytes) = postcard::to_allocvec(&batch) {
+            ctx.store().append_log(LogRecord::new(0, bytes.into())).ok();
+        }
+        ctx.broadcast(&batch, None).ok();
+        ctx.log_kv("batches", &self.batches.to_string());
+    }
+}
+
+impl Protocol<Message> for BatchReplicate {
+    fn name(&self) -> &'static str {
+        "batch_replicate"
+    }
+
+    fn proto_tag(&self) -> ProtoTag {
+        TAG
+    }
+
+    fn init(&mut self, ctx: &mut Ctx<Message>) {
+        self.id = ctx.node_id();
+        let role = if self.id == LEADER { "leader" } else { "follower" };
+        ctx.log_kv_pinned("role", role);
+    }
+
+    fn start(&mut self, ctx: &mut Ctx<Message>) {
+        if self.id != LEADER {
+            self.write_timer = Some(ctx.set_timer(sim_from_ms(WRITE_PERIOD_MS)));
+        }
+    }
+
+    fn on_message(&mut self, ctx: &mut Ctx<Message>, _src: NodeId, msg: Message) {
+        match msg {
+            Message::Write { key, value } if self.id == LEADER => {
+                self.pending.push((key, value));
+                if self.window.is_none() {
+                    let close_at = ctx.now().saturating_add(sim_from_ms(BATCH_WINDOW_MS));
+                    self.window = Some(ctx.set_watermark(close_at));
+                }
+            }
+            Message::Batch { seq, entries } if self.id != LEADER => {
+                self.applied += entries.len() as u64;
+                ctx.log_kv("last_batch", &seq.to_string());
+                ctx.log_kv("applied", &self.applied.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn on_timer(&mut self, ctx: &mut Ctx<Message>, timer: TimerId) {
+        if Some(timer) == self.window {
+            self.flush(ctx);
+        } else if Some(timer) == self.write_timer {
+            self.writes_issued += 1;
+            let write = Message::Write {
+                key: format!("n{}-{}", self.id, self.writes_issued),
+                value: self.writes_issued.to_string(),
+            };
+            ctx.send(LEADER, &write).ok();
+            self.write_timer = Some(ctx.set_timer(sim_from_ms(WRITE_PERIOD_MS)));
+        }
+    }
+
+    fn on_fault(&mut self, _ctx: &mut Ctx<Message>, fault: FaultEvent) {
+        if let FaultEvent::NodeCrashed = fault {
+            // Pending timers are dropped on crash; a restart calls `start` again.
+            self.pending.clear();
+            self.window = None;
+            self.write_timer = None;
+        }
+    }
+
+    fn sample_messages(&self) -> Vec<Message> {
+        vec![
+            Message::Write {
+                key: "key".into(),
+                value: "value".into(),
+            },
+            Message::Batch {
+                seq: 1,
+                entries: vec![("key".into(), "value".into())],
+            },
+        ]
+    }
+}
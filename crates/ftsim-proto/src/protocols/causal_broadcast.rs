@@ -0,0 +1,375 @@
+//! # ftsim-proto::protocols::causal_broadcast
+//!
+//! Causal broadcast with vector clocks. A client `Put` on any node
+//! broadcasts the write, stamped with the node's vector clock: how many
+//! messages from each origin the node had delivered, its own count
+//! including the new message. A node delivers a message, appending it to
+//! its local log, once it has delivered the message's predecessor from the
+//! same origin and everything the origin had delivered before sending it.
+//! Messages that arrive early, as reordering links make them, wait in a
+//! buffer; duplicates are dropped.
+//!
+//! Every `SYNC_PERIOD_MS`, each node sends its clock to its peers, which
+//! answer with the logged messages it lacks, so that messages lost to drops,
+//! partitions or crashes are delivered in the end.
+//!
+//! A node appends each message to its store's log, and fsyncs it, before it
+//! counts the message as delivered; one it cannot store stays in the buffer
+//! for the next try. A restarted node rebuilds its log and clock from the
+//! store, and the sync brings back what its buffer held.
+//!
+//! Each node publishes its log in delivery order with `Ctx::publish_state`
+//! at most once per sync period, which the engine's `causal_delivery`
+//! invariant checks, along with the `delivered`, `buffered` and `held_back`
+//! metrics on every change, the last counting the messages that had to
+//! wait.
+
+use crate::{
+    api::{decode_message, encode_message, LogRecord},
+    Ctx, FaultEvent, Protocol,
+};
+use ftsim_types::{
+    client::{ClientOp, ClientResponse},
+    envelope::ProtoTag,
+    errors::CodecError,
+    id::{NodeId, TimerId},
+    time::sim_from_ms,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const TAG: ProtoTag = ProtoTag(8);
+
+/// How often a node sends its clock to its peers, in milliseconds.
+pub const SYNC_PERIOD_MS: u64 = 50;
+
+/// How many messages from each origin a node had delivered. Origins it had
+/// delivered none from are left out.
+pub type VectorClock = BTreeMap<NodeId, u64>;
+
+/// A broadcast write.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Broadcast {
+    pub origin: NodeId,
+    /// The origin's clock when it sent the message, which counts the message
+    /// itself.
+    pub clock: VectorClock,
+    pub key: String,
+    pub value: String,
+}
+
+impl Broadcast {
+    /// The message's position among those from its origin, counting from 1.
+    pub fn seq(&self) -> u64 {
+        self.clock.get(&self.origin).copied().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Message {
+    Broadcast(Broadcast),
+    /// The sender's clock, asking for the messages it lacks.
+    Sync { clock: VectorClock },
+    /// The messages the receiver's clock lacked, in delivery order.
+    Resend { messages: Vec<Broadcast> },
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct CausalBroadcast {
+    clock: VectorClock,
+    /// Every delivered message, in delivery order.
+    log: Vec<Broadcast>,
+    /// Messages waiting for their causal predecessors.
+    buffer: Vec<Broadcast>,
+    /// How many messages have waited in the buffer.
+    held_back: u64,
+    /// Whether the log or buffer changed since the state was last published.
+    unpublished: bool,
+    #[serde(skip)]
+    sync_timer: Option<TimerId>,
+}
+
+/// What a causal broadcast node publishes with `Ctx::publish_state`.
+#[derive(Serialize)]
+struct PublishedState<'a> {
+    /// The log, in delivery order.
+    delivered: &'a [Broadcast],
+    buffered: usize,
+}
+
+impl CausalBroadcast {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The log, in delivery order.
+    pub fn log(&self) -> &[Broadcast] {
+        &self.log
+    }
+
+    fn delivered_from(&self, origin: NodeId) -> u64 {
+        self.clock.get(&origin).copied().unwrap_or(0)
+    }
+
+    /// Whether every causal predecessor of `msg` has been delivered.
+    fn is_deliverable(&self, msg: &Broadcast) -> bool {
+        msg.clock.iter().all(|(&node, &count)| {
+            let delivered = self.delivered_from(node);
+            if node == msg.origin {
+                count == delivered + 1
+            } else {
+                count <= delivered
+            }
+        })
+    }
+
+    fn deliver(&mut self, msg: Broadcast) {
+        self.clock.insert(msg.origin, msg.seq());
+        self.log.push(msg);
+    }
+
+    /// Buffers `msg` unless it was already delivered or buffered, then
+    /// delivers whatever the buffer holds that has become deliverable, each
+    /// once `persist` stored it. Returns whether the log or buffer changed.
+    fn receive(&mut self, msg: Broadcast, persist: &mut impl FnMut(&Broadcast) -> bool) -> bool {
+        let seen = msg.seq() <= self.delivered_from(msg.origin)
+            || self.buffer.iter().any(|held| held.origin == msg.origin && held.seq() == msg.seq());
+        if !seen {
+            if !self.is_deliverable(&msg) {
+                self.held_back += 1;
+            }
+            self.buffer.push(msg);
+        }
+        // A message that could not be stored before is retried, even when
+        // `msg` brings nothing new
+        let mut changed = !seen;
+        while let Some(i) = self.buffer.iter().position(|held| self.is_deliverable(held)) {
+            if !persist(&self.buffer[i]) {
+                break;
+            }
+            let msg = self.buffer.swap_remove(i);
+            self.deliver(msg);
+            changed = true;
+        }
+        changed
+    }
+
+    /// Delivers `messages` as `receive` does, storing each in `ctx`'s store.
+    fn receive_all(&mut self, ctx: &mut Ctx<Message>, messages: impl IntoIterator<Item = Broadcast>) {
+        let mut changed = false;
+        for msg in messages {
+            changed |= self.receive(msg, &mut |msg| persist(ctx, msg));
+        }
+        if changed {
+            self.changed(ctx);
+        }
+    }
+
+    /// Delivers a write of this node's and broadcasts it, once stored.
+    /// Returns whether it was stored.
+    fn write(&mut self, ctx: &mut Ctx<Message>, key: String, value: String) -> bool {
+        let origin = ctx.node_id();
+        let mut clock = self.clock.clone();
+        clock.insert(origin, self.delivered_from(origin) + 1);
+        let msg = Broadcast { origin, clock, key, value };
+        if !persist(ctx, &msg) {
+            return false;
+        }
+        self.deliver(msg.clone());
+        self.changed(ctx);
+        ctx.broadcast(&Message::Broadcast(msg), None).ok();
+        true
+    }
+
+    /// Rebuilds the log and clock from the store. Records that are damaged,
+    /// or stored twice after a failed fsync, are skipped.
+    fn load(&mut self, ctx: &mut Ctx<Message>) {
+        self.clock.clear();
+        self.log.clear();
+        let mut store = ctx.store();
+        for idx in 0.. {
+            match store.read_log(idx) {
+                Ok(Some(rec)) if rec.verify() => {
+                    let Ok(msg) = decode_message::<Broadcast>(&rec.data) else {
+                        continue;
+                    };
+                    if self.is_deliverable(&msg) {
+                        self.deliver(msg);
+                    }
+                }
+                Ok(Some(_)) | Err(_) => {}
+                Ok(None) => break,
+            }
+        }
+    }
+
+    /// Updates the metrics after the log or buffer changed, leaving the
+    /// state to the next `publish`.
+    fn changed(&mut self, ctx: &mut Ctx<Message>) {
+        self.unpublished = true;
+        ctx.log_metric("delivered", self.log.len() as f64);
+        ctx.log_metric("buffered", self.buffer.len() as f64);
+        ctx.log_metric("held_back", self.held_back as f64);
+    }
+
+    /// Publishes the log, if it changed since it was last published.
+    fn publish(&mut self, ctx: &mut Ctx<Message>) {
+        if std::mem::take(&mut self.unpublished) {
+            ctx.publish_state(&PublishedState { delivered: &self.log, buffered: self.buffer.len() });
+        }
+    }
+}
+
+/// Appends `msg` to the node's store log and fsyncs it. Returns whether it
+/// is durable.
+fn persist(ctx: &mut Ctx<Message>, msg: &Broadcast) -> bool {
+    let Ok(bytes) = encode_message(msg) else {
+        return false;
+    };
+    let mut store = ctx.store();
+    store.append_log(LogRecord::new(0, bytes.into())).is_ok() && store.fsync().is_ok()
+}
+
+impl Protocol<Message> for CausalBroadcast {
+    fn name(&self) -> &'static str {
+        "causal_broadcast"
+    }
+
+    fn proto_tag(&self) -> ProtoTag {
+        TAG
+    }
+
+    fn init(&mut self, ctx: &mut Ctx<Message>) {
+        // Also called on restart: the buffer is lost, and the log is what
+        // the store kept
+        self.buffer.clear();
+        self.load(ctx);
+        self.changed(ctx);
+        self.publish(ctx);
+    }
+
+    fn start(&mut self, ctx: &mut Ctx<Message>) {
+        self.sync_timer = Some(ctx.set_periodic_timer(sim_from_ms(SYNC_PERIOD_MS)));
+    }
+
+    fn on_message(&mut self, ctx: &mut Ctx<Message>, src: NodeId, msg: Message) {
+        match msg {
+            Message::Broadcast(msg) => self.receive_all(ctx, [msg]),
+            Message::Sync { clock } => {
+                let missing: Vec<Broadcast> = self
+                    .log
+                    .iter()
+                    .filter(|msg| msg.seq() > clock.get(&msg.origin).copied().unwrap_or(0))
+                    .cloned()
+                    .collect();
+                if !missing.is_empty() {
+                    ctx.send(src, &Message::Resend { messages: missing }).ok();
+                }
+            }
+            Message::Resend { messages } => self.receive_all(ctx, messages),
+        }
+    }
+
+    fn on_timer(&mut self, ctx: &mut Ctx<Message>, timer: TimerId) {
+        if Some(timer) == self.sync_timer {
+            self.publish(ctx);
+            ctx.broadcast(&Message::Sync { clock: self.clock.clone() }, None).ok();
+        }
+    }
+
+    fn on_fault(&mut self, ctx: &mut Ctx<Message>, fault: FaultEvent) {
+        if let FaultEvent::NodeCrashed = fault {
+            // Pending timers are dropped on crash; a restart calls `start` again.
+            self.sync_timer = None;
+            self.publish(ctx);
+        }
+    }
+
+    fn on_shutdown(&mut self, ctx: &mut Ctx<Message>) {
+        self.publish(ctx);
+    }
+
+    fn on_client_request(&mut self, ctx: &mut Ctx<Message>, op: &ClientOp) -> Option<ClientResponse> {
+        match op {
+            ClientOp::Put { key, value } => Some(if self.write(ctx, key.clone(), value.clone()) {
+                ClientResponse::Ok
+            } else {
+                ClientResponse::Rejected("store write failed".to_string())
+            }),
+            // The latest delivered write of the key
+            ClientOp::Get { key } => Some(ClientResponse::Value(
+                self.log.iter().rev().find(|msg| &msg.key == key).map(|msg| msg.value.clone()),
+            )),
+            ClientOp::Custom { .. } => None,
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<Vec<u8>> {
+        encode_message(self).ok()
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), CodecError> {
+        let timer = self.sync_timer;
+        *self = decode_message(state)?;
+        self.sync_timer = timer;
+        Ok(())
+    }
+
+    fn sample_messages(&self) -> Vec<Message> {
+        let clock = VectorClock::from([(0, 1), (1, 2)]);
+        let msg = Broadcast { origin: 1, clock: clock.clone(), key: "key".into(), value: "value".into() };
+        vec![Message::Broadcast(msg.clone()), Message::Sync { clock }, Message::Resend { messages: vec![msg] }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(origin: NodeId, clock: &[(NodeId, u64)]) -> Broadcast {
+        let key = format!("{}@{:?}", origin, clock);
+        Broadcast { origin, clock: clock.iter().copied().collect(), key, value: String::new() }
+    }
+
+    #[test]
+    fn test_receive_holds_back_messages_until_their_predecessors_arrive() {
+        let mut node = CausalBroadcast::new();
+        let mut stored = Vec::new();
+        let mut persist = |msg: &Broadcast| {
+            stored.push(msg.key.clone());
+            true
+        };
+        // Node 1's reply to node 0's first write, then node 0's second write,
+        // both ahead of that first write
+        let first = msg(0, &[(0, 1)]);
+        let second = msg(0, &[(0, 2)]);
+        let reply = msg(1, &[(0, 1), (1, 1)]);
+        assert!(node.receive(reply.clone(), &mut persist));
+        assert!(node.receive(second.clone(), &mut persist));
+        assert!(node.log().is_empty());
+        assert_eq!(node.buffer.len(), 2);
+
+        assert!(node.receive(first.clone(), &mut persist));
+        assert!(node.buffer.is_empty());
+        assert_eq!(node.log()[0], first);
+        assert_eq!(node.log().len(), 3);
+        assert_eq!(node.held_back, 2);
+        // Duplicates change nothing
+        assert!(!node.receive(reply, &mut persist));
+        assert_eq!(stored.len(), 3);
+    }
+
+    #[test]
+    fn test_receive_keeps_messages_it_cannot_store() {
+        let mut node = CausalBroadcast::new();
+        let first = msg(0, &[(0, 1)]);
+        assert!(node.receive(first.clone(), &mut |_| false));
+        assert!(node.log().is_empty());
+        assert_eq!(node.buffer, [first.clone()]);
+        // A duplicate retries it
+        assert!(node.receive(first.clone(), &mut |_| true));
+        assert_eq!(node.log(), [first]);
+        assert!(node.buffer.is_empty());
+        assert_eq!(node.held_back, 0);
+    }
+}
@@ -0,0 +1,289 @@
+//! # ftsim-proto::protocols::crdt
+//!
+//! State-based CRDTs: a grow-only counter and an observed-remove set,
+//! replicated by merging whole states. Every `MERGE_PERIOD_MS`, each node
+//! sends its state to its peers, which merge it into theirs. Merging takes
+//! per-node maxima for the counter and unions for the set, so it is
+//! commutative, associative and idempotent: duplicated, reordered or lost
+//! merges cannot make replicas disagree once they hear from each other.
+//!
+//! Client `Put`s are the workload. Key `incr` adds the value, a number, to
+//! the counter; keys `add` and `remove` add the value to the set or remove
+//! it. A remove only removes the adds the node has seen, so an add
+//! concurrent with it wins. `Get` of `counter` or `set` reads them.
+//!
+//! A crash keeps the state, as if it were on disk. Each node publishes its
+//! `counter`, its `elements` count and a hash of its encoded state under
+//! `api::REPLICA_HASH_KEY`, which the engine's
+//! `crdt_convergence_after_quiescence` invariant compares across nodes.
+
+use crate::{
+    api::{decode_message, encode_message, REPLICA_HASH_KEY},
+    Ctx, FaultEvent, Protocol,
+};
+use ftsim_types::{
+    client::{ClientOp, ClientResponse},
+    envelope::ProtoTag,
+    errors::CodecError,
+    id::{NodeId, TimerId},
+    time::sim_from_ms,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+const TAG: ProtoTag = ProtoTag(9);
+
+/// How often a node sends its state to its peers, in milliseconds.
+pub const MERGE_PERIOD_MS: u64 = 50;
+
+/// A grow-only counter: how much each node has added.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct GCounter {
+    counts: BTreeMap<NodeId, u64>,
+}
+
+impl GCounter {
+    /// The total. A merge cannot refuse counts, so one whose sum does not
+    /// fit saturates.
+    pub fn value(&self) -> u64 {
+        self.counts.values().fold(0, |sum, &count| sum.saturating_add(count))
+    }
+
+    /// Adds `by` to the node's count, unless the total would overflow.
+    pub fn increment(&mut self, node: NodeId, by: u64) -> Result<(), String> {
+        let total = self.counts.values().try_fold(by, |sum, &count| sum.checked_add(count));
+        if total.is_none() {
+            return Err(format!("adding {} would overflow the counter", by));
+        }
+        // The node's count is part of the total, so it cannot overflow either.
+        *self.counts.entry(node).or_default() += by;
+        Ok(())
+    }
+
+    /// Keeps the larger count of each node. Returns whether any grew.
+    pub fn merge(&mut self, other: &GCounter) -> bool {
+        let mut changed = false;
+        for (&node, &count) in &other.counts {
+            let held = self.counts.entry(node).or_default();
+            if count > *held {
+                *held = count;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// Identifies one add: the node that made it and how many adds that node
+/// had made, this one included.
+pub type Dot = (NodeId, u64);
+
+/// An observed-remove set. Every add is tagged with a fresh dot, and a
+/// remove tombstones the dots of the element it has seen, so an element is
+/// present while it has an add that no remove saw.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct OrSet {
+    adds: BTreeMap<String, BTreeSet<Dot>>,
+    removed: BTreeSet<Dot>,
+}
+
+impl OrSet {
+    pub fn contains(&self, element: &str) -> bool {
+        self.adds.get(element).is_some_and(|dots| dots.iter().any(|dot| !self.removed.contains(dot)))
+    }
+
+    /// The present elements, in order.
+    pub fn elements(&self) -> Vec<&str> {
+        self.adds.keys().filter(|element| self.contains(element)).map(String::as_str).collect()
+    }
+
+    pub fn add(&mut self, element: String, dot: Dot) {
+        self.adds.entry(element).or_default().insert(dot);
+    }
+
+    pub fn remove(&mut self, element: &str) {
+        if let Some(dots) = self.adds.get(element) {
+            self.removed.extend(dots);
+        }
+    }
+
+    /// Takes the union of both sets' adds and removes. Returns whether
+    /// anything was new.
+    pub fn merge(&mut self, other: &OrSet) -> bool {
+        let mut changed = false;
+        for (element, dots) in &other.adds {
+            let held = self.adds.entry(element.clone()).or_default();
+            for dot in dots {
+                changed |= held.insert(*dot);
+            }
+        }
+        for dot in &other.removed {
+            changed |= self.removed.insert(*dot);
+        }
+        changed
+    }
+}
+
+/// Everything a node replicates.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct State {
+    pub counter: GCounter,
+    pub set: OrSet,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Message {
+    /// The sender's whole state, to be merged.
+    State(State),
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Crdt {
+    state: State,
+    /// How many adds this node has made, numbering its dots.
+    adds: u64,
+    #[serde(skip)]
+    merge_timer: Option<TimerId>,
+}
+
+impl Crdt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// A 64-bit FNV-1a hash of the encoded state, in hex. Nodes holding the
+    /// same state have the same hash.
+    fn hash(&self) -> String {
+        let bytes = encode_message(&self.state).unwrap_or_default();
+        let hash = bytes
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3));
+        format!("{:016x}", hash)
+    }
+
+    fn publish(&self, ctx: &mut Ctx<Message>) {
+        let (counter, elements) = (self.state.counter.value(), self.state.set.elements().len() as u64);
+        ctx.log_kv_u64("counter", counter);
+        ctx.log_kv_u64("elements", elements);
+        ctx.log_kv_pinned(REPLICA_HASH_KEY, &self.hash());
+        ctx.log_metric("counter", counter as f64);
+        ctx.log_metric("elements", elements as f64);
+    }
+
+    /// Applies a client write, or returns why it was refused.
+    fn write(&mut self, node: NodeId, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "incr" => {
+                let by = value.parse().map_err(|_| format!("'{}' is not a count", value))?;
+                self.state.counter.increment(node, by)?;
+            }
+            "add" => {
+                self.adds += 1;
+                self.state.set.add(value.to_string(), (node, self.adds));
+            }
+            "remove" => self.state.set.remove(value),
+            _ => return Err(format!("unknown operation '{}'; use incr, add or remove", key)),
+        }
+        Ok(())
+    }
+}
+
+impl Protocol<Message> for Crdt {
+    fn name(&self) -> &'static str {
+        "crdt"
+    }
+
+    fn proto_tag(&self) -> ProtoTag {
+        TAG
+    }
+
+    fn init(&mut self, ctx: &mut Ctx<Message>) {
+        self.publish(ctx);
+    }
+
+    fn start(&mut self, ctx: &mut Ctx<Message>) {
+        self.merge_timer = Some(ctx.set_periodic_timer(sim_from_ms(MERGE_PERIOD_MS)));
+    }
+
+    fn on_message(&mut self, ctx: &mut Ctx<Message>, _src: NodeId, msg: Message) {
+        let Message::State(state) = msg;
+        let grew = self.state.counter.merge(&state.counter);
+        if self.state.set.merge(&state.set) || grew {
+            self.publish(ctx);
+        }
+    }
+
+    fn on_timer(&mut self, ctx: &mut Ctx<Message>, timer: TimerId) {
+        if Some(timer) == self.merge_timer {
+            ctx.broadcast(&Message::State(self.state.clone()), None).ok();
+        }
+    }
+
+    fn on_fault(&mut self, _ctx: &mut Ctx<Message>, fault: FaultEvent) {
+        if let FaultEvent::NodeCrashed = fault {
+            // Pending timers are dropped on crash; a restart calls `start` again.
+            self.merge_timer = None;
+        }
+    }
+
+    fn on_client_request(&mut self, ctx: &mut Ctx<Message>, op: &ClientOp) -> Option<ClientResponse> {
+        match op {
+            ClientOp::Put { key, value } => match self.write(ctx.node_id(), key, value) {
+                Ok(()) => {
+                    self.publish(ctx);
+                    Some(ClientResponse::Ok)
+                }
+                Err(reason) => Some(ClientResponse::Rejected(reason)),
+            },
+            ClientOp::Get { key } => match key.as_str() {
+                "counter" => Some(ClientResponse::Value(Some(self.state.counter.value().to_string()))),
+                "set" => Some(ClientResponse::Value(Some(self.state.set.elements().join(",")))),
+                _ => Some(ClientResponse::Rejected(format!("unknown key '{}'; use counter or set", key))),
+            },
+            ClientOp::Custom { .. } => None,
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<Vec<u8>> {
+        encode_message(self).ok()
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), CodecError> {
+        let timer = self.merge_timer;
+        *self = decode_message(state)?;
+        self.merge_timer = timer;
+        Ok(())
+    }
+
+    fn sample_messages(&self) -> Vec<Message> {
+        let mut state = State::default();
+        state.counter.increment(0, 2).ok();
+        state.set.add("x".into(), (1, 1));
+        state.set.add("y".into(), (1, 2));
+        state.set.remove("y");
+        vec![Message::State(state)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_an_increment_that_would_overflow_the_counter_is_refused() {
+        let mut counter = GCounter::default();
+        counter.increment(0, u64::MAX - 1).unwrap();
+        assert!(counter.increment(1, 2).is_err());
+        counter.increment(1, 1).unwrap();
+        assert_eq!(counter.value(), u64::MAX);
+
+        let mut other = GCounter::default();
+        other.increment(2, 5).unwrap();
+        counter.merge(&other);
+        assert_eq!(counter.value(), u64::MAX);
+    }
+}
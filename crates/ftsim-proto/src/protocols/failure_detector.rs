@@ -0,0 +1,125 @@
+//! # ftsim-proto::protocols::failure_detector
+//!
+//! A ping-based failure detector, meant to run alongside another protocol
+//! on the same nodes. Every node pings each of its peers periodically and
+//! suspects a peer that has not answered for a while. Each node publishes
+//! the peers it suspects under the `suspects` KV, as a comma-separated
+//! list, so that the views of different nodes can be compared, e.g. with
+//! `SimulationReport::suspicions`.
+
+use crate::{Ctx, FaultEvent, Protocol};
+use ftsim_types::{
+    envelope::ProtoTag,
+    id::{NodeId, TimerId},
+    time::{sim_from_ms, SimTime},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+const TAG: ProtoTag = ProtoTag(4);
+
+/// How often a node pings its peers, in milliseconds.
+const PING_PERIOD_MS: u64 = 20;
+
+/// How long a peer may go without answering before it is suspected, in
+/// milliseconds.
+const SUSPECT_AFTER_MS: u64 = 100;
+
+/// The KV the suspected peers are published under.
+pub const SUSPECTS_KEY: &str = "suspects";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Message {
+    Ping,
+    Pong,
+}
+
+#[derive(Default)]
+pub struct FailureDetector {
+    /// When each peer last answered a ping, or when watching it started.
+    last_heard: BTreeMap<NodeId, SimTime>,
+    suspects: BTreeSet<NodeId>,
+    ping_timer: Option<TimerId>,
+}
+
+impl FailureDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Recomputes the suspects, publishing them if they changed.
+    fn update_suspects(&mut self, ctx: &mut Ctx<Message>) {
+        let now = ctx.now();
+        let suspects: BTreeSet<NodeId> = self
+            .last_heard
+            .iter()
+            .filter(|(_, &heard)| now.saturating_sub(heard) > sim_from_ms(SUSPECT_AFTER_MS))
+            .map(|(&peer, _)| peer)
+            .collect();
+        if suspects != self.suspects {
+            self.suspects = suspects;
+            self.publish(ctx);
+        }
+    }
+
+    fn publish(&self, ctx: &mut Ctx<Message>) {
+        let list: Vec<String> = self.suspects.iter().map(|peer| peer.to_string()).collect();
+        ctx.log_kv_pinned(SUSPECTS_KEY, &list.join(","));
+        ctx.log_metric("suspects", self.suspects.len() as f64);
+    }
+}
+
+impl Protocol<Message> for FailureDetector {
+    fn name(&self) -> &'static str {
+        "failure_detector"
+    }
+
+    fn proto_tag(&self) -> ProtoTag {
+        TAG
+    }
+
+    fn init(&mut self, ctx: &mut Ctx<Message>) {
+        // Peers get a full timeout to answer the first ping, also after a
+        // restart
+        let now = ctx.now();
+        self.last_heard = ctx.peers().into_iter().map(|peer| (peer, now)).collect();
+        self.suspects.clear();
+        self.publish(ctx);
+    }
+
+    fn start(&mut self, ctx: &mut Ctx<Message>) {
+        self.ping_timer = Some(ctx.set_periodic_timer(sim_from_ms(PING_PERIOD_MS)));
+    }
+
+    fn on_message(&mut self, ctx: &mut Ctx<Message>, src: NodeId, msg: Message) {
+        match msg {
+            Message::Ping => {
+                ctx.send(src, &Message::Pong).ok();
+            }
+            Message::Pong => {
+                if let Some(heard) = self.last_heard.get_mut(&src) {
+                    *heard = ctx.now();
+                }
+                self.update_suspects(ctx);
+            }
+        }
+    }
+
+    fn on_timer(&mut self, ctx: &mut Ctx<Message>, timer: TimerId) {
+        if Some(timer) == self.ping_timer {
+            ctx.broadcast(&Message::Ping, None).ok();
+            self.update_suspects(ctx);
+        }
+    }
+
+    fn on_fault(&mut self, _ctx: &mut Ctx<Message>, fault: FaultEvent) {
+        if let FaultEvent::NodeCrashed = fault {
+            // Pending timers are dropped on crash; a restart calls `start` again.
+            self.ping_timer = None;
+        }
+    }
+
+    fn sample_messages(&self) -> Vec<Message> {
+        vec![Message::Ping, Message::Pong]
+    }
+}
@@ -0,0 +1,236 @@
+//! # ftsim-proto::protocols::gossip
+//!
+//! An anti-entropy example. Every node holds a key-value map whose entries
+//! carry a version, and a write wins over every write its node had seen:
+//! versions are Lamport clocks, ties broken by the writing node. A client
+//! `Put` on any node writes locally and pushes the entry to a few random
+//! peers. Every `GOSSIP_PERIOD_MS`, each node also sends a digest of its
+//! versions to one random peer, which answers with the entries the sender
+//! lacks and asks for the ones it lacks itself, so that nodes cut off by a
+//! partition or a crash catch up once they can talk again.
+//!
+//! A crash loses the map; anti-entropy refills it after the restart. Each
+//! node publishes its `entries` count and a hash of its versions under
+//! `api::REPLICA_HASH_KEY`, which the engine compares across nodes to report
+//! when they converge.
+
+use crate::{
+    api::{decode_message, encode_message, REPLICA_HASH_KEY},
+    Ctx, FaultEvent, Protocol,
+};
+use ftsim_types::{
+    client::{ClientOp, ClientResponse},
+    envelope::ProtoTag,
+    errors::CodecError,
+    id::{NodeId, TimerId},
+    time::sim_from_ms,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+const TAG: ProtoTag = ProtoTag(6);
+
+/// How often a node exchanges digests with a random peer, in milliseconds.
+pub const GOSSIP_PERIOD_MS: u64 = 50;
+
+/// How many random peers a new write is pushed to.
+pub const FANOUT: usize = 2;
+
+/// The version of an entry. Later versions compare greater.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub clock: u64,
+    pub origin: NodeId,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub value: String,
+    pub version: Version,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Message {
+    /// The sender's version of every key it holds.
+    Digest { versions: BTreeMap<String, Version> },
+    /// The answer to a digest: the entries newer than the digest's, and the
+    /// keys of which the digest had newer versions.
+    Exchange { entries: BTreeMap<String, Entry>, wanted: Vec<String> },
+    /// Entries the receiver may lack: a new write, or those asked for in an
+    /// exchange.
+    Entries { entries: BTreeMap<String, Entry> },
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Gossip {
+    entries: BTreeMap<String, Entry>,
+    /// The Lamport clock: the highest version clock this node has seen.
+    clock: u64,
+    #[serde(skip)]
+    gossip_timer: Option<TimerId>,
+}
+
+impl Gossip {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn versions(&self) -> BTreeMap<String, Version> {
+        self.entries.iter().map(|(key, entry)| (key.clone(), entry.version)).collect()
+    }
+
+    /// Whether `version` of `key` is newer than the one held, if any.
+    fn is_newer(&self, key: &str, version: Version) -> bool {
+        self.entries.get(key).map_or(true, |entry| version > entry.version)
+    }
+
+    /// Keeps the entries newer than those held, publishing if any were.
+    fn merge(&mut self, ctx: &mut Ctx<Message>, entries: BTreeMap<String, Entry>) {
+        let mut changed = false;
+        for (key, entry) in entries {
+            self.clock = self.clock.max(entry.version.clock);
+            if self.is_newer(&key, entry.version) {
+                self.entries.insert(key, entry);
+                changed = true;
+            }
+        }
+        if changed {
+            self.publish(ctx);
+        }
+    }
+
+    fn write(&mut self, ctx: &mut Ctx<Message>, key: String, value: String) {
+        self.clock += 1;
+        let entry = Entry { value, version: Version { clock: self.clock, origin: ctx.node_id() } };
+        self.entries.insert(key.clone(), entry.clone());
+        self.publish(ctx);
+
+        let mut peers = ctx.peers();
+        ctx.rng_shuffle(&mut peers);
+        let chosen: BTreeSet<NodeId> = peers.into_iter().take(FANOUT).collect();
+        let msg = Message::Entries { entries: BTreeMap::from([(key, entry)]) };
+        ctx.broadcast(&msg, Some(&|peer| chosen.contains(&peer))).ok();
+    }
+
+    /// Sends a digest to a random peer.
+    fn gossip(&self, ctx: &mut Ctx<Message>) {
+        let peers = ctx.peers();
+        if peers.is_empty() {
+            return;
+        }
+        let peer = *ctx.rng_choose(&peers);
+        ctx.send(peer, &Message::Digest { versions: self.versions() }).ok();
+    }
+
+    /// A 64-bit FNV-1a hash of every key and version, in hex. Nodes holding
+    /// the same versions have the same hash.
+    fn hash(&self) -> String {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for (key, entry) in &self.entries {
+            let (clock, origin) = (entry.version.clock.to_le_bytes(), entry.version.origin.to_le_bytes());
+            for byte in key.as_bytes().iter().chain(&[0]).chain(&clock).chain(&origin) {
+                hash = (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        format!("{:016x}", hash)
+    }
+
+    fn publish(&self, ctx: &mut Ctx<Message>) {
+        ctx.log_kv_u64("entries", self.entries.len() as u64);
+        ctx.log_kv_pinned(REPLICA_HASH_KEY, &self.hash());
+        ctx.log_metric("entries", self.entries.len() as f64);
+    }
+}
+
+impl Protocol<Message> for Gossip {
+    fn name(&self) -> &'static str {
+        "gossip"
+    }
+
+    fn proto_tag(&self) -> ProtoTag {
+        TAG
+    }
+
+    fn init(&mut self, ctx: &mut Ctx<Message>) {
+        // Also called on restart, after the crash lost the map
+        self.entries.clear();
+        self.clock = 0;
+        self.publish(ctx);
+    }
+
+    fn start(&mut self, ctx: &mut Ctx<Message>) {
+        self.gossip_timer = Some(ctx.set_periodic_timer(sim_from_ms(GOSSIP_PERIOD_MS)));
+    }
+
+    fn on_message(&mut self, ctx: &mut Ctx<Message>, src: NodeId, msg: Message) {
+        match msg {
+            Message::Digest { versions } => {
+                let entries = self
+                    .entries
+                    .iter()
+                    .filter(|(key, entry)| versions.get(*key).map_or(true, |&version| entry.version > version))
+                    .map(|(key, entry)| (key.clone(), entry.clone()))
+                    .collect();
+                let wanted: Vec<String> =
+                    versions.into_iter().filter(|(key, version)| self.is_newer(key, *version)).map(|(key, _)| key).collect();
+                ctx.send(src, &Message::Exchange { entries, wanted }).ok();
+            }
+            Message::Exchange { entries, wanted } => {
+                let reply: BTreeMap<String, Entry> = wanted
+                    .into_iter()
+                    .filter_map(|key| self.entries.get(&key).map(|entry| (key, entry.clone())))
+                    .collect();
+                self.merge(ctx, entries);
+                if !reply.is_empty() {
+                    ctx.send(src, &Message::Entries { entries: reply }).ok();
+                }
+            }
+            Message::Entries { entries } => self.merge(ctx, entries),
+        }
+    }
+
+    fn on_timer(&mut self, ctx: &mut Ctx<Message>, timer: TimerId) {
+        if Some(timer) == self.gossip_timer {
+            self.gossip(ctx);
+        }
+    }
+
+    fn on_fault(&mut self, _ctx: &mut Ctx<Message>, fault: FaultEvent) {
+        if let FaultEvent::NodeCrashed = fault {
+            // Pending timers are dropped on crash; a restart calls `start` again.
+            self.gossip_timer = None;
+        }
+    }
+
+    fn on_client_request(&mut self, ctx: &mut Ctx<Message>, op: &ClientOp) -> Option<ClientResponse> {
+        match op {
+            ClientOp::Put { key, value } => {
+                self.write(ctx, key.clone(), value.clone());
+                Some(ClientResponse::Ok)
+            }
+            ClientOp::Get { key } => Some(ClientResponse::Value(self.entries.get(key).map(|e| e.value.clone()))),
+            ClientOp::Custom { .. } => None,
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<Vec<u8>> {
+        encode_message(self).ok()
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), CodecError> {
+        let timer = self.gossip_timer;
+        *self = decode_message(state)?;
+        self.gossip_timer = timer;
+        Ok(())
+    }
+
+    fn sample_messages(&self) -> Vec<Message> {
+        let version = Version { clock: 1, origin: 0 };
+        let entries = BTreeMap::from([("key".to_string(), Entry { value: "value".into(), version })]);
+        vec![
+            Message::Digest { versions: BTreeMap::from([("key".to_string(), version)]) },
+            Message::Exchange { entries: entries.clone(), wanted: vec!["key".into()] },
+            Message::Entries { entries },
+        ]
+    }
+}
@@ -0,0 +1,592 @@
+//! # ftsim-proto::protocols::lease_kv
+//!
+//! A key-value store whose leader serves reads locally under a time-based
+//! lease, the one bundled protocol whose safety rests on `ctx.now()`.
+//!
+//! Every `HEARTBEAT_MS`, the leader sends its term and a round number to
+//! its followers, with the writes each one lacks: its whole map to a
+//! follower it has not synced yet, then only the entries newer than the
+//! version the follower last acknowledged holding. A follower that accepts
+//! the heartbeat promises, on its own clock, not to vote for anyone else for
+//! `LEASE_MS`, and acknowledges the round with the latest version it holds. Once a quorum has acknowledged a round, the
+//! leader holds a lease until `LEASE_MS` after it sent the round, on its own
+//! clock, and the writes the round carried are committed. The leader serves
+//! a `Get` only while `ctx.now()` is before the lease's expiry, and refuses
+//! it otherwise; followers refuse every operation.
+//!
+//! A follower that has not heard from a leader for a random election
+//! timeout, and whose promise has run out, stands for election in a new
+//! term. Nodes grant their vote only when their own promise has run out and
+//! the candidate holds every write they hold. With clocks that agree, a
+//! leader's lease thus ends before any follower's promise, and no new
+//! leader can commit a write while the old one still serves reads. A leader
+//! whose clock runs behind keeps serving them after its lease really ended,
+//! and reads stale values.
+//!
+//! The term and vote are kept under one store KV key, and every change to
+//! the map is appended to the store's log and fsynced before the node acts
+//! on it. A restarted node reloads them and comes back as a follower. It
+//! cannot tell what it promised before the crash, so it waits out a whole
+//! lease before voting. Each node publishes its role, its term, the writes
+//! it committed as leader and its last read with `Ctx::publish_state`, which
+//! the engine's `linearizable_reads` invariant checks, along with
+//! `reads_served` and `reads_refused` metrics. Those are the engine's record
+//! rather than protocol state, and outlive a crash.
+
+use crate::{
+    api::{decode_message, encode_message, LogRecord},
+    Ctx, FaultEvent, Protocol,
+};
+use ftsim_types::{
+    client::{ClientOp, ClientResponse},
+    envelope::ProtoTag,
+    errors::CodecError,
+    id::{NodeId, TimerId},
+    time::{sim_from_ms, SimTime},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+const TAG: ProtoTag = ProtoTag(10);
+
+/// How long a quorum's acknowledgement lets the leader serve reads, and how
+/// long a follower's promise not to vote lasts, in milliseconds.
+pub const LEASE_MS: u64 = 200;
+
+/// How often a leader sends heartbeats, in milliseconds.
+pub const HEARTBEAT_MS: u64 = 50;
+
+/// How often a node checks its election deadline, in milliseconds. The
+/// tick runs on sim time, so clock skew does not change how often
+/// heartbeats go out.
+const TICK_MS: u64 = 10;
+
+/// The range election timeouts are drawn from, on the node's own clock.
+const ELECTION_TIMEOUT_MS: std::ops::RangeInclusive<u64> = 250..=400;
+
+/// The store KV key of the current term and vote.
+const HARD_STATE_KEY: &[u8] = b"lease_kv/hard_state";
+
+/// Orders writes: a later term wins, then a later index.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub term: u64,
+    pub index: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub value: String,
+    pub version: Version,
+}
+
+/// A read the leader served.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Read {
+    pub key: String,
+    /// The value read, with its version, or `None` if the key was unset.
+    pub entry: Option<Entry>,
+    /// How many reads the node had served, this one included.
+    pub seq: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+impl Role {
+    fn as_str(self) -> &'static str {
+        match self {
+            Role::Follower => "follower",
+            Role::Candidate => "candidate",
+            Role::Leader => "leader",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Message {
+    /// The round being acknowledged and the latest version in the leader's
+    /// map. `entries` are the leader's entries newer than `since`, or its
+    /// whole map if `since` is `None`.
+    Heartbeat { term: u64, round: u64, since: Option<Version>, last: Version, entries: BTreeMap<String, Entry> },
+    /// Acknowledges a heartbeat with the latest version the sender holds,
+    /// or, with a newer term, tells a deposed leader it is.
+    HeartbeatAck { term: u64, round: u64, last: Version },
+    RequestVote { term: u64, last: Version },
+    /// A granted vote; refusals are not sent.
+    Vote { term: u64 },
+}
+
+/// The current term and vote, as stored.
+#[derive(Serialize, Deserialize)]
+struct HardState {
+    term: u64,
+    voted_for: Option<NodeId>,
+}
+
+/// A change to the map, as appended to the store's log.
+#[derive(Serialize, Deserialize)]
+struct Stored {
+    /// Whether `entries` replace the whole map rather than update it.
+    replace: bool,
+    last: Version,
+    entries: BTreeMap<String, Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LeaseKv {
+    term: u64,
+    voted_for: Option<NodeId>,
+    role: Role,
+    leader: Option<NodeId>,
+    data: BTreeMap<String, Entry>,
+    /// The latest version in `data`.
+    last: Version,
+    /// Until when, on this node's clock, it votes for no one.
+    promised_until: SimTime,
+    /// When the next election starts, on this node's clock, unless a leader
+    /// is heard from.
+    election_deadline: SimTime,
+    votes: BTreeSet<NodeId>,
+    /// While leader: until when, on this node's clock, it serves reads.
+    lease_expiry: SimTime,
+    /// While leader: the last heartbeat round sent, when it was sent, the
+    /// latest version it carried, and who acknowledged it.
+    round: u64,
+    round_sent_at: SimTime,
+    round_last: Version,
+    round_acks: BTreeSet<NodeId>,
+    /// While leader: the version each follower last acknowledged holding
+    /// all of the leader's writes up to. Followers missing from it are sent
+    /// the whole map.
+    matched: BTreeMap<NodeId, Version>,
+    /// While leader: writes not yet committed, with the first round that
+    /// carries them.
+    pending: Vec<(u64, String, Entry)>,
+    /// The latest write of each key this node committed as leader.
+    committed: BTreeMap<String, Entry>,
+    last_read: Option<Read>,
+    reads_served: u64,
+    reads_refused: u64,
+    /// Ticks since the node started, to pace heartbeats.
+    ticks: u64,
+    #[serde(skip)]
+    tick_timer: Option<TimerId>,
+}
+
+impl Default for LeaseKv {
+    fn default() -> Self {
+        Self {
+            term: 0,
+            voted_for: None,
+            role: Role::Follower,
+            leader: None,
+            data: BTreeMap::new(),
+            last: Version::default(),
+            promised_until: 0,
+            election_deadline: 0,
+            votes: BTreeSet::new(),
+            lease_expiry: 0,
+            round: 0,
+            round_sent_at: 0,
+            round_last: Version::default(),
+            round_acks: BTreeSet::new(),
+            matched: BTreeMap::new(),
+            pending: Vec::new(),
+            committed: BTreeMap::new(),
+            last_read: None,
+            reads_served: 0,
+            reads_refused: 0,
+            ticks: 0,
+            tick_timer: None,
+        }
+    }
+}
+
+/// What a lease KV node publishes with `Ctx::publish_state`.
+#[derive(Serialize)]
+struct PublishedState<'a> {
+    role: &'static str,
+    term: u64,
+    leader: Option<NodeId>,
+    lease_expiry: SimTime,
+    committed: &'a BTreeMap<String, Entry>,
+    last_read: Option<&'a Read>,
+}
+
+impl LeaseKv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn quorum(ctx: &Ctx<Message>) -> usize {
+        ctx.cluster_size() / 2 + 1
+    }
+
+    fn reset_election_deadline(&mut self, ctx: &mut Ctx<Message>) {
+        self.election_deadline = ctx.now().saturating_add(sim_from_ms(ctx.rng_range(ELECTION_TIMEOUT_MS)));
+    }
+
+    /// Stores the current term and vote. Returns whether they were written.
+    fn save_hard_state(&self, ctx: &mut Ctx<Message>) -> bool {
+        let Ok(bytes) = encode_message(&HardState { term: self.term, voted_for: self.voted_for }) else {
+            return false;
+        };
+        let mut store = ctx.store();
+        store.kv_put(bytes::Bytes::from_static(HARD_STATE_KEY), bytes.into()).is_ok() && store.fsync().is_ok()
+    }
+
+    /// Appends a change to the map to the store's log. Returns whether it
+    /// was written.
+    fn save(ctx: &mut Ctx<Message>, stored: &Stored) -> bool {
+        let Ok(bytes) = encode_message(stored) else {
+            return false;
+        };
+        let mut store = ctx.store();
+        store.append_log(LogRecord::new(stored.last.term, bytes.into())).is_ok() && store.fsync().is_ok()
+    }
+
+    /// Restores the term, vote and map from the store. Damaged records are
+    /// skipped.
+    fn load(&mut self, ctx: &mut Ctx<Message>) {
+        if let Ok(Some(bytes)) = ctx.store().kv_get(HARD_STATE_KEY) {
+            if let Ok(hard_state) = decode_message::<HardState>(&bytes) {
+                self.term = hard_state.term;
+                self.voted_for = hard_state.voted_for;
+            }
+        }
+        for idx in 0.. {
+            let record = ctx.store().read_log(idx);
+            match record {
+                Ok(Some(rec)) if rec.verify() => {
+                    if let Ok(stored) = decode_message::<Stored>(&rec.data) {
+                        self.apply(stored);
+                    }
+                }
+                Ok(Some(_)) | Err(_) => {}
+                Ok(None) => break,
+            }
+        }
+    }
+
+    /// Adopts a newer term as a follower with no leader known. A vote is
+    /// only sent once stored, so failing to store the term is safe.
+    fn become_follower(&mut self, ctx: &mut Ctx<Message>, term: u64) {
+        self.term = term;
+        self.voted_for = None;
+        self.role = Role::Follower;
+        self.leader = None;
+        self.lease_expiry = 0;
+        self.pending.clear();
+        self.save_hard_state(ctx);
+    }
+
+    fn start_election(&mut self, ctx: &mut Ctx<Message>) {
+        self.term += 1;
+        self.role = Role::Candidate;
+        self.leader = None;
+        self.voted_for = Some(ctx.node_id());
+        self.votes = BTreeSet::from([ctx.node_id()]);
+        self.reset_election_deadline(ctx);
+        if !self.save_hard_state(ctx) {
+            // Without the vote stored, it cannot count it; try again later
+            self.votes.clear();
+            return;
+        }
+        ctx.broadcast(&Message::RequestVote { term: self.term, last: self.last }, None).ok();
+        self.count_votes(ctx);
+    }
+
+    fn count_votes(&mut self, ctx: &mut Ctx<Message>) {
+        if self.role == Role::Candidate && self.votes.len() >= Self::quorum(ctx) {
+            self.role = Role::Leader;
+            self.leader = Some(ctx.node_id());
+            self.lease_expiry = 0;
+            self.round_acks.clear();
+            self.matched.clear();
+            self.send_heartbeat(ctx);
+        }
+    }
+
+    fn send_heartbeat(&mut self, ctx: &mut Ctx<Message>) {
+        self.round += 1;
+        self.round_sent_at = ctx.now();
+        self.round_last = self.last;
+        self.round_acks = BTreeSet::from([ctx.node_id()]);
+        for peer in ctx.peers() {
+            let since = self.matched.get(&peer).copied();
+            let entries = match since {
+                Some(since) => self
+                    .data
+                    .iter()
+                    .filter(|(_, entry)| entry.version > since)
+                    .map(|(key, entry)| (key.clone(), entry.clone()))
+                    .collect(),
+                None => self.data.clone(),
+            };
+            let msg = Message::Heartbeat { term: self.term, round: self.round, since, last: self.last, entries };
+            ctx.send(peer, &msg).ok();
+        }
+        self.check_round(ctx);
+    }
+
+    /// Stores and applies the leader's entries if they are newer than what
+    /// this node holds and follow on from it.
+    fn adopt(
+        &mut self,
+        ctx: &mut Ctx<Message>,
+        since: Option<Version>,
+        last: Version,
+        entries: BTreeMap<String, Entry>,
+    ) {
+        let follows = since.map_or(true, |since| since == self.last);
+        let stored = Stored { replace: since.is_none(), last, entries };
+        if last > self.last && follows && Self::save(ctx, &stored) {
+            self.apply(stored);
+        }
+    }
+
+    fn apply(&mut self, stored: Stored) {
+        if stored.replace {
+            self.data.clear();
+        }
+        self.data.extend(stored.entries);
+        self.last = stored.last;
+    }
+
+    /// Renews the lease and commits the round's writes once a quorum has
+    /// acknowledged it.
+    fn check_round(&mut self, ctx: &mut Ctx<Message>) {
+        if self.round_acks.len() < Self::quorum(ctx) {
+            return;
+        }
+        self.lease_expiry = self.lease_expiry.max(self.round_sent_at.saturating_add(sim_from_ms(LEASE_MS)));
+        // A leader honors its own lease as a promise not to vote
+        self.promised_until = self.promised_until.max(self.lease_expiry);
+        let round = self.round;
+        let (done, pending) = std::mem::take(&mut self.pending).into_iter().partition(|(first, _, _)| *first <= round);
+        self.pending = pending;
+        for (_, key, entry) in done {
+            if self.committed.get(&key).map_or(true, |held: &Entry| held.version < entry.version) {
+                self.committed.insert(key, entry);
+            }
+        }
+    }
+
+    /// Stores and applies a client write. Returns whether it was stored.
+    fn write(&mut self, ctx: &mut Ctx<Message>, key: String, value: String) -> bool {
+        let version = Version { term: self.term, index: self.last.index + 1 };
+        let entry = Entry { value, version };
+        let stored = Stored { replace: false, last: version, entries: BTreeMap::from([(key.clone(), entry.clone())]) };
+        if !Self::save(ctx, &stored) {
+            return false;
+        }
+        self.apply(stored);
+        self.pending.push((self.round + 1, key, entry));
+        true
+    }
+
+    fn read(&mut self, ctx: &Ctx<Message>, key: &str) -> ClientResponse {
+        if self.role != Role::Leader {
+            self.reads_refused += 1;
+            return ClientResponse::Rejected(self.not_leader());
+        }
+        if ctx.now() >= self.lease_expiry {
+            self.reads_refused += 1;
+            return ClientResponse::Rejected("the lease has expired".into());
+        }
+        self.reads_served += 1;
+        let entry = self.data.get(key).cloned();
+        let value = entry.as_ref().map(|entry| entry.value.clone());
+        self.last_read = Some(Read { key: key.to_string(), entry, seq: self.reads_served });
+        ClientResponse::Value(value)
+    }
+
+    fn not_leader(&self) -> String {
+        match self.leader {
+            Some(leader) => format!("not the leader; node {} is", leader),
+            None => "not the leader; no leader known".into(),
+        }
+    }
+
+    fn publish(&self, ctx: &mut Ctx<Message>) {
+        ctx.publish_state(&PublishedState {
+            role: self.role.as_str(),
+            term: self.term,
+            leader: self.leader,
+            lease_expiry: self.lease_expiry,
+            committed: &self.committed,
+            last_read: self.last_read.as_ref(),
+        });
+        ctx.log_kv_pinned("role", self.role.as_str());
+        ctx.log_kv_u64("term", self.term);
+        ctx.log_metric("term", self.term as f64);
+        ctx.log_metric("reads_served", self.reads_served as f64);
+        ctx.log_metric("reads_refused", self.reads_refused as f64);
+    }
+}
+
+impl Protocol<Message> for LeaseKv {
+    fn name(&self) -> &'static str {
+        "lease_kv"
+    }
+
+    fn proto_tag(&self) -> ProtoTag {
+        TAG
+    }
+
+    fn init(&mut self, ctx: &mut Ctx<Message>) {
+        // Also called on restart: only what was stored survives a crash,
+        // besides the engine's record of reads and committed writes
+        *self = Self {
+            committed: std::mem::take(&mut self.committed),
+            last_read: self.last_read.take(),
+            reads_served: self.reads_served,
+            reads_refused: self.reads_refused,
+            ..Self::default()
+        };
+        self.load(ctx);
+        self.promised_until = ctx.now().saturating_add(sim_from_ms(LEASE_MS));
+        self.publish(ctx);
+    }
+
+    fn start(&mut self, ctx: &mut Ctx<Message>) {
+        self.reset_election_deadline(ctx);
+        self.tick_timer = Some(ctx.set_periodic_timer(sim_from_ms(TICK_MS)));
+    }
+
+    fn on_message(&mut self, ctx: &mut Ctx<Message>, src: NodeId, msg: Message) {
+        match msg {
+            Message::Heartbeat { term, round, since, last, entries } => {
+                if term < self.term {
+                    ctx.send(src, &Message::HeartbeatAck { term: self.term, round, last: self.last }).ok();
+                    return;
+                }
+                if term > self.term {
+                    self.become_follower(ctx, term);
+                }
+                self.role = Role::Follower;
+                self.leader = Some(src);
+                self.promised_until = ctx.now().saturating_add(sim_from_ms(LEASE_MS));
+                self.reset_election_deadline(ctx);
+                self.adopt(ctx, since, last, entries);
+                ctx.send(src, &Message::HeartbeatAck { term, round, last: self.last }).ok();
+            }
+            Message::HeartbeatAck { term, round, last } => {
+                if term > self.term {
+                    self.become_follower(ctx, term);
+                    self.reset_election_deadline(ctx);
+                } else if self.role == Role::Leader && term == self.term && round == self.round {
+                    // A follower holding anything else is sent the whole map
+                    if last == self.round_last {
+                        self.matched.insert(src, last);
+                    } else {
+                        self.matched.remove(&src);
+                    }
+                    self.round_acks.insert(src);
+                    self.check_round(ctx);
+                }
+            }
+            Message::RequestVote { term, last } => {
+                // Still bound by a promise, a node ignores candidates altogether
+                if ctx.now() < self.promised_until {
+                    return;
+                }
+                if term > self.term {
+                    self.become_follower(ctx, term);
+                }
+                if term == self.term && self.voted_for.map_or(true, |vote| vote == src) && last >= self.last {
+                    self.voted_for = Some(src);
+                    self.reset_election_deadline(ctx);
+                    if self.save_hard_state(ctx) {
+                        ctx.send(src, &Message::Vote { term }).ok();
+                    }
+                }
+            }
+            Message::Vote { term } => {
+                if term == self.term && self.role == Role::Candidate {
+                    self.votes.insert(src);
+                    self.count_votes(ctx);
+                }
+            }
+        }
+        self.publish(ctx);
+    }
+
+    fn on_timer(&mut self, ctx: &mut Ctx<Message>, timer: TimerId) {
+        if Some(timer) != self.tick_timer {
+            return;
+        }
+        self.ticks += 1;
+        match self.role {
+            Role::Leader => {
+                if self.ticks % (HEARTBEAT_MS / TICK_MS) == 0 {
+                    self.send_heartbeat(ctx);
+                }
+            }
+            Role::Follower | Role::Candidate => {
+                let now = ctx.now();
+                if now >= self.election_deadline && now >= self.promised_until {
+                    self.start_election(ctx);
+                }
+            }
+        }
+        self.publish(ctx);
+    }
+
+    fn on_fault(&mut self, _ctx: &mut Ctx<Message>, fault: FaultEvent) {
+        if let FaultEvent::NodeCrashed = fault {
+            // Pending timers are dropped on crash; a restart calls `start` again.
+            self.tick_timer = None;
+            self.role = Role::Follower;
+            self.leader = None;
+            self.lease_expiry = 0;
+            self.pending.clear();
+        }
+    }
+
+    fn on_client_request(&mut self, ctx: &mut Ctx<Message>, op: &ClientOp) -> Option<ClientResponse> {
+        let response = match op {
+            // The write commits with the next round a quorum acknowledges
+            ClientOp::Put { key, value } if self.role == Role::Leader => {
+                if self.write(ctx, key.clone(), value.clone()) {
+                    ClientResponse::Ok
+                } else {
+                    ClientResponse::Rejected("store write failed".into())
+                }
+            }
+            ClientOp::Put { .. } => ClientResponse::Rejected(self.not_leader()),
+            ClientOp::Get { key } => self.read(ctx, key),
+            ClientOp::Custom { .. } => return None,
+        };
+        self.publish(ctx);
+        Some(response)
+    }
+
+    fn snapshot_state(&self) -> Option<Vec<u8>> {
+        encode_message(self).ok()
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), CodecError> {
+        let timer = self.tick_timer;
+        *self = decode_message(state)?;
+        self.tick_timer = timer;
+        Ok(())
+    }
+
+    fn sample_messages(&self) -> Vec<Message> {
+        let last = Version { term: 2, index: 3 };
+        let since = Some(Version { term: 2, index: 2 });
+        let entries = BTreeMap::from([("key".to_string(), Entry { value: "value".into(), version: last })]);
+        vec![
+            Message::Heartbeat { term: 2, round: 7, since, last, entries },
+            Message::HeartbeatAck { term: 2, round: 7, last },
+            Message::RequestVote { term: 3, last },
+            Message::Vote { term: 3 },
+        ]
+    }
+}
@@ -2,9 +2,77 @@
 //!
 //! This module contains example protocol implementations that demonstrate
 //! how to use the FTSim SDK.
+//!
+//! Each protocol sits behind a cargo feature of the same name.
+//! `KNOWN_PROTOCOLS` lists every protocol whether or not it was compiled in,
+//! so that callers can tell a disabled protocol from an unknown one.
+
+use ftsim_types::envelope::ProtoTag;
+
+#[cfg(feature = "batch_replicate")]
+pub mod batch_replicate;
+
+#[cfg(feature = "causal_broadcast")]
+pub mod causal_broadcast;
+
+#[cfg(feature = "crdt")]
+pub mod crdt;
+
+#[cfg(feature = "failure_detector")]
+pub mod failure_detector;
+
+#[cfg(feature = "gossip")]
+pub mod gossip;
+
+#[cfg(feature = "lease_kv")]
+pub mod lease_kv;
+
+#[cfg(feature = "ping")]
+pub mod ping;
 
 #[cfg(feature = "primary_backup")]
 pub mod primary_backup;
 
 #[cfg(feature = "raft_lite")]
 pub mod raft_lite;
+
+#[cfg(feature = "two_phase_commit")]
+pub mod two_phase_commit;
+
+/// A protocol that exists in this crate, compiled in or not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownProtocol {
+    pub name: &'static str,
+    pub tag: ProtoTag,
+    /// The cargo feature that compiles the protocol in.
+    pub feature: &'static str,
+    /// Whether the feature was enabled in this build.
+    pub compiled: bool,
+}
+
+macro_rules! known_protocols {
+    ($($name:literal => $tag:literal),* $(,)?) => {
+        /// Every protocol in this crate, in tag order.
+        pub static KNOWN_PROTOCOLS: &[KnownProtocol] = &[$(
+            KnownProtocol {
+                name: $name,
+                tag: ProtoTag($tag),
+                feature: $name,
+                compiled: cfg!(feature = $name),
+            },
+        )*];
+    };
+}
+
+known_protocols! {
+    "raft_lite" => 1,
+    "primary_backup" => 2,
+    "batch_replicate" => 3,
+    "failure_detector" => 4,
+    "two_phase_commit" => 5,
+    "gossip" => 6,
+    "ping" => 7,
+    "causal_broadcast" => 8,
+    "crdt" => 9,
+    "lease_kv" => 10,
+}
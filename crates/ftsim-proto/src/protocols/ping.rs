@@ -0,0 +1,158 @@
+//! # ftsim-proto::protocols::ping
+//!
+//! An echo benchmark for the network model. Every node periodically sends
+//! each peer a Ping stamped with its own clock, and the peer echoes the
+//! stamp back in a Pong, so the round-trip time needs no synchronized
+//! clocks. Each RTT is recorded in the `ping_rtt` histogram, in
+//! nanoseconds. Once per period every node publishes the min, average and
+//! p99 of its recent RTTs to each peer under the `rtt` KV, as a JSON object
+//! keyed by peer, and the worst peer's p99 as the `rtt_p99_ms` metric, so
+//! that phase checks can watch a `LinkDelay` take effect.
+
+use crate::{Ctx, FaultEvent, Protocol};
+use ftsim_types::{
+    envelope::ProtoTag,
+    id::{NodeId, TimerId},
+    time::{sim_from_ms, SimTime},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+const TAG: ProtoTag = ProtoTag(7);
+
+/// How often a node pings its peers, in milliseconds.
+const PING_PERIOD_MS: u64 = 20;
+
+/// How many of the latest RTTs to each peer the statistics cover.
+const WINDOW: usize = 16;
+
+/// The KV the per-peer RTT statistics are published under.
+pub const RTT_KEY: &str = "rtt";
+
+/// The histogram metric every RTT is recorded in.
+pub const RTT_HISTOGRAM: &str = "ping_rtt";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Message {
+    /// Carries the sender's clock when it was sent.
+    Ping { sent_at: SimTime },
+    /// Echoes the stamp of the Ping it answers.
+    Pong { sent_at: SimTime },
+}
+
+/// The RTT statistics to one peer, in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RttStats {
+    pub min_ms: f64,
+    pub avg_ms: f64,
+    pub p99_ms: f64,
+    pub samples: usize,
+}
+
+impl RttStats {
+    /// Summarizes `rtts`, or returns `None` if there are none.
+    fn of(rtts: &VecDeque<SimTime>) -> Option<Self> {
+        let mut sorted: Vec<SimTime> = rtts.iter().copied().collect();
+        sorted.sort_unstable();
+        let min = *sorted.first()?;
+        let sum: SimTime = sorted.iter().sum();
+        // The nearest-rank percentile
+        let rank = (sorted.len() * 99).div_ceil(100).max(1);
+        let ms = |t: SimTime| t as f64 / 1e6;
+        Some(Self {
+            min_ms: ms(min),
+            avg_ms: ms(sum) / sorted.len() as f64,
+            p99_ms: ms(sorted[rank - 1]),
+            samples: sorted.len(),
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct Ping {
+    /// The latest RTTs to each peer, oldest first.
+    rtts: BTreeMap<NodeId, VecDeque<SimTime>>,
+    ping_timer: Option<TimerId>,
+}
+
+impl Ping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the RTT statistics to each peer that has answered.
+    pub fn stats(&self) -> BTreeMap<NodeId, RttStats> {
+        self.rtts.iter().filter_map(|(&peer, rtts)| Some((peer, RttStats::of(rtts)?))).collect()
+    }
+
+    fn record(&mut self, ctx: &mut Ctx<Message>, peer: NodeId, rtt: SimTime) {
+        let rtts = self.rtts.entry(peer).or_default();
+        if rtts.len() == WINDOW {
+            rtts.pop_front();
+        }
+        rtts.push_back(rtt);
+        ctx.log_histogram(RTT_HISTOGRAM, rtt as f64);
+    }
+
+    fn publish(&self, ctx: &mut Ctx<Message>) {
+        let stats = self.stats();
+        if stats.is_empty() {
+            return;
+        }
+        ctx.log_kv_json(RTT_KEY, &stats);
+        let worst = stats.values().map(|s| s.p99_ms).fold(0.0, f64::max);
+        ctx.log_metric("rtt_p99_ms", worst);
+    }
+}
+
+impl Protocol<Message> for Ping {
+    fn name(&self) -> &'static str {
+        "ping"
+    }
+
+    fn proto_tag(&self) -> ProtoTag {
+        TAG
+    }
+
+    fn init(&mut self, _ctx: &mut Ctx<Message>) {
+        // RTTs measured before a crash say nothing about the links now
+        self.rtts.clear();
+    }
+
+    fn start(&mut self, ctx: &mut Ctx<Message>) {
+        self.ping_timer = Some(ctx.set_periodic_timer(sim_from_ms(PING_PERIOD_MS)));
+    }
+
+    fn on_message(&mut self, ctx: &mut Ctx<Message>, src: NodeId, msg: Message) {
+        match msg {
+            Message::Ping { sent_at } => {
+                ctx.send(src, &Message::Pong { sent_at }).ok();
+            }
+            Message::Pong { sent_at } => {
+                // A corrupted pong can claim a time still to come
+                if let Some(rtt) = ctx.now().checked_sub(sent_at) {
+                    self.record(ctx, src, rtt);
+                }
+            }
+        }
+    }
+
+    fn on_timer(&mut self, ctx: &mut Ctx<Message>, timer: TimerId) {
+        if Some(timer) == self.ping_timer {
+            self.publish(ctx);
+            let sent_at = ctx.now();
+            ctx.broadcast(&Message::Ping { sent_at }, None).ok();
+        }
+    }
+
+    fn on_fault(&mut self, _ctx: &mut Ctx<Message>, fault: FaultEvent) {
+        if let FaultEvent::NodeCrashed = fault {
+            // Pending timers are dropped on crash; a restart calls `start` again.
+            self.ping_timer = None;
+        }
+    }
+
+    fn sample_messages(&self) -> Vec<Message> {
+        vec![Message::Ping { sent_at: sim_from_ms(20) }, Message::Pong { sent_at: sim_from_ms(20) }]
+    }
+}
@@ -2,31 +2,101 @@
 //!
 //! An example implementation of a simple Primary-Backup replication protocol.
 //! This demonstrates the basic usage of the `Protocol<M>` SDK.
+//!
+//! A primary told of its own upcoming crash hands primaryship and its data
+//! to the lowest-numbered backup first, so that writes keep being accepted
+//! while it is down.
+//!
+//! An unannounced crash is detected instead: the primary heartbeats its
+//! backups, and a backup that has not heard from it for a while promotes
+//! itself. Backups wait longer the higher their id, so the lowest-numbered
+//! live backup takes over and announces itself before the others time out.
+//! Every change of primary starts a new epoch, and of two claims the one
+//! with the higher epoch wins. Epochs are dealt out to the nodes in turn,
+//! so two nodes never claim the same one, and each node publishes the
+//! epoch it knows as its `term`. A node that comes back, a former primary
+//! included, announces what it knows, and anyone who knows of a later
+//! epoch answers with the current primary. Until that answer arrives a
+//! former primary still takes writes, which the new primary's next update
+//! overwrites.
+//!
+//! A client `Put` is answered once a backup acknowledges the update that
+//! carries it, so that an answered write outlives the primary. Puts still
+//! waiting when the primary steps down are rejected.
 
-use crate::{Ctx, FaultEvent, Protocol};
+use crate::{
+    api::{decode_message, encode_message},
+    Ctx, FaultEvent, Protocol,
+};
 use ftsim_types::{
+    client::{ClientOp, ClientResponse},
     envelope::ProtoTag,
-    id::{NodeId, TimerId},
+    errors::CodecError,
+    id::{ClientRequestId, NodeId, TimerId},
+    scenario::FaultKind,
+    time::{sim_from_ms, SimTime},
 };
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
 const TAG: ProtoTag = ProtoTag(2);
 
+/// How often the primary heartbeats, and backups check on it, in
+/// milliseconds.
+const HEARTBEAT_PERIOD_MS: u64 = 20;
+
+/// How long the lowest-numbered backup goes without hearing from the
+/// primary before promoting itself, in milliseconds. Each further backup
+/// waits this much longer than the one before it.
+const FAILOVER_TIMEOUT_MS: u64 = 100;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Message {
     WriteRequest { key: String, value: String },
     Ack { key: String },
-    StateUpdate { state: IndexMap<String, String> },
+    /// The primary's whole state, after it applied `writes` writes.
+    StateUpdate { state: IndexMap<String, String>, writes: u64 },
+    /// A backup holds the state after the primary's first `writes` writes.
+    UpdateAck { writes: u64 },
+    /// The old primary names its successor, with the data it takes over.
+    Handoff { primary: NodeId, epoch: u64, state: IndexMap<String, String> },
+    /// Sent periodically by the primary of `epoch`.
+    Heartbeat { epoch: u64 },
+    /// Names the primary of `epoch`: sent by a newly promoted primary, by
+    /// a node that comes back, and in answer to a claim of an older epoch.
+    PrimaryAnnounce { primary: NodeId, epoch: u64 },
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct PrimaryBackup {
     id: NodeId,
     primary: NodeId,
     is_primary: bool,
     peers: Vec<NodeId>,
     data: IndexMap<String, String>,
+    /// Incremented by every change of primary.
+    epoch: u64,
+    /// When this node last heard from the primary, or started waiting on it.
+    last_heard: SimTime,
+    heartbeat_timer: Option<TimerId>,
+    /// How many writes this node has applied as primary. It never goes
+    /// back, so that a backup's acknowledgment cannot cover a later write.
+    writes: u64,
+    /// Client `Put`s awaiting a backup's acknowledgment, with the count of
+    /// writes that covers each.
+    pending_puts: Vec<(u64, ClientRequestId)>,
+}
+
+/// What a primary-backup node publishes with `Ctx::publish_state`.
+#[derive(Serialize)]
+struct PublishedState<'a> {
+    role: &'static str,
+    primary: NodeId,
+    epoch: u64,
+    data_entries: usize,
+    /// The most recently added key.
+    last_key: Option<&'a str>,
+    crashed: bool,
 }
 
 impl PrimaryBackup {
@@ -36,6 +106,129 @@ impl PrimaryBackup {
             ..Default::default()
         }
     }
+
+    fn role(&self) -> &'static str {
+        if self.is_primary {
+            "primary"
+        } else {
+            "backup"
+        }
+    }
+
+    /// Publishes the TUI-visible state, and the size of the data as a
+    /// metric for charting.
+    fn publish(&self, ctx: &mut Ctx<Message>, crashed: bool) {
+        ctx.publish_state(&PublishedState {
+            role: self.role(),
+            primary: self.primary,
+            epoch: self.epoch,
+            data_entries: self.data.len(),
+            last_key: self.data.last().map(|(key, _)| key.as_str()),
+            crashed,
+        });
+        ctx.log_metric("data_entries", self.data.len() as f64);
+    }
+
+    /// Applies a write on the primary and replicates the new state to the
+    /// backups.
+    fn write(&mut self, ctx: &mut Ctx<Message>, key: String, value: String) {
+        tracing::info!(node_id = self.id, key = %key, value = %value, "✍️  PRIMARY: Processing write request");
+        self.data.insert(key, value);
+        self.writes += 1;
+        self.publish(ctx, false);
+
+        // Replicate to backups
+        let update = Message::StateUpdate {
+            state: self.data.clone(),
+            writes: self.writes,
+        };
+        tracing::info!(node_id = self.id, peers = ?self.peers, "📡 PRIMARY: Replicating state to backups");
+        ctx.broadcast(&update, None).ok();
+    }
+
+    /// Makes `primary` the primary of `epoch`, updating the role this node
+    /// reports.
+    fn set_primary(&mut self, ctx: &mut Ctx<Message>, primary: NodeId, epoch: u64) {
+        self.primary = primary;
+        self.epoch = epoch;
+        self.is_primary = self.id == primary;
+        self.last_heard = ctx.now();
+        if !self.is_primary {
+            let reason = format!("not primary; node {} is", primary);
+            self.answer_puts(ctx, u64::MAX, ClientResponse::Rejected(reason));
+        }
+        ctx.log_kv_pinned("role", self.role());
+        ctx.log_kv_u64("term", epoch);
+        self.publish(ctx, false);
+    }
+
+    /// Answers the pending client `Put`s covered by the first `writes`
+    /// writes with `response`.
+    fn answer_puts(&mut self, ctx: &mut Ctx<Message>, writes: u64, response: ClientResponse) {
+        self.pending_puts.retain(|&(covered_by, request)| {
+            if covered_by > writes {
+                return true;
+            }
+            ctx.respond_to_client(request, response.clone());
+            false
+        });
+    }
+
+    /// Returns the first epoch after the one this node knows that belongs
+    /// to `claimant`. Of `n` nodes, epoch `e` belongs to node `e % n`.
+    fn epoch_for(&self, claimant: NodeId) -> u64 {
+        let n = self.peers.len() as u64 + 1;
+        let next = self.epoch + 1;
+        next + (u64::from(claimant) + n - next % n) % n
+    }
+
+    /// Weighs a claim that `primary` is the primary of `epoch`, adopting it
+    /// if it is newer than what this node knows. A claim of an older epoch
+    /// is answered with the current primary. Returns whether `primary` is
+    /// now the primary.
+    fn observe_claim(&mut self, ctx: &mut Ctx<Message>, src: NodeId, primary: NodeId, epoch: u64) -> bool {
+        if epoch < self.epoch {
+            let announce = Message::PrimaryAnnounce { primary: self.primary, epoch: self.epoch };
+            ctx.send(src, &announce).ok();
+            return false;
+        }
+        if epoch == self.epoch {
+            self.last_heard = ctx.now();
+            return true;
+        }
+        tracing::info!(node_id = self.id, primary, epoch, "👑 Learned of a new primary");
+        self.set_primary(ctx, primary, epoch);
+        true
+    }
+
+    /// Checks on the primary from a backup, promoting this node once the
+    /// primary has been silent for longer than this node's failover timeout.
+    fn check_primary(&mut self, ctx: &mut Ctx<Message>) {
+        // The backups ranked ahead of this one each get a timeout to take over
+        let rank = self.peers.iter().filter(|&&peer| peer < self.id && peer != self.primary).count() as u64;
+        let timeout = sim_from_ms(FAILOVER_TIMEOUT_MS * (rank + 1));
+        if ctx.now().saturating_sub(self.last_heard) <= timeout {
+            return;
+        }
+        let epoch = self.epoch_for(self.id);
+        tracing::warn!(node_id = self.id, old = self.primary, epoch, "👑 BACKUP: Primary silent, taking over");
+        self.set_primary(ctx, self.id, epoch);
+        let announce = Message::PrimaryAnnounce { primary: self.id, epoch: self.epoch };
+        ctx.broadcast(&announce, None).ok();
+    }
+
+    /// Drains the primary ahead of a crash at `at`: the lowest-numbered
+    /// backup takes over, with the current data.
+    fn hand_off(&mut self, ctx: &mut Ctx<Message>, at: SimTime) {
+        let Some(&successor) = self.peers.first() else {
+            return;
+        };
+        tracing::info!(node_id = self.id, successor, at, "🔀 PRIMARY: Handing off before scheduled crash");
+        let epoch = self.epoch_for(successor);
+        let handoff = Message::Handoff { primary: successor, epoch, state: self.data.clone() };
+        ctx.broadcast(&handoff, None).ok();
+        self.set_primary(ctx, successor, epoch);
+    }
 }
 
 impl Protocol<Message> for PrimaryBackup {
@@ -50,30 +243,26 @@ impl Protocol<Message> for PrimaryBackup {
     fn init(&mut self, ctx: &mut Ctx<Message>) {
         self.id = ctx.node_id();
         self.is_primary = self.id == self.primary;
-        // A real implementation would discover peers. Here we assume a fixed set.
-        self.peers = (0..3).filter(|&i| i != self.id).collect();
-        let role = if self.is_primary { "primary" } else { "backup" };
-        ctx.log_kv("role", role);
-        ctx.log_kv("data_entries", &self.data.len().to_string());
+        self.peers = ctx.peers();
+        let role = self.role();
+        ctx.log_kv_pinned("role", role);
+        ctx.log_kv_u64("term", self.epoch);
+        self.publish(ctx, false);
         tracing::info!(node_id = self.id, role = role, peers = ?self.peers, "🔧 Primary-backup node initialized");
     }
 
+    fn start(&mut self, ctx: &mut Ctx<Message>) {
+        // The primary gets a full timeout to be heard, also after a restart
+        self.last_heard = ctx.now();
+        self.heartbeat_timer = Some(ctx.set_periodic_timer(sim_from_ms(HEARTBEAT_PERIOD_MS)));
+    }
+
     fn on_message(&mut self, ctx: &mut Ctx<Message>, src: NodeId, msg: Message) {
         match msg {
             Message::WriteRequest { key, value } => {
                 if self.is_primary {
-                    tracing::info!(node_id = self.id, key = %key, value = %value, "✍️  PRIMARY: Processing write request");
-                    self.data.insert(key.clone(), value.clone());
-                    ctx.log_kv("data_entries", &self.data.len().to_string());
-                    ctx.log_kv("last_write_key", &key);
-                    
-                    // Replicate to backups
-                    let update = Message::StateUpdate {
-                        state: self.data.clone(),
-                    };
-                    tracing::info!(node_id = self.id, peers = ?self.peers, "📡 PRIMARY: Replicating state to backups");
-                    ctx.broadcast(&update, None).ok();
-                    
+                    self.write(ctx, key.clone(), value);
+
                     // Acknowledge the original sender
                     tracing::info!(node_id = self.id, src = src, key = %key, "✅ PRIMARY: Sending acknowledgment");
                     ctx.send(src, &Message::Ack { key }).ok();
@@ -81,16 +270,17 @@ impl Protocol<Message> for PrimaryBackup {
                     tracing::warn!(node_id = self.id, key = %key, "❌ BACKUP: Received write request, should go to primary");
                 }
             }
-            Message::StateUpdate { state } => {
-                if !self.is_primary {
+            Message::StateUpdate { state, writes } => {
+                if src != self.primary {
+                    tracing::warn!(node_id = self.id, src, primary = self.primary, "❌ Ignoring state update from a former primary");
+                } else if !self.is_primary {
+                    self.last_heard = ctx.now();
                     let old_size = self.data.len();
                     let new_size = state.len();
                     tracing::info!(node_id = self.id, old_entries = old_size, new_entries = new_size, "🔄 BACKUP: Received state update from primary");
                     self.data = state;
-                    ctx.log_kv("data_entries", &self.data.len().to_string());
-                    if let Some((last_key, _)) = self.data.last() {
-                        ctx.log_kv("last_key", last_key);
-                    }
+                    self.publish(ctx, false);
+                    ctx.send(src, &Message::UpdateAck { writes }).ok();
                 } else {
                     tracing::warn!(node_id = self.id, "❌ PRIMARY: Received state update, ignoring");
                 }
@@ -98,29 +288,109 @@ impl Protocol<Message> for PrimaryBackup {
             Message::Ack { key } => {
                 tracing::info!(node_id = self.id, src = src, key = %key, "✅ Received write acknowledgment");
             }
+            Message::UpdateAck { writes } if self.is_primary => {
+                self.answer_puts(ctx, writes, ClientResponse::Ok);
+            }
+            Message::UpdateAck { .. } => {}
+            Message::Handoff { primary, epoch, state } => {
+                tracing::info!(node_id = self.id, src, primary, epoch, "🔀 Primary handed off");
+                if self.observe_claim(ctx, src, primary, epoch) {
+                    self.data = state;
+                    self.publish(ctx, false);
+                }
+            }
+            Message::Heartbeat { epoch } => {
+                self.observe_claim(ctx, src, src, epoch);
+            }
+            Message::PrimaryAnnounce { primary, epoch } => {
+                self.observe_claim(ctx, src, primary, epoch);
+            }
         }
     }
 
-    fn on_timer(&mut self, _ctx: &mut Ctx<Message>, _timer: TimerId) {
-        // Not used in this simple protocol
+    fn on_timer(&mut self, ctx: &mut Ctx<Message>, timer: TimerId) {
+        if Some(timer) != self.heartbeat_timer {
+            return;
+        }
+        if self.is_primary {
+            ctx.broadcast(&Message::Heartbeat { epoch: self.epoch }, None).ok();
+        } else {
+            self.check_primary(ctx);
+        }
     }
 
     fn on_fault(&mut self, ctx: &mut Ctx<Message>, fault: FaultEvent) {
         match fault {
             FaultEvent::NodeCrashed => {
-                tracing::warn!(node_id = self.id, role = if self.is_primary { "primary" } else { "backup" }, "💥 Node crashed - entering recovery mode");
-                ctx.log_kv("fault", "crash_detected");
-                ctx.log_kv("status", "crashed");
+                tracing::warn!(node_id = self.id, role = self.role(), "💥 Node crashed - entering recovery mode");
+                // Pending timers are dropped on crash; a restart calls `start` again.
+                self.heartbeat_timer = None;
+                // So are pending client requests
+                self.pending_puts.clear();
+                self.publish(ctx, true);
             }
             FaultEvent::NodeRecovered => {
-                tracing::info!(node_id = self.id, role = if self.is_primary { "primary" } else { "backup" }, "🔄 Node recovered from crash");
-                ctx.log_kv("status", "recovered");
-                // Re-initialize state tracking
-                ctx.log_kv("data_entries", &self.data.len().to_string());
+                tracing::info!(node_id = self.id, role = self.role(), "🔄 Node recovered from crash");
+                self.publish(ctx, false);
+                // Whoever knows of a later primary answers, demoting a
+                // former primary
+                let announce = Message::PrimaryAnnounce { primary: self.primary, epoch: self.epoch };
+                ctx.broadcast(&announce, None).ok();
             }
+            FaultEvent::Scheduled { fault_kind: FaultKind::Crash, at } if self.is_primary => self.hand_off(ctx, at),
             _ => {
                 tracing::info!(node_id = self.id, ?fault, "⚠️  Other fault event received");
             }
         }
     }
+
+    fn on_client_request(&mut self, ctx: &mut Ctx<Message>, op: &ClientOp) -> Option<ClientResponse> {
+        match op {
+            ClientOp::Put { key, value } if self.is_primary => {
+                self.write(ctx, key.clone(), value.clone());
+                match ctx.client_request_id() {
+                    Some(request) if !self.peers.is_empty() => {
+                        self.pending_puts.push((self.writes, request));
+                        Some(ClientResponse::Pending)
+                    }
+                    _ => Some(ClientResponse::Ok),
+                }
+            }
+            ClientOp::Put { .. } => Some(ClientResponse::Rejected(format!("not primary; node {} is", self.primary))),
+            // Backups serve reads from their last replicated state
+            ClientOp::Get { key } => Some(ClientResponse::Value(self.data.get(key).cloned())),
+            ClientOp::Custom { .. } => None,
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<Vec<u8>> {
+        encode_message(self).ok()
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), CodecError> {
+        *self = decode_message(state)?;
+        Ok(())
+    }
+
+    fn sample_messages(&self) -> Vec<Message> {
+        vec![
+            Message::WriteRequest {
+                key: "key".into(),
+                value: "value".into(),
+            },
+            Message::Ack { key: "key".into() },
+            Message::StateUpdate {
+                state: IndexMap::from([("key".into(), "value".into())]),
+                writes: 1,
+            },
+            Message::UpdateAck { writes: 1 },
+            Message::Handoff {
+                primary: 1,
+                epoch: 1,
+                state: IndexMap::from([("key".into(), "value".into())]),
+            },
+            Message::Heartbeat { epoch: 1 },
+            Message::PrimaryAnnounce { primary: 1, epoch: 1 },
+        ]
+    }
 }
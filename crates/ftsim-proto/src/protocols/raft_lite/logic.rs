@@ -2,9 +2,17 @@
 //!
 //! Contains the business logic for handling Raft RPCs and timeouts.
 
-use super::{rpc::*, state::Role, Message, RaftLite};
+use super::{
+    persist,
+    rpc::*,
+    state::{LogEntry, Role},
+    Message, RaftLite, ELECTION_TIMEOUT_MS, MAX_ENTRIES_PER_APPEND,
+};
 use crate::Ctx;
-use ftsim_types::id::NodeId;
+use ftsim_types::{
+    id::NodeId,
+    time::{checked_sub, sim_from_ms},
+};
 
 pub fn handle_election_timeout(raft: &mut RaftLite, ctx: &mut Ctx<Message>) {
     if raft.state.role == Role::Leader {
@@ -17,12 +25,25 @@ pub fn handle_election_timeout(raft: &mut RaftLite, ctx: &mut Ctx<Message>) {
     raft.state.role = Role::Candidate;
     raft.state.current_term += 1;
     raft.state.voted_for = Some(raft.state.id);
+    raft.state.leader_id = None;
     raft.state.votes_received.clear();
     raft.state.votes_received.insert(raft.state.id);
 
     // Reset timer for the new election
     raft.reset_election_timer(ctx);
 
+    // A vote that is not durable could be cast again after a crash
+    if !persist::save_hard_state(ctx, &raft.state, raft.fsync_votes) {
+        tracing::warn!(term = raft.state.current_term, "Could not persist own vote; not campaigning");
+        return;
+    }
+
+    // A single-node cluster elects itself
+    if raft.state.votes_received.len() >= raft.state.quorum() {
+        become_leader(raft, ctx);
+        return;
+    }
+
     // Send RequestVote RPCs to all peers
     let args = RequestVote {
         term: raft.state.current_term,
@@ -45,12 +66,18 @@ pub fn handle_request_vote(
     }
 
     let mut vote_granted = false;
-    if args.term == raft.state.current_term {
-        if raft.state.voted_for.is_none() || raft.state.voted_for == Some(args.candidate_id) {
-            // Simplified log check: a real implementation would be more rigorous.
-            if args.last_log_term >= raft.state.last_log_term() {
-                vote_granted = true;
-                raft.state.voted_for = Some(args.candidate_id);
+    if args.term == raft.state.current_term
+        && (raft.state.voted_for.is_none() || raft.state.voted_for == Some(args.candidate_id))
+    {
+        // Only a candidate whose log is at least as up-to-date as ours may
+        // win, so that a leader holds every committed entry.
+        let up_to_date = (args.last_log_term, args.last_log_index)
+            >= (raft.state.last_log_term(), raft.state.last_log_index());
+        if up_to_date {
+            raft.state.voted_for = Some(args.candidate_id);
+            vote_granted = persist::save_hard_state(ctx, &raft.state, raft.fsync_votes);
+            if vote_granted {
+                raft.reset_election_timer(ctx);
             }
         }
     }
@@ -94,33 +121,169 @@ pub fn handle_append_entries(
         raft.become_follower(ctx, args.term);
     }
 
-    let mut success = false;
-    if args.term == raft.state.current_term {
-        success = true;
-        // This is where a follower would append entries to its log.
-        // Since this is a heartbeat, we just reset the timer.
-        raft.reset_election_timer(ctx);
-    }
-
-    let reply = AppendEntriesReply {
+    let reply = |raft: &RaftLite, success, match_index| AppendEntriesReply {
         term: raft.state.current_term,
         success,
+        match_index,
     };
+    if args.term < raft.state.current_term {
+        let reply = reply(raft, false, 0);
+        ctx.send(src, &Message::AppendEntriesReply(reply)).ok();
+        return;
+    }
+
+    // A candidate that hears from the leader of its term steps down
+    raft.state.role = Role::Follower;
+    raft.state.leader_id = Some(args.leader_id);
+    raft.reset_election_timer(ctx);
+
+    // The consistency check: our log must hold the entry just before the
+    // new ones. If not, the leader retries from further back.
+    if raft.state.entry_term(args.prev_log_index) != Some(args.prev_log_term) {
+        let retry_after = args.prev_log_index.saturating_sub(1).min(raft.state.last_log_index());
+        let reply = reply(raft, false, retry_after);
+        ctx.send(src, &Message::AppendEntriesReply(reply)).ok();
+        return;
+    }
+
+    // Skip the entries we already hold; the first that conflicts drops it
+    // and everything after it
+    let mut index = args.prev_log_index;
+    let mut entries = args.entries.into_iter().peekable();
+    while let Some(entry) = entries.peek() {
+        match raft.state.entry_term(index + 1) {
+            Some(term) if term == entry.term => {
+                index += 1;
+                entries.next();
+            }
+            Some(_) => {
+                tracing::info!(index = index + 1, "Truncating conflicting log suffix");
+                raft.state.truncate(index);
+                break;
+            }
+            None => break,
+        }
+    }
+    let new: Vec<LogEntry> = entries.collect();
+    if !new.is_empty() {
+        // Acknowledge only what is durable; the leader sends the rest again
+        let durable = persist::save_entries(ctx, index + 1, &new);
+        if durable < new.len() {
+            tracing::warn!(index = index + 1 + durable as u64, "Could not persist log entries");
+        }
+        if durable == 0 {
+            let reply = reply(raft, false, index);
+            ctx.send(src, &Message::AppendEntriesReply(reply)).ok();
+            return;
+        }
+        raft.state.truncate(index);
+        for entry in new.into_iter().take(durable) {
+            raft.state.append(entry);
+            index += 1;
+        }
+    }
+
+    // Only entries known to match the leader's log can be committed
+    if args.leader_commit > raft.state.commit_index {
+        raft.state.commit_index = args.leader_commit.min(index).max(raft.state.commit_index);
+        raft.state.apply_committed();
+    }
+
+    let reply = reply(raft, true, index);
     ctx.send(src, &Message::AppendEntriesReply(reply)).ok();
 }
 
 pub fn handle_append_entries_reply(
-    _raft: &mut RaftLite,
-    _ctx: &mut Ctx<Message>,
-    _src: NodeId,
-    _reply: AppendEntriesReply,
+    raft: &mut RaftLite,
+    ctx: &mut Ctx<Message>,
+    src: NodeId,
+    reply: AppendEntriesReply,
 ) {
-    // Logic to update next_index and match_index for the follower would go here.
+    if reply.term > raft.state.current_term {
+        raft.become_follower(ctx, reply.term);
+        return;
+    }
+    if raft.state.role != Role::Leader || reply.term != raft.state.current_term {
+        return;
+    }
+    raft.state.last_heard.insert(src, ctx.now());
+
+    if reply.success {
+        let matched = raft.state.match_index.entry(src).or_insert(0);
+        *matched = (*matched).max(reply.match_index);
+        let matched = *matched;
+        raft.state.next_index.insert(src, matched + 1);
+        advance_commit_index(raft);
+    } else {
+        let next = raft.state.next_index.get(&src).copied().unwrap_or(1);
+        if reply.match_index + 1 >= next {
+            // The follower holds everything before the entries but could not
+            // persist them; they go out again with the next AppendEntries
+            raft.state.next_index.insert(src, reply.match_index + 1);
+            return;
+        }
+        // Back off, at least one entry, and try again right away
+        let next = next.saturating_sub(1).min(reply.match_index + 1).max(1);
+        raft.state.next_index.insert(src, next);
+        send_append_entries(raft, ctx, src);
+    }
+}
+
+/// Appends `command` to the leader's log and replicates it. Returns false if
+/// the entry could not be persisted, in which case it is dropped.
+pub fn append_command(raft: &mut RaftLite, ctx: &mut Ctx<Message>, command: Vec<u8>) -> bool {
+    let entry = LogEntry { term: raft.state.current_term, command };
+    let index = raft.state.last_log_index() + 1;
+    if persist::save_entries(ctx, index, std::slice::from_ref(&entry)) == 0 {
+        tracing::warn!(index, "Could not persist a new log entry");
+        return false;
+    }
+    raft.state.append(entry);
+    advance_commit_index(raft);
+    send_heartbeats(raft, ctx);
+    true
+}
+
+/// Commits the highest entry of the current term a quorum holds, with
+/// everything before it. Entries of earlier terms are only committed this
+/// way, never by counting their replicas.
+fn advance_commit_index(raft: &mut RaftLite) {
+    let state = &mut raft.state;
+    for index in (state.commit_index + 1..=state.last_log_index()).rev() {
+        if state.entry_term(index) != Some(state.current_term) {
+            break;
+        }
+        let replicas = 1 + state.match_index.values().filter(|&&m| m >= index).count();
+        if replicas >= state.quorum() {
+            state.commit_index = index;
+            state.apply_committed();
+            break;
+        }
+    }
+}
+
+/// Steps a leader down if fewer than a quorum, itself included, answered it
+/// within the longest election timeout: a leader cut off from the majority
+/// stops serving once the majority may have elected another. Returns whether
+/// it is still the leader.
+pub fn check_quorum(raft: &mut RaftLite, ctx: &mut Ctx<Message>) -> bool {
+    let Ok(since) = checked_sub(ctx.now(), sim_from_ms(*ELECTION_TIMEOUT_MS.end())) else {
+        // No leader can have been cut off for a whole timeout yet
+        return true;
+    };
+    let heard = 1 + raft.state.last_heard.values().filter(|&&at| at >= since).count();
+    if heard >= raft.state.quorum() {
+        return true;
+    }
+    tracing::info!(term = raft.state.current_term, heard, "Lost contact with a quorum, stepping down");
+    raft.step_down(ctx);
+    false
 }
 
 fn become_leader(raft: &mut RaftLite, ctx: &mut Ctx<Message>) {
     tracing::info!(term = raft.state.current_term, "Elected as leader");
     raft.state.role = Role::Leader;
+    raft.state.leader_id = Some(raft.state.id);
 
     // Stop the election timer, leaders don't need it.
     if let Some(timer) = raft.election_timer.take() {
@@ -136,12 +299,40 @@ fn become_leader(raft: &mut RaftLite, ctx: &mut Ctx<Message>) {
         .map(|&id| (id, last_log_index + 1))
         .collect();
     raft.state.match_index = raft.state.peers.iter().map(|&id| (id, 0)).collect();
+    // Every peer gets an election timeout to answer before it counts as lost
+    let now = ctx.now();
+    raft.state.last_heard = raft.state.peers.iter().map(|&id| (id, now)).collect();
 
-    // Send initial empty AppendEntries (heartbeat) to all peers
+    // A no-op entry of the new term lets the entries of earlier terms
+    // commit along with it. Replicating it doubles as the first heartbeat;
+    // keep sending them until stepping down
+    if !append_command(raft, ctx, Vec::new()) {
+        send_heartbeats(raft, ctx);
+    }
+    raft.start_heartbeats(ctx);
+}
+
+/// Sends every peer an AppendEntries with the entries it lacks, if any, or
+/// an empty one as a heartbeat.
+pub fn send_heartbeats(raft: &mut RaftLite, ctx: &mut Ctx<Message>) {
+    for peer in raft.state.peers.clone() {
+        send_append_entries(raft, ctx, peer);
+    }
+}
+
+/// Sends `peer` the entries from its `next_index` on, at most
+/// `MAX_ENTRIES_PER_APPEND` of them.
+fn send_append_entries(raft: &mut RaftLite, ctx: &mut Ctx<Message>, peer: NodeId) {
+    let state = &raft.state;
+    let next = state.next_index.get(&peer).copied().unwrap_or(state.last_log_index() + 1).max(1);
+    let prev_log_index = next - 1;
     let args = AppendEntries {
-        term: raft.state.current_term,
-        leader_id: raft.state.id,
+        term: state.current_term,
+        leader_id: state.id,
+        prev_log_index,
+        prev_log_term: state.entry_term(prev_log_index).unwrap_or(0),
+        entries: state.entries_from(next, MAX_ENTRIES_PER_APPEND),
+        leader_commit: state.commit_index,
     };
-    ctx.broadcast(&Message::AppendEntries(args), None).ok();
-    // A real leader would have a heartbeat timer.
+    ctx.send(peer, &Message::AppendEntries(args)).ok();
 }
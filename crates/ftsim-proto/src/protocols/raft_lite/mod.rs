@@ -3,9 +3,26 @@
 //! A simplified implementation of the Raft consensus algorithm.
 //! It focuses on leader election and log replication to demonstrate a more
 //! complex protocol using the FTSim SDK.
+//!
+//! A client `Put` on the leader appends an entry to its log, which the
+//! leader replicates with AppendEntries and commits once a majority holds
+//! it; every node then applies the committed entries to its key-value state
+//! machine, which serves `Get`s. Followers reject `Put`s. A new leader
+//! appends an empty entry, so that the entries of earlier terms commit.
+//!
+//! A leader sends heartbeats every `HEARTBEAT_INTERVAL_MS` and steps down if
+//! fewer than a quorum answered it within an election timeout, so that a
+//! leader cut off by a partition stops taking writes.
+//!
+//! The term, vote and log are persisted with `ctx.store()`, so that a
+//! restarted node comes back with them; everything else is rebuilt. Each
+//! node publishes its `applied_index`, and its `last_log_index`,
+//! `last_log_term` and `log_hash`, which the engine's `log_matching`
+//! invariant compares across nodes.
 
-use super::super::{Ctx, FaultEvent, Protocol};
+use super::super::{api::decode_message, api::encode_message, Ctx, FaultEvent, Protocol};
 use ftsim_types::{
+    client::{ClientOp, ClientResponse},
     envelope::ProtoTag,
     id::{NodeId, TimerId},
     time::sim_from_ms,
@@ -13,14 +30,29 @@ use ftsim_types::{
 use serde::{Deserialize, Serialize};
 
 mod logic;
+mod persist;
 mod rpc;
 mod state;
 
 use rpc::{AppendEntries, AppendEntriesReply, RequestVote, RequestVoteReply};
-use state::{Role, State};
+use state::{Command, LogEntry, PublishedState, Role, State};
 
 const TAG: ProtoTag = ProtoTag(1);
 
+/// How often a leader sends heartbeats; well under the minimum election
+/// timeout.
+const HEARTBEAT_INTERVAL_MS: u64 = 50;
+
+/// The range election timeouts are drawn from, Raft's recommended 150-300ms.
+const ELECTION_TIMEOUT_MS: std::ops::RangeInclusive<u64> = 150..=300;
+
+/// The most entries one AppendEntries carries.
+const MAX_ENTRIES_PER_APPEND: usize = 64;
+
+/// The KV a node publishes the hash of its committed log under when the run
+/// ends, as 16 hex digits. Nodes that agree on the log publish equal hashes.
+pub const FINAL_LOG_HASH_KEY: &str = "final_log_hash";
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Message {
     RequestVote(RequestVote),
@@ -29,9 +61,30 @@ pub enum Message {
     AppendEntriesReply(AppendEntriesReply),
 }
 
+/// What a RaftLite timer is for, carried as its payload.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TimerKind {
+    Election,
+    Heartbeat,
+}
+
 pub struct RaftLite {
     state: State,
     election_timer: Option<TimerId>,
+    /// The periodic heartbeat timer, armed while leader.
+    heartbeat_timer: Option<TimerId>,
+    /// Whether the term and vote are fsynced once written.
+    fsync_votes: bool,
+    /// What `publish` last published, so that it publishes only changes.
+    published: Option<Published>,
+}
+
+/// The state a node published, with the end of its log.
+#[derive(PartialEq)]
+struct Published {
+    state: PublishedState,
+    last_log_term: u64,
+    log_hash: u64,
 }
 
 impl Default for RaftLite {
@@ -39,6 +92,9 @@ impl Default for RaftLite {
         Self {
             state: State::new(),
             election_timer: None,
+            heartbeat_timer: None,
+            fsync_votes: true,
+            published: None,
         }
     }
 }
@@ -53,13 +109,22 @@ impl Protocol<Message> for RaftLite {
     }
 
     fn init(&mut self, ctx: &mut Ctx<Message>) {
+        // Also called on restart: only what was persisted survives a crash
+        self.state = State::new();
+        self.election_timer = None;
+        self.heartbeat_timer = None;
         self.state.id = ctx.node_id();
-        // Assume 5 nodes for now. A real implementation would get this from config.
-        self.state.peers = (0..5).filter(|&i| i != self.state.id).collect();
-        self.state.role = Role::Follower;
+        self.state.peers = ctx.peers();
+        self.state.cluster_size = ctx.cluster_size();
+        persist::load(ctx, &mut self.state);
+        self.published = None;
+        self.publish(ctx);
+        // A node coming up has always published its role in lower case
+        ctx.log_kv_pinned("role", "follower");
+    }
+
+    fn start(&mut self, ctx: &mut Ctx<Message>) {
         self.reset_election_timer(ctx);
-        ctx.log_kv("role", "follower");
-        ctx.log_kv("term", &self.state.current_term.to_string());
     }
 
     fn on_message(&mut self, ctx: &mut Ctx<Message>, src: NodeId, msg: Message) {
@@ -73,15 +138,25 @@ impl Protocol<Message> for RaftLite {
                 logic::handle_append_entries_reply(self, ctx, src, reply)
             }
         }
-        // Update TUI-visible state
-        ctx.log_kv("term", &self.state.current_term.to_string());
-        ctx.log_kv("role", &self.state.role.to_string());
+        self.publish(ctx);
+    }
+
+    fn on_timer(&mut self, _ctx: &mut Ctx<Message>, _timer: TimerId) {
+        // Every RaftLite timer carries a `TimerKind` and fires through
+        // `on_timer_payload`.
     }
 
-    fn on_timer(&mut self, ctx: &mut Ctx<Message>, timer: TimerId) {
-        if self.election_timer == Some(timer) {
-            logic::handle_election_timeout(self, ctx);
+    fn on_timer_payload(&mut self, ctx: &mut Ctx<Message>, _timer: TimerId, payload: &[u8]) {
+        match decode_message::<TimerKind>(payload) {
+            Ok(TimerKind::Election) => logic::handle_election_timeout(self, ctx),
+            Ok(TimerKind::Heartbeat) => {
+                if self.state.role == Role::Leader && logic::check_quorum(self, ctx) {
+                    logic::send_heartbeats(self, ctx);
+                }
+            }
+            Err(e) => tracing::warn!("Undecodable timer payload: {}", e),
         }
+        self.publish(ctx);
     }
 
     fn on_fault(&mut self, _ctx: &mut Ctx<Message>, _fault: FaultEvent) {
@@ -89,25 +164,137 @@ impl Protocol<Message> for RaftLite {
         // handling is needed, but we could log the event.
         tracing::info!("Raft node received a fault notification.");
     }
+
+    fn on_client_request(&mut self, ctx: &mut Ctx<Message>, op: &ClientOp) -> Option<ClientResponse> {
+        let response = match op {
+            // The entry is appended; it shows in `applied_index` once committed
+            ClientOp::Put { key, value } if self.state.role == Role::Leader => {
+                let command = encode_message(&Command::Put { key: key.clone(), value: value.clone() }).ok()?;
+                if logic::append_command(self, ctx, command) {
+                    ClientResponse::Ok
+                } else {
+                    ClientResponse::Rejected("could not persist the entry".into())
+                }
+            }
+            ClientOp::Put { .. } => ClientResponse::Rejected(match self.state.leader_id {
+                Some(leader) => format!("not the leader; node {} is", leader),
+                None => "not the leader; no leader known".into(),
+            }),
+            // Reads are served from what this node applied, which may lag
+            ClientOp::Get { key } => ClientResponse::Value(self.state.data.get(key).cloned()),
+            ClientOp::Custom { .. } => return None,
+        };
+        self.publish(ctx);
+        Some(response)
+    }
+
+    fn on_shutdown(&mut self, ctx: &mut Ctx<Message>) {
+        ctx.log_kv(FINAL_LOG_HASH_KEY, &format!("{:016x}", self.state.committed_log_hash()));
+    }
+
+    fn sample_messages(&self) -> Vec<Message> {
+        vec![
+            Message::RequestVote(RequestVote {
+                term: 2,
+                candidate_id: 1,
+                last_log_index: 3,
+                last_log_term: 1,
+            }),
+            Message::RequestVoteReply(RequestVoteReply {
+                term: 2,
+                vote_granted: true,
+            }),
+            Message::AppendEntries(AppendEntries {
+                term: 2,
+                leader_id: 1,
+                prev_log_index: 3,
+                prev_log_term: 1,
+                entries: vec![LogEntry { term: 2, command: vec![] }],
+                leader_commit: 3,
+            }),
+            Message::AppendEntriesReply(AppendEntriesReply {
+                term: 2,
+                success: true,
+                match_index: 4,
+            }),
+        ]
+    }
 }
 
 impl RaftLite {
+    /// A RaftLite with a durability bug: it writes its term and vote to the
+    /// store but never fsyncs them, so a crash can take back a vote it
+    /// already cast. Only stores with `Durability::BufferedUntilFsync` lose
+    /// the write.
+    pub fn without_vote_fsync() -> Self {
+        Self { fsync_votes: false, ..Self::default() }
+    }
+
+    /// Publishes the TUI-visible state. The role is also published on its
+    /// own, for the leader checks that read the `role` KV, the end of the
+    /// log for the `log_matching` invariant, and the term and commit index
+    /// as metrics, for charting. Publishes nothing if none of it changed
+    /// since the last time.
+    fn publish(&mut self, ctx: &mut Ctx<Message>) {
+        let published = Published {
+            state: self.state.published(),
+            last_log_term: self.state.last_log_term(),
+            log_hash: self.state.log_hash_at(self.state.last_log_index()),
+        };
+        if self.published.as_ref() == Some(&published) {
+            return;
+        }
+        ctx.publish_state(&published.state);
+        ctx.log_kv_pinned("role", &self.state.role.to_string());
+        ctx.log_kv_u64("applied_index", self.state.last_applied);
+        ctx.log_kv_u64("last_log_index", published.state.log_len);
+        ctx.log_kv_u64("last_log_term", published.last_log_term);
+        ctx.log_kv("log_hash", &format!("{:016x}", published.log_hash));
+        ctx.log_metric("term", self.state.current_term as f64);
+        ctx.log_metric("commit_index", self.state.commit_index as f64);
+        self.published = Some(published);
+    }
+
     /// Resets the election timer to a new random duration.
     fn reset_election_timer(&mut self, ctx: &mut Ctx<Message>) {
-        if let Some(timer) = self.election_timer.take() {
+        // Use the deterministic RNG for election timeouts.
+        let timeout = sim_from_ms(ctx.rng_range(ELECTION_TIMEOUT_MS));
+        if let Some(timer) = self.election_timer {
+            // Push a still-pending timer out to the new deadline rather than
+            // replacing it; only a later deadline can be reached by extending.
+            if let Some(remaining) = ctx.timer_remaining(timer).filter(|r| *r <= timeout) {
+                ctx.extend_timer(timer, timeout - remaining);
+                return;
+            }
             ctx.cancel_timer(timer);
         }
-        // Use the deterministic RNG for election timeouts.
-        let timeout_ms = 150 + (ctx.rng_u64() % 151); // Raft's recommended 150-300ms
-        let timer = ctx.set_timer(sim_from_ms(timeout_ms));
-        self.election_timer = Some(timer);
+        self.election_timer = ctx.set_timer_with(timeout, &TimerKind::Election).ok();
     }
 
-    /// Converts the node to a follower state.
+    /// Converts the node to a follower state in a newer `term`.
     fn become_follower(&mut self, ctx: &mut Ctx<Message>, term: u64) {
         self.state.current_term = term;
-        self.state.role = Role::Follower;
         self.state.voted_for = None;
+        if !persist::save_hard_state(ctx, &self.state, self.fsync_votes) {
+            tracing::warn!(term, "Could not persist the new term");
+        }
+        self.step_down(ctx);
+    }
+
+    /// Becomes a follower in the current term, with no leader known.
+    fn step_down(&mut self, ctx: &mut Ctx<Message>) {
+        self.state.role = Role::Follower;
+        self.state.leader_id = None;
+        if let Some(timer) = self.heartbeat_timer.take() {
+            ctx.cancel_timer(timer);
+        }
         self.reset_election_timer(ctx);
     }
+
+    /// Starts sending heartbeats every `HEARTBEAT_INTERVAL_MS` until the
+    /// node steps down.
+    fn start_heartbeats(&mut self, ctx: &mut Ctx<Message>) {
+        let period = sim_from_ms(HEARTBEAT_INTERVAL_MS);
+        self.heartbeat_timer = ctx.set_periodic_timer_with(period, &TimerKind::Heartbeat).ok();
+    }
 }
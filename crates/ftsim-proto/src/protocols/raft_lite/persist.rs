@@ -0,0 +1,96 @@
+//! # ftsim-proto::protocols::raft_lite::persist
+//!
+//! Keeps Raft's persistent state in the node's store, so that it survives a
+//! crash. The current term and vote live under one KV key. Log entries are
+//! appended to the store's log tagged with their Raft index. Truncating a
+//! conflicting suffix writes nothing of its own: the entry that replaces it
+//! is stored with the same index, and replay drops everything after an
+//! index that is written again. Every write is fsynced before the node acts
+//! on it, unless the node was built `without_vote_fsync`.
+
+use super::{
+    state::{LogEntry, State},
+    Message,
+};
+use crate::{
+    api::{decode_message, encode_message, LogRecord},
+    Ctx,
+};
+use ftsim_types::id::NodeId;
+use serde::{Deserialize, Serialize};
+
+/// The store KV key of the current term and vote.
+const HARD_STATE_KEY: &[u8] = b"raft/hard_state";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HardState {
+    term: u64,
+    voted_for: Option<NodeId>,
+}
+
+/// A log entry as stored, with its index in the Raft log.
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredEntry {
+    index: u64,
+    entry: LogEntry,
+}
+
+/// Persists the current term and vote, fsyncing them unless `fsync` is
+/// false. Returns whether they were written.
+pub fn save_hard_state(ctx: &mut Ctx<Message>, state: &State, fsync: bool) -> bool {
+    let hard_state = HardState { term: state.current_term, voted_for: state.voted_for };
+    let Ok(bytes) = encode_message(&hard_state) else {
+        return false;
+    };
+    let mut store = ctx.store();
+    store.kv_put(bytes::Bytes::from_static(HARD_STATE_KEY), bytes.into()).is_ok() && (!fsync || store.fsync().is_ok())
+}
+
+/// Persists `entries` as the log from `first_index` on, up to the first one
+/// that cannot be written. Returns how many of them are durable.
+pub fn save_entries(ctx: &mut Ctx<Message>, first_index: u64, entries: &[LogEntry]) -> usize {
+    let mut store = ctx.store();
+    let mut written = 0;
+    for (index, entry) in (first_index..).zip(entries) {
+        let Ok(bytes) = encode_message(&StoredEntry { index, entry: entry.clone() }) else {
+            break;
+        };
+        if store.append_log(LogRecord::new(entry.term, bytes.into())).is_err() {
+            break;
+        }
+        written += 1;
+    }
+    if written == 0 || store.fsync().is_err() {
+        return 0;
+    }
+    written
+}
+
+/// Restores the term, vote and log from the store into `state`. Damaged
+/// records are skipped.
+pub fn load(ctx: &mut Ctx<Message>, state: &mut State) {
+    let mut store = ctx.store();
+    if let Ok(Some(bytes)) = store.kv_get(HARD_STATE_KEY) {
+        if let Ok(hard_state) = decode_message::<HardState>(&bytes) {
+            state.current_term = hard_state.term;
+            state.voted_for = hard_state.voted_for;
+        }
+    }
+    for idx in 0.. {
+        match store.read_log(idx) {
+            Ok(Some(rec)) if rec.verify() => {
+                let Ok(stored) = decode_message::<StoredEntry>(&rec.data) else {
+                    continue;
+                };
+                // An entry can only follow the ones before it
+                if stored.index > state.last_log_index() + 1 {
+                    continue;
+                }
+                state.truncate(stored.index - 1);
+                state.append(stored.entry);
+            }
+            Ok(Some(_)) | Err(_) => {}
+            Ok(None) => break,
+        }
+    }
+}
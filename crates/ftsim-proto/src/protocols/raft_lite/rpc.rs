@@ -3,6 +3,7 @@
 //! Defines the structs for Raft's Remote Procedure Calls (RPCs), which are
 //! serialized as messages.
 
+use super::state::LogEntry;
 use ftsim_types::id::NodeId;
 use serde::{Deserialize, Serialize};
 
@@ -24,12 +25,20 @@ pub struct RequestVoteReply {
 pub struct AppendEntries {
     pub term: u64,
     pub leader_id: NodeId,
-    // In a real implementation, this would contain log entries.
-    // Simplified for this example.
+    /// The index and term of the entry just before `entries`, which the
+    /// follower must hold for them to apply.
+    pub prev_log_index: u64,
+    pub prev_log_term: u64,
+    /// Empty for a heartbeat.
+    pub entries: Vec<LogEntry>,
+    pub leader_commit: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AppendEntriesReply {
     pub term: u64,
     pub success: bool,
+    /// On success, the index of the last entry the follower now shares with
+    /// the leader. On failure, the index the leader should retry after.
+    pub match_index: u64,
 }
@@ -2,10 +2,15 @@
 //!
 //! Defines the core state machine for the RaftLite protocol.
 
-use ftsim_types::id::NodeId;
+use crate::api::decode_message;
+use ftsim_types::{id::NodeId, time::SimTime};
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashSet};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum Role {
     Follower,
     Candidate,
@@ -18,54 +23,122 @@ impl std::fmt::Display for Role {
     }
 }
 
-/// Represents a single entry in the Raft log.
-#[derive(Clone, Debug)]
+/// What a RaftLite node publishes with `Ctx::publish_state`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PublishedState {
+    pub role: Role,
+    pub term: u64,
+    pub voted_for: Option<NodeId>,
+    pub log_len: u64,
+    pub commit_index: u64,
+    pub last_applied: u64,
+    /// How much of its log a leader knows each follower holds; empty on
+    /// other nodes.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub match_index: BTreeMap<NodeId, u64>,
+}
+
+/// Represents a single entry in the Raft log. A leader appends an entry
+/// with an empty command when elected, so that it can commit the entries of
+/// earlier terms.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LogEntry {
     pub term: u64,
     pub command: Vec<u8>,
 }
 
+/// What a non-empty log entry's command does to the state machine.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Command {
+    Put { key: String, value: String },
+}
+
 /// The persistent and volatile state for a Raft node.
 pub struct State {
     // --- Persistent state on all servers ---
     pub id: NodeId,
     pub peers: Vec<NodeId>,
+    /// Every node in the cluster, this one included, reachable or not.
+    pub cluster_size: usize,
     pub current_term: u64,
     pub voted_for: Option<NodeId>,
-    pub log: Vec<LogEntry>,
+    /// Private so that `log_hashes` stays in step; see `append` and
+    /// `truncate`.
+    log: Vec<LogEntry>,
 
     // --- Volatile state on all servers ---
     pub role: Role,
+    /// The leader of the current term, once heard from.
+    pub leader_id: Option<NodeId>,
     pub commit_index: u64,
     pub last_applied: u64,
+    /// The state machine: every committed `Put`, applied in log order.
+    pub data: BTreeMap<String, String>,
+    /// `log_hashes[i]` hashes the log up to and including entry `i + 1`.
+    log_hashes: Vec<u64>,
 
     // --- Volatile state on leaders ---
     pub next_index: BTreeMap<NodeId, u64>,
     pub match_index: BTreeMap<NodeId, u64>,
+    /// When each peer last answered an AppendEntries of the current term.
+    pub last_heard: BTreeMap<NodeId, SimTime>,
 
     // --- Volatile state on candidates ---
     pub votes_received: HashSet<NodeId>,
 }
 
 impl State {
+    /// FNV-1a over the term and command of every committed entry, so that
+    /// nodes can compare their committed logs without shipping them.
+    pub fn committed_log_hash(&self) -> u64 {
+        self.log_hash_at(self.commit_index)
+    }
+
+    /// FNV-1a over the term and command of every entry up to `index`.
+    pub fn log_hash_at(&self, index: u64) -> u64 {
+        let len = (index as usize).min(self.log.len());
+        len.checked_sub(1).map_or(FNV_OFFSET, |i| self.log_hashes[i])
+    }
+
     pub fn new() -> Self {
         Self {
             id: 0,
             peers: Vec::new(),
+            cluster_size: 1,
             current_term: 0,
             voted_for: None,
             log: vec![],
             role: Role::Follower,
+            leader_id: None,
             commit_index: 0,
             last_applied: 0,
+            data: BTreeMap::new(),
+            log_hashes: vec![],
             next_index: BTreeMap::new(),
             match_index: BTreeMap::new(),
+            last_heard: BTreeMap::new(),
             votes_received: HashSet::new(),
         }
     }
 
+    pub fn published(&self) -> PublishedState {
+        PublishedState {
+            role: self.role,
+            term: self.current_term,
+            voted_for: self.voted_for,
+            log_len: self.last_log_index(),
+            commit_index: self.commit_index,
+            last_applied: self.last_applied,
+            match_index: match self.role {
+                Role::Leader => self.match_index.clone(),
+                _ => BTreeMap::new(),
+            },
+        }
+    }
+
+    /// A majority of the whole cluster, not just of the peers linked to.
     pub fn quorum(&self) -> usize {
-        (self.peers.len() + 1) / 2 + 1
+        self.cluster_size / 2 + 1
     }
 
     pub fn last_log_index(&self) -> u64 {
@@ -75,4 +148,59 @@ impl State {
     pub fn last_log_term(&self) -> u64 {
         self.log.last().map_or(0, |entry| entry.term)
     }
+
+    /// The entry at `index`, counting from 1.
+    pub fn entry(&self, index: u64) -> Option<&LogEntry> {
+        index.checked_sub(1).and_then(|i| self.log.get(i as usize))
+    }
+
+    /// The term of the entry at `index`, or 0 for index 0.
+    pub fn entry_term(&self, index: u64) -> Option<u64> {
+        if index == 0 {
+            return Some(0);
+        }
+        self.entry(index).map(|entry| entry.term)
+    }
+
+    /// The entries from `index` on, at most `max` of them.
+    pub fn entries_from(&self, index: u64, max: usize) -> Vec<LogEntry> {
+        let start = (index.max(1) as usize - 1).min(self.log.len());
+        self.log[start..].iter().take(max).cloned().collect()
+    }
+
+    pub fn append(&mut self, entry: LogEntry) {
+        let hash = entry
+            .term
+            .to_le_bytes()
+            .into_iter()
+            .chain(entry.command.iter().copied())
+            .fold(self.log_hash_at(self.last_log_index()), |hash, b| (hash ^ b as u64).wrapping_mul(FNV_PRIME));
+        self.log.push(entry);
+        self.log_hashes.push(hash);
+    }
+
+    /// Drops every entry after `index`.
+    pub fn truncate(&mut self, index: u64) {
+        self.log.truncate(index as usize);
+        self.log_hashes.truncate(index as usize);
+    }
+
+    /// Applies the committed entries not applied yet, in order.
+    pub fn apply_committed(&mut self) {
+        while self.last_applied < self.commit_index {
+            self.last_applied += 1;
+            let Some(entry) = self.entry(self.last_applied) else {
+                break;
+            };
+            if entry.command.is_empty() {
+                continue;
+            }
+            match decode_message(&entry.command) {
+                Ok(Command::Put { key, value }) => {
+                    self.data.insert(key, value);
+                }
+                Err(e) => tracing::warn!(index = self.last_applied, "Undecodable Raft command: {}", e),
+            }
+        }
+    }
 }
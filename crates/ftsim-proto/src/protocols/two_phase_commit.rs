@@ -0,0 +1,494 @@
+//! # ftsim-proto::protocols::two_phase_commit
+//!
+//! An example of a blocking commit protocol. Node 0 coordinates; every
+//! other node is a participant. A client `Put` on the coordinator starts a
+//! transaction writing the key on every participant:
+//!
+//! 1. The coordinator sends `Prepare` to the participants.
+//! 2. A participant that can take the key's lock persists the prepared
+//!    write and votes yes; one whose key is locked by another transaction
+//!    votes no.
+//! 3. With every vote yes, the coordinator persists a commit decision;
+//!    with a no vote, or without all votes within `PREPARE_TIMEOUT_MS`,
+//!    an abort decision. It then tells the participants one at a time,
+//!    `DECISION_SPACING_MS` apart, and they acknowledge.
+//!
+//! A participant that voted yes cannot decide on its own: until the
+//! coordinator's decision reaches it, the transaction is in doubt and its
+//! key stays locked. If the coordinator crashes after telling only some
+//! participants to commit, the participants disagree on the key until the
+//! coordinator recovers, re-reads its decisions from the store and sends
+//! them again. Transactions it began but never decided are aborted on
+//! recovery.
+//!
+//! A node that fails to persist a record acts as if it had not written it:
+//! the coordinator rejects the `Put` or aborts instead of committing, and a
+//! participant votes no, or leaves the decision unacknowledged until it can
+//! persist it.
+//!
+//! A crash loses everything a node has not persisted with `ctx.store()`.
+//! Each node publishes its `role`, the `committed`, `aborted` and
+//! `in_doubt` transaction counts and its `data_entries` as metrics.
+
+use crate::{
+    api::{decode_message, encode_message, LogRecord},
+    Ctx, FaultEvent, Protocol,
+};
+use ftsim_types::{
+    client::{ClientOp, ClientResponse},
+    envelope::ProtoTag,
+    errors::StoreError,
+    id::{NodeId, TimerId},
+    time::sim_from_ms,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+const TAG: ProtoTag = ProtoTag(5);
+
+/// The coordinating node.
+pub const COORDINATOR: NodeId = 0;
+
+/// How long the coordinator waits for votes before aborting, in
+/// milliseconds.
+pub const PREPARE_TIMEOUT_MS: u64 = 100;
+
+/// The gap between two decisions the coordinator sends, in milliseconds.
+pub const DECISION_SPACING_MS: u64 = 25;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Message {
+    Prepare { txn: u64, key: String, value: String },
+    /// A participant's vote. A participant recovering with a transaction in
+    /// doubt sends its yes vote again to ask for the decision.
+    Vote { txn: u64, yes: bool },
+    Commit { txn: u64 },
+    Abort { txn: u64 },
+    Ack { txn: u64 },
+}
+
+/// What a node persists, as log records.
+#[derive(Debug, Serialize, Deserialize)]
+enum Record {
+    /// The coordinator started a transaction.
+    Begun { txn: u64, key: String },
+    /// A participant voted yes, and holds the key's lock until the decision.
+    Prepared { txn: u64, key: String, value: String },
+    /// The outcome of a transaction. The coordinator persists it before
+    /// telling anyone.
+    Decided { txn: u64, commit: bool },
+    /// Every participant acknowledged the coordinator's decision.
+    Done { txn: u64 },
+}
+
+/// A transaction, as the coordinator tracks it.
+#[derive(Debug, Default)]
+struct Txn {
+    key: String,
+    yes_votes: BTreeSet<NodeId>,
+    decision: Option<bool>,
+    acks: BTreeSet<NodeId>,
+}
+
+#[derive(Default)]
+pub struct TwoPhaseCommit {
+    id: NodeId,
+    participants: Vec<NodeId>,
+    next_txn: u64,
+    /// The coordinator's transactions that are not done.
+    txns: BTreeMap<u64, Txn>,
+    /// Vote timeouts, by timer.
+    timeouts: BTreeMap<TimerId, u64>,
+    /// Decisions waiting to be sent, in order.
+    outbox: VecDeque<(NodeId, Message)>,
+    outbox_timer: Option<TimerId>,
+    /// A participant's prepared writes, by transaction.
+    prepared: BTreeMap<u64, (String, String)>,
+    /// The outcome of every transaction a participant learned about.
+    outcomes: BTreeMap<u64, bool>,
+    data: BTreeMap<String, String>,
+    committed: u64,
+    aborted: u64,
+}
+
+/// What a two-phase commit node publishes with `Ctx::publish_state`.
+#[derive(Serialize)]
+struct PublishedState {
+    role: &'static str,
+    committed: u64,
+    aborted: u64,
+    in_doubt: usize,
+    data_entries: usize,
+}
+
+impl TwoPhaseCommit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn is_coordinator(&self) -> bool {
+        self.id == COORDINATOR
+    }
+
+    fn role(&self) -> &'static str {
+        if self.is_coordinator() {
+            "coordinator"
+        } else {
+            "participant"
+        }
+    }
+
+    /// The transactions waiting on a decision: undecided on the
+    /// coordinator, prepared on a participant.
+    fn in_doubt(&self) -> usize {
+        if self.is_coordinator() {
+            self.txns.values().filter(|t| t.decision.is_none()).count()
+        } else {
+            self.prepared.len()
+        }
+    }
+
+    fn publish(&self, ctx: &mut Ctx<Message>) {
+        ctx.publish_state(&PublishedState {
+            role: self.role(),
+            committed: self.committed,
+            aborted: self.aborted,
+            in_doubt: self.in_doubt(),
+            data_entries: self.data.len(),
+        });
+        ctx.log_metric("committed", self.committed as f64);
+        ctx.log_metric("aborted", self.aborted as f64);
+        ctx.log_metric("in_doubt", self.in_doubt() as f64);
+        ctx.log_metric("data_entries", self.data.len() as f64);
+    }
+
+    /// Appends a record to the node's log and syncs it, so that it survives
+    /// a crash. On error the record may or may not survive one.
+    fn persist(ctx: &mut Ctx<Message>, record: &Record) -> Result<(), StoreError> {
+        let bytes = encode_message(record).map_err(|err| StoreError::Io(err.to_string()))?;
+        let mut store = ctx.store();
+        store.append_log(LogRecord::new(0, bytes.into()))?;
+        store.fsync()
+    }
+
+    /// Reads back every intact record in the node's log, oldest first.
+    fn replay(ctx: &mut Ctx<Message>) -> Vec<Record> {
+        let mut store = ctx.store();
+        let mut records = Vec::new();
+        for idx in 0.. {
+            match store.read_log(idx) {
+                Ok(Some(rec)) if rec.verify() => records.extend(decode_message(&rec.data).ok()),
+                Ok(Some(_)) | Err(_) => {}
+                Ok(None) => break,
+            }
+        }
+        records
+    }
+
+    /// Starts a transaction writing `key` on every participant.
+    fn begin(&mut self, ctx: &mut Ctx<Message>, key: String, value: String) -> Result<(), StoreError> {
+        let txn = self.next_txn;
+        self.next_txn += 1;
+        Self::persist(ctx, &Record::Begun { txn, key: key.clone() })?;
+        tracing::info!(node_id = self.id, txn, key = %key, "2PC: preparing");
+        let prepare = Message::Prepare { txn, key: key.clone(), value };
+        for &participant in &self.participants {
+            ctx.send(participant, &prepare).ok();
+        }
+        self.txns.insert(txn, Txn { key, ..Txn::default() });
+        let timeout = ctx.set_timer(sim_from_ms(PREPARE_TIMEOUT_MS));
+        self.timeouts.insert(timeout, txn);
+        self.publish(ctx);
+        Ok(())
+    }
+
+    /// Persists the coordinator's decision on `txn` and queues it for every
+    /// participant. A commit that fails to persist becomes an abort, which
+    /// recovery presumes anyway if its record is lost too.
+    fn decide(&mut self, ctx: &mut Ctx<Message>, txn: u64, mut commit: bool) {
+        if !self.txns.get(&txn).is_some_and(|state| state.decision.is_none()) {
+            return;
+        }
+        if let Err(err) = Self::persist(ctx, &Record::Decided { txn, commit }) {
+            tracing::warn!(node_id = self.id, txn, commit, %err, "2PC: failed to persist the decision");
+            if commit {
+                commit = false;
+                // Supersedes the commit record on replay if that one survives
+                Self::persist(ctx, &Record::Decided { txn, commit }).ok();
+            }
+        }
+        let Some(state) = self.txns.get_mut(&txn) else {
+            return;
+        };
+        state.decision = Some(commit);
+        tracing::info!(node_id = self.id, txn, key = %state.key, commit, "2PC: decided");
+        self.count_outcome(commit);
+        self.queue_decision(ctx, txn, commit);
+        self.publish(ctx);
+    }
+
+    fn count_outcome(&mut self, commit: bool) {
+        if commit {
+            self.committed += 1;
+        } else {
+            self.aborted += 1;
+        }
+    }
+
+    fn decision_message(txn: u64, commit: bool) -> Message {
+        if commit {
+            Message::Commit { txn }
+        } else {
+            Message::Abort { txn }
+        }
+    }
+
+    fn queue_decision(&mut self, ctx: &mut Ctx<Message>, txn: u64, commit: bool) {
+        for &participant in &self.participants {
+            self.outbox.push_back((participant, Self::decision_message(txn, commit)));
+        }
+        if self.outbox_timer.is_none() {
+            self.send_next_decision(ctx);
+        }
+    }
+
+    /// Sends the oldest queued decision, and schedules the next one.
+    fn send_next_decision(&mut self, ctx: &mut Ctx<Message>) {
+        self.outbox_timer = None;
+        if let Some((participant, msg)) = self.outbox.pop_front() {
+            ctx.send(participant, &msg).ok();
+        }
+        if !self.outbox.is_empty() {
+            self.outbox_timer = Some(ctx.set_timer(sim_from_ms(DECISION_SPACING_MS)));
+        }
+    }
+
+    fn on_vote(&mut self, ctx: &mut Ctx<Message>, src: NodeId, txn: u64, yes: bool) {
+        let Some(state) = self.txns.get_mut(&txn) else {
+            // Done, or begun before a crash that lost its record: either way
+            // the participant gets the decision the coordinator would have
+            // presumed
+            let commit = self.outcomes.get(&txn).copied().unwrap_or(false);
+            ctx.send(src, &Self::decision_message(txn, commit)).ok();
+            return;
+        };
+        if let Some(commit) = state.decision {
+            // A recovering participant asking for the decision
+            ctx.send(src, &Self::decision_message(txn, commit)).ok();
+        } else if !yes {
+            self.decide(ctx, txn, false);
+        } else {
+            state.yes_votes.insert(src);
+            if state.yes_votes.len() == self.participants.len() {
+                self.decide(ctx, txn, true);
+            }
+        }
+    }
+
+    fn on_ack(&mut self, ctx: &mut Ctx<Message>, src: NodeId, txn: u64) {
+        let Some(state) = self.txns.get_mut(&txn) else {
+            return;
+        };
+        state.acks.insert(src);
+        if state.acks.len() == self.participants.len() {
+            let commit = state.decision.unwrap_or(false);
+            self.txns.remove(&txn);
+            self.outcomes.insert(txn, commit);
+            // Without the record, recovery sends the decision again and the
+            // participants acknowledge it again
+            if let Err(err) = Self::persist(ctx, &Record::Done { txn }) {
+                tracing::warn!(node_id = self.id, txn, %err, "2PC: failed to persist a finished transaction");
+            }
+        }
+    }
+
+    fn on_prepare(&mut self, ctx: &mut Ctx<Message>, src: NodeId, txn: u64, key: String, value: String) {
+        if self.outcomes.contains_key(&txn) {
+            return;
+        }
+        let locked = self.prepared.iter().any(|(&other, (k, _))| other != txn && *k == key);
+        let yes = !locked && (self.prepared.contains_key(&txn) || self.prepare(ctx, txn, key, value));
+        ctx.send(src, &Message::Vote { txn, yes }).ok();
+    }
+
+    /// Persists a participant's prepared write and takes the key's lock.
+    /// Returns whether it was persisted, and so whether to vote yes.
+    fn prepare(&mut self, ctx: &mut Ctx<Message>, txn: u64, key: String, value: String) -> bool {
+        if let Err(err) = Self::persist(ctx, &Record::Prepared { txn, key: key.clone(), value: value.clone() }) {
+            tracing::warn!(node_id = self.id, txn, %err, "2PC: failed to persist the prepared write");
+            return false;
+        }
+        self.prepared.insert(txn, (key, value));
+        self.publish(ctx);
+        true
+    }
+
+    /// Applies the coordinator's decision on `txn` on a participant. The
+    /// coordinator forgets a decision once acknowledged, so one that fails
+    /// to persist is neither applied nor acknowledged: the transaction stays
+    /// in doubt until the participant asks again after a restart.
+    fn on_decision(&mut self, ctx: &mut Ctx<Message>, src: NodeId, txn: u64, commit: bool) {
+        if !self.outcomes.contains_key(&txn) {
+            if let Err(err) = Self::persist(ctx, &Record::Decided { txn, commit }) {
+                tracing::warn!(node_id = self.id, txn, commit, %err, "2PC: failed to persist the decision");
+                return;
+            }
+            self.apply(txn, commit);
+            tracing::info!(node_id = self.id, txn, commit, "2PC: applied decision");
+            self.publish(ctx);
+        }
+        ctx.send(src, &Message::Ack { txn }).ok();
+    }
+
+    /// Applies a decision to the participant's state.
+    fn apply(&mut self, txn: u64, commit: bool) {
+        let prepared = self.prepared.remove(&txn);
+        if let (true, Some((key, value))) = (commit, prepared) {
+            self.data.insert(key, value);
+        }
+        self.outcomes.insert(txn, commit);
+        self.count_outcome(commit);
+    }
+
+    /// Rebuilds the coordinator's state from its log, aborts what it never
+    /// decided and sends every unacknowledged decision again.
+    fn recover_coordinator(&mut self, ctx: &mut Ctx<Message>) {
+        // A later decision on a transaction supersedes an earlier one
+        let mut decisions = BTreeMap::new();
+        for record in Self::replay(ctx) {
+            match record {
+                Record::Begun { txn, key } => {
+                    self.next_txn = self.next_txn.max(txn + 1);
+                    self.txns.insert(txn, Txn { key, ..Txn::default() });
+                }
+                Record::Decided { txn, commit } => {
+                    if let Some(state) = self.txns.get_mut(&txn) {
+                        state.decision = Some(commit);
+                    }
+                    decisions.insert(txn, commit);
+                }
+                Record::Done { txn } => {
+                    if let Some(state) = self.txns.remove(&txn) {
+                        self.outcomes.insert(txn, state.decision.unwrap_or(false));
+                    }
+                }
+                Record::Prepared { .. } => {}
+            }
+        }
+        for commit in decisions.into_values() {
+            self.count_outcome(commit);
+        }
+        let pending: Vec<(u64, Option<bool>)> = self.txns.iter().map(|(&txn, t)| (txn, t.decision)).collect();
+        for (txn, decision) in pending {
+            match decision {
+                Some(commit) => self.queue_decision(ctx, txn, commit),
+                None => self.decide(ctx, txn, false),
+            }
+        }
+        tracing::info!(node_id = self.id, pending = self.txns.len(), "2PC: coordinator recovered");
+    }
+
+    /// Rebuilds a participant's state from its log and asks the coordinator
+    /// about every transaction still in doubt.
+    fn recover_participant(&mut self, ctx: &mut Ctx<Message>) {
+        for record in Self::replay(ctx) {
+            match record {
+                Record::Prepared { txn, key, value } => {
+                    self.prepared.insert(txn, (key, value));
+                }
+                // A decision whose fsync failed may survive next to the one
+                // persisted when it was sent again
+                Record::Decided { txn, commit } if !self.outcomes.contains_key(&txn) => self.apply(txn, commit),
+                Record::Decided { .. } | Record::Begun { .. } | Record::Done { .. } => {}
+            }
+        }
+        for &txn in self.prepared.keys() {
+            ctx.send(COORDINATOR, &Message::Vote { txn, yes: true }).ok();
+        }
+        tracing::info!(node_id = self.id, in_doubt = self.prepared.len(), "2PC: participant recovered");
+    }
+}
+
+impl Protocol<Message> for TwoPhaseCommit {
+    fn name(&self) -> &'static str {
+        "two_phase_commit"
+    }
+
+    fn proto_tag(&self) -> ProtoTag {
+        TAG
+    }
+
+    fn init(&mut self, ctx: &mut Ctx<Message>) {
+        self.id = ctx.node_id();
+        self.participants = ctx.peers().into_iter().filter(|&peer| peer != COORDINATOR).collect();
+        ctx.log_kv_pinned("role", self.role());
+        self.publish(ctx);
+    }
+
+    fn on_message(&mut self, ctx: &mut Ctx<Message>, src: NodeId, msg: Message) {
+        match msg {
+            Message::Prepare { txn, key, value } => self.on_prepare(ctx, src, txn, key, value),
+            Message::Vote { txn, yes } if self.is_coordinator() => self.on_vote(ctx, src, txn, yes),
+            Message::Commit { txn } => self.on_decision(ctx, src, txn, true),
+            Message::Abort { txn } => self.on_decision(ctx, src, txn, false),
+            Message::Ack { txn } if self.is_coordinator() => self.on_ack(ctx, src, txn),
+            msg => tracing::warn!(node_id = self.id, ?msg, "2PC: unexpected message for a participant"),
+        }
+    }
+
+    fn on_timer(&mut self, ctx: &mut Ctx<Message>, timer: TimerId) {
+        if Some(timer) == self.outbox_timer {
+            self.send_next_decision(ctx);
+        } else if let Some(txn) = self.timeouts.remove(&timer) {
+            if self.txns.get(&txn).is_some_and(|t| t.decision.is_none()) {
+                tracing::warn!(node_id = self.id, txn, "2PC: votes timed out");
+                self.decide(ctx, txn, false);
+            }
+        }
+    }
+
+    fn on_fault(&mut self, ctx: &mut Ctx<Message>, fault: FaultEvent) {
+        match fault {
+            FaultEvent::NodeCrashed => {
+                // Everything not persisted is lost, along with the timers
+                let (id, participants) = (self.id, std::mem::take(&mut self.participants));
+                *self = Self { id, participants, ..Self::default() };
+            }
+            FaultEvent::NodeRecovered => {
+                if self.is_coordinator() {
+                    self.recover_coordinator(ctx);
+                } else {
+                    self.recover_participant(ctx);
+                }
+                self.publish(ctx);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_client_request(&mut self, ctx: &mut Ctx<Message>, op: &ClientOp) -> Option<ClientResponse> {
+        match op {
+            // The transaction has begun; its outcome shows in the metrics
+            ClientOp::Put { key, value } if self.is_coordinator() => match self.begin(ctx, key.clone(), value.clone()) {
+                Ok(()) => Some(ClientResponse::Ok),
+                Err(err) => Some(ClientResponse::Rejected(format!("failed to persist the transaction: {}", err))),
+            },
+            ClientOp::Put { .. } => {
+                Some(ClientResponse::Rejected(format!("not the coordinator; node {} is", COORDINATOR)))
+            }
+            // Participants serve reads from what they committed
+            ClientOp::Get { key } => Some(ClientResponse::Value(self.data.get(key).cloned())),
+            ClientOp::Custom { .. } => None,
+        }
+    }
+
+    fn sample_messages(&self) -> Vec<Message> {
+        vec![
+            Message::Prepare { txn: 1, key: "key".into(), value: "value".into() },
+            Message::Vote { txn: 1, yes: true },
+            Message::Commit { txn: 1 },
+            Message::Abort { txn: 1 },
+            Message::Ack { txn: 1 },
+        ]
+    }
+}
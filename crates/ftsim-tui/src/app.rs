@@ -2,7 +2,36 @@
 //!
 //! Defines the `App` struct, which holds the state for the TUI.
 
-use ftsim_engine::{control::ControlMsg, telemetry::snapshot::Snapshot, prelude::NodeId};
+use crate::{review::Review, theme::Theme};
+use ftsim_engine::{
+    control::{BreakKind, Breakpoint, ControlMsg},
+    prelude::{sim_from_ms, NodeId},
+    telemetry::snapshot::{EventSeverity, LogSnap, Snapshot},
+};
+use std::path::PathBuf;
+
+/// The index of the logs panel in the focus cycle.
+pub const LOGS_PANEL: usize = 3;
+
+/// The speeds, in sim time per wall-clock time, that `+` and `-` step through.
+/// Stepping past the last one removes the limit.
+pub const SPEED_STEPS: &[f32] = &[0.001, 0.01, 0.1, 0.25, 0.5, 1.0, 2.0, 4.0, 10.0, 100.0];
+
+/// What a numeric prompt asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    /// The number of events to step.
+    StepCount,
+    /// The sim time, in milliseconds, to run until.
+    RunUntilMs,
+}
+
+/// A modal numeric entry opened by `n` or `u`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub input: String,
+}
 
 /// Represents the state of the TUI application.
 pub struct App {
@@ -14,12 +43,36 @@ pub struct App {
     pub is_paused: bool,
     /// Whether log filtering is enabled.
     pub filter_logs: bool,
+    /// The least severe events the logs panel shows.
+    pub min_severity: EventSeverity,
     /// Current focused panel index.
     pub focused_panel: usize,
     /// Channel to send control messages to the simulation engine.
     control_tx: crossbeam_channel::Sender<ControlMsg>,
     /// Selected node for operations (kill, restart, etc.).
     pub selected_node: Option<NodeId>,
+    /// Index into `SPEED_STEPS` of the current speed; `None` is unlimited.
+    pub speed_step: Option<usize>,
+    /// The open numeric prompt, if any. It captures all key presses.
+    pub prompt: Option<Prompt>,
+    /// Events executed by the last step or run-until request, once the
+    /// engine has paused after it.
+    pub last_stepped: Option<u64>,
+    /// Breakpoints set from the TUI.
+    pub breakpoints: Vec<Breakpoint>,
+    /// The breakpoint the engine last paused at, until the run resumes.
+    pub last_breakpoint: Option<String>,
+    /// The run's effective configuration, one setting per line, shown
+    /// read-only in the help popup.
+    pub config_summary: Vec<String>,
+    /// The styles every widget draws with.
+    pub theme: Theme,
+    /// Where the engine spills the events its recent-events ring evicts.
+    pub event_spill: Option<PathBuf>,
+    /// The review of the logged events, once the run is over.
+    pub review: Option<Review>,
+    /// The number of log lines the logs panel shows, as of the last draw.
+    pub log_rows: usize,
     // Add other UI state here, e.g., scroll positions, etc.
 }
 
@@ -30,26 +83,99 @@ impl App {
             show_help: false,
             is_paused: false,
             filter_logs: false,
+            min_severity: EventSeverity::Debug,
             focused_panel: 0,
             control_tx,
             selected_node: None,
+            speed_step: None,
+            prompt: None,
+            last_stepped: None,
+            breakpoints: Vec::new(),
+            last_breakpoint: None,
+            config_summary: Vec::new(),
+            theme: Theme::default(),
+            event_spill: None,
+            review: None,
+            log_rows: 10,
         }
     }
 
-    /// Called on every UI tick.
-    pub fn on_tick(&mut self) {}
+    /// Called on every UI tick. Takes in the spilled events read since the
+    /// last tick, during review.
+    pub fn on_tick(&mut self) {
+        let keep = self.log_filter();
+        if let Some(review) = &mut self.review {
+            review.poll(keep);
+        }
+    }
 
     /// Updates the app's state with a new snapshot from the engine.
     pub fn update_snapshot(&mut self, snapshot: Snapshot) {
+        if let Some(stepped) = snapshot.stepped {
+            self.last_stepped = Some(stepped);
+            self.is_paused = true;
+        }
+        if let Some(breakpoint) = &snapshot.breakpoint {
+            self.last_breakpoint = Some(breakpoint.clone());
+            self.is_paused = true;
+        }
+        if let Some(outcome) = &snapshot.finished {
+            self.review = Some(Review::new(
+                outcome.clone(),
+                self.event_spill.as_deref(),
+                snapshot.recent_events.clone(),
+            ));
+        }
         self.snapshot = Some(snapshot);
     }
 
+    /// Returns the log filter: events below the minimum severity are
+    /// hidden, and with filtering on, so are those of other nodes than the
+    /// selected one (node 0 if none is selected).
+    pub fn log_filter(&self) -> impl Fn(&LogSnap) -> bool {
+        let (filter, node, min) = (self.filter_logs, self.selected_node.unwrap_or(0), self.min_severity);
+        move |event| event.severity >= min && (!filter || event.node_id == Some(node))
+    }
+
+    /// Pages the logs panel back, during review.
+    pub fn logs_page_up(&mut self) {
+        let (rows, keep) = (self.log_rows, self.log_filter());
+        if let Some(review) = &mut self.review {
+            review.page_up(rows, keep);
+        }
+    }
+
+    /// Pages the logs panel forward, during review.
+    pub fn logs_page_down(&mut self) {
+        let rows = self.log_rows;
+        if let Some(review) = &mut self.review {
+            review.page_down(rows);
+        }
+    }
+
+    /// Jumps the logs panel to the oldest kept event, during review.
+    pub fn logs_home(&mut self) {
+        let (rows, keep) = (self.log_rows, self.log_filter());
+        if let Some(review) = &mut self.review {
+            review.home(rows, keep);
+        }
+    }
+
+    /// Jumps the logs panel to the newest event, during review.
+    pub fn logs_end(&mut self) {
+        if let Some(review) = &mut self.review {
+            review.end();
+        }
+    }
+
     pub fn toggle_help(&mut self) {
         self.show_help = !self.show_help;
     }
 
     pub fn toggle_pause(&mut self) {
         self.is_paused = !self.is_paused;
+        self.last_stepped = None;
+        self.last_breakpoint = None;
         let msg = if self.is_paused {
             ControlMsg::Pause
         } else {
@@ -60,12 +186,111 @@ impl App {
         }
     }
 
+    /// Asks the engine to end the run, which may already be over.
+    pub fn shutdown(&mut self) {
+        let _ = self.control_tx.send(ControlMsg::Shutdown);
+    }
+
     pub fn single_step(&mut self) {
         if let Err(e) = self.control_tx.send(ControlMsg::Step) {
             eprintln!("Failed to send step message: {}", e);
         }
     }
 
+    pub fn open_prompt(&mut self, kind: PromptKind) {
+        self.prompt = Some(Prompt {
+            kind,
+            input: String::new(),
+        });
+    }
+
+    pub fn cancel_prompt(&mut self) {
+        self.prompt = None;
+    }
+
+    /// Appends a digit to the open prompt; other characters are ignored.
+    pub fn prompt_input(&mut self, c: char) {
+        if let Some(prompt) = &mut self.prompt {
+            if c.is_ascii_digit() && prompt.input.len() < 19 {
+                prompt.input.push(c);
+            }
+        }
+    }
+
+    pub fn prompt_backspace(&mut self) {
+        if let Some(prompt) = &mut self.prompt {
+            prompt.input.pop();
+        }
+    }
+
+    /// Closes the prompt and sends the request it describes. An empty entry
+    /// sends nothing.
+    pub fn submit_prompt(&mut self) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+        let Ok(value) = prompt.input.parse::<u64>() else {
+            return;
+        };
+        let msg = match prompt.kind {
+            PromptKind::StepCount => ControlMsg::StepN(value),
+            PromptKind::RunUntilMs => ControlMsg::RunUntil(sim_from_ms(value)),
+        };
+        self.is_paused = true;
+        self.last_stepped = None;
+        if let Err(e) = self.control_tx.send(msg) {
+            eprintln!("Failed to send step message: {}", e);
+        }
+    }
+
+    /// Sets or clears a breakpoint on events of `kind` at the selected node
+    /// (node 0 if none is selected).
+    pub fn toggle_breakpoint(&mut self, kind: BreakKind) {
+        let breakpoint = Breakpoint {
+            kind: Some(kind),
+            node: Some(self.selected_node.unwrap_or(0)),
+            ..Breakpoint::default()
+        };
+        let msg = if let Some(i) = self.breakpoints.iter().position(|b| *b == breakpoint) {
+            self.breakpoints.remove(i);
+            ControlMsg::ClearBreakpoint(breakpoint)
+        } else {
+            self.breakpoints.push(breakpoint.clone());
+            ControlMsg::SetBreakpoint(breakpoint)
+        };
+        if let Err(e) = self.control_tx.send(msg) {
+            eprintln!("Failed to send breakpoint message: {}", e);
+        }
+    }
+
+    /// Returns the current speed limit, or `None` if unlimited.
+    pub fn speed(&self) -> Option<f32> {
+        self.speed_step.map(|i| SPEED_STEPS[i])
+    }
+
+    pub fn speed_up(&mut self) {
+        self.speed_step = match self.speed_step {
+            Some(i) if i + 1 < SPEED_STEPS.len() => Some(i + 1),
+            _ => None,
+        };
+        self.send_speed();
+    }
+
+    pub fn slow_down(&mut self) {
+        self.speed_step = match self.speed_step {
+            None => Some(SPEED_STEPS.len() - 1),
+            Some(i) => Some(i.saturating_sub(1)),
+        };
+        self.send_speed();
+    }
+
+    fn send_speed(&mut self) {
+        let speed = self.speed().unwrap_or(0.0);
+        if let Err(e) = self.control_tx.send(ControlMsg::SetSpeed(speed)) {
+            eprintln!("Failed to send speed message: {}", e);
+        }
+    }
+
     pub fn inject_partition(&mut self) {
         // For demo: partition nodes 0,1 from nodes 2,3 (if they exist)
         // In a real implementation, this would use UI to select partition sets
@@ -101,10 +326,14 @@ impl App {
         }
     }
 
+    /// Steps the log filter from every event to the selected node's, then
+    /// to the warnings and faults of every node, then back.
     pub fn toggle_filter_logs(&mut self) {
-        self.filter_logs = !self.filter_logs;
-        // TODO: Implement log filtering UI
-        eprintln!("Log filtering {}", if self.filter_logs { "enabled" } else { "disabled" });
+        (self.filter_logs, self.min_severity) = match (self.filter_logs, self.min_severity) {
+            (false, EventSeverity::Debug) => (true, EventSeverity::Debug),
+            (true, _) => (false, EventSeverity::Warn),
+            (false, _) => (false, EventSeverity::Debug),
+        };
     }
 
     pub fn cycle_focus(&mut self) {
@@ -2,11 +2,16 @@
 //!
 //! Handles user keyboard input and maps it to actions within the TUI app.
 
-use crate::app::App;
+use crate::app::{App, PromptKind, LOGS_PANEL};
+use ftsim_engine::control::BreakKind;
 use crossterm::event::{KeyCode, KeyEvent};
 
 /// Handles a key press event and updates the app state accordingly.
 pub fn handle_key_press(key: KeyEvent, app: &mut App) {
+    if app.prompt.is_some() {
+        handle_prompt_key(key, app);
+        return;
+    }
     match key.code {
         KeyCode::Char('?') => {
             app.toggle_help();
@@ -17,6 +22,24 @@ pub fn handle_key_press(key: KeyEvent, app: &mut App) {
         KeyCode::Char('.') => {
             app.single_step();
         }
+        KeyCode::Char('n') => {
+            app.open_prompt(PromptKind::StepCount);
+        }
+        KeyCode::Char('u') => {
+            app.open_prompt(PromptKind::RunUntilMs);
+        }
+        KeyCode::Char('b') => {
+            app.toggle_breakpoint(BreakKind::Deliver);
+        }
+        KeyCode::Char('t') => {
+            app.toggle_breakpoint(BreakKind::TimerFired);
+        }
+        KeyCode::Char('+') | KeyCode::Char('=') => {
+            app.speed_up();
+        }
+        KeyCode::Char('-') => {
+            app.slow_down();
+        }
         KeyCode::Char('p') => {
             app.inject_partition();
         }
@@ -32,6 +55,22 @@ pub fn handle_key_press(key: KeyEvent, app: &mut App) {
         KeyCode::Tab => {
             app.cycle_focus();
         }
+        // Paging through the logs only works once the run is over
+        KeyCode::PageUp if app.focused_panel == LOGS_PANEL => app.logs_page_up(),
+        KeyCode::PageDown if app.focused_panel == LOGS_PANEL => app.logs_page_down(),
+        KeyCode::Home if app.focused_panel == LOGS_PANEL => app.logs_home(),
+        KeyCode::End if app.focused_panel == LOGS_PANEL => app.logs_end(),
+        _ => {}
+    }
+}
+
+/// Handles a key press while a numeric prompt is open.
+fn handle_prompt_key(key: KeyEvent, app: &mut App) {
+    match key.code {
+        KeyCode::Char(c) => app.prompt_input(c),
+        KeyCode::Backspace => app.prompt_backspace(),
+        KeyCode::Enter => app.submit_prompt(),
+        KeyCode::Esc => app.cancel_prompt(),
         _ => {}
     }
 }
@@ -75,6 +114,66 @@ mod tests {
         assert!(!app.is_paused);
     }
 
+    #[test]
+    fn test_speed_keys() {
+        let (tx, rx) = crossbeam_channel::unbounded::<ControlMsg>();
+        let mut app = App::new(tx);
+        assert_eq!(app.speed(), None);
+
+        handle_key_press(KeyEvent::new(KeyCode::Char('-'), KeyModifiers::empty()), &mut app);
+        assert_eq!(app.speed(), Some(100.0));
+        assert!(matches!(rx.try_recv(), Ok(ControlMsg::SetSpeed(s)) if s == 100.0));
+
+        handle_key_press(KeyEvent::new(KeyCode::Char('+'), KeyModifiers::empty()), &mut app);
+        assert_eq!(app.speed(), None);
+        assert!(matches!(rx.try_recv(), Ok(ControlMsg::SetSpeed(s)) if s == 0.0));
+    }
+
+    #[test]
+    fn test_step_prompts() {
+        let (tx, rx) = crossbeam_channel::unbounded::<ControlMsg>();
+        let mut app = App::new(tx);
+        let press = |app: &mut App, code| handle_key_press(KeyEvent::new(code, KeyModifiers::empty()), app);
+
+        press(&mut app, KeyCode::Char('n'));
+        for code in [KeyCode::Char('1'), KeyCode::Char('q'), KeyCode::Char('5'), KeyCode::Char('0')] {
+            press(&mut app, code);
+        }
+        press(&mut app, KeyCode::Backspace);
+        press(&mut app, KeyCode::Enter);
+        assert!(app.prompt.is_none());
+        assert!(app.is_paused);
+        assert!(matches!(rx.try_recv(), Ok(ControlMsg::StepN(15))));
+
+        press(&mut app, KeyCode::Char('u'));
+        press(&mut app, KeyCode::Char('2'));
+        press(&mut app, KeyCode::Enter);
+        assert!(matches!(rx.try_recv(), Ok(ControlMsg::RunUntil(t)) if t == 2_000_000));
+
+        press(&mut app, KeyCode::Char('n'));
+        press(&mut app, KeyCode::Char('3'));
+        press(&mut app, KeyCode::Esc);
+        assert!(app.prompt.is_none());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_breakpoint_keys() {
+        let (tx, rx) = crossbeam_channel::unbounded::<ControlMsg>();
+        let mut app = App::new(tx);
+        app.selected_node = Some(2);
+        let key = KeyEvent::new(KeyCode::Char('t'), KeyModifiers::empty());
+
+        handle_key_press(key, &mut app);
+        assert_eq!(app.breakpoints.len(), 1);
+        assert!(matches!(rx.try_recv(), Ok(ControlMsg::SetBreakpoint(b))
+            if b.kind == Some(BreakKind::TimerFired) && b.node == Some(2)));
+
+        handle_key_press(key, &mut app);
+        assert!(app.breakpoints.is_empty());
+        assert!(matches!(rx.try_recv(), Ok(ControlMsg::ClearBreakpoint(_))));
+    }
+
     #[test]
     fn test_filter_logs_key() {
         let mut app = create_test_app();
@@ -84,9 +183,14 @@ mod tests {
         handle_key_press(key, &mut app);
         assert!(app.filter_logs);
         
-        // Toggle back
+        // Then to warnings and faults of every node
+        use ftsim_engine::prelude::EventSeverity::*;
         handle_key_press(key, &mut app);
-        assert!(!app.filter_logs);
+        assert_eq!((app.filter_logs, app.min_severity), (false, Warn));
+
+        // And back to everything
+        handle_key_press(key, &mut app);
+        assert_eq!((app.filter_logs, app.min_severity), (false, Debug));
     }
 
     #[test]
@@ -118,6 +222,14 @@ mod tests {
             KeyEvent::new(KeyCode::Char('?'), KeyModifiers::empty()),
             KeyEvent::new(KeyCode::Char(' '), KeyModifiers::empty()),
             KeyEvent::new(KeyCode::Char('.'), KeyModifiers::empty()),
+            KeyEvent::new(KeyCode::Char('+'), KeyModifiers::empty()),
+            KeyEvent::new(KeyCode::Char('-'), KeyModifiers::empty()),
+            KeyEvent::new(KeyCode::Char('n'), KeyModifiers::empty()),
+            KeyEvent::new(KeyCode::Esc, KeyModifiers::empty()),
+            KeyEvent::new(KeyCode::Char('u'), KeyModifiers::empty()),
+            KeyEvent::new(KeyCode::Esc, KeyModifiers::empty()),
+            KeyEvent::new(KeyCode::Char('b'), KeyModifiers::empty()),
+            KeyEvent::new(KeyCode::Char('t'), KeyModifiers::empty()),
             KeyEvent::new(KeyCode::Char('p'), KeyModifiers::empty()),
             KeyEvent::new(KeyCode::Char('k'), KeyModifiers::empty()),
             KeyEvent::new(KeyCode::Char('r'), KeyModifiers::empty()),
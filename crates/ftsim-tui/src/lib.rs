@@ -5,10 +5,10 @@
 
 #![forbid(unsafe_code)]
 
-use crate::app::App;
+use crate::{app::App, theme::Theme};
 use anyhow::Result;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event as CEvent, KeyCode},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event as CEvent, KeyCode, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -16,19 +16,25 @@ use ftsim_engine::{control::ControlMsg, telemetry::snapshot::Snapshot};
 use ratatui::{backend::CrosstermBackend, Terminal};
 use std::{
     io,
+    path::PathBuf,
     time::{Duration, Instant},
 };
 
 mod app;
 mod input;
-mod theme;
+mod review;
+pub mod theme;
 mod ui;
 
 /// The main entry point for running the TUI.
 /// It takes a receiver for `Snapshot` updates from the engine and a sender for control messages.
+/// Once the run is over, the logs panel reviews the events spilled to `event_spill`, if any.
 pub fn run_tui(
     snapshot_rx: crossbeam_channel::Receiver<Snapshot>,
     control_tx: crossbeam_channel::Sender<ControlMsg>,
+    config_summary: Vec<String>,
+    theme: Theme,
+    event_spill: Option<PathBuf>,
 ) -> Result<()> {
     // Setup terminal
     enable_raw_mode()?;
@@ -39,7 +45,13 @@ pub fn run_tui(
 
     // Create app and run the event loop
     let mut app = App::new(control_tx);
+    app.config_summary = config_summary;
+    app.theme = theme;
+    app.event_spill = event_spill;
     let res = run_app(&mut terminal, &mut app, snapshot_rx);
+    // Whether the user quit or the TUI failed, the run should not go on
+    // without it
+    app.shutdown();
 
     // Restore terminal
     disable_raw_mode()?;
@@ -57,6 +69,14 @@ pub fn run_tui(
     Ok(())
 }
 
+/// Leaves the alternate screen and raw mode, for callers that must restore
+/// the terminal while `run_tui` is still running, such as a panic hook.
+/// Errors are ignored, since there is nothing left to do about them.
+pub fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture, crossterm::cursor::Show);
+}
+
 fn run_app<B: ratatui::backend::Backend>(
     terminal: &mut Terminal<B>,
     app: &mut App,
@@ -66,6 +86,7 @@ fn run_app<B: ratatui::backend::Backend>(
     let mut last_tick = Instant::now();
 
     loop {
+        app.log_rows = ui::log_rows(terminal.size()?);
         terminal.draw(|f| ui::draw(f, app))?;
 
         let timeout = tick_rate
@@ -75,7 +96,9 @@ fn run_app<B: ratatui::backend::Backend>(
         // Handle input and updates
         if crossterm::event::poll(timeout)? {
             if let CEvent::Key(key) = event::read()? {
-                if key.code == KeyCode::Char('q') {
+                // Raw mode turns Ctrl-C into a key press rather than SIGINT
+                let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if ctrl_c || (key.code == KeyCode::Char('q') && app.prompt.is_none()) {
                     return Ok(());
                 }
                 input::handle_key_press(key, app);
@@ -0,0 +1,376 @@
+//! # ftsim-tui::review
+//!
+//! Post-run review of the logged events. Once the run is over, the logs
+//! panel pages through the whole history the engine kept: the events it
+//! spilled to disk followed by its final recent-events ring. Spill segments
+//! are read on a loader thread, newest first and only as paging reaches
+//! them, so neither a long run's spill nor a slow disk holds up the UI.
+
+use crossbeam_channel::{Receiver, Sender};
+use ftsim_engine::telemetry::{snapshot::LogSnap, spill::SpillReader};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::{Path, PathBuf},
+};
+
+/// What the loader thread sends back.
+enum Loaded {
+    /// The number of segments on disk.
+    Opened(usize),
+    /// The events of the next older segment, oldest first.
+    Segment(Vec<LogSnap>),
+    /// The number of spilled events, by type.
+    Counts(BTreeMap<String, u64>),
+    /// Why loading stopped.
+    Failed(String),
+}
+
+/// The UI's end of the loader thread.
+struct Loader {
+    /// Asks for the next older segment.
+    requests: Sender<()>,
+    loaded: Receiver<Loaded>,
+    /// The segments not received yet, once the spill is open.
+    remaining: Option<usize>,
+    /// Whether a segment is on its way.
+    in_flight: bool,
+    /// Whether the spilled events are still being counted.
+    counting: bool,
+}
+
+impl Loader {
+    /// Starts a thread reading the spill in `dir`. It sends the newest
+    /// segment unasked, then counts every spilled event, then sends one
+    /// older segment per request.
+    fn spawn(dir: PathBuf) -> Self {
+        let (requests, requested) = crossbeam_channel::unbounded();
+        let (send, loaded) = crossbeam_channel::unbounded();
+        std::thread::spawn(move || {
+            if let Err(failure) = Self::run(&dir, &requested, &send) {
+                send.send(Loaded::Failed(failure)).ok();
+            }
+        });
+        Self { requests, loaded, remaining: None, in_flight: true, counting: true }
+    }
+
+    /// The loader thread. Returns early once the review is dropped.
+    fn run(dir: &Path, requested: &Receiver<()>, send: &Sender<Loaded>) -> Result<(), String> {
+        let unavailable = |e: std::io::Error| format!("spilled events unavailable: {}", e);
+        let reader = SpillReader::open(dir).map_err(unavailable)?;
+        let mut next = reader.segment_count();
+        let load = |index: usize| {
+            reader.load_segment(index).map_err(|e| format!("segment {} unreadable: {}", index, e))
+        };
+        if send.send(Loaded::Opened(next)).is_err() || next == 0 {
+            return Ok(());
+        }
+        next -= 1;
+        if send.send(Loaded::Segment(load(next)?)).is_err() {
+            return Ok(());
+        }
+        let mut counts = BTreeMap::new();
+        for index in 0..reader.segment_count() {
+            for event in reader.load_segment(index).map_err(unavailable)? {
+                *counts.entry(event.event_type).or_default() += 1;
+            }
+        }
+        if send.send(Loaded::Counts(counts)).is_err() {
+            return Ok(());
+        }
+        while next > 0 && requested.recv().is_ok() {
+            next -= 1;
+            if send.send(Loaded::Segment(load(next)?)).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a message is still to come.
+    fn busy(&self) -> bool {
+        self.in_flight || self.counting
+    }
+}
+
+/// The review state of a finished run.
+pub struct Review {
+    /// How the run ended.
+    pub outcome: String,
+    /// Reads the spill, until it is all loaded or loading fails.
+    loader: Option<Loader>,
+    /// The loaded events, oldest first.
+    events: VecDeque<LogSnap>,
+    /// Matching events between the bottom of the view and the newest one.
+    offset: usize,
+    /// The offset and view height paging is heading for, once older
+    /// events arrive.
+    target: Option<(usize, usize)>,
+    /// The number of events kept, by type, spilled and in memory.
+    pub counts: BTreeMap<String, u64>,
+    /// Why the spilled events are missing from the review, if they are.
+    pub error: Option<String>,
+}
+
+impl Review {
+    /// Starts reviewing the events spilled to `spill_dir`, if any, followed
+    /// by `tail`, the final recent-events ring. The spill is read in the
+    /// background; `poll` takes in what has been read.
+    pub fn new(outcome: String, spill_dir: Option<&Path>, tail: Vec<LogSnap>) -> Self {
+        let mut counts = BTreeMap::new();
+        for event in &tail {
+            *counts.entry(event.event_type.clone()).or_default() += 1;
+        }
+        Self {
+            outcome,
+            loader: spill_dir.map(|dir| Loader::spawn(dir.to_path_buf())),
+            events: tail.into(),
+            offset: 0,
+            target: None,
+            counts,
+            error: None,
+        }
+    }
+
+    /// Takes in what the loader has read since the last call, without
+    /// waiting, and moves on towards where paging was heading.
+    pub fn poll(&mut self, keep: impl Fn(&LogSnap) -> bool) {
+        self.receive(&keep, false);
+    }
+
+    /// Takes in the loader's messages, waiting for those to come if `wait`.
+    fn receive(&mut self, keep: &impl Fn(&LogSnap) -> bool, wait: bool) {
+        loop {
+            let Some(loader) = &mut self.loader else {
+                return;
+            };
+            let message = if wait && loader.busy() {
+                loader.loaded.recv().ok()
+            } else {
+                loader.loaded.try_recv().ok()
+            };
+            let Some(message) = message else {
+                return;
+            };
+            match message {
+                Loaded::Opened(segments) => {
+                    loader.remaining = Some(segments);
+                    loader.in_flight = segments > 0;
+                }
+                Loaded::Segment(events) => {
+                    loader.remaining = loader.remaining.map(|remaining| remaining - 1);
+                    loader.in_flight = false;
+                    for event in events.into_iter().rev() {
+                        self.events.push_front(event);
+                    }
+                }
+                Loaded::Counts(counts) => {
+                    loader.counting = false;
+                    for (kind, count) in counts {
+                        *self.counts.entry(kind).or_default() += count;
+                    }
+                }
+                Loaded::Failed(error) => {
+                    self.error = Some(error);
+                    self.loader = None;
+                }
+            }
+            if self.loader.as_ref().is_some_and(|loader| !loader.busy() && loader.remaining == Some(0)) {
+                self.loader = None;
+            }
+            if let Some((target, rows)) = self.target {
+                self.seek(target, rows, keep);
+            }
+        }
+    }
+
+    /// Waits until the loader has nothing left on its way.
+    #[cfg(test)]
+    fn settle(&mut self, keep: impl Fn(&LogSnap) -> bool) {
+        self.receive(&keep, true);
+    }
+
+    /// The number of events kept, spilled and in memory, as far as they
+    /// are counted.
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// Whether the spilled events are still being counted.
+    pub fn counting(&self) -> bool {
+        self.loader.as_ref().is_some_and(|loader| loader.counting)
+    }
+
+    /// The number of events loaded so far.
+    pub fn loaded(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Whether older events are still on disk.
+    pub fn has_unloaded(&self) -> bool {
+        self.loader.as_ref().is_some_and(|loader| loader.remaining != Some(0))
+    }
+
+    fn matching(&self, keep: &impl Fn(&LogSnap) -> bool) -> usize {
+        self.events.iter().filter(|e| keep(e)).count()
+    }
+
+    /// Scrolls back to `target` matching events from the newest, as far
+    /// as the loaded events go, and asks for older ones if the view would
+    /// reach back past them.
+    fn seek(&mut self, target: usize, rows: usize, keep: &impl Fn(&LogSnap) -> bool) {
+        let matching = self.matching(keep);
+        self.offset = target.min(matching.saturating_sub(rows));
+        self.target = None;
+        if matching >= target.saturating_add(rows) || !self.has_unloaded() {
+            return;
+        }
+        if let Some(loader) = &mut self.loader {
+            if !loader.in_flight {
+                loader.in_flight = loader.requests.send(()).is_ok();
+            }
+        }
+        self.target = Some((target, rows));
+    }
+
+    /// Scrolls back by `rows` matching events.
+    pub fn page_up(&mut self, rows: usize, keep: impl Fn(&LogSnap) -> bool) {
+        self.seek(self.offset + rows, rows, &keep);
+    }
+
+    /// Scrolls forward by `rows` matching events.
+    pub fn page_down(&mut self, rows: usize) {
+        self.offset = self.offset.saturating_sub(rows);
+        self.target = None;
+    }
+
+    /// Scrolls back to the oldest event, loading every segment.
+    pub fn home(&mut self, rows: usize, keep: impl Fn(&LogSnap) -> bool) {
+        self.seek(usize::MAX, rows, &keep);
+    }
+
+    /// Scrolls to the newest event.
+    pub fn end(&mut self) {
+        self.offset = 0;
+        self.target = None;
+    }
+
+    /// Returns the matching events in view, oldest first. The view stays
+    /// put when the filter changes, as far as enough events match.
+    pub fn visible(&self, rows: usize, keep: impl Fn(&LogSnap) -> bool) -> Vec<&LogSnap> {
+        let matching: Vec<&LogSnap> = self.events.iter().filter(|e| keep(e)).collect();
+        let end = matching.len().saturating_sub(self.offset);
+        matching[end.saturating_sub(rows)..end].to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ftsim_engine::{
+        prelude::{EventId, EventSeverity, SimTime},
+        telemetry::spill::EventSpill,
+    };
+
+    fn event(event_id: u64) -> LogSnap {
+        LogSnap {
+            seq: event_id,
+            event_id: EventId(event_id),
+            time: event_id as SimTime * 1_000,
+            event_type: if event_id % 10 == 0 { "FAULT" } else { "DELIVER" }.to_string(),
+            details: format!("event {}", event_id),
+            node_id: Some((event_id % 2) as u32),
+            severity: if event_id % 10 == 0 { EventSeverity::Fault } else { EventSeverity::Debug },
+        }
+    }
+
+    fn ids(events: Vec<&LogSnap>) -> Vec<u64> {
+        events.iter().map(|e| e.event_id.0).collect()
+    }
+
+    /// Spills events 0..900 into several segments and keeps 900..1000 as
+    /// the final ring.
+    fn review(name: &str) -> (Review, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("ftsim-review-{}-{}", name, std::process::id()));
+        let mut spill = EventSpill::create(&dir, 256 * 1024).unwrap();
+        for id in 0..900 {
+            spill.append(&event(id)).unwrap();
+        }
+        spill.flush().unwrap();
+        let tail = (900..1_000).map(event).collect();
+        let mut review = Review::new("stop time".to_string(), Some(&dir), tail);
+        review.settle(|_| true);
+        (review, dir)
+    }
+
+    #[test]
+    fn test_pages_back_through_spilled_segments_on_demand() {
+        let (mut review, dir) = review("paging");
+        let all = |_: &LogSnap| true;
+        assert_eq!(review.total(), 1_000);
+        assert!(review.has_unloaded());
+        assert_eq!(ids(review.visible(3, all)), [997, 998, 999]);
+
+        // Paging reaches into the spill without loading all of it
+        for _ in 0..20 {
+            review.page_up(10, all);
+            review.settle(all);
+        }
+        assert_eq!(ids(review.visible(3, all)), [797, 798, 799]);
+        assert!(review.has_unloaded() && review.loaded() < 1_000);
+
+        review.home(10, all);
+        review.settle(all);
+        assert!(!review.has_unloaded());
+        assert_eq!(ids(review.visible(10, all)), (0..10).collect::<Vec<_>>());
+        review.page_up(10, all);
+        assert_eq!(ids(review.visible(10, all)), (0..10).collect::<Vec<_>>());
+
+        review.page_down(10);
+        assert_eq!(ids(review.visible(10, all)), (10..20).collect::<Vec<_>>());
+        review.end();
+        assert_eq!(ids(review.visible(2, all)), [998, 999]);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_spilled_events_are_read_off_the_calling_thread() {
+        let dir = std::env::temp_dir().join(format!("ftsim-review-background-{}", std::process::id()));
+        let mut spill = EventSpill::create(&dir, 256 * 1024).unwrap();
+        for id in 0..900 {
+            spill.append(&event(id)).unwrap();
+        }
+        spill.flush().unwrap();
+        let mut review = Review::new("stop time".to_string(), Some(&dir), (900..1_000).map(event).collect());
+        // Nothing spilled is taken in until the review is polled
+        assert_eq!((review.loaded(), review.total()), (100, 100));
+        assert!(review.has_unloaded());
+
+        review.settle(|_| true);
+        assert_eq!(review.total(), 1_000);
+        assert!(review.loaded() > 100 && !review.counting());
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_paging_applies_the_filter() {
+        let (mut review, dir) = review("filter");
+        let faults = |e: &LogSnap| e.event_type == "FAULT";
+        assert_eq!(review.counts["FAULT"], 100);
+        assert_eq!(ids(review.visible(2, faults)), [980, 990]);
+        review.page_up(2, faults);
+        review.settle(faults);
+        assert_eq!(ids(review.visible(2, faults)), [960, 970]);
+        review.home(2, faults);
+        review.settle(faults);
+        assert_eq!(ids(review.visible(2, faults)), [0, 10]);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_review_without_a_spill_shows_the_ring() {
+        let mut review = Review::new("quiescent".to_string(), None, (0..5).map(event).collect());
+        review.home(10, |_| true);
+        assert_eq!(ids(review.visible(10, |_| true)), [0, 1, 2, 3, 4]);
+        assert!(review.error.is_none());
+    }
+}
@@ -1,11 +1,185 @@
 //! # ftsim-tui::theme
 //!
-//! Defines the color palette and styling constants for the TUI to ensure a
-//! consistent and aesthetically pleasing look.
+//! Defines the color themes of the TUI. Every style a widget uses comes from
+//! the `Theme` held by the `App`, so switching themes restyles everything.
+//! Node statuses are also told apart by symbol, which keeps the mono theme
+//! and color-blind users covered.
 
-use ratatui::style::{Color, Style};
+use ftsim_engine::node::NodeStatus;
+use ratatui::style::{Color, Modifier, Style};
+use std::{fmt, str::FromStr};
 
-pub const BORDER_STYLE: Style = Style::new().fg(Color::DarkGray);
-pub const FOCUSED_BORDER_STYLE: Style = Style::new().fg(Color::Cyan);
-pub const TEXT_STYLE: Style = Style::new().fg(Color::White);
-pub const TITLE_STYLE: Style = Style::new().fg(Color::LightCyan);
+/// The environment variable that picks a theme when `--theme` is not given.
+pub const THEME_ENV: &str = "FTSIM_THEME";
+
+/// The built-in themes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+    /// No colors at all, only bold, reversed and underlined text.
+    Mono,
+}
+
+impl ThemeName {
+    pub const ALL: [ThemeName; 4] = [ThemeName::Dark, ThemeName::Light, ThemeName::HighContrast, ThemeName::Mono];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ThemeName::Dark => "dark",
+            ThemeName::Light => "light",
+            ThemeName::HighContrast => "high-contrast",
+            ThemeName::Mono => "mono",
+        }
+    }
+
+    /// Returns `flag` if set, otherwise the theme named by `FTSIM_THEME`,
+    /// otherwise the dark theme.
+    pub fn resolve(flag: Option<ThemeName>) -> Result<ThemeName, String> {
+        if let Some(name) = flag {
+            return Ok(name);
+        }
+        match std::env::var(THEME_ENV) {
+            Ok(value) if !value.is_empty() => value.parse().map_err(|e| format!("{}: {}", THEME_ENV, e)),
+            _ => Ok(ThemeName::default()),
+        }
+    }
+}
+
+impl fmt::Display for ThemeName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ThemeName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ThemeName::ALL
+            .into_iter()
+            .find(|name| name.as_str() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = ThemeName::ALL.iter().map(|n| n.as_str()).collect();
+                format!("unknown theme '{}', expected one of {}", s, names.join(", "))
+            })
+    }
+}
+
+/// Every style the TUI draws with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub name: ThemeName,
+    /// Fills the whole screen behind the widgets.
+    pub background: Style,
+    pub border: Style,
+    pub focused_border: Style,
+    pub text: Style,
+    pub title: Style,
+    /// The "FTSim" badge at the left of the status bar.
+    pub badge: Style,
+    pub time: Style,
+    pub speed: Style,
+    /// Values that need a second look, such as evicted KVs.
+    pub warning: Style,
+    /// The status bar banner shown while paused at a breakpoint.
+    pub alert: Style,
+    pub graph_edge: Style,
+    pub node_up: Style,
+    pub node_down: Style,
+    pub node_recovering: Style,
+}
+
+impl Theme {
+    pub fn new(name: ThemeName) -> Self {
+        match name {
+            ThemeName::Dark => Self {
+                name,
+                background: Style::new().bg(Color::Black),
+                border: Style::new().fg(Color::DarkGray),
+                focused_border: Style::new().fg(Color::Cyan),
+                text: Style::new().fg(Color::White),
+                title: Style::new().fg(Color::LightCyan),
+                badge: Style::new().bg(Color::Cyan).fg(Color::Black),
+                time: Style::new().fg(Color::Green),
+                speed: Style::new().fg(Color::Yellow),
+                warning: Style::new().fg(Color::Yellow),
+                alert: Style::new().bg(Color::Red).fg(Color::White),
+                graph_edge: Style::new().fg(Color::Gray),
+                node_up: Style::new().fg(Color::Green),
+                node_down: Style::new().fg(Color::Red),
+                node_recovering: Style::new().fg(Color::Yellow),
+            },
+            ThemeName::Light => Self {
+                name,
+                background: Style::new().bg(Color::White),
+                border: Style::new().fg(Color::Gray),
+                focused_border: Style::new().fg(Color::Blue),
+                text: Style::new().fg(Color::Black),
+                title: Style::new().fg(Color::Blue).add_modifier(Modifier::BOLD),
+                badge: Style::new().bg(Color::Blue).fg(Color::White),
+                time: Style::new().fg(Color::Blue),
+                speed: Style::new().fg(Color::Magenta),
+                warning: Style::new().fg(Color::Magenta),
+                alert: Style::new().bg(Color::Red).fg(Color::White),
+                graph_edge: Style::new().fg(Color::DarkGray),
+                node_up: Style::new().fg(Color::Green),
+                node_down: Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+                node_recovering: Style::new().fg(Color::Magenta),
+            },
+            // Blue/yellow rather than green/red, which most forms of color
+            // blindness still tell apart
+            ThemeName::HighContrast => Self {
+                name,
+                background: Style::new().bg(Color::Black),
+                border: Style::new().fg(Color::White),
+                focused_border: Style::new().fg(Color::LightYellow).add_modifier(Modifier::BOLD),
+                text: Style::new().fg(Color::White),
+                title: Style::new().fg(Color::White).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                badge: Style::new().bg(Color::White).fg(Color::Black),
+                time: Style::new().fg(Color::LightCyan),
+                speed: Style::new().fg(Color::LightYellow),
+                warning: Style::new().fg(Color::LightYellow).add_modifier(Modifier::BOLD),
+                alert: Style::new().bg(Color::LightYellow).fg(Color::Black).add_modifier(Modifier::BOLD),
+                graph_edge: Style::new().fg(Color::White),
+                node_up: Style::new().fg(Color::LightCyan),
+                node_down: Style::new().fg(Color::LightYellow).add_modifier(Modifier::BOLD | Modifier::REVERSED),
+                node_recovering: Style::new().fg(Color::White).add_modifier(Modifier::ITALIC),
+            },
+            ThemeName::Mono => Self {
+                name,
+                background: Style::new(),
+                border: Style::new(),
+                focused_border: Style::new().add_modifier(Modifier::BOLD),
+                text: Style::new(),
+                title: Style::new().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                badge: Style::new().add_modifier(Modifier::REVERSED),
+                time: Style::new().add_modifier(Modifier::BOLD),
+                speed: Style::new(),
+                warning: Style::new().add_modifier(Modifier::BOLD),
+                alert: Style::new().add_modifier(Modifier::REVERSED | Modifier::BOLD),
+                graph_edge: Style::new(),
+                node_up: Style::new(),
+                node_down: Style::new().add_modifier(Modifier::BOLD | Modifier::REVERSED),
+                node_recovering: Style::new().add_modifier(Modifier::ITALIC),
+            },
+        }
+    }
+
+    /// Returns the symbol and style a node status is drawn with.
+    pub fn status(&self, status: NodeStatus) -> (&'static str, Style) {
+        match status {
+            NodeStatus::Up => ("●", self.node_up),
+            NodeStatus::Down => ("✗", self.node_down),
+            NodeStatus::Recovering => ("◐", self.node_recovering),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::new(ThemeName::default())
+    }
+}
@@ -2,40 +2,54 @@
 //!
 //! Renders the help popup widget.
 
-use crate::theme;
+use crate::theme::Theme;
 use ratatui::{prelude::*, widgets::*};
 
-pub fn draw_help_popup(f: &mut Frame) {
+pub fn draw_help_popup(f: &mut Frame, theme: &Theme, config_summary: &[String]) {
     let block = Block::default()
         .title(" Help ")
         .borders(Borders::ALL)
-        .border_style(theme::FOCUSED_BORDER_STYLE);
+        .border_style(theme.focused_border);
 
     let text = "
     q - Quit
     ? - Toggle Help
     Space - Pause/Resume
     . - Single Step
+    n - Step N Events
+    u - Run Until Time (ms)
+    b / t - Toggle Delivery / Timer Breakpoint on Node
+    + / - - Speed Up / Slow Down
     p - Inject Partition
     k - Kill Node
     r - Restart Node
-    / - Filter Logs
+    / - Filter Logs: Node, Warnings and Faults, Off
     Tab - Cycle Focus
+    PgUp / PgDn / Home / End - Page Logs (focused, after the run)
+
+    Node status: ● Up   ✗ Down   ◐ Recovering
     ";
 
-    let paragraph = Paragraph::new(text)
-        .style(theme::TEXT_STYLE)
+    let mut lines: Vec<Line> = text.lines().map(Line::from).collect();
+    if !config_summary.is_empty() {
+        lines.push(Line::styled("    Configuration", theme.title));
+        lines.extend(config_summary.iter().map(|s| Line::from(format!("    {}", s))));
+    }
+
+    let paragraph = Paragraph::new(lines)
+        .style(theme.text)
         .block(block)
         .alignment(Alignment::Left);
 
     // Create a centered area for the popup
-    let area = centered_rect(60, 50, f.size());
+    let height = if config_summary.is_empty() { 50 } else { 80 };
+    let area = centered_rect(60, height, f.size());
     f.render_widget(Clear, area); // this clears the background
     f.render_widget(paragraph, area);
 }
 
 /// Helper to create a centered rectangle.
-fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+pub(super) fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
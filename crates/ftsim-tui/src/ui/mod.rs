@@ -7,15 +7,21 @@ use ratatui::{prelude::*, widgets::*};
 
 mod help;
 mod layout;
+mod prompt;
 mod widgets;
 
+/// Returns the number of event lines the logs panel pages by in a terminal
+/// of `size`: its inner height, less the review summary line.
+pub fn log_rows(size: Rect) -> usize {
+    let area = layout::create_main_layout(size)[3];
+    (area.height.saturating_sub(3) as usize).max(1)
+}
+
 /// The main draw function that renders the entire UI.
 pub fn draw(f: &mut Frame, app: &App) {
     let main_layout = layout::create_main_layout(f.size());
-    f.render_widget(
-        Block::new().style(Style::new().bg(Color::Black)),
-        f.size(),
-    );
+    let theme = &app.theme;
+    f.render_widget(Block::new().style(theme.background), f.size());
 
     if app.snapshot.is_some() {
         // Render the main widgets
@@ -30,8 +36,12 @@ pub fn draw(f: &mut Frame, app: &App) {
     } else {
         // Show a loading/waiting message
         let area = f.size();
-        let block = Block::default().title(" FTSim ").borders(Borders::ALL);
+        let block = Block::default()
+            .title(" FTSim ")
+            .borders(Borders::ALL)
+            .border_style(theme.border);
         let text = Paragraph::new("Waiting for simulation to start...")
+            .style(theme.text)
             .alignment(Alignment::Center)
             .block(block);
         f.render_widget(text, area);
@@ -39,6 +49,266 @@ pub fn draw(f: &mut Frame, app: &App) {
 
     // Render the help popup if active
     if app.show_help {
-        help::draw_help_popup(f);
+        help::draw_help_popup(f, theme, &app.config_summary);
+    }
+
+    // The numeric prompt is modal, so it draws over everything else
+    if let Some(p) = &app.prompt {
+        prompt::draw_prompt_popup(f, theme, p);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::{Theme, ThemeName};
+    use ftsim_engine::{
+        control::ControlMsg,
+        node::NodeStatus,
+        telemetry::snapshot::{
+            LatencyPercentiles, LinkSnap, MetricSample, MetricsSnapshot, NodeSnap, NodeTraffic, Snapshot,
+        },
+    };
+    use ratatui::{backend::TestBackend, buffer::Buffer, Terminal};
+
+    fn render(theme: ThemeName, show_help: bool) -> Buffer {
+        let (tx, _rx) = crossbeam_channel::unbounded::<ControlMsg>();
+        let mut app = App::new(tx);
+        app.theme = Theme::new(theme);
+        app.is_paused = true;
+        app.last_breakpoint = Some("deliver@1".to_string());
+        let statuses = [NodeStatus::Up, NodeStatus::Down, NodeStatus::Recovering];
+        app.update_snapshot(Snapshot {
+            time: 1_500_000,
+            nodes: statuses
+                .into_iter()
+                .enumerate()
+                .map(|(id, status)| NodeSnap {
+                    id: id as u32,
+                    status,
+                    timers: 0,
+                    byzantine: false,
+                    gray_failure: None,
+                    custom: Default::default(),
+                    evicted_kvs: 1,
+                    metrics: [(
+                        "term".to_string(),
+                        vec![MetricSample { time: 0, value: 1.0 }, MetricSample { time: 1_000_000, value: 4.0 }],
+                    )]
+                    .into_iter()
+                    .collect(),
+                    store: None,
+                    traffic: NodeTraffic { sent: 10 + id as u64, received: 7, dropped: id as u64 },
+                })
+                .collect(),
+            links: vec![
+                LinkSnap { id: 0, src: 0, dst: 1, is_partitioned: false, delivered: 4, dropped: 0 },
+                LinkSnap { id: 1, src: 1, dst: 0, is_partitioned: true, delivered: 40, dropped: 3 },
+            ],
+            recent_events: Vec::new(),
+            metrics: MetricsSnapshot {
+                delivery_latency: LatencyPercentiles { count: 12, p50: 3_000_000, p95: 3_000_000, p99: 4_500_000 },
+                ..MetricsSnapshot::default()
+            },
+            slo_violation: None,
+            stepped: None,
+            breakpoint: None,
+            finished: None,
+        });
+        app.show_help = show_help;
+
+        // Wide enough for every column of the node grid
+        let mut terminal = Terminal::new(TestBackend::new(180, 60)).unwrap();
+        terminal.draw(|f| draw(f, &app)).unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    fn text(buffer: &Buffer) -> String {
+        buffer.content.iter().map(|cell| cell.symbol()).collect()
+    }
+
+    #[test]
+    fn test_mono_theme_emits_no_colors() {
+        let buffer = render(ThemeName::Mono, true);
+        for cell in &buffer.content {
+            assert_eq!((cell.fg, cell.bg), (Color::Reset, Color::Reset), "colored cell {:?}", cell);
+        }
+        // Emphasis survives as modifiers
+        assert!(buffer.content.iter().any(|cell| cell.modifier.contains(Modifier::REVERSED)));
+
+        let colored = render(ThemeName::Dark, true);
+        assert!(colored.content.iter().any(|cell| cell.fg != Color::Reset));
+    }
+
+    #[test]
+    fn test_node_statuses_have_symbols() {
+        for theme in ThemeName::ALL {
+            // The node list, not the help overlay's legend
+            let text = text(&render(theme, false));
+            for status in ["● Up", "✗ Down", "◐ Recovering"] {
+                assert!(text.contains(status), "{} theme is missing '{}'", theme, status);
+            }
+        }
+    }
+
+    #[test]
+    fn test_logs_panel_reviews_the_run_once_it_is_over() {
+        use ftsim_engine::{
+            prelude::{EventId, EventSeverity, SimTime},
+            telemetry::snapshot::LogSnap,
+        };
+        let (tx, _rx) = crossbeam_channel::unbounded::<ControlMsg>();
+        let mut app = App::new(tx);
+        let event = |event_id: u64, event_type: &str, node_id| LogSnap {
+            seq: event_id,
+            event_id: EventId(event_id),
+            time: event_id as SimTime * 1_000_000,
+            event_type: event_type.to_string(),
+            details: format!("event {}", event_id),
+            node_id: Some(node_id),
+            severity: if event_type == "FAULT" { EventSeverity::Fault } else { EventSeverity::Debug },
+        };
+        let snapshot = |finished: Option<&str>| Snapshot {
+            time: 3_000_000,
+            nodes: Vec::new(),
+            links: Vec::new(),
+            recent_events: vec![event(1, "DELIVER", 0), event(2, "DELIVER", 1), event(3, "FAULT", 1)],
+            metrics: MetricsSnapshot::default(),
+            slo_violation: None,
+            stepped: None,
+            breakpoint: None,
+            finished: finished.map(str::to_string),
+        };
+        let draw_text = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+            terminal.draw(|f| draw(f, app)).unwrap();
+            text(terminal.backend().buffer())
+        };
+
+        app.update_snapshot(snapshot(None));
+        let live = draw_text(&app);
+        assert!(live.contains("Logs / Timeline") && live.contains("event 3"), "{}", live);
+        // Showing only warnings and up leaves the fault
+        app.min_severity = EventSeverity::Warn;
+        let faults = draw_text(&app);
+        assert!(faults.contains("Logs / Timeline [warn+]"), "{}", faults);
+        assert!(faults.contains("event 3") && !faults.contains("event 2"), "{}", faults);
+        app.min_severity = EventSeverity::Debug;
+
+        app.update_snapshot(snapshot(Some("stopped at t=3ms")));
+        app.selected_node = Some(1);
+        app.toggle_filter_logs();
+        let review = draw_text(&app);
+        assert!(review.contains("Review: stopped at t=3ms [node 1]"), "{}", review);
+        assert!(review.contains("3 events kept, 2 DELIVER, 1 FAULT"), "{}", review);
+        assert!(review.contains("event 2") && !review.contains("event 1 "), "{}", review);
+    }
+
+    #[test]
+    fn test_node_grid_counts_messages() {
+        let text = text(&render(ThemeName::Dark, false));
+        assert!(text.contains("Msgs"), "{}", text);
+        // Drops are only shown once there are some
+        assert!(text.contains("10/7 "), "{}", text);
+        assert!(text.contains("11/7 (-1)") && text.contains("12/7 (-2)"), "{}", text);
+    }
+
+    #[test]
+    fn test_node_grid_prefers_published_state() {
+        use serde_json::json;
+        let (tx, _rx) = crossbeam_channel::unbounded::<ControlMsg>();
+        let mut app = App::new(tx);
+        let customs = [
+            // The published state wins over the plain KV and the metric
+            vec![("state", json!({ "role": "Leader", "term": 7 })), ("role", json!("leader"))],
+            vec![("role", json!("backup")), ("term", json!("5"))],
+            Vec::new(),
+        ];
+        app.update_snapshot(Snapshot {
+            time: 0,
+            nodes: customs
+                .into_iter()
+                .enumerate()
+                .map(|(id, custom)| NodeSnap {
+                    id: id as u32,
+                    status: NodeStatus::Up,
+                    timers: 0,
+                    byzantine: false,
+                    gray_failure: None,
+                    custom: custom.into_iter().map(|(key, value)| (key.to_string(), value)).collect(),
+                    evicted_kvs: 0,
+                    metrics: [("term".to_string(), vec![MetricSample { time: 0, value: 4.0 }])].into_iter().collect(),
+                    store: None,
+                    traffic: NodeTraffic::default(),
+                })
+                .collect(),
+            links: Vec::new(),
+            recent_events: Vec::new(),
+            metrics: MetricsSnapshot::default(),
+            slo_violation: None,
+            stepped: None,
+            breakpoint: None,
+            finished: None,
+        });
+        let mut terminal = Terminal::new(TestBackend::new(180, 60)).unwrap();
+        terminal.draw(|f| draw(f, &app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let rows: Vec<String> = buffer
+            .content
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .filter(|row| row.contains("● Up"))
+            .map(|row| row.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect();
+        assert!(rows[0].contains("Up Leader 7 "), "{:?}", rows);
+        assert!(rows[1].contains("Up backup 4 "), "{:?}", rows);
+        assert!(rows[2].contains("Up ? 4 "), "{:?}", rows);
+    }
+
+    #[test]
+    fn test_graph_weights_links_by_traffic() {
+        let buffer = render(ThemeName::Dark, false);
+        let rows: Vec<String> = (0..buffer.area.height)
+            .map(|y| (0..buffer.area.width).map(|x| buffer.get(x, y).symbol()).collect())
+            .collect();
+        let row = |prefix: &str| rows.iter().position(|r| r.contains(prefix)).unwrap_or_else(|| panic!("{:?}", rows));
+        // The busiest link comes first with a full bar
+        assert!(row("1 -> 0") < row("0 -> 1"));
+        assert!(rows[row("1 -> 0")].contains(&format!("{}     40 (-3) partitioned", "█".repeat(20))));
+        assert!(rows[row("0 -> 1")].contains(&format!("{}{}      4", "█".repeat(2), " ".repeat(18))));
+    }
+
+    #[test]
+    fn test_metrics_panel_charts_selected_node() {
+        let text = text(&render(ThemeName::Dark, false));
+        assert!(text.contains("Metrics: node 0"), "{}", text);
+        assert!(text.contains("term 4"), "{}", text);
+        assert!(text.contains("delivery p50 3.000 p95 3.000 p99 4.500 ms"), "{}", text);
+        // No client request was answered
+        assert!(!text.contains("client"), "{}", text);
+    }
+
+    #[test]
+    fn test_metric_sparkline_uses_sim_time_axis() {
+        use widgets::metrics::resample;
+        let sample = |time, value| MetricSample { time, value };
+        // Two samples close together, then nothing until now: the later
+        // value holds for most of the axis
+        let samples = [sample(0, 1.0), sample(10, 3.0)];
+        assert_eq!(resample(&samples, 100, 10), [8; 10]);
+        let samples = [sample(0, 1.0), sample(50, 3.0), sample(60, 2.0)];
+        assert_eq!(resample(&samples, 100, 10), [1, 1, 1, 1, 8, 5, 5, 5, 5, 5]);
+        // A flat series sits on the lowest bar
+        assert_eq!(resample(&[sample(5, 7.0)], 5, 3), [1, 1, 1]);
+        assert!(resample(&[], 5, 3).is_empty());
+    }
+
+    #[test]
+    fn test_theme_names_round_trip() {
+        for theme in ThemeName::ALL {
+            assert_eq!(theme.as_str().parse::<ThemeName>(), Ok(theme));
+        }
+        assert!("solarized".parse::<ThemeName>().unwrap_err().contains("high-contrast"));
+        assert_eq!(ThemeName::resolve(Some(ThemeName::Light)), Ok(ThemeName::Light));
     }
 }
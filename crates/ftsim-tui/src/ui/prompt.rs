@@ -0,0 +1,28 @@
+//! # ftsim-tui::ui::prompt
+//!
+//! Renders the modal numeric prompt used by the step and run-until controls.
+
+use super::help::centered_rect;
+use crate::{
+    app::{Prompt, PromptKind},
+    theme::Theme,
+};
+use ratatui::{prelude::*, widgets::*};
+
+pub fn draw_prompt_popup(f: &mut Frame, theme: &Theme, prompt: &Prompt) {
+    let title = match prompt.kind {
+        PromptKind::StepCount => " Step N events ",
+        PromptKind::RunUntilMs => " Run until (ms) ",
+    };
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(theme.focused_border);
+
+    let text = format!("> {}_\n\nEnter - Confirm   Esc - Cancel", prompt.input);
+    let paragraph = Paragraph::new(text).style(theme.text).block(block);
+
+    let area = centered_rect(40, 20, f.size());
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
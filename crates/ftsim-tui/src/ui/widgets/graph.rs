@@ -1,17 +1,49 @@
 //! # ftsim-tui::ui::widgets::graph
 //!
-//! Renders the Cluster Graph widget. This is currently a placeholder.
+//! Renders the Cluster Graph widget: the links, busiest first, each with a
+//! bar weighted by the messages it delivered against the busiest one.
+//! Partitioned links and links that dropped messages are flagged.
 
-use crate::{app::App, theme};
+use crate::app::App;
 use ratatui::{prelude::*, widgets::*};
 
-pub fn draw_graph(f: &mut Frame, _app: &App, area: Rect) {
+/// How many cells the busiest link's bar fills.
+const BAR_WIDTH: usize = 20;
+
+pub fn draw_graph(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.theme;
     let block = Block::default()
         .title(" Cluster Graph ")
         .borders(Borders::ALL)
-        .border_style(theme::BORDER_STYLE);
-    let text = Paragraph::new("Graph rendering not yet implemented.")
-        .alignment(Alignment::Center)
-        .block(block);
-    f.render_widget(text, area);
+        .border_style(theme.border);
+    let mut links: Vec<_> = app.snapshot.iter().flat_map(|s| &s.links).collect();
+    if links.is_empty() {
+        let text = Paragraph::new("No links.").style(theme.graph_edge).alignment(Alignment::Center).block(block);
+        f.render_widget(text, area);
+        return;
+    }
+    links.sort_by_key(|l| (std::cmp::Reverse(l.delivered), l.id));
+    let busiest = links[0].delivered.max(1);
+
+    let lines: Vec<Line> = links
+        .iter()
+        .map(|link| {
+            // Rounded up, so a link that delivered anything shows
+            let filled = link.delivered.saturating_mul(BAR_WIDTH as u64).div_ceil(busiest) as usize;
+            let mut spans = vec![
+                Span::styled(format!("{:>3} -> {:<3} ", link.src, link.dst), theme.text),
+                Span::styled("█".repeat(filled), theme.graph_edge),
+                Span::raw(" ".repeat(BAR_WIDTH - filled)),
+                Span::styled(format!(" {:>6}", link.delivered), theme.text),
+            ];
+            if link.dropped > 0 {
+                spans.push(Span::styled(format!(" (-{})", link.dropped), theme.warning));
+            }
+            if link.is_partitioned {
+                spans.push(Span::styled(" partitioned", theme.warning));
+            }
+            Line::from(spans)
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines).block(block), area);
 }
@@ -1,14 +1,82 @@
 //! # ftsim-tui::ui::widgets::logs
 //!
-//! Renders the Logs and Timeline widget. This is currently a placeholder.
+//! Renders the Logs and Timeline widget: the engine's recent events while
+//! the run goes on, and a pageable review of every kept event, headed by a
+//! summary of them, once it is over.
 
-use crate::{app::App, theme};
+use crate::app::{App, LOGS_PANEL};
+use ftsim_engine::telemetry::snapshot::{EventSeverity, LogSnap};
 use ratatui::{prelude::*, widgets::*};
 
-pub fn draw_logs_panel(f: &mut Frame, _app: &App, area: Rect) {
-    let block = Block::default()
-        .title(" Logs / Timeline ")
-        .borders(Borders::ALL)
-        .border_style(theme::BORDER_STYLE);
-    f.render_widget(block, area);
+/// The number of event types the review summary names.
+const SUMMARY_TYPES: usize = 4;
+
+pub fn draw_logs_panel(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.theme;
+    let border = if app.focused_panel == LOGS_PANEL { theme.focused_border } else { theme.border };
+    let mut filter = if app.filter_logs {
+        format!("[node {}] ", app.selected_node.unwrap_or(0))
+    } else {
+        String::new()
+    };
+    if app.min_severity > EventSeverity::Debug {
+        filter.push_str(&format!("[{}+] ", app.min_severity));
+    }
+    let rows = area.height.saturating_sub(2) as usize;
+
+    let (title, lines) = match &app.review {
+        Some(review) => {
+            let mut counts: Vec<_> = review.counts.iter().collect();
+            counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            let mut summary = format!("{} events kept", review.total());
+            for (kind, count) in counts.iter().take(SUMMARY_TYPES) {
+                summary.push_str(&format!(", {} {}", count, kind));
+            }
+            if let Some(error) = &review.error {
+                summary.push_str(&format!(" ({})", error));
+            } else if review.counting() {
+                summary.push_str(" (counting spilled events)");
+            } else if review.has_unloaded() {
+                summary.push_str(&format!(" ({} loaded, older events on disk)", review.loaded()));
+            }
+            let mut lines = vec![Line::styled(summary, theme.title)];
+            let visible = review.visible(rows.saturating_sub(1), app.log_filter());
+            lines.extend(visible.into_iter().map(|event| log_line(app, event)));
+            let title = format!(" Review: {} {}- PgUp/PgDn/Home/End ", review.outcome, filter);
+            (title, lines)
+        }
+        None => {
+            let keep = app.log_filter();
+            let events: Vec<&LogSnap> = app
+                .snapshot
+                .iter()
+                .flat_map(|s| s.recent_events.iter())
+                .filter(|e| keep(e))
+                .collect();
+            let lines = events[events.len().saturating_sub(rows)..]
+                .iter()
+                .map(|event| log_line(app, event))
+                .collect();
+            (format!(" Logs / Timeline {}", filter), lines)
+        }
+    };
+
+    let block = Block::default().title(title).borders(Borders::ALL).border_style(border);
+    f.render_widget(Paragraph::new(lines).style(theme.text).block(block), area);
+}
+
+fn log_line<'a>(app: &App, event: &'a LogSnap) -> Line<'a> {
+    let node = event.node_id.map_or_else(|| "-".to_string(), |n| n.to_string());
+    let theme = &app.theme;
+    let kind = format!("n{:<3} {} ", node, event.event_type);
+    let kind = match event.severity {
+        EventSeverity::Fault => Span::styled(kind, theme.alert),
+        EventSeverity::Warn => Span::styled(kind, theme.warning),
+        EventSeverity::Debug | EventSeverity::Info => Span::raw(kind),
+    };
+    Line::from(vec![
+        Span::styled(format!("{:>12.3} ms ", event.time as f64 / 1_000_000.0), theme.time),
+        kind,
+        Span::raw(event.details.as_str()),
+    ])
 }
@@ -1,14 +1,135 @@
 //! # ftsim-tui::ui::widgets::metrics
 //!
-//! Renders the Metrics Panel widget. This is currently a placeholder.
+//! Renders the Metrics Panel widget: a sparkline of each numeric metric the
+//! selected node (node 0 if none is selected) publishes with `log_metric`.
+//! The x-axis is sim time, from the oldest retained sample to now. Above
+//! them, once anything was recorded, sit the run's message delivery and
+//! client request latency percentiles.
 
-use crate::{app::App, theme};
+use crate::app::App;
+use ftsim_engine::{
+    prelude::SimTime,
+    telemetry::snapshot::{LatencyPercentiles, MetricSample},
+};
 use ratatui::{prelude::*, widgets::*};
 
-pub fn draw_metrics_panel(f: &mut Frame, _app: &App, area: Rect) {
+/// Width of the label column left of each sparkline.
+const LABEL_WIDTH: u16 = 24;
+
+/// Sparkline bars are scaled into `1..=BAR_LEVELS`, so the minimum still
+/// shows as the lowest bar rather than as a gap.
+const BAR_LEVELS: u64 = 8;
+
+pub fn draw_metrics_panel(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.theme;
+    let node_id = app.selected_node.unwrap_or(0);
     let block = Block::default()
-        .title(" Metrics ")
+        .title(format!(" Metrics: node {} ", node_id))
         .borders(Borders::ALL)
-        .border_style(theme::BORDER_STYLE);
+        .border_style(theme.border);
+    let inner = block.inner(area);
     f.render_widget(block, area);
+
+    let Some(snapshot) = &app.snapshot else {
+        return;
+    };
+    let latencies = [
+        ("delivery", snapshot.metrics.delivery_latency),
+        ("client", snapshot.metrics.client_request_latency),
+    ];
+    let latencies: Vec<_> = latencies.iter().filter(|(_, l)| l.count > 0).collect();
+    let [header, inner] = *Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(latencies.len() as u16), Constraint::Min(0)])
+        .split(inner)
+    else {
+        return;
+    };
+    let lines: Vec<_> = latencies.iter().map(|(label, latency)| latency_line(label, latency, theme)).collect();
+    f.render_widget(Paragraph::new(lines), header);
+
+    let Some(node) = snapshot.nodes.iter().find(|n| n.id == node_id) else {
+        return;
+    };
+    if node.metrics.is_empty() {
+        f.render_widget(Paragraph::new("no metrics").style(theme.text), inner);
+        return;
+    }
+
+    let mut constraints = vec![Constraint::Length(1); node.metrics.len()];
+    constraints.push(Constraint::Min(0));
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(constraints)
+        .split(inner);
+    for ((key, samples), row) in node.metrics.iter().zip(rows.iter()) {
+        let Some(latest) = samples.last() else {
+            continue;
+        };
+        let cols = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(LABEL_WIDTH), Constraint::Min(0)])
+            .split(*row);
+        let label = Line::from(vec![
+            Span::styled(format!("{} ", key), theme.title),
+            Span::styled(format_value(latest.value), theme.text),
+        ]);
+        f.render_widget(Paragraph::new(label), cols[0]);
+        let bars = resample(samples, snapshot.time, cols[1].width as usize);
+        let sparkline = Sparkline::default().data(&bars).max(BAR_LEVELS).style(theme.graph_edge);
+        f.render_widget(sparkline, cols[1]);
+    }
+}
+
+/// One latency distribution's percentiles, in milliseconds.
+fn latency_line<'a>(label: &str, latency: &LatencyPercentiles, theme: &crate::theme::Theme) -> Line<'a> {
+    let ms = |ns: u64| ns as f64 / 1e6;
+    Line::from(vec![
+        Span::styled(format!("{} ", label), theme.title),
+        Span::styled(
+            format!("p50 {:.3} p95 {:.3} p99 {:.3} ms", ms(latency.p50), ms(latency.p95), ms(latency.p99)),
+            theme.text,
+        ),
+    ])
+}
+
+/// Shows integral values without a fractional part.
+fn format_value(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+/// Spreads `samples` over `width` columns by sim time, from the first
+/// sample to `now`. Each column holds the value in effect at its end,
+/// scaled into `1..=BAR_LEVELS`.
+pub(crate) fn resample(samples: &[MetricSample], now: SimTime, width: usize) -> Vec<u64> {
+    let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+        return Vec::new();
+    };
+    let (start, end) = (first.time, now.max(last.time));
+    let span = end - start;
+    let (min, max) = samples
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), s| (lo.min(s.value), hi.max(s.value)));
+    let scale = |value: f64| {
+        if max > min {
+            1 + ((value - min) / (max - min) * (BAR_LEVELS - 1) as f64).round() as u64
+        } else {
+            1
+        }
+    };
+
+    let mut next = 0;
+    (0..width)
+        .map(|col| {
+            let at = start + span * (col as u128 + 1) / width as u128;
+            while next + 1 < samples.len() && samples[next + 1].time <= at {
+                next += 1;
+            }
+            scale(samples[next].value)
+        })
+        .collect()
 }
@@ -2,8 +2,8 @@
 //!
 //! Renders the status bar and the node status grid.
 
-use crate::{app::App, theme};
-use ftsim_engine::node::NodeStatus;
+use crate::app::App;
+use ftsim_engine::prelude::STATE_KEY;
 use ratatui::{prelude::*, widgets::*};
 
 pub fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) {
@@ -13,20 +13,50 @@ pub fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) {
         .map(|s| format!("{:.3} ms", s.time as f64 / 1_000_000.0))
         .unwrap_or_else(|| "N/A".to_string());
 
-    let text = Line::from(vec![
-        Span::styled(" FTSim ", Style::new().bg(Color::Cyan).fg(Color::Black)),
+    let speed_str = match app.speed() {
+        Some(speed) => format!("speed {}x", speed),
+        None => "speed max".to_string(),
+    };
+
+    let theme = &app.theme;
+    let run_span = if !app.is_paused {
+        Span::raw("running")
+    } else if let Some(breakpoint) = &app.last_breakpoint {
+        Span::styled(format!(" paused at breakpoint '{}' ", breakpoint), theme.alert)
+    } else if let Some(stepped) = app.last_stepped {
+        Span::raw(format!("paused at t={}, stepped {} events", time_str, stepped))
+    } else {
+        Span::raw("paused")
+    };
+
+    let mut spans = vec![
+        Span::styled(" FTSim ", theme.badge),
+        Span::raw(" | "),
+        Span::styled(time_str, theme.time),
         Span::raw(" | "),
-        Span::styled(time_str, Style::new().fg(Color::Green)),
+        run_span,
+        Span::raw(" | "),
+    ];
+    // Stays up until a later window meets the SLO
+    if let Some(window) = app.snapshot.as_ref().and_then(|s| s.slo_violation) {
+        spans.push(Span::styled(format!(" SLO violated: {} ", window), theme.alert));
+        spans.push(Span::raw(" | "));
+    }
+    spans.extend([
+        Span::raw(format!("{} breakpoints", app.breakpoints.len())),
+        Span::raw(" | "),
+        Span::styled(speed_str, theme.speed),
         Span::raw(" | Press '?' for help, 'q' to quit"),
     ]);
-    f.render_widget(Paragraph::new(text), area);
+    f.render_widget(Paragraph::new(Line::from(spans)).style(theme.text), area);
 }
 
 pub fn draw_node_status_grid(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.theme;
     let block = Block::default()
         .title(" Node Status ")
         .borders(Borders::ALL)
-        .border_style(theme::BORDER_STYLE);
+        .border_style(theme.border);
 
     let Some(snapshot) = &app.snapshot else {
         f.render_widget(block, area);
@@ -34,17 +64,17 @@ pub fn draw_node_status_grid(f: &mut Frame, app: &App, area: Rect) {
     };
 
     let rows = snapshot.nodes.iter().map(|node| {
-        let status_style = match node.status {
-            NodeStatus::Up => Style::new().fg(Color::Green),
-            NodeStatus::Down => Style::new().fg(Color::Red),
-            NodeStatus::Recovering => Style::new().fg(Color::Yellow),
-        };
-        let role = node
-            .custom
-            .get("role")
+        let (symbol, status_style) = theme.status(node.status);
+        // Protocols publish structured state; older ones plain KVs
+        let state = node.custom.get(STATE_KEY);
+        let role = state
+            .and_then(|s| s.get("role"))
+            .or_else(|| node.custom.get("role"))
             .and_then(|v| v.as_str())
             .unwrap_or("?");
-        let term = node.custom.get("term").map(|v| {
+        let term = state.and_then(|s| s.get("term")).and_then(|v| v.as_u64());
+        let term = term.or_else(|| node.metric("term").map(|t| t as u64));
+        let term = term.map(|t| t.to_string()).or_else(|| node.custom.get("term").map(|v| {
             if let Some(n) = v.as_u64() {
                 n.to_string()
             } else if let Some(s) = v.as_str() {
@@ -52,13 +82,43 @@ pub fn draw_node_status_grid(f: &mut Frame, app: &App, area: Rect) {
             } else {
                 "-".into()
             }
-        }).unwrap_or_else(|| "-".into());
+        })).unwrap_or_else(|| "-".into());
+        // Flag nodes whose custom KVs have been trimmed to the per-node limit.
+        let kvs = if node.evicted_kvs > 0 {
+            Cell::from(format!("{} (-{})", node.custom.len(), node.evicted_kvs))
+                .style(theme.warning)
+        } else {
+            Cell::from(node.custom.len().to_string())
+        };
+        // Persisted state: log length@last term, KV key count, and size.
+        let store = node
+            .store
+            .as_ref()
+            .map(|s| {
+                let term = s.last_log_term.map_or("-".into(), |t| t.to_string());
+                let mut summary = format!("log {}@{} kv {} {}B", s.log_len, term, s.kv_keys, s.approx_bytes);
+                if s.throttled_writes > 0 {
+                    summary.push_str(&format!(" throttled {}", s.throttled_writes));
+                }
+                summary
+            })
+            .unwrap_or_else(|| "-".into());
+        // Messages sent/received, and those lost on the way to the node
+        let traffic = node.traffic;
+        let msgs = if traffic.dropped > 0 {
+            Cell::from(format!("{}/{} (-{})", traffic.sent, traffic.received, traffic.dropped)).style(theme.warning)
+        } else {
+            Cell::from(format!("{}/{}", traffic.sent, traffic.received))
+        };
 
         Row::new(vec![
             Cell::from(node.id.to_string()),
-            Cell::from(format!("{:?}", node.status)).style(status_style),
+            Cell::from(format!("{} {:?}", symbol, node.status)).style(status_style),
             Cell::from(role.to_string()),
-            Cell::from(term),
+            Cell::from(Line::from(term).alignment(Alignment::Right)),
+            kvs,
+            msgs,
+            Cell::from(store),
         ])
     });
 
@@ -68,12 +128,16 @@ pub fn draw_node_status_grid(f: &mut Frame, app: &App, area: Rect) {
             Constraint::Length(4),
             Constraint::Length(12),
             Constraint::Length(12),
-            Constraint::Min(8),
+            Constraint::Length(8),
+            Constraint::Length(10),
+            Constraint::Length(14),
+            Constraint::Min(20),
         ],
     )
     .header(
-        Row::new(vec!["ID", "Status", "Role", "Term"]).style(theme::TITLE_STYLE),
+        Row::new(vec!["ID", "Status", "Role", "Term", "KVs", "Msgs", "Store"]).style(theme.title),
     )
+    .style(theme.text)
     .block(block);
 
     f.render_widget(table, area);
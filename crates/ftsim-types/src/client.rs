@@ -0,0 +1,76 @@
+//! # ftsim-types::client
+//!
+//! Client operations that scenarios issue to protocols with the
+//! `ClientRequest` action, and the responses protocols answer them with.
+//! They are deliberately generic, key-value shaped, so that one scenario
+//! workload can drive any protocol that stores keys.
+
+use serde::{Deserialize, Serialize};
+
+/// A client operation issued to a node.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub enum ClientOp {
+    Put { key: String, value: String },
+    Get { key: String },
+    /// Protocol-specific bytes, hex encoded in the scenario.
+    Custom { bytes_hex: String },
+}
+
+impl ClientOp {
+    /// Returns the operation's name, for metrics and logs.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ClientOp::Put { .. } => "put",
+            ClientOp::Get { .. } => "get",
+            ClientOp::Custom { .. } => "custom",
+        }
+    }
+
+    /// Replaces every `{i}` in the operation's key and value with `i`, so
+    /// that the repeats of an `Every` directive can touch distinct keys.
+    pub fn instantiate(&self, i: u64) -> ClientOp {
+        let fill = |s: &str| s.replace("{i}", &i.to_string());
+        match self {
+            ClientOp::Put { key, value } => ClientOp::Put {
+                key: fill(key),
+                value: fill(value),
+            },
+            ClientOp::Get { key } => ClientOp::Get { key: fill(key) },
+            ClientOp::Custom { .. } => self.clone(),
+        }
+    }
+
+    /// Decodes the bytes of a `Custom` operation.
+    pub fn custom_bytes(&self) -> Option<Result<Vec<u8>, String>> {
+        let ClientOp::Custom { bytes_hex } = self else {
+            return None;
+        };
+        let hex = bytes_hex.trim();
+        if !hex.is_ascii() || hex.len() % 2 != 0 {
+            return Some(Err(format!("'{}' is not an even number of hex digits", hex)));
+        }
+        Some(
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| format!("invalid hex '{}'", &hex[i..i + 2])))
+                .collect(),
+        )
+    }
+}
+
+/// A protocol's answer to a client operation.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub enum ClientResponse {
+    /// A write was applied.
+    Ok,
+    /// The value read, or `None` if the key is not set.
+    Value(Option<String>),
+    /// The node will not serve the operation, e.g. because it is not the
+    /// leader.
+    Rejected(String),
+    /// The node answers later, with `ProtoCtx::respond_to_client`, e.g.
+    /// once a write is replicated.
+    Pending,
+}
@@ -5,14 +5,14 @@
 //! but also essential metadata for routing, tracing, and fault injection.
 
 use crate::{
-    id::{NodeId},
+    id::{MsgId, NodeId},
     time::SimTime,
 };
 use bytes::Bytes;
 
 /// A unique tag identifying the protocol namespace for a message.
 /// This allows multiple protocols to run on the same node without interference.
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize)]
 pub struct ProtoTag(pub u16);
 
 /// A wrapper for all messages sent over the simulated network.
@@ -31,10 +31,22 @@ pub struct Envelope {
     /// The protocol-specific payload, serialized into raw bytes.
     pub payload: Bytes,
     /// A unique, deterministically-assigned ID for this message instance.
-    pub msg_id: u64,
+    pub msg_id: MsgId,
     /// The simulation time when this message was created.
     pub create_time: SimTime,
+    /// The sender's clock, skew included, when this message was created.
+    pub sent_at_local: SimTime,
     /// An ID used to correlate related events (e.g., a request and its response)
     /// for observability and debugging.
     pub trace_id: u64,
+    /// Set when this envelope carries one fragment of a larger message. All
+    /// fragments share the original message's `msg_id`.
+    pub fragment: Option<FragmentInfo>,
+}
+
+/// The position of a fragment within its original message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FragmentInfo {
+    pub index: u32,
+    pub count: u32,
 }
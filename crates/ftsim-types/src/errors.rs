@@ -14,6 +14,8 @@ pub enum SimError {
     TimeOverflow { base: SimTime, offset: SimTime },
     #[error("Simulation time underflow: {base} - {offset}")]
     TimeUnderflow { base: SimTime, offset: SimTime },
+    #[error("Simulation time overflow: {span} * {factor}")]
+    TimeProductOverflow { span: SimTime, factor: u128 },
     #[error("Monotonic ID counter overflowed")]
     IdOverflow,
     #[error("Node with ID {0} not found")]
@@ -22,6 +24,10 @@ pub enum SimError {
     LinkNotFound(u64),
     #[error("Protocol with tag {0:?} not registered")]
     ProtocolNotRegistered(super::envelope::ProtoTag),
+    #[error("Protocol '{protocol}' on node {node} does not support state snapshots")]
+    SnapshotUnsupported { node: u32, protocol: &'static str },
+    #[error("Failed to restore the state of node {node}: {message}")]
+    RestoreFailed { node: u32, message: String },
 }
 
 /// An error related to parsing or validating configuration files.
@@ -29,8 +35,10 @@ pub enum SimError {
 pub enum ConfigError {
     #[error("I/O error reading config file: {0}")]
     Io(#[from] std::io::Error),
+    #[cfg(feature = "toml")]
     #[error("TOML parsing error: {0}")]
     Toml(#[from] toml::de::Error),
+    #[cfg(feature = "yaml")]
     #[error("YAML parsing error: {0}")]
     Yaml(#[from] serde_yaml::Error),
     #[error("Validation error in scenario '{name}': {message}")]
@@ -53,6 +61,10 @@ pub enum StoreError {
     NotFound(u64),
     #[error("Operation failed due to injected fault")]
     FaultInjected,
+    #[error("Record at index {0} has been compacted into a snapshot")]
+    Compacted(u64),
+    #[error("Write of {bytes} bytes exceeds the store's write quota (simulated)")]
+    Throttled { bytes: u64 },
 }
 
 /// An error originating from the network subsystem.
@@ -2,7 +2,13 @@
 //!
 //! Defines the core identifier types used throughout the simulation.
 //! Using distinct types for different kinds of IDs helps prevent bugs where,
-//! for example, a `NodeId` might be accidentally used as a `LinkId`.
+//! for example, a `TimerId` might be accidentally used as an `EventId`.
+//! Timer, message and event IDs are newtypes that do not convert into each
+//! other; each converts from and to its raw `u64` for code that needs to
+//! make one up or store it.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 /// A unique identifier for a node in the simulation.
 /// Invariant: Initially spawned nodes MUST have contiguous IDs from 0 to N-1.
@@ -11,8 +17,62 @@ pub type NodeId = u32;
 /// A unique identifier for a directed link between two nodes.
 pub type LinkId = u64;
 
-/// A unique identifier for a timer set by a protocol.
-pub type TimerId = u64;
+macro_rules! id_newtype {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(pub u64);
+
+        impl $name {
+            pub const MAX: Self = Self(u64::MAX);
+
+            /// Returns the ID after this one, or `None` on overflow.
+            pub fn checked_next(self) -> Option<Self> {
+                self.0.checked_add(1).map(Self)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl From<u64> for $name {
+            fn from(id: u64) -> Self {
+                Self(id)
+            }
+        }
+
+        impl From<$name> for u64 {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+    };
+}
+
+id_newtype! {
+    /// A unique identifier for a timer set by a protocol.
+    TimerId
+}
+
+id_newtype! {
+    /// A unique identifier for a message. All fragments of a message share
+    /// its ID.
+    MsgId
+}
+
+id_newtype! {
+    /// A unique identifier for a scheduled event in the simulation's master queue.
+    EventId
+}
+
+/// Identifies a request sent with `Ctx::request`. It is the ID of the
+/// request's timeout timer.
+pub type RequestId = TimerId;
 
-/// A unique identifier for a scheduled event in the simulation's master queue.
-pub type EventId = u64;
+/// Identifies a client request a scenario issued. It is the ID of the
+/// request's event.
+pub type ClientRequestId = EventId;
@@ -9,14 +9,25 @@
 pub const MET_NET_MSG_SENT: &str = "ftsim_net_msg_sent_total";
 pub const MET_NET_MSG_DELIVERED: &str = "ftsim_net_msg_delivered_total";
 pub const MET_NET_MSG_DROPPED: &str = "ftsim_net_msg_dropped_total";
+pub const MET_NET_FRAGMENTS: &str = "ftsim_net_fragments_total";
+pub const MET_NET_REASSEMBLY_FAILED: &str = "ftsim_net_reassembly_failures_total";
 pub const MET_TIMER_FIRED: &str = "ftsim_timer_fired_total";
 pub const MET_NODE_CRASHED: &str = "ftsim_node_crashed_total";
 pub const MET_NODE_RESTARTED: &str = "ftsim_node_restarted_total";
+pub const MET_SIMULTANEOUS_RESTARTS: &str = "ftsim_simultaneous_restarts_total";
+pub const MET_GRAY_FAILURE_IGNORED: &str = "ftsim_gray_failure_ignored_total";
 pub const MET_STORE_WRITE_ERR: &str = "ftsim_store_write_errors_total";
+pub const MET_STORE_TIME: &str = "ftsim_store_time_ns_total";
+pub const MET_STORE_THROTTLED: &str = "ftsim_store_throttled_writes_total";
+pub const MET_DELAY_CLAMPED: &str = "ftsim_delay_clamped_total";
+pub const MET_CODEC_ERRORS: &str = "ftsim_codec_errors_total";
+pub const MET_CLIENT_REQUESTS: &str = "ftsim_client_requests_total";
+pub const MET_CLIENT_LATENCY_HISTO: &str = "ftsim_client_request_latency_ns";
 pub const MET_LATENCY_HISTO: &str = "ftsim_net_latency_ns";
 pub const MET_EVENT_EXEC_HISTO: &str = "ftsim_event_exec_ns";
 pub const MET_NODES_UP_GAUGE: &str = "ftsim_nodes_up";
 pub const MET_LINKS_PARTITIONED_GAUGE: &str = "ftsim_links_partitioned";
+pub const MET_CACHE_EVICTED: &str = "ftsim_cache_evictions_total";
 
 // --- Label Keys ---
 pub const LBL_NODE: &str = "node";
@@ -27,3 +38,4 @@ pub const LBL_EVENT: &str = "event";
 pub const LBL_PROTO: &str = "proto";
 pub const LBL_REASON: &str = "reason";
 pub const LBL_KIND: &str = "kind";
+pub const LBL_CACHE: &str = "cache";
@@ -4,30 +4,351 @@
 //! This is the authoritative schema for defining simulation experiments.
 
 use crate::{
+    client::ClientOp,
     envelope::ProtoTag,
     id::{LinkId, NodeId},
-    time::{deserialize_sim_time, SimTime},
+    time::{
+        deserialize_optional_sim_time, deserialize_sim_time, deserialize_skew, serialize_optional_sim_time,
+        serialize_sim_time, serialize_skew, SimDuration, SimTime,
+    },
 };
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
 
 /// The top-level structure for a scenario definition file.
 #[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Scenario {
     pub name: String,
     pub seed: Option<u64>,
     pub initial: InitialSpec,
     pub topology: super::topology::TopologySpec,
-    pub directives: Vec<Directive>,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directives: Vec<DirectiveSpec>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_optional_sim_time",
+        serialize_with = "serialize_optional_sim_time"
+    )]
     pub stop_at: Option<SimTime>,
+    /// Stop after this many events have been processed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_after_events: Option<u64>,
+    /// Stop once the protocols go idle: only periodic directives remain.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stop_on_quiescence: bool,
+    #[serde(default)]
+    pub telemetry: TelemetrySpec,
+    /// What to do when a protocol fails to decode a delivered message.
+    #[serde(default)]
+    pub on_codec_error: CodecErrorPolicy,
+    /// How events at the same sim time are ordered.
+    #[serde(default)]
+    pub scheduling: SchedulingPolicy,
+    /// What to do with messages stamped further ahead of the receiver's
+    /// clock than the sender's skew should allow.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub future_message_policy: Option<FutureMessagePolicy>,
+    /// Defers a node's events at an instant it floods, so that the clock
+    /// can advance past it. Off unless set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flood_valve: Option<FloodValve>,
+    /// Staggers the restarts that crashes with a duration schedule. Off
+    /// unless set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart_policy: Option<RestartPolicy>,
+    /// Named stages of the experiment, each checked when it ends.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub phases: Vec<Phase>,
+    /// Journal only a sample of message lifecycles. Without this, a message
+    /// journal records every message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub journal_sampling: Option<JournalSampling>,
+    /// Built-in invariants, by name, checked during the run.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub invariants: Vec<String>,
+    /// Check invariants after every this many non-fault events. Faults are
+    /// always followed by a check. Defaults to every event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invariant_check_every: Option<u64>,
+    /// A latency objective for client requests, judged per window.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slo: Option<Slo>,
+    /// The names phase expressions may read. When set, an expression that
+    /// reads anything else is rejected as the scenario loads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expr_schema: Option<ExprSchema>,
+    /// Limits on the engine's own bookkeeping, so long runs stay bounded.
+    #[serde(default)]
+    pub caches: CachesSpec,
+}
+
+/// The KVs, metrics and oracles expressions may read. The scenario's own
+/// invariants are always allowed as oracles.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ExprSchema {
+    #[serde(default)]
+    pub kvs: Vec<String>,
+    #[serde(default)]
+    pub metrics: Vec<String>,
+    #[serde(default)]
+    pub oracles: Vec<String>,
+}
+
+/// A latency objective for client requests. Sim time after `warmup` is cut
+/// into consecutive windows of `window`; a window violates the objective
+/// if the p99 latency of the requests that completed in it exceeds
+/// `p99_ms`. Windows in which no request completed are not judged.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Slo {
+    pub p99_ms: f64,
+    #[serde(deserialize_with = "deserialize_sim_time", serialize_with = "serialize_sim_time")]
+    pub window: SimTime,
+    /// Requests completing before this are not judged.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_sim_time",
+        serialize_with = "serialize_sim_time"
+    )]
+    pub warmup: SimTime,
+}
+
+impl Slo {
+    /// The p99 latency target, in sim time.
+    pub fn target(&self) -> SimTime {
+        (self.p99_ms * 1_000_000.0).round() as SimTime
+    }
+}
+
+/// A named window `[start, end)` of the run. Its expectations are checked
+/// once every event before `end` has run.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Phase {
+    pub name: String,
+    #[serde(deserialize_with = "deserialize_sim_time", serialize_with = "serialize_sim_time")]
+    pub start: SimTime,
+    #[serde(deserialize_with = "deserialize_sim_time", serialize_with = "serialize_sim_time")]
+    pub end: SimTime,
+    #[serde(default)]
+    pub expect: Vec<PhaseCheck>,
+}
+
+/// A condition on the world that must hold when a phase ends.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
+pub enum PhaseCheck {
+    /// Some up node reports the `leader` or `primary` role.
+    LeaderExists,
+    /// At least this fraction of nodes is up.
+    MinAvailability(f64),
+    /// The latest value of a numeric metric published with `log_metric`
+    /// compares to `value` as `op` says, on `node` or, if unset, on some up
+    /// node.
+    Metric {
+        key: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        node: Option<NodeId>,
+        op: Comparison,
+        value: f64,
+    },
+    /// An expression over the run's signals, such as
+    /// `count(nodes where status == "Down") <= 1`, that must hold. The
+    /// language is described in `ftsim_engine::expr`.
+    Expr(String),
+}
+
+/// A numeric comparison, written as its operator in scenario files.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    #[serde(rename = "<")]
+    Lt,
+    #[serde(rename = "<=")]
+    Le,
+    #[serde(rename = "==")]
+    Eq,
+    #[serde(rename = "!=")]
+    Ne,
+    #[serde(rename = ">=")]
+    Ge,
+    #[serde(rename = ">")]
+    Gt,
+}
+
+impl Comparison {
+    /// Whether `lhs op rhs` holds.
+    pub fn holds(self, lhs: f64, rhs: f64) -> bool {
+        match self {
+            Comparison::Lt => lhs < rhs,
+            Comparison::Le => lhs <= rhs,
+            Comparison::Eq => lhs == rhs,
+            Comparison::Ne => lhs != rhs,
+            Comparison::Ge => lhs >= rhs,
+            Comparison::Gt => lhs > rhs,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Ge => ">=",
+            Comparison::Gt => ">",
+        }
+    }
+}
+
+/// How the engine reacts when a protocol rejects a message it cannot decode.
+/// Every policy counts the message per (protocol tag, destination node).
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CodecErrorPolicy {
+    /// Log the error and drop the message.
+    #[default]
+    Drop,
+    /// Drop the message, logging it only as a warning, for runs that
+    /// expect some, such as mixed-version ones.
+    CountAndContinue,
+    /// Stop the simulation, reporting the offending envelope.
+    Fail,
+}
+
+/// How events scheduled for the same instant are ordered.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SchedulingPolicy {
+    /// Insertion order, with watermarks and UI ticks after everything else.
+    /// Traces recorded before `Priority` existed only replay under this.
+    #[default]
+    Legacy,
+    /// Faults, then timers, then deliveries, then watermarks, then UI ticks,
+    /// each kind in insertion order.
+    Priority,
+}
+
+/// Checks each delivered message's send time, read off the sender's clock,
+/// against the receiver's clock. A message sent more than `max_skew` in the
+/// receiver's future is handled by `action`; messages from the past are
+/// always delivered.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct FutureMessagePolicy {
+    #[serde(deserialize_with = "deserialize_sim_time", serialize_with = "serialize_sim_time")]
+    pub max_skew: SimTime,
+    #[serde(default)]
+    pub action: FutureMessageAction,
+}
+
+/// What happens to a message from the future.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FutureMessageAction {
+    /// Drop the message before the protocol sees it.
+    #[default]
+    Drop,
+    /// Deliver it with `MessageMeta::from_future` set.
+    DeliverWithFlag,
+}
+
+/// How automatically scheduled restarts are spread out. A crash with a
+/// duration restarts its node `stagger` later than the duration alone
+/// would, drawn anew for every crash, so that nodes crashed together do not
+/// all come back in the same instant. Restarts directed by the scenario or
+/// requested by a user are not staggered.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct RestartPolicy {
+    pub stagger: DelaySpec,
+}
+
+/// An emergency valve against a protocol that floods one instant with
+/// events, e.g. by re-arming a zero-delay timer, which would keep the clock
+/// from ever reaching the faults scheduled after that instant.
+///
+/// Once a node has run `threshold` events at one sim time, each of its
+/// further events at that time is deferred to `quantum` later instead of
+/// running, keeping its order among them. Faults, watermarks and UI ticks
+/// are never deferred. Deferral depends only on the order events run in,
+/// so a seed still replays the same way with the same valve.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct FloodValve {
+    pub threshold: u64,
+    #[serde(
+        default = "default_flood_quantum",
+        deserialize_with = "deserialize_sim_time",
+        serialize_with = "serialize_sim_time"
+    )]
+    pub quantum: SimTime,
+}
+
+fn default_flood_quantum() -> SimTime {
+    crate::time::sim_from_us(1)
+}
+
+/// Which message lifecycles a message journal keeps.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct JournalSampling {
+    /// The fraction of messages journaled, chosen by hashing the message id
+    /// with the seed so that replays sample the same messages.
+    pub rate: f64,
+    /// Categories journaled in full regardless of `rate`.
+    #[serde(default)]
+    pub always_include: Vec<JournalCategory>,
+}
+
+/// A class of messages that sampling can be told never to skip.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalCategory {
+    /// Sent by a node that was down, byzantine or partitioned, or delivered
+    /// over a partitioned link.
+    Faults,
+    /// Rejected by the receiving protocol's decoder.
+    Errors,
+    /// Sent by a node that reports the `leader` or `primary` role.
+    Leadership,
+}
+
+impl std::str::FromStr for CodecErrorPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop" => Ok(Self::Drop),
+            "count-and-continue" => Ok(Self::CountAndContinue),
+            "fail" => Ok(Self::Fail),
+            _ => Err(format!(
+                "unknown codec error policy '{}'; expected drop, count-and-continue or fail",
+                s
+            )),
+        }
+    }
 }
 
 impl Scenario {
     /// Validates the scenario for logical consistency.
     pub fn validate(&self) -> Result<(), String> {
         let num_nodes = self.initial.nodes;
-        for (i, directive) in self.directives.iter().enumerate() {
+        let tags = self.initial.proto.tags();
+        if tags.is_empty() {
+            return Err("initial.proto lists no protocols".to_string());
+        }
+        if let Some((i, tag)) = tags.iter().enumerate().find(|(i, tag)| tags[..*i].contains(tag)) {
+            return Err(format!("initial.proto lists tag {} twice, at {}", tag.0, i));
+        }
+        for (i, DirectiveSpec { directive, label, announce_before }) in self.directives.iter().enumerate() {
+            if label.as_deref().is_some_and(|label| label.trim().is_empty()) {
+                return Err(format!("Directive {} has an empty label", i));
+            }
             let action = directive.action();
             // Validate NodeIds are in range
             if let Some(node_id) = action.node_id() {
@@ -65,31 +386,540 @@ impl Scenario {
                     ));
                 }
             }
+            if let Action::LinkDelay { dist, .. } = action {
+                dist.validate().map_err(|e| format!("Directive {}: {}", i, e))?;
+            }
+            if let Action::LinkDrop { p, .. } | Action::LinkDuplicate { p, .. } = action {
+                if !(0.0..=1.0).contains(p) {
+                    return Err(format!("Directive {} sets a link probability {} outside 0..=1", i, p));
+                }
+            }
+            if let Action::DropNth { src, dst, n, .. } | Action::DelayNth { src, dst, n, .. } = action {
+                if let Some(node) = [src, dst].into_iter().find(|&&n| n as usize >= num_nodes) {
+                    return Err(format!("Directive {} targets messages of invalid NodeId {}", i, node));
+                }
+                if src == dst {
+                    return Err(format!("Directive {} targets messages from node {} to itself", i, src));
+                }
+                if *n == 0 {
+                    return Err(format!("Directive {} counts occurrences from 1, not 0", i));
+                }
+            }
+            if let Action::GrayFailure { ignore_from, .. } = action {
+                match ignore_from {
+                    GrayFilter::Peers(peers) => {
+                        if let Some(peer) = peers.iter().find(|&&n| n as usize >= num_nodes) {
+                            return Err(format!("Directive {} ignores messages from invalid NodeId {}", i, peer));
+                        }
+                    }
+                    GrayFilter::Probability(p) if !(0.0..=1.0).contains(p) => {
+                        return Err(format!("Directive {} ignores messages with probability {} outside 0..=1", i, p));
+                    }
+                    GrayFilter::Probability(_) => {}
+                }
+            }
+            if let Action::ClientRequest { op, .. } = action {
+                if let Some(Err(e)) = op.custom_bytes() {
+                    return Err(format!("Directive {}: {}", i, e));
+                }
+                if announce_before.is_some() {
+                    return Err(format!("Directive {} announces a client request; only faults can be announced", i));
+                }
+            }
+        }
+        if let Some(latency) = &self.initial.store.latency {
+            for (op, spec) in [("read", &latency.read), ("write", &latency.write), ("fsync", &latency.fsync)] {
+                spec.validate().map_err(|e| format!("store.latency.{}: {}", op, e))?;
+            }
+        }
+        if let Some(quota) = &self.initial.store.write_quota {
+            if quota.bytes_per_sec == 0 {
+                return Err("store.write_quota.bytes_per_sec must be positive".to_string());
+            }
+            if let Some(node) = quota.nodes.iter().find(|&&n| n as usize >= num_nodes) {
+                return Err(format!("store.write_quota names invalid NodeId {}", node));
+            }
+        }
+        if self.telemetry.max_metric_samples == 0 {
+            return Err("telemetry.max_metric_samples must be at least 1".to_string());
+        }
+        if let Some(valve) = &self.flood_valve {
+            if valve.threshold == 0 || valve.quantum == 0 {
+                return Err("flood_valve.threshold and flood_valve.quantum must be positive".to_string());
+            }
+        }
+        if let Some(policy) = &self.restart_policy {
+            policy.stagger.validate().map_err(|e| format!("restart_policy.stagger: {}", e))?;
+        }
+        if self.invariant_check_every == Some(0) {
+            return Err("invariant_check_every must be at least 1".to_string());
+        }
+        if let Some(slo) = &self.slo {
+            if !(slo.p99_ms.is_finite() && slo.p99_ms > 0.0) || slo.window == 0 {
+                return Err("slo.p99_ms and slo.window must be positive".to_string());
+            }
+        }
+        if self.caches.pending_clients.max_entries == 0 {
+            return Err("caches.pending_clients.max_entries must be at least 1".to_string());
+        }
+        if let Some(sampling) = &self.journal_sampling {
+            if !(0.0..=1.0).contains(&sampling.rate) {
+                return Err(format!("Journal sampling rate {} is outside 0..=1", sampling.rate));
+            }
+        }
+        for phase in &self.phases {
+            if phase.start > phase.end {
+                return Err(format!("Phase '{}' ends before it starts", phase.name));
+            }
+            for check in &phase.expect {
+                match check {
+                    PhaseCheck::MinAvailability(fraction) if !(0.0..=1.0).contains(fraction) => {
+                        return Err(format!(
+                            "Phase '{}' availability {} is outside 0..=1",
+                            phase.name, fraction
+                        ));
+                    }
+                    PhaseCheck::Metric { node: Some(node), .. } if *node as usize >= num_nodes => {
+                        return Err(format!("Phase '{}' checks a metric of invalid NodeId {}", phase.name, node));
+                    }
+                    PhaseCheck::Metric { key, value, .. } if !value.is_finite() => {
+                        return Err(format!("Phase '{}' compares metric '{}' to {}", phase.name, key, value));
+                    }
+                    PhaseCheck::Expr(source) if source.trim().is_empty() => {
+                        return Err(format!("Phase '{}' has an empty expression", phase.name));
+                    }
+                    _ => {}
+                }
+            }
         }
         Ok(())
     }
+
+    /// Starts building a scenario programmatically.
+    pub fn builder(name: impl Into<String>, nodes: usize, proto: ProtoTag) -> ScenarioBuilder {
+        ScenarioBuilder {
+            scenario: Scenario {
+                name: name.into(),
+                seed: None,
+                initial: InitialSpec {
+                    nodes,
+                    proto: proto.into(),
+                    store: StoreSpec::default(),
+                    net: NetSpec::default(),
+                },
+                topology: super::topology::TopologySpec::FullMesh,
+                directives: Vec::new(),
+                stop_at: None,
+                stop_after_events: None,
+                stop_on_quiescence: false,
+                telemetry: TelemetrySpec::default(),
+                on_codec_error: CodecErrorPolicy::default(),
+                scheduling: SchedulingPolicy::default(),
+                future_message_policy: None,
+                flood_valve: None,
+                restart_policy: None,
+                phases: Vec::new(),
+                journal_sampling: None,
+                invariants: Vec::new(),
+                invariant_check_every: None,
+                slo: None,
+                expr_schema: None,
+                caches: CachesSpec::default(),
+            },
+        }
+    }
+}
+
+/// Builds a `Scenario` in code, for generators and tests. The result is
+/// validated by `build`.
+#[derive(Debug, Clone)]
+pub struct ScenarioBuilder {
+    scenario: Scenario,
+}
+
+impl ScenarioBuilder {
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.scenario.seed = Some(seed);
+        self
+    }
+
+    pub fn topology(mut self, topology: super::topology::TopologySpec) -> Self {
+        self.scenario.topology = topology;
+        self
+    }
+
+    pub fn stop_at(mut self, time: SimTime) -> Self {
+        self.scenario.stop_at = Some(time);
+        self
+    }
+
+    /// Schedules `action` at an absolute time.
+    pub fn at(mut self, time: SimTime, action: Action) -> Self {
+        self.scenario.directives.push(Directive::At(time, action).into());
+        self
+    }
+
+    /// Like `at`, naming the directive `label` in the logs.
+    pub fn at_labeled(mut self, label: impl Into<String>, time: SimTime, action: Action) -> Self {
+        self.scenario.directives.push(DirectiveSpec {
+            directive: Directive::At(time, action),
+            label: Some(label.into()),
+            announce_before: None,
+        });
+        self
+    }
+
+    /// Schedules `action` at an absolute time, announced to the nodes it
+    /// affects `announce_before` ahead.
+    pub fn announced(mut self, at: SimTime, announce_before: SimTime, action: Action) -> Self {
+        self.scenario.directives.push(DirectiveSpec {
+            directive: Directive::At(at, action),
+            label: None,
+            announce_before: Some(announce_before),
+        });
+        self
+    }
+
+    pub fn scheduling(mut self, policy: SchedulingPolicy) -> Self {
+        self.scenario.scheduling = policy;
+        self
+    }
+
+    pub fn future_message_policy(mut self, policy: FutureMessagePolicy) -> Self {
+        self.scenario.future_message_policy = Some(policy);
+        self
+    }
+
+    pub fn flood_valve(mut self, valve: FloodValve) -> Self {
+        self.scenario.flood_valve = Some(valve);
+        self
+    }
+
+    pub fn restart_policy(mut self, policy: RestartPolicy) -> Self {
+        self.scenario.restart_policy = Some(policy);
+        self
+    }
+
+    pub fn slo(mut self, slo: Slo) -> Self {
+        self.scenario.slo = Some(slo);
+        self
+    }
+
+    pub fn phase(mut self, name: impl Into<String>, start: SimTime, end: SimTime, expect: Vec<PhaseCheck>) -> Self {
+        self.scenario.phases.push(Phase {
+            name: name.into(),
+            start,
+            end,
+            expect,
+        });
+        self
+    }
+
+    /// Validates and returns the scenario.
+    pub fn build(self) -> Result<Scenario, String> {
+        self.scenario.validate()?;
+        Ok(self.scenario)
+    }
 }
 
 /// Specifies the initial state of the simulation world.
-#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct InitialSpec {
     pub nodes: usize,
-    pub proto: ProtoTag,
+    pub proto: ProtoSpec,
+    #[serde(default)]
+    pub store: StoreSpec,
+    #[serde(default)]
+    pub net: NetSpec,
+}
+
+/// The protocols every node runs: one tag, or a list of distinct tags. The
+/// first protocol listed is the node's primary one, which answers for the
+/// node in reports and receives tagless broadcasts.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ProtoSpec {
+    One(ProtoTag),
+    Many(Vec<ProtoTag>),
+}
+
+impl ProtoSpec {
+    /// Returns the tags of the protocols, in order.
+    pub fn tags(&self) -> &[ProtoTag] {
+        match self {
+            ProtoSpec::One(tag) => std::slice::from_ref(tag),
+            ProtoSpec::Many(tags) => tags,
+        }
+    }
+}
+
+impl From<ProtoTag> for ProtoSpec {
+    fn from(tag: ProtoTag) -> Self {
+        ProtoSpec::One(tag)
+    }
+}
+
+/// Network-wide settings applied to every link.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct NetSpec {
+    /// Maximum payload size in bytes. `None` means unlimited.
+    #[serde(default)]
+    pub mtu: Option<usize>,
+    /// What to do with payloads larger than the MTU.
+    #[serde(default)]
+    pub oversize_policy: OversizePolicy,
+    /// How long a partially reassembled message is kept before it is discarded.
+    #[serde(default = "default_reassembly_timeout", deserialize_with = "deserialize_sim_time", serialize_with = "serialize_sim_time")]
+    pub reassembly_timeout: SimTime,
+    /// The maximum number of partially reassembled messages held per node.
+    #[serde(default = "default_max_reassemblies")]
+    pub max_reassemblies: usize,
+}
+
+impl Default for NetSpec {
+    fn default() -> Self {
+        Self {
+            mtu: None,
+            oversize_policy: OversizePolicy::default(),
+            reassembly_timeout: default_reassembly_timeout(),
+            max_reassemblies: default_max_reassemblies(),
+        }
+    }
+}
+
+fn default_reassembly_timeout() -> SimTime {
+    1_000_000_000
+}
+
+fn default_max_reassemblies() -> usize {
+    64
+}
+
+/// Limits on a bounded engine cache. Once it holds `max_entries`, inserting
+/// evicts the entry inserted longest ago; an entry older than `max_age`, if
+/// set, is evicted as well.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CacheSpec {
+    pub max_entries: usize,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_optional_sim_time",
+        serialize_with = "serialize_optional_sim_time"
+    )]
+    pub max_age: Option<SimTime>,
+}
+
+/// The limits of each bounded engine cache. The fragment reassembly buffers
+/// take theirs from `initial.net`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CachesSpec {
+    /// Client requests a protocol answered `Pending` and has yet to
+    /// respond to. An evicted request goes unanswered.
+    #[serde(default = "default_pending_clients_cache")]
+    pub pending_clients: CacheSpec,
+}
+
+impl Default for CachesSpec {
+    fn default() -> Self {
+        Self {
+            pending_clients: default_pending_clients_cache(),
+        }
+    }
+}
+
+fn default_pending_clients_cache() -> CacheSpec {
+    CacheSpec { max_entries: 65_536, max_age: None }
+}
+
+/// How the network treats payloads that exceed the link MTU.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OversizePolicy {
+    /// Drop the message.
+    #[default]
+    Reject,
+    /// Split the payload into MTU-sized fragments that travel independently
+    /// and are reassembled at the destination. The message is dispatched only
+    /// once every fragment has arrived, so losing any fragment loses the
+    /// whole message.
+    Fragment,
+}
+
+/// Specifies how each node's store is configured.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct StoreSpec {
+    #[serde(default)]
+    pub durability: Durability,
+    /// Simulated latency of store operations, applied to every node.
+    #[serde(default)]
+    pub latency: Option<StoreLatencySpec>,
+    /// Stamp appended log records with a checksum.
+    #[serde(default)]
+    pub checksums: bool,
+    /// Limits how fast nodes may write to their stores.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write_quota: Option<WriteQuotaSpec>,
+}
+
+/// A store write-rate quota: at most `bytes_per_sec` bytes written per
+/// simulated second, enforced by a token bucket holding up to `burst_bytes`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct WriteQuotaSpec {
+    pub bytes_per_sec: u64,
+    /// Bucket capacity. Defaults to one second's worth of writes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst_bytes: Option<u64>,
+    #[serde(default)]
+    pub on_exceeded: QuotaAction,
+    /// The throttled nodes. Empty means every node.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nodes: Vec<NodeId>,
+}
+
+impl WriteQuotaSpec {
+    pub fn burst(&self) -> u64 {
+        self.burst_bytes.unwrap_or(self.bytes_per_sec)
+    }
+
+    pub fn applies_to(&self, node: NodeId) -> bool {
+        self.nodes.is_empty() || self.nodes.contains(&node)
+    }
+}
+
+/// What happens to a write that exceeds its quota.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuotaAction {
+    /// Fail the write with `StoreError::Throttled`.
+    #[default]
+    Throttle,
+    /// Complete the write, charging the wait for the quota to refill as
+    /// store latency.
+    Delay,
+}
+
+/// Delay distributions (in nanoseconds) for each class of store operation.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct StoreLatencySpec {
+    pub read: DelaySpec,
+    pub write: DelaySpec,
+    pub fsync: DelaySpec,
+}
+
+/// Controls which store writes survive a node crash.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// Every write is durable as soon as it completes.
+    #[default]
+    AlwaysDurable,
+    /// Writes are buffered and only become durable on `fsync`; a crash
+    /// discards everything written since the last successful `fsync`.
+    BufferedUntilFsync,
+}
+
+/// Settings for the telemetry pipeline that feeds snapshots to the TUI.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TelemetrySpec {
+    /// The maximum number of unpinned custom KV entries retained per node.
+    /// Beyond this, the least recently updated keys are evicted.
+    #[serde(default = "default_max_node_kvs")]
+    pub max_node_kvs: usize,
+    /// Include the first few KV keys of each node's store in snapshots.
+    /// Off by default because the key list can be large.
+    #[serde(default)]
+    pub include_store_keys: bool,
+    /// Include fault state that the node itself cannot observe, such as an
+    /// active gray failure, in snapshots.
+    #[serde(default)]
+    pub include_fault_details: bool,
+    /// The number of samples of each numeric metric retained per node.
+    #[serde(default = "default_max_metric_samples")]
+    pub max_metric_samples: usize,
+    /// The number of recent events kept for snapshots. Warnings and faults
+    /// are kept apart from other events, up to this many again, so a flood
+    /// of deliveries never pushes them out.
+    #[serde(default = "default_event_buffer")]
+    pub event_buffer: usize,
+    /// Send every snapshot, even one that differs from the last sent only in
+    /// its time. Off by default: such snapshots are skipped, bar a heartbeat,
+    /// which takes comparing each snapshot against the last.
+    #[serde(default)]
+    pub send_unchanged_snapshots: bool,
+}
+
+impl Default for TelemetrySpec {
+    fn default() -> Self {
+        Self {
+            max_node_kvs: default_max_node_kvs(),
+            include_store_keys: false,
+            include_fault_details: false,
+            max_metric_samples: default_max_metric_samples(),
+            event_buffer: default_event_buffer(),
+            send_unchanged_snapshots: false,
+        }
+    }
+}
+
+fn default_max_node_kvs() -> usize {
+    1024
+}
+
+fn default_max_metric_samples() -> usize {
+    120
+}
+
+fn default_event_buffer() -> usize {
+    100
+}
+
+/// A scheduled directive, with an optional label that names it in the logs
+/// and telemetry when its action runs.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct DirectiveSpec {
+    #[serde(flatten)]
+    pub directive: Directive,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// If set, the nodes each run of the action affects are told about it
+    /// this long ahead with `FaultEvent::Scheduled`, as for planned
+    /// maintenance, or from the start if it runs sooner. Node actions
+    /// affect their node; the others affect every node.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_optional_sim_time",
+        serialize_with = "serialize_optional_sim_time"
+    )]
+    pub announce_before: Option<SimTime>,
+}
+
+impl From<Directive> for DirectiveSpec {
+    fn from(directive: Directive) -> Self {
+        DirectiveSpec { directive, label: None, announce_before: None }
+    }
 }
 
 /// A directive that schedules an action to occur at a specific time.
 #[derive(Deserialize, Serialize, Debug, Clone)]
-#[serde(rename_all = "PascalCase")]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
 pub enum Directive {
-    At(#[serde(deserialize_with = "deserialize_sim_time")] SimTime, Action),
+    At(#[serde(deserialize_with = "deserialize_sim_time", serialize_with = "serialize_sim_time")] SimTime, Action),
     Every {
-        #[serde(deserialize_with = "deserialize_sim_time")]
+        #[serde(deserialize_with = "deserialize_sim_time", serialize_with = "serialize_sim_time")]
         period: SimTime,
         repeats: u64,
         action: Action,
     },
     After {
-        #[serde(deserialize_with = "deserialize_sim_time")]
+        #[serde(deserialize_with = "deserialize_sim_time", serialize_with = "serialize_sim_time")]
         offset: SimTime,
         action: Action,
     },
@@ -107,26 +937,164 @@ impl Directive {
 
 /// An action that modifies the state of the simulation world, typically to inject a fault.
 #[derive(Deserialize, Serialize, Debug, Clone)]
-#[serde(rename_all = "PascalCase")]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
 pub enum Action {
+    /// Cuts every link between nodes in different sets. Nodes in no set
+    /// keep all their links.
     Partition { sets: Vec<Vec<NodeId>> },
     HealPartition,
     Crash {
         node: NodeId,
-        #[serde(deserialize_with = "deserialize_sim_time")]
-        duration: SimTime
+        duration: SimDuration,
     },
     Restart { node: NodeId },
     LinkDelay { link: LinkId, dist: DelaySpec },
     LinkDrop { link: LinkId, p: f64 },
+    /// Makes the link deliver each message twice with probability `p`.
+    LinkDuplicate { link: LinkId, p: f64 },
     BroadcastBytes { payload_hex: String, #[serde(default)] proto_tag: Option<ProtoTag> },
-    ClockSkew { node: NodeId, skew: i128 },
+    ClockSkew {
+        node: NodeId,
+        #[serde(deserialize_with = "deserialize_skew", serialize_with = "serialize_skew")]
+        skew: i128,
+    },
     StoreFault { node: NodeId, kind: StoreFaultKind, rate: f64 },
     ByzantineFlip { node: NodeId, enabled: bool },
-    Custom { name: String, args: toml::Value },
+    /// Makes `node` silently ignore messages from some senders for
+    /// `duration`, while it stays up and its own sends go out as usual. The
+    /// network is unaffected, so link statistics stay healthy.
+    GrayFailure {
+        node: NodeId,
+        ignore_from: GrayFilter,
+        duration: SimDuration,
+    },
+    Custom { name: String, args: CustomArgs },
+    /// Drops the `n`th message (counting from 1) sent from `src` to `dst`
+    /// after this directive runs, counting only messages of kind `variant`
+    /// if given.
+    DropNth {
+        src: NodeId,
+        dst: NodeId,
+        #[serde(default)]
+        variant: Option<String>,
+        n: u64,
+    },
+    /// Like `DropNth`, but holds the message back by `by` nanoseconds
+    /// before the link's own delay.
+    DelayNth {
+        src: NodeId,
+        dst: NodeId,
+        #[serde(default)]
+        variant: Option<String>,
+        n: u64,
+        #[serde(deserialize_with = "deserialize_sim_time", serialize_with = "serialize_sim_time")]
+        by: SimTime,
+    },
+    /// Issues a client operation to `node`'s protocol. Under an `Every`
+    /// directive, `{i}` in the operation's key and value is replaced with
+    /// the repeat's index, counting from 0.
+    ClientRequest { node: NodeId, op: ClientOp },
+}
+
+/// The kind of fault an `Action` injects, as announced to protocols.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum FaultKind {
+    Partition,
+    HealPartition,
+    Crash,
+    Restart,
+    LinkDelay,
+    LinkDrop,
+    LinkDuplicate,
+    BroadcastBytes,
+    ClockSkew,
+    StoreFault,
+    ByzantineFlip,
+    GrayFailure,
+    Custom,
+    DropNth,
+    DelayNth,
+}
+
+impl FaultKind {
+    /// Returns the kind in snake case, e.g. `crash`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FaultKind::Partition => "partition",
+            FaultKind::HealPartition => "heal_partition",
+            FaultKind::Crash => "crash",
+            FaultKind::Restart => "restart",
+            FaultKind::LinkDelay => "link_delay",
+            FaultKind::LinkDrop => "link_drop",
+            FaultKind::LinkDuplicate => "link_duplicate",
+            FaultKind::BroadcastBytes => "broadcast_bytes",
+            FaultKind::ClockSkew => "clock_skew",
+            FaultKind::StoreFault => "store_fault",
+            FaultKind::ByzantineFlip => "byzantine_flip",
+            FaultKind::GrayFailure => "gray_failure",
+            FaultKind::Custom => "custom",
+            FaultKind::DropNth => "drop_nth",
+            FaultKind::DelayNth => "delay_nth",
+        }
+    }
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Which incoming messages a gray-failing node ignores: every message from
+/// the listed senders, e.g. `ignore_from = [0]`, or each message with a
+/// probability, e.g. `ignore_from = 0.3`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum GrayFilter {
+    Peers(Vec<NodeId>),
+    Probability(f64),
+}
+
+/// Free-form arguments of a custom action. The scalars, arrays and tables of
+/// any config format deserialize into it, so that the engine can pass them
+/// through without depending on one.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CustomArgs {
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<CustomArgs>),
+    Table(BTreeMap<String, CustomArgs>),
 }
 
 impl Action {
+    /// Returns the kind of fault the action injects, or `None` for a client
+    /// request.
+    pub fn fault_kind(&self) -> Option<FaultKind> {
+        Some(match self {
+            Action::Partition { .. } => FaultKind::Partition,
+            Action::HealPartition => FaultKind::HealPartition,
+            Action::Crash { .. } => FaultKind::Crash,
+            Action::Restart { .. } => FaultKind::Restart,
+            Action::LinkDelay { .. } => FaultKind::LinkDelay,
+            Action::LinkDrop { .. } => FaultKind::LinkDrop,
+            Action::LinkDuplicate { .. } => FaultKind::LinkDuplicate,
+            Action::BroadcastBytes { .. } => FaultKind::BroadcastBytes,
+            Action::ClockSkew { .. } => FaultKind::ClockSkew,
+            Action::StoreFault { .. } => FaultKind::StoreFault,
+            Action::ByzantineFlip { .. } => FaultKind::ByzantineFlip,
+            Action::GrayFailure { .. } => FaultKind::GrayFailure,
+            Action::Custom { .. } => FaultKind::Custom,
+            Action::DropNth { .. } => FaultKind::DropNth,
+            Action::DelayNth { .. } => FaultKind::DelayNth,
+            Action::ClientRequest { .. } => return None,
+        })
+    }
+
     /// Returns the node ID associated with the action, if any.
     pub fn node_id(&self) -> Option<NodeId> {
         match self {
@@ -134,20 +1102,81 @@ impl Action {
             | Action::Restart { node }
             | Action::ClockSkew { node, .. }
             | Action::StoreFault { node, .. }
-            | Action::ByzantineFlip { node, .. } => Some(*node),
+            | Action::ByzantineFlip { node, .. }
+            | Action::GrayFailure { node, .. }
+            | Action::ClientRequest { node, .. } => Some(*node),
             _ => None,
         }
     }
 }
 
-/// A serializable version of `DelayDist` for scenarios.
+/// The default upper bound on delays drawn from an unbounded distribution:
+/// one minute, in nanoseconds.
+pub const DEFAULT_MAX_DELAY: u64 = 60_000_000_000;
+
+/// A serializable version of `DelayDist` for scenarios, in nanoseconds.
+/// `Normal` and `Pareto` are unbounded, so their draws are clamped into
+/// `min..=max`, with `max` defaulting to `DEFAULT_MAX_DELAY`.
 #[derive(Deserialize, Serialize, Debug, Clone, Copy)]
-#[serde(rename_all = "PascalCase")]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
 pub enum DelaySpec {
     Const(u64),
     Uniform { lo: u64, hi: u64 },
-    Normal { mu: f64, sigma: f64 },
-    Pareto { scale: f64, shape: f64 },
+    Normal {
+        mu: f64,
+        sigma: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max: Option<u64>,
+    },
+    Pareto {
+        scale: f64,
+        shape: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max: Option<u64>,
+    },
+}
+
+impl DelaySpec {
+    /// Returns the range every delay drawn from this spec falls in.
+    pub fn bounds(&self) -> (u64, u64) {
+        match *self {
+            DelaySpec::Const(delay) => (delay, delay),
+            DelaySpec::Uniform { lo, hi } => (lo, hi),
+            DelaySpec::Normal { min, max, .. } | DelaySpec::Pareto { min, max, .. } => {
+                (min.unwrap_or(0), max.unwrap_or(DEFAULT_MAX_DELAY))
+            }
+        }
+    }
+
+    /// Rejects parameters that do not describe a distribution of delays.
+    pub fn validate(&self) -> Result<(), String> {
+        let params = match *self {
+            DelaySpec::Const(_) => vec![],
+            DelaySpec::Uniform { lo, hi } if lo > hi => {
+                return Err(format!("Uniform delay has lo {} above hi {}", lo, hi));
+            }
+            DelaySpec::Uniform { .. } => vec![],
+            DelaySpec::Normal { mu, sigma, .. } => vec![("mu", mu), ("sigma", sigma)],
+            DelaySpec::Pareto { scale, shape, .. } => vec![("scale", scale), ("shape", shape)],
+        };
+        if let Some((name, value)) = params.into_iter().find(|(_, v)| !v.is_finite() || *v < 0.0) {
+            return Err(format!("delay parameter {} is {}; it must be finite and non-negative", name, value));
+        }
+        if let DelaySpec::Pareto { shape, .. } = *self {
+            if shape == 0.0 {
+                return Err("Pareto delay has shape 0; it must be positive".to_string());
+            }
+        }
+        let (min, max) = self.bounds();
+        if min > max {
+            return Err(format!("delay bounds have min {} above max {}", min, max));
+        }
+        Ok(())
+    }
 }
 
 /// Kinds of storage faults that can be injected.
@@ -159,4 +1188,82 @@ pub enum StoreFaultKind {
     ReadError,
     FsyncFail,
     FsyncDelay,
+    /// Reads return the record with some of its bytes flipped.
+    BitRot,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_args_keep_toml_values() {
+        let text = r#"Custom = { name = "probe", args = { n = 3, p = 0.5, tags = ["a", "b"], on = true } }"#;
+        let Action::Custom { name, args } = toml::from_str::<Action>(text).unwrap() else {
+            panic!("not a custom action");
+        };
+        assert_eq!(name, "probe");
+        let table = BTreeMap::from([
+            ("n".to_string(), CustomArgs::Integer(3)),
+            ("p".to_string(), CustomArgs::Float(0.5)),
+            (
+                "tags".to_string(),
+                CustomArgs::Array(vec![CustomArgs::String("a".into()), CustomArgs::String("b".into())]),
+            ),
+            ("on".to_string(), CustomArgs::Boolean(true)),
+        ]);
+        assert_eq!(args, CustomArgs::Table(table));
+    }
+
+    #[test]
+    fn test_directive_labels_round_trip() {
+        let text = r#"
+At = [5_000_000, { Crash = { node = 1, duration = "forever" } }]
+label = "crash"
+"#;
+        let spec: DirectiveSpec = toml::from_str(text).unwrap();
+        assert_eq!(spec.label.as_deref(), Some("crash"));
+        assert!(matches!(spec.directive, Directive::At(5_000_000, Action::Crash { node: 1, .. })));
+        let back: DirectiveSpec = toml::from_str(&toml::to_string(&spec).unwrap()).unwrap();
+        assert_eq!(back.label.as_deref(), Some("crash"));
+
+        let unlabeled: DirectiveSpec = toml::from_str("At = [0, \"HealPartition\"]").unwrap();
+        assert_eq!(unlabeled.label, None);
+    }
+
+    #[test]
+    fn test_any_directive_can_be_announced() {
+        let text = r#"
+Every = { period = 10_000_000, repeats = 3, action = { Restart = { node = 0 } } }
+announce_before = 2_000_000
+"#;
+        let spec: DirectiveSpec = toml::from_str(text).unwrap();
+        assert_eq!(spec.announce_before, Some(2_000_000));
+        assert!(matches!(spec.directive, Directive::Every { repeats: 3, .. }));
+        let back: DirectiveSpec = toml::from_str(&toml::to_string(&spec).unwrap()).unwrap();
+        assert_eq!(back.announce_before, Some(2_000_000));
+        assert_eq!(spec.directive.action().fault_kind(), Some(FaultKind::Restart));
+    }
+
+    #[test]
+    fn test_link_probabilities_are_checked() {
+        let scenario = |action| Scenario::builder("s", 2, ProtoTag(1)).at(0, action).build();
+        assert!(scenario(Action::LinkDuplicate { link: 0, p: 1.0 }).is_ok());
+        let err = scenario(Action::LinkDuplicate { link: 0, p: 1.5 }).unwrap_err();
+        assert_eq!(err, "Directive 0 sets a link probability 1.5 outside 0..=1");
+        assert!(scenario(Action::LinkDrop { link: 0, p: -0.1 }).is_err());
+    }
+
+    #[test]
+    fn test_misplaced_fields_are_rejected() {
+        let scenario = |initial: &str| {
+            let text = format!("name = \"s\"\ntopology = \"FullMesh\"\ndirectives = []\n[initial]\nnodes = 3\nproto = 1\n{}", initial);
+            toml::from_str::<Scenario>(&text)
+        };
+        assert!(scenario("").is_ok());
+        // Under `[initial]`, `stop_at` would otherwise be dropped without a word
+        let err = scenario("stop_at = 1_000").unwrap_err().to_string();
+        assert!(err.contains("unknown field `stop_at`"), "{}", err);
+        assert!(toml::from_str::<Action>("Crash = { node = 1, duraton = \"forever\" }").is_err());
+    }
 }
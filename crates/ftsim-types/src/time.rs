@@ -5,7 +5,8 @@
 //! high resolution for network and processing delays.
 
 use crate::errors::SimError;
-use serde::{Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 
 /// The fundamental unit of time in the simulation, measured in nanoseconds.
 /// A `u128` provides an enormous range, preventing overflow for any practical simulation duration.
@@ -17,6 +18,89 @@ pub const SIM_EPOCH: SimTime = 0;
 /// The maximum representable simulation time.
 pub const MAX_SIM_TIME: SimTime = u128::MAX;
 
+/// A span of simulation time that may never end, such as how long a node
+/// stays down after a crash.
+///
+/// In config files a finite duration is written as nanoseconds and an endless
+/// one as the string `"forever"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SimDuration {
+    Finite(SimTime),
+    Forever,
+}
+
+impl SimDuration {
+    /// Returns when the duration ends if it starts at `start`, or `None` if it
+    /// never ends.
+    pub fn end_after(self, start: SimTime) -> Result<Option<SimTime>, SimError> {
+        match self {
+            SimDuration::Finite(duration) => checked_add(start, duration).map(Some),
+            SimDuration::Forever => Ok(None),
+        }
+    }
+}
+
+impl From<SimTime> for SimDuration {
+    fn from(duration: SimTime) -> Self {
+        SimDuration::Finite(duration)
+    }
+}
+
+impl fmt::Display for SimDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimDuration::Finite(duration) => write!(f, "{}ns", duration),
+            SimDuration::Forever => write!(f, "forever"),
+        }
+    }
+}
+
+impl Serialize for SimDuration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            SimDuration::Finite(duration) => serialize_sim_time(duration, serializer),
+            SimDuration::Forever => serializer.serialize_str("forever"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SimDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SimDurationVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for SimDurationVisitor {
+            type Value = SimDuration;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a number of nanoseconds or \"forever\"")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                Ok(SimDuration::Finite(value as u128))
+            }
+
+            fn visit_u128<E: serde::de::Error>(self, value: u128) -> Result<Self::Value, E> {
+                Ok(SimDuration::Finite(value))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                u64::try_from(value)
+                    .map(|value| SimDuration::Finite(value as u128))
+                    .map_err(|_| E::custom("SimDuration cannot be negative"))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                match value {
+                    "forever" => Ok(SimDuration::Forever),
+                    _ => Err(E::invalid_value(serde::de::Unexpected::Str(value), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(SimDurationVisitor)
+    }
+}
+
 /// Helper function to convert milliseconds to `SimTime`.
 pub fn sim_from_ms(ms: u64) -> SimTime {
     (ms as u128) * 1_000_000
@@ -39,6 +123,23 @@ pub fn checked_sub(base: SimTime, offset: SimTime) -> Result<SimTime, SimError>
         .ok_or(SimError::TimeUnderflow { base, offset })
 }
 
+/// Performs a checked multiplication of a span of `SimTime`, returning an
+/// error on overflow.
+pub fn checked_mul(span: SimTime, factor: u128) -> Result<SimTime, SimError> {
+    span.checked_mul(factor)
+        .ok_or(SimError::TimeProductOverflow { span, factor })
+}
+
+/// Shifts `time` by a signed clock skew, returning an error if the result
+/// falls outside the representable range.
+pub fn checked_skew(time: SimTime, skew_ns: i128) -> Result<SimTime, SimError> {
+    if skew_ns >= 0 {
+        checked_add(time, skew_ns.unsigned_abs())
+    } else {
+        checked_sub(time, skew_ns.unsigned_abs())
+    }
+}
+
 /// Custom deserializer for SimTime that handles both u64 and u128 values.
 /// TOML only supports up to u64, so we need to handle the conversion manually.
 pub fn deserialize_sim_time<'de, D>(deserializer: D) -> Result<SimTime, D::Error>
@@ -82,6 +183,56 @@ where
     deserializer.deserialize_any(SimTimeVisitor)
 }
 
+/// Custom deserializer for a signed clock skew in nanoseconds. TOML only
+/// supports 64-bit integers, so the skew is read as one and widened.
+pub fn deserialize_skew<'de, D>(deserializer: D) -> Result<i128, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct SkewVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for SkewVisitor {
+        type Value = i128;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("an integer representing nanoseconds")
+        }
+
+        fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            Ok(value as i128)
+        }
+
+        fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            Ok(value as i128)
+        }
+
+        fn visit_i128<E>(self, value: i128) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            Ok(value)
+        }
+    }
+
+    deserializer.deserialize_any(SkewVisitor)
+}
+
+/// Custom serializer for a clock skew, written as an `i64` like
+/// `serialize_sim_time` writes a `u64`.
+pub fn serialize_skew<S>(skew: &i128, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let skew = i64::try_from(*skew).map_err(|_| serde::ser::Error::custom("skew does not fit in 64 bits"))?;
+    serializer.serialize_i64(skew)
+}
+
 /// Custom deserializer for Option<SimTime>
 pub fn deserialize_optional_sim_time<'de, D>(deserializer: D) -> Result<Option<SimTime>, D::Error>
 where
@@ -130,3 +281,93 @@ where
 
     deserializer.deserialize_option(OptionalSimTimeVisitor)
 }
+
+/// Custom serializer for `SimTime`. Writes the time as a `u64`, since config
+/// formats such as TOML cannot represent 128-bit integers.
+pub fn serialize_sim_time<S>(time: &SimTime, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let time = u64::try_from(*time)
+        .map_err(|_| serde::ser::Error::custom("SimTime does not fit in 64 bits"))?;
+    serializer.serialize_u64(time)
+}
+
+/// Custom serializer for Option<SimTime>
+pub fn serialize_optional_sim_time<S>(time: &Option<SimTime>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match time {
+        Some(time) => serialize_sim_time(time, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_arithmetic_at_the_boundaries() {
+        assert_eq!(checked_add(MAX_SIM_TIME - 1, 1).unwrap(), MAX_SIM_TIME);
+        assert!(matches!(
+            checked_add(MAX_SIM_TIME, 1),
+            Err(SimError::TimeOverflow { base: MAX_SIM_TIME, offset: 1 })
+        ));
+        assert_eq!(checked_sub(1, 1).unwrap(), SIM_EPOCH);
+        assert!(matches!(checked_sub(SIM_EPOCH, 1), Err(SimError::TimeUnderflow { .. })));
+        assert_eq!(checked_mul(MAX_SIM_TIME / 2, 2).unwrap(), MAX_SIM_TIME - 1);
+        assert!(matches!(
+            checked_mul(MAX_SIM_TIME / 2, 3),
+            Err(SimError::TimeProductOverflow { span, factor: 3 }) if span == MAX_SIM_TIME / 2
+        ));
+
+        assert_eq!(checked_skew(10, 5).unwrap(), 15);
+        assert_eq!(checked_skew(10, -10).unwrap(), SIM_EPOCH);
+        assert!(matches!(checked_skew(10, -11), Err(SimError::TimeUnderflow { .. })));
+        assert!(matches!(checked_skew(MAX_SIM_TIME, 1), Err(SimError::TimeOverflow { .. })));
+        assert_eq!(checked_skew(MAX_SIM_TIME, i128::MIN).unwrap(), MAX_SIM_TIME - (1 << 127));
+    }
+
+    #[test]
+    fn test_sim_duration_end_and_serde() {
+        assert_eq!(SimDuration::Finite(5).end_after(10).unwrap(), Some(15));
+        assert_eq!(SimDuration::Forever.end_after(MAX_SIM_TIME).unwrap(), None);
+        assert!(matches!(
+            SimDuration::Finite(MAX_SIM_TIME).end_after(1),
+            Err(SimError::TimeOverflow { .. })
+        ));
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Crash {
+            duration: SimDuration,
+        }
+        for (text, duration) in [
+            ("duration = 300\n", SimDuration::Finite(300)),
+            ("duration = \"forever\"\n", SimDuration::Forever),
+        ] {
+            let crash: Crash = toml::from_str(text).unwrap();
+            assert_eq!(crash.duration, duration);
+            assert_eq!(toml::to_string(&crash).unwrap(), text);
+        }
+        assert!(toml::from_str::<Crash>("duration = \"never\"").is_err());
+        assert!(toml::from_str::<Crash>("duration = -1").is_err());
+    }
+
+    #[test]
+    fn test_skew_serde_fits_toml_integers() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Skew {
+            #[serde(deserialize_with = "deserialize_skew", serialize_with = "serialize_skew")]
+            skew: i128,
+        }
+        for (text, skew) in [("skew = -500000000\n", -500_000_000), ("skew = 20\n", 20)] {
+            let parsed: Skew = toml::from_str(text).unwrap();
+            assert_eq!(parsed.skew, skew);
+            assert_eq!(toml::to_string(&parsed).unwrap(), text);
+        }
+        assert!(toml::from_str::<Skew>("skew = \"fast\"").is_err());
+        assert!(toml::to_string(&Skew { skew: i64::MAX as i128 + 1 }).is_err());
+    }
+}
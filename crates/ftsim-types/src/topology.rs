@@ -8,7 +8,7 @@ use serde::{Deserialize, Serialize};
 
 /// An enum representing different ways to specify the network graph.
 #[derive(Deserialize, Serialize, Debug, Clone)]
-#[serde(rename_all = "PascalCase")]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
 pub enum TopologySpec {
     /// Every node is connected to every other node.
     FullMesh,